@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+use anyhow::{bail, Result};
+
+/// Frames run when `--frames` isn't given, ten seconds of emulated time
+const DEFAULT_FRAMES: u64 = 600;
+
+pub const USAGE: &str = "\
+Usage: mips-cli [OPTIONS] [GAME]
+
+Runs the BIOS, a PS-EXE or a disc image (GAME, a file or a name in the games directory) without a
+window, for a number of frames, and writes what the console output.
+
+Options:
+  --sys-dir DIR     System directory with the BIOS and the games (default: current directory)
+  --bios FILE       BIOS dump to boot, instead of the first supported one in the system directory
+  --frames N        Frames to run (default: 600)
+  --png DIR         Save the last frame to DIR/frameN.png
+  --png-every N     Save every Nth frame instead of the last one only
+  --wav FILE        Save the audio output
+  --trace FILE      Save the address and machine code of every instruction executed
+  --tty             Print the lines the program prints (debug port and BIOS putchar)
+  -h, --help        Show this help";
+
+/// Command line options
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    pub sys_dir: Option<PathBuf>,
+    pub bios: Option<PathBuf>,
+    /// BIOS shell if `None`
+    pub game: Option<String>,
+    pub frames: u64,
+    /// Where the frames are saved
+    pub png: Option<PathBuf>,
+    /// Save every Nth frame, the last one only if `None`
+    pub png_every: Option<u64>,
+    pub wav: Option<PathBuf>,
+    pub trace: Option<PathBuf>,
+    pub tty: bool,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            sys_dir: None,
+            bios: None,
+            game: None,
+            frames: DEFAULT_FRAMES,
+            png: None,
+            png_every: None,
+            wav: None,
+            trace: None,
+            tty: false,
+            help: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut cli = Args::default();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((n, v)) => (n.to_string(), Some(v.to_string())),
+                None => (arg.clone(), None),
+            };
+
+            let mut value = || -> Result<String> {
+                match inline_value.clone().or_else(|| args.next()) {
+                    Some(v) => Ok(v),
+                    None => bail!("Missing value for {}", name),
+                }
+            };
+
+            match name.as_str() {
+                "--sys-dir" => cli.sys_dir = Some(value()?.into()),
+                "--bios" => cli.bios = Some(value()?.into()),
+                "--frames" => cli.frames = value()?.parse()?,
+                "--png" => cli.png = Some(value()?.into()),
+                "--png-every" => {
+                    let every = value()?.parse()?;
+                    if every == 0 {
+                        bail!("--png-every must be at least 1");
+                    }
+                    cli.png_every = Some(every);
+                }
+                "--wav" => cli.wav = Some(value()?.into()),
+                "--trace" => cli.trace = Some(value()?.into()),
+                "--tty" => cli.tty = true,
+                "-h" | "--help" => cli.help = true,
+                other if other.starts_with('-') => bail!("Unknown argument '{}'", other),
+                _ if cli.game.is_none() => cli.game = Some(arg),
+                _ => bail!("Only one game can be run, got '{}' too", arg),
+            }
+        }
+
+        if cli.png_every.is_some() && cli.png.is_none() {
+            bail!("--png-every needs --png");
+        }
+
+        Ok(cli)
+    }
+}
+
+#[test]
+fn parse() {
+    let args = |list: &[&str]| Args::parse_from(list.iter().map(|s| s.to_string()));
+
+    assert_eq!(args(&[]).unwrap(), Args::default());
+
+    let cli = args(&["psxtest_cpu.exe", "--frames=120", "--png", "out", "--png-every", "60", "--tty"]).unwrap();
+    assert_eq!(cli.game.as_deref(), Some("psxtest_cpu.exe"));
+    assert_eq!(cli.frames, 120);
+    assert_eq!(cli.png, Some(PathBuf::from("out")));
+    assert_eq!(cli.png_every, Some(60));
+    assert!(cli.tty);
+
+    assert!(args(&["--png-every", "10"]).is_err());
+    assert!(args(&["--png", "out", "--png-every", "0"]).is_err());
+    assert!(args(&["a.exe", "b.exe"]).is_err());
+    assert!(args(&["--frames"]).is_err());
+}
@@ -0,0 +1,150 @@
+//! Command line runner for scripted testing: boots the BIOS, a PS-EXE or a disc without a window or
+//! an audio device, runs it for a number of frames as fast as possible and writes the frames, the
+//! audio and a CPU trace to files, to diff them against known good ones.
+
+mod args;
+mod wav;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use mips_core::{cpu_trace, ConsoleManager, CpuFrame};
+use mips_core::input::ControllerType;
+use crate::args::{Args, USAGE};
+use crate::wav::WavWriter;
+
+fn main() -> ExitCode {
+    // The log goes to stderr, stdout is left to the program's output
+    tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::INFO)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if args.help {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<()> {
+    let sys_dir = match &args.sys_dir {
+        Some(dir) => dir.clone(),
+        None => env::current_dir()?,
+    };
+
+    // Files given by their path are loaded from there, the others from the games directory
+    let game = args.game.as_ref().map(|game| match fs::canonicalize(game) {
+        Ok(path) => path.display().to_string(),
+        Err(_) => game.clone(),
+    });
+
+    let mut mips = ConsoleManager::new();
+    mips.set_bios(args.bios.clone());
+    mips.load_game(&sys_dir, game.as_deref())?;
+    mips.connect_device(0, ControllerType::DigitalPad);
+    mips.set_tty_capture(args.tty);
+
+    if let Some(dir) = &args.png {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut wav = match &args.wav {
+        Some(path) => Some(WavWriter::create(path).with_context(|| format!("Failed to create {}", path.display()))?),
+        None => None,
+    };
+    let mut trace = match &args.trace {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+            mips.set_cpu_trace(Some(cpu_trace::DEFAULT_LIMIT));
+            Some(BufWriter::new(file))
+        }
+        None => None,
+    };
+
+    info!("Running {} for {} frames", game.as_deref().unwrap_or("the BIOS"), args.frames);
+
+    let mut last_frame = None;
+    for frame in 1..=args.frames {
+        mips.update();
+
+        if let Some(wav) = &mut wav {
+            wav.write(&mips.take_audio().samples)?;
+        } else {
+            // Dropped, but it has to be taken every frame
+            mips.take_audio();
+        }
+
+        if let Some(trace) = &mut trace {
+            let (entries, dropped) = mips.take_cpu_trace();
+            for entry in entries {
+                writeln!(trace, "{}", entry)?;
+            }
+            if dropped > 0 {
+                warn!("Frame {}: {} instructions left out of the trace", frame, dropped);
+                writeln!(trace, "# {} instructions dropped", dropped)?;
+            }
+        }
+
+        for line in mips.take_tty_lines() {
+            println!("{}", line);
+        }
+
+        if let Some(picture) = mips.get_frame() {
+            if let (Some(dir), Some(every)) = (&args.png, args.png_every)
+                && frame % every == 0
+            {
+                save_png(&picture, dir, frame)?;
+            }
+            last_frame = Some((frame, picture));
+        }
+    }
+
+    if let Some(wav) = wav {
+        wav.finish()?;
+    }
+    if let Some(mut trace) = trace {
+        trace.flush()?;
+    }
+
+    match last_frame {
+        Some((frame, picture)) => {
+            // The hash alone is enough to tell whether the output changed
+            println!("frame {} {}x{} {:016x}", frame, picture.width, picture.height, picture.hash());
+
+            if let Some(dir) = &args.png
+                && args.png_every.is_none()
+            {
+                save_png(&picture, dir, frame)?;
+            }
+        }
+        None => warn!("The console didn't output any frame"),
+    }
+
+    Ok(())
+}
+
+fn save_png(picture: &CpuFrame, dir: &Path, frame: u64) -> Result<()> {
+    let path = dir.join(format!("frame{}.png", frame));
+    fs::write(&path, picture.to_png()?).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! 16bit stereo WAV files of the audio output, written as the console runs
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use mips_core::audio::AUDIO_FREQ_HZ;
+
+const CHANNELS: u16 = 2;
+const BYTES_PER_SAMPLE: u16 = 2;
+const HEADER_SIZE: u32 = 44;
+
+pub struct WavWriter {
+    file: BufWriter<File>,
+    /// Bytes of samples written
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path) -> io::Result<WavWriter> {
+        let mut wav = WavWriter {
+            file: BufWriter::new(File::create(path)?),
+            data_size: 0,
+        };
+        // The sizes are filled in by `finish`
+        wav.write_header()?;
+
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = CHANNELS * BYTES_PER_SAMPLE;
+
+        let f = &mut self.file;
+        f.write_all(b"RIFF")?;
+        f.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        f.write_all(b"WAVE")?;
+        f.write_all(b"fmt ")?;
+        f.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        f.write_all(&1u16.to_le_bytes())?;
+        f.write_all(&CHANNELS.to_le_bytes())?;
+        f.write_all(&AUDIO_FREQ_HZ.to_le_bytes())?;
+        f.write_all(&(AUDIO_FREQ_HZ * u32::from(block_align)).to_le_bytes())?;
+        f.write_all(&block_align.to_le_bytes())?;
+        f.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+        f.write_all(b"data")?;
+        f.write_all(&self.data_size.to_le_bytes())?;
+
+        Ok(())
+    }
+
+    /// Interleaved left/right samples
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size = self.data_size.saturating_add(samples.len() as u32 * u32::from(BYTES_PER_SAMPLE));
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
@@ -0,0 +1,85 @@
+//! Interpreter throughput on a few instruction mixes: `cargo bench -p mips-core --features bench`
+//!
+//! The CPU on its own is measured in nanoseconds per instruction, then whole frames with all the
+//! events (GPU, SPU, timers...) are timed against real time, with the dynarec too where the host
+//! supports it, along with its speedup over the interpreter. The SPU is also measured on its own, in nanoseconds per 44.1kHz sample. All of
+//! them keep the best of several runs since anything else running on the machine only ever makes
+//! them slower.
+
+use std::time::{Duration, Instant};
+use mips_core::bench::{Audio, Interpreter, Workload};
+
+const RUNS: u32 = 10;
+const INSTRUCTIONS_PER_RUN: u32 = 1_000_000;
+const FRAMES_PER_RUN: u32 = 60;
+const SAMPLES_PER_RUN: u32 = 44_100;
+
+/// Duration of an NTSC frame
+const REALTIME_FRAME: Duration = Duration::from_nanos(16_683_350);
+
+/// Fastest of `RUNS` calls to `f`, after a first one to warm up
+fn best_of(mut f: impl FnMut()) -> Duration {
+    f();
+
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    for workload in Workload::ALL {
+        let mut interpreter = Interpreter::new(workload);
+
+        let cpu = best_of(|| interpreter.run_instructions(INSTRUCTIONS_PER_RUN));
+        let insn_ns = cpu.as_secs_f64() * 1e9 / f64::from(INSTRUCTIONS_PER_RUN);
+
+        let frames = best_of(|| {
+            for _ in 0..FRAMES_PER_RUN {
+                interpreter.run_frame();
+            }
+        });
+        let frame = frames / FRAMES_PER_RUN;
+
+        println!(
+            "{:<10} {:>6.2} ns/insn {:>8.1} MIPS {:>8.3} ms/frame {:>6.1}x realtime",
+            workload.name(),
+            insn_ns,
+            1e3 / insn_ns,
+            frame.as_secs_f64() * 1000.,
+            REALTIME_FRAME.as_secs_f64() / frame.as_secs_f64(),
+        );
+
+        if interpreter.use_dynarec() {
+            let frames = best_of(|| {
+                for _ in 0..FRAMES_PER_RUN {
+                    interpreter.run_frame();
+                }
+            });
+            let dynarec_frame = frames / FRAMES_PER_RUN;
+
+            println!(
+                "{:<10} {:>8.3} ms/frame {:>6.1}x realtime with the dynarec, {:.2}x the interpreter",
+                "",
+                dynarec_frame.as_secs_f64() * 1000.,
+                REALTIME_FRAME.as_secs_f64() / dynarec_frame.as_secs_f64(),
+                frame.as_secs_f64() / dynarec_frame.as_secs_f64(),
+            );
+        }
+    }
+
+    let mut audio = Audio::new();
+    let spu = best_of(|| audio.run_samples(SAMPLES_PER_RUN));
+    let sample_ns = spu.as_secs_f64() * 1e9 / f64::from(SAMPLES_PER_RUN);
+
+    println!(
+        "{:<10} {:>6.0} ns/sample {:>6.1}x realtime",
+        "spu",
+        sample_ns,
+        1e9 / 44_100. / sample_ns,
+    );
+}
@@ -0,0 +1,64 @@
+//! Criterion benchmarks of the core: `cargo bench -p mips-core --features bench --bench suite`
+//!
+//! Unlike the `interpreter` bench which prints a summary, these keep their results between runs
+//! so that a change can be compared against a baseline (`--save-baseline` and `--baseline`).
+//! The workloads are generated by `mips_core::bench` and don't need any system file.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use mips_core::bench::{Audio, Interpreter, Workload};
+
+const INSTRUCTIONS: u32 = 10_000;
+/// A frame worth of samples
+const SAMPLES: u32 = 735;
+/// Frames run before saving, so that the state isn't the one of a console just reset
+const WARMUP_FRAMES: u32 = 10;
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+    group.throughput(Throughput::Elements(INSTRUCTIONS.into()));
+
+    for workload in Workload::ALL {
+        let mut interpreter = Interpreter::new(workload);
+
+        group.bench_function(workload.name(), |b| {
+            b.iter(|| interpreter.run_instructions(INSTRUCTIONS))
+        });
+    }
+
+    group.finish();
+}
+
+fn spu(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spu");
+    group.throughput(Throughput::Elements(SAMPLES.into()));
+
+    let mut audio = Audio::new();
+    group.bench_function("mix", |b| b.iter(|| audio.run_samples(SAMPLES)));
+
+    group.finish();
+}
+
+fn savestate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("savestate");
+
+    let mut interpreter = Interpreter::new(Workload::Memory);
+    for _ in 0..WARMUP_FRAMES {
+        interpreter.run_frame();
+    }
+    let state = interpreter.save_state();
+    let raw = interpreter.save_state_raw();
+
+    group.bench_function("save", |b| b.iter(|| interpreter.save_state()));
+    group.bench_function("save_raw", |b| b.iter(|| interpreter.save_state_raw()));
+    group.bench_function("load", |b| {
+        b.iter_batched(|| state.clone(), |state| interpreter.load_state(&state), BatchSize::LargeInput)
+    });
+    group.bench_function("load_raw", |b| {
+        b.iter_batched(|| raw.clone(), |raw| interpreter.load_state(&raw), BatchSize::LargeInput)
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, interpreter, spu, savestate);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+/// Sample rate of every console's audio output
+pub const AUDIO_FREQ_HZ: u32 = 44_100;
+
+/// Audio produced by the console since the last time it was taken: interleaved left/right pairs at
+/// `AUDIO_FREQ_HZ`
+#[derive(Default)]
+pub struct AudioChunk {
+    /// Position of the first stereo pair in the console's audio stream, counted in pairs since
+    /// power on. The next chunk starts at `timestamp + frames()` unless samples were dropped in
+    /// between.
+    pub timestamp: u64,
+    pub samples: Vec<i16>,
+}
+
+impl AudioChunk {
+    /// Number of stereo pairs
+    pub fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Timestamp of the end of the chunk, in seconds of emulated time
+    pub fn end_time(&self) -> f64 {
+        (self.timestamp + self.frames() as u64) as f64 / f64::from(AUDIO_FREQ_HZ)
+    }
+}
+
+/// What the console mixes into its sound output
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AudioSource {
+    /// The SPU voices, the sound effects and sequenced music, with the reverb
+    Spu,
+    /// Audio tracks of the disc
+    CdDa,
+    /// XA ADPCM streamed from data sectors, the usual music and voice overs of FMV-heavy games
+    Xa,
+}
+
+impl AudioSource {
+    pub const ALL: [AudioSource; 3] = [AudioSource::Spu, AudioSource::CdDa, AudioSource::Xa];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioSource::Spu => "SPU voices",
+            AudioSource::CdDa => "CD audio",
+            AudioSource::Xa => "XA streams",
+        }
+    }
+}
+
+/// Volume of each `AudioSource`, on top of the game's own. The console's state doesn't change with
+/// it (the reverb and the capture buffers still get everything), only what comes out.
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct AudioMix {
+    /// Indexed by `AudioSource`, 0 to 1
+    volumes: [f32; 3],
+}
+
+impl AudioMix {
+    pub fn volume(&self, source: AudioSource) -> f32 {
+        self.volumes[source as usize]
+    }
+
+    pub fn set_volume(&mut self, source: AudioSource, volume: f32) {
+        self.volumes[source as usize] = volume.clamp(0., 1.);
+    }
+
+    /// Volume of `source` as a 1.15 fixed point multiplier
+    pub(crate) fn gain(&self, source: AudioSource) -> i32 {
+        (self.volume(source) * 32768.) as i32
+    }
+}
+
+impl Default for AudioMix {
+    fn default() -> Self {
+        AudioMix { volumes: [1.; 3] }
+    }
+}
+
+/// Audio played in place of the CD-DA tracks of the discs, provided by the frontend (a remastered
+/// soundtrack, say)
+pub trait Soundtrack: Send + Sync {
+    /// Replacement of track `track`, numbered from 1, or `None` to play the disc's
+    fn open_track(&self, track: u8) -> Option<Box<dyn TrackAudio>>;
+}
+
+/// Replacement audio of a track, at `AUDIO_FREQ_HZ` in stereo like the CD-DA it replaces
+pub trait TrackAudio: Send {
+    /// Fill `samples` with the interleaved left/right pairs from pair `position` on, counted from
+    /// the start of the track. Past the end of the audio it's silence.
+    fn read(&mut self, position: u64, samples: &mut [i16]);
+}
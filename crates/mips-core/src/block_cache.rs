@@ -0,0 +1,32 @@
+//! Block cache of the recompiler: the blocks of game code it translated, how often they ran and
+//! how often the game overwrote them, to find what a recompiler bug or a slowdown comes from.
+//!
+//! The execution counts start over when a block is translated again. The invalidations are counted
+//! by address across translations, a block that keeps being invalidated is self-modifying code (or
+//! code sharing its page with data the game writes).
+
+/// Block of game code translated by the recompiler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledBlock {
+    /// Address of the first instruction
+    pub start: u32,
+    /// Address past the last instruction, the delay slot of a final branch included
+    pub end: u32,
+    /// Size of the host code, in bytes, both versions of the block included
+    pub host_size: usize,
+    /// Times the block was entered since it was translated
+    pub executions: u64,
+    /// Times a block starting at `start` was dropped because its code was written to
+    pub invalidations: u32,
+}
+
+impl CompiledBlock {
+    /// Instructions in the block
+    pub fn len(&self) -> u32 {
+        self.end.wrapping_sub(self.start) / 4
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,235 @@
+//! GameShark cheat codes, and the databases listing them per game.
+//!
+//! The database format is the one used by the community cheat lists: a `:SERIAL` line starts the
+//! entries of a game, a `[Name]` line starts a cheat and the following lines hold its codes, one
+//! `AAAAAAAA VVVV` pair per line. Lines starting with `;` or `#` are comments.
+//!
+//! ```text
+//! :SCUS-94300
+//! [Widescreen 16:9]
+//! 8001a2b4 0c00
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use crate::error::{MipsError, MipsResult};
+
+/// Access to the console RAM for the codes, addresses are offsets in the RAM
+pub trait CheatMemory {
+    fn read8(&self, addr: u32) -> u8;
+    fn read16(&self, addr: u32) -> u16;
+    fn write8(&mut self, addr: u32, val: u8);
+    fn write16(&mut self, addr: u32, val: u16);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatCode {
+    /// 30: constant 8bit write
+    Write8 { addr: u32, val: u8 },
+    /// 80: constant 16bit write
+    Write16 { addr: u32, val: u16 },
+    /// 10: 16bit increment
+    Increment16 { addr: u32, val: u16 },
+    /// 11: 16bit decrement
+    Decrement16 { addr: u32, val: u16 },
+    /// 20: 8bit increment
+    Increment8 { addr: u32, val: u8 },
+    /// 21: 8bit decrement
+    Decrement8 { addr: u32, val: u8 },
+    /// D0/D1: run the next code only if the 16bit value is (D0) or isn't (D1) `val`
+    If16 { addr: u32, val: u16, equal: bool },
+    /// E0/E1: run the next code only if the 8bit value is (E0) or isn't (E1) `val`
+    If8 { addr: u32, val: u8, equal: bool },
+}
+
+impl CheatCode {
+    /// Parse an `AAAAAAAA VVVV` line
+    pub fn parse(line: &str) -> MipsResult<CheatCode> {
+        let invalid = || MipsError::InvalidCheat(line.to_string());
+
+        let (target, value) = line.trim().split_once(char::is_whitespace).ok_or_else(invalid)?;
+        if target.len() != 8 || value.trim().len() != 4 {
+            return Err(invalid());
+        }
+
+        let target = u32::from_str_radix(target, 16).map_err(|_| invalid())?;
+        let val = u16::from_str_radix(value.trim(), 16).map_err(|_| invalid())?;
+
+        let addr = target & 0x1f_ffff;
+        let val8 = val as u8;
+
+        let code = match target >> 24 {
+            0x30 => CheatCode::Write8 { addr, val: val8 },
+            0x80 => CheatCode::Write16 { addr, val },
+            0x10 => CheatCode::Increment16 { addr, val },
+            0x11 => CheatCode::Decrement16 { addr, val },
+            0x20 => CheatCode::Increment8 { addr, val: val8 },
+            0x21 => CheatCode::Decrement8 { addr, val: val8 },
+            0xd0 => CheatCode::If16 { addr, val, equal: true },
+            0xd1 => CheatCode::If16 { addr, val, equal: false },
+            0xe0 => CheatCode::If8 { addr, val: val8, equal: true },
+            0xe1 => CheatCode::If8 { addr, val: val8, equal: false },
+            _ => return Err(invalid()),
+        };
+
+        Ok(code)
+    }
+}
+
+/// The `AAAAAAAA VVVV` line `parse` takes
+impl fmt::Display for CheatCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kind, addr, val): (u32, u32, u16) = match *self {
+            CheatCode::Write8 { addr, val } => (0x30, addr, val.into()),
+            CheatCode::Write16 { addr, val } => (0x80, addr, val),
+            CheatCode::Increment16 { addr, val } => (0x10, addr, val),
+            CheatCode::Decrement16 { addr, val } => (0x11, addr, val),
+            CheatCode::Increment8 { addr, val } => (0x20, addr, val.into()),
+            CheatCode::Decrement8 { addr, val } => (0x21, addr, val.into()),
+            CheatCode::If16 { addr, val, equal } => (if equal { 0xd0 } else { 0xd1 }, addr, val),
+            CheatCode::If8 { addr, val, equal } => (if equal { 0xe0 } else { 0xe1 }, addr, val.into()),
+        };
+
+        write!(f, "{:08X} {:04X}", (kind << 24) | addr, val)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub codes: Vec<CheatCode>,
+}
+
+impl Cheat {
+    /// Run the codes, meant to be called once per frame
+    pub fn apply(&self, mem: &mut dyn CheatMemory) {
+        let mut skip = false;
+
+        for &code in &self.codes {
+            if skip {
+                skip = false;
+                continue;
+            }
+
+            match code {
+                CheatCode::Write8 { addr, val } => mem.write8(addr, val),
+                CheatCode::Write16 { addr, val } => mem.write16(addr, val),
+                CheatCode::Increment16 { addr, val } => {
+                    mem.write16(addr, mem.read16(addr).wrapping_add(val))
+                }
+                CheatCode::Decrement16 { addr, val } => {
+                    mem.write16(addr, mem.read16(addr).wrapping_sub(val))
+                }
+                CheatCode::Increment8 { addr, val } => {
+                    mem.write8(addr, mem.read8(addr).wrapping_add(val))
+                }
+                CheatCode::Decrement8 { addr, val } => {
+                    mem.write8(addr, mem.read8(addr).wrapping_sub(val))
+                }
+                CheatCode::If16 { addr, val, equal } => skip = (mem.read16(addr) == val) != equal,
+                CheatCode::If8 { addr, val, equal } => skip = (mem.read8(addr) == val) != equal,
+            }
+        }
+    }
+}
+
+/// Cheats of many games, keyed by disc serial number
+#[derive(Debug, Default)]
+pub struct CheatDatabase {
+    games: HashMap<String, Vec<Cheat>>,
+}
+
+impl CheatDatabase {
+    pub fn parse(text: &str) -> MipsResult<CheatDatabase> {
+        let mut db = CheatDatabase::default();
+        let mut serial: Option<String> = None;
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(s) = line.strip_prefix(':') {
+                serial = Some(s.trim().to_ascii_uppercase());
+                continue;
+            }
+
+            let Some(serial) = &serial else {
+                return Err(MipsError::InvalidCheat(format!("{} (no game)", line)));
+            };
+            let cheats = db.games.entry(serial.clone()).or_default();
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                cheats.push(Cheat { name: name.to_string(), codes: Vec::new() });
+                continue;
+            }
+
+            match cheats.last_mut() {
+                Some(cheat) => cheat.codes.push(CheatCode::parse(line)?),
+                None => return Err(MipsError::InvalidCheat(format!("{} (no cheat name)", line))),
+            }
+        }
+
+        Ok(db)
+    }
+
+    /// Cheats for `serial`, empty if the game isn't in the database
+    pub fn cheats(&self, serial: &str) -> &[Cheat] {
+        self.games
+            .get(&serial.to_ascii_uppercase())
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+#[test]
+fn parse_and_apply() {
+    struct Ram([u8; 16]);
+
+    impl CheatMemory for Ram {
+        fn read8(&self, addr: u32) -> u8 {
+            self.0[addr as usize]
+        }
+
+        fn read16(&self, addr: u32) -> u16 {
+            u16::from_le_bytes([self.0[addr as usize], self.0[addr as usize + 1]])
+        }
+
+        fn write8(&mut self, addr: u32, val: u8) {
+            self.0[addr as usize] = val;
+        }
+
+        fn write16(&mut self, addr: u32, val: u16) {
+            self.0[addr as usize..addr as usize + 2].copy_from_slice(&val.to_le_bytes());
+        }
+    }
+
+    let db = CheatDatabase::parse(
+        "; Test database\n\
+         :SLUS-00001\n\
+         [Widescreen]\n\
+         80000002 1234\n\
+         E0000000 0001\n\
+         30000004 00ff\n\
+         D1000002 1234\n\
+         30000005 00ff\n",
+    )
+    .unwrap();
+
+    let cheats = db.cheats("slus-00001");
+    assert_eq!(cheats.len(), 1);
+    assert_eq!(cheats[0].codes.len(), 5);
+    assert!(db.cheats("SLUS-00002").is_empty());
+
+    let mut ram = Ram([0; 16]);
+    ram.0[0] = 1;
+    cheats[0].apply(&mut ram);
+
+    assert_eq!(ram.read16(2), 0x1234);
+    assert_eq!(ram.0[4], 0xff);
+    // The condition is false since the first code just wrote 0x1234
+    assert_eq!(ram.0[5], 0);
+
+    assert!(CheatCode::parse("12345678 0000").is_err());
+    assert_eq!(cheats[0].codes[2].to_string(), "30000004 00FF");
+    assert!(CheatDatabase::parse("80000002 1234").is_err());
+}
@@ -0,0 +1,95 @@
+//! Compatibility database: what we know about specific games that can't be detected from the disc
+//! itself, keyed by disc serial number. The quirks are applied by the console on top of the
+//! settings when it powers on with the game's disc, entries get some as games are found to need
+//! them.
+
+use crate::input::ControllerType;
+use crate::ps1::Region;
+
+/// Workarounds for the games that don't run right with the settings the user picked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// Run the CPU with the interpreter even when the dynarec is picked
+    pub interpreter: bool,
+    /// Leave the GTE instructions to the interpreter when the dynarec runs the rest of the code
+    pub interpreter_gte: bool,
+    /// Plug the DualShock with the analog mode already on, for the games that never turn it on
+    /// themselves and don't tell the player to press ANALOG
+    pub analog: bool,
+}
+
+impl Quirks {
+    const NONE: Quirks = Quirks { interpreter: false, interpreter_gte: false, analog: false };
+}
+
+pub struct GameCompat {
+    pub serial: &'static str,
+    pub title: &'static str,
+    /// Where the game was released, from the publisher's catalog rather than the serial's prefix
+    pub region: Region,
+    /// False for games that don't work with (or don't recognize) an analog controller
+    pub dualshock: bool,
+    pub quirks: Quirks,
+}
+
+static GAMES: &[GameCompat] = &[
+    GameCompat {
+        serial: "SCES-00001",
+        title: "Ridge Racer",
+        region: Region::Europe,
+        dualshock: false,
+        quirks: Quirks::NONE,
+    },
+    GameCompat {
+        serial: "SCUS-94003",
+        title: "Battle Arena Toshinden",
+        region: Region::NorthAmerica,
+        dualshock: false,
+        quirks: Quirks::NONE,
+    },
+    GameCompat {
+        serial: "SCUS-94300",
+        title: "Ridge Racer",
+        region: Region::NorthAmerica,
+        dualshock: false,
+        quirks: Quirks::NONE,
+    },
+];
+
+/// Entry for `serial`, if the game is in the database
+pub fn lookup(serial: &str) -> Option<&'static GameCompat> {
+    GAMES.iter().find(|g| g.serial.eq_ignore_ascii_case(serial))
+}
+
+/// Controller to plug in for `serial`: a digital pad for the games known not to support the
+/// DualShock, a DualShock otherwise
+pub fn preferred_controller(serial: &str) -> ControllerType {
+    match lookup(serial) {
+        Some(game) if !game.dualshock => ControllerType::DigitalPad,
+        _ => ControllerType::DualShock,
+    }
+}
+
+/// Region the game with the disc `serial` was released in, `None` for the games not in the
+/// database
+pub fn region(serial: &str) -> Option<Region> {
+    lookup(serial).map(|game| game.region)
+}
+
+/// Quirks of the game with the disc `serial`, none for the games not in the database
+pub fn quirks(serial: &str) -> Quirks {
+    lookup(serial).map_or_else(Quirks::default, |game| game.quirks)
+}
+
+#[test]
+fn controller_lookup() {
+    assert_eq!(preferred_controller("scus-94300"), ControllerType::DigitalPad);
+    assert_eq!(preferred_controller("SCUS-94194"), ControllerType::DualShock);
+}
+
+#[test]
+fn region_lookup() {
+    assert_eq!(region("sces-00001"), Some(Region::Europe));
+    assert_eq!(region("SCUS-94194"), None);
+    assert_eq!(quirks("SCUS-94194"), Quirks::default());
+}
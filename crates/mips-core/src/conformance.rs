@@ -0,0 +1,113 @@
+//! Instruction set conformance: runs test programs that check the CPU and GTE against the real
+//! hardware (amidog's `psxtest_cpu.exe` and `psxtest_gte.exe`) and reads their results from the
+//! lines they print, so that accuracy can be tracked from one commit to the next.
+
+use std::fmt;
+use crate::ConsoleManager;
+
+/// The test programs print nothing for a while when they're done, give up waiting after that
+/// many frames without a line
+pub const IDLE_FRAMES: u32 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+}
+
+#[derive(Debug, Default)]
+pub struct ConformanceReport {
+    /// Everything the program printed
+    pub lines: Vec<String>,
+    pub passed: usize,
+    /// Lines reporting a failure
+    pub failures: Vec<String>,
+    /// False if the program was still printing when the frame limit was hit
+    pub finished: bool,
+}
+
+impl ConformanceReport {
+    pub fn success(&self) -> bool {
+        self.finished && self.failures.is_empty() && self.passed > 0
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for failure in &self.failures {
+            writeln!(f, "FAILED: {}", failure)?;
+        }
+
+        write!(f, "{} passed, {} failed", self.passed, self.failures.len())?;
+        if !self.finished {
+            write!(f, " (timed out)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Result reported by a line, if it's the end of a test. The programs print the name of each
+/// test followed by "ok", or what went wrong.
+pub fn parse_line(line: &str) -> Option<Outcome> {
+    let line = line.to_ascii_lowercase();
+    let last_word = line.split(|c: char| !c.is_ascii_alphanumeric()).rfind(|w| !w.is_empty());
+
+    if line.contains("fail") || line.contains("error") {
+        Some(Outcome::Failed)
+    } else if matches!(last_word, Some("ok" | "passed")) {
+        Some(Outcome::Passed)
+    } else {
+        None
+    }
+}
+
+/// Run the program loaded in `console` until it goes quiet for `IDLE_FRAMES` frames, or for at
+/// most `max_frames` frames
+pub fn run(console: &mut ConsoleManager, max_frames: u32) -> ConformanceReport {
+    let mut report = ConformanceReport::default();
+    let mut idle = 0;
+
+    console.set_tty_capture(true);
+
+    for _ in 0..max_frames {
+        console.update();
+        // Nobody's watching
+        console.get_frame();
+        console.take_audio();
+
+        let lines = console.take_tty_lines();
+        if lines.is_empty() {
+            idle += 1;
+            if idle >= IDLE_FRAMES && !report.lines.is_empty() {
+                report.finished = true;
+                break;
+            }
+            continue;
+        }
+
+        idle = 0;
+        for line in lines {
+            match parse_line(&line) {
+                Some(Outcome::Passed) => report.passed += 1,
+                Some(Outcome::Failed) => report.failures.push(line.clone()),
+                None => (),
+            }
+            report.lines.push(line);
+        }
+    }
+
+    console.set_tty_capture(false);
+
+    report
+}
+
+#[test]
+fn result_lines() {
+    assert_eq!(parse_line("add ... ok"), Some(Outcome::Passed));
+    assert_eq!(parse_line("RTPS: OK\r"), Some(Outcome::Passed));
+    assert_eq!(parse_line("lwl failed: expected 12345678 got 00005678"), Some(Outcome::Failed));
+    assert_eq!(parse_line("CPU tests by amidog"), None);
+    assert_eq!(parse_line("Press a button to look"), None);
+    assert_eq!(parse_line(""), None);
+}
@@ -0,0 +1,73 @@
+//! CPU trace: the instructions executed, in order, to diff the run of a test program against a
+//! known good one. Each entry is the address of the instruction and its machine code.
+//!
+//! Tracing runs the interpreter, without the recompiler, and slows it down noticeably. Entries past
+//! `limit` between two `take` calls are counted but not kept, so that a program stuck in a loop
+//! doesn't use up the memory.
+
+use std::fmt;
+
+/// Entries kept between two calls to `take`: 8 frames of the CPU running flat out
+pub const DEFAULT_LIMIT: usize = 8 * 564_480;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u32,
+    pub instruction: u32,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:08x} {:08x}", self.pc, self.instruction)
+    }
+}
+
+pub struct CpuTrace {
+    entries: Vec<TraceEntry>,
+    limit: usize,
+    /// Entries past `limit` since the last `take`
+    dropped: u64,
+}
+
+impl CpuTrace {
+    pub fn new(limit: usize) -> CpuTrace {
+        CpuTrace {
+            entries: Vec::new(),
+            limit,
+            dropped: 0,
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, pc: u32, instruction: u32) {
+        if self.entries.len() < self.limit {
+            self.entries.push(TraceEntry { pc, instruction });
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    /// The entries recorded since the last call, and the number of the ones dropped
+    pub fn take(&mut self) -> (Vec<TraceEntry>, u64) {
+        let dropped = self.dropped;
+        self.dropped = 0;
+
+        (std::mem::take(&mut self.entries), dropped)
+    }
+}
+
+#[test]
+fn limit() {
+    let mut trace = CpuTrace::new(2);
+    for pc in 0..5 {
+        trace.record(0xbfc0_0000 + pc * 4, 0);
+    }
+
+    let (entries, dropped) = trace.take();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].to_string(), "bfc00004 00000000");
+    assert_eq!(dropped, 3);
+
+    trace.record(0, 0);
+    assert_eq!(trace.take(), (vec![TraceEntry { pc: 0, instruction: 0 }], 0));
+}
@@ -1,10 +1,12 @@
 use thiserror::Error;
+#[cfg(feature = "ps1")]
 use crate::ps1;
 
 pub type MipsResult<T> = Result<T, MipsError>;
 
 #[derive(Error, Debug)]
 pub enum MipsError {
+    #[cfg(feature = "ps1")]
     #[error("PS1 error: {0}")]
     Ps1Error(#[from] ps1::Ps1Error),
 
@@ -14,6 +16,28 @@ pub enum MipsError {
     #[error("Flexbuffers serialization error: {0}")]
     Flexbuffers(#[from] flexbuffers::SerializationError),
 
+    #[error("PNG encoding error: {0}")]
+    Png(#[from] png::EncodingError),
+
     #[error("Invalid state: {0}")]
     InvalidState(String),
+
+    #[error("Invalid cheat code: {0}")]
+    InvalidCheat(String),
+
+    #[cfg(feature = "ps1")]
+    #[error("No supported BIOS: {0}")]
+    NoBios(ps1::bios::BiosSearch),
+
+    #[error("Invalid startup script: {0}")]
+    InvalidScript(String),
+
+    #[error("Invalid memory card image: {0}")]
+    InvalidMemoryCard(String),
+
+    #[error("Invalid input config: {0}")]
+    InvalidInputConfig(String),
+
+    #[error("{0} are disabled in hardcore mode")]
+    Hardcore(&'static str),
 }
\ No newline at end of file
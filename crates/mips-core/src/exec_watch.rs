@@ -0,0 +1,193 @@
+//! Data execution watch: flags the code running from RAM written since it was last executed, which
+//! catches self-modifying code and code loaded at runtime. It can also stop the emulation right
+//! before such an instruction executes.
+//!
+//! Writes are tracked per 32bit word of RAM, addresses are offsets in the RAM.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecWatchSettings {
+    /// Stop the emulation before executing a written word
+    pub break_on_hit: bool,
+    /// Also track DMA writes (code loaded from the disc for instance), not only CPU stores
+    pub include_dma: bool,
+    /// Ignore the writes done more than `max_age` frames ago, 0 for no limit
+    pub max_age: u32,
+}
+
+/// Code executed from written RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecWatchHit {
+    /// Address of the first instruction executed
+    pub pc: u32,
+    /// Number of consecutive words executed that were written by the same `writer`
+    pub words: u32,
+    /// Address of the store instruction that wrote the code, `None` for DMA
+    pub writer: Option<u32>,
+    /// Number of frames between the write and the execution
+    pub age: u32,
+    /// The emulation was stopped before executing `pc`
+    pub stopped: bool,
+}
+
+/// Hits kept between two calls to `take_hits`, the next ones are dropped
+const MAX_HITS: usize = 256;
+
+/// Value of `writers` for DMA writes, instructions are always word aligned
+const DMA_WRITER: u32 = 1;
+
+pub struct ExecWatch {
+    settings: ExecWatchSettings,
+    /// Frame of the last write for every word, 0 if it wasn't written since it last ran
+    written: Vec<u32>,
+    /// Address of the store instruction that last wrote each word
+    writers: Vec<u32>,
+    /// Current frame, starts at 1
+    frame: u32,
+    hits: Vec<ExecWatchHit>,
+}
+
+impl ExecWatch {
+    /// Watch `ram_size` bytes of RAM
+    pub fn new(settings: ExecWatchSettings, ram_size: usize) -> ExecWatch {
+        ExecWatch {
+            settings,
+            written: vec![0; ram_size / 4],
+            writers: vec![0; ram_size / 4],
+            frame: 1,
+            hits: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> ExecWatchSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ExecWatchSettings) {
+        self.settings = settings;
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame = self.frame.wrapping_add(1).max(1);
+    }
+
+    /// Record a write at `offset` by the store at `writer`, `None` for DMA
+    pub fn record_write(&mut self, offset: u32, writer: Option<u32>) {
+        if writer.is_none() && !self.settings.include_dma {
+            return;
+        }
+
+        let i = (offset >> 2) as usize;
+
+        self.written[i] = self.frame;
+        self.writers[i] = writer.unwrap_or(DMA_WRITER);
+    }
+
+    /// Called before executing the instruction at `pc`, stored at `offset`. Returns true if the
+    /// emulation must stop first.
+    pub fn check_exec(&mut self, offset: u32, pc: u32) -> bool {
+        let i = (offset >> 2) as usize;
+
+        let written = self.written[i];
+        if written == 0 {
+            return false;
+        }
+        // Only report each write once
+        self.written[i] = 0;
+
+        let age = self.frame.wrapping_sub(written);
+        if self.settings.max_age != 0 && age >= self.settings.max_age {
+            return false;
+        }
+
+        let writer = match self.writers[i] {
+            DMA_WRITER => None,
+            w => Some(w),
+        };
+
+        // Copy loops write a whole block with the same store, there's no point reporting every
+        // word of it
+        if let Some(last) = self.hits.last_mut()
+            && last.writer == writer
+            && last.pc.wrapping_add(last.words * 4) == pc
+        {
+            last.words += 1;
+            return false;
+        }
+
+        let stopped = self.settings.break_on_hit;
+
+        if self.hits.len() < MAX_HITS {
+            self.hits.push(ExecWatchHit {
+                pc,
+                words: 1,
+                writer,
+                age,
+                stopped,
+            });
+        }
+
+        stopped
+    }
+
+    /// Hits since the last call
+    pub fn take_hits(&mut self) -> Vec<ExecWatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+}
+
+#[test]
+fn watch_hits() {
+    let mut watch = ExecWatch::new(ExecWatchSettings::default(), 0x100);
+
+    // Copy loop writing 3 words, then some unrelated code is written
+    for offset in [0x10, 0x14, 0x18] {
+        watch.record_write(offset, Some(0x8001_0000));
+    }
+    watch.record_write(0x40, Some(0x8001_0100));
+    // DMA is ignored by default
+    watch.record_write(0x80, None);
+    watch.next_frame();
+
+    assert!(!watch.check_exec(0x0c, 0x8000_000c));
+    for offset in [0x10, 0x14, 0x18, 0x1c] {
+        assert!(!watch.check_exec(offset, 0x8000_0000 + offset));
+    }
+    assert!(!watch.check_exec(0x40, 0x8000_0040));
+    assert!(!watch.check_exec(0x80, 0x8000_0080));
+
+    let hits = watch.take_hits();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0], ExecWatchHit {
+        pc: 0x8000_0010,
+        words: 3,
+        writer: Some(0x8001_0000),
+        age: 1,
+        stopped: false,
+    });
+    assert_eq!(hits[1].writer, Some(0x8001_0100));
+
+    // Each write is only reported once
+    assert!(!watch.check_exec(0x10, 0x8000_0010));
+    assert!(watch.take_hits().is_empty());
+
+    watch.set_settings(ExecWatchSettings {
+        break_on_hit: true,
+        include_dma: true,
+        max_age: 2,
+    });
+    watch.record_write(0x80, None);
+    watch.record_write(0x90, Some(0x8001_0000));
+    watch.next_frame();
+    watch.next_frame();
+
+    // Too old
+    assert!(!watch.check_exec(0x80, 0xa000_0080));
+    assert!(!watch.check_exec(0x90, 0x8000_0090));
+    assert!(watch.take_hits().is_empty());
+
+    watch.record_write(0x80, None);
+    assert!(watch.check_exec(0x80, 0xa000_0080));
+    let hits = watch.take_hits();
+    assert_eq!(hits[0].writer, None);
+    assert!(hits[0].stopped);
+}
@@ -1,9 +1,77 @@
+use std::hash::Hasher;
+use fnv::FnvHasher;
+use crate::error::MipsResult;
+#[cfg(feature = "ps1")]
 use crate::ps1::Ps1Frame;
 
+pub use mips_psx_gpu::handle::PixelFormat;
+
+#[derive(Clone)]
 pub struct CpuFrame {
     pub pixels: Vec<u32>,
     pub width: u32,
     pub height: u32,
+    pub format: PixelFormat,
+    /// Aspect ratio the picture is meant to be shown at, `None` for the ratio of its dimensions
+    pub aspect: Option<f32>,
+}
+
+impl CpuFrame {
+    /// FNV-1a hash of the pixels, to compare pictures between runs or machines without shipping
+    /// them around
+    pub fn hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        for pixel in &self.pixels {
+            hasher.write(&pixel.to_le_bytes());
+        }
+
+        hasher.finish()
+    }
+
+    /// The picture shrunk to `width` x `height`, each pixel the average of the ones it covers.
+    /// Meant for thumbnails: the result is smaller than the frame, or the same size.
+    pub fn downscale(&self, width: u32, height: u32) -> CpuFrame {
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+
+        for y in 0..height {
+            let top = y * self.height / height;
+            let bottom = ((y + 1) * self.height / height).max(top + 1);
+            for x in 0..width {
+                let left = x * self.width / width;
+                let right = ((x + 1) * self.width / width).max(left + 1);
+
+                let mut sum = [0u32; 3];
+                for row in top..bottom {
+                    for &pixel in &self.pixels[(row * self.width + left) as usize..(row * self.width + right) as usize] {
+                        sum[0] += (pixel >> 16) & 0xff;
+                        sum[1] += (pixel >> 8) & 0xff;
+                        sum[2] += pixel & 0xff;
+                    }
+                }
+                let count = (bottom - top) * (right - left);
+                pixels.push(((sum[0] / count) << 16) | ((sum[1] / count) << 8) | (sum[2] / count));
+            }
+        }
+
+        CpuFrame { pixels, width, height, format: self.format, aspect: self.aspect }
+    }
+
+    /// The picture as a PNG file
+    pub fn to_png(&self) -> MipsResult<Vec<u8>> {
+        let mut png = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut writer = encoder.write_header()?;
+
+        let rgb: Vec<u8> = self.pixels.iter()
+            .flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+            .collect();
+        writer.write_image_data(&rgb)?;
+        writer.finish()?;
+
+        Ok(png)
+    }
 }
 
 #[cfg(feature = "ps1")]
@@ -13,6 +81,8 @@ impl From<Ps1Frame> for CpuFrame {
             width: frame.width,
             height: frame.height,
             pixels: frame.pixels,
+            format: frame.format,
+            aspect: None,
         }
     }
 }
\ No newline at end of file
@@ -1,15 +1,60 @@
 mod pad;
 
 use std::collections::HashMap;
+#[cfg(feature = "std")]
 use std::path::Path;
+use std::time::Instant;
 use ini::Ini;
 use log::warn;
 use num_traits::FromPrimitive;
+use crate::error::{MipsError, MipsResult};
 
 pub use crate::input::pad::{Button, ButtonState};
 
 pub type ButtonQueue = Vec<(ButtonState, Button)>;
 
+/// A button change and when it happened on the host
+#[derive(Copy, Clone, Debug)]
+pub struct InputEvent {
+    pub state: ButtonState,
+    pub button: Button,
+    pub time: Instant,
+}
+
+/// The changes of `events` without their time, to be applied at the start of the frame
+pub fn untimed(events: &[InputEvent]) -> ButtonQueue {
+    events.iter().map(|e| (e.state, e.button)).collect()
+}
+
+/// Buttons and motion of the PlayStation Mouse
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct MouseInput {
+    /// Motion since the last input, in mouse counts. Positive is right and down.
+    pub dx: i32,
+    pub dy: i32,
+    pub left: bool,
+    pub right: bool,
+}
+
+/// Aim and buttons of a lightgun
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct LightgunInput {
+    /// Where the gun points, as a fraction of the width and height of the game picture (0 to 1, from
+    /// the top left corner). `None` points away from the screen, that's how the games reload.
+    pub position: Option<(f32, f32)>,
+    pub trigger: bool,
+    pub a: bool,
+    pub b: bool,
+}
+
+/// Positions of the DualShock sticks, each `(x, y)` from -0x8000 to 0x7fff. Positive is right and
+/// down.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct AnalogInput {
+    pub left: (i16, i16),
+    pub right: (i16, i16),
+}
+
 #[derive(Hash, Copy, Clone, Eq, PartialEq, Debug)]
 pub enum DeviceType {
     Unknown,
@@ -17,42 +62,101 @@ pub enum DeviceType {
     DualShock,
 }
 
+/// Type of controller emulated on a console port
+#[derive(serde::Serialize, serde::Deserialize, Hash, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ControllerType {
+    Disconnected,
+    DigitalPad,
+    DualShock,
+    Mouse,
+    NeGcon,
+    GunCon,
+    Justifier,
+}
+
+impl ControllerType {
+    pub const ALL: [ControllerType; 7] = [
+        ControllerType::DigitalPad,
+        ControllerType::DualShock,
+        ControllerType::Mouse,
+        ControllerType::NeGcon,
+        ControllerType::GunCon,
+        ControllerType::Justifier,
+        ControllerType::Disconnected,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ControllerType::Disconnected => "Disconnected",
+            ControllerType::DigitalPad => "Digital Pad",
+            ControllerType::DualShock => "DualShock",
+            ControllerType::Mouse => "Mouse",
+            ControllerType::NeGcon => "NeGcon",
+            ControllerType::GunCon => "GunCon",
+            ControllerType::Justifier => "Justifier",
+        }
+    }
+}
+
+/// Input profile in the ini format used before the frontend had its own configuration
 pub struct InputConfig {
     device_type: DeviceType,
+    /// Device input names (SDL key or controller button names) to console buttons
     bindings: HashMap<String, Button>
 }
 
 impl InputConfig {
     pub fn write(&self) { todo!() }
 
-    pub fn bindings(&self) -> HashMap<String, Button> {
-        self.bindings.clone()
+    #[cfg(feature = "std")]
+    pub fn load(path: &Path) -> MipsResult<Self> {
+        let ini = Ini::load_from_file(path)
+            .map_err(|e| MipsError::InvalidInputConfig(format!("{}: {}", path.display(), e)))?;
+
+        Self::from_ini(&ini)
+            .map_err(|e| MipsError::InvalidInputConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn parse(text: &str) -> MipsResult<Self> {
+        let ini = Ini::load_from_str(text).map_err(|e| MipsError::InvalidInputConfig(e.to_string()))?;
+
+        Self::from_ini(&ini).map_err(MipsError::InvalidInputConfig)
     }
-}
 
-impl From<&Path> for InputConfig {
-    fn from(path: &Path) -> Self {
-        let ini = Ini::load_from_file(path).unwrap();
-        let device_type = ini.section(Some("Device")).unwrap().get("Type").unwrap();
-        let bindings_sec = ini.section(Some("Bindings")).unwrap();
+    fn from_ini(ini: &Ini) -> Result<Self, String> {
+        let device_type = ini.section(Some("Device"))
+            .and_then(|device| device.get("Type"))
+            .ok_or("missing Type in [Device]")?;
+        let bindings_sec = ini.section(Some("Bindings")).ok_or("missing [Bindings]")?;
 
-        let mut device_type = match device_type {
+        let device_type = match device_type {
             "Keyboard" => DeviceType::Keyboard,
             "Dualshock" => DeviceType::DualShock,
             _ => {
-                warn!("Unknown device type in input config file {}: DeviceType = {}", path.display(), device_type);
+                warn!("Unknown device type in input config file: DeviceType = {}", device_type);
                 DeviceType::Unknown
             },
         };
 
         let mut bindings = HashMap::new();
         for (device_input, psx_input) in bindings_sec {
-            bindings.insert(device_input.to_string(), Button::from_u32(psx_input.parse::<u32>().unwrap()).unwrap());
+            let button = psx_input.parse::<u32>().ok()
+                .and_then(Button::from_u32)
+                .ok_or_else(|| format!("invalid button {} for {}", psx_input, device_input))?;
+            bindings.insert(device_input.to_string(), button);
         }
 
-        InputConfig {
+        Ok(InputConfig {
             device_type,
             bindings
-        }
+        })
+    }
+
+    pub fn device_type(&self) -> DeviceType {
+        self.device_type
+    }
+
+    pub fn bindings(&self) -> HashMap<String, Button> {
+        self.bindings.clone()
     }
 }
\ No newline at end of file
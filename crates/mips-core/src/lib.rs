@@ -1,46 +1,731 @@
-use std::path::Path;
-use crate::input::{ButtonQueue, DeviceType};
-use crate::ps1::Ps1;
+// The consoles and their manager
+#[cfg(feature = "ps1")]
+use {
+    std::ops::Range,
+    std::path::Path,
+    std::sync::Arc,
+    std::time::{Duration, Instant},
+    crate::audio::{AudioMix, Soundtrack},
+    crate::cheats::Cheat,
+    crate::block_cache::CompiledBlock,
+    crate::cpu_trace::TraceEntry,
+    crate::exec_watch::{ExecWatchHit, ExecWatchSettings},
+    crate::script::StartupScript,
+    crate::input::{AnalogInput, ButtonQueue, ControllerType, InputEvent, LightgunInput, MouseInput},
+    crate::ps1::Ps1,
+    crate::rewind::{RewindBuffer, RewindSettings},
+    crate::vfs::Vfs,
+};
+#[cfg(all(feature = "ps1", feature = "std"))]
+use std::path::PathBuf;
 
+pub mod audio;
+pub mod block_cache;
+pub mod cheats;
+#[cfg(feature = "ps1")]
+pub mod compat;
+#[cfg(feature = "ps1")]
+pub mod conformance;
+pub mod cpu_trace;
+pub mod exec_watch;
 pub mod input;
+pub mod ram_search;
+pub mod rewind;
+pub mod script;
+pub mod stats;
+pub mod vfs;
 mod error;
 
 #[cfg(feature = "ps1")]
 mod ps1;
+/// Interpreter workloads for `benches/`
+#[cfg(all(feature = "ps1", feature = "bench"))]
+#[doc(hidden)]
+pub use ps1::bench;
 mod gfx;
 
+#[cfg(feature = "ps1")]
+pub use ps1::{
+    bios, CpuBackend, CpuClock, DebugRender, InternalResolution, Interpolation, MemoryCardScope, Region,
+    RendererBackend, Wireframe,
+};
+#[cfg(all(feature = "ps1", feature = "std"))]
+pub use ps1::disc_count;
+/// Compressed copy of a state made by `ConsoleManager::save_state_raw`, to compress it off the
+/// emulation thread
+#[cfg(feature = "ps1")]
+pub use ps1::compress_state;
+#[cfg(feature = "ps1")]
+pub use ps1::state_thumbnail;
+/// Saves on the memory card images, see `mem_card::Fs`
+#[cfg(feature = "ps1")]
+pub use ps1::mem_card;
+pub use audio::AudioChunk;
 pub use error::MipsError;
+pub use gfx::{CpuFrame, PixelFormat};
+pub use stats::FrameStats;
+/// Disc image reading, for the hosts implementing `cdimage::Image` over their own storage, see
+/// `ConsoleManager::load_disc_source`
+pub use cdimage;
+#[cfg(feature = "ps1")]
 use crate::error::MipsResult;
-use crate::gfx::CpuFrame;
 
+#[cfg(feature = "ps1")]
 pub trait Console {
-    fn update(&mut self);
+    /// Emulate a frame
+    fn update(&mut self) -> FrameStats;
+    /// Generate a frame's worth of audio with the game frozen, for the sound to carry on while the
+    /// frontend is paused in a menu. Nothing else moves, the game resumes exactly where it was.
+    fn update_paused(&mut self);
     fn get_frame(&mut self) -> Option<CpuFrame>;
-    fn get_audio_samples(&mut self) -> &[i16];
-    fn clear_audio_samples(&mut self);
-    fn connect_device(&mut self, port: usize, device_type: DeviceType);
+    /// Audio generated since the last call. Must be called at least once per frame, the console
+    /// only buffers a frame's worth of samples.
+    fn take_audio(&mut self) -> AudioChunk;
+    /// Plug a controller of type `device_type` in `port`. Types the console doesn't emulate leave
+    /// the port disconnected.
+    fn connect_device(&mut self, port: usize, device_type: ControllerType);
+    /// Type of the controller currently plugged in `port`
+    fn device_type(&self, port: usize) -> ControllerType;
+    /// Serial number of the disc in the drive (e.g. "SCUS-94300")
+    fn serial_number(&self) -> Option<String>;
+    /// Open the drive's shell and take the disc out. It stays open until `swap_disc`.
+    fn eject_disc(&mut self);
+    /// Put the disc image `disc_path` (relative to the games directory) in the drive, opening the
+    /// shell first if a disc is in. The shell closes half a second later, for the game to notice
+    /// the change: that's how the multi-disc games see that the next disc was inserted.
+    #[cfg(feature = "std")]
+    fn swap_disc(&mut self, disc_path: &str) -> MipsResult<()>;
+    /// `swap_disc` with the disc image handed over in memory
+    #[cfg(feature = "std")]
+    fn swap_disc_image(&mut self, image: &[u8]) -> MipsResult<()>;
+    /// `swap_disc` with the disc read through `source`
+    fn swap_disc_source(&mut self, source: Box<dyn cdimage::Image + Send>) -> MipsResult<()>;
+    /// True while the drive's shell is open
+    fn is_shell_open(&self) -> bool;
     fn handle_inputs(&mut self, inputs: ButtonQueue);
+    fn handle_port_inputs(&mut self, port: usize, inputs: ButtonQueue);
+    /// Buttons and motion of the mouse in `port`, ignored by the other controllers
+    fn handle_mouse(&mut self, port: usize, input: MouseInput);
+    /// Aim and buttons of the lightgun in `port`, ignored by the other controllers
+    fn handle_lightgun(&mut self, port: usize, input: LightgunInput);
+    /// Sticks of the DualShock in `port`, the left one twists a neGcon. Ignored by the other
+    /// controllers. Expected once per frame: the pad counts the frames with it to fall back to
+    /// digital mode.
+    fn handle_analog(&mut self, port: usize, input: AnalogInput);
+    /// Strength of the big (left) and small (right) rumble motors of the controller in `port`
+    fn rumble(&self, port: usize) -> (u8, u8);
+    /// Like `handle_port_inputs`, but each change reaches the pad when the game polls it at the
+    /// same point of the next frame as the input happened within `window`, the span of host time
+    /// the frame stands for
+    fn handle_timed_inputs(&mut self, port: usize, inputs: &[InputEvent], window: Range<Instant>);
     fn refresh_devices(&mut self);
+    /// Contents of the memory card in `slot`, if one is connected. Error if there's no such slot.
+    fn memory_card_image(&self, slot: usize) -> MipsResult<Option<Vec<u8>>>;
+    /// Replace the memory card in `slot` with one holding `image` (or disconnect it if `None`).
+    /// The new card isn't backed by any file.
+    fn set_memory_card_image(&mut self, slot: usize, image: Option<Vec<u8>>) -> MipsResult<()>;
+    /// Insert the memory card image stored at `path` in `slot`, a freshly formatted one if the file
+    /// doesn't exist yet. The game's writes are saved to the file once they stop for a second, and
+    /// when the card is replaced or the console is dropped.
+    fn set_memory_card_file(&mut self, slot: usize, path: &Path) -> MipsResult<()>;
+    /// Replace the contents of the memory card in `slot` with `image`, saved to the card's file
+    /// right away. The game sees a new card, as if it had been swapped.
+    fn rewrite_memory_card(&mut self, slot: usize, image: Vec<u8>) -> MipsResult<()>;
+    /// Keep the game's writes to the memory card in `slot` in memory instead of saving them to
+    /// the card's file. Sticks across `set_memory_card_image`.
+    fn set_memory_card_read_only(&mut self, slot: usize, read_only: bool);
+    /// Insert the cards of `scope` from the `memcards` directory of the system directory
+    fn set_memory_card_scope(&mut self, scope: MemoryCardScope);
+    /// Frame skip: don't draw anything from the next frame on, the timings are unchanged.
+    /// `get_frame` returns `None` for the frames that would show skipped drawing.
+    fn set_skip_draws(&mut self, skip: bool);
+    /// Run with NTSC timings (60 fields per second) even if the console is PAL
+    fn set_force_ntsc_timing(&mut self, force: bool);
+    /// Run the CPU with `backend`, or with the interpreter if it's not available on the host
+    fn set_cpu_backend(&mut self, backend: CpuBackend);
+    /// Run the CPU faster or slower than the rest of the console, see `CpuClock`
+    fn set_cpu_clock(&mut self, clock: CpuClock);
+    /// Draw at `resolution`, the frames returned by `get_frame` are that much larger
+    fn set_internal_resolution(&mut self, resolution: InternalResolution);
+    /// Draw with `renderer`, or in software if it can't start
+    fn set_renderer(&mut self, renderer: RendererBackend);
+    /// Draw the vertices projected by the GTE at their precise positions (PGXP)
+    fn set_pgxp(&mut self, enabled: bool);
+    /// Dither the shaded and blended draws like the console
+    fn set_dithering(&mut self, enabled: bool);
+    /// Blend the semi-transparent draws with the GPU's 15bit precision
+    fn set_accurate_transparency(&mut self, enabled: bool);
+    /// Draw with 8 bits per color component instead of 5, see `PixelFormat`
+    fn set_true_color(&mut self, enabled: bool);
+    /// Show how many times the draws wrote each pixel instead of the picture
+    fn set_overdraw_heatmap(&mut self, enabled: bool);
+    /// Rasterize the split-screen viewports on threads of their own
+    fn set_parallel_draw_areas(&mut self, enabled: bool);
+    /// Leave out parts of the drawing, see `DebugRender`
+    fn set_debug_render(&mut self, debug: DebugRender);
+    /// Project the 3D geometry for a 16:9 picture, see `CpuFrame::aspect`
+    fn set_widescreen_hack(&mut self, enabled: bool);
+    /// Resample the SPU voices with `interpolation`
+    fn set_interpolation(&mut self, interpolation: Interpolation);
+    /// Output the audio sources at the volumes of `mix`
+    fn set_audio_mix(&mut self, mix: AudioMix);
+    /// Replace the cheats applied after every frame
+    fn set_cheats(&mut self, cheats: Vec<Cheat>);
+    /// Apply an IPS or BPS patch to the game executable (sideloaded or booted from the disc),
+    /// which is then sideloaded in place of the one the BIOS would boot. Must be called before
+    /// the BIOS starts the game.
+    fn patch_executable(&mut self, patch: &Path) -> MipsResult<()>;
+    /// Run `script` once, as soon as the game executable is in RAM
+    fn set_startup_script(&mut self, script: StartupScript) -> MipsResult<()>;
+    /// Start watching for code executed from written RAM, or stop if `settings` is `None`
+    fn set_exec_watch(&mut self, settings: Option<ExecWatchSettings>);
+    /// Code executed from written RAM since the last call
+    fn take_exec_watch_hits(&mut self) -> Vec<ExecWatchHit>;
+    /// Start tracing the instructions executed, keeping up to `limit` of them between two calls to
+    /// `take_cpu_trace`, or stop if `limit` is `None`
+    fn set_cpu_trace(&mut self, limit: Option<usize>);
+    /// Instructions executed since the last call, and how many of them didn't fit
+    fn take_cpu_trace(&mut self) -> (Vec<TraceEntry>, u64);
+    /// Blocks currently translated by the recompiler, none with the interpreter
+    fn compiled_blocks(&self) -> Vec<CompiledBlock>;
+    /// Host machine code of the translated block starting at `pc`
+    fn compiled_block_code(&self, pc: u32) -> Option<Vec<u8>>;
+    /// `len` bytes of RAM from `addr` (mirrors and KSEG addresses included), wrapping around
+    fn read_memory(&self, addr: u32, len: usize) -> Vec<u8>;
+    fn write_memory(&mut self, addr: u32, data: &[u8]);
+    /// Copy of the whole RAM, for the RAM search
+    fn ram(&self) -> Vec<u8>;
+    /// Keep the lines the program prints (debug port and BIOS `putchar`) for `take_tty_lines`
+    fn set_tty_capture(&mut self, capture: bool);
+    /// Lines printed since the last call, while capturing
+    fn take_tty_lines(&mut self) -> Vec<String>;
+    /// Hex SHA-256 of the game executable (not the patched one), which tells versions of a game
+    /// apart
+    fn game_hash(&mut self) -> Option<String>;
+    /// Human readable state of the console (CPU, interrupts, drives...), for bug reports
+    fn diagnostics(&self) -> String;
+    /// Snapshot of the whole console, to be restored with `load_state`. The system files and the
+    /// disc image aren't included, a thumbnail of the last frame is, see `state_thumbnail`.
+    fn save_state(&self) -> Vec<u8>;
+    /// Same as `save_state` but not compressed and without thumbnail, for the rewind buffer.
+    /// `load_state` takes both.
+    fn save_state_raw(&self) -> Vec<u8>;
+    /// The thumbnail `save_state` would save, `None` before the first frame
+    fn thumbnail(&self) -> Option<CpuFrame>;
+    /// Restore a snapshot made by `save_state`, on a console running the same BIOS and the same
+    /// disc. The memory cards keep their contents and the controllers stay connected. The
+    /// current state is untouched if the snapshot can't be restored.
+    fn load_state(&mut self, state: &[u8]) -> MipsResult<()>;
 }
 
+#[cfg(feature = "ps1")]
 pub struct ConsoleManager {
     active: Option<Box<dyn Console>>,
+    /// BIOS dump used by the next `load_game`, `None` to pick one from the system directory
+    #[cfg(feature = "std")]
+    bios: Option<PathBuf>,
+    /// See `set_vfs`
+    vfs: Arc<dyn Vfs>,
+    /// See `set_soundtrack`
+    soundtrack: Option<Arc<dyn Soundtrack>>,
+    /// Discs up to that many bytes are read into memory by `load_game`, 0 reads them on demand
+    preload_limit: u64,
+    /// See `set_fast_boot`
+    fast_boot: bool,
+    /// See `set_hle_bios`
+    hle_bios: bool,
+    /// Challenge mode, see `set_hardcore`
+    hardcore: bool,
+    /// See `set_force_ntsc_timing`
+    force_ntsc_timing: bool,
+    /// See `set_cpu_backend`
+    cpu_backend: CpuBackend,
+    /// See `set_cpu_clock`
+    cpu_clock: CpuClock,
+    /// See `set_internal_resolution`
+    internal_resolution: InternalResolution,
+    /// See `set_renderer`
+    renderer: RendererBackend,
+    /// See `set_pgxp`
+    pgxp: bool,
+    /// See `set_dithering`
+    dithering: bool,
+    /// See `set_accurate_transparency`
+    accurate_transparency: bool,
+    /// See `set_true_color`
+    true_color: bool,
+    /// See `set_overdraw_heatmap`
+    overdraw_heatmap: bool,
+    /// See `set_parallel_draw_areas`
+    parallel_draw_areas: bool,
+    /// See `set_debug_render`
+    debug_render: DebugRender,
+    /// See `set_widescreen_hack`
+    widescreen_hack: bool,
+    /// See `set_interpolation`
+    interpolation: Interpolation,
+    /// See `set_memory_card_scope`
+    memory_card_scope: MemoryCardScope,
+    /// See `set_audio_mix`
+    audio_mix: AudioMix,
+    /// Subtracted from the time of the inputs given to `handle_timed_inputs`
+    input_latency: Duration,
+    /// Snapshots for `rewind`, when enabled
+    rewind: Option<RewindBuffer>,
+    /// Frames emulated since the console started, minus the ones rewound
+    frame: u64,
 }
 
+#[cfg(feature = "ps1")]
 impl ConsoleManager {
     pub fn new() -> Self {
-        Self { active: None }
+        Self {
+            active: None,
+            #[cfg(feature = "std")]
+            bios: None,
+            vfs: vfs::host(),
+            soundtrack: None,
+            preload_limit: 0,
+            fast_boot: false,
+            hle_bios: false,
+            hardcore: false,
+            force_ntsc_timing: false,
+            cpu_backend: CpuBackend::default(),
+            cpu_clock: CpuClock::NATIVE,
+            internal_resolution: InternalResolution::default(),
+            renderer: RendererBackend::default(),
+            pgxp: false,
+            dithering: true,
+            accurate_transparency: true,
+            true_color: false,
+            overdraw_heatmap: false,
+            parallel_draw_areas: false,
+            debug_render: DebugRender::default(),
+            widescreen_hack: false,
+            interpolation: Interpolation::default(),
+            memory_card_scope: MemoryCardScope::default(),
+            audio_mix: AudioMix::default(),
+            input_latency: Duration::ZERO,
+            rewind: None,
+            frame: 0,
+        }
+    }
+
+    /// Use the dump in `path` (a raw image or a ZIP archive) from the next `load_game` on, or let
+    /// the console pick a supported one in the system directory if `None`, of the disc's region if
+    /// there's one
+    #[cfg(feature = "std")]
+    pub fn set_bios(&mut self, path: Option<PathBuf>) {
+        self.bios = path;
+    }
+
+    /// Skip the BIOS boot logo and the license check of the consoles powered on from now on,
+    /// straight to the game or the shell: the games start several seconds sooner. With the BIOS
+    /// versions whose logo isn't located the executable of the disc is booted directly, the shell
+    /// keeps its logo.
+    pub fn set_fast_boot(&mut self, enabled: bool) {
+        self.fast_boot = enabled;
+    }
+
+    /// Emulate the BIOS kernel when the system directory has no dump, from the next `load_game`
+    /// on. Discs still need the CDC firmware. There's no shell and most games boot, but a real
+    /// dump is always the better choice.
+    pub fn set_hle_bios(&mut self, enabled: bool) {
+        self.hle_bios = enabled;
+    }
+
+    /// Read discs of up to `bytes` entirely into memory when they're loaded, so that a drive going
+    /// to sleep or a slow network share can't stall the game. 0 reads them as they're accessed.
+    pub fn set_preload_limit(&mut self, bytes: u64) {
+        self.preload_limit = bytes;
+    }
+
+    /// Play the audio tracks of the discs from `soundtrack` instead, from the next `load_game` on:
+    /// the tracks are replaced as the drive reads them, so the game's looping and its CD volume
+    /// still apply. The audio the SPU captures changes, a game could read it back.
+    pub fn set_soundtrack(&mut self, soundtrack: Option<Arc<dyn Soundtrack>>) {
+        self.soundtrack = soundtrack;
+    }
+
+    /// Hardcore mode, for RetroAchievements: cheats, executable patches, startup scripts and forced
+    /// NTSC timings are refused. Entering it drops the cheats and the timings of the running
+    /// console but can't undo patches, the game should be restarted.
+    pub fn set_hardcore(&mut self, enabled: bool) {
+        self.hardcore = enabled;
+
+        if enabled {
+            self.force_ntsc_timing = false;
+            self.cpu_clock = CpuClock::NATIVE;
+
+            if let Some(console) = &mut self.active {
+                console.set_cheats(Vec::new());
+                console.set_force_ntsc_timing(false);
+                console.set_cpu_clock(CpuClock::NATIVE);
+            }
+        }
+    }
+
+    pub fn is_hardcore(&self) -> bool {
+        self.hardcore
+    }
+
+    /// Error if `feature` isn't allowed in hardcore mode
+    fn check_hardcore(&self, feature: &'static str) -> MipsResult<()> {
+        if self.hardcore {
+            return Err(MipsError::Hardcore(feature));
+        }
+
+        Ok(())
+    }
+
+    /// Compensate for a player reacting `latency` late to what they see or hear (output buffers,
+    /// display lag...): inputs given to `handle_timed_inputs` are latched as if they had happened
+    /// that much earlier. They can't be latched before the start of the frame, so only the part
+    /// of the latency within the frame is made up for.
+    pub fn set_input_latency(&mut self, latency: Duration) {
+        self.input_latency = latency;
+    }
+
+    /// Run PAL games at NTSC timings, 60 fields per second instead of 50: they run faster, as they
+    /// would on an NTSC console. The SPU keeps its clock so the pitch doesn't change, but music
+    /// sequenced on the VBlank plays faster. Applies to the running console and the next ones.
+    pub fn set_force_ntsc_timing(&mut self, force: bool) -> MipsResult<()> {
+        if force {
+            self.check_hardcore("Forced NTSC timings")?;
+        }
+
+        self.force_ntsc_timing = force;
+        if let Some(console) = &mut self.active {
+            console.set_force_ntsc_timing(force);
+        }
+
+        Ok(())
+    }
+
+    /// Run the CPU with the interpreter or the dynarec. Both give the same results, the dynarec is
+    /// faster but only available on x86-64 hosts. Applies to the running console and the next
+    /// ones.
+    pub fn set_cpu_backend(&mut self, backend: CpuBackend) {
+        self.cpu_backend = backend;
+        if let Some(console) = &mut self.active {
+            console.set_cpu_backend(backend);
+        }
+    }
+
+    /// Run the CPU at `clock`, for the games that slow down when there's a lot going on. The
+    /// timers, the video and the sound keep the console's speed. Applies to the running console
+    /// and the next ones. Not allowed in hardcore mode.
+    pub fn set_cpu_clock(&mut self, clock: CpuClock) -> MipsResult<()> {
+        if !clock.is_native() {
+            self.check_hardcore("CPU overclocking")?;
+        }
+
+        self.cpu_clock = clock;
+        if let Some(console) = &mut self.active {
+            console.set_cpu_clock(clock);
+        }
+
+        Ok(())
+    }
+
+    /// Draw the polygons at a multiple of the native resolution. The frames are upscaled as much,
+    /// the VRAM the game reads back stays native. Applies to the running console and the next
+    /// ones.
+    pub fn set_internal_resolution(&mut self, resolution: InternalResolution) {
+        self.internal_resolution = resolution;
+        if let Some(console) = &mut self.active {
+            console.set_internal_resolution(resolution);
+        }
+    }
+
+    /// Draw with the software rasterizer or the host's GPU. Save states load with either.
+    /// Applies to the running console and the next ones.
+    pub fn set_renderer(&mut self, renderer: RendererBackend) {
+        self.renderer = renderer;
+        if let Some(console) = &mut self.active {
+            console.set_renderer(renderer);
+        }
+    }
+
+    /// Track the vertices the GTE projects with subpixel precision and draw the polygons at those
+    /// positions (PGXP): no more jittering polygons, and with the hardware renderer textures
+    /// drawn in perspective. Games see the same GTE results. Applies to the running console and
+    /// the next ones.
+    pub fn set_pgxp(&mut self, enabled: bool) {
+        self.pgxp = enabled;
+        if let Some(console) = &mut self.active {
+            console.set_pgxp(enabled);
+        }
     }
 
+    /// Blend the semi-transparent draws with 5 bits per color component like the GPU, on by
+    /// default. Off they're blended with 8 bits, smoother but with different rounding, which shows
+    /// in the effects adding up many layers. Ignored in true color. Applies to the running console
+    /// and the next ones.
+    pub fn set_accurate_transparency(&mut self, enabled: bool) {
+        self.accurate_transparency = enabled;
+        if let Some(console) = &mut self.active {
+            console.set_accurate_transparency(enabled);
+        }
+    }
+
+    /// Dither the shaded and blended draws with the GPU's ordered pattern, on by default like the
+    /// console. Applies to the running console and the next ones.
+    pub fn set_dithering(&mut self, enabled: bool) {
+        self.dithering = enabled;
+        if let Some(console) = &mut self.active {
+            console.set_dithering(enabled);
+        }
+    }
+
+    /// Keep the 24bit colors the draws compute instead of truncating them to the console's 15bit:
+    /// smoother gradients, best with the dithering off. The frames are `PixelFormat::Rgb888`.
+    /// Games see the same VRAM contents. Applies to the running console and the next ones.
+    pub fn set_true_color(&mut self, enabled: bool) {
+        self.true_color = enabled;
+        if let Some(console) = &mut self.active {
+            console.set_true_color(enabled);
+        }
+    }
+
+    /// Replace the picture with a heatmap of the draws: black for the pixels nothing was drawn to
+    /// since the last flip, then blue, cyan, green, yellow, orange and red as the same pixels get
+    /// drawn over, white from 8 times. The fills clearing the screen aren't counted. Software
+    /// renderer only, the hardware one keeps showing the picture. Applies to the running console
+    /// and the next ones.
+    pub fn set_overdraw_heatmap(&mut self, enabled: bool) {
+        self.overdraw_heatmap = enabled;
+        if let Some(console) = &mut self.active {
+            console.set_overdraw_heatmap(enabled);
+        }
+    }
+
+    /// Rasterize the draws of split-screen games on several threads: the viewports that don't
+    /// overlap nor texture from each other are drawn at the same time. Off by default, the threads
+    /// don't share the texture cache, which the rare games drawing from stale cache contents
+    /// notice. Software renderer only, without effect in WebAssembly. Applies to the running
+    /// console and the next ones.
+    pub fn set_parallel_draw_areas(&mut self, enabled: bool) {
+        self.parallel_draw_areas = enabled;
+        if let Some(console) = &mut self.active {
+            console.set_parallel_draw_areas(enabled);
+        }
+    }
+
+    /// Have the GTE project the X coordinates 3/4 as wide, so that 4:3 games draw their 3D
+    /// geometry for a 16:9 picture: the frames then have an `aspect` of 16:9. The 2D elements
+    /// (menus, HUD) end up stretched, and some games cull what they think is off screen, leaving
+    /// the sides empty. The GTE results change, the games see them. Applies to the running console
+    /// and the next ones.
+    pub fn set_widescreen_hack(&mut self, enabled: bool) {
+        self.widescreen_hack = enabled;
+        if let Some(console) = &mut self.active {
+            console.set_widescreen_hack(enabled);
+        }
+    }
+
+    /// Resample the SPU voices to their pitch with the console's gaussian filter (the default),
+    /// the nearest sample or a cubic spline. The voices the SPU captures to its RAM change too,
+    /// which a game could read back. Applies to the running console and the next ones.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+        if let Some(console) = &mut self.active {
+            console.set_interpolation(interpolation);
+        }
+    }
+
+    /// Turn the SPU voices, the CD audio tracks and the XA streams up or down independently, to
+    /// find which one plays a sound or to play the game over another soundtrack. Only the output
+    /// changes, the games see the same SPU. Applies to the running console and the next ones.
+    pub fn set_audio_mix(&mut self, mix: AudioMix) {
+        self.audio_mix = mix;
+        if let Some(console) = &mut self.active {
+            console.set_audio_mix(mix);
+        }
+    }
+
+    /// Start the consoles with `card1.mcr` and `card2.mcr` from the `memcards` directory of the
+    /// system directory, or with a card named after the disc's serial number in slot 1. The games'
+    /// writes are saved a second after they stop. Cards inserted with `set_memory_card_file`
+    /// replace them. Applies to the running console and the next ones.
+    pub fn set_memory_card_scope(&mut self, scope: MemoryCardScope) {
+        self.memory_card_scope = scope;
+        if let Some(console) = &mut self.active {
+            console.set_memory_card_scope(scope);
+        }
+    }
+
+    /// Draw the outlines of the polygons over them or instead of them, the textured draws without
+    /// their textures and the semi-transparent ones opaque, to find where a rendering glitch comes
+    /// from. The hardware renderer only does the wireframe. Applies to the running console and the
+    /// next ones.
+    pub fn set_debug_render(&mut self, debug: DebugRender) {
+        self.debug_render = debug;
+        if let Some(console) = &mut self.active {
+            console.set_debug_render(debug);
+        }
+    }
+
+    /// True if a console is powered on
+    pub fn is_running(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Read and write the memory card files and the patches given by path in `vfs` instead of the
+    /// host's files, from the next console powered on. Without the `std` feature there are no
+    /// such files until then.
+    pub fn set_vfs(&mut self, vfs: Arc<dyn Vfs>) {
+        self.vfs = vfs;
+    }
+
+    #[cfg(feature = "std")]
     pub fn load_game(&mut self, game_dir: &Path, disc: Option<&str>) -> MipsResult<()> {
-        self.active = Some(Box::new(Ps1::new(game_dir, disc)?));
+        let console = Ps1::new(
+            game_dir,
+            disc,
+            self.bios.as_deref(),
+            self.preload_limit,
+            self.soundtrack.clone(),
+            self.hle_bios,
+            Arc::clone(&self.vfs),
+        )?;
+        self.power_on(Box::new(console));
+
         Ok(())
     }
 
+    /// Boot the PS-EXE `exe`, whatever its name, with the BIOS of the system directory `sys_dir`:
+    /// once the BIOS has initialized the kernel the executable is copied in RAM and started in
+    /// place of the shell, with the PC, GP and SP of its header. For homebrew development, the
+    /// build can be run as soon as it's linked.
+    #[cfg(feature = "std")]
+    pub fn load_exe(&mut self, sys_dir: &Path, exe: &Path) -> MipsResult<()> {
+        if !ps1::is_exe(exe) {
+            return Err(ps1::Ps1Error::BadExe.into());
+        }
+        let exe = std::path::absolute(exe)
+            .map_err(|_| ps1::Ps1Error::FileOrDirNotFound(exe.display().to_string()))?;
+
+        self.load_game(sys_dir, Some(&exe.to_string_lossy()))
+    }
+
+    /// Like `load_game` without a system directory, for the hosts that don't have one (browsers,
+    /// libretro cores, tests): boot the BIOS dump `bios`, with the drive's firmware
+    /// `cdc_firmware` if there's one, and the PS-EXE or disc image `game` if any. The memory cards
+    /// are left out until `set_memory_card_image`.
+    pub fn load_buffers(&mut self, bios: &[u8], cdc_firmware: Option<&[u8]>, game: Option<&[u8]>) -> MipsResult<()> {
+        let console = Ps1::from_buffers(bios, cdc_firmware, game)?;
+        self.power_on(Box::new(console));
+
+        Ok(())
+    }
+
+    /// Like `load_buffers` with the disc read through `source`, for the hosts that keep their disc
+    /// images where cdimage can't open them (no filesystem, archives, network): the sectors are
+    /// read as the drive needs them
+    pub fn load_disc_source(
+        &mut self,
+        bios: &[u8],
+        cdc_firmware: &[u8],
+        source: Box<dyn cdimage::Image + Send>,
+    ) -> MipsResult<()> {
+        let console = Ps1::from_disc_source(bios, cdc_firmware, source)?;
+        self.power_on(Box::new(console));
+
+        Ok(())
+    }
+
+    /// Make `console` the running one, with the settings of the manager
+    fn power_on(&mut self, mut console: Box<Ps1>) {
+        console.set_vfs(Arc::clone(&self.vfs));
+        console.set_fast_boot(self.fast_boot);
+        console.set_force_ntsc_timing(self.force_ntsc_timing);
+        console.set_cpu_backend(self.cpu_backend);
+        console.set_cpu_clock(self.cpu_clock);
+        console.set_internal_resolution(self.internal_resolution);
+        console.set_renderer(self.renderer);
+        console.set_pgxp(self.pgxp);
+        console.set_dithering(self.dithering);
+        console.set_accurate_transparency(self.accurate_transparency);
+        console.set_true_color(self.true_color);
+        console.set_overdraw_heatmap(self.overdraw_heatmap);
+        console.set_parallel_draw_areas(self.parallel_draw_areas);
+        console.set_debug_render(self.debug_render);
+        console.set_widescreen_hack(self.widescreen_hack);
+        console.set_interpolation(self.interpolation);
+        console.set_audio_mix(self.audio_mix);
+        console.set_memory_card_scope(self.memory_card_scope);
+
+        self.active = Some(console);
+        self.frame = 0;
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+    }
+
+    /// Take snapshots of the console for `rewind` as it runs, or stop and drop them if `settings`
+    /// is `None`. The snapshots are kept if the settings don't change. None are taken in hardcore
+    /// mode.
+    pub fn set_rewind(&mut self, settings: Option<RewindSettings>) {
+        match settings {
+            Some(settings) => {
+                if self.rewind.as_ref().is_none_or(|r| r.settings() != settings) {
+                    self.rewind = Some(RewindBuffer::new(settings));
+                }
+            }
+            None => self.rewind = None,
+        }
+    }
+
+    /// Go back about `frames` frames, to the newest snapshot at least that old (or the oldest
+    /// one). Returns the number of frames actually rewound, 0 if there's no snapshot to go back
+    /// to. Not allowed in hardcore mode.
+    pub fn rewind(&mut self, frames: u64) -> MipsResult<u64> {
+        self.check_hardcore("Rewind")?;
+
+        let (Some(console), Some(rewind)) = (&mut self.active, &mut self.rewind) else {
+            return Ok(0);
+        };
+        let Some((frame, state)) = rewind.rewind_to(self.frame.saturating_sub(frames)) else {
+            return Ok(0);
+        };
+
+        console.load_state(state)?;
+        console.set_force_ntsc_timing(self.force_ntsc_timing);
+
+        let rewound = self.frame.saturating_sub(frame);
+        self.frame = frame;
+        Ok(rewound)
+    }
+
+    /// Frames `rewind` can go back
+    pub fn rewind_depth(&self) -> u64 {
+        self.rewind.as_ref().map_or(0, |r| r.depth(self.frame))
+    }
+
     // Delegate to active console
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> FrameStats {
+        let Some(console) = &mut self.active else {
+            return FrameStats::default();
+        };
+
+        let stats = console.update();
+        self.frame += 1;
+
+        if let Some(rewind) = &mut self.rewind
+            && !self.hardcore
+            && rewind.wants_snapshot(self.frame)
+        {
+            rewind.push(self.frame, console.save_state_raw());
+        }
+
+        stats
+    }
+
+    /// Doesn't count as a frame, for the rewind or anything else
+    pub fn update_paused(&mut self) {
         if let Some(console) = &mut self.active {
-            console.update();
+            console.update_paused();
         }
     }
 
@@ -48,33 +733,308 @@ impl ConsoleManager {
         self.active.as_mut().and_then(|c| c.get_frame())
     }
 
-    pub fn get_audio_samples(&mut self) -> &[i16] {
-        self.active.as_mut()
-            .map(|c| c.get_audio_samples())
-            .unwrap_or(&[])
+    pub fn take_audio(&mut self) -> AudioChunk {
+        self.active.as_mut().map(|c| c.take_audio()).unwrap_or_default()
     }
 
-    pub fn clear_audio_samples(&mut self) {
+    pub fn set_skip_draws(&mut self, skip: bool) {
         if let Some(console) = &mut self.active {
-            console.clear_audio_samples();
+            console.set_skip_draws(skip);
         }
     }
 
-    pub fn connect_device(&mut self, port: usize, device: DeviceType) {
+    pub fn connect_device(&mut self, port: usize, device: ControllerType) {
         if let Some(console) = &mut self.active {
             console.connect_device(port, device);
         }
     }
 
+    pub fn device_type(&self, port: usize) -> ControllerType {
+        self.active.as_ref().map_or(ControllerType::Disconnected, |c| c.device_type(port))
+    }
+
+    pub fn serial_number(&self) -> Option<String> {
+        self.active.as_ref().and_then(|c| c.serial_number())
+    }
+
+    /// Title of the game in the drive, from the compatibility database. `None` for the games it
+    /// doesn't know, the frontends fall back on the file name.
+    pub fn game_title(&self) -> Option<&'static str> {
+        self.serial_number().and_then(|serial| compat::lookup(&serial)).map(|game| game.title)
+    }
+
+    pub fn eject_disc(&mut self) {
+        if let Some(console) = &mut self.active {
+            console.eject_disc();
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn swap_disc(&mut self, disc_path: &str) -> MipsResult<()> {
+        match &mut self.active {
+            Some(console) => console.swap_disc(disc_path),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn swap_disc_image(&mut self, image: &[u8]) -> MipsResult<()> {
+        match &mut self.active {
+            Some(console) => console.swap_disc_image(image),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    pub fn swap_disc_source(&mut self, source: Box<dyn cdimage::Image + Send>) -> MipsResult<()> {
+        match &mut self.active {
+            Some(console) => console.swap_disc_source(source),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    pub fn is_shell_open(&self) -> bool {
+        self.active.as_ref().is_some_and(|c| c.is_shell_open())
+    }
+
     pub fn handle_inputs(&mut self, inputs: ButtonQueue) {
         if let Some(console) = &mut self.active {
             console.handle_inputs(inputs);
         }
     }
 
+    pub fn handle_port_inputs(&mut self, port: usize, inputs: ButtonQueue) {
+        if let Some(console) = &mut self.active {
+            console.handle_port_inputs(port, inputs);
+        }
+    }
+
+    pub fn handle_mouse(&mut self, port: usize, input: MouseInput) {
+        if let Some(console) = &mut self.active {
+            console.handle_mouse(port, input);
+        }
+    }
+
+    pub fn handle_lightgun(&mut self, port: usize, input: LightgunInput) {
+        if let Some(console) = &mut self.active {
+            console.handle_lightgun(port, input);
+        }
+    }
+
+    pub fn handle_analog(&mut self, port: usize, input: AnalogInput) {
+        if let Some(console) = &mut self.active {
+            console.handle_analog(port, input);
+        }
+    }
+
+    /// Both motors stopped if no console is running
+    pub fn rumble(&self, port: usize) -> (u8, u8) {
+        self.active.as_ref().map_or((0, 0), |c| c.rumble(port))
+    }
+
+    pub fn handle_timed_inputs(&mut self, port: usize, inputs: &[InputEvent], window: Range<Instant>) {
+        let Some(console) = &mut self.active else {
+            return;
+        };
+
+        if self.input_latency.is_zero() {
+            console.handle_timed_inputs(port, inputs, window);
+            return;
+        }
+
+        let inputs: Vec<InputEvent> = inputs.iter()
+            .map(|input| InputEvent {
+                time: input.time.checked_sub(self.input_latency).unwrap_or(window.start),
+                ..*input
+            })
+            .collect();
+        console.handle_timed_inputs(port, &inputs, window);
+    }
+
     pub fn refresh_devices(&mut self) {
         if let Some(console) = &mut self.active {
             console.refresh_devices();
         }
     }
+
+    /// See `Console::memory_card_image`, `None` without a console
+    pub fn memory_card_image(&self, slot: usize) -> MipsResult<Option<Vec<u8>>> {
+        match &self.active {
+            Some(console) => console.memory_card_image(slot),
+            None => Ok(None),
+        }
+    }
+
+    /// Replace the cheats, an empty list is always accepted
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) -> MipsResult<()> {
+        if !cheats.is_empty() {
+            self.check_hardcore("Cheats")?;
+        }
+
+        if let Some(console) = &mut self.active {
+            console.set_cheats(cheats);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_memory_card_image(&mut self, slot: usize, image: Option<Vec<u8>>) -> MipsResult<()> {
+        match &mut self.active {
+            Some(console) => console.set_memory_card_image(slot, image),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    pub fn set_memory_card_file(&mut self, slot: usize, path: &Path) -> MipsResult<()> {
+        match &mut self.active {
+            Some(console) => console.set_memory_card_file(slot, path),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    /// Put back a card image edited with `mem_card::Fs`
+    pub fn rewrite_memory_card(&mut self, slot: usize, image: Vec<u8>) -> MipsResult<()> {
+        match &mut self.active {
+            Some(console) => console.rewrite_memory_card(slot, image),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    pub fn set_memory_card_read_only(&mut self, slot: usize, read_only: bool) {
+        if let Some(console) = &mut self.active {
+            console.set_memory_card_read_only(slot, read_only);
+        }
+    }
+
+    pub fn patch_executable(&mut self, patch: &Path) -> MipsResult<()> {
+        self.check_hardcore("Executable patches")?;
+
+        match &mut self.active {
+            Some(console) => console.patch_executable(patch),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    pub fn set_startup_script(&mut self, script: StartupScript) -> MipsResult<()> {
+        self.check_hardcore("Startup scripts")?;
+
+        match &mut self.active {
+            Some(console) => console.set_startup_script(script),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    pub fn set_exec_watch(&mut self, settings: Option<ExecWatchSettings>) {
+        if let Some(console) = &mut self.active {
+            console.set_exec_watch(settings);
+        }
+    }
+
+    pub fn take_exec_watch_hits(&mut self) -> Vec<ExecWatchHit> {
+        self.active.as_mut().map(|c| c.take_exec_watch_hits()).unwrap_or_default()
+    }
+
+    pub fn set_cpu_trace(&mut self, limit: Option<usize>) {
+        if let Some(console) = &mut self.active {
+            console.set_cpu_trace(limit);
+        }
+    }
+
+    pub fn take_cpu_trace(&mut self) -> (Vec<TraceEntry>, u64) {
+        self.active.as_mut().map(|c| c.take_cpu_trace()).unwrap_or_default()
+    }
+
+    /// Blocks of the block cache of the recompiler, in no particular order, see `block_cache`
+    pub fn compiled_blocks(&self) -> Vec<CompiledBlock> {
+        self.active.as_ref().map(|c| c.compiled_blocks()).unwrap_or_default()
+    }
+
+    /// Host machine code (x86-64) of the translated block starting at `pc`, `None` if there's no
+    /// such block anymore
+    pub fn compiled_block_code(&self, pc: u32) -> Option<Vec<u8>> {
+        self.active.as_ref()?.compiled_block_code(pc)
+    }
+
+    pub fn read_memory(&self, addr: u32, len: usize) -> MipsResult<Vec<u8>> {
+        match &self.active {
+            Some(console) => Ok(console.read_memory(addr, len)),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    /// RAM for the RAM search, which is a cheating tool as far as hardcore mode is concerned
+    pub fn ram(&self) -> MipsResult<Vec<u8>> {
+        self.check_hardcore("RAM search")?;
+
+        match &self.active {
+            Some(console) => Ok(console.ram()),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    /// Poke RAM from outside of the game, which is cheating as far as hardcore mode is concerned
+    pub fn write_memory(&mut self, addr: u32, data: &[u8]) -> MipsResult<()> {
+        self.check_hardcore("Memory writes")?;
+
+        match &mut self.active {
+            Some(console) => {
+                console.write_memory(addr, data);
+                Ok(())
+            }
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    pub fn set_tty_capture(&mut self, capture: bool) {
+        if let Some(console) = &mut self.active {
+            console.set_tty_capture(capture);
+        }
+    }
+
+    pub fn take_tty_lines(&mut self) -> Vec<String> {
+        self.active.as_mut().map(|c| c.take_tty_lines()).unwrap_or_default()
+    }
+
+    pub fn game_hash(&mut self) -> Option<String> {
+        self.active.as_mut().and_then(|c| c.game_hash())
+    }
+
+    pub fn diagnostics(&self) -> String {
+        self.active.as_ref().map(|c| c.diagnostics()).unwrap_or_default()
+    }
+
+    pub fn save_state(&self) -> MipsResult<Vec<u8>> {
+        match &self.active {
+            Some(console) => Ok(console.save_state()),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    /// Same as `save_state` but not compressed, see `compress_state`
+    pub fn save_state_raw(&self) -> MipsResult<Vec<u8>> {
+        match &self.active {
+            Some(console) => Ok(console.save_state_raw()),
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
+
+    /// Thumbnail of the last frame, for the states made with `save_state_raw` and compressed
+    /// later with `compress_state`
+    pub fn thumbnail(&self) -> Option<CpuFrame> {
+        self.active.as_ref().and_then(|c| c.thumbnail())
+    }
+
+    /// Save states can't be loaded in hardcore mode. Forced NTSC timings follow
+    /// `set_force_ntsc_timing`, not the state.
+    pub fn load_state(&mut self, state: &[u8]) -> MipsResult<()> {
+        self.check_hardcore("Save state loads")?;
+
+        match &mut self.active {
+            Some(console) => {
+                console.load_state(state)?;
+                console.set_force_ntsc_timing(self.force_ntsc_timing);
+                Ok(())
+            }
+            None => Err(MipsError::InvalidState("No console running".to_string())),
+        }
+    }
 }
\ No newline at end of file
@@ -1,103 +1,499 @@
 use std::error::Error;
+use std::ops::Range;
 use std::path::Path;
-use cdimage::cue::Cue;
-use log::{error, info};
+use std::sync::Arc;
+use std::time::Instant;
+use cdimage::Image;
+use log::{debug, info, warn};
+use crate::audio::{AudioMix, Soundtrack};
+use crate::cheats::{Cheat, CheatMemory};
+use crate::compat::{self, Quirks};
+use crate::block_cache::CompiledBlock;
+use crate::cpu_trace::{CpuTrace, TraceEntry};
+use crate::exec_watch::{ExecWatch, ExecWatchHit, ExecWatchSettings};
+use crate::script::StartupScript;
 use crate::ps1::mem_card::MemoryCardFile;
 use crate::ps1::psx::bus::Bus;
 use crate::ps1::util::ds::box_slice::BoxSlice;
-use crate::ps1::util::fs::sys_dir::{SearchFor, SysDir};
 use crate::error::MipsResult;
-use crate::input::{ButtonQueue, DeviceType};
-use crate::ps1::psx::bios::bios::Bios;
+use crate::input::{AnalogInput, ButtonQueue, ControllerType, InputEvent, LightgunInput, MouseInput};
 use crate::ps1::psx::cd::disc::Disc;
-use crate::ps1::psx::exe::Exe;
-use crate::ps1::psx::graphics::rasterizer::handle::Frame;
-use psx::pad_memcard::gamepad::{DigitalPad, DualShock};
-use crate::ps1::util::fs::file::bin;
+use crate::ps1::psx::cd::disc::soundtrack::SoundtrackImage;
+use crate::ps1::psx::exe::{self, Exe, ExeSignature};
+use crate::ps1::psx::bios::bios::Bios;
+use mips_psx_gpu::handle::{Frame, RasterizerOption};
+use crate::ps1::psx::graphics::gpu::VideoStandard;
+use crate::ps1::psx::xmem::RAM_SIZE;
+use psx::pad_memcard::gamepad::{DigitalPad, DualShock, NeGcon};
+use psx::pad_memcard::lightgun::{GunCon, Justifier, LightgunState};
+use psx::pad_memcard::mouse::Mouse;
+use crate::vfs::{self, Vfs};
+// The system directory and the files on disk
+#[cfg(feature = "std")]
+use {
+    std::fs,
+    std::io::Read,
+    std::path::PathBuf,
+    cdimage::cue::Cue,
+    crate::ps1::util::fs::sys_dir::{SearchFor, SysDir},
+    crate::ps1::psx::cd::disc::formats::{self, ImageFormat},
+    crate::ps1::psx::cd::disc::patch::{self, DiscPatch, PatchedImage},
+    crate::ps1::util::fs::file::bin,
+};
 
 mod hash;
 mod psx;
 mod settings;
 mod util;
 mod error;
-mod mem_card;
+pub mod mem_card;
 mod bitwise;
+mod savestate;
 
 pub use error::Ps1Error;
-pub use psx::graphics::rasterizer::handle::Frame as Ps1Frame;
+pub use settings::audio::Interpolation;
+pub use settings::memcard::MemoryCardScope;
+pub use settings::cpu::{CpuBackend, CpuClock};
+pub use settings::graphics::{DebugRender, InternalResolution, RendererBackend, Wireframe};
+/// BIOS dumps: the supported ones and the ones found on disk
+pub use psx::bios::dumps as bios;
+#[cfg(feature = "bench")]
+pub use psx::bench;
+#[cfg(feature = "std")]
+pub use psx::cd::disc::formats::disc_count;
+pub use mips_psx_gpu::handle::Frame as Ps1Frame;
+/// Region of a disc, and of the games in the compatibility database
+pub use psx::cd::disc::Region;
+pub use savestate::compress as compress_state;
+pub use savestate::thumbnail as state_thumbnail;
 
-use crate::{gfx, Console};
+use crate::{gfx, AudioChunk, Console, FrameStats};
+use crate::ps1::psx::cd::CdInterface;
+#[cfg(feature = "std")]
 use crate::ps1::psx::cd::CDC_ROM_SIZE;
+use crate::ps1::psx::processor::{cop0, irq, RegisterIndex};
 use crate::ps1::psx::pad_memcard::{DeviceInterface, DisconnectedDevice};
+use crate::ps1::psx::pad_memcard::memory_card::{MemoryCard, FLASH_SIZE};
 use crate::ps1::settings::Ps1Settings;
 
 pub struct Ps1 {
     bus: Box<Bus>,
     settings: Ps1Settings,
     memcard_files: BoxSlice<MemoryCardFile, 2>,
-    sys_dir: SysDir
+    /// `None` when started from buffers: no memory card files and no discs then
+    #[cfg(feature = "std")]
+    sys_dir: Option<SysDir>,
+    /// Where the memory card files and the patches given by path are
+    vfs: Arc<dyn Vfs>,
+    /// Sideloaded executable as it was at power on, when the game is a PS-EXE instead of a disc
+    exe: Option<Vec<u8>>,
+    cheats: Vec<Cheat>,
+    /// Script waiting for the game executable to be loaded
+    startup_script: Option<(StartupScript, ExeSignature)>,
+    /// Discs up to that many bytes are read into memory when inserted
+    preload_limit: u64,
+    /// Replaces the audio tracks of the discs inserted
+    soundtrack: Option<Arc<dyn Soundtrack>>,
+    /// False when started without a CDC firmware dump: the drive doesn't answer, no disc can be
+    /// inserted
+    cdc_firmware: bool,
+    /// Offset in the BIOS of the call to the boot logo animation, if it's known
+    boot_logo_hook: Option<u32>,
+    /// The last frame shrunk for the savestates, `None` until one is drawn
+    thumbnail: Option<gfx::CpuFrame>,
 }
 
 impl Ps1 {
-    pub fn new(sys_dir: &Path, game_path: Option<&str>) -> MipsResult<Ps1> {
+    /// `bios` is the dump to use, or `None` to pick a supported one in the system directory, of the
+    /// disc's region if there's one. Discs no larger than `preload_limit` bytes are read into memory, 0 disables it.
+    /// The audio tracks `soundtrack` has are played from it instead of the discs. The CDC firmware
+    /// is only needed to play a disc, the BIOS shell and the PS-EXEs run without it. With
+    /// `hle_bios` the kernel is emulated when there's no dump in the system directory, see
+    /// `Bios::new_hle`. The memory card files are read and written in `vfs`.
+    #[cfg(feature = "std")]
+    pub fn new(
+        sys_dir: &Path,
+        game_path: Option<&str>,
+        bios: Option<&Path>,
+        preload_limit: u64,
+        soundtrack: Option<Arc<dyn Soundtrack>>,
+        hle_bios: bool,
+        vfs: Arc<dyn Vfs>,
+    ) -> MipsResult<Ps1> {
         let sys_dir = SysDir::new(sys_dir);
 
-        let mut cdc_firmware = {
-            let cdc_firmware_path = sys_dir.search(SearchFor::CdcFirmware)?;
-            open_cdc_firmware(cdc_firmware_path.as_path())?
+        // Absolute paths don't need the games directory, which scripts running test programs
+        // usually don't have
+        let game_path = match game_path {
+            Some(game_path) if Path::new(game_path).is_absolute() => Some(PathBuf::from(game_path)),
+            Some(game_path) => Some(sys_dir.search(SearchFor::Games)?.join(game_path)),
+            None => None,
         };
 
-        //let test_exe = {
-        //    let exe_path = sys_dir.search(SearchFor::Executables)?;
-        //    let test_exe_path = exe_path.join("psxtest_cpu.exe");
-        //    open_exe(test_exe_path.as_path())?
-        //};
+        // PS-EXEs are sideloaded once the BIOS is done initializing the kernel
+        let exe_path = game_path.clone().filter(|path| is_exe(path));
+        let disc_path = game_path.as_ref().filter(|_| exe_path.is_none());
+        let exe = match &exe_path {
+            Some(path) => Some(fs::read(path).map_err(|_| Ps1Error::FileOrDirNotFound(path.display().to_string()))?),
+            None => None,
+        };
 
-        let bios = {
-            let bios_path = sys_dir.search(SearchFor::Bios)?;
-            open_bios(bios_path.as_path())?
+        let cdc_firmware = match sys_dir.search(SearchFor::CdcFirmware) {
+            Ok(path) => Some(open_cdc_firmware(path.as_path())?),
+            Err(_) if disc_path.is_none() => {
+                warn!("No CDC firmware in {}, the drive won't answer", sys_dir.roms_dir().display());
+                None
+            }
+            Err(_) => {
+                return Err(Ps1Error::NoCdcFirmware(sys_dir.roms_dir().display().to_string()).into());
+            }
         };
 
-        let disc = {
-            match game_path {
-                Some(game_path) => {
-                    let games_path = sys_dir.search(SearchFor::Games)?;
-                    let disc_path = games_path.join(game_path);
-                    Some(open_disc(disc_path.as_path())?)
-                },
-                None => None
+        let disc = match disc_path {
+            Some(disc_path) => {
+                Some(open_disc(disc_path.as_path(), &sys_dir.disc_cache_dir(), preload_limit, soundtrack.as_ref())?)
+            }
+            None => None,
+        };
+
+        // The BIOS of a region refuses the discs of the others, a dump of the disc's region is
+        // picked when there's one. The database knows better than the prefix of the serial.
+        let region = disc.as_ref().and_then(|disc| {
+            let serial = disc.serial_number();
+            compat::region(&serial.to_string()).or_else(|| serial.region())
+        });
+        let bios = match bios {
+            Some(path) => bios::open(path)?,
+            None => match bios::find(&sys_dir.roms_dir(), region) {
+                Ok(bios) => bios,
+                Err(e) if hle_bios => {
+                    warn!("{}, using the emulated BIOS", e);
+                    Bios::new_hle()
+                }
+                Err(e) => return Err(e),
+            },
+        };
+
+        let boot_logo_hook = bios.metadata().animation_jump_hook;
+        let bus = match &cdc_firmware {
+            Some(rom) => Box::new(Bus::new(bios, **rom, disc)?),
+            None => Box::new(Bus::with_cd(bios, CdInterface::new_without_firmware())),
+        };
+
+        let mut ps1 = Ps1::with_bus(bus, boot_logo_hook, exe, cdc_firmware.is_some())?;
+        ps1.sys_dir = Some(sys_dir);
+        ps1.vfs = vfs;
+        ps1.preload_limit = preload_limit;
+        ps1.soundtrack = soundtrack;
+        ps1.insert_default_memory_cards();
+
+        Ok(ps1)
+    }
+
+    /// Start without a system directory, for the hosts that don't have one (browsers, libretro
+    /// cores, tests): `bios` is the BIOS dump, `cdc_firmware` the one of the drive and `game` a
+    /// PS-EXE, sideloaded once the BIOS is done, or a disc image, told apart by their contents.
+    /// The slots are left without memory cards until `set_memory_card_image`.
+    ///
+    /// cdimage only reads discs from files: a disc image is written once to a directory of the
+    /// system's temporary directory, which the browsers don't have. Without the `std` feature
+    /// disc images are refused, see `from_disc_source`. Without a game the console boots to the
+    /// BIOS shell.
+    pub fn from_buffers(bios: &[u8], cdc_firmware: Option<&[u8]>, game: Option<&[u8]>) -> MipsResult<Ps1> {
+        let (exe, disc) = match game {
+            Some(game) if game.starts_with(exe::MAGIC) => (Some(game.to_vec()), None),
+            #[cfg(feature = "std")]
+            Some(image) => {
+                if cdc_firmware.is_none() {
+                    return Err(Ps1Error::NoCdcFirmware("memory".to_string()).into());
+                }
+                (None, Some(open_disc_image(image, &memory_cache_dir(), 0, None)?))
+            }
+            #[cfg(not(feature = "std"))]
+            Some(_) => return Err(Ps1Error::NoSysDir.into()),
+            None => (None, None),
+        };
+
+        Ps1::from_parts(bios, cdc_firmware, exe, disc)
+    }
+
+    /// Like `from_buffers` with a disc read through `source`, for the hosts that store the disc
+    /// images their own way: the sectors are read as the drive asks for them.
+    pub fn from_disc_source(bios: &[u8], cdc_firmware: &[u8], source: Box<dyn Image + Send>) -> MipsResult<Ps1> {
+        let disc = Disc::new(source, 0)?;
+        info!("Disc serial number: {}", disc.serial_number());
+
+        Ps1::from_parts(bios, Some(cdc_firmware), None, Some(disc))
+    }
+
+    fn from_parts(
+        bios: &[u8],
+        cdc_firmware: Option<&[u8]>,
+        exe: Option<Vec<u8>>,
+        disc: Option<Disc>,
+    ) -> MipsResult<Ps1> {
+        let bios = Bios::from_bytes(bios)?;
+
+        let boot_logo_hook = bios.metadata().animation_jump_hook;
+        let bus = match cdc_firmware {
+            Some(rom) => {
+                let rom = rom.try_into().map_err(|_| Ps1Error::BadCdcFirmware)?;
+                Box::new(Bus::new(bios, rom, disc)?)
             }
+            None => Box::new(Bus::with_cd(bios, CdInterface::new_without_firmware())),
         };
 
+        Ps1::with_bus(bus, boot_logo_hook, exe, cdc_firmware.is_some())
+    }
+
+    fn with_bus(
+        mut bus: Box<Bus>,
+        boot_logo_hook: Option<u32>,
+        exe: Option<Vec<u8>>,
+        cdc_firmware: bool,
+    ) -> MipsResult<Ps1> {
+        if let Some(exe) = &exe {
+            bus.exe = Some(Exe::from_bytes(exe)?);
+        }
+
+        // The settings are applied on top of the overrides of the game
+        let mut settings = Ps1Settings::default();
+        if let Some(disc) = bus.cd.disc() {
+            let serial = disc.serial_number().to_string();
+            let quirks = compat::quirks(&serial);
+            if quirks != Quirks::default() {
+                info!("Overrides for {}: {:?}", serial, quirks);
+            }
+            settings.set_quirks(quirks);
+        }
+
         Ok(Ps1 {
-            bus: Box::new(Bus::new(bios, *cdc_firmware, disc)?),
-            settings: Ps1Settings::default(),
+            bus,
+            settings,
             memcard_files: BoxSlice::from_vec(vec![MemoryCardFile::dummy(), MemoryCardFile::dummy()]),
-            sys_dir
+            #[cfg(feature = "std")]
+            sys_dir: None,
+            vfs: vfs::host(),
+            exe,
+            cheats: Vec::new(),
+            startup_script: None,
+            preload_limit: 0,
+            soundtrack: None,
+            cdc_firmware,
+            boot_logo_hook,
+            thumbnail: None,
         })
     }
 
+    /// Read and write the memory card files and the patches given by path in `vfs` instead of the
+    /// host's files. The cards already inserted keep the files they were read from.
+    pub fn set_vfs(&mut self, vfs: Arc<dyn Vfs>) {
+        self.vfs = vfs;
+    }
+
+    /// Insert the cards of the memory card scope, unless they're already in. A card that can't
+    /// be loaded leaves the slot as it was.
+    #[cfg(feature = "std")]
+    fn insert_default_memory_cards(&mut self) {
+        let Some(memcards_dir) = self.sys_dir.as_ref().map(SysDir::memcards_dir) else {
+            return;
+        };
+        let serial = self.serial_number();
+
+        for slot in 0..self.memcard_files.len() {
+            let file_name = self.settings.memory_card_scope().file_name(slot, serial.as_deref());
+            let path = memcards_dir.join(file_name);
+            if self.memcard_files[slot].path() == path {
+                continue;
+            }
+
+            if let Err(e) = self.set_memory_card_file(slot, &path) {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    /// Without a system directory there's no memory card scope
+    #[cfg(not(feature = "std"))]
+    fn insert_default_memory_cards(&mut self) {}
+
+    /// Open the shell and put the disc image `disc_path`, relative to the games directory, in the
+    /// drive. The shell closes by itself after a while.
+    #[cfg(feature = "std")]
     pub fn insert_disc(&mut self, disc_path: &str) -> MipsResult<()> {
+        let Some(sys_dir) = &self.sys_dir else {
+            return Err(Ps1Error::NoSysDir.into());
+        };
+        self.check_cdc_firmware()?;
+
         let disc = {
-            let games_path = self.sys_dir.search(SearchFor::Games)?;
+            let games_path = sys_dir.search(SearchFor::Games)?;
             let disc_path = games_path.join(disc_path);
-            open_disc(disc_path.as_path())?
+            open_disc(disc_path.as_path(), &sys_dir.disc_cache_dir(), self.preload_limit, self.soundtrack.as_ref())?
+        };
+
+        info!("Inserting {} ({})", disc_path, disc.serial_number());
+        self.bus.insert_disc(disc);
+        Ok(())
+    }
+
+    /// Go from the BIOS initialization straight to the game or the shell, without the boot logo
+    /// and the license check. Before the console runs: the logo may already be on its way
+    /// otherwise.
+    ///
+    /// The call to the logo animation is removed from the BIOS when its address is known. The
+    /// other BIOS versions sideload the executable of the disc once the kernel is initialized,
+    /// like a PS-EXE, and show the logo anyway without a disc.
+    pub fn set_fast_boot(&mut self, enabled: bool) {
+        if !enabled || self.settings.fast_boot() {
+            return;
+        }
+
+        info!("Fast boot: on");
+        self.settings.set_fast_boot(true);
+        match self.boot_logo_hook {
+            // The call becomes a NOP
+            Some(hook) => self.bus.xmem.patch_bios(hook, 0),
+            // The emulated BIOS has no logo
+            None if self.bus.hle.is_some() => (),
+            None => self.sideload_disc_executable(),
+        }
+    }
+
+    /// Boot the executable of the disc without the shell, unless one is sideloaded already
+    fn sideload_disc_executable(&mut self) {
+        if self.bus.exe.is_some() {
+            return;
+        }
+        let Some(disc) = self.bus.cd.disc_mut() else {
+            warn!("No known way to skip the boot logo of this BIOS without a disc");
+            return;
+        };
+
+        match disc.boot_executable().and_then(|exe| Exe::from_bytes(&exe)) {
+            Ok(exe) => self.bus.exe = Some(exe),
+            Err(e) => warn!("Can't skip the boot logo of this BIOS: {}", e),
+        }
+    }
+
+    /// Like `insert_disc` with the disc image handed over in memory
+    #[cfg(feature = "std")]
+    pub fn insert_disc_image(&mut self, image: &[u8]) -> MipsResult<()> {
+        self.check_cdc_firmware()?;
+
+        let cache_dir = match &self.sys_dir {
+            Some(sys_dir) => sys_dir.disc_cache_dir(),
+            None => memory_cache_dir(),
         };
+        let disc = open_disc_image(image, &cache_dir, self.preload_limit, self.soundtrack.as_ref())?;
 
+        info!("Inserting a disc from memory ({})", disc.serial_number());
         self.bus.insert_disc(disc);
         Ok(())
     }
 
+    /// Like `insert_disc` with the disc read through `source`
+    pub fn insert_disc_source(&mut self, source: Box<dyn Image + Send>) -> MipsResult<()> {
+        self.check_cdc_firmware()?;
+
+        let source: Box<dyn Image + Send> = match &self.soundtrack {
+            Some(soundtrack) => Box::new(SoundtrackImage::new(source, Arc::clone(soundtrack))),
+            None => source,
+        };
+        let disc = Disc::new(source, self.preload_limit)?;
+
+        info!("Inserting {}", disc.serial_number());
+        self.bus.insert_disc(disc);
+        Ok(())
+    }
+
+    fn check_cdc_firmware(&self) -> MipsResult<()> {
+        if self.cdc_firmware {
+            return Ok(());
+        }
+
+        #[cfg(feature = "std")]
+        let dir = match &self.sys_dir {
+            Some(sys_dir) => sys_dir.roms_dir().display().to_string(),
+            None => "memory".to_string(),
+        };
+        #[cfg(not(feature = "std"))]
+        let dir = "memory".to_string();
+        Err(Ps1Error::NoCdcFirmware(dir).into())
+    }
+
+    /// Restart the rasterizer thread with the renderer of the settings
+    fn start_renderer(&mut self) {
+        if !self.bus.gpu.set_renderer(self.settings.renderer()) {
+            warn!("No usable GPU for the hardware renderer, drawing in software");
+        }
+
+        self.restore_rasterizer_options();
+    }
+
+    /// The rasterizer starts at the native resolution when it's restarted, and a saved state
+    /// brings back the color options it was saved with
+    fn restore_rasterizer_options(&mut self) {
+        let upscale_shift = self.settings.internal_resolution().upscale_shift();
+        if upscale_shift != 0 {
+            self.bus.gpu.set_rasterizer_option(RasterizerOption::UpscaleShift(upscale_shift));
+        }
+
+        let gpu = &mut self.bus.gpu;
+        gpu.set_rasterizer_option(RasterizerOption::DitherForceDisable(!self.settings.dithering()));
+        gpu.set_rasterizer_option(RasterizerOption::Draw24Bpp(self.settings.true_color()));
+        gpu.set_rasterizer_option(RasterizerOption::AccurateTransparency(self.settings.accurate_transparency()));
+        if self.settings.overdraw_heatmap() {
+            gpu.set_rasterizer_option(RasterizerOption::Overdraw(true));
+        }
+        if self.settings.parallel_draw_areas() {
+            gpu.set_rasterizer_option(RasterizerOption::ParallelDrawAreas(true));
+        }
+        // The wireframe options are part of the states
+        self.apply_debug_render();
+    }
+
+    fn apply_debug_render(&mut self) {
+        let debug = self.settings.debug_render();
+        let gpu = &mut self.bus.gpu;
+
+        gpu.set_rasterizer_option(RasterizerOption::Wireframe(debug.wireframe != Wireframe::Off));
+        gpu.set_rasterizer_option(RasterizerOption::DrawPolygons(debug.wireframe != Wireframe::Only));
+        gpu.set_rasterizer_option(RasterizerOption::DrawTextures(!debug.no_textures));
+        gpu.set_rasterizer_option(RasterizerOption::DrawSemiTransparency(!debug.no_semi_transparency));
+    }
+
+    /// The game executable as shipped: the sideloaded PS-EXE, or the one the disc boots
+    fn original_executable(&mut self) -> MipsResult<Vec<u8>> {
+        match (&self.exe, self.bus.cd.disc_mut()) {
+            (Some(exe), _) => Ok(exe.clone()),
+            (None, Some(disc)) => disc.boot_executable(),
+            (None, None) => Err(Ps1Error::BadExe.into()),
+        }
+    }
+
     pub fn poll_mem_cards(&mut self) {
         let mut memory_cards = self.bus.pad_memcard.memory_cards_mut();
         for (file, mc) in self.memcard_files.iter_mut().zip(memory_cards.iter_mut()) {
             let device = mc.device_mut();
 
             device.new_frame();
-            file.maybe_dump(device);
+            if !file.is_dummy() {
+                file.maybe_dump(device);
+            }
         }
     }
 
+    /// Save the pending writes of the card in `slot` to its file right away
+    fn flush_mem_card(&mut self, slot: usize) {
+        if self.memcard_files[slot].is_dummy() {
+            return;
+        }
+
+        let memory_cards = self.bus.pad_memcard.memory_cards();
+        self.memcard_files[slot].force_dump(memory_cards[slot].device());
+    }
+
     pub fn poll_gamepads(&mut self, button_states: ButtonQueue) {
         // Refresh pads
         let gamepads = self.bus.pad_memcard.gamepads_mut();
@@ -110,59 +506,181 @@ impl Ps1 {
     }
 }
 
+impl Drop for Ps1 {
+    fn drop(&mut self) {
+        // Don't lose a save made less than a second ago
+        for slot in 0..self.memcard_files.len() {
+            self.flush_mem_card(slot);
+        }
+    }
+}
+
 impl Console for Ps1 {
-    fn update(&mut self) {
-        self.bus.update();
+    fn update(&mut self) -> FrameStats {
+        let stats = self.bus.update();
+
+        if self.startup_script.as_ref().is_some_and(|(_, exe)| exe.is_loaded(&self.bus.xmem)) {
+            let (script, _) = self.startup_script.take().unwrap();
+            if script.run(&mut self.bus.xmem) {
+                info!("Startup script applied");
+            } else {
+                warn!("Startup script stopped by a check, is it for this version of the game?");
+            }
+        }
+
+        for cheat in &self.cheats {
+            cheat.apply(&mut self.bus.xmem);
+        }
+
+        self.poll_mem_cards();
+
+        stats
     }
 
-    fn clear_audio_samples(&mut self) {
-        self.bus.clear_audio_samples()
+    fn update_paused(&mut self) {
+        self.bus.update_paused();
     }
 
-    fn connect_device(&mut self, port: usize, mut device_type: DeviceType) {
-        let gamepads = self.bus.pad_memcard.gamepads_mut();
+    fn connect_device(&mut self, port: usize, device_type: ControllerType) {
+        let mut gamepads = self.bus.pad_memcard.gamepads_mut();
+        let Some(gamepad) = gamepads.get_mut(port) else {
+            warn!("Can't connect a controller to port {}, there are 2", port + 1);
+            return;
+        };
 
         let new_pad: Box<dyn DeviceInterface> = match device_type {
-            DeviceType::Unknown => Box::new(DisconnectedDevice),
-            DeviceType::Keyboard => Box::new(DigitalPad::new()),
-            DeviceType::DualShock => Box::new(DualShock::new()),
-            _ => {
-                error!(
-                "Received bogus controller config for port {}: {:?}.\
-                               Disconnecting it",
-                port, device_type
-                );
-                device_type = DeviceType::Unknown;
-                Box::new(DisconnectedDevice)
+            ControllerType::Disconnected => Box::new(DisconnectedDevice),
+            ControllerType::DigitalPad => Box::new(DigitalPad::new()),
+            ControllerType::DualShock => {
+                let mut pad = DualShock::new();
+                pad.set_analog_mode(self.settings.quirks().analog);
+                Box::new(pad)
             }
+            ControllerType::Mouse => Box::new(Mouse::new()),
+            ControllerType::GunCon => Box::new(GunCon::new()),
+            ControllerType::Justifier => Box::new(Justifier::new()),
+            ControllerType::NeGcon => Box::new(NeGcon::new()),
         };
 
         info!("New controller on port {}: {}", port, new_pad.description());
 
-        gamepads[port].connect_device(new_pad);
+        gamepad.connect_device(new_pad);
+    }
+
+    fn device_type(&self, port: usize) -> ControllerType {
+        let gamepads = self.bus.pad_memcard.gamepads();
+
+        gamepads
+            .get(port)
+            .map_or(ControllerType::Disconnected, |gamepad| gamepad.device().controller_type())
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.bus.cd.disc().map(|disc| disc.serial_number().to_string())
+    }
+
+    fn eject_disc(&mut self) {
+        if let Some(disc) = self.bus.cd.eject_disc() {
+            info!("Ejected {}", disc.serial_number());
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn swap_disc(&mut self, disc_path: &str) -> MipsResult<()> {
+        self.insert_disc(disc_path)
+    }
+
+    #[cfg(feature = "std")]
+    fn swap_disc_image(&mut self, image: &[u8]) -> MipsResult<()> {
+        self.insert_disc_image(image)
+    }
+
+    fn swap_disc_source(&mut self, source: Box<dyn Image + Send>) -> MipsResult<()> {
+        self.insert_disc_source(source)
+    }
+
+    fn is_shell_open(&self) -> bool {
+        self.bus.cd.is_shell_open()
     }
 
     fn get_frame(&mut self) -> Option<gfx::CpuFrame> {
-        match self.bus.take_frame() {
-            Some(frame) => Some(gfx::CpuFrame::from(frame)),
-            None => None
+        let mut frame = gfx::CpuFrame::from(self.bus.take_frame()?);
+        if self.settings.widescreen_hack() {
+            frame.aspect = Some(16. / 9.);
         }
+        self.thumbnail = Some(frame.downscale(
+            savestate::THUMBNAIL_WIDTH.min(frame.width),
+            savestate::THUMBNAIL_HEIGHT.min(frame.height),
+        ));
+
+        Some(frame)
     }
 
-    fn get_audio_samples(&mut self) -> &[i16] {
-        self.bus.get_audio_samples()
+    fn take_audio(&mut self) -> AudioChunk {
+        self.bus.take_audio()
     }
 
     fn handle_inputs(&mut self, inputs: ButtonQueue) {
-        let gamepads = self.bus.pad_memcard.gamepads_mut();
+        self.handle_port_inputs(0, inputs);
+    }
 
-        let device = gamepads[0].device_mut();
+    fn handle_port_inputs(&mut self, port: usize, inputs: ButtonQueue) {
+        let mut gamepads = self.bus.pad_memcard.gamepads_mut();
+        let Some(gamepad) = gamepads.get_mut(port) else {
+            warn!("Dropping the inputs of port {}, there are 2", port + 1);
+            return;
+        };
+
+        let device = gamepad.device_mut();
 
         for (state, button) in inputs.iter() {
             device.set_button_state(*button, *state);
         }
     }
 
+    fn handle_mouse(&mut self, port: usize, input: MouseInput) {
+        if let Some(gamepad) = self.bus.pad_memcard.gamepads_mut().get_mut(port) {
+            gamepad.device_mut().set_mouse_state(input);
+        }
+    }
+
+    fn handle_lightgun(&mut self, port: usize, input: LightgunInput) {
+        let state = LightgunState {
+            aim: input.position.and_then(|(x, y)| self.bus.gpu.aim(x, y)),
+            trigger: input.trigger,
+            a: input.a,
+            b: input.b,
+        };
+
+        if let Some(gamepad) = self.bus.pad_memcard.gamepads_mut().get_mut(port) {
+            gamepad.device_mut().set_lightgun_state(state);
+        }
+    }
+
+    fn handle_analog(&mut self, port: usize, input: AnalogInput) {
+        if let Some(gamepad) = self.bus.pad_memcard.gamepads_mut().get_mut(port) {
+            gamepad.device_mut().set_axis_state(input.left, input.right);
+        }
+    }
+
+    fn rumble(&self, port: usize) -> (u8, u8) {
+        self.bus.pad_memcard.gamepads().get(port).map_or((0, 0), |gamepad| gamepad.device().get_rumble())
+    }
+
+    fn handle_timed_inputs(&mut self, port: usize, inputs: &[InputEvent], window: Range<Instant>) {
+        let span = window.end.saturating_duration_since(window.start).as_secs_f64();
+
+        for input in inputs {
+            let position = if span > 0.0 {
+                input.time.saturating_duration_since(window.start).as_secs_f64() / span
+            } else {
+                1.0
+            };
+
+            self.bus.schedule_input(position, port, input.state, input.button);
+        }
+    }
+
     fn refresh_devices(&mut self) {
         // Refresh pads
         let mut gamepads = self.bus.pad_memcard.gamepads_mut();
@@ -171,30 +689,448 @@ impl Console for Ps1 {
             device.new_frame();
         }
     }
+
+    fn memory_card_image(&self, slot: usize) -> MipsResult<Option<Vec<u8>>> {
+        check_slot(slot)?;
+        let memory_cards = self.bus.pad_memcard.memory_cards();
+
+        Ok(memory_cards[slot].device().get_memory().map(|m| m.to_vec()))
+    }
+
+    fn set_memory_card_image(&mut self, slot: usize, image: Option<Vec<u8>>) -> MipsResult<()> {
+        check_slot(slot)?;
+        let device: Box<dyn DeviceInterface> = match image {
+            Some(image) => Box::new(memory_card_from_image(image)?),
+            None => Box::new(DisconnectedDevice),
+        };
+
+        info!("New memory card in slot {}: {}", slot, device.description());
+
+        // Make sure we don't flush the new contents over the previous card's file
+        self.flush_mem_card(slot);
+        let read_only = self.memcard_files[slot].is_read_only();
+        self.memcard_files[slot] = MemoryCardFile::dummy();
+        self.memcard_files[slot].set_read_only(read_only);
+
+        let mut memory_cards = self.bus.pad_memcard.memory_cards_mut();
+        memory_cards[slot].connect_device(device);
+
+        Ok(())
+    }
+
+    fn set_memory_card_file(&mut self, slot: usize, path: &Path) -> MipsResult<()> {
+        check_slot(slot)?;
+        let (mut file, card) = MemoryCardFile::load_or_create(Arc::clone(&self.vfs), path)
+            .map_err(|e| Ps1Error::BadMemoryCard(format!("{}: {}", path.display(), e)))?;
+
+        info!("New memory card in slot {}: {}", slot, path.display());
+
+        self.flush_mem_card(slot);
+        file.set_read_only(self.memcard_files[slot].is_read_only());
+        self.memcard_files[slot] = file;
+
+        let mut memory_cards = self.bus.pad_memcard.memory_cards_mut();
+        memory_cards[slot].connect_device(Box::new(card));
+
+        Ok(())
+    }
+
+    fn rewrite_memory_card(&mut self, slot: usize, image: Vec<u8>) -> MipsResult<()> {
+        check_slot(slot)?;
+        let card = memory_card_from_image(image)?;
+
+        info!("Rewrote the memory card in slot {}", slot);
+
+        let mut memory_cards = self.bus.pad_memcard.memory_cards_mut();
+        memory_cards[slot].connect_device(Box::new(card));
+        if !self.memcard_files[slot].is_dummy() {
+            self.memcard_files[slot].rewrite(memory_cards[slot].device());
+        }
+
+        Ok(())
+    }
+
+    fn set_memory_card_scope(&mut self, scope: MemoryCardScope) {
+        if scope == self.settings.memory_card_scope() {
+            return;
+        }
+
+        info!("Memory cards: {}", scope.name());
+        self.settings.set_memory_card_scope(scope);
+        self.insert_default_memory_cards();
+    }
+
+    fn set_memory_card_read_only(&mut self, slot: usize, read_only: bool) {
+        if let Err(e) = check_slot(slot) {
+            warn!("Can't change the memory card protection: {}", e);
+            return;
+        }
+
+        if read_only != self.memcard_files[slot].is_read_only() {
+            info!("Memory card in slot {} is {}", slot, if read_only { "read-only" } else { "writable" });
+        }
+
+        self.memcard_files[slot].set_read_only(read_only);
+    }
+
+    fn set_skip_draws(&mut self, skip: bool) {
+        self.bus.gpu.set_skip_draws(skip);
+    }
+
+    fn set_force_ntsc_timing(&mut self, force: bool) {
+        if force && self.bus.gpu.video_standard() == VideoStandard::Pal {
+            info!("PAL console running at NTSC timings");
+        }
+
+        self.bus.gpu.set_force_ntsc_timing(force);
+    }
+
+    fn set_cpu_backend(&mut self, backend: CpuBackend) {
+        if backend != self.settings.cpu_backend() {
+            info!("CPU backend: {:?}", backend);
+        }
+
+        self.settings.set_cpu_backend(backend);
+        self.bus.set_dynarec(self.settings.effective_cpu_backend() == CpuBackend::Dynarec);
+        self.bus.set_dynarec_gte(!self.settings.quirks().interpreter_gte);
+    }
+
+    fn set_cpu_clock(&mut self, clock: CpuClock) {
+        if clock != self.settings.cpu_clock() {
+            info!("CPU clock: {}%", clock.percent());
+        }
+
+        self.settings.set_cpu_clock(clock);
+        self.bus.set_cpu_clock(clock);
+    }
+
+    fn set_internal_resolution(&mut self, resolution: InternalResolution) {
+        if resolution == self.settings.internal_resolution() {
+            return;
+        }
+
+        info!("Internal resolution: {}x", resolution.multiplier());
+        self.settings.set_internal_resolution(resolution);
+        self.bus.gpu.set_rasterizer_option(RasterizerOption::UpscaleShift(resolution.upscale_shift()));
+    }
+
+    fn set_renderer(&mut self, renderer: RendererBackend) {
+        if renderer == self.settings.renderer() {
+            return;
+        }
+
+        info!("Renderer: {:?}", renderer);
+        // Kept even if it can't start, not to try again on every call
+        self.settings.set_renderer(renderer);
+        self.start_renderer();
+    }
+
+    fn set_pgxp(&mut self, enabled: bool) {
+        if enabled == self.settings.pgxp() {
+            return;
+        }
+
+        info!("PGXP: {}", if enabled { "on" } else { "off" });
+        self.settings.set_pgxp(enabled);
+        self.bus.gte.pgxp_mut().set_enabled(enabled);
+    }
+
+    fn set_dithering(&mut self, enabled: bool) {
+        if enabled == self.settings.dithering() {
+            return;
+        }
+
+        info!("Dithering: {}", if enabled { "on" } else { "off" });
+        self.settings.set_dithering(enabled);
+        self.bus.gpu.set_rasterizer_option(RasterizerOption::DitherForceDisable(!enabled));
+    }
+
+    fn set_accurate_transparency(&mut self, enabled: bool) {
+        if enabled == self.settings.accurate_transparency() {
+            return;
+        }
+
+        info!("Accurate transparency: {}", if enabled { "on" } else { "off" });
+        self.settings.set_accurate_transparency(enabled);
+        self.bus.gpu.set_rasterizer_option(RasterizerOption::AccurateTransparency(enabled));
+    }
+
+    fn set_true_color(&mut self, enabled: bool) {
+        if enabled == self.settings.true_color() {
+            return;
+        }
+
+        info!("True color: {}", if enabled { "on" } else { "off" });
+        self.settings.set_true_color(enabled);
+        self.bus.gpu.set_rasterizer_option(RasterizerOption::Draw24Bpp(enabled));
+    }
+
+    fn set_overdraw_heatmap(&mut self, enabled: bool) {
+        if enabled == self.settings.overdraw_heatmap() {
+            return;
+        }
+
+        info!("Overdraw heatmap: {}", if enabled { "on" } else { "off" });
+        self.settings.set_overdraw_heatmap(enabled);
+        self.bus.gpu.set_rasterizer_option(RasterizerOption::Overdraw(enabled));
+    }
+
+    fn set_parallel_draw_areas(&mut self, enabled: bool) {
+        if enabled == self.settings.parallel_draw_areas() {
+            return;
+        }
+
+        info!("Parallel draw areas: {}", if enabled { "on" } else { "off" });
+        self.settings.set_parallel_draw_areas(enabled);
+        self.bus.gpu.set_rasterizer_option(RasterizerOption::ParallelDrawAreas(enabled));
+    }
+
+    fn set_debug_render(&mut self, debug: DebugRender) {
+        if debug == self.settings.debug_render() {
+            return;
+        }
+
+        info!("Debug render: {:?}", debug);
+        self.settings.set_debug_render(debug);
+        self.apply_debug_render();
+    }
+
+    fn set_widescreen_hack(&mut self, enabled: bool) {
+        if enabled == self.settings.widescreen_hack() {
+            return;
+        }
+
+        info!("Widescreen hack: {}", if enabled { "on" } else { "off" });
+        self.settings.set_widescreen_hack(enabled);
+        self.bus.gte.set_widescreen(enabled);
+    }
+
+    fn set_interpolation(&mut self, interpolation: Interpolation) {
+        if interpolation == self.settings.interpolation() {
+            return;
+        }
+
+        info!("SPU interpolation: {}", interpolation.name());
+        self.settings.set_interpolation(interpolation);
+        self.bus.spu.set_interpolation(interpolation);
+    }
+
+    fn set_audio_mix(&mut self, mix: AudioMix) {
+        self.bus.spu.set_mix(mix);
+    }
+
+    fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        for cheat in &cheats {
+            info!("Cheat enabled: {}", cheat.name);
+        }
+
+        self.cheats = cheats;
+    }
+
+    fn patch_executable(&mut self, patch_path: &Path) -> MipsResult<()> {
+        let exe = self.original_executable()?;
+
+        let patch = self.vfs.read(patch_path)
+            .map_err(|_| Ps1Error::FileOrDirNotFound(patch_path.display().to_string()))?;
+
+        self.bus.exe = Some(Exe::from_bytes(&exe::patch::apply(&exe, &patch)?)?);
+        info!("Patched the executable with {}", patch_path.display());
+
+        Ok(())
+    }
+
+    fn set_startup_script(&mut self, script: StartupScript) -> MipsResult<()> {
+        // A sideloaded executable is the one that ends up in RAM, patched or not
+        let signature = match (&self.bus.exe, self.bus.cd.disc_mut()) {
+            (Some(exe), _) => exe.signature(),
+            (None, Some(disc)) => Exe::from_bytes(&disc.boot_executable()?)?.signature(),
+            (None, None) => return Err(Ps1Error::BadExe.into()),
+        };
+
+        self.startup_script = Some((script, signature));
+
+        Ok(())
+    }
+
+    fn set_exec_watch(&mut self, settings: Option<ExecWatchSettings>) {
+        match (settings, &mut self.bus.exec_watch) {
+            (Some(settings), Some(watch)) => watch.set_settings(settings),
+            (Some(settings), None) => {
+                self.bus.exec_watch = Some(Box::new(ExecWatch::new(settings, RAM_SIZE)))
+            }
+            (None, _) => self.bus.exec_watch = None,
+        }
+    }
+
+    fn take_exec_watch_hits(&mut self) -> Vec<ExecWatchHit> {
+        self.bus.exec_watch.as_mut().map(|w| w.take_hits()).unwrap_or_default()
+    }
+
+    fn set_cpu_trace(&mut self, limit: Option<usize>) {
+        self.bus.cpu_trace = limit.map(|limit| Box::new(CpuTrace::new(limit)));
+    }
+
+    fn take_cpu_trace(&mut self) -> (Vec<TraceEntry>, u64) {
+        self.bus.cpu_trace.as_mut().map(|t| t.take()).unwrap_or_default()
+    }
+
+    fn compiled_blocks(&self) -> Vec<CompiledBlock> {
+        self.bus.dynarec.as_ref().map(|d| d.compiled_blocks()).unwrap_or_default()
+    }
+
+    fn compiled_block_code(&self, pc: u32) -> Option<Vec<u8>> {
+        self.bus.dynarec.as_ref()?.block_code(pc)
+    }
+
+    fn read_memory(&self, addr: u32, len: usize) -> Vec<u8> {
+        (0..len).map(|i| self.bus.xmem.read8(addr.wrapping_add(i as u32))).collect()
+    }
+
+    fn write_memory(&mut self, addr: u32, data: &[u8]) {
+        for (i, &b) in data.iter().enumerate() {
+            self.bus.xmem.write8(addr.wrapping_add(i as u32), b);
+        }
+    }
+
+    fn ram(&self) -> Vec<u8> {
+        self.bus.xmem.ram()
+    }
+
+    fn set_tty_capture(&mut self, capture: bool) {
+        self.bus.tty.set_capture(capture);
+    }
+
+    fn take_tty_lines(&mut self) -> Vec<String> {
+        self.bus.tty.take_lines()
+    }
+
+    fn game_hash(&mut self) -> Option<String> {
+        let exe = self.original_executable().ok()?;
+
+        Some(hash::sha::sha256(&exe).iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    fn diagnostics(&self) -> String {
+        let bus = &self.bus;
+        let cpu = &bus.cpu;
+
+        let mut lines = vec![
+            format!(
+                "PC {:08x}{}, RA {:08x}, SP {:08x}",
+                cpu.current_pc(),
+                if cpu.in_delay_slot() { " (delay slot)" } else { "" },
+                cpu.reg(RegisterIndex(31)),
+                cpu.reg(RegisterIndex(29)),
+            ),
+            format!("SR {:08x}, CAUSE {:08x}", bus.cop0.sr(), cop0::cause(bus)),
+            format!("IRQ status {:04x}, mask {:04x}", irq::status(bus), irq::mask(bus)),
+            format!("CD drive {:?}", bus.cd.state()),
+        ];
+        if let Some(disc) = bus.cd.disc() {
+            lines.push(format!("Disc {}", disc.serial_number()));
+        }
+
+        lines.join("\n")
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        // Only fails if a part of the state can't be represented, which would be a bug
+        savestate::serialize(&self.bus, self.thumbnail.as_ref()).expect("Failed to serialize the console state")
+    }
+
+    fn save_state_raw(&self) -> Vec<u8> {
+        savestate::serialize_raw(&self.bus).expect("Failed to serialize the console state")
+    }
+
+    fn thumbnail(&self) -> Option<gfx::CpuFrame> {
+        self.thumbnail.clone()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> MipsResult<()> {
+        let saved = savestate::deserialize(state)?;
+        self.bus.load_state(saved)?;
+
+        // The state always comes back in the software rasterizer
+        if self.settings.renderer() != RendererBackend::Software {
+            self.start_renderer();
+        } else {
+            self.restore_rasterizer_options();
+        }
+
+        // Rewinding loads one per frame
+        debug!("Savestate loaded");
+        Ok(())
+    }
 }
 
-fn open_bios(bios_path: &Path) -> MipsResult<Bios> {
-    let rom = bin::from_file(bios_path)?;
-    let bios = Bios::new(rom)?;
-    Ok(bios)
+/// Memory card holding `image`, which must be a formatted card
+fn memory_card_from_image(image: Vec<u8>) -> MipsResult<MemoryCard> {
+    if image.len() != FLASH_SIZE {
+        return Err(Ps1Error::BadMemoryCard(
+            format!("expected {}B, got {}B", FLASH_SIZE, image.len())
+        ).into());
+    }
+
+    let card = MemoryCard::new_with_memory(BoxSlice::from_vec(image));
+    if !card.is_format_valid() {
+        return Err(Ps1Error::BadMemoryCard("unsupported or broken format".to_string()).into());
+    }
+
+    Ok(card)
 }
 
 /// Attempt to find the CDC firmware in the system directory
+#[cfg(feature = "std")]
 fn open_cdc_firmware(cdc_firmware_path: &Path) -> MipsResult<BoxSlice<u8, CDC_ROM_SIZE>> {
     let rom = bin::from_file(cdc_firmware_path)?;
     Ok(rom)
 }
 
-fn open_disc(disc_path: &Path) -> MipsResult<Disc> {
-    let path = disc_path;
+/// Error if there's no memory card slot `slot`, the frontends and the netplay peers pick it
+fn check_slot(slot: usize) -> MipsResult<()> {
+    if slot >= 2 {
+        return Err(Ps1Error::InvalidState(format!("No memory card slot {}", slot + 1)).into());
+    }
+
+    Ok(())
+}
+
+/// Open the disc image in `disc_path`, whatever its format. Multi-disc PBPs take the disc number
+/// after a `#` (`game.pbp#2`), the images cdimage can't read are converted in `cache_dir`. The
+/// audio tracks `soundtrack` has are played from it instead of the disc.
+#[cfg(feature = "std")]
+fn open_disc(
+    disc_path: &Path,
+    cache_dir: &Path,
+    preload_limit: u64,
+    soundtrack: Option<&Arc<dyn Soundtrack>>,
+) -> MipsResult<Disc> {
+    let (image_path, index) = formats::split_disc_index(disc_path);
+    let path = image_path.as_path();
+
+    let format = formats::sniff(path)?;
+    debug!("Disc image format: {:?}", format);
 
-    let disc = if path.extension().and_then(|ext| ext.to_str()) == Some("cue") {
-        Cue::new(path)
-    } else {
-        Cue::new_from_zip(path)
-    }.unwrap();
+    let disc = match format {
+        ImageFormat::Zip => Cue::new_from_zip(path),
+        _ => Cue::new(&formats::cue_sheet(path, format, index, cache_dir)?),
+    }.map_err(|e| Ps1Error::BadDiscFormat(e.to_string()))?;
 
-    let disc = Disc::new(Box::new(disc))?;
+    let disc: Box<dyn Image + Send> = match patch::find_patch(path) {
+        Some(patch_path) => {
+            info!("Applying patch {}", patch_path.display());
+            let patch = DiscPatch::open(&patch_path)?;
+            Box::new(PatchedImage::new(Box::new(disc), patch)?)
+        }
+        None => Box::new(disc),
+    };
+
+    let disc: Box<dyn Image + Send> = match soundtrack {
+        Some(soundtrack) => Box::new(SoundtrackImage::new(disc, Arc::clone(soundtrack))),
+        None => disc,
+    };
+
+    let disc = Disc::new(disc, preload_limit)?;
 
     let serial = disc.serial_number();
     let region = disc.region();
@@ -205,8 +1141,33 @@ fn open_disc(disc_path: &Path) -> MipsResult<Disc> {
     Ok(disc)
 }
 
-fn open_exe(path: &Path) -> MipsResult<Exe> {
-    let exe = Exe::new(path);
+/// `open_disc` for an image handed over in memory, written to `cache_dir` first
+#[cfg(feature = "std")]
+fn open_disc_image(
+    image: &[u8],
+    cache_dir: &Path,
+    preload_limit: u64,
+    soundtrack: Option<&Arc<dyn Soundtrack>>,
+) -> MipsResult<Disc> {
+    let path = formats::spill(image, cache_dir)?;
+
+    open_disc(&path, cache_dir, preload_limit, soundtrack)
+}
+
+/// Where the discs handed over in memory are written without a system directory
+#[cfg(feature = "std")]
+fn memory_cache_dir() -> PathBuf {
+    std::env::temp_dir().join("mips-disc-cache")
+}
 
-    exe
-}
\ No newline at end of file
+/// PS-EXEs are told apart from the disc images by their header, homebrew toolchains don't all
+/// name them `.exe`
+#[cfg(feature = "std")]
+pub(crate) fn is_exe(path: &Path) -> bool {
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exe")) {
+        return true;
+    }
+
+    let mut magic = [0; exe::MAGIC.len()];
+    fs::File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok_and(|_| magic == *exe::MAGIC)
+}
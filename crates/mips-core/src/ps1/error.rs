@@ -22,8 +22,22 @@ pub enum Ps1Error {
     BadDiscFormat(String),
     #[error("Invalid or unknown CDC firmware")]
     BadCdcFirmware,
+    #[error("No CDC firmware in {0}: discs can't be played without it, only the BIOS and PS-EXEs")]
+    NoCdcFirmware(String),
+    #[error("Started from memory, without a system directory to load the discs from")]
+    NoSysDir,
     #[error("Invalid PSX executable")]
     BadExe,
     #[error("Failed to patch BIOS")]
-    PatchBiosFailed
+    PatchBiosFailed,
+    #[error("Invalid disc patch: {0}")]
+    BadDiscPatch(String),
+    #[error("Invalid executable patch: {0}")]
+    BadExePatch(String),
+    #[error("Invalid memory card image: {0}")]
+    BadMemoryCard(String),
+    #[error("Invalid ZIP archive: {0}")]
+    BadArchive(String),
+    #[error("Invalid savestate: {0}")]
+    BadSavestate(String),
 }
\ No newline at end of file
@@ -1,16 +1,21 @@
-use std::fs::File;
 use std::io;
-use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use log::{error, info, warn};
 use crate::ps1::psx::pad_memcard::DeviceInterface;
 use crate::ps1::psx::pad_memcard::memory_card::{MemoryCard, FLASH_SIZE};
 use crate::ps1::util::ds::box_slice::BoxSlice;
+use crate::vfs::Vfs;
+
+mod fs;
+
+pub use fs::{ExportFormat, Fs, Save, BLOCK_SIZE, CARD_SIZE, ICON_SIZE};
 
 /// Structure holding the state of the Memory Card image on disc in order to keep it in sync with
 /// the emulated one.
-#[derive(Debug)]
-pub struct MemoryCardFile {
+pub(crate) struct MemoryCardFile {
+    /// Where the image is stored, `None` for the dummy
+    vfs: Option<Arc<dyn Vfs>>,
     /// Path to the Memory Card image
     file_path: PathBuf,
     /// Counter used to figure out if we need to flush the MemoryCard to the disc yet. Contains
@@ -18,23 +23,29 @@ pub struct MemoryCardFile {
     write_pending_since: Option<u8>,
     /// Last write counter received from the memory card. Used to detect writes.
     last_write_counter: u32,
+    /// Never flush the card: the game's writes only live in memory until the console is powered
+    /// off
+    read_only: bool,
 }
 
 impl MemoryCardFile {
-    /// Attempt to load a Memory Card image from `file_path`. If the file does not exist a freshly
-    /// formatted Memory Card image will be created instead.
+    /// Attempt to load a Memory Card image from `file_path` in `vfs`. If the file does not exist a
+    /// freshly formatted Memory Card image will be created instead.
     ///
     /// This function will return an error if `file_path` contains an unknown or unsupported file
     /// format in order to avoid data loss.
-    pub fn load_or_create(file_path: &Path) -> io::Result<(MemoryCardFile, MemoryCard)> {
+    pub fn load_or_create(vfs: Arc<dyn Vfs>, file_path: &Path) -> io::Result<(MemoryCardFile, MemoryCard)> {
+        let data = vfs.read(file_path);
         let mut mcf = MemoryCardFile {
+            vfs: Some(vfs),
             file_path: file_path.into(),
             write_pending_since: None,
             last_write_counter: 0,
+            read_only: false,
         };
 
-        let mut file = match File::open(file_path) {
-            Ok(f) => f,
+        let data = match data {
+            Ok(data) => data,
             Err(e) => {
                 if e.kind() == io::ErrorKind::NotFound {
                     // All is good, it just means that the file doesn't exist yet, we can start
@@ -51,26 +62,16 @@ impl MemoryCardFile {
             }
         };
 
-        let metadata = file.metadata()?;
-
-        if !metadata.is_file() {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a file!"));
-        }
-
-        if metadata.len() != FLASH_SIZE as u64 {
+        if data.len() != FLASH_SIZE {
             let msg = format!(
                 "Invalid file size (expected {}B MCR file, got {}B instead)",
                 FLASH_SIZE,
-                metadata.len()
+                data.len()
             );
             return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
         }
 
-        let mut memory = BoxSlice::from_vec(vec![0; FLASH_SIZE]);
-
-        file.read_exact(&mut *memory)?;
-
-        let card = MemoryCard::new_with_memory(memory);
+        let card = MemoryCard::new_with_memory(BoxSlice::from_vec(data));
 
         // Let's add one more test to see if this looks like a proper memory card image
         if !card.is_format_valid() {
@@ -88,9 +89,11 @@ impl MemoryCardFile {
     /// Allocates a dummy MemoryCardFile that won't do anything
     pub fn dummy() -> MemoryCardFile {
         MemoryCardFile {
+            vfs: None,
             file_path: PathBuf::new(),
             write_pending_since: None,
             last_write_counter: 0,
+            read_only: false,
         }
     }
 
@@ -99,6 +102,21 @@ impl MemoryCardFile {
         &self.file_path
     }
 
+    /// True if this was made by `dummy`: the card isn't backed by any file
+    pub fn is_dummy(&self) -> bool {
+        self.vfs.is_none()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Stop flushing the card to disk, or resume. Writes made in the meantime are flushed when
+    /// the card stops being read-only.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
     /// Check if the memory card contents need to be backed up. Should be called once per frame.
     pub fn maybe_dump(&mut self, mc: &dyn DeviceInterface) {
         let new_write_counter = mc.write_counter();
@@ -110,7 +128,7 @@ impl MemoryCardFile {
             self.last_write_counter = new_write_counter;
         } else {
             // No write since last time
-            self.write_pending_since = self.write_pending_since.map(|n| n + 1);
+            self.write_pending_since = self.write_pending_since.map(|n| n.saturating_add(1));
         }
 
         // XXX this algorithm has one potential weakness: if some game writes *continuously* to the
@@ -147,8 +165,18 @@ impl MemoryCardFile {
         }
     }
 
+    /// The card in the slot was replaced by `mc`, with contents of its own: save them now
+    pub fn rewrite(&mut self, mc: &dyn DeviceInterface) {
+        self.last_write_counter = mc.write_counter();
+        self.dump(mc);
+    }
+
     /// Dump the memory card to disk if a write is pending
     fn dump(&mut self, mc: &dyn DeviceInterface) {
+        if self.read_only {
+            return;
+        }
+
         let memory = match mc.get_memory() {
             Some(m) => m,
             // That shouldn't happen, probably?
@@ -160,13 +188,16 @@ impl MemoryCardFile {
 
         // XXX Should we implement some sort of memory card history to allow the user to undo
         // mistakes?
-        if self.file_path.as_os_str().is_empty() {
+        let Some(vfs) = &self.vfs else {
             // This is a dummy writer. We probably shouldn't end up here.
             warn!("Attempt to dump to a dummy Memory Card file");
             return;
-        }
+        };
+
+        // The directory is only created once there's something to save in it
+        let written = vfs.write(&self.file_path, memory);
 
-        if let Err(e) = File::create(&self.file_path).and_then(|mut file| file.write_all(memory)) {
+        if let Err(e) = written {
             // This is bad, we can't open the memory card file
             error!(
                 "Can't open memory card file '{}' for writing: {}",
@@ -0,0 +1,490 @@
+//! Saves stored on a memory card image: their export to the single-save formats of the other
+//! emulators and tools and back, and their creation, renaming and deletion.
+//!
+//! A card is 16 blocks of 8 KiB. The first one holds the directory: a header frame, then one
+//! 128-byte frame per data block telling whether it's in use, by which file, and which block comes
+//! next. A save spans one or more blocks chained that way; its first block starts with the title
+//! and icon the BIOS shows.
+//!
+//! `Fs` works on a copy of the card, see `ConsoleManager::rewrite_memory_card` to put it back in
+//! the console.
+
+use crate::error::{MipsError, MipsResult};
+
+/// Size of a directory frame, and of a sector
+const FRAME_SIZE: usize = 128;
+/// Size of a block, what the saves are made of
+pub const BLOCK_SIZE: usize = 64 * FRAME_SIZE;
+/// Size of a whole card image
+pub const CARD_SIZE: usize = 16 * BLOCK_SIZE;
+/// Blocks after the directory
+const DATA_BLOCKS: usize = 15;
+
+/// Directory entry states, in the low byte of the first word
+const FIRST_BLOCK: u8 = 0x51;
+const MIDDLE_BLOCK: u8 = 0x52;
+const LAST_BLOCK: u8 = 0x53;
+/// Blocks that are free, or were deleted (`0xa1` to `0xa3`) and can be reused
+const FREE_BLOCK: u8 = 0xa0;
+
+/// Longest file name, in bytes
+const NAME_LEN: usize = 20;
+
+/// Offset of the data in a PSV file, after the header
+const PSV_HEADER_SIZE: usize = 0x84;
+/// Size of the header of a DexDrive card, before the image
+const GME_HEADER_SIZE: usize = 0xf40;
+
+/// Size of a side of the save icons, in pixels
+pub const ICON_SIZE: usize = 16;
+
+/// Save found on a card
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Save {
+    /// File name in the directory: region, product code and the game's own identifier
+    /// (`BASCUS-94426GT2-0`)
+    pub name: String,
+    /// What the BIOS shows, decoded from Shift-JIS as far as ASCII goes
+    pub title: String,
+    /// Data blocks holding the save, in order, 0 to 14
+    pub blocks: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The directory frame followed by the blocks: the single-save format of most emulators and
+    /// card managers (ePSXe, DuckStation, MemcardRex...)
+    Mcs,
+    /// PlayStation 3 save. The signature needs the console's keys and is left blank: the PS3
+    /// doesn't take the file as is, the tools that re-sign saves and the emulators do.
+    Psv,
+    /// Just the blocks, for the tools that take raw save data
+    Raw,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Mcs, ExportFormat::Psv, ExportFormat::Raw];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Mcs => "mcs",
+            ExportFormat::Psv => "psv",
+            ExportFormat::Raw => "bin",
+        }
+    }
+}
+
+/// Filesystem of a memory card image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fs {
+    image: Vec<u8>,
+}
+
+impl Fs {
+    /// Filesystem of the card `image`
+    pub fn new(image: Vec<u8>) -> MipsResult<Fs> {
+        if image.len() != CARD_SIZE || &image[..2] != b"MC" {
+            return Err(MipsError::InvalidMemoryCard("not a memory card image".to_string()));
+        }
+
+        Ok(Fs { image })
+    }
+
+    /// The card image, with the changes made so far
+    pub fn image(&self) -> &[u8] {
+        &self.image
+    }
+
+    pub fn into_image(self) -> Vec<u8> {
+        self.image
+    }
+
+    /// The data blocks of `save`, back to back
+    pub fn data(&self, save: &Save) -> Vec<u8> {
+        save.blocks.iter().flat_map(|&b| block(&self.image, b).iter().copied()).collect()
+    }
+
+    /// Frames of the icon animation the BIOS shows for `save`, 1 to 3 `ICON_SIZE` square RGBA
+    /// pictures. Empty if the save has no title block.
+    pub fn icon(&self, save: &Save) -> Vec<Vec<u8>> {
+        let header = block(&self.image, save.blocks[0]);
+        if &header[..2] != b"SC" {
+            return Vec::new();
+        }
+
+        let frames = match header[2] {
+            0x12 => 2,
+            0x13 => 3,
+            _ => 1,
+        };
+        let palette: Vec<[u8; 4]> = header[0x60..0x80]
+            .chunks_exact(2)
+            .map(|c| rgba(u16::from_le_bytes([c[0], c[1]])))
+            .collect();
+
+        (0..frames)
+            .map(|f| {
+                let bitmap = &header[(f + 1) * FRAME_SIZE..(f + 2) * FRAME_SIZE];
+                bitmap.iter()
+                    // The first pixel is in the low nibble
+                    .flat_map(|&b| [b & 0xf, b >> 4])
+                    .flat_map(|index| palette[usize::from(index)])
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Saves on the card, in the order of their first block
+    pub fn saves(&self) -> MipsResult<Vec<Save>> {
+        let image = &self.image[..];
+        let mut saves = Vec::new();
+
+        for first in 0..DATA_BLOCKS {
+            let entry = directory_frame(image, first);
+            if entry[0] != FIRST_BLOCK {
+                continue;
+            }
+
+            let mut blocks = vec![first];
+            let mut next = u16::from_le_bytes([entry[8], entry[9]]);
+            while next != 0xffff {
+                let b = usize::from(next);
+                if b >= DATA_BLOCKS || blocks.contains(&b) {
+                    return Err(MipsError::InvalidMemoryCard(format!("broken block chain in block {}", first + 1)));
+                }
+
+                blocks.push(b);
+                let entry = directory_frame(image, b);
+                if entry[0] != MIDDLE_BLOCK && entry[0] != LAST_BLOCK {
+                    return Err(MipsError::InvalidMemoryCard(format!("broken block chain in block {}", first + 1)));
+                }
+                next = u16::from_le_bytes([entry[8], entry[9]]);
+            }
+
+            let name = ascii_until_nul(&entry[0x0a..0x0a + NAME_LEN + 1]);
+            let header = block(image, first);
+            let title = if &header[..2] == b"SC" {
+                shift_jis_to_ascii(&header[0x04..0x44])
+            } else {
+                String::new()
+            };
+
+            saves.push(Save { title: if title.is_empty() { name.clone() } else { title }, name, blocks });
+        }
+
+        Ok(saves)
+    }
+
+    /// The whole card as a DexDrive `.gme` file, the other format taken by most card managers
+    pub fn export_gme(&self) -> Vec<u8> {
+        let mut gme = vec![0; GME_HEADER_SIZE];
+        gme[..11].copy_from_slice(b"123-456-STD");
+        gme[0x12] = 0x01;
+        gme[0x14] = 0x01;
+        gme[0x15] = b'M';
+        // Copies of the state and the next block of the directory frames, the comments at 0x40 are
+        // left blank
+        for f in 0..=DATA_BLOCKS {
+            let frame = &self.image[f * FRAME_SIZE..(f + 1) * FRAME_SIZE];
+            gme[0x16 + f] = frame[0];
+            gme[0x26 + f] = frame[8];
+        }
+
+        gme.extend_from_slice(&self.image);
+        gme
+    }
+
+    /// Copy the save in `file` (an `.mcs` or `.psv` export) to the free blocks of the card
+    pub fn import(&mut self, file: &[u8]) -> MipsResult<Save> {
+        let (name, data) = if file.starts_with(b"\0VSP") && file.len() > PSV_HEADER_SIZE {
+            (ascii_until_nul(&file[0x64..0x64 + NAME_LEN]), &file[PSV_HEADER_SIZE..])
+        } else if file.len() > FRAME_SIZE && file[0] == FIRST_BLOCK {
+            (ascii_until_nul(&file[0x0a..0x0a + NAME_LEN]), &file[FRAME_SIZE..])
+        } else {
+            return Err(MipsError::InvalidMemoryCard("not a .mcs or .psv save".to_string()));
+        };
+        if name.is_empty() || data.is_empty() || data.len() % BLOCK_SIZE != 0 {
+            return Err(MipsError::InvalidMemoryCard("broken save file".to_string()));
+        }
+
+        let save = self.create(&name, data.len() / BLOCK_SIZE)?;
+        for (i, &b) in save.blocks.iter().enumerate() {
+            self.image[(b + 1) * BLOCK_SIZE..(b + 2) * BLOCK_SIZE]
+                .copy_from_slice(&data[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE]);
+        }
+
+        Ok(save)
+    }
+
+    /// Take the first `blocks` free blocks of the card for a new save named `name`, like the BIOS
+    /// does when a game creates a file. The data blocks are left as they were.
+    pub fn create(&mut self, name: &str, blocks: usize) -> MipsResult<Save> {
+        self.check_name(name)?;
+
+        let free: Vec<usize> = (0..DATA_BLOCKS)
+            .filter(|&b| directory_frame(&self.image, b)[0] & 0xf0 == FREE_BLOCK)
+            .take(blocks)
+            .collect();
+        if blocks == 0 || free.len() < blocks {
+            return Err(MipsError::InvalidMemoryCard(
+                format!("{} needs {} free blocks, the card has {}", name, blocks, free.len())
+            ));
+        }
+
+        for (i, &b) in free.iter().enumerate() {
+            let mut frame = [0; FRAME_SIZE];
+            frame[0] = match i {
+                0 => FIRST_BLOCK,
+                _ if i == blocks - 1 => LAST_BLOCK,
+                _ => MIDDLE_BLOCK,
+            };
+            if i == 0 {
+                frame[4..8].copy_from_slice(&((blocks * BLOCK_SIZE) as u32).to_le_bytes());
+                frame[0x0a..0x0a + name.len()].copy_from_slice(name.as_bytes());
+            }
+            let next = free.get(i + 1).map_or(0xffff, |&n| n as u16);
+            frame[8..10].copy_from_slice(&next.to_le_bytes());
+            frame[0x7f] = checksum(&frame[..0x7f]);
+
+            self.image[(b + 1) * FRAME_SIZE..(b + 2) * FRAME_SIZE].copy_from_slice(&frame);
+        }
+
+        self.saves()?
+            .into_iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| MipsError::InvalidMemoryCard("broken save file".to_string()))
+    }
+
+    /// Give `save` the file name `name`
+    pub fn rename(&mut self, save: &Save, name: &str) -> MipsResult<()> {
+        self.check_name(name)?;
+
+        let start = (save.blocks[0] + 1) * FRAME_SIZE;
+        let frame = &mut self.image[start..start + FRAME_SIZE];
+        frame[0x0a..0x0a + NAME_LEN + 1].fill(0);
+        frame[0x0a..0x0a + name.len()].copy_from_slice(name.as_bytes());
+        frame[0x7f] = checksum(&frame[..0x7f]);
+
+        Ok(())
+    }
+
+    /// `name` fits in a directory frame and isn't taken on the card
+    fn check_name(&self, name: &str) -> MipsResult<()> {
+        if name.is_empty() || name.len() > NAME_LEN || !name.is_ascii() {
+            return Err(MipsError::InvalidMemoryCard(format!("invalid save name {:?}", name)));
+        }
+        if self.saves()?.iter().any(|s| s.name == name) {
+            return Err(MipsError::InvalidMemoryCard(format!("{} is already on the card", name)));
+        }
+
+        Ok(())
+    }
+
+    /// Delete `save` the way the BIOS does: its blocks are marked deleted and reused by the next
+    /// saves, the data stays until then
+    pub fn delete(&mut self, save: &Save) {
+        for &b in &save.blocks {
+            let start = (b + 1) * FRAME_SIZE;
+            let frame = &mut self.image[start..start + FRAME_SIZE];
+            frame[0] = FREE_BLOCK | (frame[0] & 0xf);
+            frame[0x7f] = checksum(&frame[..0x7f]);
+        }
+    }
+
+    /// `save`, as a file in `format`
+    pub fn export(&self, save: &Save, format: ExportFormat) -> Vec<u8> {
+        let data = self.data(save);
+
+        match format {
+            ExportFormat::Mcs => {
+                let mut frame = directory_frame(&self.image, save.blocks[0]).to_vec();
+                // A lone save ends after its own blocks, the links to the card's other blocks don't
+                // mean anything anymore
+                frame[8] = 0xff;
+                frame[9] = 0xff;
+                frame[0x7f] = checksum(&frame[..0x7f]);
+
+                frame.extend_from_slice(&data);
+                frame
+            }
+            ExportFormat::Psv => {
+                let mut psv = vec![0; PSV_HEADER_SIZE];
+                psv[..4].copy_from_slice(b"\0VSP");
+                // 0x08: key seed, 0x1c: HMAC-SHA1 signature, both left blank
+                psv[0x38..0x3c].copy_from_slice(&0x14u32.to_le_bytes());
+                // PS1 save
+                psv[0x3c..0x40].copy_from_slice(&1u32.to_le_bytes());
+                psv[0x40..0x44].copy_from_slice(&(data.len() as u32).to_le_bytes());
+                psv[0x44..0x48].copy_from_slice(&(PSV_HEADER_SIZE as u32).to_le_bytes());
+                psv[0x48..0x4c].copy_from_slice(&0x200u32.to_le_bytes());
+                psv[0x5c..0x60].copy_from_slice(&(data.len() as u32).to_le_bytes());
+                psv[0x60..0x64].copy_from_slice(&0x9003u32.to_le_bytes());
+                let name = save.name.as_bytes();
+                psv[0x64..0x64 + name.len().min(NAME_LEN)].copy_from_slice(&name[..name.len().min(NAME_LEN)]);
+
+                psv.extend_from_slice(&data);
+                psv
+            }
+            ExportFormat::Raw => data,
+        }
+    }
+}
+
+/// Directory frame describing data block `b`
+fn directory_frame(image: &[u8], b: usize) -> &[u8] {
+    let start = (b + 1) * FRAME_SIZE;
+
+    &image[start..start + FRAME_SIZE]
+}
+
+/// Data block `b`
+fn block(image: &[u8], b: usize) -> &[u8] {
+    let start = (b + 1) * BLOCK_SIZE;
+
+    &image[start..start + BLOCK_SIZE]
+}
+
+/// Icon palette entry: 15-bit BGR, black is transparent
+fn rgba(color: u16) -> [u8; 4] {
+    let component = |shift: u16| {
+        let c = ((color >> shift) & 0x1f) as u8;
+        (c << 3) | (c >> 2)
+    };
+
+    [component(0), component(5), component(10), if color == 0 { 0 } else { 0xff }]
+}
+
+fn checksum(d: &[u8]) -> u8 {
+    d.iter().fold(0, |c, b| c ^ b)
+}
+
+fn ascii_until_nul(bytes: &[u8]) -> String {
+    bytes.iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '?' })
+        .collect()
+}
+
+/// The titles are in Shift-JIS, mostly with the full-width versions of the ASCII characters.
+/// Anything else (kana, kanji) becomes `?`.
+fn shift_jis_to_ascii(bytes: &[u8]) -> String {
+    let mut title = String::new();
+    let mut i = 0;
+
+    while i < bytes.len() && bytes[i] != 0 {
+        let b = bytes[i];
+
+        if b < 0x80 {
+            title.push(b as char);
+            i += 1;
+            continue;
+        }
+
+        let Some(&second) = bytes.get(i + 1) else {
+            break;
+        };
+        let c = match u16::from_be_bytes([b, second]) {
+            0x8140 => ' ',
+            0x8143 => ',',
+            0x8144 => '.',
+            0x8146 => ':',
+            0x8147 => ';',
+            0x8148 => '?',
+            0x8149 => '!',
+            0x815e => '/',
+            0x8169 => '(',
+            0x816a => ')',
+            0x817b => '+',
+            0x817c => '-',
+            0x8181 => '=',
+            0x8193 => '%',
+            0x8194 => '#',
+            0x8195 => '&',
+            0x8196 => '*',
+            c @ 0x824f..=0x8258 => char::from(b'0' + (c - 0x824f) as u8),
+            c @ 0x8260..=0x8279 => char::from(b'A' + (c - 0x8260) as u8),
+            c @ 0x8281..=0x829a => char::from(b'a' + (c - 0x8281) as u8),
+            _ => '?',
+        };
+        title.push(c);
+        i += 2;
+    }
+
+    title.trim_end().to_string()
+}
+
+#[test]
+fn export_saves() {
+    let mut image = vec![0; CARD_SIZE];
+    image[..2].copy_from_slice(b"MC");
+    for b in 0..DATA_BLOCKS {
+        let frame = &mut image[(b + 1) * FRAME_SIZE..(b + 2) * FRAME_SIZE];
+        frame[0] = 0xa0;
+        frame[8] = 0xff;
+        frame[9] = 0xff;
+    }
+
+    // Two block save in blocks 2 and 5
+    let name = b"BASLUS-00001SAVE";
+    let entry = &mut image[3 * FRAME_SIZE..4 * FRAME_SIZE];
+    entry[0] = FIRST_BLOCK;
+    entry[4..8].copy_from_slice(&(2 * BLOCK_SIZE as u32).to_le_bytes());
+    entry[8..10].copy_from_slice(&5u16.to_le_bytes());
+    entry[0x0a..0x0a + name.len()].copy_from_slice(name);
+    let entry = &mut image[6 * FRAME_SIZE..7 * FRAME_SIZE];
+    entry[0] = LAST_BLOCK;
+    entry[8] = 0xff;
+    entry[9] = 0xff;
+
+    let first = 3 * BLOCK_SIZE;
+    image[first..first + 2].copy_from_slice(b"SC");
+    // "ＧＴ 2" in full-width Shift-JIS
+    image[first + 4..first + 10].copy_from_slice(&[0x82, 0x66, 0x82, 0x73, 0x81, 0x40]);
+    image[first + 10] = b'2';
+    image[6 * BLOCK_SIZE] = 0x42;
+
+    let mut fs = Fs::new(image).unwrap();
+    let found = fs.saves().unwrap();
+    assert_eq!(found, vec![Save {
+        name: "BASLUS-00001SAVE".to_string(),
+        title: "GT 2".to_string(),
+        blocks: vec![2, 5],
+    }]);
+
+    let mcs = fs.export(&found[0], ExportFormat::Mcs);
+    assert_eq!(mcs.len(), FRAME_SIZE + 2 * BLOCK_SIZE);
+    assert_eq!(&mcs[8..10], &[0xff, 0xff]);
+    assert_eq!(checksum(&mcs[..FRAME_SIZE]), 0);
+    assert_eq!(mcs[FRAME_SIZE + BLOCK_SIZE], 0x42);
+
+    let psv = fs.export(&found[0], ExportFormat::Psv);
+    assert_eq!(&psv[..4], b"\0VSP");
+    assert_eq!(&psv[0x64..0x64 + name.len()], name);
+    assert_eq!(&psv[PSV_HEADER_SIZE..], &fs.export(&found[0], ExportFormat::Raw)[..]);
+
+    let icon = fs.icon(&found[0]);
+    assert_eq!(icon.len(), 1);
+    assert_eq!(icon[0].len(), ICON_SIZE * ICON_SIZE * 4);
+
+    let gme = fs.export_gme();
+    assert_eq!(&gme[..11], b"123-456-STD");
+    assert_eq!(&gme[GME_HEADER_SIZE..], fs.image());
+
+    // Deleting frees the blocks, importing back takes the first free ones
+    fs.delete(&found[0]);
+    assert!(fs.saves().unwrap().is_empty());
+    let imported = fs.import(&mcs).unwrap();
+    assert_eq!(imported.blocks, vec![0, 1]);
+    assert_eq!(imported.title, "GT 2");
+    assert_eq!(fs.image()[2 * BLOCK_SIZE], 0x42);
+    assert!(fs.import(&psv).is_err());
+    fs.delete(&imported);
+    assert_eq!(fs.import(&psv).unwrap().blocks, vec![0, 1]);
+
+    // Chains looping on themselves are rejected
+    let mut image = fs.into_image();
+    image[2 * FRAME_SIZE + 8..2 * FRAME_SIZE + 10].copy_from_slice(&0u16.to_le_bytes());
+    assert!(Fs::new(image).unwrap().saves().is_err());
+
+    assert!(Fs::new(vec![0; CARD_SIZE]).is_err());
+}
@@ -2,7 +2,6 @@ pub mod bus;
 pub mod processor;
 
 pub mod bios;
-mod addressable;
 mod memory;
 pub(crate) mod graphics;
 mod sync;
@@ -11,7 +10,12 @@ mod mdec;
 mod sound;
 pub mod cd;
 pub mod pad_memcard;
-mod xmem;
+pub(crate) mod xmem;
 pub mod exe;
 mod assembler;
-mod tty;
\ No newline at end of file
+mod tty;
+#[cfg(test)]
+mod mock_bus;
+
+#[cfg(feature = "bench")]
+pub mod bench;
@@ -78,6 +78,7 @@ pub mod syntax {
         // Coprocessor opcodes
         Mfc0(Register, u8),
         Mtc0(Register, u8),
+        Rfe,
 
         /// Global labels: can't be redefined
         Global(&'static str),
@@ -242,6 +243,11 @@ impl Assembler {
         Ok(self.location() - start_loc)
     }
 
+    /// Address of the global label `name`, if it's been assembled
+    pub fn global(&self, name: &str) -> Option<u32> {
+        self.globals.get(name).copied()
+    }
+
     /// Consume the Assembler and return the generated machine code
     /// alongside the base address
     pub fn machine_code(self) -> (Vec<u8>, u32) {
@@ -513,6 +519,11 @@ impl Assembler {
                     .t(r0)
                     .cop_r(cop_r),
             ),
+            Rfe => self.emit_code(
+                MachineCode::op(0b01_0000)
+                    .cop_opcode(0b1_0000)
+                    .function(0b01_0000),
+            ),
 
             // Alignment padding
             Align(o) => {
@@ -600,6 +611,10 @@ impl MachineCode {
         MachineCode(self.0 | ((r.0 as u32) << 11))
     }
 
+    fn function(self, f: u8) -> MachineCode {
+        MachineCode(self.0 | (f as u32))
+    }
+
     fn cop_r(self, cop_r: u8) -> MachineCode {
         MachineCode(self.0 | ((cop_r as u32) << 11))
     }
@@ -662,6 +677,7 @@ fn opcodes() {
         (Ori(T5, T5, 0xbeef), [0xef, 0xbe, 0xad, 0x35]),
         (Break(0x1234), [0x0d, 0x8d, 0x04, 0x00]),
         (Jal(Label::Absolute(0xabc)), [0xaf, 0x02, 0x00, 0x0c]),
+        (Rfe, [0x10, 0x00, 0x00, 0x42]),
     ];
 
     for &(instruction, ref expected) in &tests {
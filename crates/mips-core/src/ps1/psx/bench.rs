@@ -0,0 +1,267 @@
+//! Interpreter workloads for the benchmarks: small loops of machine code running from RAM with a
+//! dummy BIOS, so that they don't need any system file.
+
+use crate::ps1::psx::assembler::{Assembler, syntax::*};
+use crate::ps1::psx::bios::bios::Bios;
+use crate::ps1::psx::bus::Bus;
+use crate::ps1::psx::cd::CdInterface;
+use crate::ps1::psx::processor::cpu;
+use crate::ps1::psx::sound::spu;
+use crate::ps1::psx::sync;
+use crate::ps1::savestate;
+
+/// Where the code is loaded, in cached KSEG0
+const BASE: u32 = 0x8001_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// Register to register operations
+    Alu,
+    /// Loads and stores to RAM and the scratch pad
+    Memory,
+    /// Branches, calls and returns
+    Branches,
+    /// Multiplications and divisions
+    MulDiv,
+    /// Loads and stores to hardware registers
+    Hardware,
+}
+
+impl Workload {
+    pub const ALL: [Workload; 5] = [
+        Workload::Alu,
+        Workload::Memory,
+        Workload::Branches,
+        Workload::MulDiv,
+        Workload::Hardware,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Workload::Alu => "alu",
+            Workload::Memory => "memory",
+            Workload::Branches => "branches",
+            Workload::MulDiv => "muldiv",
+            Workload::Hardware => "hardware",
+        }
+    }
+
+    fn program(self) -> Vec<Instruction> {
+        let mut program = match self {
+            Workload::Alu => vec![
+                Li(T0, 0x1234_5678),
+                Li(T1, 0x9abc_def0),
+                Li(T2, 0),
+                Local("loop"),
+                Addu(T3, T0, T1),
+                Xor(T4, T3, T0),
+                Sll(T5, T4, 3),
+                Srl(T6, T5, 7),
+                Or(T7, T6, T3),
+                And(T8, T7, T1),
+                Subu(T9, T8, T2),
+                Slt(S0, T9, T0),
+                Sltu(S1, T0, T9),
+                Nor(S2, S0, S1),
+                Addiu(T2, T2, 1),
+                Ori(T0, T3, 0x55),
+                Andi(T1, T4, 0xffff),
+                Lui(S3, 0x1234),
+            ],
+            Workload::Memory => vec![
+                Li(T0, 0x8010_0000),
+                Li(T1, 0x1f80_0000),
+                Li(T2, 0),
+                Local("loop"),
+                Sw(T2, T0, 0),
+                Lw(T3, T0, 0),
+                Sh(T3, T0, 4),
+                Lhu(T4, T0, 4),
+                Sb(T4, T0, 8),
+                Lb(T5, T0, 8),
+                Sw(T2, T1, 0),
+                Lw(T6, T1, 0),
+                Lw(T7, T1, 4),
+                Addu(T8, T6, T7),
+                Addiu(T2, T2, 1),
+            ],
+            Workload::Branches => vec![
+                Li(T0, 1),
+                Local("loop"),
+                Jal(Label::Global("function")),
+                Nop,
+                Beq(T0, T0, Label::Local("skip", 'f')),
+                Nop,
+                Addiu(T1, T1, 1),
+                Local("skip"),
+                Bnez(R0, Label::Local("loop", 'b')),
+                Nop,
+                Blez(T0, Label::Local("loop", 'b')),
+                Nop,
+            ],
+            Workload::MulDiv => vec![
+                Li(T0, 12345),
+                Li(T1, 678),
+                Local("loop"),
+                Mult(T0, T1),
+                Mflo(T2),
+                Divu(T2, T1),
+                Mflo(T3),
+                Mfhi(T4),
+                Addiu(T0, T0, 1),
+            ],
+            Workload::Hardware => vec![
+                Li(T0, 0x1f80_1000),
+                Local("loop"),
+                // IRQ status and mask
+                Lw(T1, T0, 0x70),
+                Lw(T2, T0, 0x74),
+                // DMA control and interrupt registers
+                Lw(T3, T0, 0xf0),
+                Sw(T3, T0, 0xf0),
+                Lw(T4, T0, 0xf4),
+                Lw(T5, T0, 0x60),
+                Addiu(T6, T6, 1),
+            ],
+        };
+
+        program.extend_from_slice(&[B(Label::Local("loop", 'b')), Nop]);
+
+        if self == Workload::Branches {
+            program.extend_from_slice(&[Global("function"), Addiu(T2, T2, 1), Jr(RA), Nop]);
+        }
+
+        program
+    }
+}
+
+/// A console running a workload forever
+pub struct Interpreter {
+    bus: Box<Bus>,
+}
+
+impl Interpreter {
+    pub fn new(workload: Workload) -> Interpreter {
+        let cd = CdInterface::new_without_firmware();
+        let mut bus = Box::new(Bus::with_cd(Bios::new_dummy(), cd));
+
+        let mut asm = Assembler::from_base(BASE);
+        asm.assemble(&workload.program()).unwrap();
+        let (code, _) = asm.machine_code();
+        bus.xmem.ram_store_block(BASE, &code, code.len());
+
+        // Enable the instruction cache like the BIOS does
+        bus.store(0xfffe_0130, 0x0001_e988u32);
+
+        bus.cpu.pc = BASE;
+        bus.cpu.next_pc = BASE + 4;
+
+        Interpreter { bus }
+    }
+
+    /// Run the frames with the dynarec, if the host supports it. Returns false if it doesn't.
+    pub fn use_dynarec(&mut self) -> bool {
+        self.bus.set_dynarec(true);
+        self.bus.dynarec.is_some()
+    }
+
+    /// Emulate a video frame
+    pub fn run_frame(&mut self) {
+        self.bus.update();
+    }
+
+    /// Run `count` instructions without handling any event, that's the interpreter on its own
+    pub fn run_instructions(&mut self, count: u32) {
+        for _ in 0..count {
+            cpu::run_next_instruction(&mut self.bus);
+        }
+
+        // Nothing else moves the counters back since no event runs
+        sync::rebase_counters(&mut self.bus);
+    }
+
+    /// Compressed savestate, like the ones saved to the disk
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::serialize(&self.bus, None).unwrap()
+    }
+
+    /// Uncompressed savestate, like the rewind snapshots
+    pub fn save_state_raw(&self) -> Vec<u8> {
+        savestate::serialize_raw(&self.bus).unwrap()
+    }
+
+    /// Load a savestate made by `save_state` or `save_state_raw`
+    pub fn load_state(&mut self, state: &[u8]) {
+        let saved = savestate::deserialize(state).unwrap();
+        self.bus.load_state(saved).unwrap();
+    }
+}
+
+/// The SPU on its own with all the voices playing through the reverb
+pub struct Audio {
+    bus: Box<Bus>,
+}
+
+impl Audio {
+    pub fn new() -> Audio {
+        let mut interpreter = Interpreter::new(Workload::Alu);
+        let bus = &mut interpreter.bus;
+
+        let spu = |reg: u32| 0x1f80_1c00 + reg * 2;
+
+        for voice in 0..24 {
+            let base = voice * 8;
+
+            // Volume
+            bus.store(spu(base), 0x3fffu16);
+            bus.store(spu(base + 1), 0x3fffu16);
+            // Step length: a bit off 44.1kHz so that the interpolation is used
+            bus.store(spu(base + 2), 0x1100u16 + voice as u16 * 0x10);
+            // Start index, the RAM is full of blank ADPCM blocks
+            bus.store(spu(base + 3), 0x0200u16 + voice as u16 * 0x100);
+            // ADSR: fast attack, slow decay, sustain forever
+            bus.store(spu(base + 4), 0x000fu16);
+            bus.store(spu(base + 5), 0x1fc0u16);
+        }
+
+        // Main and reverb volumes
+        for reg in 0xc0..0xc4 {
+            bus.store(spu(reg), 0x3fffu16);
+        }
+        // Reverb on every voice, with the work area at the end of the RAM
+        bus.store(spu(0xcc), 0xffffu16);
+        bus.store(spu(0xcd), 0x00ffu16);
+        bus.store(spu(0xd1), 0xe000u16);
+        // Enabled, unmuted, reverb enabled
+        bus.store(spu(0xd5), 0xc080u16);
+        // Start all the voices
+        bus.store(spu(0xc4), 0xffffu16);
+        bus.store(spu(0xc5), 0x00ffu16);
+
+        Audio { bus: interpreter.bus }
+    }
+
+    /// Generate `count` stereo samples
+    pub fn run_samples(&mut self, count: u32) {
+        // The output buffer has to be emptied before it fills up
+        const CHUNK: u32 = 512;
+
+        let mut left = count;
+        while left > 0 {
+            let n = left.min(CHUNK);
+
+            self.bus.tick(n as i32 * spu::SPU_FREQ_DIVIDER);
+            spu::take_samples(&mut *self.bus);
+
+            left -= n;
+        }
+
+        sync::rebase_counters(&mut self.bus);
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio::new()
+    }
+}
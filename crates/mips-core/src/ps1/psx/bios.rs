@@ -1,3 +1,5 @@
 pub mod bios;
+pub mod dumps;
+pub mod hle;
 
 mod metadata;
\ No newline at end of file
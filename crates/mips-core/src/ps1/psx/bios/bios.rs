@@ -1,6 +1,6 @@
 use crate::error::*;
 use crate::ps1::Ps1Error;
-use crate::ps1::psx::bios::metadata;
+use crate::ps1::psx::bios::{hle, metadata};
 use crate::ps1::psx::bios::metadata::{lookup_blob, Metadata};
 use crate::ps1::util::ds::box_slice::BoxSlice;
 
@@ -22,6 +22,30 @@ impl Bios {
         }
     }
 
+    /// Same as `new` with a dump of any size, which must be the one of a BIOS
+    pub fn from_bytes(rom: &[u8]) -> MipsResult<Bios> {
+        if rom.len() != BIOS_SIZE {
+            return Err(MipsError::from(Ps1Error::UnknownBios(
+                format!("expected {}B, got {}B", BIOS_SIZE, rom.len())
+            )));
+        }
+
+        Bios::new(BoxSlice::from_vec(rom.to_vec()))
+    }
+
+    /// The BIOS emulated in Rust, for the players without a dump, see `hle`
+    pub fn new_hle() -> Bios {
+        Bios {
+            rom: BoxSlice::from_vec(hle::rom()),
+            metadata: &metadata::HLE,
+        }
+    }
+
+    /// True for the BIOS of `new_hle`
+    pub fn is_hle(&self) -> bool {
+        std::ptr::eq(self.metadata, &metadata::HLE)
+    }
+
     /// Return a static pointer to the BIOS's Metadata
     pub fn metadata(&self) -> &'static Metadata {
         self.metadata
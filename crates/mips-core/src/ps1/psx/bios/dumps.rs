@@ -0,0 +1,288 @@
+//! Finding the BIOS among the files of the system directory, and explaining what's wrong when none
+//! of them is supported. Dumps can be raw images or sit in a ZIP archive. A dump of the region of
+//! the disc is picked when there's one: the others refuse the discs of other regions.
+
+use std::fmt;
+use std::path::PathBuf;
+use crate::ps1::psx::bios::bios::BIOS_SIZE;
+use crate::ps1::psx::bios::metadata::{self, Metadata};
+/// Region of a BIOS dump, it refuses the discs of the others
+pub use crate::ps1::psx::bios::metadata::Region;
+// Looking for the dumps on disk
+#[cfg(feature = "std")]
+use {
+    std::fs,
+    std::path::Path,
+    log::{info, warn},
+    crate::error::{MipsError, MipsResult},
+    crate::ps1::hash::sha,
+    crate::ps1::psx::bios::bios::Bios,
+    crate::ps1::psx::cd::disc,
+    crate::ps1::util::ds::box_slice::BoxSlice,
+    crate::ps1::util::fs::sys_dir::SysDir,
+    crate::ps1::util::fs::zip::ZipArchive,
+};
+
+/// A BIOS dump the emulator supports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownBios {
+    /// e.g. "4.1"
+    pub version: String,
+    pub region: Region,
+    /// Hex SHA-256 of the dump
+    pub sha256: String,
+}
+
+impl KnownBios {
+    fn new(metadata: &Metadata) -> KnownBios {
+        KnownBios {
+            version: format!("{}.{}", metadata.version_major, metadata.version_minor),
+            region: metadata.region,
+            sha256: hex(&metadata.sha256),
+        }
+    }
+}
+
+/// Every dump in the database
+pub fn known_dumps() -> Vec<KnownBios> {
+    metadata::DATABASE.iter().map(KnownBios::new).collect()
+}
+
+/// A file that may be a BIOS dump: it has the size of one, or it's an archive holding one
+#[derive(Debug, Clone)]
+pub struct BiosCandidate {
+    /// The dump, or the archive holding it
+    pub path: PathBuf,
+    /// Name of the dump in the archive
+    pub entry: Option<String>,
+    /// Hex SHA-256 of the dump, `Err` if it couldn't be read
+    pub sha256: Result<String, String>,
+    /// Matching database entry, `None` for unsupported or corrupted dumps
+    pub known: Option<KnownBios>,
+}
+
+impl fmt::Display for BiosCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(entry) = &self.entry {
+            write!(f, " ({})", entry)?;
+        }
+
+        match (&self.sha256, &self.known) {
+            (Err(e), _) => write!(f, ": {}", e),
+            (Ok(_), Some(known)) => write!(f, ": BIOS {} {}", known.version, known.region),
+            (Ok(sha256), None) => write!(f, ": unknown dump, SHA-256 {}", sha256),
+        }
+    }
+}
+
+/// Where the BIOS was looked for and what was found there
+#[derive(Debug, Clone)]
+pub struct BiosSearch {
+    pub dir: PathBuf,
+    pub candidates: Vec<BiosCandidate>,
+}
+
+impl fmt::Display for BiosSearch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.candidates.is_empty() {
+            return write!(f, "no {} KiB file or ZIP archive in {}", BIOS_SIZE / 1024, self.dir.display());
+        }
+
+        write!(f, "none of the dumps in {} is supported", self.dir.display())?;
+        for candidate in &self.candidates {
+            write!(f, "; {}", candidate)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every file of the system directory that may be a BIOS dump
+#[cfg(feature = "std")]
+pub fn scan(sys_dir: &Path) -> BiosSearch {
+    let dir = SysDir::new(sys_dir).roms_dir();
+    let mut candidates = Vec::new();
+
+    for path in dir_files(&dir) {
+        candidates.extend(read_dumps(&path).into_iter().map(|(candidate, _)| candidate));
+    }
+
+    BiosSearch { dir, candidates }
+}
+
+/// First supported dump in `dir` of the region of the disc, `region`, or of any region if there's
+/// none or no disc
+#[cfg(feature = "std")]
+pub(crate) fn find(dir: &Path, region: Option<disc::Region>) -> MipsResult<Bios> {
+    let files = dir_files(dir);
+    search(dir, &files, region.map(Region::from))
+}
+
+/// The dump in `path`, a raw image or an archive
+#[cfg(feature = "std")]
+pub(crate) fn open(path: &Path) -> MipsResult<Bios> {
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    search(&dir, &[path.to_path_buf()], None)
+}
+
+#[cfg(feature = "std")]
+fn search(dir: &Path, files: &[PathBuf], region: Option<Region>) -> MipsResult<Bios> {
+    let dumps = files.iter().flat_map(|path| read_dumps(path));
+
+    match pick(dumps, region) {
+        Pick::Region(candidate, rom) => {
+            info!("Using {}", candidate);
+            Bios::new(BoxSlice::from_vec(rom))
+        }
+        Pick::Other(candidate, rom) => {
+            if let Some(region) = region {
+                warn!("No BIOS for {} in {}, using {}", region, dir.display(), candidate);
+            }
+            Bios::new(BoxSlice::from_vec(rom))
+        }
+        Pick::None(candidates) => Err(MipsError::NoBios(BiosSearch {
+            dir: dir.to_path_buf(),
+            candidates,
+        })),
+    }
+}
+
+/// Dump picked by `pick`, with its contents
+#[cfg(feature = "std")]
+enum Pick {
+    /// Of the region asked for, or of any region if none was
+    Region(BiosCandidate, Vec<u8>),
+    /// Of another region, there's none of the one asked for
+    Other(BiosCandidate, Vec<u8>),
+    /// No supported dump, these are all the ones looked at
+    None(Vec<BiosCandidate>),
+}
+
+/// First supported dump of `region` among `dumps`, the first supported one of any region if
+/// there's none. The dumps after the one picked aren't read.
+#[cfg(feature = "std")]
+fn pick(dumps: impl IntoIterator<Item = (BiosCandidate, Option<Vec<u8>>)>, region: Option<Region>) -> Pick {
+    let mut candidates = Vec::new();
+    // First supported dump, in case none is of the region
+    let mut other_region = None;
+
+    for (candidate, rom) in dumps {
+        if let (Some(known), Some(rom)) = (&candidate.known, rom) {
+            if region.is_none_or(|region| known.region == region) {
+                return Pick::Region(candidate, rom);
+            }
+            if other_region.is_none() {
+                other_region = Some((candidate.clone(), rom));
+            }
+        }
+        candidates.push(candidate);
+    }
+
+    match other_region {
+        Some((candidate, rom)) => Pick::Other(candidate, rom),
+        None => Pick::None(candidates),
+    }
+}
+
+/// Files of `dir`, sorted so that the same dump is picked every time
+#[cfg(feature = "std")]
+fn dir_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| p.is_file()).collect())
+        .unwrap_or_default();
+    files.sort();
+
+    files
+}
+
+/// The dumps in `path` with their contents: the file itself if it has the size of a BIOS, or the
+/// entries of that size if it's an archive. Nothing if it's neither.
+#[cfg(feature = "std")]
+fn read_dumps(path: &Path) -> Vec<(BiosCandidate, Option<Vec<u8>>)> {
+    let candidate = |entry: Option<String>, rom: Result<Vec<u8>, String>| {
+        let (sha256, known, rom) = match rom {
+            Ok(rom) => {
+                let sha256 = sha::sha256(&rom);
+                let known = metadata::lookup_sha256(sha256).map(KnownBios::new);
+                (Ok(hex(&sha256)), known, Some(rom))
+            }
+            Err(e) => (Err(e), None, None),
+        };
+
+        let candidate = BiosCandidate {
+            path: path.to_path_buf(),
+            entry,
+            sha256,
+            known,
+        };
+        (candidate, rom)
+    };
+
+    let is_zip = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if !is_zip {
+        let is_dump = fs::metadata(path).is_ok_and(|md| md.len() == BIOS_SIZE as u64);
+        if !is_dump {
+            return Vec::new();
+        }
+
+        let rom = fs::read(path).map_err(|e| e.to_string());
+        return vec![candidate(None, rom)];
+    }
+
+    let archive = match ZipArchive::open(path) {
+        Ok(archive) => archive,
+        Err(e) => return vec![candidate(None, Err(e.to_string()))],
+    };
+
+    archive.entries()
+        .iter()
+        .filter(|entry| entry.size as usize == BIOS_SIZE)
+        .map(|entry| candidate(Some(entry.name.clone()), archive.read(entry).map_err(|e| e.to_string())))
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn region_preference() {
+    let dump = |name: &str, region| {
+        let candidate = BiosCandidate {
+            path: PathBuf::from(name),
+            entry: None,
+            sha256: Ok(name.to_string()),
+            known: Some(KnownBios {
+                version: "4.1".to_string(),
+                region,
+                sha256: name.to_string(),
+            }),
+        };
+        (candidate, Some(name.as_bytes().to_vec()))
+    };
+    let dumps = || vec![dump("japan.bin", Region::Japan), dump("europe.bin", Region::Europe)];
+    let picked = |result| match result {
+        Pick::Region(candidate, rom) => (true, candidate.path, rom),
+        Pick::Other(candidate, rom) => (false, candidate.path, rom),
+        Pick::None(_) => panic!("No dump picked"),
+    };
+
+    // The dump of the region, even after one of another
+    assert_eq!(picked(pick(dumps(), Some(Region::Europe))),
+               (true, PathBuf::from("europe.bin"), b"europe.bin".to_vec()));
+    // The first supported one when there's none of the region, or no disc
+    assert_eq!(picked(pick(dumps(), Some(Region::NorthAmerica))),
+               (false, PathBuf::from("japan.bin"), b"japan.bin".to_vec()));
+    assert_eq!(picked(pick(dumps(), None)),
+               (true, PathBuf::from("japan.bin"), b"japan.bin".to_vec()));
+
+    // The unsupported ones are only listed
+    let (mut unknown, rom) = dump("unknown.bin", Region::Europe);
+    unknown.known = None;
+    match pick([(unknown, rom)], Some(Region::Europe)) {
+        Pick::None(candidates) => assert_eq!(candidates.len(), 1),
+        _ => panic!("Unsupported dump picked"),
+    }
+}
@@ -0,0 +1,1587 @@
+//! High level emulation of the BIOS, to boot the games without a dump of the console's ROM: the
+//! kernel services they call through the A0, B0 and C0 vectors and the system calls are run in
+//! Rust. A real dump is always preferred, this one has no shell and no boot logo and only covers
+//! what the games use.
+//!
+//! The ROM is made up here. Its reset code jumps to the trap page at `TRAP_BASE`: `BREAK` words
+//! the CPU never runs, fetching one calls the service behind it instead (see `trap`), which then
+//! returns to the caller by setting the PC. The dispatch tables, the exception handler and the
+//! thread control blocks sit in RAM at the addresses of the real kernel, the games patch them and
+//! hook their interrupt handlers the same way. The parts calling back into the game (the
+//! interrupt handler chains, the event callbacks, the thread switches) are MIPS code assembled at
+//! boot.
+
+mod card;
+mod files;
+mod libc;
+mod pad;
+
+use std::collections::VecDeque;
+use log::{error, info, warn};
+use mips_r3000::instruction::Instruction;
+use serde::{Deserialize, Serialize};
+use crate::ps1::psx::assembler::Assembler;
+use crate::ps1::psx::bios::bios::BIOS_SIZE;
+use crate::ps1::psx::bus::Bus;
+use crate::ps1::psx::exe::{self, Exe};
+use crate::ps1::psx::memory::map;
+use crate::ps1::psx::pad_memcard::{DsrState, Peripheral};
+use crate::ps1::psx::processor::{cop0, RegisterIndex};
+
+/// Page of the ROM whose instruction fetches call the kernel services: one word per function of
+/// the A0, B0 and C0 tables, then the kernel's own routines (`Routine`)
+pub const TRAP_BASE: u32 = 0xbfc1_0000;
+const TRAP_PAGE_SIZE: u32 = 0x1000;
+/// Words of each table in the trap page
+const TRAP_TABLE_SIZE: u32 = 0x100;
+
+/// Dispatch tables in RAM, where the real kernel has them
+const A0_TABLE: u32 = 0x200;
+const B0_TABLE: u32 = 0x874;
+const C0_TABLE: u32 = 0x674;
+const A0_LEN: u32 = 0xc0;
+const B0_LEN: u32 = 0x60;
+const C0_LEN: u32 = 0x20;
+
+/// The routines of `assemble_kernel`, run from KSEG0
+const KERNEL_CODE: u32 = 0x8000_1000;
+/// Process control block: a pointer to the TCB of the current thread
+const PCB: u32 = 0x6000;
+/// Heads of the 4 chains of `SysEnqIntRP`, the highest priority first
+const IRQ_CHAINS: u32 = 0x6010;
+/// Entries `SysDeqIntRP` looks through before giving up, a chain can only be longer if the game
+/// linked an entry into itself
+const IRQ_CHAIN_MAX_LEN: u32 = 256;
+/// The kernel's own entry in those chains
+const KERNEL_IRQ_HANDLER: u32 = 0x6020;
+/// Header of the executable started by `LoadExec`
+const EXEC_HEADER: u32 = 0x6040;
+const TCBS: u32 = 0x6100;
+const TCB_COUNT: u32 = 4;
+const TCB_SIZE: u32 = 0xc0;
+/// Stack of the exception handler, below the kernel heap
+const KERNEL_STACK: u32 = 0x8000_8000;
+/// Room left for `alloc_kernel_memory`
+const KERNEL_HEAP: (u32, u32) = (0x8000_8000, 0x6000);
+/// Stack of the games, unless their header or `SetConf` says otherwise
+const STACK_TOP: u32 = 0x801f_ff00;
+
+/// TCB layout: the status, then the registers saved by the exception handler
+const TCB_STATUS: u32 = 0x00;
+const TCB_EPC: u32 = 0x88;
+const TCB_HI: u32 = 0x8c;
+const TCB_LO: u32 = 0x90;
+const TCB_SR: u32 = 0x94;
+const TCB_CAUSE: u32 = 0x98;
+const TCB_FREE: u32 = 0x1000;
+const TCB_USED: u32 = 0x4000;
+
+const EVENT_COUNT: usize = 32;
+/// Event states
+const EVENT_FREE: u32 = 0;
+const EVENT_DISABLED: u32 = 0x1000;
+const EVENT_ENABLED: u32 = 0x2000;
+const EVENT_READY: u32 = 0x4000;
+/// Event modes: call the callback on delivery, or mark the event as ready
+const EVENT_MODE_CALLBACK: u32 = 0x1000;
+const EVENT_MODE_READY: u32 = 0x2000;
+/// Class of the root counter events, the counter number in the low bits
+const RCNT_EVENT: u32 = 0xf200_0000;
+/// Spec of the events delivered by the interrupts
+const EVENT_SPEC_INTERRUPT: u32 = 0x0002;
+
+const I_STAT: u32 = map::IRQ_CONTROL.0;
+const I_MASK: u32 = map::IRQ_CONTROL.0 + 4;
+const GP0: u32 = map::GPU.0;
+const GP1: u32 = map::GPU.0 + 4;
+
+/// Exception codes of CAUSE
+const EXCEPTION_SYSCALL: u32 = 8;
+
+/// Numbers of the registers the services use
+mod r {
+    pub const V0: u8 = 2;
+    pub const A0: u8 = 4;
+    pub const A1: u8 = 5;
+    pub const T8: u8 = 24;
+    pub const K0: u8 = 26;
+    pub const S0: u8 = 16;
+    pub const GP: u8 = 28;
+    pub const SP: u8 = 29;
+    pub const FP: u8 = 30;
+    pub const RA: u8 = 31;
+}
+
+/// Kernel routines run in Rust, after the tables in the trap page
+#[derive(Clone, Copy)]
+enum Routine {
+    /// Reset: set up the kernel and start the game
+    Boot,
+    /// First look at an exception, the registers not saved yet: the system calls entering and
+    /// leaving critical sections are handled right away
+    Exception,
+    /// The other exceptions, once the registers are saved in the TCB
+    SavedException,
+    /// End of the interrupt handler chains: back to the game, or to the custom exit
+    ExitInterrupt,
+    /// Pop the next event callback to call for `call_pending`, 0 if there's none
+    NextCallback,
+    /// The kernel's handler in the interrupt chains: events of the root counters and the pads
+    InterruptHandler,
+    /// Return of an executable started by `Exec`
+    ExecReturn,
+}
+
+impl Routine {
+    const ALL: [Routine; 7] = [
+        Routine::Boot,
+        Routine::Exception,
+        Routine::SavedException,
+        Routine::ExitInterrupt,
+        Routine::NextCallback,
+        Routine::InterruptHandler,
+        Routine::ExecReturn,
+    ];
+
+    fn address(self) -> u32 {
+        trap_address(3, self as u32)
+    }
+}
+
+/// Address of the trap of `function` in `table` (0 to 2 for A0 to C0, 3 for the routines)
+fn trap_address(table: u32, function: u32) -> u32 {
+    TRAP_BASE + (table * TRAP_TABLE_SIZE + function) * 4
+}
+
+/// True if fetching an instruction from `addr` calls a kernel service
+#[inline]
+pub fn is_trap(addr: u32) -> bool {
+    addr & !(TRAP_PAGE_SIZE - 1) == TRAP_BASE
+}
+
+/// The ROM of `Bios::new_hle`
+pub fn rom() -> Vec<u8> {
+    use crate::ps1::psx::assembler::syntax::*;
+
+    let mut rom = vec![0; BIOS_SIZE];
+
+    let mut place = |base: u32, code: &[Instruction]| {
+        let mut asm = Assembler::from_base(base);
+        asm.assemble(code).unwrap();
+        let (code, _) = asm.machine_code();
+        let offset = (base - 0xbfc0_0000) as usize;
+        rom[offset..offset + code.len()].copy_from_slice(&code);
+    };
+    // Reset
+    place(0xbfc0_0000, &[Li(T0, Routine::Boot.address()), Jr(T0), Nop]);
+    // Exceptions before the kernel is set up (BEV set)
+    place(0xbfc0_0180, &[Li(K0, Routine::Exception.address()), Jr(K0), Nop]);
+
+    let trap_page = (TRAP_BASE - 0xbfc0_0000) as usize;
+    for word in rom[trap_page..trap_page + TRAP_PAGE_SIZE as usize].chunks_mut(4) {
+        word.copy_from_slice(&0x0000_000du32.to_le_bytes());
+    }
+
+    rom
+}
+
+/// Called when the CPU fetches the instruction at `addr` in the trap page: runs the service and
+/// returns the instruction to run in its place, a NOP
+pub fn trap(bus: &mut Bus, addr: u32) -> Instruction {
+    // An interrupt is taken on this instruction: the handler returns here, the call is made then
+    if bus.cpu.opcode_table_offset == 0
+        && let Some(mut hle) = bus.hle.take()
+    {
+        hle.call(bus, addr);
+        bus.hle = Some(hle);
+    }
+
+    Instruction::new(0)
+}
+
+/// Addresses of the routines of `assemble_kernel`
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+struct Routines {
+    exception_save: u32,
+    exception_return: u32,
+    call_pending: u32,
+    rfe_k0: u32,
+    syscall: u32,
+    flush_cache: u32,
+    exec_start: u32,
+    halt: u32,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Event {
+    class: u32,
+    spec: u32,
+    mode: u32,
+    status: u32,
+    callback: u32,
+}
+
+impl Event {
+    const FREE: Event = Event {
+        class: 0,
+        spec: 0,
+        mode: 0,
+        status: EVENT_FREE,
+        callback: 0,
+    };
+}
+
+/// First fit allocator of `InitHeap` and `SysInitMemory`. The blocks are kept here rather than in
+/// headers in the game's memory.
+#[derive(Default, Serialize, Deserialize)]
+struct Heap {
+    blocks: Vec<HeapBlock>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct HeapBlock {
+    addr: u32,
+    size: u32,
+    used: bool,
+}
+
+impl Heap {
+    fn new(addr: u32, size: u32) -> Heap {
+        let start = addr.wrapping_add(3) & !3;
+        let size = size.saturating_sub(start.wrapping_sub(addr)) & !3;
+
+        Heap {
+            blocks: vec![HeapBlock { addr: start, size, used: false }],
+        }
+    }
+
+    /// Address of a block of `size` bytes, 0 if there's no room
+    fn alloc(&mut self, size: u32) -> u32 {
+        let Some(size) = size.max(1).checked_add(3).map(|s| s & !3) else {
+            return 0;
+        };
+        let Some(i) = self.blocks.iter().position(|b| !b.used && b.size >= size) else {
+            return 0;
+        };
+
+        let block = self.blocks[i];
+        if block.size > size {
+            self.blocks.insert(i + 1, HeapBlock {
+                addr: block.addr + size,
+                size: block.size - size,
+                used: false,
+            });
+        }
+        self.blocks[i] = HeapBlock { addr: block.addr, size, used: true };
+
+        block.addr
+    }
+
+    fn free(&mut self, addr: u32) {
+        let Some(mut i) = self.blocks.iter().position(|b| b.used && b.addr == addr) else {
+            return;
+        };
+
+        self.blocks[i].used = false;
+        if i > 0 && !self.blocks[i - 1].used {
+            self.blocks[i - 1].size += self.blocks[i].size;
+            self.blocks.remove(i);
+            i -= 1;
+        }
+        if i + 1 < self.blocks.len() && !self.blocks[i + 1].used {
+            self.blocks[i].size += self.blocks[i + 1].size;
+            self.blocks.remove(i + 1);
+        }
+    }
+
+    fn size(&self, addr: u32) -> Option<u32> {
+        self.blocks.iter().find(|b| b.used && b.addr == addr).map(|b| b.size)
+    }
+}
+
+/// State of the emulated kernel, in `Bus::hle` when it runs instead of a BIOS dump
+#[derive(Serialize, Deserialize)]
+pub struct Hle {
+    routines: Routines,
+    events: Vec<Event>,
+    /// Callbacks of the events delivered, called by `call_pending` before returning to the game
+    callbacks: VecDeque<u32>,
+    /// Buffer of `setjmp` the interrupts end with, set by `HookEntryInt`. 0 returns to the game.
+    custom_exit: u32,
+    heap: Heap,
+    kernel_heap: Heap,
+    /// `ChangeClearRCnt`: the root counter interrupts are acknowledged after delivering their
+    /// events. Turned off by the libraries handling them themselves.
+    clear_rcnt: [bool; 4],
+    pads: pad::Pads,
+    files: files::Files,
+    rand_seed: u32,
+    /// Where `strtok` goes on
+    strtok_next: u32,
+    /// `SetConf`: events, threads and the top of the stack
+    conf: [u32; 3],
+    /// Header of the executable started by `Exec`, for its return
+    exec_header: u32,
+}
+
+impl Hle {
+    pub fn new() -> Hle {
+        Hle {
+            routines: Routines::default(),
+            events: vec![Event::FREE; EVENT_COUNT],
+            callbacks: VecDeque::new(),
+            custom_exit: 0,
+            heap: Heap::default(),
+            kernel_heap: Heap::new(KERNEL_HEAP.0, KERNEL_HEAP.1),
+            clear_rcnt: [true; 4],
+            pads: pad::Pads::new(),
+            files: files::Files::new(),
+            rand_seed: 0x24040001,
+            strtok_next: 0,
+            conf: [EVENT_COUNT as u32, TCB_COUNT, STACK_TOP],
+            exec_header: 0,
+        }
+    }
+
+    fn call(&mut self, bus: &mut Bus, addr: u32) {
+        let index = (addr - TRAP_BASE) / 4;
+        let function = index % TRAP_TABLE_SIZE;
+
+        let ret = match index / TRAP_TABLE_SIZE {
+            0 => self.a0(bus, function),
+            1 => self.b0(bus, function),
+            2 => self.c0(bus, function),
+            _ => match Routine::ALL.get(function as usize) {
+                Some(&routine) => self.routine(bus, routine),
+                None => self.unimplemented("kernel", function),
+            },
+        };
+
+        // Back to the caller, through the callbacks of the events delivered on the way
+        if let Some(v0) = ret {
+            set_reg(bus, r::V0, v0);
+            let to = if self.callbacks.is_empty() { reg(bus, r::RA) } else { self.routines.call_pending };
+            jump(bus, to);
+        }
+    }
+
+    fn unimplemented(&self, table: &str, function: u32) -> Option<u32> {
+        warn!("HLE BIOS: {}({:02X}h) isn't implemented", table, function);
+
+        Some(0)
+    }
+
+    /// The standard library, the heap, the executables and the GPU. `None` when the service
+    /// went somewhere else than back to the caller.
+    fn a0(&mut self, bus: &mut Bus, function: u32) -> Option<u32> {
+        match function {
+            0x00 => Some(self.open(bus)),
+            0x01 => Some(self.lseek(bus)),
+            0x02 => Some(self.read(bus)),
+            0x03 => Some(self.write(bus)),
+            0x04 => Some(self.close(bus)),
+            // ioctl
+            0x05 => Some(0),
+            0x06 | 0x3a => self.exit(bus),
+            // isatty
+            0x07 => Some(u32::from(arg(bus, 0) < 2)),
+            // getc and getchar: there's no input
+            0x08 | 0x3b => Some(u32::MAX),
+            0x09 => {
+                let c = arg(bus, 0);
+                libc::putchar(bus, c as u8);
+                Some(c)
+            }
+            0x0a => Some(libc::todigit(bus)),
+            0x0c | 0x0d => Some(libc::strtol(bus)),
+            0x0e | 0x0f => Some(libc::abs(bus)),
+            0x10 | 0x11 => Some(libc::atoi(bus)),
+            0x12 => Some(libc::atob(bus)),
+            0x13 => Some(libc::setjmp(bus)),
+            0x14 => {
+                let (buf, value) = (arg(bus, 0), arg(bus, 1));
+                libc::longjmp(bus, buf, value);
+                None
+            }
+            0x15 => Some(libc::strcat(bus)),
+            0x16 => Some(libc::strncat(bus)),
+            0x17 => Some(libc::strcmp(bus)),
+            0x18 => Some(libc::strncmp(bus)),
+            0x19 => Some(libc::strcpy(bus)),
+            0x1a => Some(libc::strncpy(bus)),
+            0x1b => Some(libc::strlen(bus)),
+            // index and strchr, rindex and strrchr
+            0x1c | 0x1e => Some(libc::strchr(bus, false)),
+            0x1d | 0x1f => Some(libc::strchr(bus, true)),
+            0x20 => Some(libc::strpbrk(bus)),
+            0x21 => Some(libc::strspn(bus)),
+            0x22 => Some(libc::strcspn(bus)),
+            0x23 => Some(libc::strtok(bus, &mut self.strtok_next)),
+            0x24 => Some(libc::strstr(bus)),
+            0x25 => Some(libc::toupper(bus)),
+            0x26 => Some(libc::tolower(bus)),
+            0x27 => Some(libc::bcopy(bus)),
+            0x28 => Some(libc::bzero(bus)),
+            // bcmp and memcmp
+            0x29 | 0x2d => Some(libc::memcmp(bus)),
+            // memcpy and memmove, the copy is right either way
+            0x2a | 0x2c => Some(libc::memcpy(bus)),
+            0x2b => Some(libc::memset(bus)),
+            0x2e => Some(libc::memchr(bus)),
+            0x2f => Some(libc::rand(&mut self.rand_seed)),
+            0x30 => {
+                self.rand_seed = arg(bus, 0);
+                Some(0)
+            }
+            0x33 => Some(self.heap.alloc(arg(bus, 0))),
+            0x34 => {
+                self.heap.free(arg(bus, 0));
+                Some(0)
+            }
+            0x37 => Some(self.calloc(bus)),
+            0x38 => Some(self.realloc(bus)),
+            0x39 => {
+                self.heap = Heap::new(arg(bus, 0), arg(bus, 1));
+                Some(0)
+            }
+            0x3c => {
+                let c = arg(bus, 0);
+                libc::putchar(bus, c as u8);
+                Some(c)
+            }
+            0x3e => Some(libc::puts(bus)),
+            0x3f => Some(libc::printf(bus)),
+            0x40 | 0xa1 => self.system_error(bus, function),
+            0x41 => Some(self.load_test(bus)),
+            0x42 => self.load(bus),
+            0x43 => {
+                let (header, stack_base, stack_offset) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+                self.exec(bus, header, stack_base, stack_offset)
+            }
+            0x44 => {
+                jump(bus, self.routines.flush_cache);
+                None
+            }
+            0x46 | 0x47 => Some(gpu_upload(bus)),
+            0x48 => {
+                let word = arg(bus, 0);
+                bus.store::<u32>(GP1, word);
+                Some(0)
+            }
+            0x49 => {
+                let word = arg(bus, 0);
+                bus.store::<u32>(GP0, word);
+                Some(0)
+            }
+            0x4a => {
+                let (src, words) = (arg(bus, 0), arg(bus, 1));
+                for i in 0..words {
+                    let word = bus.load::<u32>(src.wrapping_add(i.wrapping_mul(4)));
+                    bus.store::<u32>(GP0, word);
+                }
+                Some(0)
+            }
+            0x4d => Some(bus.load::<u32>(GP1)),
+            // gpu_sync: the transfers are done already
+            0x4e => Some(0),
+            0x51 => self.load_exec(bus),
+            0x52 => Some(self.conf[2]),
+            // The CD-ROM and memory card initializations, the devices are always ready
+            0x54..=0x56 | 0x70..=0x72 | 0x96..=0x99 => Some(0),
+            0x9c => {
+                self.conf = [arg(bus, 0), arg(bus, 1), arg(bus, 2)];
+                Some(0)
+            }
+            0x9d => {
+                for (i, value) in self.conf.into_iter().enumerate() {
+                    let ptr = arg(bus, i);
+                    if ptr != 0 {
+                        bus.store::<u32>(ptr, value);
+                    }
+                }
+                Some(0)
+            }
+            // SetCdromIrqAutoAbort, SetMem
+            0x9e | 0x9f => Some(0),
+            0xa0 => {
+                info!("HLE BIOS: warm boot");
+                self.boot(bus)
+            }
+            // EnqueueCdIntr, DequeueCdIntr
+            0xa2 | 0xa3 => Some(0),
+            0xab | 0xac => Some(self.card_info(bus)),
+            // set_card_auto_format
+            0xad => Some(0),
+            0xb4 => Some(match arg(bus, 0) {
+                // Kernel date
+                0 => 0x1995_1204,
+                // RAM size in KiB
+                5 => 2048,
+                _ => 0,
+            }),
+            _ => self.unimplemented("A", function),
+        }
+    }
+
+    /// The events, threads, root counters, pads, files and memory cards
+    fn b0(&mut self, bus: &mut Bus, function: u32) -> Option<u32> {
+        match function {
+            0x00 => Some(self.kernel_heap.alloc(arg(bus, 0))),
+            0x01 => {
+                self.kernel_heap.free(arg(bus, 0));
+                Some(0)
+            }
+            0x02 => Some(set_rcnt(bus)),
+            0x03 => Some(get_rcnt(bus)),
+            0x04 | 0x05 => {
+                let bit = rcnt_irq(arg(bus, 0));
+                let mask = bus.load::<u32>(I_MASK);
+                let mask = if function == 0x04 { mask | bit } else { mask & !bit };
+                bus.store::<u32>(I_MASK, mask);
+                Some(1)
+            }
+            0x06 => Some(reset_rcnt(bus)),
+            0x07 => {
+                self.deliver(arg(bus, 0), arg(bus, 1));
+                Some(0)
+            }
+            0x08 => Some(self.open_event(bus)),
+            0x09 => Some(self.set_event_status(arg(bus, 0), EVENT_FREE)),
+            0x0a => self.wait_event(bus),
+            0x0b => Some(self.test_event(arg(bus, 0))),
+            0x0c => Some(self.set_event_status(arg(bus, 0), EVENT_ENABLED)),
+            0x0d => Some(self.set_event_status(arg(bus, 0), EVENT_DISABLED)),
+            0x0e => Some(self.open_thread(bus)),
+            0x0f => Some(self.close_thread(bus)),
+            0x10 => self.change_thread(bus),
+            0x12 => Some(self.pads.init(bus)),
+            0x13 => Some(self.pads.start(bus)),
+            0x14 => Some(self.pads.stop()),
+            0x15 => Some(self.pads.legacy_init(bus)),
+            0x16 => Some(self.pads.legacy_buttons()),
+            0x17 => {
+                jump(bus, self.routines.exception_return);
+                None
+            }
+            0x18 => {
+                self.custom_exit = 0;
+                Some(0)
+            }
+            0x19 => {
+                self.custom_exit = arg(bus, 0);
+                Some(0)
+            }
+            0x20 => {
+                self.undeliver(arg(bus, 0), arg(bus, 1));
+                Some(0)
+            }
+            0x32 => Some(self.open(bus)),
+            0x33 => Some(self.lseek(bus)),
+            0x34 => Some(self.read(bus)),
+            0x35 => Some(self.write(bus)),
+            0x36 => Some(self.close(bus)),
+            // ioctl
+            0x37 => Some(0),
+            0x38 => self.exit(bus),
+            0x39 => Some(u32::from(arg(bus, 0) < 2)),
+            // getc, getchar and gets: there's no input
+            0x3a | 0x3c => Some(u32::MAX),
+            0x3e => Some(0),
+            0x3b | 0x3d => {
+                let c = arg(bus, 0);
+                libc::putchar(bus, c as u8);
+                Some(c)
+            }
+            0x3f => Some(libc::puts(bus)),
+            // cd: the paths are always absolute
+            0x40 => Some(1),
+            0x41 => Some(self.format(bus)),
+            0x42 => Some(self.first_file(bus)),
+            0x43 => Some(self.next_file(bus)),
+            0x44 => Some(self.rename(bus)),
+            0x45 => Some(self.erase(bus)),
+            // undelete
+            0x46 => Some(0),
+            // AddDrv, DelDrv, PrintInstalledDevices, InitCARD, StartCARD, StopCARD
+            0x47..=0x4c => Some(0),
+            0x4d => Some(self.card_info(bus)),
+            0x4e => Some(self.card_transfer(bus, true)),
+            0x4f => Some(self.card_transfer(bus, false)),
+            // allow_new_card
+            0x50 => Some(0),
+            // Krom2RawAdd: the ROM has no font
+            0x51 => Some(u32::MAX),
+            0x53 => Some(0),
+            0x54 | 0x55 => Some(self.files.errno),
+            0x56 => Some(C0_TABLE),
+            0x57 => Some(B0_TABLE),
+            // get_bu_callback_port, testdevice
+            0x58 | 0x59 => Some(0),
+            0x5b => {
+                self.pads.clear_irq = arg(bus, 0) != 0;
+                Some(0)
+            }
+            // _card_status, _card_wait: the transfers are done already
+            0x5c | 0x5d => Some(1),
+            _ => self.unimplemented("B", function),
+        }
+    }
+
+    /// The interrupt handler chains and the kernel's setup
+    fn c0(&mut self, bus: &mut Bus, function: u32) -> Option<u32> {
+        match function {
+            // EnqueueTimerAndVblankIrqs, EnqueueSyscallHandler: done at boot
+            0x00 | 0x01 => Some(0),
+            0x02 => {
+                let (priority, entry) = (arg(bus, 0) & 3, arg(bus, 1));
+                let head = IRQ_CHAINS + priority * 4;
+                let next = bus.load::<u32>(head);
+                bus.store::<u32>(entry, next);
+                bus.store::<u32>(head, entry);
+                Some(0)
+            }
+            0x03 => {
+                let (priority, entry) = (arg(bus, 0) & 3, arg(bus, 1));
+                // The links are the first word of the entries, the head works as one
+                let mut prev = IRQ_CHAINS + priority * 4;
+                for _ in 0..IRQ_CHAIN_MAX_LEN {
+                    let cur = bus.load::<u32>(prev);
+                    if cur == 0 {
+                        return Some(0);
+                    }
+                    if cur == entry {
+                        let next = bus.load::<u32>(cur);
+                        bus.store::<u32>(prev, next);
+                        return Some(0);
+                    }
+                    prev = cur;
+                }
+                warn!("HLE BIOS: interrupt chain {} loops, can't dequeue 0x{:08x}", priority, entry);
+                Some(0)
+            }
+            // InstallExceptionHandlers
+            0x07 => Some(0),
+            0x08 => {
+                self.kernel_heap = Heap::new(arg(bus, 0), arg(bus, 1));
+                Some(0)
+            }
+            // SysInitKernelVariables
+            0x09 => Some(0),
+            0x0a => {
+                let (counter, clear) = (arg(bus, 0) & 3, arg(bus, 1) != 0);
+                let previous = std::mem::replace(&mut self.clear_rcnt[counter as usize], clear);
+                Some(u32::from(previous))
+            }
+            // InitDefInt, SetIrqAutoAck, InstallDevices, FlushStdInOutPut, AdjustA0Table
+            0x0c | 0x0d | 0x12 | 0x13 | 0x1c => Some(0),
+            _ => self.unimplemented("C", function),
+        }
+    }
+
+    fn routine(&mut self, bus: &mut Bus, routine: Routine) -> Option<u32> {
+        match routine {
+            Routine::Boot => self.boot(bus),
+            Routine::Exception => {
+                self.exception(bus);
+                None
+            }
+            Routine::SavedException => {
+                self.saved_exception(bus);
+                let ra = reg(bus, r::RA);
+                jump(bus, ra);
+                None
+            }
+            Routine::ExitInterrupt => {
+                if self.custom_exit != 0 {
+                    libc::longjmp(bus, self.custom_exit, 1);
+                } else {
+                    jump(bus, self.routines.exception_return);
+                }
+                None
+            }
+            Routine::NextCallback => Some(self.callbacks.pop_front().unwrap_or(0)),
+            Routine::InterruptHandler => {
+                self.handle_interrupts(bus);
+                Some(0)
+            }
+            Routine::ExecReturn => {
+                let header = self.exec_header;
+                for (i, r) in [r::SP, r::FP, r::GP, r::RA, r::S0].into_iter().enumerate() {
+                    let v = bus.load::<u32>(header.wrapping_add(0x28 + i as u32 * 4));
+                    set_reg(bus, r, v);
+                }
+                Some(1)
+            }
+        }
+    }
+
+    /// Set up the kernel in RAM and start the executable of the disc, or the one sideloaded
+    fn boot(&mut self, bus: &mut Bus) -> Option<u32> {
+        info!("HLE BIOS: initializing the kernel");
+
+        *self = Hle::new();
+        cop0::mtc0(bus, RegisterIndex(12), 0);
+        self.install_kernel(bus);
+        // The instruction cache on, like the BIOS leaves it
+        bus.store::<u32>(map::CACHE_CONTROL.0, 0x0001_e988);
+        bus.store::<u32>(I_MASK, 0);
+        bus.store::<u32>(I_STAT, 0);
+
+        set_reg(bus, r::SP, STACK_TOP);
+        set_reg(bus, r::FP, STACK_TOP);
+        // There's no shell to return to
+        set_reg(bus, r::RA, self.routines.halt);
+
+        let disc_exe = if bus.exe.is_some() {
+            None
+        } else {
+            let exe = match bus.cd.disc_mut() {
+                Some(disc) => disc.boot_executable().and_then(|exe| Exe::from_bytes(&exe)),
+                None => {
+                    error!("HLE BIOS: nothing to boot, the emulated BIOS has no shell");
+                    jump(bus, self.routines.halt);
+                    return None;
+                }
+            };
+            match exe {
+                Ok(exe) => Some(exe),
+                Err(e) => {
+                    error!("HLE BIOS: can't boot the disc: {}", e);
+                    jump(bus, self.routines.halt);
+                    return None;
+                }
+            }
+        };
+
+        let from_disc = disc_exe.is_some();
+        if from_disc {
+            bus.exe = disc_exe;
+        }
+        exe::sideload(bus);
+        if from_disc {
+            bus.exe = None;
+        }
+
+        // Interrupts on, none of them is unmasked yet
+        cop0::mtc0(bus, RegisterIndex(12), 0x401);
+
+        None
+    }
+
+    fn install_kernel(&mut self, bus: &mut Bus) {
+        let (code, routines) = assemble_kernel();
+        self.routines = routines;
+        bus.xmem.ram_store_block(KERNEL_CODE, &code, code.len());
+
+        // The vectors, in the slots of the real ones
+        let vector = |base: u32, target: u32| {
+            use crate::ps1::psx::assembler::syntax::*;
+
+            let mut asm = Assembler::from_base(base);
+            let scratch = if base == 0x8000_0080 { K0 } else { T0 };
+            asm.assemble(&[Li(scratch, target), Jr(scratch), Nop]).unwrap();
+            asm.machine_code().0
+        };
+        let vectors = [
+            (0x8000_0080, Routine::Exception.address()),
+            (0x8000_00a0, routines.a0_dispatch()),
+            (0x8000_00b0, routines.b0_dispatch()),
+            (0x8000_00c0, routines.c0_dispatch()),
+        ];
+        for (base, target) in vectors {
+            let code = vector(base, target);
+            bus.xmem.ram_store_block(base, &code, code.len());
+        }
+
+        for (table, base, len) in [(0, A0_TABLE, A0_LEN), (1, B0_TABLE, B0_LEN), (2, C0_TABLE, C0_LEN)] {
+            for function in 0..len {
+                let target = match (table, function) {
+                    (0, 0x44) => routines.flush_cache,
+                    (1, 0x17) => routines.exception_return,
+                    _ => trap_address(table, function),
+                };
+                bus.xmem.ram_store(base + function * 4, target);
+            }
+        }
+
+        // Where the kernel's structures are, for the games looking
+        bus.xmem.ram_store(0x108, PCB);
+        bus.xmem.ram_store(0x10c, 4u32);
+        bus.xmem.ram_store(0x110, TCBS);
+        bus.xmem.ram_store(0x114, TCB_COUNT * TCB_SIZE);
+
+        for t in 0..TCB_COUNT {
+            let status = if t == 0 { TCB_USED } else { TCB_FREE };
+            bus.xmem.ram_store(TCBS + t * TCB_SIZE + TCB_STATUS, status);
+        }
+        bus.xmem.ram_store(PCB, TCBS);
+
+        for priority in 0..4 {
+            bus.xmem.ram_store(IRQ_CHAINS + priority * 4, 0u32);
+        }
+        // The handler of the root counter events and the pads: {next, func2, func1, 0}
+        for (offset, word) in [0, 0, Routine::InterruptHandler.address(), 0].into_iter().enumerate() {
+            bus.xmem.ram_store(KERNEL_IRQ_HANDLER + offset as u32 * 4, word);
+        }
+        bus.xmem.ram_store(IRQ_CHAINS + 4, KERNEL_IRQ_HANDLER);
+    }
+
+    /// First look at an exception, the registers of the game are all still there but K0
+    fn exception(&mut self, bus: &mut Bus) {
+        let cause = cop0::mfc0(bus, RegisterIndex(13));
+        let function = reg(bus, r::A0);
+
+        if (cause >> 2) & 0x1f != EXCEPTION_SYSCALL || function > 2 {
+            jump(bus, self.routines.exception_save);
+            return;
+        }
+
+        // Enter and exit critical sections: the interrupt enable bits restored on return
+        let mut sr = cop0::mfc0(bus, RegisterIndex(12));
+        match function {
+            1 => {
+                set_reg(bus, r::V0, u32::from(sr & 0x404 == 0x404));
+                sr &= !0x404;
+            }
+            2 => sr |= 0x404,
+            _ => (),
+        }
+        cop0::mtc0(bus, RegisterIndex(12), sr);
+
+        let epc = cop0::mfc0(bus, RegisterIndex(14));
+        set_reg(bus, r::K0, epc.wrapping_add(4));
+        jump(bus, self.routines.rfe_k0);
+    }
+
+    /// The exceptions other than the interrupts, once the registers are saved in the TCB of the
+    /// current thread
+    fn saved_exception(&mut self, bus: &mut Bus) {
+        let tcb = bus.load::<u32>(PCB);
+        let cause = bus.load::<u32>(tcb + TCB_CAUSE);
+        let epc = bus.load::<u32>(tcb + TCB_EPC);
+        let code = (cause >> 2) & 0x1f;
+
+        if code == EXCEPTION_SYSCALL {
+            match bus.load::<u32>(tcb + tcb_reg(r::A0)) {
+                // ChangeThreadSubFunction: the current thread returns 1 once it's resumed
+                3 => {
+                    let next = bus.load::<u32>(tcb + tcb_reg(r::A1));
+                    bus.store::<u32>(tcb + tcb_reg(r::V0), 1);
+                    bus.store::<u32>(PCB, next);
+                }
+                function => warn!("HLE BIOS: unknown system call {}", function),
+            }
+        } else {
+            warn!("HLE BIOS: unhandled exception {} at 0x{:08x}, skipping the instruction", code, epc);
+        }
+
+        bus.store::<u32>(tcb + TCB_EPC, epc.wrapping_add(4));
+    }
+
+    /// The kernel's entry in the interrupt chains: delivers the root counter events and reads
+    /// the pads
+    fn handle_interrupts(&mut self, bus: &mut Bus) {
+        let active = bus.load::<u32>(I_STAT) & bus.load::<u32>(I_MASK);
+        let mut ack = 0;
+
+        if active & 1 != 0 {
+            self.pads.poll(bus);
+            if self.pads.clear_irq {
+                ack |= 1;
+            }
+        }
+
+        for counter in 0..4 {
+            let bit = rcnt_irq(counter);
+            if active & bit != 0 {
+                self.deliver(RCNT_EVENT | counter, EVENT_SPEC_INTERRUPT);
+                if self.clear_rcnt[counter as usize] {
+                    ack |= bit;
+                }
+            }
+        }
+
+        if ack != 0 {
+            bus.store::<u32>(I_STAT, !ack);
+        }
+    }
+
+    fn open_event(&mut self, bus: &mut Bus) -> u32 {
+        let Some(i) = self.events.iter().position(|e| e.status == EVENT_FREE) else {
+            warn!("HLE BIOS: no more events");
+            return u32::MAX;
+        };
+
+        self.events[i] = Event {
+            class: arg(bus, 0),
+            spec: arg(bus, 1),
+            mode: arg(bus, 2),
+            status: EVENT_DISABLED,
+            callback: arg(bus, 3),
+        };
+
+        0xf100_0000 | i as u32
+    }
+
+    fn event_mut(&mut self, handle: u32) -> Option<&mut Event> {
+        self.events.get_mut((handle & 0xffff) as usize).filter(|e| e.status != EVENT_FREE)
+    }
+
+    /// `CloseEvent`, `EnableEvent` and `DisableEvent`
+    fn set_event_status(&mut self, handle: u32, status: u32) -> u32 {
+        match self.event_mut(handle) {
+            Some(event) => {
+                event.status = status;
+                1
+            }
+            None => 0,
+        }
+    }
+
+    fn test_event(&mut self, handle: u32) -> u32 {
+        match self.event_mut(handle) {
+            Some(event) if event.status == EVENT_READY => {
+                event.status = EVENT_ENABLED;
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    /// Blocks until the event is delivered: the call is made again until then, the interrupts
+    /// are taken in between
+    fn wait_event(&mut self, bus: &mut Bus) -> Option<u32> {
+        let handle = arg(bus, 0);
+        match self.event_mut(handle).map(|e| e.status) {
+            Some(EVENT_ENABLED) => {
+                let pc = bus.cpu.current_pc();
+                jump(bus, pc);
+                None
+            }
+            _ => Some(self.test_event(handle)),
+        }
+    }
+
+    pub(super) fn deliver(&mut self, class: u32, spec: u32) {
+        for event in &mut self.events {
+            if event.status != EVENT_ENABLED || event.class != class || event.spec != spec {
+                continue;
+            }
+
+            match event.mode {
+                EVENT_MODE_CALLBACK if event.callback != 0 => self.callbacks.push_back(event.callback),
+                EVENT_MODE_READY => event.status = EVENT_READY,
+                _ => (),
+            }
+        }
+    }
+
+    fn undeliver(&mut self, class: u32, spec: u32) {
+        for event in &mut self.events {
+            if event.status == EVENT_READY && event.mode == EVENT_MODE_READY && event.class == class
+                && event.spec == spec
+            {
+                event.status = EVENT_ENABLED;
+            }
+        }
+    }
+
+    fn open_thread(&mut self, bus: &mut Bus) -> u32 {
+        let (pc, sp, gp) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+
+        let Some(t) = (1..TCB_COUNT).find(|&t| bus.load::<u32>(TCBS + t * TCB_SIZE + TCB_STATUS) == TCB_FREE) else {
+            warn!("HLE BIOS: no more threads");
+            return u32::MAX;
+        };
+
+        let tcb = TCBS + t * TCB_SIZE;
+        for offset in (0..TCB_SIZE).step_by(4) {
+            bus.store::<u32>(tcb + offset, 0);
+        }
+        bus.store::<u32>(tcb + TCB_STATUS, TCB_USED);
+        bus.store::<u32>(tcb + TCB_EPC, pc);
+        bus.store::<u32>(tcb + tcb_reg(r::SP), sp);
+        bus.store::<u32>(tcb + tcb_reg(r::FP), sp);
+        bus.store::<u32>(tcb + tcb_reg(r::GP), gp);
+        // Interrupts on once started
+        bus.store::<u32>(tcb + TCB_SR, 0x404);
+
+        0xff00_0000 | t
+    }
+
+    fn close_thread(&mut self, bus: &mut Bus) -> u32 {
+        let t = arg(bus, 0) & 0xffff;
+        if t == 0 || t >= TCB_COUNT {
+            return 0;
+        }
+
+        bus.store::<u32>(TCBS + t * TCB_SIZE + TCB_STATUS, TCB_FREE);
+
+        1
+    }
+
+    /// The switch is a system call, so that the exception handler saves the registers of the
+    /// current thread
+    fn change_thread(&mut self, bus: &mut Bus) -> Option<u32> {
+        let t = arg(bus, 0) & 0xffff;
+        let tcb = TCBS + t * TCB_SIZE;
+        if t >= TCB_COUNT || bus.load::<u32>(tcb + TCB_STATUS) != TCB_USED {
+            return Some(0);
+        }
+
+        set_reg(bus, r::A0, 3);
+        set_reg(bus, r::A1, tcb);
+        jump(bus, self.routines.syscall);
+
+        None
+    }
+
+    fn calloc(&mut self, bus: &mut Bus) -> u32 {
+        let size = arg(bus, 0).saturating_mul(arg(bus, 1));
+        let addr = self.heap.alloc(size);
+        if addr != 0 {
+            for i in 0..size {
+                bus.store::<u8>(addr + i, 0);
+            }
+        }
+
+        addr
+    }
+
+    fn realloc(&mut self, bus: &mut Bus) -> u32 {
+        let (old, size) = (arg(bus, 0), arg(bus, 1));
+        if old == 0 {
+            return self.heap.alloc(size);
+        }
+        if size == 0 {
+            self.heap.free(old);
+            return 0;
+        }
+
+        let new = self.heap.alloc(size);
+        if new != 0 {
+            let len = self.heap.size(old).unwrap_or(0).min(size);
+            let data = read_bytes(bus, old, len);
+            write_bytes(bus, new, &data);
+            self.heap.free(old);
+        }
+
+        new
+    }
+
+    fn exit(&mut self, bus: &mut Bus) -> Option<u32> {
+        info!("HLE BIOS: the program exited with {}", arg(bus, 0) as i32);
+        jump(bus, self.routines.halt);
+
+        None
+    }
+
+    fn system_error(&mut self, bus: &mut Bus, function: u32) -> Option<u32> {
+        error!("HLE BIOS: system error A({:02X}h), stopping", function);
+        jump(bus, self.routines.halt);
+
+        None
+    }
+
+    /// Read the executable named by the first argument, `None` if it's not one
+    fn read_exe(&mut self, bus: &mut Bus) -> Option<Vec<u8>> {
+        let path = arg(bus, 0);
+        let path = read_string(bus, path);
+        let data = self.cdrom_file(bus, &path)?;
+
+        if data.len() < 0x800 || &data[..exe::MAGIC.len()] != exe::MAGIC {
+            warn!("HLE BIOS: {} isn't an executable", String::from_utf8_lossy(&path));
+            return None;
+        }
+
+        Some(data)
+    }
+
+    /// `LoadTest(path, header)`: only the header, returns the entry point
+    fn load_test(&mut self, bus: &mut Bus) -> u32 {
+        let header = arg(bus, 1);
+        match self.read_exe(bus) {
+            Some(data) => {
+                write_bytes(bus, header, &data[0x10..0x4c]);
+                read_u32(&data, 0x10)
+            }
+            None => 0,
+        }
+    }
+
+    /// Copy the header and the code of the executable `data`, the instruction cache is flushed by
+    /// the caller
+    fn load_exe_data(bus: &mut Bus, data: &[u8], header: u32) {
+        write_bytes(bus, header, &data[0x10..0x4c]);
+
+        let (base, len) = (read_u32(data, 0x18), read_u32(data, 0x1c) as usize);
+        let text = &data[0x800..(0x800 + len).min(data.len())];
+        write_bytes(bus, base, text);
+    }
+
+    /// `Load(path, header)`, returns 1 through `flush_cache`
+    fn load(&mut self, bus: &mut Bus) -> Option<u32> {
+        let header = arg(bus, 1);
+        let data = self.read_exe(bus)?;
+
+        Hle::load_exe_data(bus, &data, header);
+        set_reg(bus, r::V0, 1);
+        jump(bus, self.routines.flush_cache);
+
+        None
+    }
+
+    /// `Exec(header, stack_base, stack_offset)`: start the executable loaded, which returns to
+    /// the caller
+    fn exec(&mut self, bus: &mut Bus, header: u32, stack_base: u32, stack_offset: u32) -> Option<u32> {
+        // The header comes from the game, the addresses wrap around like on the console
+        let field = |offset: u32| header.wrapping_add(offset);
+
+        let (bss, bss_len) = (bus.load::<u32>(field(0x18)), bus.load::<u32>(field(0x1c)));
+        for i in 0..bss_len {
+            bus.store::<u8>(bss.wrapping_add(i), 0);
+        }
+
+        for (i, r) in [r::SP, r::FP, r::GP, r::RA, r::S0].into_iter().enumerate() {
+            let v = reg(bus, r);
+            bus.store::<u32>(field(0x28 + i as u32 * 4), v);
+        }
+        if stack_base != 0 {
+            set_reg(bus, r::SP, stack_base.wrapping_add(stack_offset));
+            set_reg(bus, r::FP, stack_base.wrapping_add(stack_offset));
+        }
+        let gp = bus.load::<u32>(field(4));
+        set_reg(bus, r::GP, gp);
+        self.exec_header = header;
+
+        let entry = bus.load::<u32>(header);
+        info!("HLE BIOS: starting the executable at 0x{:08x}", entry);
+        set_reg(bus, r::T8, entry);
+        jump(bus, self.routines.exec_start);
+
+        None
+    }
+
+    /// `LoadExec(path, stack_base, stack_offset)`
+    fn load_exec(&mut self, bus: &mut Bus) -> Option<u32> {
+        let (stack_base, stack_offset) = (arg(bus, 1), arg(bus, 2));
+        let Some(data) = self.read_exe(bus) else {
+            return Some(0);
+        };
+
+        Hle::load_exe_data(bus, &data, EXEC_HEADER);
+        self.exec(bus, EXEC_HEADER, stack_base, stack_offset)
+    }
+}
+
+impl Default for Hle {
+    fn default() -> Hle {
+        Hle::new()
+    }
+}
+
+impl Routines {
+    fn a0_dispatch(&self) -> u32 {
+        self.syscall + 0x10
+    }
+
+    fn b0_dispatch(&self) -> u32 {
+        self.a0_dispatch() + 0x1c
+    }
+
+    fn c0_dispatch(&self) -> u32 {
+        self.b0_dispatch() + 0x1c
+    }
+}
+
+/// Offset of the register `r` in a TCB
+fn tcb_reg(r: u8) -> u32 {
+    0x08 + u32::from(r) * 4
+}
+
+/// The MIPS side of the kernel, see `Routines`
+fn assemble_kernel() -> (Vec<u8>, Routines) {
+    use crate::ps1::psx::assembler::syntax::*;
+
+    let saved = || (1..32u8).filter(|&r| r != r::K0 && r != r::K0 + 1);
+
+    let mut code = vec![
+        // ChangeThread's system call
+        Global("syscall"),
+        Syscall(0),
+        Jr(RA),
+        Nop,
+        Nop,
+    ];
+
+    // The vectors' dispatchers, T1 is the function number. 7 instructions each, see
+    // `Routines::a0_dispatch`.
+    for (label, table) in [("a0_dispatch", A0_TABLE), ("b0_dispatch", B0_TABLE), ("c0_dispatch", C0_TABLE)] {
+        code.extend([
+            Global(label),
+            Li(T0, table),
+            Sll(T1, T1, 2),
+            Addu(T1, T1, T0),
+            Lw(T1, T1, 0),
+            Nop,
+            Jr(T1),
+            Nop,
+        ]);
+    }
+
+    code.extend([
+        // Return from the system calls handled without saving the registers, K0 is the address
+        // after the SYSCALL
+        Global("rfe_k0"),
+        Jr(K0),
+        Rfe,
+
+        Global("halt"),
+        Local("halt"),
+        B(Label::Local("halt", 'b')),
+        Nop,
+
+        // Call the callbacks of the events delivered, V0 is kept for the caller
+        Global("call_pending"),
+        Addiu(SP, SP, -8),
+        Sw(RA, SP, 4),
+        Sw(V0, SP, 0),
+        Local("next"),
+        Li(T0, Routine::NextCallback.address()),
+        Jalr(RA, T0),
+        Nop,
+        Beqz(V0, Label::Local("done", 'f')),
+        Nop,
+        Jalr(RA, V0),
+        Nop,
+        B(Label::Local("next", 'b')),
+        Nop,
+        Local("done"),
+        Lw(RA, SP, 4),
+        Lw(V0, SP, 0),
+        Jr(RA),
+        Addiu(SP, SP, 8),
+
+        // Start an executable at T8, returning to `ExecReturn`
+        Global("exec_start"),
+        Jal(Label::Global("flush_cache")),
+        Nop,
+        La(RA, Label::Global("exec_return")),
+        Jr(T8),
+        Nop,
+        Global("exec_return"),
+        Li(T0, Routine::ExecReturn.address()),
+        Jr(T0),
+        Nop,
+
+        // FlushCache: invalidate the instruction cache from uncached memory, interrupts off,
+        // like the BIOS. Uses T0 to T5 only.
+        Global("flush_cache"),
+        La(T0, Label::Local("uncached", 'f')),
+        Lui(T1, 0xa000),
+        Or(T0, T0, T1),
+        Jr(T0),
+        Nop,
+        Local("uncached"),
+        Mfc0(T3, 12),
+        Li(T0, map::CACHE_CONTROL.0),
+        Lw(T5, T0, 0),
+        // Tag test mode, the cache isolated
+        Li(T1, 0x804),
+        Sw(T1, T0, 0),
+        Li(T1, 0x0001_0000),
+        Mtc0(T1, 12),
+        Li(T1, 0x1000),
+        Li(T2, 0),
+        Local("invalidate"),
+        Addiu(T2, T2, 16),
+        Bne(T2, T1, Label::Local("invalidate", 'b')),
+        Sw(R0, T2, -16),
+        Mtc0(R0, 12),
+        Sw(T5, T0, 0),
+        Mtc0(T3, 12),
+        Jr(RA),
+        Nop,
+
+        // The exceptions other than the quick system calls: save the registers in the TCB of the
+        // current thread and switch to the kernel stack
+        Global("exception_save"),
+        Li(K0, PCB),
+        Lw(K0, K0, 0),
+        Nop,
+    ]);
+    code.extend(saved().map(|r| Sw(Register(r), K0, tcb_reg(r) as i16)));
+    code.extend([
+        Mfhi(K1),
+        Sw(K1, K0, TCB_HI as i16),
+        Mflo(K1),
+        Sw(K1, K0, TCB_LO as i16),
+        Mfc0(K1, 14),
+        Sw(K1, K0, TCB_EPC as i16),
+        Mfc0(K1, 12),
+        Sw(K1, K0, TCB_SR as i16),
+        Mfc0(K1, 13),
+        Sw(K1, K0, TCB_CAUSE as i16),
+        Li(SP, KERNEL_STACK),
+        Andi(K1, K1, 0x7c),
+        Bnez(K1, Label::Local("not_interrupt", 'f')),
+        Nop,
+
+        // Interrupts: run the chains of `SysEnqIntRP`, the entries are {next, func2, func1, 0}.
+        // func2 is called with what func1 returns, if it's not 0.
+        Li(S0, IRQ_CHAINS),
+        Addiu(S1, S0, 16),
+        Local("chain"),
+        Lw(S2, S0, 0),
+        Nop,
+        Local("entry"),
+        Beqz(S2, Label::Local("next_chain", 'f')),
+        Nop,
+        Lw(T0, S2, 8),
+        Nop,
+        Beqz(T0, Label::Local("skip", 'f')),
+        Nop,
+        Jalr(RA, T0),
+        Nop,
+        Beqz(V0, Label::Local("skip", 'f')),
+        Nop,
+        Lw(T0, S2, 4),
+        Nop,
+        Beqz(T0, Label::Local("skip", 'f')),
+        Nop,
+        Jalr(RA, T0),
+        Move(A0, V0),
+        Local("skip"),
+        Lw(S2, S2, 0),
+        B(Label::Local("entry", 'b')),
+        Nop,
+        Local("next_chain"),
+        Addiu(S0, S0, 4),
+        Bne(S0, S1, Label::Local("chain", 'b')),
+        Nop,
+        Li(T0, Routine::ExitInterrupt.address()),
+        Jr(T0),
+        Nop,
+
+        Local("not_interrupt"),
+        Li(T0, Routine::SavedException.address()),
+        Jalr(RA, T0),
+        Nop,
+
+        // ReturnFromException: restore the registers of the current thread
+        Global("exception_return"),
+        Li(K0, PCB),
+        Lw(K0, K0, 0),
+        Nop,
+        Lw(K1, K0, TCB_HI as i16),
+        Nop,
+        Mthi(K1),
+        Lw(K1, K0, TCB_LO as i16),
+        Nop,
+        Mtlo(K1),
+        Lw(K1, K0, TCB_SR as i16),
+        Nop,
+        Mtc0(K1, 12),
+    ]);
+    code.extend(saved().map(|r| Lw(Register(r), K0, tcb_reg(r) as i16)));
+    code.extend([
+        Lw(K0, K0, TCB_EPC as i16),
+        Nop,
+        Jr(K0),
+        Rfe,
+    ]);
+
+    let mut asm = Assembler::from_base(KERNEL_CODE);
+    asm.assemble(&code).unwrap();
+
+    let global = |name| asm.global(name).unwrap();
+    let routines = Routines {
+        exception_save: global("exception_save"),
+        exception_return: global("exception_return"),
+        call_pending: global("call_pending"),
+        rfe_k0: global("rfe_k0"),
+        syscall: global("syscall"),
+        flush_cache: global("flush_cache"),
+        exec_start: global("exec_start"),
+        halt: global("halt"),
+    };
+    debug_assert_eq!(routines.c0_dispatch(), global("c0_dispatch"));
+
+    (asm.machine_code().0, routines)
+}
+
+/// Interrupt of the root counter `counter`, 3 being the VBlank
+fn rcnt_irq(counter: u32) -> u32 {
+    match counter & 3 {
+        3 => 1,
+        n => 0x10 << n,
+    }
+}
+
+/// `SetRCnt(counter, target, mode)`
+fn set_rcnt(bus: &mut Bus) -> u32 {
+    let (counter, target, mode) = (arg(bus, 0) & 3, arg(bus, 1), arg(bus, 2));
+    if counter == 3 {
+        return 1;
+    }
+
+    let mut hw_mode = 0;
+    // Interrupt at the target, repeated
+    if mode & 0x1000 != 0 {
+        hw_mode |= 0x50;
+    }
+    // Reset at the target
+    if mode & 0x0100 != 0 {
+        hw_mode |= 0x08;
+    }
+    if mode & 0x0010 != 0 {
+        hw_mode |= 0x01;
+    }
+    // The other clock source
+    if mode & 0x0001 != 0 {
+        hw_mode |= if counter == 2 { 0x200 } else { 0x100 };
+    }
+
+    let base = map::TIMERS.0 + counter * 0x10;
+    bus.store::<u32>(base + 8, target & 0xffff);
+    bus.store::<u32>(base + 4, hw_mode);
+
+    1
+}
+
+fn get_rcnt(bus: &mut Bus) -> u32 {
+    match arg(bus, 0) & 3 {
+        3 => 0,
+        counter => bus.load::<u32>(map::TIMERS.0 + counter * 0x10) & 0xffff,
+    }
+}
+
+fn reset_rcnt(bus: &mut Bus) -> u32 {
+    let counter = arg(bus, 0) & 3;
+    if counter != 3 {
+        let base = map::TIMERS.0 + counter * 0x10;
+        bus.store::<u32>(base + 4, 0);
+        bus.store::<u32>(base + 8, 0);
+        bus.store::<u32>(base, 0);
+    }
+
+    1
+}
+
+/// `GPU_dw(x, y, width, height, src)` and `gpu_send_dma`: copy a picture to the VRAM
+fn gpu_upload(bus: &mut Bus) -> u32 {
+    let (x, y, src) = (arg(bus, 0), arg(bus, 1), arg(bus, 4));
+    // Only the low halves make it to the GPU, the product of the full words could overflow
+    let (width, height) = (arg(bus, 2) & 0xffff, arg(bus, 3) & 0xffff);
+
+    bus.store::<u32>(GP0, 0xa000_0000);
+    bus.store::<u32>(GP0, (y << 16) | (x & 0xffff));
+    bus.store::<u32>(GP0, (height << 16) | width);
+    for i in 0..(width * height).div_ceil(2) {
+        let word = bus.load::<u32>(src.wrapping_add(i.wrapping_mul(4)));
+        bus.store::<u32>(GP0, word);
+    }
+
+    0
+}
+
+fn reg(bus: &Bus, r: u8) -> u32 {
+    bus.cpu.reg(RegisterIndex(r))
+}
+
+fn set_reg(bus: &mut Bus, r: u8, v: u32) {
+    bus.cpu.set_reg(RegisterIndex(r), v)
+}
+
+/// Argument `n` of the function called, from the stack after the 4th
+fn arg(bus: &mut Bus, n: usize) -> u32 {
+    match n {
+        0..4 => reg(bus, r::A0 + n as u8),
+        _ => {
+            let sp = reg(bus, r::SP);
+            bus.load::<u32>(sp.wrapping_add(n as u32 * 4))
+        }
+    }
+}
+
+/// Go on at `addr`, the instruction in the pipeline is dropped
+fn jump(bus: &mut Bus, addr: u32) {
+    bus.cpu.pc = addr;
+    bus.cpu.next_pc = addr.wrapping_add(4);
+}
+
+fn read_bytes(bus: &mut Bus, addr: u32, len: u32) -> Vec<u8> {
+    (0..len).map(|i| bus.load::<u8>(addr.wrapping_add(i))).collect()
+}
+
+fn write_bytes(bus: &mut Bus, addr: u32, bytes: &[u8]) {
+    for (i, &b) in bytes.iter().enumerate() {
+        bus.store::<u8>(addr.wrapping_add(i as u32), b);
+    }
+}
+
+/// The string at `addr` without its NUL, 1 KiB at most
+fn read_string(bus: &mut Bus, addr: u32) -> Vec<u8> {
+    let mut s = Vec::new();
+    if addr == 0 {
+        return s;
+    }
+
+    while s.len() < 1024 {
+        match bus.load::<u8>(addr.wrapping_add(s.len() as u32)) {
+            0 => break,
+            b => s.push(b),
+        }
+    }
+
+    s
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Send `command` to the device on the other side of `peripheral` like the serial port does, the
+/// replies until it stops answering
+fn transfer(peripheral: &mut Peripheral, command: &[u8]) -> Vec<u8> {
+    peripheral.select();
+
+    let mut replies = Vec::with_capacity(command.len());
+    for &b in command {
+        let (reply, dsr) = peripheral.exchange_byte(b);
+        replies.push(reply);
+        if dsr == DsrState::Idle {
+            break;
+        }
+    }
+
+    replies
+}
+
+#[test]
+fn heap() {
+    let mut heap = Heap::new(0x8010_0001, 0x100);
+
+    let a = heap.alloc(0x10);
+    let b = heap.alloc(0x20);
+    assert_eq!(a, 0x8010_0004);
+    assert_eq!(b, 0x8010_0014);
+    assert_eq!(heap.alloc(0x1000), 0);
+
+    // Freed blocks are merged and reused
+    heap.free(a);
+    heap.free(b);
+    assert_eq!(heap.blocks.len(), 1);
+    assert_eq!(heap.alloc(0x30), 0x8010_0004);
+}
+
+#[test]
+fn kernel_layout() {
+    let (code, routines) = assemble_kernel();
+
+    // Below the kernel's data
+    assert!(KERNEL_CODE + code.len() as u32 <= 0x8000_0000 + PCB);
+    assert_eq!(routines.syscall, KERNEL_CODE);
+    assert!(is_trap(Routine::ExecReturn.address()));
+    assert!(!is_trap(TRAP_BASE + TRAP_PAGE_SIZE));
+}
@@ -0,0 +1,113 @@
+//! The memory cards, read and written one sector at a time through their own protocol like the
+//! BIOS does, so that the frontend sees the writes of the emulated kernel as the games' own
+
+use crate::ps1::psx::bus::Bus;
+use crate::ps1::psx::pad_memcard::DeviceInterface;
+use crate::ps1::psx::pad_memcard::memory_card::{MemoryCard, FLASH_SIZE};
+use super::{arg, read_bytes, transfer, write_bytes, Hle};
+
+const SECTOR_SIZE: usize = 128;
+
+/// Events of the card functions: the hardware one, and the software one of the file functions
+pub(super) const HW_CARD: u32 = 0xf000_0011;
+pub(super) const SW_CARD: u32 = 0xf400_0001;
+/// Specs: done, or no card answered
+pub(super) const EV_SP_IOE: u32 = 0x0004;
+const EV_SP_TIMOUT: u32 = 0x0100;
+
+/// Sector `sector` of the card in `slot`, `None` if it didn't answer
+fn read_sector(bus: &mut Bus, slot: usize, sector: u16) -> Option<[u8; SECTOR_SIZE]> {
+    let [msb, lsb] = sector.to_be_bytes();
+
+    // Command, ID, address, acknowledge, confirmed address, data, checksum and end status
+    let mut command = [0; 10 + SECTOR_SIZE + 2];
+    command[..6].copy_from_slice(&[0x81, b'R', 0, 0, msb, lsb]);
+
+    let reply = transfer(bus.pad_memcard.memory_cards_mut()[slot], &command);
+    if reply.len() != command.len() || reply[command.len() - 1] != b'G' {
+        return None;
+    }
+
+    reply[10..10 + SECTOR_SIZE].try_into().ok()
+}
+
+/// Write `data` to sector `sector` of the card in `slot`, false if it didn't take it
+fn write_sector(bus: &mut Bus, slot: usize, sector: u16, data: &[u8]) -> bool {
+    let [msb, lsb] = sector.to_be_bytes();
+
+    // Command, ID, address, data, checksum, acknowledges and end status
+    let mut command = [0; 6 + SECTOR_SIZE + 4];
+    command[..6].copy_from_slice(&[0x81, b'W', 0, 0, msb, lsb]);
+    command[6..6 + SECTOR_SIZE].copy_from_slice(data);
+    command[6 + SECTOR_SIZE] = data.iter().fold(msb ^ lsb, |c, &b| c ^ b);
+
+    let reply = transfer(bus.pad_memcard.memory_cards_mut()[slot], &command);
+
+    reply.len() == command.len() && reply[command.len() - 1] == b'G'
+}
+
+/// Copy of the card in `slot`, to work on with `mem_card::Fs`
+pub(super) fn image(bus: &mut Bus, slot: usize) -> Option<Vec<u8>> {
+    bus.pad_memcard.memory_cards()[slot].device().get_memory().map(|m| m.to_vec())
+}
+
+/// An empty card, for `format`
+pub(super) fn formatted() -> Vec<u8> {
+    MemoryCard::new_formatted().get_memory().map_or_else(|| vec![0; FLASH_SIZE], |m| m.to_vec())
+}
+
+/// Write the sectors that differ between `original` and `image` to the card in `slot`
+pub(super) fn commit(bus: &mut Bus, slot: usize, original: &[u8], image: &[u8]) -> bool {
+    let sectors = original.chunks(SECTOR_SIZE).zip(image.chunks(SECTOR_SIZE)).enumerate();
+
+    sectors
+        .filter(|(_, (old, new))| old != new)
+        .all(|(sector, (_, new))| write_sector(bus, slot, sector as u16, new))
+}
+
+/// Port given to the card functions: 0x00 for the first slot, 0x10 for the second one
+fn slot(port: u32) -> usize {
+    ((port >> 4) & 1) as usize
+}
+
+impl Hle {
+    /// The hardware and software card events, once a transfer is done
+    fn card_done(&mut self, ok: bool) {
+        let spec = if ok { EV_SP_IOE } else { EV_SP_TIMOUT };
+
+        self.deliver(HW_CARD, spec);
+        self.deliver(SW_CARD, spec);
+    }
+
+    /// `_card_read(port, sector, dst)` and `_card_write(port, sector, src)`. The transfer is
+    /// done at once, its events are delivered before returning.
+    pub(super) fn card_transfer(&mut self, bus: &mut Bus, write: bool) -> u32 {
+        let (slot, sector, buf) = (slot(arg(bus, 0)), arg(bus, 1) as u16, arg(bus, 2));
+
+        let ok = if write {
+            let data = read_bytes(bus, buf, SECTOR_SIZE as u32);
+            write_sector(bus, slot, sector, &data)
+        } else {
+            match read_sector(bus, slot, sector) {
+                Some(data) => {
+                    write_bytes(bus, buf, &data);
+                    true
+                }
+                None => false,
+            }
+        };
+        self.card_done(ok);
+
+        1
+    }
+
+    /// `_card_info(port)` and `_card_load(port)`: whether there's a card
+    pub(super) fn card_info(&mut self, bus: &mut Bus) -> u32 {
+        let slot = slot(arg(bus, 0));
+
+        let ok = read_sector(bus, slot, 0).is_some();
+        self.card_done(ok);
+
+        1
+    }
+}
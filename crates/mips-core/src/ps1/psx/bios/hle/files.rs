@@ -0,0 +1,420 @@
+//! The file functions: the files of the disc (`cdrom:`), read whole when they're opened, and the
+//! saves on the memory cards (`bu00:` and `bu10:`), see `card`
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::ps1::mem_card::{Fs, Save, BLOCK_SIZE};
+use crate::ps1::psx::bus::Bus;
+use super::card::{self, EV_SP_IOE, SW_CARD};
+use super::{arg, read_bytes, read_string, write_bytes, Hle};
+
+/// Descriptors 0 and 1 are the TTY
+const FIRST_FD: usize = 2;
+const MAX_FILES: usize = 16;
+
+/// `open` modes
+const O_CREAT: u32 = 0x0200;
+const O_ASYNC: u32 = 0x8000;
+
+/// Error codes of `_get_errno`
+const ENOENT: u32 = 2;
+const EIO: u32 = 5;
+const EBADF: u32 = 9;
+const EEXIST: u32 = 17;
+const EINVAL: u32 = 22;
+const EMFILE: u32 = 24;
+const ENOSPC: u32 = 28;
+
+/// Size of the directory entries of `firstfile` and `nextfile`
+const DIRENT_SIZE: usize = 0x28;
+
+#[derive(Serialize, Deserialize)]
+enum Device {
+    /// Contents of a file of the disc
+    Cdrom(Vec<u8>),
+    /// Save on the card in `slot`
+    Card { slot: usize, name: String },
+}
+
+#[derive(Serialize, Deserialize)]
+struct File {
+    device: Device,
+    pos: u32,
+    /// The memory card calls deliver the card events when done
+    asynchronous: bool,
+}
+
+/// Search of `firstfile`, for `nextfile`
+#[derive(Serialize, Deserialize)]
+struct Search {
+    slot: usize,
+    pattern: Vec<u8>,
+    /// First block to look at
+    next: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct Files {
+    open: Vec<Option<File>>,
+    search: Option<Search>,
+    pub(super) errno: u32,
+}
+
+impl Files {
+    pub fn new() -> Files {
+        Files {
+            open: (0..MAX_FILES).map(|_| None).collect(),
+            search: None,
+            errno: 0,
+        }
+    }
+}
+
+/// Where `path` is: the disc and the path on it, or a card slot and the file name
+enum Path {
+    Cdrom(Vec<u8>),
+    Card(usize, String),
+}
+
+fn parse_path(path: &[u8]) -> Option<Path> {
+    let colon = path.iter().position(|&b| b == b':')?;
+    let (device, rest) = (path[..colon].to_ascii_lowercase(), &path[colon + 1..]);
+
+    match device.as_slice() {
+        b"cdrom" => Some(Path::Cdrom(rest.to_vec())),
+        // bu00 to bu0f for the first port, bu10 to bu1f for the second one
+        [b'b', b'u', port @ (b'0' | b'1'), _] => {
+            let name = String::from_utf8_lossy(rest).trim_start_matches('\\').to_string();
+            Some(Path::Card(usize::from(*port - b'0'), name))
+        }
+        _ => None,
+    }
+}
+
+/// File names of `firstfile`: `?` matches any character, `*` the rest of the name
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) | (Some(b'*'), _) => true,
+        (Some(b'?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p.eq_ignore_ascii_case(n) => matches(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+impl Hle {
+    /// Contents of the file at `path` on the disc
+    pub(super) fn cdrom_file(&mut self, bus: &mut Bus, path: &[u8]) -> Option<Vec<u8>> {
+        let Some(Path::Cdrom(path)) = parse_path(path) else {
+            warn!("HLE BIOS: can only read files from the disc, not {}", String::from_utf8_lossy(path));
+            return None;
+        };
+
+        let data = bus.cd.disc_mut().and_then(|disc| disc.read_file(&path).ok());
+        if data.is_none() {
+            warn!("HLE BIOS: {} isn't on the disc", String::from_utf8_lossy(&path));
+        }
+
+        data
+    }
+
+    fn fail(&mut self, errno: u32) -> u32 {
+        self.files.errno = errno;
+
+        u32::MAX
+    }
+
+    /// `open(path, mode)`, the number of blocks of the new saves in the top halfword of `mode`
+    pub(super) fn open(&mut self, bus: &mut Bus) -> u32 {
+        let (path, mode) = (arg(bus, 0), arg(bus, 1));
+        let path = read_string(bus, path);
+
+        let Some(fd) = (FIRST_FD..MAX_FILES).find(|&fd| self.files.open[fd].is_none()) else {
+            return self.fail(EMFILE);
+        };
+
+        let device = match parse_path(&path) {
+            Some(Path::Cdrom(path)) => {
+                match bus.cd.disc_mut().and_then(|disc| disc.read_file(&path).ok()) {
+                    Some(data) => Device::Cdrom(data),
+                    None => return self.fail(ENOENT),
+                }
+            }
+            Some(Path::Card(slot, name)) => {
+                let Some(mut fs) = card::image(bus, slot).and_then(|image| Fs::new(image).ok()) else {
+                    return self.fail(ENOENT);
+                };
+                let exists = fs.saves().is_ok_and(|saves| saves.iter().any(|s| s.name == name));
+
+                if mode & O_CREAT != 0 {
+                    if exists {
+                        return self.fail(EEXIST);
+                    }
+                    let blocks = ((mode >> 16) as usize).max(1);
+                    let original = fs.image().to_vec();
+                    if fs.create(&name, blocks).is_err() {
+                        return self.fail(ENOSPC);
+                    }
+                    if !card::commit(bus, slot, &original, fs.image()) {
+                        return self.fail(EIO);
+                    }
+                } else if !exists {
+                    return self.fail(ENOENT);
+                }
+
+                Device::Card { slot, name }
+            }
+            None => return self.fail(EINVAL),
+        };
+
+        self.files.open[fd] = Some(File {
+            device,
+            pos: 0,
+            asynchronous: mode & O_ASYNC != 0,
+        });
+
+        fd as u32
+    }
+
+    fn file(&mut self, fd: u32) -> Option<&mut File> {
+        self.files.open.get_mut(fd as usize).and_then(|f| f.as_mut())
+    }
+
+    /// The save behind a card file, and the filesystem of the card it's on
+    fn card_save(bus: &mut Bus, slot: usize, name: &str) -> Option<(Fs, Save)> {
+        let fs = Fs::new(card::image(bus, slot)?).ok()?;
+        let save = fs.saves().ok()?.into_iter().find(|s| s.name == name)?;
+
+        Some((fs, save))
+    }
+
+    /// `read(fd, dst, len)`
+    pub(super) fn read(&mut self, bus: &mut Bus) -> u32 {
+        let (fd, dst, len) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+
+        let Some(file) = self.file(fd) else {
+            return self.fail(EBADF);
+        };
+        let pos = file.pos as usize;
+        let asynchronous = file.asynchronous;
+        let data = match &file.device {
+            Device::Cdrom(data) => data.get(pos..).unwrap_or_default().to_vec(),
+            Device::Card { slot, name } => match Hle::card_save(bus, *slot, name) {
+                Some((fs, save)) => fs.data(&save).get(pos..).unwrap_or_default().to_vec(),
+                None => return self.fail(EIO),
+            },
+        };
+
+        let n = data.len().min(len as usize);
+        write_bytes(bus, dst, &data[..n]);
+        if let Some(file) = self.file(fd) {
+            file.pos += n as u32;
+        }
+        if asynchronous {
+            self.deliver(SW_CARD, EV_SP_IOE);
+        }
+
+        n as u32
+    }
+
+    /// `write(fd, src, len)`, to a save or the TTY
+    pub(super) fn write(&mut self, bus: &mut Bus) -> u32 {
+        let (fd, src, len) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+
+        if fd < FIRST_FD as u32 {
+            for b in read_bytes(bus, src, len) {
+                bus.tty.push_char(char::from(b));
+            }
+            return len;
+        }
+
+        let Some(file) = self.file(fd) else {
+            return self.fail(EBADF);
+        };
+        let (slot, name) = match &file.device {
+            Device::Card { slot, name } => (*slot, name.clone()),
+            Device::Cdrom(_) => return self.fail(EBADF),
+        };
+        let (pos, asynchronous) = (file.pos as usize, file.asynchronous);
+
+        let Some((fs, save)) = Hle::card_save(bus, slot, &name) else {
+            return self.fail(EIO);
+        };
+        let size = save.blocks.len() * BLOCK_SIZE;
+        let data = read_bytes(bus, src, len.min(size.saturating_sub(pos) as u32));
+
+        let original = fs.into_image();
+        let mut image = original.clone();
+        for (i, &b) in data.iter().enumerate() {
+            let offset = pos + i;
+            let block = save.blocks[offset / BLOCK_SIZE];
+            image[(block + 1) * BLOCK_SIZE + offset % BLOCK_SIZE] = b;
+        }
+        if !card::commit(bus, slot, &original, &image) {
+            return self.fail(EIO);
+        }
+
+        if let Some(file) = self.file(fd) {
+            file.pos += data.len() as u32;
+        }
+        if asynchronous {
+            self.deliver(SW_CARD, EV_SP_IOE);
+        }
+
+        data.len() as u32
+    }
+
+    /// `lseek(fd, offset, whence)`
+    pub(super) fn lseek(&mut self, bus: &mut Bus) -> u32 {
+        let (fd, offset, whence) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+        if whence > 1 {
+            return self.fail(EINVAL);
+        }
+
+        let Some(file) = self.file(fd) else {
+            return self.fail(EBADF);
+        };
+        file.pos = match whence {
+            0 => offset,
+            _ => file.pos.wrapping_add(offset),
+        };
+
+        file.pos
+    }
+
+    pub(super) fn close(&mut self, bus: &mut Bus) -> u32 {
+        let fd = arg(bus, 0);
+
+        match self.files.open.get_mut(fd as usize).and_then(Option::take) {
+            Some(_) => fd,
+            None => self.fail(EBADF),
+        }
+    }
+
+    /// `firstfile(pattern, dirent)`: the first save matching `pattern`, 0 if there's none
+    pub(super) fn first_file(&mut self, bus: &mut Bus) -> u32 {
+        let pattern = arg(bus, 0);
+        let pattern = read_string(bus, pattern);
+
+        self.files.search = match parse_path(&pattern) {
+            Some(Path::Card(slot, pattern)) => Some(Search {
+                slot,
+                pattern: pattern.into_bytes(),
+                next: 0,
+            }),
+            _ => {
+                warn!("HLE BIOS: can only list the memory cards, not {}", String::from_utf8_lossy(&pattern));
+                None
+            }
+        };
+
+        let dirent = arg(bus, 1);
+        self.find_next(bus, dirent)
+    }
+
+    /// `nextfile(dirent)`: the next save matching the pattern of `firstfile`
+    pub(super) fn next_file(&mut self, bus: &mut Bus) -> u32 {
+        let dirent = arg(bus, 0);
+        self.find_next(bus, dirent)
+    }
+
+    /// Fill `dirent` with the next save of the search, 0 if there's none left
+    fn find_next(&mut self, bus: &mut Bus, dirent: u32) -> u32 {
+        let Some(search) = &mut self.files.search else {
+            return 0;
+        };
+
+        let saves = card::image(bus, search.slot)
+            .and_then(|image| Fs::new(image).ok()?.saves().ok())
+            .unwrap_or_default();
+        let Some(save) = saves
+            .into_iter()
+            .filter(|s| s.blocks[0] >= search.next)
+            .find(|s| matches(&search.pattern, s.name.as_bytes()))
+        else {
+            self.files.search = None;
+            return 0;
+        };
+        search.next = save.blocks[0] + 1;
+
+        let mut entry = [0; DIRENT_SIZE];
+        entry[..save.name.len()].copy_from_slice(save.name.as_bytes());
+        entry[0x14..0x18].copy_from_slice(&0x50u32.to_le_bytes());
+        entry[0x18..0x1c].copy_from_slice(&((save.blocks.len() * BLOCK_SIZE) as u32).to_le_bytes());
+        entry[0x20..0x24].copy_from_slice(&(save.blocks[0] as u32 + 1).to_le_bytes());
+        write_bytes(bus, dirent, &entry);
+
+        dirent
+    }
+
+    /// `erase(path)`, 1 on success
+    pub(super) fn erase(&mut self, bus: &mut Bus) -> u32 {
+        let path = arg(bus, 0);
+        let path = read_string(bus, path);
+
+        let Some(Path::Card(slot, name)) = parse_path(&path) else {
+            self.files.errno = EINVAL;
+            return 0;
+        };
+        let Some((mut fs, save)) = Hle::card_save(bus, slot, &name) else {
+            self.files.errno = ENOENT;
+            return 0;
+        };
+
+        let original = fs.image().to_vec();
+        fs.delete(&save);
+
+        u32::from(card::commit(bus, slot, &original, fs.image()))
+    }
+
+    /// `rename(old, new)` on the same card, 1 on success
+    pub(super) fn rename(&mut self, bus: &mut Bus) -> u32 {
+        let (old, new) = (arg(bus, 0), arg(bus, 1));
+        let (old, new) = (read_string(bus, old), read_string(bus, new));
+
+        let (Some(Path::Card(slot, old)), Some(Path::Card(new_slot, new))) = (parse_path(&old), parse_path(&new))
+        else {
+            self.files.errno = EINVAL;
+            return 0;
+        };
+        let Some((mut fs, save)) = Hle::card_save(bus, slot, &old).filter(|_| slot == new_slot) else {
+            self.files.errno = ENOENT;
+            return 0;
+        };
+
+        let original = fs.image().to_vec();
+        if fs.rename(&save, &new).is_err() {
+            self.files.errno = EEXIST;
+            return 0;
+        }
+
+        u32::from(card::commit(bus, slot, &original, fs.image()))
+    }
+
+    /// `format(device)`, 1 on success
+    pub(super) fn format(&mut self, bus: &mut Bus) -> u32 {
+        let path = arg(bus, 0);
+        let path = read_string(bus, path);
+
+        let Some(Path::Card(slot, _)) = parse_path(&path) else {
+            self.files.errno = EINVAL;
+            return 0;
+        };
+        let Some(original) = card::image(bus, slot) else {
+            self.files.errno = ENOENT;
+            return 0;
+        };
+
+        u32::from(card::commit(bus, slot, &original, &card::formatted()))
+    }
+}
+
+#[test]
+fn file_names() {
+    assert!(matches(b"BASLUS-00001*", b"BASLUS-00001SAVE"));
+    assert!(matches(b"BASLUS-0000?SAVE", b"baslus-00001save"));
+    assert!(!matches(b"BASLUS-0000?", b"BASLUS-00001SAVE"));
+
+    assert!(matches!(parse_path(b"bu10:BASLUS-00001"), Some(Path::Card(1, ref name)) if name == "BASLUS-00001"));
+    assert!(matches!(parse_path(b"cdrom:\\DATA.BIN;1"), Some(Path::Cdrom(_))));
+    assert!(parse_path(b"tty:").is_none());
+}
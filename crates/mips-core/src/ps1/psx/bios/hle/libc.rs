@@ -0,0 +1,608 @@
+//! The C library of the A0 table: strings, memory, number conversions, `setjmp` and the standard
+//! output. The kernel's versions are followed where they differ from the standard, NULL pointers
+//! are tolerated for instance.
+
+use crate::ps1::psx::bus::Bus;
+use super::{arg, jump, r, read_string, reg, set_reg};
+
+/// Longest string read by the functions that look for the terminating NUL, the kernel's strings
+/// are much shorter and a missing NUL shouldn't read the whole RAM
+const MAX_STRING: usize = 0x10000;
+/// Widths and precisions of `printf` are clamped to that, the BIOS formats in a small fixed buffer
+const MAX_PRINTF_FIELD: usize = 0x100;
+
+fn load(bus: &mut Bus, addr: u32) -> u8 {
+    bus.load::<u8>(addr)
+}
+
+fn store(bus: &mut Bus, addr: u32, b: u8) {
+    bus.store::<u8>(addr, b)
+}
+
+fn strlen_at(bus: &mut Bus, s: u32) -> u32 {
+    let mut len = 0;
+    while (len as usize) < MAX_STRING && load(bus, s.wrapping_add(len)) != 0 {
+        len += 1;
+    }
+
+    len
+}
+
+pub(super) fn strlen(bus: &mut Bus) -> u32 {
+    match arg(bus, 0) {
+        0 => 0,
+        s => strlen_at(bus, s),
+    }
+}
+
+/// Copy the string `src` with its NUL at `dst`, `n` characters at most
+fn copy_string(bus: &mut Bus, dst: u32, src: u32, n: u32) {
+    for i in 0..n {
+        let b = load(bus, src.wrapping_add(i));
+        store(bus, dst.wrapping_add(i), b);
+        if b == 0 {
+            return;
+        }
+    }
+}
+
+pub(super) fn strcat(bus: &mut Bus) -> u32 {
+    let (dst, src) = (arg(bus, 0), arg(bus, 1));
+    if dst == 0 || src == 0 {
+        return 0;
+    }
+
+    let end = dst.wrapping_add(strlen_at(bus, dst));
+    copy_string(bus, end, src, MAX_STRING as u32);
+
+    dst
+}
+
+pub(super) fn strncat(bus: &mut Bus) -> u32 {
+    let (dst, src, n) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+    if dst == 0 || src == 0 {
+        return 0;
+    }
+
+    let end = dst.wrapping_add(strlen_at(bus, dst));
+    let len = strlen_at(bus, src).min(n);
+    for i in 0..len {
+        let b = load(bus, src.wrapping_add(i));
+        store(bus, end.wrapping_add(i), b);
+    }
+    store(bus, end.wrapping_add(len), 0);
+
+    dst
+}
+
+fn compare_strings(bus: &mut Bus, s1: u32, s2: u32, n: u32) -> u32 {
+    match (s1, s2) {
+        (0, 0) => return 0,
+        (0, _) => return -1i32 as u32,
+        (_, 0) => return 1,
+        _ => (),
+    }
+
+    for i in 0..n {
+        let (c1, c2) = (load(bus, s1.wrapping_add(i)), load(bus, s2.wrapping_add(i)));
+        if c1 != c2 || c1 == 0 {
+            return (i32::from(c1) - i32::from(c2)) as u32;
+        }
+    }
+
+    0
+}
+
+pub(super) fn strcmp(bus: &mut Bus) -> u32 {
+    let (s1, s2) = (arg(bus, 0), arg(bus, 1));
+
+    compare_strings(bus, s1, s2, MAX_STRING as u32)
+}
+
+pub(super) fn strncmp(bus: &mut Bus) -> u32 {
+    let (s1, s2, n) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+
+    compare_strings(bus, s1, s2, n)
+}
+
+pub(super) fn strcpy(bus: &mut Bus) -> u32 {
+    let (dst, src) = (arg(bus, 0), arg(bus, 1));
+    if dst == 0 || src == 0 {
+        return 0;
+    }
+
+    copy_string(bus, dst, src, MAX_STRING as u32);
+
+    dst
+}
+
+pub(super) fn strncpy(bus: &mut Bus) -> u32 {
+    let (dst, src, n) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+    if dst == 0 || src == 0 {
+        return 0;
+    }
+
+    // Padded with NULs up to `n`, like the standard one
+    let mut ended = false;
+    for i in 0..n {
+        let b = if ended { 0 } else { load(bus, src.wrapping_add(i)) };
+        ended |= b == 0;
+        store(bus, dst.wrapping_add(i), b);
+    }
+
+    dst
+}
+
+/// `strchr` and `strrchr`: the first or the last `c` in `s`, the NUL included
+pub(super) fn strchr(bus: &mut Bus, last: bool) -> u32 {
+    let (s, c) = (arg(bus, 0), arg(bus, 1) as u8);
+    if s == 0 {
+        return 0;
+    }
+
+    let mut found = 0;
+    for i in 0..=strlen_at(bus, s) {
+        let addr = s.wrapping_add(i);
+        if load(bus, addr) == c {
+            found = addr;
+            if !last {
+                break;
+            }
+        }
+    }
+
+    found
+}
+
+/// Offset of the first character of `s` that is (`in_set`) or isn't in `set`, the length of `s`
+/// if there's none
+fn span(bus: &mut Bus, s: u32, set: u32, in_set: bool) -> u32 {
+    let set = read_string(bus, set);
+    let len = strlen_at(bus, s);
+
+    (0..len).find(|&i| set.contains(&load(bus, s.wrapping_add(i))) == in_set).unwrap_or(len)
+}
+
+pub(super) fn strpbrk(bus: &mut Bus) -> u32 {
+    let (s, set) = (arg(bus, 0), arg(bus, 1));
+    if s == 0 || set == 0 {
+        return 0;
+    }
+
+    let i = span(bus, s, set, true);
+    match load(bus, s.wrapping_add(i)) {
+        0 => 0,
+        _ => s.wrapping_add(i),
+    }
+}
+
+pub(super) fn strspn(bus: &mut Bus) -> u32 {
+    let (s, set) = (arg(bus, 0), arg(bus, 1));
+    if s == 0 || set == 0 {
+        return 0;
+    }
+
+    span(bus, s, set, false)
+}
+
+pub(super) fn strcspn(bus: &mut Bus) -> u32 {
+    let (s, set) = (arg(bus, 0), arg(bus, 1));
+    if s == 0 || set == 0 {
+        return 0;
+    }
+
+    span(bus, s, set, true)
+}
+
+/// `next` is where the previous call stopped
+pub(super) fn strtok(bus: &mut Bus, next: &mut u32) -> u32 {
+    let (s, delimiters) = (arg(bus, 0), arg(bus, 1));
+    let s = if s == 0 { *next } else { s };
+    if s == 0 || delimiters == 0 {
+        return 0;
+    }
+
+    let start = s.wrapping_add(span(bus, s, delimiters, false));
+    if load(bus, start) == 0 {
+        *next = 0;
+        return 0;
+    }
+
+    let end = start.wrapping_add(span(bus, start, delimiters, true));
+    if load(bus, end) == 0 {
+        *next = 0;
+    } else {
+        store(bus, end, 0);
+        *next = end.wrapping_add(1);
+    }
+
+    start
+}
+
+pub(super) fn strstr(bus: &mut Bus) -> u32 {
+    let (s, sub) = (arg(bus, 0), arg(bus, 1));
+    if s == 0 || sub == 0 {
+        return 0;
+    }
+
+    let haystack = read_string(bus, s);
+    let needle = read_string(bus, sub);
+    if needle.is_empty() {
+        return s;
+    }
+
+    haystack
+        .windows(needle.len())
+        .position(|w| w == needle.as_slice())
+        .map_or(0, |i| s.wrapping_add(i as u32))
+}
+
+pub(super) fn toupper(bus: &mut Bus) -> u32 {
+    u32::from((arg(bus, 0) as u8).to_ascii_uppercase())
+}
+
+pub(super) fn tolower(bus: &mut Bus) -> u32 {
+    u32::from((arg(bus, 0) as u8).to_ascii_lowercase())
+}
+
+/// Copy `len` bytes, right even if the ranges overlap
+fn copy(bus: &mut Bus, dst: u32, src: u32, len: u32) {
+    let bytes: Vec<u8> = (0..len).map(|i| load(bus, src.wrapping_add(i))).collect();
+    for (i, b) in bytes.into_iter().enumerate() {
+        store(bus, dst.wrapping_add(i as u32), b);
+    }
+}
+
+pub(super) fn memcpy(bus: &mut Bus) -> u32 {
+    let (dst, src, len) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+    if dst == 0 || src == 0 {
+        return 0;
+    }
+
+    copy(bus, dst, src, len);
+
+    dst
+}
+
+/// `bcopy` takes the source first
+pub(super) fn bcopy(bus: &mut Bus) -> u32 {
+    let (src, dst, len) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+    if dst != 0 && src != 0 {
+        copy(bus, dst, src, len);
+    }
+
+    0
+}
+
+pub(super) fn memset(bus: &mut Bus) -> u32 {
+    let (dst, c, len) = (arg(bus, 0), arg(bus, 1) as u8, arg(bus, 2));
+    if dst == 0 {
+        return 0;
+    }
+
+    for i in 0..len {
+        store(bus, dst.wrapping_add(i), c);
+    }
+
+    dst
+}
+
+pub(super) fn bzero(bus: &mut Bus) -> u32 {
+    let (dst, len) = (arg(bus, 0), arg(bus, 1));
+    if dst == 0 {
+        return 0;
+    }
+
+    for i in 0..len {
+        store(bus, dst.wrapping_add(i), 0);
+    }
+
+    dst
+}
+
+pub(super) fn memcmp(bus: &mut Bus) -> u32 {
+    let (s1, s2, len) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+    if s1 == 0 || s2 == 0 {
+        return 0;
+    }
+
+    for i in 0..len {
+        let (c1, c2) = (load(bus, s1.wrapping_add(i)), load(bus, s2.wrapping_add(i)));
+        if c1 != c2 {
+            return (i32::from(c1) - i32::from(c2)) as u32;
+        }
+    }
+
+    0
+}
+
+pub(super) fn memchr(bus: &mut Bus) -> u32 {
+    let (s, c, len) = (arg(bus, 0), arg(bus, 1) as u8, arg(bus, 2));
+    if s == 0 {
+        return 0;
+    }
+
+    (0..len).map(|i| s.wrapping_add(i)).find(|&addr| load(bus, addr) == c).unwrap_or(0)
+}
+
+/// The kernel's linear congruential generator, 15 bits per call
+pub(super) fn rand(seed: &mut u32) -> u32 {
+    *seed = seed.wrapping_mul(0x41c6_4e6d).wrapping_add(0x3039);
+
+    (*seed >> 16) & 0x7fff
+}
+
+pub(super) fn abs(bus: &mut Bus) -> u32 {
+    (arg(bus, 0) as i32).wrapping_abs() as u32
+}
+
+/// Value of the digit `c` in any base up to 36, 9999999 if it's not a digit
+fn digit(c: u8) -> u32 {
+    match c {
+        b'0'..=b'9' => u32::from(c - b'0'),
+        b'a'..=b'z' => u32::from(c - b'a') + 10,
+        b'A'..=b'Z' => u32::from(c - b'A') + 10,
+        _ => 9_999_999,
+    }
+}
+
+pub(super) fn todigit(bus: &mut Bus) -> u32 {
+    digit(arg(bus, 0) as u8)
+}
+
+/// Parse the number at `s` in `base` (0 tells it from the prefix), returns it and where it ends
+fn parse_number(bus: &mut Bus, s: u32, base: u32) -> (u32, u32) {
+    let mut p = s;
+    while matches!(load(bus, p), b' ' | b'\t' | b'\n' | b'\r') {
+        p = p.wrapping_add(1);
+    }
+
+    let negative = load(bus, p) == b'-';
+    if matches!(load(bus, p), b'-' | b'+') {
+        p = p.wrapping_add(1);
+    }
+
+    let hex_prefix = load(bus, p) == b'0' && matches!(load(bus, p.wrapping_add(1)), b'x' | b'X');
+    let base = match base {
+        0 if hex_prefix => 16,
+        0 if load(bus, p) == b'0' => 8,
+        0 => 10,
+        b => b,
+    };
+    if base == 16 && hex_prefix {
+        p = p.wrapping_add(2);
+    }
+
+    let start = p;
+    let mut value = 0u32;
+    loop {
+        let d = digit(load(bus, p));
+        if d >= base {
+            break;
+        }
+        value = value.wrapping_mul(base).wrapping_add(d);
+        p = p.wrapping_add(1);
+    }
+
+    if p == start {
+        return (0, s);
+    }
+
+    (if negative { value.wrapping_neg() } else { value }, p)
+}
+
+/// `strtol` and `strtoul`, the same without overflow checks
+pub(super) fn strtol(bus: &mut Bus) -> u32 {
+    let (s, end, base) = (arg(bus, 0), arg(bus, 1), arg(bus, 2));
+    if s == 0 {
+        return 0;
+    }
+
+    let (value, stop) = parse_number(bus, s, base);
+    if end != 0 {
+        bus.store::<u32>(end, stop);
+    }
+
+    value
+}
+
+pub(super) fn atoi(bus: &mut Bus) -> u32 {
+    match arg(bus, 0) {
+        0 => 0,
+        s => parse_number(bus, s, 10).0,
+    }
+}
+
+/// `atob(src, &num)`: the number goes to `num`, returns where it ends
+pub(super) fn atob(bus: &mut Bus) -> u32 {
+    let (s, num) = (arg(bus, 0), arg(bus, 1));
+    if s == 0 {
+        return 0;
+    }
+
+    let (value, stop) = parse_number(bus, s, 10);
+    if num != 0 {
+        bus.store::<u32>(num, value);
+    }
+
+    stop
+}
+
+/// Registers saved by `setjmp`: RA, SP, FP, S0 to S7 and GP
+fn jmp_buf_registers() -> impl Iterator<Item = u8> {
+    [r::RA, r::SP, r::FP].into_iter().chain(r::S0..r::S0 + 8).chain([r::GP])
+}
+
+pub(super) fn setjmp(bus: &mut Bus) -> u32 {
+    let buf = arg(bus, 0);
+
+    for (i, r) in jmp_buf_registers().enumerate() {
+        let v = reg(bus, r);
+        bus.store::<u32>(buf.wrapping_add(i as u32 * 4), v);
+    }
+
+    0
+}
+
+/// Back to the `setjmp` of `buf`, which returns `value`
+pub(super) fn longjmp(bus: &mut Bus, buf: u32, value: u32) {
+    for (i, r) in jmp_buf_registers().enumerate() {
+        let v = bus.load::<u32>(buf.wrapping_add(i as u32 * 4));
+        set_reg(bus, r, v);
+    }
+
+    set_reg(bus, r::V0, value);
+    let ra = reg(bus, r::RA);
+    jump(bus, ra);
+}
+
+pub(super) fn putchar(bus: &mut Bus, c: u8) {
+    // The calls through the A0 and B0 vectors are caught on their way while capturing, see
+    // `Tty::check_bios_call`
+    if !bus.tty.is_capturing() {
+        bus.tty.push_char(char::from(c));
+    }
+}
+
+pub(super) fn puts(bus: &mut Bus) -> u32 {
+    let s = arg(bus, 0);
+    if s != 0 {
+        for b in read_string(bus, s) {
+            bus.tty.push_char(char::from(b));
+        }
+    }
+    bus.tty.push_char('\n');
+
+    1
+}
+
+pub(super) fn printf(bus: &mut Bus) -> u32 {
+    let format = arg(bus, 0);
+    if format == 0 {
+        return 0;
+    }
+
+    let format = read_string(bus, format);
+    let mut next_arg = 1;
+    let mut out = Vec::new();
+    let mut chars = format.into_iter().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != b'%' {
+            out.push(c);
+            continue;
+        }
+
+        let mut left = false;
+        let mut zero = false;
+        let mut sign = None;
+        let mut alternate = false;
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                b'-' => left = true,
+                b'0' => zero = true,
+                b'+' => sign = Some(b'+'),
+                b' ' => sign = sign.or(Some(b' ')),
+                b'#' => alternate = true,
+                _ => break,
+            }
+            chars.next();
+        }
+
+        let mut number = |chars: &mut std::iter::Peekable<std::vec::IntoIter<u8>>, bus: &mut Bus| {
+            if chars.peek() == Some(&b'*') {
+                chars.next();
+                next_arg += 1;
+                return Some((arg(bus, next_arg - 1) as usize).min(MAX_PRINTF_FIELD));
+            }
+
+            let mut n: Option<usize> = None;
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                let digit = usize::from(d - b'0');
+                n = Some(n.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                chars.next();
+            }
+            n.map(|n| n.min(MAX_PRINTF_FIELD))
+        };
+        let width = number(&mut chars, bus).unwrap_or(0);
+        let precision = if chars.peek() == Some(&b'.') {
+            chars.next();
+            Some(number(&mut chars, bus).unwrap_or(0))
+        } else {
+            None
+        };
+        while matches!(chars.peek(), Some(b'l' | b'h')) {
+            chars.next();
+        }
+
+        let Some(conversion) = chars.next() else {
+            break;
+        };
+        if conversion == b'%' {
+            out.push(b'%');
+            continue;
+        }
+
+        let value = arg(bus, next_arg);
+        next_arg += 1;
+        let (prefix, mut digits): (&[u8], Vec<u8>) = match conversion {
+            b'd' | b'i' => {
+                let v = value as i32;
+                let prefix: &[u8] = match (v < 0, sign) {
+                    (true, _) => b"-",
+                    (false, Some(b'+')) => b"+",
+                    (false, Some(_)) => b" ",
+                    (false, None) => b"",
+                };
+                (prefix, v.unsigned_abs().to_string().into_bytes())
+            }
+            b'u' => (b"", value.to_string().into_bytes()),
+            b'x' => (if alternate { b"0x" } else { b"" }, format!("{:x}", value).into_bytes()),
+            b'X' => (if alternate { b"0X" } else { b"" }, format!("{:X}", value).into_bytes()),
+            b'p' => (b"", format!("{:08x}", value).into_bytes()),
+            b'o' => (if alternate { b"0" } else { b"" }, format!("{:o}", value).into_bytes()),
+            b'c' => (b"", vec![value as u8]),
+            b's' => {
+                let mut s = if value == 0 { b"(null)".to_vec() } else { read_string(bus, value) };
+                if let Some(p) = precision {
+                    s.truncate(p);
+                }
+                (b"", s)
+            }
+            other => {
+                // Unknown conversion, printed as is
+                out.extend([b'%', other]);
+                continue;
+            }
+        };
+
+        if let Some(p) = precision.filter(|_| !matches!(conversion, b's' | b'c')) {
+            let zeros = p.saturating_sub(digits.len());
+            if zeros > 0 {
+                digits = std::iter::repeat_n(b'0', zeros).chain(digits).collect();
+            }
+        }
+
+        let len = prefix.len() + digits.len();
+        let pad = width.saturating_sub(len);
+        if left {
+            out.extend_from_slice(prefix);
+            out.extend(digits);
+            out.extend(std::iter::repeat_n(b' ', pad));
+        } else if zero && precision.is_none() && conversion != b's' {
+            out.extend_from_slice(prefix);
+            out.extend(std::iter::repeat_n(b'0', pad));
+            out.extend(digits);
+        } else {
+            out.extend(std::iter::repeat_n(b' ', pad));
+            out.extend_from_slice(prefix);
+            out.extend(digits);
+        }
+    }
+
+    for &b in &out {
+        bus.tty.push_char(char::from(b));
+    }
+
+    out.len() as u32
+}
@@ -0,0 +1,113 @@
+//! The controllers, read by the kernel at each vertical blanking once `StartPAD` (or the older
+//! `PAD_init`) was called
+
+use serde::{Deserialize, Serialize};
+use crate::ps1::psx::bus::Bus;
+use super::{arg, transfer, I_MASK};
+
+/// Longest reply read from a controller, the analog ones send 4 halfwords after the buttons
+const MAX_REPLY: usize = 34;
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct Pads {
+    /// Buffer and its size given to `InitPAD`, for each port
+    buffers: [(u32, u32); 2],
+    /// Set by `StartPAD`
+    started: bool,
+    /// `ChangeClearPAD`: the interrupt is acknowledged once the controllers are read. Turned off
+    /// by the libraries handling the VBlank interrupt themselves.
+    pub(super) clear_irq: bool,
+    /// Word given to `PAD_init`, 0 until it's called
+    legacy_buffer: u32,
+    /// Buttons held on both ports for `PAD_dr`, set bits for the pressed ones
+    legacy_buttons: u32,
+}
+
+impl Pads {
+    pub fn new() -> Pads {
+        Pads {
+            buffers: [(0, 0); 2],
+            started: false,
+            clear_irq: true,
+            legacy_buffer: 0,
+            legacy_buttons: 0,
+        }
+    }
+
+    /// `InitPAD(buf1, size1, buf2, size2)`
+    pub fn init(&mut self, bus: &mut Bus) -> u32 {
+        self.buffers = [(arg(bus, 0), arg(bus, 1)), (arg(bus, 2), arg(bus, 3))];
+        self.started = false;
+
+        1
+    }
+
+    pub fn start(&mut self, bus: &mut Bus) -> u32 {
+        self.started = true;
+        // The reads happen on the VBlank interrupt
+        let mask = bus.load::<u32>(I_MASK);
+        bus.store::<u32>(I_MASK, mask | 1);
+
+        1
+    }
+
+    pub fn stop(&mut self) -> u32 {
+        self.started = false;
+
+        1
+    }
+
+    /// `PAD_init(type, buf)`: the buttons of both ports in a word, read and started at once
+    pub fn legacy_init(&mut self, bus: &mut Bus) -> u32 {
+        self.legacy_buffer = arg(bus, 1);
+        if self.legacy_buffer != 0 {
+            bus.store::<u32>(self.legacy_buffer, 0);
+        }
+        self.start(bus);
+
+        2
+    }
+
+    /// `PAD_dr()`
+    pub fn legacy_buttons(&self) -> u32 {
+        self.legacy_buttons
+    }
+
+    /// Read the controllers into the buffers of the game, at the VBlank interrupt
+    pub fn poll(&mut self, bus: &mut Bus) {
+        if !self.started {
+            return;
+        }
+
+        let mut command = [0; MAX_REPLY];
+        command[..2].copy_from_slice(&[0x01, 0x42]);
+
+        let mut legacy_buttons = 0;
+        for port in 0..2 {
+            let reply = transfer(bus.pad_memcard.gamepads_mut()[port], &command);
+            let connected = reply.len() > 3;
+
+            // Status, the ID then the data, without the 0x5a sent in between
+            let mut data = vec![if connected { 0x00 } else { 0xff }];
+            if connected {
+                data.push(reply[1]);
+                data.extend_from_slice(&reply[3..]);
+
+                let buttons = u16::from_le_bytes([reply[3], reply.get(4).copied().unwrap_or(0xff)]);
+                legacy_buttons |= u32::from(!buttons) << (port * 16);
+            }
+
+            let (buffer, size) = self.buffers[port];
+            if buffer != 0 {
+                for (i, &b) in data.iter().take(size as usize).enumerate() {
+                    bus.store::<u8>(buffer + i as u32, b);
+                }
+            }
+        }
+
+        self.legacy_buttons = legacy_buttons;
+        if self.legacy_buffer != 0 {
+            bus.store::<u32>(self.legacy_buffer, legacy_buttons);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use crate::ps1::psx::bios::bios::BIOS_SIZE;
 use crate::ps1::hash::sha;
 
@@ -12,6 +13,34 @@ pub enum Region {
     Europe,
 }
 
+impl Region {
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Japan => "Japan",
+            Region::NorthAmerica => "North America",
+            Region::Europe => "Europe",
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<crate::ps1::psx::cd::disc::Region> for Region {
+    fn from(region: crate::ps1::psx::cd::disc::Region) -> Region {
+        use crate::ps1::psx::cd::disc::Region as DiscRegion;
+
+        match region {
+            DiscRegion::Japan => Region::Japan,
+            DiscRegion::NorthAmerica => Region::NorthAmerica,
+            DiscRegion::Europe => Region::Europe,
+        }
+    }
+}
+
 pub struct Metadata {
     pub sha256: [u8; 32],
     pub version_major: u8,
@@ -302,3 +331,13 @@ pub static DATABASE: [Metadata; 24] = [
         animation_jump_hook: None,
     },
 ];
+
+/// The BIOS made up by `hle`, which isn't a dump: not in the database, so that no file is ever
+/// mistaken for it. Its SHA-256 is the one of the generated ROM, what the savestates check.
+pub static HLE: Metadata = Metadata {
+    sha256: [0; 32],
+    version_major: 0,
+    version_minor: 0,
+    region: Region::NorthAmerica,
+    animation_jump_hook: None,
+};
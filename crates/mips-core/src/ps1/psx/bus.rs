@@ -1,9 +1,17 @@
 use std::option::Option;
 use std::cmp::min;
+use std::mem;
 use log::{info, warn};
+use crate::audio::AudioChunk;
+use crate::stats::FrameStats;
 use crate::error::MipsResult;
-use crate::ps1::psx::addressable::{AccessWidth, Addressable};
+use crate::ps1::Ps1Error;
+use crate::input::{Button, ButtonState};
+use crate::exec_watch::ExecWatch;
+use crate::cpu_trace::CpuTrace;
+use mips_r3000::addressable::{AccessWidth, Addressable};
 use crate::ps1::psx::bios::bios::Bios;
+use crate::ps1::psx::bios::hle::Hle;
 use crate::ps1::psx::graphics::gpu::{Gpu, VideoStandard};
 use crate::ps1::psx::processor::{cpu, irq, ClockCycle};
 use crate::ps1::psx::memory::scratch_pad::ScratchPad;
@@ -13,19 +21,23 @@ use crate::ps1::psx::sync::Synchronizer;
 use crate::ps1::psx::{cd, mdec, pad_memcard, sync, timers, xmem};
 use crate::ps1::psx::cd::disc;
 use crate::ps1::psx::cd::disc::Disc;
-use crate::ps1::psx::exe::Exe;
+use crate::ps1::psx::exe::{self, Exe};
 use crate::ps1::psx::graphics::gpu;
-use crate::ps1::psx::graphics::rasterizer::handle::Frame;
+use mips_psx_gpu::handle::Frame;
 use crate::ps1::psx::mdec::MDec;
 use crate::ps1::psx::memory::dma::Dma;
 use crate::ps1::psx::memory::{dma, map};
+use crate::ps1::psx::memory::map::Region;
 use crate::ps1::psx::pad_memcard::PadMemCard;
 use crate::ps1::psx::processor::cpu::Cpu;
+use crate::ps1::psx::processor::dynarec::Dynarec;
 use crate::ps1::psx::processor::gte::Gte;
 use crate::ps1::psx::sound::spu;
 use crate::ps1::psx::timers::Timers;
 use crate::ps1::psx::tty::Tty;
+use crate::ps1::settings::cpu::CpuClock;
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Bus {
     pub cpu: Cpu,
     pub cop0: Cop0,
@@ -51,19 +63,52 @@ pub struct Bus {
     /// When this variable is `true` the CPU is stopped for DMA operation
     cpu_stalled_for_dma: bool,
     pub frame_done: bool,
+    /// Set to stop the emulation before the end of the frame. The next `update` picks up where
+    /// this one stopped.
+    #[serde(skip)]
+    pub(crate) debug_break: bool,
+    /// Set up by the frontend, not part of the console state
+    #[serde(skip)]
     pub exe: Option<Exe>,
-    tty: Tty
+    #[serde(skip)]
+    pub exec_watch: Option<Box<ExecWatch>>,
+    #[serde(skip)]
+    pub cpu_trace: Option<Box<CpuTrace>>,
+    /// Runs the CPU instead of the interpreter when set, see `set_dynarec`
+    #[serde(skip)]
+    pub(crate) dynarec: Option<Box<Dynarec>>,
+    /// Counters for the frame being emulated
+    #[serde(skip)]
+    pub(crate) stats: FrameStats,
+    /// Length of the last frame, used to place timed inputs within the next one
+    frame_cycles: ClockCycle,
+    /// Speed of the CPU, see `tick_cpu`. Set up by the frontend.
+    #[serde(skip)]
+    cpu_clock: CpuClock,
+    /// What's left of the CPU cycles `tick_cpu` didn't turn into whole cycles of the console, in
+    /// hundredths of a CPU cycle
+    #[serde(default)]
+    cpu_cycle_remainder: ClockCycle,
+    pub(crate) tty: Tty,
+    /// Kernel emulated in place of a BIOS dump, see `Bios::new_hle`
+    #[serde(default)]
+    pub hle: Option<Box<Hle>>,
 }
 
 impl Bus {
 
     pub fn new(bios: Bios, cdc_firmware: [u8; cd::CDC_ROM_SIZE], disc: Option<disc::Disc>) -> MipsResult<Bus> {
         let cd = cd::CdInterface::new(disc, cdc_firmware)?;
-        
+
+        Ok(Bus::with_cd(bios, cd))
+    }
+
+    pub(crate) fn with_cd(bios: Bios, cd: cd::CdInterface) -> Bus {
         let mut xmem = xmem::XMemory::new();
         xmem.set_bios(bios.rom());
+        let hle = bios.is_hle().then(|| Box::new(Hle::new()));
 
-        Ok(Bus {
+        Bus {
             cpu: Cpu::new(),
             cop0: Cop0::new(),
             gte: Gte::new(),
@@ -85,17 +130,98 @@ impl Bus {
             dma_timing_penalty: 0,
             cpu_stalled_for_dma: false,
             frame_done: false,
+            debug_break: false,
             exe: None,
+            exec_watch: None,
+            cpu_trace: None,
+            dynarec: None,
+            stats: FrameStats::default(),
+            frame_cycles: cpu::CPU_FREQ_HZ / 60,
+            cpu_clock: CpuClock::NATIVE,
+            cpu_cycle_remainder: 0,
             tty: Tty::new(),
-        })
+            hle,
+        }
     }
 
-    pub fn insert_disc(&mut self, disc: Disc)  {
-        self.gpu.reset(disc.region().video_standard());
+    /// Swap the disc in the drive for `disc`, like a player opening the shell. The rest of the
+    /// console keeps running: the video standard stays the one of the disc it started with.
+    pub fn insert_disc(&mut self, disc: Disc) {
         self.cd.load_disc(disc);
     }
 
+    /// Replace the console state with `saved`, deserialized from a savestate. The parts that
+    /// aren't saved (BIOS, CDC firmware, disc image, connected devices...) are taken from the
+    /// running console. The state is left untouched if the savestate was made with another BIOS
+    /// or another disc.
+    pub fn load_state(&mut self, mut saved: Bus) -> MipsResult<()> {
+        saved.xmem.copy_bios(&self.xmem)?;
+        saved.cd.cdc.copy_rom(&self.cd.cdc);
+
+        // Checked before taking the disc out, `set_disc` would refuse it but the drive would be
+        // left open
+        if let (Some(current), Some(saved)) = (self.cd.disc(), saved.cd.disc())
+            && current.serial_number() != saved.serial_number()
+        {
+            return Err(Ps1Error::BadSerialNumber {
+                expected: current.serial_number(),
+                got: saved.serial_number(),
+            }.into());
+        }
+
+        let disc = self.cd.cdc.take_disc();
+        saved.cd.cdc.set_disc(disc).map_err(|(e, _)| e)?;
+
+        saved.pad_memcard = {
+            let mut pad_memcard = mem::replace(&mut self.pad_memcard, PadMemCard::new());
+            pad_memcard.load_state(saved.pad_memcard);
+            pad_memcard
+        };
+
+        saved.debug_break = false;
+        saved.exe = self.exe.take();
+        saved.exec_watch = self.exec_watch.take();
+        saved.cpu_trace = self.cpu_trace.take();
+        // The RAM changed under the translated code
+        saved.dynarec = self.dynarec.take();
+        if let Some(dynarec) = &mut saved.dynarec {
+            dynarec.clear(&mut saved.xmem);
+        }
+        // Whether the lines are captured is up to the frontend
+        saved.tty = mem::replace(&mut self.tty, Tty::new());
+        // So are the vertex precision, the widescreen hack and the SPU interpolation and mix
+        *saved.gte.pgxp_mut() = mem::take(self.gte.pgxp_mut());
+        saved.gte.set_widescreen(self.gte.is_widescreen());
+        saved.spu.set_interpolation(self.spu.interpolation());
+        saved.spu.set_mix(self.spu.mix());
+        saved.cpu_clock = self.cpu_clock;
+
+        *self = saved;
+
+        Ok(())
+    }
+
+    /// Run the CPU with the dynarec instead of the interpreter. Stays on the interpreter if the
+    /// host can't run the dynarec.
+    pub fn set_dynarec(&mut self, enabled: bool) {
+        if !enabled {
+            self.dynarec = None;
+            self.xmem.clear_code_pages();
+        } else if self.dynarec.is_none() {
+            self.dynarec = Dynarec::new().map(Box::new);
+        }
+    }
+
+    /// Have the dynarec translate the GTE instructions or leave them to the interpreter, see
+    /// `Dynarec::set_translate_gte`
+    pub fn set_dynarec_gte(&mut self, translate: bool) {
+        if let Some(dynarec) = &mut self.dynarec {
+            dynarec.set_translate_gte(&mut self.xmem, translate);
+        }
+    }
+
     /// Returns true if the instruction cache is enabled in the CACHE_CONTROL register
+    #[inline]
     pub(crate) fn icache_enabled(&self) -> bool {
         self.cache_control & 0x800 != 0
     }
@@ -105,45 +231,118 @@ impl Bus {
         self.cache_control & 4 != 0
     }
 
+    #[inline]
     pub fn tick(&mut self, cycles: ClockCycle) {
         self.cycles += cycles;
     }
 
-    pub fn update(&mut self) {
+    /// Advance the clock by `cycles` of the CPU core (instructions, cache refills), shorter or
+    /// longer than the ones of the rest of the console when it's overclocked or underclocked. The
+    /// bus accesses still take the console's time.
+    #[inline]
+    pub fn tick_cpu(&mut self, cycles: ClockCycle) {
+        if self.cpu_clock.is_native() {
+            self.cycles += cycles;
+            return;
+        }
+
+        let percent = ClockCycle::from(self.cpu_clock.percent());
+        let hundredths = self.cpu_cycle_remainder + cycles * 100;
+        self.cycles += hundredths / percent;
+        self.cpu_cycle_remainder = hundredths % percent;
+    }
+
+    /// True if the CPU runs at the speed of the console: `tick_cpu` is a plain `tick`
+    #[inline]
+    pub(crate) fn cpu_clock_is_native(&self) -> bool {
+        self.cpu_clock.is_native()
+    }
+
+    /// Run the CPU at `clock`, see `CpuClock`
+    pub fn set_cpu_clock(&mut self, clock: CpuClock) {
+        self.cpu_clock = clock;
+        self.cpu_cycle_remainder = 0;
+    }
+
+    pub fn update(&mut self) -> FrameStats {
+        let start = self.cycles;
+
         self.frame_done = false;
         while !self.frame_done {
             if self.cpu_stalled_for_dma {
                 // Fast forward to the next event
                 self.cycles = self.sync.first_event();
             } else {
-                while !sync::is_event_pending(self) {
-                    cpu::run_next_instruction(self);
+                cpu::run_until_event(self);
+
+                if self.debug_break {
+                    self.debug_break = false;
+                    return self.take_stats(start);
                 }
             }
 
             sync::handle_events(self);
         }
 
+        if let Some(watch) = &mut self.exec_watch {
+            watch.next_frame();
+        }
+
+        // Inputs scheduled after the last poll of the frame wait for the next one
+        self.pad_memcard.latch_inputs(ClockCycle::MAX);
+        self.frame_cycles = self.cycles - start;
+
+        let stats = self.take_stats(start);
+
         // Rebase the event counters relative to the cycle_counter to make sure they don't overflow
         sync::rebase_counters(self);
+
+        stats
     }
 
-    pub fn take_frame(&mut self) -> Option<Frame> {
-        self.gpu.take_frame()
+    /// Advance the sound by one frame without running the rest of the console, for the frontend to
+    /// keep playing something while the game is paused. See `spu::run_frozen`.
+    pub fn update_paused(&mut self) {
+        // The console didn't run, that's not for the stats of the next frame
+        let audio_samples = self.stats.audio_samples;
+
+        spu::run_frozen(self, self.frame_cycles);
+
+        self.stats.audio_samples = audio_samples;
     }
 
-    /// Get pending audio samples since the last call to `clear_audio_samples`
-    pub fn get_audio_samples(&mut self) -> &[i16] {
-        spu::get_samples(self)
+    /// Press or release `button` on the gamepad in `port` when the game polls it after `position`
+    /// (0 to 1) of the next frame
+    pub fn schedule_input(&mut self, position: f64, port: usize, state: ButtonState, button: Button) {
+        let offset = (position.clamp(0.0, 1.0) * self.frame_cycles as f64) as ClockCycle;
+
+        self.pad_memcard.schedule_input(self.cycles + offset, port, state, button);
     }
 
-    /// Clear any pending audio samples. This must be called at least once per frame.
-    pub fn clear_audio_samples(&mut self) {
-        spu::clear_samples(self)
+    /// Counters since the last call, `start` is the cycle counter when the frame started
+    fn take_stats(&mut self, start: ClockCycle) -> FrameStats {
+        let mut stats = mem::take(&mut self.stats);
+
+        stats.cycles = (self.cycles - start) as u64;
+        stats.pc = self.cpu.current_pc();
+        stats.duplicated = stats.draw_calls == 0 && stats.vram_transfers == 0;
+
+        stats
+    }
+
+    pub fn take_frame(&mut self) -> Option<Frame> {
+        self.gpu.take_frame()
+    }
+
+    /// Take the audio samples generated since the last call. This must be called at least once per
+    /// frame.
+    pub fn take_audio(&mut self) -> AudioChunk {
+        spu::take_samples(self)
     }
 
     /// Execute a memory read and return the value alongside with the number of cycles necessary for
     /// the load to complete;
+    #[inline]
     pub(crate) fn load<T: Addressable>(&mut self, address: u32) -> T {
         let abs_addr = map::mask_region(address);
 
@@ -157,119 +356,117 @@ impl Bus {
             return self.xmem.ram_load(offset);
         }
 
-        if let Some(offset) = map::BIOS.contains(abs_addr) {
+        self.load_slow(abs_addr)
+    }
+
+    /// Loads from anything but the RAM, kept out of line so that the RAM path can be inlined
+    #[inline(never)]
+    fn load_slow<T: Addressable>(&mut self, abs_addr: u32) -> T {
+        let region = map::region(abs_addr);
+        let offset = abs_addr - region.base();
+
+        match region {
             // XXX Mednafen doesn't add any penalty for BIOS read, which sounds wrong. It's
             // probably not a common-enough occurence to matter
-            return self.xmem.bios_load(offset);
-        }
+            Region::Bios => self.xmem.bios_load(offset),
+            // Normally handled directly in `CpuBus::fast_load`
+            Region::ScratchPad => self.scratch_pad.load(offset),
+            Region::Spu => {
+                if T::width() == AccessWidth::Word {
+                    self.tick(36);
+                } else {
+                    self.tick(16);
+                }
 
-        if let Some(offset) = map::SPU.contains(abs_addr) {
-            if T::width() == AccessWidth::Word {
-                self.tick(36);
-            } else {
-                self.tick(16);
+                spu::load(self, offset)
             }
-
-            return spu::load(self, offset);
-        }
-
-        if let Some(offset) = map::DMA.contains(abs_addr) {
-            self.tick(1);
-            return dma::load(self, offset);
-        }
-
-        if let Some(offset) = map::TIMERS.contains(abs_addr) {
-            self.tick(1);
-            return timers::load(self, offset);
-        }
-
-        if let Some(offset) = map::GPU.contains(abs_addr) {
-            self.tick(1);
-            return gpu::load(self, offset);
-        }
-
-        if let Some(offset) = map::MDEC.contains(abs_addr) {
-            self.tick(1);
-            return mdec::load(self, offset);
-        }
-
-        if let Some(offset) = map::PAD_MEMCARD.contains(abs_addr) {
-            self.tick(1);
-            return pad_memcard::load(self, offset);
-        }
-
-        if let Some(offset) = map::CDROM.contains(abs_addr) {
-            self.tick(6 * T::width() as i32);
-            return cd::load(self, offset);
-        }
-
-        if let Some(off) = map::IRQ_CONTROL.contains(abs_addr) {
-            self.tick(1);
-
-            let v = match off {
-                0 => u32::from(irq::status(self)),
-                4 => u32::from(irq::mask(self)),
-                _ => panic!("Unhandled IRQ load at address {:08x}", abs_addr),
-            };
-
-            // Since the IRQ registers are only 16bit wide the high 32bits are undefined. In
-            // practice the high bits appear to maintain the value of the previous load.
-            //
-            // We could try to emulate this behaviour by keeping track of the previously loaded
-            // value and use that but it's unclear if any game relies on this edge case.
-            //
-            // So why 0x1f80 in the high bits?
-            //
-            // In the BIOS IRQ handler just before loading the value of the mask the code loads the
-            // base address of the IRQ handler (0x1f801070) then does an LW of the mask (base + 4).
-            // In this case the load will return 0x1f80 in the high 16 bits.
-            //
-            // So any code that does "load IRQ register address -> load IRQ register" in sequence
-            // will have 1f80 in the high bits, so it's a sane default.
-            return Addressable::from_u32(v | 0x1f80_0000);
-        }
-
-        if map::EXPANSION_1.contains(abs_addr).is_some() {
-            // No expansion implemented. Returns full ones when no
-            // expansion is present
-            return Addressable::from_u32(!0);
-        }
-
-        if map::CACHE_CONTROL.contains(abs_addr).is_some() {
-            if T::width() != AccessWidth::Word {
-                panic!("Unhandled cache control access");
+            Region::Dma => {
+                self.tick(1);
+                dma::load(self, offset)
             }
-
-            return Addressable::from_u32(self.cache_control);
-        }
-
-        if let Some(offset) = map::MEM_CONTROL.contains(abs_addr) {
-            if T::width() != AccessWidth::Word {
-                panic!("Unhandled MEM_CONTROL {:?} access", T::width());
+            Region::Timers => {
+                self.tick(1);
+                timers::load(self, offset)
+            }
+            Region::Gpu => {
+                self.tick(1);
+                gpu::load(self, offset)
+            }
+            Region::MDec => {
+                self.tick(1);
+                mdec::load(self, offset)
+            }
+            Region::PadMemCard => {
+                self.tick(1);
+                pad_memcard::load(self, offset)
+            }
+            Region::CdRom => {
+                self.tick(6 * T::width() as i32);
+                cd::load(self, offset)
             }
+            Region::IrqControl => {
+                self.tick(1);
+
+                let v = match offset {
+                    0 => u32::from(irq::status(self)),
+                    4 => u32::from(irq::mask(self)),
+                    _ => panic!("Unhandled IRQ load at address {:08x}", abs_addr),
+                };
+
+                // Since the IRQ registers are only 16bit wide the high 32bits are undefined. In
+                // practice the high bits appear to maintain the value of the previous load.
+                //
+                // We could try to emulate this behaviour by keeping track of the previously loaded
+                // value and use that but it's unclear if any game relies on this edge case.
+                //
+                // So why 0x1f80 in the high bits?
+                //
+                // In the BIOS IRQ handler just before loading the value of the mask the code loads
+                // the base address of the IRQ handler (0x1f801070) then does an LW of the mask
+                // (base + 4). In this case the load will return 0x1f80 in the high 16 bits.
+                //
+                // So any code that does "load IRQ register address -> load IRQ register" in
+                // sequence will have 1f80 in the high bits, so it's a sane default.
+                Addressable::from_u32(v | 0x1f80_0000)
+            }
+            // No expansion implemented. Returns full ones when no expansion is present
+            Region::Expansion1 => Addressable::from_u32(!0),
+            Region::CacheControl => {
+                if T::width() != AccessWidth::Word {
+                    panic!("Unhandled cache control access");
+                }
 
-            let index = (offset >> 2) as usize;
+                Addressable::from_u32(self.cache_control)
+            }
+            Region::MemControl => {
+                if T::width() != AccessWidth::Word {
+                    panic!("Unhandled MEM_CONTROL {:?} access", T::width());
+                }
 
-            return Addressable::from_u32(self.mem_control[index]);
-        }
+                let index = (offset >> 2) as usize;
 
-        if map::RAM_SIZE.contains(abs_addr).is_some() {
-            if T::width() != AccessWidth::Word {
-                panic!("Unhandled RAM_SIZE access");
+                Addressable::from_u32(self.mem_control[index])
             }
+            Region::RamSize => {
+                if T::width() != AccessWidth::Word {
+                    panic!("Unhandled RAM_SIZE access");
+                }
 
-            return Addressable::from_u32(self.ram_size);
-        }
-
-        if cfg!(feature = "debugger") {
-            warn!("Unhandled load at address {:08x}", abs_addr);
-            Addressable::from_u32(0xdeaddead)
-        } else {
-            panic!("Unhandled load at address {:08x}", abs_addr);
+                Addressable::from_u32(self.ram_size)
+            }
+            _ => {
+                if cfg!(feature = "debugger") {
+                    warn!("Unhandled load at address {:08x}", abs_addr);
+                    Addressable::from_u32(0xdeaddead)
+                } else {
+                    panic!("Unhandled load at address {:08x}", abs_addr);
+                }
+            }
         }
     }
 
     /// Decode `address` and perform the store to the target module
+    #[inline]
     pub(crate) fn store<T: Addressable>(&mut self, address: u32, val: T) {
         let abs_addr = map::mask_region(address);
 
@@ -279,135 +476,95 @@ impl Bus {
         }
 
         if let Some(offset) = map::SCRATCH_PAD.contains(abs_addr) {
-            return self.scratch_pad.store(offset, val);
-        }
-
-        if let Some(offset) = map::SPU.contains(abs_addr) {
-            spu::store(self, offset, val);
+            self.scratch_pad.store(offset, val);
             return;
         }
 
-        if let Some(offset) = map::DMA.contains(abs_addr) {
-            dma::store(self, offset, val);
-            return;
-        }
-
-        if let Some(offset) = map::TIMERS.contains(abs_addr) {
-            timers::store(self, offset, val);
-            return;
-        }
-
-        if let Some(offset) = map::GPU.contains(abs_addr) {
-            gpu::store(self, offset, val);
-            return;
-        }
-
-        if let Some(offset) = map::MDEC.contains(abs_addr) {
-            mdec::store(self, offset, val);
-            return;
-        }
-
-        if let Some(offset) = map::PAD_MEMCARD.contains(abs_addr) {
-            pad_memcard::store(self, offset, val);
-            return;
-        }
-
-        if let Some(offset) = map::CDROM.contains(abs_addr) {
-            cd::store(self, offset, val);
-            return;
-        }
+        self.store_slow(abs_addr, val)
+    }
 
-        if let Some(offset) = map::IRQ_CONTROL.contains(abs_addr) {
-            match offset {
+    /// Stores to the hardware registers, kept out of line so that the memory accesses can be
+    /// inlined
+    #[inline(never)]
+    fn store_slow<T: Addressable>(&mut self, abs_addr: u32, val: T) {
+        let region = map::region(abs_addr);
+        let offset = abs_addr - region.base();
+
+        match region {
+            Region::Spu => spu::store(self, offset, val),
+            Region::Dma => dma::store(self, offset, val),
+            Region::Timers => timers::store(self, offset, val),
+            Region::Gpu => gpu::store(self, offset, val),
+            Region::MDec => mdec::store(self, offset, val),
+            Region::PadMemCard => pad_memcard::store(self, offset, val),
+            Region::CdRom => cd::store(self, offset, val),
+            Region::IrqControl => match offset {
                 0 => irq::ack(self, val.as_u16()),
                 4 => irq::set_mask(self, val.as_u16()),
                 _ => panic!("Unhandled IRQ store at address {:08x}", abs_addr),
-            }
-
-            return;
-        }
-
-        if let Some(offset) = map::EXPANSION_1.contains(abs_addr) {
-            warn!("Unhandled write to expansion 1 register {:x}", offset);
-            return;
-        }
-
-        if let Some(offset) = map::MEM_CONTROL.contains(abs_addr) {
-            if T::width() != AccessWidth::Word {
-                panic!("Unhandled MEM_CONTROL {:?} access", T::width());
-            }
-
-            let val = val.as_u32();
-
-            // We don't actually implement those registers, we assume that all BIOSes and games are
-            // going to use the default memory configuration. I'm not aware of any game that breaks
-            // this assumption. Still, we can catch any attempt at using a non-standard
-            // configuration and report an error.
-            match offset {
-                // Expansion 1 base address
-                0 => {
-                    if val != 0x1f00_0000 {
-                        panic!("Bad expansion 1 base address: 0x{:08x}", val);
-                    }
-                }
-                // Expansion 2 base address
-                4 => {
-                    if val != 0x1f80_2000 {
-                        panic!("Bad expansion 2 base address: 0x{:08x}", val);
-                    }
+            },
+            Region::Expansion1 => warn!("Unhandled write to expansion 1 register {:x}", offset),
+            Region::MemControl => {
+                if T::width() != AccessWidth::Word {
+                    panic!("Unhandled MEM_CONTROL {:?} access", T::width());
                 }
-                _ => (),
-            }
 
-            let index = (offset >> 2) as usize;
-            self.mem_control[index] = val;
-            return;
-        }
+                let val = val.as_u32();
+
+                // We don't actually implement those registers, we assume that all BIOSes and games
+                // are going to use the default memory configuration. I'm not aware of any game that
+                // breaks this assumption. Still, we can catch any attempt at using a non-standard
+                // configuration and report an error.
+                match offset {
+                    // Expansion 1 base address
+                    0 if val != 0x1f00_0000 => panic!("Bad expansion 1 base address: 0x{:08x}", val),
+                    // Expansion 2 base address
+                    4 if val != 0x1f80_2000 => panic!("Bad expansion 2 base address: 0x{:08x}", val),
+                    _ => (),
+                }
 
-        if map::CACHE_CONTROL.contains(abs_addr).is_some() {
-            if T::width() != AccessWidth::Word {
-                panic!("Unhandled cache control access");
+                let index = (offset >> 2) as usize;
+                self.mem_control[index] = val;
             }
+            Region::CacheControl => {
+                if T::width() != AccessWidth::Word {
+                    panic!("Unhandled cache control access");
+                }
 
-            self.cache_control = val.as_u32();
-
-            return;
-        }
-
-        if map::RAM_SIZE.contains(abs_addr).is_some() {
-            if T::width() != AccessWidth::Word {
-                panic!("Unhandled RAM_SIZE access");
+                self.cache_control = val.as_u32();
             }
+            Region::RamSize => {
+                if T::width() != AccessWidth::Word {
+                    panic!("Unhandled RAM_SIZE access");
+                }
 
-            self.ram_size = val.as_u32();
-            return;
-        }
-
-        if let Some(offset) = map::EXPANSION_2.contains(abs_addr) {
-            if offset == 0x23 || offset == 0x80 {
-                self.tty.push_char(val.as_u8() as char);
+                self.ram_size = val.as_u32();
             }
-            else if offset == 0x41 || offset == 0x42 {
-                let post_code = val.as_u32() & 0x0F;
-                info!("BIOS POST status: {:x}", post_code);
-                if post_code == 0x07 {
-                    //TODO: sideload exe
+            Region::Expansion2 => {
+                if offset == 0x23 || offset == 0x80 {
+                    self.tty.push_char(val.as_u8() as char);
+                }
+                else if offset == 0x41 || offset == 0x42 {
+                    let post_code = val.as_u32() & 0x0F;
+                    info!("BIOS POST status: {:x}", post_code);
+                    // The kernel is initialized and the BIOS is about to start the shell
+                    if post_code == 0x07 {
+                        exe::sideload(self);
+                    }
+                }
+                else if offset == 0x70 {
+                    info!("BIOS POST2 status: {:0x}", val.as_u32() & 0x0F);
+                }
+                else {
+                    warn!("Unhandled write to expansion 2 register {:x}", offset);
                 }
             }
-            else if offset == 0x70 {
-                info!("BIOS POST2 status: {:0x}", val.as_u32() & 0x0F);
-            }
-            else {
-                warn!("Unhandled write to expansion 2 register {:x}", offset);
-            }
-            return;
+            _ => panic!(
+                "Unhandled store at address {:08x} (val=0x{:08x})",
+                abs_addr,
+                val.as_u32()
+            ),
         }
-
-        panic!(
-            "Unhandled store at address {:08x} (val=0x{:08x})",
-            abs_addr,
-            val.as_u32()
-        );
     }
 
     pub fn set_dma_timing_penalty(&mut self, penalty: ClockCycle) {
@@ -2,6 +2,7 @@ mod cdc;
 pub mod disc;
 pub mod iso9660;
 
+use std::collections::VecDeque;
 use std::ops::{Deref, DerefMut};
 use cdimage::DiscPosition;
 use log::info;
@@ -9,12 +10,16 @@ use disc::Disc;
 use crate::error::{MipsError, MipsResult};
 use crate::ps1::hash::sha::sha256;
 use crate::ps1::Ps1Error;
-use crate::ps1::psx::addressable::Addressable;
+use mips_r3000::addressable::Addressable;
 use crate::ps1::psx::bus::Bus;
 use crate::ps1::psx::cd;
-use crate::ps1::psx::processor::irq;
+use crate::ps1::psx::processor::irq::{Interrupt, IrqLines};
+use crate::ps1::psx::sound::spu;
+use crate::ps1::psx::sync::{self, Scheduler};
 use crate::ps1::util::ds::box_slice::BoxSlice;
 
+const CDSYNC: sync::SyncToken = sync::SyncToken::CdRom;
+
 pub use cdc::CdcState;
 pub use cdc::MC68HC05_ROM_DUMP_SIZE as CDC_ROM_SIZE;
 
@@ -24,10 +29,12 @@ pub struct CdInterface {
     /// Counter to prevent overclocking the CDC when the MDEC is active (since it's probably
     /// streaming data from the CD)
     mdec_busy_cooldown: u16,
+    /// Audio output of the controller the SPU hasn't mixed yet
+    audio: VecDeque<[i16; 2]>,
 }
 
 impl CdInterface {
-    pub fn new(disc: Option<Disc>, mut cdc_rom: [u8; cd::CDC_ROM_SIZE]) -> MipsResult<CdInterface> {
+    pub fn new(disc: Option<Disc>, cdc_rom: [u8; cd::CDC_ROM_SIZE]) -> MipsResult<CdInterface> {
         if !cfg!(test) {
             // Check that we get the expected firmware. Not all CDC firmware versions will be
             // compatible with this code since there have been significant changes between
@@ -41,6 +48,20 @@ impl CdInterface {
             }
         }
 
+        Ok(CdInterface::new_unchecked(disc, cdc_rom))
+    }
+
+    /// Drive without a firmware dump: the controller is nothing but `BRA *` loops, which is also
+    /// where all the vectors point to. The host registers work but the commands are never
+    /// answered, which is enough for the BIOS shell and the PS-EXEs.
+    pub(crate) fn new_without_firmware() -> CdInterface {
+        let cdc_rom = std::array::from_fn(|i| if i % 2 == 0 { 0x20 } else { 0xfe });
+
+        CdInterface::new_unchecked(None, cdc_rom)
+    }
+
+    /// Same as `new` without checking the firmware
+    fn new_unchecked(disc: Option<Disc>, mut cdc_rom: [u8; cd::CDC_ROM_SIZE]) -> CdInterface {
         let region = disc
             .as_ref()
             .map(|d| d.region())
@@ -60,10 +81,11 @@ impl CdInterface {
 
         let cdc = cdc::Cdc::new(&cdc_rom, disc);
 
-        Ok(CdInterface {
+        CdInterface {
             cdc: Box::new(cdc),
             mdec_busy_cooldown: 0,
-        })
+            audio: VecDeque::new(),
+        }
     }
 
     pub fn set_cd_loading_speed(&mut self, loading_speed: u8) {
@@ -74,6 +96,14 @@ impl CdInterface {
         self.cdc.disc_present()
     }
 
+    pub fn disc(&self) -> Option<&Disc> {
+        self.cdc.disc()
+    }
+
+    pub fn disc_mut(&mut self) -> Option<&mut Disc> {
+        self.cdc.disc_mut()
+    }
+
     pub fn eject_disc(&mut self) -> Option<Disc> {
         self.cdc.take_disc()
     }
@@ -82,6 +112,10 @@ impl CdInterface {
         self.cdc.load_disc(disc)
     }
 
+    pub fn is_shell_open(&self) -> bool {
+        self.cdc.is_shell_open()
+    }
+
     pub fn state(&self) -> CdcState {
         self.cdc.state()
     }
@@ -93,53 +127,122 @@ impl CdInterface {
     pub fn sled_position(&self) -> DiscPosition {
         self.cdc.position()
     }
+
+    /// Next stereo sample of the audio output, queued by `run` for the SPU
+    pub fn next_audio_sample(&mut self) -> [i16; 2] {
+        // The SPU catches us up before it mixes our output, the queue can't run dry
+        self.audio.pop_front().unwrap_or_default()
+    }
+}
+
+/// What the CD interface needs from the bus. Implemented by the `Bus`, and by the mock bus of the
+/// unit tests.
+pub trait CdBus: IrqLines + Scheduler {
+    fn cd(&mut self) -> &mut CdInterface;
+
+    /// True while the MDEC is decoding, the game is probably streaming a video from the disc then
+    fn mdec_busy(&self) -> bool;
+}
+
+impl CdBus for Bus {
+    fn cd(&mut self) -> &mut CdInterface {
+        &mut self.cd
+    }
+
+    fn mdec_busy(&self) -> bool {
+        self.mdec.is_busy()
+    }
+}
+
+/// Run the controller until it's caught up with the CPU. It runs at the SPU's pace, its audio
+/// output is queued until the SPU mixes it.
+pub fn run<B: CdBus>(bus: &mut B) {
+    let mut elapsed = bus.resync(CDSYNC);
+
+    while elapsed >= spu::SPU_FREQ_DIVIDER {
+        elapsed -= spu::SPU_FREQ_DIVIDER;
+
+        let sample = run_audio_cycle(bus);
+        bus.cd().audio.push_back(sample);
+    }
+
+    bus.rewind(CDSYNC, elapsed);
+
+    // Everything the CPU can observe syncs us before the access and the SPU catches us up before
+    // it mixes our audio, so the only reason to come back early is our interrupt: it must go up on
+    // the exact sample the firmware raises it. The host masking all of them is the only way to be
+    // sure it won't.
+    let samples = if bus.cd().cdc.irq_masked() {
+        spu::SPU_BLOCK_SAMPLES
+    } else {
+        1
+    };
+
+    bus.next_event(CDSYNC, samples * spu::SPU_FREQ_DIVIDER - elapsed);
 }
 
 /// Called by the DMA when it wants to get our CD data
-pub fn dma_load(bus: &mut Bus) -> u32 {
+pub fn dma_load<B: CdBus>(bus: &mut B) -> u32 {
+    run(bus);
+
+    let cdc = &mut bus.cd().cdc;
+
     // We read 4 bytes at a time
-    let b0 = u32::from(bus.cd.cdc.host_dma_read());
-    let b1 = u32::from(bus.cd.cdc.host_dma_read());
-    let b2 = u32::from(bus.cd.cdc.host_dma_read());
-    let b3 = u32::from(bus.cd.cdc.host_dma_read());
+    let b0 = u32::from(cdc.host_dma_read());
+    let b1 = u32::from(cdc.host_dma_read());
+    let b2 = u32::from(cdc.host_dma_read());
+    let b3 = u32::from(cdc.host_dma_read());
 
     // Pack in a little endian word
     b0 | (b1 << 8) | (b2 << 16) | (b3 << 24)
 }
 
-pub fn run_audio_cycle(bus: &mut Bus) -> [i16; 2] {
-    if bus.mdec.is_busy() {
+fn run_audio_cycle<B: CdBus>(bus: &mut B) -> [i16; 2] {
+    let mdec_busy = bus.mdec_busy();
+    let cd = bus.cd();
+
+    if mdec_busy {
         // Prevent overclocking for a quarter of a second
-        bus.cd.mdec_busy_cooldown = 44_100 / 4;
-    } else if bus.cd.mdec_busy_cooldown > 0 {
-        bus.cd.mdec_busy_cooldown -= 1;
+        cd.mdec_busy_cooldown = 44_100 / 4;
+    } else if cd.mdec_busy_cooldown > 0 {
+        cd.mdec_busy_cooldown -= 1;
     }
 
-    let sample = bus.cd.cdc.run_audio_cycle(bus.cd.mdec_busy_cooldown == 0);
+    let sample = cd.cdc.run_audio_cycle(cd.mdec_busy_cooldown == 0);
     refresh_irq(bus);
 
     sample
 }
 
-pub fn store<T: Addressable>(bus: &mut Bus, off: u32, val: T) {
+pub fn store<B: CdBus, T: Addressable>(bus: &mut B, off: u32, val: T) {
+    run(bus);
+
     let v = val.as_u8();
     let off = off as u8;
 
-    bus.cd.cdc.host_write(off, v);
+    bus.cd().cdc.host_write(off, v);
 
     refresh_irq(bus);
+
+    // The next event depends on the interrupt mask, nothing gets generated here since we've just
+    // synced
+    run(bus);
 }
 
-pub fn load<T: Addressable>(bus: &mut Bus, off: u32) -> T {
+pub fn load<B: CdBus, T: Addressable>(bus: &mut B, off: u32) -> T {
+    run(bus);
+
     let off = off as u8;
 
-    let v = bus.cd.cdc.host_read(off);
+    let v = bus.cd().cdc.host_read(off);
 
     T::from_u32(u32::from(v))
 }
 
-fn refresh_irq(bus: &mut Bus) {
-    irq::set_level(bus, irq::Interrupt::CdRom, bus.cd.cdc.irq_active());
+fn refresh_irq<B: CdBus>(bus: &mut B) {
+    let active = bus.cd().cdc.irq_active();
+
+    bus.set_irq_level(Interrupt::CdRom, active);
 }
 
 /// This is the SHA256 for the firmware we tested with, `scph-5502_SC430939.bin`.
@@ -153,3 +256,64 @@ pub const CDC_ROM_SHA256: [u8; 32] = [
     0xb7, 0xea, 0xb5, 0x4e, 0xad, 0x48, 0xc1, 0xdd, 0xb1, 0xa1, 0xa5, 0xd2, 0x69, 0x92, 0x42, 0xdb,
 ];
 
+
+#[cfg(test)]
+impl CdBus for crate::ps1::psx::mock_bus::MockBus<CdInterface> {
+    fn cd(&mut self) -> &mut CdInterface {
+        &mut self.device
+    }
+
+    fn mdec_busy(&self) -> bool {
+        false
+    }
+}
+
+#[test]
+fn host_registers() {
+    use crate::ps1::psx::mock_bus::MockBus;
+
+    // The drive controller is left spinning on `BRA *`, only the decoder answers
+    let mut bus = MockBus::new(CdInterface::new_without_firmware());
+
+    // The low bits of the status register are the register bank
+    store(&mut bus, 0, 1u8);
+    assert_eq!(load::<_, u8>(&mut bus, 0) & 3, 1);
+
+    // Bank 1: interrupt mask, read back from bank 0
+    store(&mut bus, 2, 0x1fu8);
+    store(&mut bus, 0, 0u8);
+    assert_eq!(load::<_, u8>(&mut bus, 3), 0x1f);
+
+    // Parameter FIFO
+    assert_ne!(load::<_, u8>(&mut bus, 0) & (1 << 3), 0);
+    store(&mut bus, 2, 0x12u8);
+    assert_eq!(load::<_, u8>(&mut bus, 0) & (1 << 3), 0);
+
+    // Nothing for the host yet
+    assert!(!bus.irq_level(Interrupt::CdRom));
+}
+
+#[test]
+fn irq_deadline() {
+    use crate::ps1::psx::mock_bus::MockBus;
+
+    let mut bus = MockBus::new(CdInterface::new_without_firmware());
+
+    // Some interrupts are unmasked at reset, the controller has to come back on every sample
+    bus.tick(10 * spu::SPU_FREQ_DIVIDER + 5);
+    run(&mut bus);
+    assert_eq!(bus.device.audio.len(), 10);
+    assert_eq!(bus.next_event[CDSYNC as usize], bus.cycles - 5 + spu::SPU_FREQ_DIVIDER);
+
+    // None of them can go up once they're all masked
+    store(&mut bus, 0, 1u8);
+    store(&mut bus, 2, 0u8);
+    assert_eq!(
+        bus.next_event[CDSYNC as usize],
+        bus.cycles - 5 + spu::SPU_BLOCK_SAMPLES * spu::SPU_FREQ_DIVIDER
+    );
+
+    // The SPU takes the samples in order
+    assert_eq!(bus.device.next_audio_sample(), [0, 0]);
+    assert_eq!(bus.device.audio.len(), 9);
+}
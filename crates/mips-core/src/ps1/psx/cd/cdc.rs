@@ -10,6 +10,11 @@ pub use uc::ROM_DUMP_SIZE as MC68HC05_ROM_DUMP_SIZE;
 use crate::ps1::Ps1Error;
 use crate::ps1::psx::cd::disc::Disc;
 
+/// How long the shell stays open when a disc is swapped, in µs. The firmware polls the lid switch
+/// and the games poll the firmware's status: the games that ask for another disc wait for the lid
+/// to open then close, they'd miss a shorter change.
+const DISC_SWAP_OPEN_US: u32 = 500_000;
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Cdc {
     uc: uc::Uc,
@@ -53,9 +58,10 @@ impl Cdc {
         cdc
     }
 
-    /// Remove the disc and emulate an open tray
+    /// Remove the disc and emulate an open tray. The shell stays open until a disc is loaded.
     pub fn take_disc(&mut self) -> Option<Disc> {
         self.set_shell_open(true);
+        self.shell_close_delay = None;
         self.dsp.disc_removed();
 
         self.disc.take()
@@ -101,13 +107,20 @@ impl Cdc {
         self.disc.is_some()
     }
 
+    pub fn disc(&self) -> Option<&Disc> {
+        self.disc.as_ref()
+    }
+
+    pub fn disc_mut(&mut self) -> Option<&mut Disc> {
+        self.disc.as_mut()
+    }
+
     pub fn load_disc(&mut self, disc: Disc) {
         // Make sure any previous disc is gone
         self.take_disc();
 
         self.disc = Some(disc);
-        // Wait half a second before closing the shell
-        self.shell_close_delay = Some(us_to_audio_cycles(500_000));
+        self.shell_close_delay = Some(us_to_audio_cycles(DISC_SWAP_OPEN_US));
     }
 
     pub fn set_cd_loading_speed(&mut self, loading_speed: u8) {
@@ -182,10 +195,25 @@ impl Cdc {
         self.decoder.host_irq_active()
     }
 
+    /// Returns true if the host masked all the interrupts, none can go up until it unmasks them
+    pub fn irq_masked(&self) -> bool {
+        self.decoder.host_irq_masked()
+    }
+
+    /// True if the audio sent to the SPU comes from XA ADPCM sectors, false for CD-DA tracks
+    pub fn is_streaming_xa(&self) -> bool {
+        self.decoder.is_streaming_xa()
+    }
+
     pub fn set_debug(&mut self, debug: bool) {
         self.uc.set_debug(debug);
     }
 
+    /// True while the shell is open, ejected or in the middle of a disc swap
+    pub fn is_shell_open(&self) -> bool {
+        self.uc.is_shell_open()
+    }
+
     pub fn set_shell_open(&mut self, opened: bool) {
         self.uc.set_shell_open(opened);
     }
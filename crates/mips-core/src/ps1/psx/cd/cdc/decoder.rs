@@ -90,6 +90,10 @@ pub struct Decoder {
     host_result: HostFifo,
     /// Buffer for output samples before they're sent to the SPU
     output_buffer: OutputBuffer,
+    /// True if the last samples queued in `output_buffer` were XA ADPCM rather than CD-DA, for the
+    /// frontend's mixer. Not worth a place in the save states: the next sector sets it again.
+    #[serde(skip)]
+    streaming_xa: bool,
 }
 
 impl Decoder {
@@ -137,6 +141,7 @@ impl Decoder {
             host_params: HostFifo::new(),
             host_result: HostFifo::new(),
             output_buffer: OutputBuffer::new(),
+            streaming_xa: false,
         }
     }
 
@@ -148,6 +153,10 @@ impl Decoder {
         !self.output_buffer.is_empty()
     }
 
+    pub fn is_streaming_xa(&self) -> bool {
+        self.streaming_xa
+    }
+
     fn host_command(&mut self, cmd: u8) {
         self.host_command = cmd;
         self.command_busy = true;
@@ -262,6 +271,11 @@ impl Decoder {
         self.hintsts & self.hintmsk != 0
     }
 
+    /// Returns true if the host masked all the interrupts
+    pub fn host_irq_masked(&self) -> bool {
+        self.hintmsk == 0
+    }
+
     pub fn push_param(&mut self, param: u8) {
         if self.host_params.is_full() {
             warn!("Decoder param FIFO overflow!");
@@ -429,6 +443,8 @@ impl Decoder {
         stereo: bool,
         frequency: AudioFrequency,
     ) -> u16 {
+        self.streaming_xa = matches!(frequency, AudioFrequency::Xa18k9 | AudioFrequency::Xa37k8);
+
         let samples = &self.sample_buffer[..usize::from(sample_count)];
         let mut nout_samples = 0;
 
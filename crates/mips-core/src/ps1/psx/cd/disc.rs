@@ -4,6 +4,10 @@
 /// support audio tracks anyway...
 
 mod cache;
+#[cfg(feature = "std")]
+pub mod formats;
+pub mod patch;
+pub mod soundtrack;
 
 use std::fmt;
 pub use cache::Cache as CdCache;
@@ -24,9 +28,14 @@ pub struct Disc {
 }
 
 impl Disc {
-    /// Reify a disc using `image` as a backend.
-    pub fn new(image: Box<dyn Image + Send>) -> MipsResult<Disc> {
-        let mut cache = CdCache::new(image);
+    /// Reify a disc using `image` as a backend. Discs no larger than `preload_limit` bytes are read
+    /// into memory right away.
+    pub fn new(image: Box<dyn Image + Send>, preload_limit: u64) -> MipsResult<Disc> {
+        let mut cache = if preload_limit > 0 {
+            CdCache::new_preloaded(image, preload_limit)
+        } else {
+            CdCache::new(image)
+        };
 
         let serial = extract_serial_number(&mut cache)?;
 
@@ -59,6 +68,47 @@ impl Disc {
     pub fn serial_number(&self) -> SerialNumber {
         self.serial
     }
+
+    /// Read the executable the BIOS boots, as named in SYSTEM.CNF
+    pub fn boot_executable(&mut self) -> MipsResult<Vec<u8>> {
+        let system_cnf = read_system_cnf(&mut self.cache)?;
+        let Some(path) = parse_boot_path_from_system_cnf(&system_cnf) else {
+            let desc = "no BOOT line in SYSTEM.CNF".to_string();
+            return Err(MipsError::from(Ps1Error::BadDiscFormat(desc)));
+        };
+
+        self.read_path(&path)
+    }
+
+    /// Read the file at `path` (`cdrom:\DATA\FILE.BIN;1`, the device and the version being
+    /// optional), for the file functions of the emulated BIOS
+    pub fn read_file(&mut self, path: &[u8]) -> MipsResult<Vec<u8>> {
+        let path = path.split(|&b| b == b';').next().unwrap();
+        let path = path.rsplit(|&b| b == b':').next().unwrap().to_ascii_uppercase();
+        let parts: Vec<&[u8]> = path.split(|&b| b == b'\\' || b == b'/').filter(|p| !p.is_empty()).collect();
+
+        self.read_path(&parts)
+    }
+
+    /// Read the file at `path`, split in directories and file name
+    fn read_path(&mut self, path: &[&[u8]]) -> MipsResult<Vec<u8>> {
+        let iso_error = |e: iso9660::IsoError| MipsError::from(Ps1Error::BadDiscFormat(e.to_string()));
+
+        let Some((name, dirs)) = path.split_last() else {
+            let desc = "empty path".to_string();
+            return Err(MipsError::from(Ps1Error::BadDiscFormat(desc)));
+        };
+
+        let mut dir = iso9660::open_image(&mut self.cache).map_err(iso_error)?;
+        for d in dirs {
+            dir = dir.cd(&mut self.cache, d).map_err(iso_error)?;
+        }
+
+        let name = [*name, b";1"].concat();
+        dir.entry_by_name(&name)
+            .and_then(|entry| entry.read_file(&mut self.cache))
+            .map_err(iso_error)
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -228,7 +278,9 @@ fn extract_serial_number(image: &mut CdCache) -> MipsResult<SerialNumber> {
     parse_serial_number_from_system_cnf(&system_cnf)
 }
 
-fn parse_serial_number_from_system_cnf(system_cnf: &[u8]) -> MipsResult<SerialNumber> {
+/// Path of the executable booted by the BIOS, split in directories and file name, without the
+/// "cdrom:" prefix and the ";1" version suffix
+fn parse_boot_path_from_system_cnf(system_cnf: &[u8]) -> Option<Vec<&[u8]>> {
     // Now we need to parse the SYSTEM.CNF file to get the content of the "BOOT" line
     let mut boot_path = None;
 
@@ -244,7 +296,19 @@ fn parse_serial_number_from_system_cnf(system_cnf: &[u8]) -> MipsResult<SerialNu
         }
     }
 
-    let boot_path = match boot_path {
+    // boot_path should look like "cdrom:\FOO\BAR\...\aaaa_ddd.dd;1"
+    //
+    // Most (but not all) paths ends with a ";1", so get rid of it here
+    let boot_path = boot_path?.split(|&b| b == b';').next().unwrap();
+
+    // boot_path should look like "cdrom:\FOO\BAR\...\aaaa_ddd.dd"
+    let path = boot_path.rsplit(|&b| b == b':').next().unwrap();
+
+    Some(path.split(|&b| b == b'\\').filter(|p| !p.is_empty()).collect())
+}
+
+fn parse_serial_number_from_system_cnf(system_cnf: &[u8]) -> MipsResult<SerialNumber> {
+    let boot_path = match parse_boot_path_from_system_cnf(system_cnf) {
         Some(b) => b,
         None => {
             warn!("Couldn't find BOOT line in SYSTEM.CNF");
@@ -252,16 +316,7 @@ fn parse_serial_number_from_system_cnf(system_cnf: &[u8]) -> MipsResult<SerialNu
         }
     };
 
-    // boot_path should look like "cdrom:\FOO\BAR\...\aaaa_ddd.dd;1"
-    //
-    // Most (but not all) paths ends with a ";1", so get rid of it here
-    let boot_path = boot_path.split(|&b| b == b';').next().unwrap();
-
-    // boot_path should look like "cdrom:\FOO\BAR\...\aaaa_ddd.dd"
-    let bin_name = boot_path
-        .split(|&b| b == b':' || b == b'\\')
-        .last()
-        .unwrap();
+    let bin_name = boot_path.last().copied().unwrap_or_default();
 
     let serial = SerialNumber::from_bin_name(bin_name);
 
@@ -1,19 +1,33 @@
 //! Multi-threaded prefetching cache for PSX discs.
 //!
-//! This cache tries to read sectors ahead of the emulator to avoid any I/O lockup
+//! This cache tries to read sectors ahead of the emulator to avoid any I/O lockup. Small discs can
+//! also be read entirely when they're loaded, for drives that are slow to wake up or network
+//! shares that stall now and then. Without the `std` feature there's no prefetcher thread, the
+//! sectors are read as the emulator asks for them.
 
 use cdimage::sector::Sector;
 use cdimage::DiscPosition;
 use cdimage::{Image, Toc};
-use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use log::{info, warn};
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::{Condvar, Mutex, MutexGuard};
+#[cfg(feature = "std")]
 use std::thread;
 
 pub struct Cache {
     /// The reader state and a Condvar used to notify the reader when it should read a new sector
     /// (or used by the reader to let the cache know that a sector has been read).
+    #[cfg(feature = "std")]
     reader: Arc<(Mutex<Reader>, Condvar)>,
     /// Thread handle for the prefetcher
+    #[cfg(feature = "std")]
     handle: Option<thread::JoinHandle<()>>,
+    /// Without a prefetcher the sectors are read by the emulator itself, as it needs them
+    #[cfg(not(feature = "std"))]
+    reader: Reader,
+    #[cfg(not(feature = "std"))]
+    image: Box<dyn Image + Send>,
     /// CD table of contents
     toc: Toc,
 }
@@ -26,7 +40,48 @@ impl Cache {
     }
 
     pub fn new_with_toc(image: Box<dyn Image + Send>, toc: Toc) -> Cache {
-        let reader = Arc::new((Mutex::new(Reader::new()), Condvar::new()));
+        Cache::start(image, toc, Reader::new())
+    }
+
+    /// Read the whole disc right away if it's no larger than `limit` bytes (counting 2352 bytes per
+    /// sector), otherwise behave like `new`
+    pub fn new_preloaded(mut image: Box<dyn Image + Send>, limit: u64) -> Cache {
+        let toc = image.toc().clone();
+        let mut reader = Reader::new();
+
+        let sectors = disc_sectors(&toc);
+        let size = sectors as u64 * SECTOR_SIZE;
+        if size > limit {
+            info!("Not preloading the disc, {} MiB is above the limit", size >> 20);
+            return Cache::start(image, toc, reader);
+        }
+
+        let mut errors = 0;
+        let mut dp = DiscPosition::ZERO;
+        for _ in 0..sectors {
+            let sector = image.read_sector(dp).map_err(Arc::new);
+            if sector.is_err() {
+                errors += 1;
+            }
+            reader.sectors.insert(dp, sector);
+
+            match dp.next() {
+                Some(next) => dp = next,
+                None => break,
+            }
+        }
+
+        if errors > 0 {
+            warn!("{} sectors couldn't be read while preloading the disc", errors);
+        }
+        info!("Preloaded {} MiB of disc", size >> 20);
+
+        Cache::start(image, toc, reader)
+    }
+
+    #[cfg(feature = "std")]
+    fn start(image: Box<dyn Image + Send>, toc: Toc, reader: Reader) -> Cache {
+        let reader = Arc::new((Mutex::new(reader), Condvar::new()));
 
         let thread_reader = reader.clone();
 
@@ -47,12 +102,29 @@ impl Cache {
         }
     }
 
+    #[cfg(not(feature = "std"))]
+    fn start(image: Box<dyn Image + Send>, toc: Toc, reader: Reader) -> Cache {
+        Cache { reader, image, toc }
+    }
+
+    #[cfg(feature = "std")]
     fn reader(&self) -> (MutexGuard<Reader>, &Condvar) {
         let (reader, cond) = &*self.reader;
 
         (reader.lock().unwrap(), cond)
     }
 
+    #[cfg(not(feature = "std"))]
+    pub fn read_sector(&mut self, dp: DiscPosition) -> CachedResult<Sector> {
+        let image = &mut self.image;
+
+        self.reader.sectors
+            .entry(dp)
+            .or_insert_with(|| image.read_sector(dp).map_err(Arc::new))
+            .clone()
+    }
+
+    #[cfg(feature = "std")]
     pub fn read_sector(&mut self, dp: DiscPosition) -> CachedResult<Sector> {
         let (mut reader, cond) = self.reader();
 
@@ -78,6 +150,7 @@ impl Cache {
     }
 }
 
+#[cfg(feature = "std")]
 impl ::std::ops::Drop for Cache {
     fn drop(&mut self) {
         {
@@ -104,10 +177,13 @@ struct Reader {
     /// The actual sector cache
     sectors: SectorCache,
     /// Number of sectors left to prefetch before becoming idle
+    #[cfg(feature = "std")]
     prefetch_remaining: u32,
     /// Next sector we should attempt to prefetch (if `prefetch_remaining` is > 0).
+    #[cfg(feature = "std")]
     prefetch_next: DiscPosition,
     /// Set to true when the prefetcher should quit
+    #[cfg(feature = "std")]
     quit: bool,
 }
 
@@ -115,13 +191,17 @@ impl Reader {
     fn new() -> Reader {
         Reader {
             sectors: SectorCache::with_capacity_and_hasher(CACHE_CAPACITY, Default::default()),
+            #[cfg(feature = "std")]
             prefetch_remaining: 0,
+            #[cfg(feature = "std")]
             prefetch_next: DiscPosition::INNERMOST,
+            #[cfg(feature = "std")]
             quit: false,
         }
     }
 }
 
+#[cfg(feature = "std")]
 fn run_prefetcher(mut image: Box<dyn Image>, reader: Arc<(Mutex<Reader>, Condvar)>) {
     let (reader_mutex, cond) = &*reader;
 
@@ -173,7 +253,20 @@ fn run_prefetcher(mut image: Box<dyn Image>, reader: Arc<(Mutex<Reader>, Condvar
     }
 }
 
+/// Number of sectors in the program area, up to the end of the last track
+fn disc_sectors(toc: &Toc) -> u32 {
+    toc.tracks()
+        .iter()
+        .map(|t| t.start.sector_index() + t.length.sector_index())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Size of a raw sector, what reading the disc costs in memory
+const SECTOR_SIZE: u64 = 2352;
+
 /// Number of sectors to read ahead
+#[cfg(feature = "std")]
 const PREFETCH_READAHEAD_SECTORS: u32 = 75;
 
 /// Initial capacity of the cache. We'll be able to put that many elements before reallocating.
@@ -0,0 +1,659 @@
+//! Disc image formats beyond the CUE sheets and ZIP archives cdimage reads: PSP EBOOT.PBP images,
+//! ECM compressed images and bare ISO/BIN/IMG dumps without a CUE sheet.
+//!
+//! The format is sniffed from the first bytes of the file, the extension is only a last resort for
+//! the CUE sheets. The images cdimage can't read are converted once to a BIN/CUE pair in the cache
+//! directory, bare BIN dumps only get a CUE sheet there.
+
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use log::info;
+use crate::error::{MipsError, MipsResult};
+use crate::ps1::Ps1Error;
+
+const SECTOR_SIZE: usize = 2352;
+const ISO_SECTOR_SIZE: usize = 2048;
+
+/// First 12 bytes of every data sector
+const SYNC: [u8; 12] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+/// Sectors before the first track, at MSF 00:00:00
+const LEAD_IN: u32 = 150;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    Cue,
+    /// ZIP archive holding a BIN/CUE dump
+    Zip,
+    /// PSP EBOOT.PBP holding one or more discs
+    Pbp,
+    /// Error Code Modeler: a raw image stripped of its sync patterns, headers and error correction
+    Ecm,
+    /// Raw 2352 bytes/sector dump of a single data track
+    RawBin,
+    /// Data of the 2048 bytes/sector payload of each sector only
+    Iso,
+}
+
+/// Tell the format of the image in `path` from its contents
+pub fn sniff(path: &Path) -> MipsResult<ImageFormat> {
+    let mut file = File::open(path).map_err(|e| io_error(path, e))?;
+    let mut header = vec![0; 0x8010];
+    let len = read_up_to(&mut file, &mut header).map_err(|e| io_error(path, e))?;
+    let header = &header[..len];
+
+    let format = if header.starts_with(b"\0PBP") {
+        ImageFormat::Pbp
+    } else if header.starts_with(b"ECM\0") {
+        ImageFormat::Ecm
+    } else if header.starts_with(b"PK\x03\x04") {
+        ImageFormat::Zip
+    } else if header.starts_with(&SYNC) {
+        ImageFormat::RawBin
+    } else if header.get(0x8001..0x8006) == Some(b"CD001") {
+        // Primary volume descriptor in sector 16
+        ImageFormat::Iso
+    } else if is_cue_sheet(header) || path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("cue")) {
+        ImageFormat::Cue
+    } else {
+        return Err(bad_format(format!("{}: unknown disc image format", path.display())));
+    };
+
+    Ok(format)
+}
+
+fn is_cue_sheet(header: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(header) else {
+        return false;
+    };
+
+    text.lines().any(|line| line.trim_start().starts_with("FILE ")) && text.contains("TRACK ")
+}
+
+/// CUE sheet cdimage can open for `path` in `format`, converting the image in `cache_dir` if
+/// needed. `disc` picks the disc of a multi-disc PBP, from 0.
+pub fn cue_sheet(path: &Path, format: ImageFormat, disc: usize, cache_dir: &Path) -> MipsResult<PathBuf> {
+    if format == ImageFormat::Cue {
+        return Ok(path.to_path_buf());
+    }
+
+    let dir = cache_dir.join(cache_name(path)?);
+    let cue = dir.join(format!("disc{}.cue", disc + 1));
+    // Written last, once the image is complete
+    if cue.is_file() {
+        return Ok(cue);
+    }
+
+    fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+
+    let sheet = match format {
+        ImageFormat::Cue | ImageFormat::Zip => unreachable!("cdimage reads {:?} images", format),
+        ImageFormat::RawBin => {
+            // The sheet isn't next to the image, the path must hold from anywhere
+            let bin = fs::canonicalize(path).map_err(|e| io_error(path, e))?;
+            single_track_sheet(&bin, raw_track_mode(path)?)
+        }
+        ImageFormat::Iso => {
+            info!("Converting {} to BIN", path.display());
+            let bin = dir.join("disc1.bin");
+            convert(path, &bin, iso_to_bin)?;
+            single_track_sheet(Path::new("disc1.bin"), "MODE2/2352")
+        }
+        ImageFormat::Ecm => {
+            info!("Decoding {}", path.display());
+            let bin = dir.join("disc1.bin");
+            convert(path, &bin, decode_ecm)?;
+            single_track_sheet(Path::new("disc1.bin"), raw_track_mode(&bin)?)
+        }
+        ImageFormat::Pbp => {
+            info!("Extracting disc {} of {}", disc + 1, path.display());
+            let name = format!("disc{}.bin", disc + 1);
+            let mut tracks = Vec::new();
+            convert(path, &dir.join(&name), |input, output| {
+                tracks = extract_pbp_disc(input, output, disc)?;
+                Ok(())
+            })?;
+            pbp_sheet(Path::new(&name), &tracks)
+        }
+    };
+
+    fs::write(&cue, sheet).map_err(|e| io_error(&cue, e))?;
+
+    Ok(cue)
+}
+
+/// Discs in the image in `path`: several for the multi-disc PBPs, one for anything else
+pub fn disc_count(path: &Path) -> MipsResult<usize> {
+    if sniff(path)? != ImageFormat::Pbp {
+        return Ok(1);
+    }
+
+    let mut input = BufReader::new(File::open(path).map_err(|e| io_error(path, e))?);
+    Ok(pbp_discs(&mut input)?.len())
+}
+
+/// `game.pbp#2` is the second disc of `game.pbp`, anything else the first disc of the image.
+/// Paths to existing files are taken whole.
+pub fn split_disc_index(path: &Path) -> (PathBuf, usize) {
+    if !path.exists() {
+        let s = path.to_string_lossy();
+        if let Some((image, n)) = s.rsplit_once('#')
+            && let Ok(n @ 1..) = n.parse::<usize>()
+        {
+            return (PathBuf::from(image), n - 1);
+        }
+    }
+
+    (path.to_path_buf(), 0)
+}
+
+/// File of `cache_dir` holding the disc image `image`, handed over in memory, for cdimage to read
+/// it. Named after the contents, an image is only written the first time. CUE sheets are refused,
+/// the BIN files they name aren't in memory.
+pub fn spill(image: &[u8], cache_dir: &Path) -> MipsResult<PathBuf> {
+    if is_cue_sheet(&image[..image.len().min(0x8010)]) {
+        return Err(bad_format("a CUE sheet can't be loaded from memory, only a single file image"));
+    }
+
+    let mut hasher = fnv::FnvHasher::default();
+    image.hash(&mut hasher);
+
+    let dir = cache_dir.join("memory");
+    let path = dir.join(format!("{:016x}.img", hasher.finish()));
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, image).map_err(|e| io_error(&tmp, e))?;
+    fs::rename(&tmp, &path).map_err(|e| io_error(&path, e))?;
+
+    Ok(path)
+}
+
+/// Cache subdirectory of the image in `path`: changes if the image is replaced
+fn cache_name(path: &Path) -> MipsResult<String> {
+    let metadata = fs::metadata(path).map_err(|e| io_error(path, e))?;
+    let modified = metadata.modified().ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+
+    let mut hasher = fnv::FnvHasher::default();
+    path.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    modified.hash(&mut hasher);
+
+    let stem = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+
+    Ok(format!("{}-{:016x}", stem, hasher.finish()))
+}
+
+/// Run `conversion` from `input` to `output`, through a temporary file so that an interrupted
+/// conversion isn't taken for a complete one
+fn convert<F>(input: &Path, output: &Path, conversion: F) -> MipsResult<()>
+where
+    F: FnOnce(&mut BufReader<File>, &mut BufWriter<File>) -> MipsResult<()>,
+{
+    let tmp = output.with_extension("tmp");
+
+    let mut reader = BufReader::new(File::open(input).map_err(|e| io_error(input, e))?);
+    let mut writer = BufWriter::new(File::create(&tmp).map_err(|e| io_error(&tmp, e))?);
+
+    conversion(&mut reader, &mut writer)?;
+    writer.flush().map_err(|e| io_error(&tmp, e))?;
+    drop(writer);
+
+    fs::rename(&tmp, output).map_err(|e| io_error(output, e))
+}
+
+/// Mode of the data track of a raw dump, from the header of its first sector
+fn raw_track_mode(bin: &Path) -> MipsResult<&'static str> {
+    let mut sector = [0; 16];
+    let mut file = File::open(bin).map_err(|e| io_error(bin, e))?;
+    file.read_exact(&mut sector).map_err(|e| io_error(bin, e))?;
+
+    match sector[15] {
+        1 => Ok("MODE1/2352"),
+        2 => Ok("MODE2/2352"),
+        mode => Err(bad_format(format!("{}: unknown sector mode {}", bin.display(), mode))),
+    }
+}
+
+fn single_track_sheet(bin: &Path, mode: &str) -> String {
+    format!("FILE \"{}\" BINARY\n  TRACK 01 {}\n    INDEX 01 00:00:00\n", bin.display(), mode)
+}
+
+/// CUE sheet of a disc extracted from a PBP, the tracks are all in one BIN
+fn pbp_sheet(bin: &Path, tracks: &[PbpTrack]) -> String {
+    let mut sheet = format!("FILE \"{}\" BINARY\n", bin.display());
+
+    for track in tracks {
+        let mode = if track.audio { "AUDIO" } else { "MODE2/2352" };
+        let [m, s, f] = msf(track.start - LEAD_IN);
+        sheet += &format!("  TRACK {:02} {}\n    INDEX 01 {:02}:{:02}:{:02}\n", track.number, mode, m, s, f);
+    }
+
+    sheet
+}
+
+/// Minutes, seconds and frames of the sector `index`
+fn msf(index: u32) -> [u32; 3] {
+    [index / (60 * 75), (index / 75) % 60, index % 75]
+}
+
+fn bcd(v: u32) -> u8 {
+    ((v / 10) << 4 | (v % 10)) as u8
+}
+
+fn from_bcd(b: u8) -> u32 {
+    u32::from(b >> 4) * 10 + u32::from(b & 0xf)
+}
+
+/// Wrap each 2048 byte block of an ISO in a mode 2 form 1 sector, the way they're on a PlayStation
+/// disc
+fn iso_to_bin(input: &mut BufReader<File>, output: &mut BufWriter<File>) -> MipsResult<()> {
+    let mut data = [0; ISO_SECTOR_SIZE];
+
+    for lba in 0.. {
+        let len = read_up_to(input, &mut data).map_err(conversion_error)?;
+        if len == 0 {
+            break;
+        }
+        data[len..].fill(0);
+
+        let mut sector = [0; SECTOR_SIZE];
+        sector[..12].copy_from_slice(&SYNC);
+        let [m, s, f] = msf(lba + LEAD_IN);
+        sector[12..16].copy_from_slice(&[bcd(m), bcd(s), bcd(f), 2]);
+        // Plain data subheader, repeated
+        sector[16..24].copy_from_slice(&[0, 0, 0x08, 0, 0, 0, 0x08, 0]);
+        sector[24..24 + ISO_SECTOR_SIZE].copy_from_slice(&data);
+        finish_mode2_form1(&mut sector);
+
+        output.write_all(&sector).map_err(conversion_error)?;
+    }
+
+    Ok(())
+}
+
+/// Rebuild the raw image from an ECM file
+fn decode_ecm(input: &mut BufReader<File>, output: &mut BufWriter<File>) -> MipsResult<()> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic).map_err(conversion_error)?;
+    if &magic != b"ECM\0" {
+        return Err(bad_format("not an ECM file"));
+    }
+
+    let mut edc = 0;
+
+    loop {
+        // Type in the 2 LSBs, then a count of up to 32 bits, 7 bits per byte after the first 5
+        let mut c = read_u8(input)?;
+        let kind = c & 3;
+        let mut count = u32::from(c >> 2) & 0x1f;
+        let mut shift = 5;
+        while c & 0x80 != 0 {
+            if shift > 31 {
+                return Err(bad_format("corrupt ECM record"));
+            }
+            c = read_u8(input)?;
+            count |= u32::from(c & 0x7f) << shift;
+            shift += 7;
+        }
+        if count == 0xffff_ffff {
+            break;
+        }
+        let count = count as usize + 1;
+
+        for _ in 0..(if kind == 0 { 1 } else { count }) {
+            let mut sector = [0u8; SECTOR_SIZE];
+
+            let out: &[u8] = match kind {
+                0 => {
+                    // Bytes stored as is, streamed since the count comes from the file
+                    let mut left = count;
+                    while left > 0 {
+                        let raw = &mut sector[..left.min(SECTOR_SIZE)];
+                        input.read_exact(raw).map_err(conversion_error)?;
+                        edc = edc_update(edc, raw);
+                        output.write_all(raw).map_err(conversion_error)?;
+                        left -= raw.len();
+                    }
+                    continue;
+                }
+                1 => {
+                    // Mode 1: address and data
+                    sector[..12].copy_from_slice(&SYNC);
+                    input.read_exact(&mut sector[12..15]).map_err(conversion_error)?;
+                    sector[15] = 1;
+                    input.read_exact(&mut sector[16..16 + ISO_SECTOR_SIZE]).map_err(conversion_error)?;
+                    let sum = edc_update(0, &sector[..0x810]);
+                    sector[0x810..0x814].copy_from_slice(&sum.to_le_bytes());
+                    ecc_generate(&mut sector, false);
+                    &sector
+                }
+                2 => {
+                    // Mode 2 form 1: subheader and data, the sync and header aren't stored
+                    input.read_exact(&mut sector[20..24 + ISO_SECTOR_SIZE]).map_err(conversion_error)?;
+                    sector.copy_within(20..24, 16);
+                    finish_mode2_form1(&mut sector);
+                    &sector[16..]
+                }
+                _ => {
+                    // Mode 2 form 2: subheader and data, no ECC
+                    input.read_exact(&mut sector[20..0x92c]).map_err(conversion_error)?;
+                    sector.copy_within(20..24, 16);
+                    let sum = edc_update(0, &sector[0x10..0x92c]);
+                    sector[0x92c..0x930].copy_from_slice(&sum.to_le_bytes());
+                    &sector[16..]
+                }
+            };
+
+            edc = edc_update(edc, out);
+            output.write_all(out).map_err(conversion_error)?;
+        }
+    }
+
+    let mut expected = [0; 4];
+    input.read_exact(&mut expected).map_err(conversion_error)?;
+    if u32::from_le_bytes(expected) != edc {
+        return Err(bad_format("ECM checksum mismatch, the file is damaged"));
+    }
+
+    Ok(())
+}
+
+/// Fill the EDC and ECC of a mode 2 form 1 sector. The ECC covers the header as if it were zero.
+fn finish_mode2_form1(sector: &mut [u8; SECTOR_SIZE]) {
+    let sum = edc_update(0, &sector[0x10..0x818]);
+    sector[0x818..0x81c].copy_from_slice(&sum.to_le_bytes());
+    ecc_generate(sector, true);
+}
+
+/// CRC-32 of the sectors, with the CD-ROM polynomial
+fn edc_update(mut edc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        edc ^= u32::from(b);
+        for _ in 0..8 {
+            edc = (edc >> 1) ^ if edc & 1 != 0 { 0xd801_8001 } else { 0 };
+        }
+    }
+
+    edc
+}
+
+/// Compute the P and Q parity bytes (Reed-Solomon product code) of a mode 1 or mode 2 form 1
+/// sector
+fn ecc_generate(sector: &mut [u8; SECTOR_SIZE], zero_address: bool) {
+    let address: [u8; 4] = sector[12..16].try_into().unwrap();
+    if zero_address {
+        sector[12..16].fill(0);
+    }
+
+    let p = ecc_block(&sector[12..], 86, 24, 2, 86);
+    sector[0x81c..0x8c8].copy_from_slice(&p);
+    let q = ecc_block(&sector[12..], 52, 43, 86, 88);
+    sector[0x8c8..0x930].copy_from_slice(&q);
+
+    sector[12..16].copy_from_slice(&address);
+}
+
+fn ecc_block(src: &[u8], major_count: usize, minor_count: usize, major_mult: usize, minor_inc: usize) -> Vec<u8> {
+    let (f_lut, b_lut) = ecc_luts();
+    let size = major_count * minor_count;
+    let mut parity = vec![0; major_count * 2];
+
+    for major in 0..major_count {
+        let mut index = (major >> 1) * major_mult + (major & 1);
+        let mut a = 0u8;
+        let mut b = 0u8;
+
+        for _ in 0..minor_count {
+            let v = src[index];
+            index += minor_inc;
+            if index >= size {
+                index -= size;
+            }
+            a ^= v;
+            b ^= v;
+            a = f_lut[usize::from(a)];
+        }
+
+        a = b_lut[usize::from(f_lut[usize::from(a)] ^ b)];
+        parity[major] = a;
+        parity[major + major_count] = a ^ b;
+    }
+
+    parity
+}
+
+/// Multiplication by 2 in GF(2^8) and its inverse for the ECC
+fn ecc_luts() -> ([u8; 256], [u8; 256]) {
+    let mut f = [0; 256];
+    let mut b = [0; 256];
+
+    for i in 0..256 {
+        let j = (i << 1) ^ if i & 0x80 != 0 { 0x11d } else { 0 };
+        f[i] = j as u8;
+        b[i ^ j] = i as u8;
+    }
+
+    (f, b)
+}
+
+/// Track of a disc in a PBP, from its TOC
+#[derive(Debug, PartialEq, Eq)]
+struct PbpTrack {
+    number: u32,
+    audio: bool,
+    /// Absolute sector index of INDEX 01
+    start: u32,
+}
+
+/// Sectors per compressed block of a PBP disc
+const PBP_BLOCK_SECTORS: usize = 16;
+const PBP_BLOCK_SIZE: usize = PBP_BLOCK_SECTORS * SECTOR_SIZE;
+
+/// Offsets of the PSISOIMG0000 blocks of the discs in a PBP
+fn pbp_discs<R: Read + Seek>(input: &mut R) -> MipsResult<Vec<u64>> {
+    let mut header = [0; 0x28];
+    input.read_exact(&mut header).map_err(conversion_error)?;
+    if !header.starts_with(b"\0PBP") {
+        return Err(bad_format("not a PBP file"));
+    }
+    let psar = u64::from(u32::from_le_bytes(header[0x24..0x28].try_into().unwrap()));
+
+    let mut magic = [0; 16];
+    input.seek(SeekFrom::Start(psar)).map_err(conversion_error)?;
+    input.read_exact(&mut magic).map_err(conversion_error)?;
+
+    if magic.starts_with(b"PSISOIMG0000") {
+        return Ok(vec![psar]);
+    }
+    if &magic != b"PSTITLEIMG000000" {
+        return Err(bad_format("no PlayStation disc in the PBP"));
+    }
+
+    // Multi-disc: table of up to 5 offsets from the PSAR
+    let mut table = [0; 20];
+    input.seek(SeekFrom::Start(psar + 0x200)).map_err(conversion_error)?;
+    input.read_exact(&mut table).map_err(conversion_error)?;
+
+    Ok(table.chunks_exact(4)
+        .map(|o| u32::from_le_bytes(o.try_into().unwrap()))
+        .take_while(|&o| o != 0)
+        .map(|o| psar + u64::from(o))
+        .collect())
+}
+
+/// Write disc `disc` of the PBP `input` to `output` as a raw image, returning its tracks
+fn extract_pbp_disc<R: Read + Seek, W: Write>(input: &mut R, output: &mut W, disc: usize) -> MipsResult<Vec<PbpTrack>> {
+    let discs = pbp_discs(input)?;
+    let Some(&base) = discs.get(disc) else {
+        return Err(bad_format(format!("no disc {} in the PBP, it has {}", disc + 1, discs.len())));
+    };
+
+    let mut toc = [0; 0x400];
+    input.seek(SeekFrom::Start(base + 0x800)).map_err(conversion_error)?;
+    input.read_exact(&mut toc).map_err(conversion_error)?;
+    let (tracks, lead_out) = parse_pbp_toc(&toc)?;
+    let image_len = (lead_out - LEAD_IN) as usize * SECTOR_SIZE;
+
+    // Index of the compressed blocks: offset from the data, length, then padding and a hash
+    let block_count = image_len.div_ceil(PBP_BLOCK_SIZE);
+    let mut index = vec![0; block_count * 32];
+    input.seek(SeekFrom::Start(base + 0x4000)).map_err(conversion_error)?;
+    input.read_exact(&mut index).map_err(conversion_error)?;
+
+    let data = base + 0x10_0000;
+    let mut written = 0;
+
+    for entry in index.chunks_exact(32) {
+        let offset = u64::from(u32::from_le_bytes(entry[0..4].try_into().unwrap()));
+        let len = usize::from(u16::from_le_bytes([entry[4], entry[5]]));
+
+        let mut compressed = vec![0; len];
+        input.seek(SeekFrom::Start(data + offset)).map_err(conversion_error)?;
+        input.read_exact(&mut compressed).map_err(conversion_error)?;
+
+        let block = if len == PBP_BLOCK_SIZE {
+            compressed
+        } else {
+            miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, PBP_BLOCK_SIZE)
+                .map_err(|e| bad_format(format!("corrupt PBP block: {:?}", e)))?
+        };
+
+        let keep = block.len().min(image_len - written);
+        output.write_all(&block[..keep]).map_err(conversion_error)?;
+        written += keep;
+    }
+
+    if written != image_len {
+        return Err(bad_format("truncated PBP disc"));
+    }
+
+    Ok(tracks)
+}
+
+/// Tracks and lead-out sector of the TOC of a PBP disc: the Q subchannel entries of the lead-in,
+/// 10 bytes each
+fn parse_pbp_toc(toc: &[u8]) -> MipsResult<(Vec<PbpTrack>, u32)> {
+    let mut tracks = Vec::new();
+    let mut lead_out = None;
+
+    for entry in toc.chunks_exact(10).take_while(|e| e[0] != 0) {
+        let sector = (from_bcd(entry[7]) * 60 + from_bcd(entry[8])) * 75 + from_bcd(entry[9]);
+
+        match entry[2] {
+            0xa2 => lead_out = Some(sector),
+            0xa0 | 0xa1 => (),
+            point => tracks.push(PbpTrack {
+                number: from_bcd(point),
+                // Control bit 2 is set for data tracks
+                audio: entry[0] & 0x40 == 0,
+                start: sector,
+            }),
+        }
+    }
+
+    match lead_out {
+        Some(lead_out) if !tracks.is_empty() && tracks.iter().all(|t| t.start >= LEAD_IN && t.start < lead_out) => {
+            Ok((tracks, lead_out))
+        }
+        // The official releases encrypt it
+        _ => Err(bad_format("unreadable PBP table of contents, is it an encrypted PSN release?")),
+    }
+}
+
+fn read_u8<R: Read>(input: &mut R) -> MipsResult<u8> {
+    let mut b = [0];
+    input.read_exact(&mut b).map_err(conversion_error)?;
+
+    Ok(b[0])
+}
+
+/// Fill as much of `buf` as the reader has left, returns the length read
+fn read_up_to<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+
+    while len < buf.len() {
+        match input.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+
+    Ok(len)
+}
+
+fn bad_format(reason: impl Into<String>) -> MipsError {
+    MipsError::from(Ps1Error::BadDiscFormat(reason.into()))
+}
+
+fn io_error(path: &Path, e: io::Error) -> MipsError {
+    bad_format(format!("{}: {}", path.display(), e))
+}
+
+fn conversion_error(e: io::Error) -> MipsError {
+    bad_format(format!("can't convert the image: {}", e))
+}
+
+#[test]
+fn pbp_toc() {
+    let mut toc = vec![0; 0x400];
+    let entries: [[u8; 10]; 5] = [
+        [0x41, 0, 0xa0, 0, 0, 0, 0, 0x01, 0x20, 0x00],
+        [0x01, 0, 0xa1, 0, 0, 0, 0, 0x02, 0x00, 0x00],
+        [0x01, 0, 0xa2, 0, 0, 0, 0, 0x40, 0x00, 0x00],
+        [0x41, 0, 0x01, 0, 0, 0, 0, 0x00, 0x02, 0x00],
+        [0x01, 0, 0x02, 0, 0, 0, 0, 0x30, 0x12, 0x34],
+    ];
+    for (i, e) in entries.iter().enumerate() {
+        toc[i * 10..(i + 1) * 10].copy_from_slice(e);
+    }
+
+    let (tracks, lead_out) = parse_pbp_toc(&toc).unwrap();
+    assert_eq!(lead_out, 40 * 60 * 75);
+    assert_eq!(tracks, vec![
+        PbpTrack { number: 1, audio: false, start: 150 },
+        PbpTrack { number: 2, audio: true, start: (30 * 60 + 12) * 75 + 34 },
+    ]);
+    assert_eq!(
+        pbp_sheet(Path::new("d.bin"), &tracks),
+        "FILE \"d.bin\" BINARY\n  TRACK 01 MODE2/2352\n    INDEX 01 00:00:00\n  \
+         TRACK 02 AUDIO\n    INDEX 01 30:10:34\n",
+    );
+
+    // Lead-out before the tracks, as decrypting garbage would give
+    toc[27] = 0;
+    assert!(parse_pbp_toc(&toc).is_err());
+}
+
+#[test]
+fn edc() {
+    // Check value of the CRC-32/CD-ROM-EDC
+    assert_eq!(edc_update(0, b"123456789"), 0x6ec2_edc4);
+}
+
+#[test]
+fn spill_images() {
+    let cache_dir = std::env::temp_dir().join(format!("mips-spill-test-{}", std::process::id()));
+    let mut image = vec![0; 4 * SECTOR_SIZE];
+    image[..SYNC.len()].copy_from_slice(&SYNC);
+
+    let path = spill(&image, &cache_dir).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), image);
+    assert_eq!(sniff(&path).unwrap(), ImageFormat::RawBin);
+    // Written once
+    assert_eq!(spill(&image, &cache_dir).unwrap(), path);
+    image[SECTOR_SIZE] = 1;
+    assert_ne!(spill(&image, &cache_dir).unwrap(), path);
+
+    assert!(spill(b"FILE \"game.bin\" BINARY\n  TRACK 01 MODE2/2352\n", &cache_dir).is_err());
+
+    fs::remove_dir_all(&cache_dir).unwrap();
+}
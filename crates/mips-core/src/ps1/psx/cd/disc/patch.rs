@@ -0,0 +1,778 @@
+//! Disc image patches (PPF3 and xdelta), applied to the sectors as they're read so that
+//! translations and romhacks can be used without modifying the dump.
+//!
+//! Patches are made against the raw 2352 bytes/sector image of a single-bin dump, so offsets are
+//! counted from the start of the first track.
+
+use std::collections::HashMap;
+use std::io;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+use cdimage::{Bcd, CdResult, DiscPosition, Image, Msf, Sector, Toc};
+use log::{error, warn};
+use crate::error::{MipsError, MipsResult};
+use crate::ps1::Ps1Error;
+
+const SECTOR_SIZE: usize = 2352;
+
+/// Size of the largest disc image (100 minutes), no patch window can be bigger
+const MAX_IMAGE_LEN: u64 = 100 * 60 * 75 * SECTOR_SIZE as u64;
+
+/// Extensions of the patches picked up next to a disc image
+#[cfg(feature = "std")]
+const PATCH_EXTENSIONS: [&str; 3] = ["ppf", "xdelta", "vcdiff"];
+
+/// Look for a patch named after the disc image (`game.cue` -> `game.ppf`, ...)
+#[cfg(feature = "std")]
+pub fn find_patch(disc_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| disc_path.with_extension(ext))
+        .find(|path| path.is_file())
+}
+
+fn bad_patch(reason: impl Into<String>) -> MipsError {
+    MipsError::from(Ps1Error::BadDiscPatch(reason.into()))
+}
+
+/// Disc image with a patch applied on the fly
+pub struct PatchedImage {
+    image: Box<dyn Image + Send>,
+    patch: DiscPatch,
+    /// Sector index of the first sector of the first track
+    base: u32,
+}
+
+impl PatchedImage {
+    pub fn new(mut image: Box<dyn Image + Send>, patch: DiscPatch) -> MipsResult<PatchedImage> {
+        let base = Msf::from_sector_index(0)
+            .ok_or_else(|| bad_patch("invalid MSF"))
+            .and_then(|msf| {
+                image
+                    .toc()
+                    .track(Bcd::ONE)
+                    .and_then(|track| track.disc_position(msf))
+                    .map_err(|e| bad_patch(format!("can't locate the first track: {}", e)))
+            })?
+            .sector_index();
+
+        if let DiscPatch::Ppf(ppf) = &patch
+            && let Some(block) = &ppf.block_check
+        {
+            let mut source = ImageSource { image: &mut *image, base };
+            let mut expected = vec![0; block.len()];
+            let matches = source
+                .read_at(PPF_BLOCK_CHECK_OFFSET, &mut expected)
+                .is_ok_and(|_| expected == *block);
+
+            if !matches {
+                warn!("The PPF patch was made for a different image, it probably won't work");
+            }
+        }
+
+        Ok(PatchedImage { image, patch, base })
+    }
+}
+
+impl Image for PatchedImage {
+    fn image_format(&self) -> String {
+        format!("{} ({} patch)", self.image.image_format(), self.patch.format())
+    }
+
+    fn read_sector(&mut self, position: DiscPosition) -> CdResult<Sector> {
+        let mut sector = self.image.read_sector(position)?;
+
+        if let DiscPosition::Program(msf) = position
+            && let Some(lba) = msf.sector_index().checked_sub(self.base)
+        {
+            let mut source = ImageSource { image: &mut *self.image, base: self.base };
+            self.patch.apply(lba, sector.data_2352_mut(), &mut source)?;
+        }
+
+        Ok(sector)
+    }
+
+    fn toc(&self) -> &Toc {
+        self.image.toc()
+    }
+}
+
+/// Read access to the unpatched image as a flat file
+trait Source {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> CdResult<()>;
+}
+
+struct ImageSource<'a> {
+    image: &'a mut (dyn Image + Send),
+    base: u32,
+}
+
+impl Source for ImageSource<'_> {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> CdResult<()> {
+        let mut offset = offset;
+        let mut buf = buf;
+
+        while !buf.is_empty() {
+            let index = u32::try_from(offset / SECTOR_SIZE as u64)
+                .ok()
+                .and_then(|lba| self.base.checked_add(lba))
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "patch source offset out of range")
+                })?;
+            let start = (offset % SECTOR_SIZE as u64) as usize;
+            let len = buf.len().min(SECTOR_SIZE - start);
+
+            // Past the end of the disc, it can only be reached by a broken patch
+            let Some(msf) = Msf::from_sector_index(index) else {
+                buf.fill(0);
+                return Ok(());
+            };
+
+            let sector = self.image.read_sector(DiscPosition::Program(msf))?;
+            buf[..len].copy_from_slice(&sector.data_2352()[start..start + len]);
+
+            offset += len as u64;
+            buf = &mut buf[len..];
+        }
+
+        Ok(())
+    }
+}
+
+pub enum DiscPatch {
+    Ppf(Ppf),
+    Xdelta(Xdelta),
+}
+
+impl DiscPatch {
+    #[cfg(feature = "std")]
+    pub fn open(path: &Path) -> MipsResult<DiscPatch> {
+        let data = fs::read(path).map_err(|e| bad_patch(format!("{}: {}", path.display(), e)))?;
+
+        DiscPatch::parse(data)
+    }
+
+    /// Parse a patch, the format is detected from its header
+    pub fn parse(data: Vec<u8>) -> MipsResult<DiscPatch> {
+        if data.starts_with(b"PPF") {
+            Ppf::parse(&data).map(DiscPatch::Ppf)
+        } else if data.starts_with(&VCDIFF_MAGIC) {
+            Xdelta::parse(data).map(DiscPatch::Xdelta)
+        } else {
+            Err(bad_patch("unknown patch format"))
+        }
+    }
+
+    fn format(&self) -> &'static str {
+        match self {
+            DiscPatch::Ppf(_) => "PPF",
+            DiscPatch::Xdelta(_) => "xdelta",
+        }
+    }
+
+    /// Patch the raw data of sector `lba`, counted from the start of the first track
+    fn apply(&mut self, lba: u32, data: &mut [u8; SECTOR_SIZE], source: &mut dyn Source) -> CdResult<()> {
+        match self {
+            DiscPatch::Ppf(ppf) => {
+                ppf.apply(lba, data);
+                Ok(())
+            }
+            DiscPatch::Xdelta(xdelta) => xdelta.apply(lba, data, source),
+        }
+    }
+}
+
+/// Bounds-checked reader for the patch files
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> MipsResult<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len());
+        let end = end.ok_or_else(|| bad_patch("unexpected end of file"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> MipsResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u64_le(&mut self) -> MipsResult<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn u32_be(&mut self) -> MipsResult<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// VCDIFF integer: big-endian base 128, the high bit is set on all bytes but the last
+    fn varint(&mut self) -> MipsResult<u64> {
+        let mut v = 0u64;
+
+        loop {
+            let b = self.u8()?;
+            if v >> 57 != 0 {
+                return Err(bad_patch("integer overflow"));
+            }
+            v = (v << 7) | u64::from(b & 0x7f);
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+    }
+
+    fn size(&mut self) -> MipsResult<usize> {
+        usize::try_from(self.varint()?).map_err(|_| bad_patch("size overflow"))
+    }
+}
+
+/// The PPF3 block check holds 1024 bytes of the original image starting at this offset
+const PPF_BLOCK_CHECK_OFFSET: u64 = 0x9320;
+
+/// PlayStation Patch File, version 3
+pub struct Ppf {
+    /// Patched bytes as `(offset in the sector, data)`, per sector, in patch order
+    sectors: HashMap<u32, Vec<(usize, Vec<u8>)>>,
+    /// Part of the original image, used to check that the patch is applied to the right one
+    block_check: Option<Vec<u8>>,
+}
+
+impl Ppf {
+    fn parse(data: &[u8]) -> MipsResult<Ppf> {
+        let mut r = Reader::new(data);
+
+        match r.bytes(5)? {
+            b"PPF30" => (),
+            b"PPF10" | b"PPF20" => return Err(bad_patch("only PPF3 patches are supported")),
+            _ => return Err(bad_patch("bad PPF header")),
+        }
+
+        // Encoding method and description
+        r.bytes(1 + 50)?;
+        let _image_type = r.u8()?;
+        let block_check = r.u8()? != 0;
+        let undo = r.u8()? != 0;
+        let _dummy = r.u8()?;
+
+        let block_check = if block_check {
+            Some(r.bytes(1024)?.to_vec())
+        } else {
+            None
+        };
+
+        // The optional FILE_ID.DIZ is at the end of the file, followed by its length
+        let mut end = data.len();
+        if data.len() >= 6 && &data[end - 6..end - 2] == b".DIZ" {
+            let diz_len = usize::from(u16::from_le_bytes([data[end - 2], data[end - 1]]));
+            // "@BEGIN_FILE_ID.DIZ" + text + "@END_FILE_ID.DIZ" + length
+            end = end
+                .checked_sub(18 + diz_len + 16 + 2)
+                .ok_or_else(|| bad_patch("bad FILE_ID.DIZ"))?;
+        }
+        let mut r = Reader { data: &data[..end], pos: r.pos };
+
+        let mut sectors: HashMap<u32, Vec<(usize, Vec<u8>)>> = HashMap::new();
+
+        while !r.is_empty() {
+            let mut offset = r.u64_le()?;
+            let len = usize::from(r.u8()?);
+            let mut bytes = r.bytes(len)?;
+            if undo {
+                r.bytes(len)?;
+            }
+
+            // Records can cross sector boundaries
+            while !bytes.is_empty() {
+                let lba = u32::try_from(offset / SECTOR_SIZE as u64)
+                    .map_err(|_| bad_patch("offset out of range"))?;
+                let start = (offset % SECTOR_SIZE as u64) as usize;
+                let len = bytes.len().min(SECTOR_SIZE - start);
+
+                sectors.entry(lba).or_default().push((start, bytes[..len].to_vec()));
+
+                offset += len as u64;
+                bytes = &bytes[len..];
+            }
+        }
+
+        Ok(Ppf { sectors, block_check })
+    }
+
+    fn apply(&self, lba: u32, data: &mut [u8; SECTOR_SIZE]) {
+        for (start, bytes) in self.sectors.get(&lba).into_iter().flatten() {
+            data[*start..*start + bytes.len()].copy_from_slice(bytes);
+        }
+    }
+}
+
+const VCDIFF_MAGIC: [u8; 4] = [0xd6, 0xc3, 0xc4, 0x00];
+
+/// Header indicator bits
+const VCD_DECOMPRESS: u8 = 0x01;
+const VCD_CODETABLE: u8 = 0x02;
+/// xdelta3 extension: application data (the file names) follows the header
+const VCD_APPHEADER: u8 = 0x04;
+
+/// Window indicator bits
+const VCD_SOURCE: u8 = 0x01;
+const VCD_TARGET: u8 = 0x02;
+/// xdelta3 extension: the window holds an Adler-32 checksum of the target
+const VCD_ADLER32: u8 = 0x04;
+
+/// Windows are decoded when a sector they cover is read, this many are kept around
+const DECODED_WINDOWS: usize = 2;
+
+/// VCDIFF (RFC 3284) delta, as produced by xdelta3 without secondary compression
+pub struct Xdelta {
+    patch: Vec<u8>,
+    windows: Vec<Window>,
+    code_table: Box<CodeTable>,
+    /// Most recently used windows first, as `(window index, target data)`
+    decoded: Vec<(usize, Vec<u8>)>,
+}
+
+struct Window {
+    /// Offset of the window in the patched image
+    target_offset: u64,
+    target_len: usize,
+    /// Offset and length of the data copied from the original image
+    source: (u64, usize),
+    data: (usize, usize),
+    inst: (usize, usize),
+    addr: (usize, usize),
+    adler32: Option<u32>,
+}
+
+impl Xdelta {
+    fn parse(patch: Vec<u8>) -> MipsResult<Xdelta> {
+        let mut r = Reader::new(&patch);
+        r.bytes(VCDIFF_MAGIC.len())?;
+
+        let indicator = r.u8()?;
+        if indicator & VCD_DECOMPRESS != 0 {
+            // Only matters if a window uses it, checked below
+            r.u8()?;
+        }
+        if indicator & VCD_CODETABLE != 0 {
+            return Err(bad_patch("custom VCDIFF code tables aren't supported"));
+        }
+        if indicator & VCD_APPHEADER != 0 {
+            let len = r.size()?;
+            r.bytes(len)?;
+        }
+
+        let mut windows = Vec::new();
+        let mut target_offset = 0;
+
+        while !r.is_empty() {
+            let indicator = r.u8()?;
+            if indicator & VCD_TARGET != 0 {
+                return Err(bad_patch("copies from the target aren't supported"));
+            }
+
+            let source = if indicator & VCD_SOURCE != 0 {
+                let len = r.size()?;
+                (r.varint()?, len)
+            } else {
+                (0, 0)
+            };
+
+            let delta_len = r.size()?;
+            let delta_end = r.pos.saturating_add(delta_len);
+
+            let target_len = r.size()?;
+            if r.u8()? != 0 {
+                return Err(bad_patch("secondary compression isn't supported, use xdelta3 -S none"));
+            }
+            let data_len = r.size()?;
+            let inst_len = r.size()?;
+            let addr_len = r.size()?;
+            let adler32 = if indicator & VCD_ADLER32 != 0 {
+                Some(r.u32_be()?)
+            } else {
+                None
+            };
+
+            let data = r.pos;
+            r.bytes(data_len)?;
+            let inst = r.pos;
+            r.bytes(inst_len)?;
+            let addr = r.pos;
+            r.bytes(addr_len)?;
+
+            if r.pos != delta_end {
+                return Err(bad_patch("bad VCDIFF window length"));
+            }
+
+            // The windows are allocated when they're decoded, they must fit in a disc image
+            let fits = |offset: u64, len: usize| {
+                offset.checked_add(len as u64).is_some_and(|end| end <= MAX_IMAGE_LEN)
+            };
+            if !fits(source.0, source.1) || !fits(target_offset, target_len) {
+                return Err(bad_patch("VCDIFF window larger than a disc image"));
+            }
+
+            windows.push(Window {
+                target_offset,
+                target_len,
+                source,
+                data: (data, data_len),
+                inst: (inst, inst_len),
+                addr: (addr, addr_len),
+                adler32,
+            });
+
+            target_offset = target_offset
+                .checked_add(target_len as u64)
+                .ok_or_else(|| bad_patch("VCDIFF target too large"))?;
+        }
+
+        Ok(Xdelta {
+            patch,
+            windows,
+            code_table: Box::new(default_code_table()),
+            decoded: Vec::new(),
+        })
+    }
+
+    fn apply(&mut self, lba: u32, data: &mut [u8; SECTOR_SIZE], source: &mut dyn Source) -> CdResult<()> {
+        let start = u64::from(lba) * SECTOR_SIZE as u64;
+        let end = start + SECTOR_SIZE as u64;
+
+        // Windows are sorted by offset
+        let first = self.windows.partition_point(|w| w.target_offset + w.target_len as u64 <= start);
+
+        for index in first..self.windows.len() {
+            let window_start = self.windows[index].target_offset;
+            if window_start >= end {
+                break;
+            }
+
+            let Some(target) = self.window(index, source)? else {
+                // Leave the sector untouched rather than returning garbage
+                return Ok(());
+            };
+
+            let from = start.max(window_start);
+            let to = end.min(window_start + target.len() as u64);
+
+            data[(from - start) as usize..(to - start) as usize]
+                .copy_from_slice(&target[(from - window_start) as usize..(to - window_start) as usize]);
+        }
+
+        Ok(())
+    }
+
+    /// Decoded data of window `index`, `None` if it couldn't be decoded
+    fn window(&mut self, index: usize, source: &mut dyn Source) -> CdResult<Option<&[u8]>> {
+        match self.decoded.iter().position(|(i, _)| *i == index) {
+            Some(pos) => {
+                let entry = self.decoded.remove(pos);
+                self.decoded.insert(0, entry);
+            }
+            None => {
+                let window = &self.windows[index];
+                let mut source_data = vec![0; window.source.1];
+                source.read_at(window.source.0, &mut source_data)?;
+
+                let target = match self.decode(window, &source_data) {
+                    Ok(target) => target,
+                    Err(e) => {
+                        error!("Failed to decode xdelta window {}: {}", index, e);
+                        return Ok(None);
+                    }
+                };
+
+                if window.adler32.is_some_and(|sum| sum != adler32(&target)) {
+                    warn!("xdelta checksum mismatch, the patch was probably made for a different image");
+                }
+
+                self.decoded.insert(0, (index, target));
+                self.decoded.truncate(DECODED_WINDOWS);
+            }
+        }
+
+        Ok(Some(&self.decoded[0].1))
+    }
+
+    fn decode(&self, window: &Window, source: &[u8]) -> MipsResult<Vec<u8>> {
+        let section = |(start, len): (usize, usize)| Reader::new(&self.patch[start..start + len]);
+        let mut data = section(window.data);
+        let mut inst = section(window.inst);
+        let mut addr = section(window.addr);
+
+        let mut cache = AddressCache::default();
+        let mut target = Vec::with_capacity(window.target_len);
+
+        while !inst.is_empty() {
+            let index = usize::from(inst.u8()?);
+
+            for op in self.code_table[index] {
+                let size = match op.size {
+                    0 if op.kind != Inst::Noop => inst.size()?,
+                    size => usize::from(size),
+                };
+
+                if target.len() + size > window.target_len {
+                    return Err(bad_patch("window overflow"));
+                }
+
+                match op.kind {
+                    Inst::Noop => (),
+                    Inst::Add => target.extend_from_slice(data.bytes(size)?),
+                    Inst::Run => {
+                        let b = data.u8()?;
+                        target.resize(target.len() + size, b);
+                    }
+                    Inst::Copy => {
+                        let here = source.len() + target.len();
+                        let from = cache.decode(&mut addr, here, op.mode)?;
+                        if from >= here {
+                            return Err(bad_patch("copy from the future"));
+                        }
+
+                        // The copy can overlap the data it produces, go byte by byte
+                        for i in from..from + size {
+                            let b = if i < source.len() {
+                                source[i]
+                            } else {
+                                target[i - source.len()]
+                            };
+                            target.push(b);
+                        }
+                    }
+                }
+            }
+        }
+
+        if target.len() != window.target_len {
+            return Err(bad_patch("window underflow"));
+        }
+
+        Ok(target)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Inst {
+    Noop,
+    Add,
+    Run,
+    Copy,
+}
+
+#[derive(Clone, Copy)]
+struct Op {
+    kind: Inst,
+    /// 0 if the size follows in the instruction stream
+    size: u8,
+    mode: u8,
+}
+
+const NOOP: Op = Op { kind: Inst::Noop, size: 0, mode: 0 };
+
+type CodeTable = [[Op; 2]; 256];
+
+const NEAR_SIZE: usize = 4;
+const SAME_SIZE: usize = 3;
+
+/// RFC 3284 section 5.6
+fn default_code_table() -> CodeTable {
+    let op = |kind, size, mode| Op { kind, size, mode };
+    let mut table = Vec::with_capacity(256);
+
+    table.push([op(Inst::Run, 0, 0), NOOP]);
+    for size in 0..=17 {
+        table.push([op(Inst::Add, size, 0), NOOP]);
+    }
+
+    let modes = (2 + NEAR_SIZE + SAME_SIZE) as u8;
+
+    for mode in 0..modes {
+        table.push([op(Inst::Copy, 0, mode), NOOP]);
+        for size in 4..=18 {
+            table.push([op(Inst::Copy, size, mode), NOOP]);
+        }
+    }
+
+    for mode in 0..modes {
+        let copy_sizes = if mode < 6 { 4..=6 } else { 4..=4 };
+        for add_size in 1..=4 {
+            for copy_size in copy_sizes.clone() {
+                table.push([op(Inst::Add, add_size, 0), op(Inst::Copy, copy_size, mode)]);
+            }
+        }
+    }
+
+    for mode in 0..modes {
+        table.push([op(Inst::Copy, 4, mode), op(Inst::Add, 1, 0)]);
+    }
+
+    table.try_into().unwrap_or_else(|_| unreachable!("the default code table has 256 entries"))
+}
+
+struct AddressCache {
+    near: [usize; NEAR_SIZE],
+    next_slot: usize,
+    same: Vec<usize>,
+}
+
+impl Default for AddressCache {
+    fn default() -> Self {
+        AddressCache {
+            near: [0; NEAR_SIZE],
+            next_slot: 0,
+            same: vec![0; SAME_SIZE * 256],
+        }
+    }
+}
+
+impl AddressCache {
+    fn decode(&mut self, addr: &mut Reader, here: usize, mode: u8) -> MipsResult<usize> {
+        let mode = usize::from(mode);
+
+        let a = match mode {
+            // VCD_SELF
+            0 => addr.size()?,
+            // VCD_HERE
+            1 => here.checked_sub(addr.size()?).ok_or_else(|| bad_patch("bad address"))?,
+            m if m < 2 + NEAR_SIZE => self.near[m - 2].saturating_add(addr.size()?),
+            m => self.same[(m - 2 - NEAR_SIZE) * 256 + usize::from(addr.u8()?)],
+        };
+
+        self.near[self.next_slot] = a;
+        self.next_slot = (self.next_slot + 1) % NEAR_SIZE;
+        self.same[a % (SAME_SIZE * 256)] = a;
+
+        Ok(a)
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    // Small enough chunks for the sums not to overflow before the modulo
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+impl Source for Vec<u8> {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> CdResult<()> {
+        let offset = offset as usize;
+        buf.copy_from_slice(&self[offset..offset + buf.len()]);
+        Ok(())
+    }
+}
+
+#[test]
+fn ppf3() {
+    let mut ppf = b"PPF30\x02".to_vec();
+    ppf.extend_from_slice(&[b' '; 50]);
+    // BIN image, no block check, undo data
+    ppf.extend_from_slice(&[0, 0, 1, 0]);
+    // Crosses the boundary between the first two sectors
+    ppf.extend_from_slice(&(SECTOR_SIZE as u64 - 2).to_le_bytes());
+    ppf.extend_from_slice(&[4, 1, 2, 3, 4, 0, 0, 0, 0]);
+    ppf.extend_from_slice(&(SECTOR_SIZE as u64 * 2 + 16).to_le_bytes());
+    ppf.extend_from_slice(&[1, 0xaa, 0]);
+    ppf.extend_from_slice(b"@BEGIN_FILE_ID.DIZtest@END_FILE_ID.DIZ\x04\x00");
+
+    let mut patch = DiscPatch::parse(ppf).unwrap();
+    let mut source: Vec<u8> = Vec::new();
+
+    let mut sector = [0; SECTOR_SIZE];
+    patch.apply(0, &mut sector, &mut source).unwrap();
+    assert_eq!(sector[SECTOR_SIZE - 2..], [1, 2]);
+
+    let mut sector = [0; SECTOR_SIZE];
+    patch.apply(1, &mut sector, &mut source).unwrap();
+    assert_eq!(sector[..3], [3, 4, 0]);
+
+    let mut sector = [0; SECTOR_SIZE];
+    patch.apply(2, &mut sector, &mut source).unwrap();
+    assert_eq!(sector[16], 0xaa);
+    assert!(sector.iter().filter(|&&b| b != 0).count() == 1);
+}
+
+#[test]
+fn xdelta() {
+    let mut source: Vec<u8> = (0..SECTOR_SIZE * 2).map(|i| i as u8).collect();
+
+    // A single window: copy the first 4 bytes of the source, add 3 bytes, then a run of 0xee over
+    // the rest of the first sector and copy the second sector as is
+    let inst = [
+        20, // COPY 4, VCD_SELF
+        4,  // ADD 3
+        0, 0x92, 0x29, // RUN 2345
+        19, 0x92, 0x30, // COPY 2352, VCD_SELF
+    ];
+    let data = [0xa, 0xb, 0xc, 0xee];
+    let addr = [0x00, 0x92, 0x30];
+
+    let mut delta = Vec::new();
+    // Target window length: 4704
+    delta.extend_from_slice(&[0xa4, 0x60, 0]);
+    delta.extend_from_slice(&[data.len() as u8, inst.len() as u8, addr.len() as u8]);
+    delta.extend_from_slice(&data);
+    delta.extend_from_slice(&inst);
+    delta.extend_from_slice(&addr);
+
+    let mut vcdiff = VCDIFF_MAGIC.to_vec();
+    vcdiff.push(0);
+    // VCD_SOURCE, 4704 bytes from offset 0
+    vcdiff.extend_from_slice(&[VCD_SOURCE, 0xa4, 0x60, 0]);
+    vcdiff.push(delta.len() as u8);
+    vcdiff.extend_from_slice(&delta);
+
+    let mut patch = DiscPatch::parse(vcdiff).unwrap();
+
+    let mut sector = [0; SECTOR_SIZE];
+    patch.apply(0, &mut sector, &mut source).unwrap();
+    assert_eq!(sector[..8], [0, 1, 2, 3, 0xa, 0xb, 0xc, 0xee]);
+    assert_eq!(sector[SECTOR_SIZE - 1], 0xee);
+
+    let mut sector = [0; SECTOR_SIZE];
+    patch.apply(1, &mut sector, &mut source).unwrap();
+    assert_eq!(sector[..], source[SECTOR_SIZE..]);
+
+    // Past the end of the patched image
+    let mut sector = [0x55; SECTOR_SIZE];
+    patch.apply(2, &mut sector, &mut source).unwrap();
+    assert!(sector.iter().all(|&b| b == 0x55));
+}
+
+#[test]
+fn xdelta_oversized_window() {
+    let mut vcdiff = VCDIFF_MAGIC.to_vec();
+    vcdiff.push(0);
+    // VCD_SOURCE, 32GiB from offset 0
+    vcdiff.extend_from_slice(&[VCD_SOURCE, 0x81, 0x80, 0x80, 0x80, 0x80, 0x00, 0]);
+    // Empty delta
+    vcdiff.extend_from_slice(&[5, 0, 0, 0, 0, 0]);
+
+    assert!(DiscPatch::parse(vcdiff).is_err());
+}
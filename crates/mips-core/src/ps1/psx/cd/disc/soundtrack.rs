@@ -0,0 +1,79 @@
+//! CD-DA tracks replaced by the frontend's audio as the sectors are read: the drive seeks, loops
+//! and plays them as on the disc, and the CD volume registers and the SPU mixing still apply.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use cdimage::{CdResult, DiscPosition, Image, Sector, Toc};
+use crate::audio::{Soundtrack, TrackAudio};
+
+/// Stereo pairs in a CD-DA sector
+const PAIRS_PER_SECTOR: usize = 588;
+
+/// First 12 bytes of every data sector, never seen in the replaced audio
+const SYNC: [u8; 12] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+/// Disc image with its audio tracks replaced on the fly
+pub struct SoundtrackImage {
+    image: Box<dyn Image + Send>,
+    soundtrack: Arc<dyn Soundtrack>,
+    /// First sector and end of each track, in track order
+    tracks: Vec<(u32, u32)>,
+    /// The tracks opened so far, `None` for the ones the soundtrack doesn't replace
+    opened: HashMap<u8, Option<Box<dyn TrackAudio>>>,
+}
+
+impl SoundtrackImage {
+    pub fn new(image: Box<dyn Image + Send>, soundtrack: Arc<dyn Soundtrack>) -> SoundtrackImage {
+        let tracks = image.toc()
+            .tracks()
+            .iter()
+            .map(|t| (t.start.sector_index(), t.start.sector_index() + t.length.sector_index()))
+            .collect();
+
+        SoundtrackImage { image, soundtrack, tracks, opened: HashMap::new() }
+    }
+}
+
+impl Image for SoundtrackImage {
+    fn image_format(&self) -> String {
+        format!("{} (replaced soundtrack)", self.image.image_format())
+    }
+
+    fn read_sector(&mut self, position: DiscPosition) -> CdResult<Sector> {
+        let mut sector = self.image.read_sector(position)?;
+
+        let DiscPosition::Program(msf) = position else {
+            return Ok(sector);
+        };
+        let index = msf.sector_index();
+        let Some((n, &(start, _))) = self.tracks.iter()
+            .enumerate()
+            .find(|&(_, &(start, end))| index >= start && index < end)
+        else {
+            return Ok(sector);
+        };
+
+        // Data tracks are left alone, whatever the soundtrack says
+        let data = sector.data_2352_mut();
+        if data.starts_with(&SYNC) {
+            return Ok(sector);
+        }
+
+        let track = (n + 1) as u8;
+        let audio = self.opened.entry(track).or_insert_with(|| self.soundtrack.open_track(track));
+        if let Some(audio) = audio {
+            let mut samples = [0; PAIRS_PER_SECTOR * 2];
+            audio.read(u64::from(index - start) * PAIRS_PER_SECTOR as u64, &mut samples);
+
+            for (bytes, sample) in data.chunks_exact_mut(2).zip(samples) {
+                bytes.copy_from_slice(&sample.to_le_bytes());
+            }
+        }
+
+        Ok(sector)
+    }
+
+    fn toc(&self) -> &Toc {
+        self.image.toc()
+    }
+}
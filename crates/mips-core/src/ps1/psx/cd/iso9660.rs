@@ -86,7 +86,6 @@ impl Directory {
 
     /// Attempt to "cd" to a subdirectory, returning a new `Directory`
     /// instance
-    #[allow(dead_code)]
     pub fn cd(&self, image: &mut CdCache, name: &[u8]) -> Result<Directory, IsoError> {
         let entry = self.entry_by_name(name)?;
 
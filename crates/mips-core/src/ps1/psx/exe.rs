@@ -2,11 +2,11 @@
 //! executables. This doesn't emulate any real world hardware, it's
 //! inspired by mednafen's method of loading EXEs.
 
-use std::path::Path;
-use std::fs::File;
-use std::io;
+pub mod patch;
+
 use std::io::Read;
 use log::info;
+use crate::cheats::CheatMemory;
 use crate::error::{MipsError, MipsResult};
 use crate::ps1::Ps1Error;
 use crate::ps1::psx::assembler::{Assembler, syntax::*};
@@ -15,7 +15,9 @@ use crate::ps1::psx::bus::Bus;
 use crate::ps1::psx::cd::disc::Region;
 use crate::ps1::psx::memory;
 use crate::ps1::psx::processor::RegisterIndex;
-use crate::ps1::util::fs::file::bin;
+
+/// First bytes of every PS-EXE
+pub const MAGIC: &[u8; 16] = b"PS-X EXE\0\0\0\0\0\0\0\0";
 
 pub struct Exe {
     /// Base address/dest addr in ram for the executable
@@ -38,12 +40,18 @@ pub struct Exe {
 }
 
 impl Exe {
-    pub fn new(path: &Path)  -> MipsResult<Exe> {
-        let mut bin =  bin::get_file(path)?;
+    /// Parse an executable already in memory (patched, extracted from a disc...)
+    pub fn from_bytes(data: &[u8]) -> MipsResult<Exe> {
+        // Header
+        if data.len() < 2048 {
+            return Err(MipsError::from(Ps1Error::BadExe))
+        }
+
+        let mut bin = data;
 
         let mut buf = [0; 16];
         bin.read_exact(&mut buf);
-        if &buf != b"PS-X EXE\0\0\0\0\0\0\0\0" {
+        if &buf != MAGIC {
             // Bad magic, this is not a PlayStation executable
             return Err(MipsError::from(Ps1Error::BadExe))
         }
@@ -58,8 +66,8 @@ impl Exe {
 
         // Let's be on the safe side and reject anormaly big
         // programs. Since the PlayStation RAM is 2MB big it doesn't
-        // make sense to have bigger programs
-        if text_len > 2 * 1024 * 1024 {
+        // make sense to have programs that don't fit in it
+        if text_len > RAM_LEN - (base & 0x1f_ffff) {
             return Err(MipsError::from(Ps1Error::BadExe));
         }
 
@@ -69,11 +77,12 @@ impl Exe {
         read_u32(&mut bin)?;
 
         let memfill_base = read_u32(&mut bin)?;
-        let memfill_len = read_u32(&mut bin)?;
+        // The fill stops at the end of the RAM, it doesn't wrap around
+        let memfill_len = read_u32(&mut bin)?.min(RAM_LEN - (memfill_base & 0x1f_ffff));
 
         // For some reason the initial SP address comes with an
         // "offset" (per No$), not sure what that's for
-        let initial_sp = read_u32(&mut bin)? + read_u32(&mut bin)?;
+        let initial_sp = read_u32(&mut bin)?.wrapping_add(read_u32(&mut bin)?);
 
         // The next 20bytes are padding
         bin.read_exact(&mut [0; 20]);
@@ -293,6 +302,30 @@ impl Exe {
         self.region
     }
 
+    /// Parts of the text used to tell when the executable has been copied in RAM: the bytes
+    /// around its first and last non-zero ones, since the BIOS copies it in order
+    pub fn signature(&self) -> ExeSignature {
+        const LEN: usize = 64;
+
+        let first = self.text.iter().position(|&b| b != 0);
+        let last = self.text.iter().rposition(|&b| b != 0);
+
+        let chunks = match (first, last) {
+            (Some(first), Some(last)) => {
+                let head = first..(first + LEN).min(self.text.len());
+                let tail = (last + 1).saturating_sub(LEN)..last + 1;
+
+                [head, tail]
+                    .into_iter()
+                    .map(|r| (self.base.wrapping_add(r.start as u32), self.text[r].to_vec()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        ExeSignature { chunks }
+    }
+
     /// Patch the BIOS animation jump to run the loader code
     /// instead. Returns an error if the patching failed.
     pub fn patch_bios(&self, bios: &mut Bios) {
@@ -319,19 +352,45 @@ impl Exe {
     }
 }
 
+pub struct ExeSignature {
+    /// RAM address and expected contents
+    chunks: Vec<(u32, Vec<u8>)>,
+}
+
+impl ExeSignature {
+    pub fn is_loaded(&self, mem: &dyn CheatMemory) -> bool {
+        self.chunks.iter().all(|(addr, bytes)| {
+            bytes
+                .iter()
+                .enumerate()
+                .all(|(i, &b)| mem.read8(addr.wrapping_add(i as u32) & 0x1f_ffff) == b)
+        })
+    }
+}
+
+/// Copy the executable in RAM and jump to its entry point, in place of the one the BIOS would
+/// boot
 pub fn sideload(bus: &mut Bus) {
     if let Some(exe) = &bus.exe {
+        info!("Sideloading PS-EXE, ENTRY=0x{:08x}", exe.entry);
+
+        if exe.memfill_len != 0 {
+            let zeros = vec![0; exe.memfill_len as usize];
+            bus.xmem.ram_store_block(exe.memfill_base, &zeros, zeros.len());
+        }
         bus.xmem.ram_store_block(exe.base, exe.text.as_slice(), exe.text.len());
         bus.cpu.set_reg(RegisterIndex(28), exe.initial_gp);
         if exe.initial_sp != 0 {
             bus.cpu.set_reg(RegisterIndex(29), exe.initial_sp);
             bus.cpu.set_reg(RegisterIndex(30), exe.initial_sp);
-            bus.cpu.pc = exe.entry;
         }
+        // The instruction in the pipeline is dropped
+        bus.cpu.pc = exe.entry;
+        bus.cpu.next_pc = exe.entry.wrapping_add(4);
     }
 }
 
-fn read_u32(f: &mut File) -> MipsResult<u32> {
+fn read_u32(f: &mut impl Read) -> MipsResult<u32> {
     let mut b = [0; 4];
 
     f.read_exact(&mut b).unwrap();
@@ -342,6 +401,10 @@ fn read_u32(f: &mut File) -> MipsResult<u32> {
         | ((b[3] as u32) << 24))
 }
 
+/// Size of the RAM, the code and the memfill region of the EXE
+/// must fit in it
+const RAM_LEN: u32 = 2 * 1024 * 1024;
+
 /// Offset of the register containing the machine code FIFO for
 /// loading the EXE
 const EXE_FIFO_OFFSET: u32 = 0x100;
@@ -0,0 +1,233 @@
+//! IPS and BPS patches for executables. They're small enough to be patched in memory as a whole
+//! before being loaded.
+
+use crate::error::{MipsError, MipsResult};
+use crate::ps1::Ps1Error;
+
+fn bad_patch(reason: &str) -> MipsError {
+    MipsError::from(Ps1Error::BadExePatch(reason.to_string()))
+}
+
+/// Apply an IPS or BPS `patch` to `data`, the format is detected from the header
+pub fn apply(data: &[u8], patch: &[u8]) -> MipsResult<Vec<u8>> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(data, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(data, patch)
+    } else {
+        Err(bad_patch("unknown patch format"))
+    }
+}
+
+fn apply_ips(data: &[u8], patch: &[u8]) -> MipsResult<Vec<u8>> {
+    let mut out = data.to_vec();
+    let mut r = Reader { data: patch, pos: 5 };
+
+    loop {
+        let offset = r.bytes(3)?;
+        if offset == b"EOF" {
+            break;
+        }
+        let offset = usize::from(offset[0]) << 16 | usize::from(offset[1]) << 8 | usize::from(offset[2]);
+
+        let size = r.bytes(2)?;
+        let size = usize::from(u16::from_be_bytes([size[0], size[1]]));
+
+        // A size of 0 introduces a run of the same byte
+        let (len, fill) = if size == 0 {
+            let run = r.bytes(3)?;
+            (usize::from(u16::from_be_bytes([run[0], run[1]])), Some(run[2]))
+        } else {
+            (size, None)
+        };
+
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+
+        match fill {
+            Some(b) => out[offset..offset + len].fill(b),
+            None => out[offset..offset + len].copy_from_slice(r.bytes(len)?),
+        }
+    }
+
+    // Extension: the new length of the file can follow the end marker
+    if let Ok(len) = r.bytes(3) {
+        out.truncate(usize::from(len[0]) << 16 | usize::from(len[1]) << 8 | usize::from(len[2]));
+    }
+
+    Ok(out)
+}
+
+fn apply_bps(data: &[u8], patch: &[u8]) -> MipsResult<Vec<u8>> {
+    // Source, target and patch CRC32s
+    const FOOTER_LEN: usize = 12;
+
+    if patch.len() < 4 + FOOTER_LEN {
+        return Err(bad_patch("file too short"));
+    }
+
+    let footer = &patch[patch.len() - FOOTER_LEN..];
+    let crc = |i: usize| u32::from_le_bytes(footer[i * 4..i * 4 + 4].try_into().unwrap());
+
+    if crc32(&patch[..patch.len() - 4]) != crc(2) {
+        return Err(bad_patch("corrupted patch"));
+    }
+    if crc32(data) != crc(0) {
+        return Err(bad_patch("the patch was made for a different executable"));
+    }
+
+    let mut r = Reader { data: &patch[..patch.len() - FOOTER_LEN], pos: 4 };
+
+    let source_len = r.number()?;
+    let target_len = r.number()?;
+    let metadata_len = r.number()?;
+    r.bytes(metadata_len)?;
+
+    if source_len != data.len() {
+        return Err(bad_patch("the patch was made for a different executable"));
+    }
+
+    let oob = || bad_patch("out of bounds access");
+
+    let mut out = Vec::with_capacity(target_len);
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+
+    while r.pos < r.data.len() {
+        let action = r.number()?;
+        let len = (action >> 2) + 1;
+
+        if out.len() + len > target_len {
+            return Err(bad_patch("target overflow"));
+        }
+
+        match action & 3 {
+            // SourceRead
+            0 => {
+                let start = out.len();
+                out.extend_from_slice(data.get(start..start + len).ok_or_else(oob)?);
+            }
+            // TargetRead
+            1 => out.extend_from_slice(r.bytes(len)?),
+            // SourceCopy
+            2 => {
+                source_offset = r.relative(source_offset)?;
+                out.extend_from_slice(data.get(source_offset..source_offset + len).ok_or_else(oob)?);
+                source_offset += len;
+            }
+            // TargetCopy, the copy can overlap the data it produces
+            _ => {
+                target_offset = r.relative(target_offset)?;
+                for _ in 0..len {
+                    let b = *out.get(target_offset).ok_or_else(oob)?;
+                    out.push(b);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_len || crc32(&out) != crc(1) {
+        return Err(bad_patch("bad patch output"));
+    }
+
+    Ok(out)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> MipsResult<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len());
+        let end = end.ok_or_else(|| bad_patch("unexpected end of file"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// BPS number: little-endian base 128, the high bit is set on the last byte. Each extra byte also adds
+    /// its weight so that there's a single encoding for each value.
+    fn number(&mut self) -> MipsResult<usize> {
+        let overflow = || bad_patch("number overflow");
+        let mut n = 0usize;
+        let mut shift = 1usize;
+
+        loop {
+            let b = self.bytes(1)?[0];
+
+            n = usize::from(b & 0x7f)
+                .checked_mul(shift)
+                .and_then(|v| v.checked_add(n))
+                .ok_or_else(overflow)?;
+            if b & 0x80 != 0 {
+                return Ok(n);
+            }
+            shift = shift.checked_mul(128).ok_or_else(overflow)?;
+            n = n.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+
+    /// BPS copy offset: relative to the end of the previous copy as sign + magnitude
+    fn relative(&mut self, offset: usize) -> MipsResult<usize> {
+        let n = self.number()?;
+        let delta = n >> 1;
+
+        let offset = if n & 1 != 0 {
+            offset.checked_sub(delta)
+        } else {
+            offset.checked_add(delta)
+        };
+
+        offset.ok_or_else(|| bad_patch("out of bounds access"))
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+#[test]
+fn ips_and_bps() {
+    let data = b"PS-X EXE hello world".to_vec();
+
+    let mut ips = b"PATCH".to_vec();
+    // Write "HELLO" at 9, then a run of 3 '!' past the end
+    ips.extend_from_slice(&[0, 0, 9, 0, 5]);
+    ips.extend_from_slice(b"HELLO");
+    ips.extend_from_slice(&[0, 0, 20, 0, 0, 0, 3, b'!']);
+    ips.extend_from_slice(b"EOF");
+
+    let patched = apply(&data, &ips).unwrap();
+    assert_eq!(patched, b"PS-X EXE HELLO world!!!");
+
+    // SourceRead 9, TargetRead "HELLO", SourceCopy 6 from 14 then TargetCopy 3 from 19, which
+    // overlaps the bytes it produces
+    let mut bps = b"BPS1".to_vec();
+    bps.extend_from_slice(&[0x80 | 20, 0x80 | 23, 0x80]);
+    bps.push(0x80 | (8 << 2));
+    bps.push(0x80 | (4 << 2 | 1));
+    bps.extend_from_slice(b"HELLO");
+    bps.extend_from_slice(&[0x80 | (5 << 2 | 2), 0x80 | (14 << 1)]);
+    bps.extend_from_slice(&[0x80 | (2 << 2 | 3), 0x80 | (19 << 1)]);
+    bps.extend_from_slice(&crc32(&data).to_le_bytes());
+    bps.extend_from_slice(&crc32(b"PS-X EXE HELLO worldddd").to_le_bytes());
+    bps.extend_from_slice(&crc32(&bps).to_le_bytes());
+
+    let patched = apply(&data, &bps).unwrap();
+    assert_eq!(patched, b"PS-X EXE HELLO worldddd");
+
+    assert!(apply(b"another executable", &bps).is_err());
+}
@@ -1,4 +1,3 @@
 pub mod gpu;
 mod commands;
 mod fifo;
-pub mod rasterizer;
\ No newline at end of file
@@ -5,8 +5,13 @@ use log::warn;
 use crate::ps1::psx::bios::bios::Bios;
 use crate::ps1::psx::bus::Bus;
 use crate::ps1::psx::cd;
-use crate::ps1::psx::graphics::gpu::{DrawMode, State};
+use crate::ps1::psx::graphics::gpu::State;
 use crate::ps1::psx::processor::ClockCycle;
+use mips_psx_gpu::regs::DrawMode;
+use mips_psx_gpu::primitive::{
+    vram_access_dimensions, NoShading, NoTexture, Opaque, Position, Shaded, ShadingMode, TextureBlending,
+    TextureMode, TextureRaw, TransparencyMode, Transparent,
+};
 
 /// Description of the various GP0 commands
 pub struct Command {
@@ -35,111 +40,6 @@ impl Command {
     }
 }
 
-pub trait TransparencyMode {
-    fn is_transparent() -> bool;
-}
-
-pub struct Transparent;
-
-impl TransparencyMode for Transparent {
-    fn is_transparent() -> bool {
-        true
-    }
-}
-
-pub struct Opaque;
-
-impl TransparencyMode for Opaque {
-    fn is_transparent() -> bool {
-        false
-    }
-}
-
-pub trait TextureMode {
-    fn is_textured() -> bool;
-    fn is_raw_texture() -> bool;
-}
-
-pub struct NoTexture;
-
-impl TextureMode for NoTexture {
-    fn is_textured() -> bool {
-        false
-    }
-
-    fn is_raw_texture() -> bool {
-        false
-    }
-}
-
-pub struct TextureBlending;
-
-impl TextureMode for TextureBlending {
-    fn is_textured() -> bool {
-        true
-    }
-
-    fn is_raw_texture() -> bool {
-        false
-    }
-}
-
-pub struct TextureRaw;
-
-impl TextureMode for TextureRaw {
-    fn is_textured() -> bool {
-        true
-    }
-
-    fn is_raw_texture() -> bool {
-        true
-    }
-}
-
-pub trait ShadingMode {
-    fn is_shaded() -> bool;
-}
-
-pub struct NoShading;
-
-impl ShadingMode for NoShading {
-    fn is_shaded() -> bool {
-        false
-    }
-}
-
-pub struct Shaded;
-
-impl ShadingMode for Shaded {
-    fn is_shaded() -> bool {
-        true
-    }
-}
-
-/// A vertex's coordinates
-#[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
-pub struct Position {
-    pub x: i32,
-    pub y: i32,
-}
-
-impl Position {
-    pub fn new(x: i32, y: i32) -> Position {
-        Position { x, y }
-    }
-
-    pub fn from_command(c: u32) -> Position {
-        let x = c;
-        let y = c >> 16;
-
-        // XXX The coordinates each take 16bits and are signed, however mednafen extends the sign
-        // starting on bit 11, not 15.
-        let x = extend_to_i32(x, 11);
-        let y = extend_to_i32(y, 11);
-        Position::new(x, y)
-    }
-}
-
 /// Extend a signed value on `n` bit to an i32
 fn extend_to_i32(val: u32, n: usize) -> i32 {
     let shift = 32 - n;
@@ -324,6 +224,36 @@ where
     draw_time
 }
 
+/// Send the rasterizer the precise positions (PGXP) of the vertices of the polygon at the front of
+/// the FIFO that the GTE projected, before the polygon itself
+fn send_precise_vertices<Texture, Shading>(bus: &mut Bus, nvertices: usize)
+where
+    Texture: TextureMode,
+    Shading: ShadingMode,
+{
+    if !bus.gte.pgxp().is_enabled() {
+        return;
+    }
+
+    let mut index = 0;
+
+    for v in 0..nvertices {
+        if v == 0 || Shading::is_shaded() {
+            // Color (or command) word
+            index += 1;
+        }
+
+        if let Some(vertex) = bus.gte.pgxp().lookup(bus.gpu.command_fifo.peek_at(index)) {
+            bus.gpu.rasterizer.push_precise_vertex(v, vertex);
+        }
+        index += 1;
+
+        if Texture::is_textured() {
+            index += 1;
+        }
+    }
+}
+
 fn cmd_handle_poly_tri<Transparency, Texture, Shading>(bus: &mut Bus)
 where
     Transparency: TransparencyMode,
@@ -336,6 +266,8 @@ where
         Position::new(0, 0),
     ];
 
+    send_precise_vertices::<Texture, Shading>(bus, coords.len());
+
     // Load the triangle coordinates. Since we only care about timings here we don't need to load
     // the texture coordinates or anything.
     for (v, coord) in coords.iter_mut().enumerate() {
@@ -382,6 +314,8 @@ where
         Position::new(0, 0),
     ];
 
+    send_precise_vertices::<Texture, Shading>(bus, coords.len());
+
     // Load the triangle coordinates. Since we only care about timings here we don't need to load
     // the texture coordinates or anything.
     //
@@ -621,30 +555,6 @@ where
     bus.gpu.state = State::PolyLine(opcode, end_pos);
 }
 
-/// Parses the command word for a VRAM store, load or copy and returns the dimensions of the target
-/// rectangle
-pub fn vram_access_dimensions(dim: u32, is_load: bool) -> (i32, i32) {
-    // Width is in GPU pixels, i.e. 16bits per pixel
-    let mut width = dim & 0x3ff;
-    let mut height = (dim >> 16) & 0x1ff;
-
-    // XXX recheck this, a comment in mednafen says that the results for VRAM load are inconsistent
-    if width == 0 {
-        width = 1024;
-    }
-
-    // XXX not sure about this difference, taken from mednafen
-    if is_load {
-        if height > 0x200 {
-            height &= 0x1ff;
-        }
-    } else if height == 0 {
-        height = 512;
-    }
-
-    (width as i32, height as i32)
-}
-
 /// Parses the command word for a VRAM store or load and returns the number of words about to be
 /// read/written
 fn vram_access_length_words(dim: u32, is_load: bool) -> i32 {
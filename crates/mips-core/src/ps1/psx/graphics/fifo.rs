@@ -71,6 +71,16 @@ impl CommandFifo {
 
         self.buffer[i as usize]
     }
+
+    /// Returns the element `offset` entries after the top of the FIFO without popping anything.
+    /// Should *not* be called with fewer than `offset + 1` elements in the FIFO!
+    pub fn peek_at(&self, offset: usize) -> u32 {
+        debug_assert!(offset < self.len());
+
+        let i = self.read_index.wrapping_add(offset as u8) % COMMAND_FIFO_DEPTH as u8;
+
+        self.buffer[i as usize]
+    }
 }
 
 #[test]
@@ -110,6 +120,7 @@ fn test_command_fifo() {
     assert!(fifo.is_full());
     assert!(!fifo.is_empty());
     assert_eq!(fifo.len(), COMMAND_FIFO_DEPTH);
+    assert_eq!(fifo.peek_at(3), 3);
 
     // Empty
     for i in 0..COMMAND_FIFO_DEPTH {
@@ -1,14 +1,18 @@
 use log::warn;
-use crate::ps1::psx::addressable::{AccessWidth, Addressable};
+use mips_r3000::addressable::{AccessWidth, Addressable};
 use crate::ps1::psx::bus::Bus;
-use crate::ps1::psx::graphics::rasterizer::handle::{Frame, Handle, RasterizerOption};
-use crate::ps1::psx::graphics::{commands, fifo, rasterizer};
-use crate::ps1::psx::graphics::commands::{Command, Position};
+use mips_psx_gpu::handle::{self, Frame, RasterizerOption};
+use mips_psx_gpu::primitive::Position;
+use mips_psx_gpu::regs::{DisplayMode, DrawMode, MaskSettings, TextureWindow};
+use crate::ps1::psx::graphics::{commands, fifo};
+use crate::ps1::psx::graphics::commands::Command;
+use crate::ps1::psx::pad_memcard::lightgun::BeamPosition;
 use crate::ps1::psx::processor::{irq, ClockCycle};
 use crate::ps1::psx::processor::cpu::CPU_FREQ_HZ;
 use crate::ps1::psx::{sync, timers};
-use crate::ps1::psx::graphics::rasterizer::draw::rasterizer::Pixel;
-use crate::ps1::psx::graphics::rasterizer::handle;
+use crate::ps1::settings::graphics::RendererBackend;
+
+pub use mips_psx_gpu::regs::VideoStandard;
 
 const GPUSYNC: sync::SyncToken = sync::SyncToken::Gpu;
 
@@ -17,6 +21,17 @@ pub struct Gpu {
     pub(crate) state: State,
     pub(crate) rasterizer: handle::Handle,
     video_standard: VideoStandard,
+    /// Run with the NTSC clock and line counts whatever the console and the display mode, so that
+    /// PAL games get 60 fields per second
+    force_ntsc_timing: bool,
+    /// Draw commands only update the state, see `set_skip_draws`
+    skip_draws: bool,
+    /// True if drawing was skipped at some point of the current frame
+    frame_draws_skipped: bool,
+    /// True if drawing was skipped during the previous frame
+    last_frame_draws_skipped: bool,
+    /// True if the last frame sent shows a buffer drawn while drawing was skipped
+    hide_frame: bool,
     /// Current value of the display mode
     pub(crate) display_mode: DisplayMode,
     /// Number of the first line displayed on the screen
@@ -98,6 +113,11 @@ impl Gpu {
             state: State::Idle,
             rasterizer: handle::start(),
             video_standard,
+            force_ntsc_timing: false,
+            skip_draws: false,
+            frame_draws_skipped: false,
+            last_frame_draws_skipped: false,
+            hide_frame: false,
             display_mode: DisplayMode::new(),
             display_line_start: 0x10,
             display_line_end: 0x100,
@@ -145,6 +165,88 @@ impl Gpu {
         self.video_standard
     }
 
+    pub fn set_force_ntsc_timing(&mut self, force: bool) {
+        self.force_ntsc_timing = force;
+        self.refresh_lines_per_field();
+    }
+
+    /// Standard of the GPU clock
+    fn clock_standard(&self) -> VideoStandard {
+        if self.force_ntsc_timing {
+            VideoStandard::Ntsc
+        } else {
+            self.video_standard
+        }
+    }
+
+    /// Standard of the line timings, selected by the display mode
+    fn line_standard(&self) -> VideoStandard {
+        if self.force_ntsc_timing {
+            VideoStandard::Ntsc
+        } else {
+            self.display_mode.standard()
+        }
+    }
+
+    /// First and last+1 lines of the active display area
+    fn display_lines(&self) -> (u16, u16) {
+        let shift = self.display_line_shift();
+
+        (self.display_line_start.saturating_sub(shift), self.display_line_end - shift)
+    }
+
+    /// With NTSC timings forced the display area a PAL game sets up goes past the end of the
+    /// shorter field, it's moved up by that many lines to fit (leaving a few lines of blanking) or
+    /// the VBlank would never come. The rasterizer still gets the lines numbered as the game set
+    /// them up.
+    fn display_line_shift(&self) -> u16 {
+        /// Last line of the active area in an NTSC field
+        const NTSC_LAST_LINE: u16 = 259;
+
+        if !self.force_ntsc_timing || self.display_mode.standard() != VideoStandard::Pal {
+            return 0;
+        }
+
+        self.display_line_end.saturating_sub(NTSC_LAST_LINE)
+    }
+
+    /// Beam position under the point at fraction `(x, y)` of the display area, from the top left.
+    /// `None` if the point is outside the display area.
+    pub fn aim(&self, x: f32, y: f32) -> Option<BeamPosition> {
+        let (line_start, line_end) = self.display_lines();
+        let (column_start, column_end) = (self.display_column_start, self.display_column_end);
+
+        let on_screen = (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y);
+
+        if !on_screen || line_end <= line_start || column_end <= column_start {
+            return None;
+        }
+
+        let line = line_start + (f32::from(line_end - line_start) * y) as u16;
+        let column = column_start + (f32::from(column_end - column_start) * x) as u16;
+
+        Some(BeamPosition { line, column })
+    }
+
+    /// GPU cycles since the start of the current line. Only meaningful outside of the hsync, where
+    /// `cycles_to_line_event` counts down to it.
+    fn beam_column(&self) -> ClockCycle {
+        ClockCycle::from(self.line_length()) - HSYNC_LEN_CYCLES - self.cycles_to_line_event
+    }
+
+    /// GPU cycles until the beam reaches `aim` if it's further along the current line
+    fn cycles_to_beam(&self, aim: Option<BeamPosition>) -> Option<ClockCycle> {
+        let aim = aim?;
+
+        if self.in_hsync || aim.line != self.cur_line {
+            return None;
+        }
+
+        let ahead = ClockCycle::from(aim.column) - self.beam_column();
+
+        (ahead > 0).then_some(ahead)
+    }
+
     pub fn take_frame(&mut self) -> Option<Frame> {
         // If we were waiting for a VRAM read we must fetch it before attempting to recover a
         // frame, otherwise we'll receive the VRAM read from the rasterizer and think that it's a
@@ -154,13 +256,33 @@ impl Gpu {
             self.state = State::VRamLoad(Some((frame, 0)));
         }
 
-        self.rasterizer.take_frame()
+        let frame = self.rasterizer.take_frame()?;
+
+        (!self.hide_frame).then_some(frame)
+    }
+
+    /// Frame skip: the draw commands still take their time and update the GPU state, but nothing
+    /// is drawn. Transfers to and from the VRAM still happen. The frames following one with
+    /// skipped drawing aren't returned by `take_frame`: games usually display the buffer drawn
+    /// during the previous frame, it would show an older picture.
+    pub fn set_skip_draws(&mut self, skip: bool) {
+        if skip != self.skip_draws {
+            self.skip_draws = skip;
+            self.rasterizer.set_option(RasterizerOption::SkipDraws(skip));
+        }
+
+        self.frame_draws_skipped |= skip;
     }
 
     pub fn set_rasterizer_option(&mut self, opt: RasterizerOption) {
         self.rasterizer.set_option(opt)
     }
 
+    /// Draw with `backend` from now on, see `Handle::set_renderer`
+    pub fn set_renderer(&mut self, backend: RendererBackend) -> bool {
+        self.rasterizer.set_renderer(backend)
+    }
+
     /// Pop a command from the `command_fifo` and return it while also sending it to the rasterizer
     /// as a side effect.
     pub(crate) fn command_pop_to_rasterizer(&mut self) -> u32 {
@@ -208,7 +330,7 @@ impl Gpu {
     fn status(&self) -> u32 {
         let mut s = 0;
 
-        s |= self.draw_mode.0 & 0x7ff;
+        s |= self.draw_mode.raw() & 0x7ff;
 
         s |= (self.mask_settings.draw_with_mask_bit() as u32) << 11;
         s |= (self.mask_settings.check_mask_bit() as u32) << 12;
@@ -220,8 +342,8 @@ impl Gpu {
 
         s |= (self.draw_mode.texture_disable() as u32) << 15;
 
-        s |= ((self.display_mode.0 >> 6) & 1) << 16;
-        s |= (self.display_mode.0 & 0x3f) << 17;
+        s |= ((self.display_mode.raw() >> 6) & 1) << 16;
+        s |= (self.display_mode.raw() & 0x3f) << 17;
 
         s |= (self.display_off as u32) << 23;
         // TODO: bit 24 - IRQ1 (*not* VSync)
@@ -340,7 +462,7 @@ impl Gpu {
     /// Returns the number of GPU cycles elapsed while the CPU ran `cpu_cyles`. Any fractional
     /// leftover cycle will be stored in `remaining_fractional_cycles`
     fn tick(&mut self, cpu_cycles: ClockCycle) -> ClockCycle {
-        let clock_ratio = match self.video_standard {
+        let clock_ratio = match self.clock_standard() {
             VideoStandard::Ntsc => GPU_CYCLES_PER_CPU_CYCLES_NTSC,
             VideoStandard::Pal => GPU_CYCLES_PER_CPU_CYCLES_PAL,
         };
@@ -357,7 +479,7 @@ impl Gpu {
 
     /// Returns the total length of a line (including horizontal blanking)
     fn line_length(&self) -> u16 {
-        match self.display_mode.standard() {
+        match self.line_standard() {
             // I'm not really sure what justifies this `line_phase` business but that's what
             // mednafen does. Maybe the real value is close to 3412.5 and therefore we have close
             // to one full cycle added every other cycle?
@@ -369,14 +491,14 @@ impl Gpu {
     /// Refresh the value of `lines_per_field` based on the display mode
     fn refresh_lines_per_field(&mut self) {
         self.lines_per_field = if self.display_mode.is_interlaced() {
-            let l = match self.display_mode.standard() {
+            let l = match self.line_standard() {
                 VideoStandard::Ntsc => 263,
                 VideoStandard::Pal => 313,
             };
 
             l - self.bottom_field as u16
         } else {
-            match self.display_mode.standard() {
+            match self.line_standard() {
                 VideoStandard::Ntsc => 263,
                 VideoStandard::Pal => 314,
             }
@@ -408,7 +530,7 @@ impl Gpu {
     fn gp1_get_info(&mut self, val: u32) {
         // XXX what happens if we're in the middle of a framebuffer read?
         let v = match val & 0xf {
-            2 => self.tex_window.0,
+            2 => self.tex_window.raw(),
             3 => {
                 let top = self.clip_y_min as u32;
                 let left = self.clip_x_min as u32;
@@ -447,7 +569,12 @@ pub fn run(bus: &mut Bus) {
 
     timers::run_gpu_clocks(bus, elapsed_gpu_cycles);
 
+    // A Justifier pulls the lightpen interrupt when the beam goes past its aim
+    let lightpen = bus.pad_memcard.lightpen_irq();
+
     while elapsed_gpu_cycles >= bus.gpu.cycles_to_line_event {
+        check_lightpen(bus, lightpen, bus.gpu.cycles_to_line_event);
+
         elapsed_gpu_cycles -= bus.gpu.cycles_to_line_event;
 
         // We either reached hsync or left it
@@ -465,18 +592,26 @@ pub fn run(bus: &mut Bus) {
         }
     }
 
+    check_lightpen(bus, lightpen, elapsed_gpu_cycles);
+
     bus.gpu.cycles_to_line_event -= elapsed_gpu_cycles;
 
-    // New we need to program the next sync at `cycles_to_line_event`. Where it gets tricky is that
-    // we program sync events based on the CPU clock, so we need to do the conversion
-    let mut delta = bus.gpu.cycles_to_line_event as u64 * FRACTIONAL_FACTOR;
+    // If the beam reaches the lightgun before the next line event we want to be called right after
+    let next_event = match bus.gpu.cycles_to_beam(lightpen) {
+        Some(c) => c.min(bus.gpu.cycles_to_line_event),
+        None => bus.gpu.cycles_to_line_event,
+    };
+
+    // New we need to program the next sync at `next_event`. Where it gets tricky is that we
+    // program sync events based on the CPU clock, so we need to do the conversion
+    let mut delta = next_event as u64 * FRACTIONAL_FACTOR;
     // Don't forget the fractional cycle we have leftover
     delta -= u64::from(bus.gpu.remaining_fractional_cycles);
 
     // Finally divide by the frequency factor, rounding *up* (we want to be called when the event
     // has occurred, not just before). Remember that in order to divide `x` by `y` rounding up you
     // need to do `(x + y - 1) / y`.
-    let clock_ratio = match bus.gpu.video_standard {
+    let clock_ratio = match bus.gpu.clock_standard() {
         VideoStandard::Ntsc => GPU_CYCLES_PER_CPU_CYCLES_NTSC,
         VideoStandard::Pal => GPU_CYCLES_PER_CPU_CYCLES_PAL,
     };
@@ -495,6 +630,16 @@ pub fn run(bus: &mut Bus) {
     sync::next_event(bus, GPUSYNC, delta as ClockCycle);
 }
 
+/// Pulse the lightpen interrupt if the beam goes past `aim` in the next `gpu_cycles`. Must be called
+/// before `cycles_to_line_event` is updated.
+fn check_lightpen(bus: &mut Bus, aim: Option<BeamPosition>, gpu_cycles: ClockCycle) {
+    if let Some(ahead) = bus.gpu.cycles_to_beam(aim)
+        && ahead <= gpu_cycles
+    {
+        irq::trigger(bus, irq::Interrupt::Lightpen);
+    }
+}
+
 pub fn dma_can_write(bus: &mut Bus) -> bool {
     run(bus);
     bus.gpu.dma_can_write()
@@ -504,7 +649,8 @@ pub fn dma_can_write(bus: &mut Bus) -> bool {
 fn handle_eol(bus: &mut Bus) {
     let mut eof = false;
 
-    bus.gpu.rasterizer.end_of_line(bus.gpu.cur_line);
+    let line = bus.gpu.cur_line + bus.gpu.display_line_shift();
+    bus.gpu.rasterizer.end_of_line(line);
 
     bus.gpu.new_line();
 
@@ -519,7 +665,7 @@ fn handle_eol(bus: &mut Bus) {
     // it can trigger early if `display_line_end` is set to some silly value but is it really worth
     // a special case?
     if !bus.gpu.frame_drawn {
-        let draw_line = match bus.gpu.video_standard {
+        let draw_line = match bus.gpu.clock_standard() {
             VideoStandard::Ntsc => 256,
             VideoStandard::Pal => 308,
         };
@@ -551,7 +697,9 @@ fn handle_eol(bus: &mut Bus) {
         bus.gpu.refresh_lines_per_field();
     }
 
-    if cur_line == bus.gpu.display_line_end && bus.gpu.display_active {
+    let (display_line_start, display_line_end) = bus.gpu.display_lines();
+
+    if cur_line == display_line_end && bus.gpu.display_active {
         // We're leaving the active display area.
         bus.gpu.display_active = false;
         bus.gpu.cur_line_vram_offset = 0;
@@ -575,7 +723,7 @@ fn handle_eol(bus: &mut Bus) {
             // the logic behind putting a minimum line value here. The comment in mednafen mentions
             // Descent(NTSC) which reaches the end of frame at line 236 and Mikagura Shoujo
             // Tanteidan which sets it to 192 during the intro FMV.
-            let line_min = match bus.gpu.video_standard {
+            let line_min = match bus.gpu.clock_standard() {
                 VideoStandard::Ntsc => 232,
                 VideoStandard::Pal => 260,
             };
@@ -586,7 +734,7 @@ fn handle_eol(bus: &mut Bus) {
         }
     }
 
-    if cur_line == bus.gpu.display_line_start && !bus.gpu.display_active {
+    if cur_line == display_line_start && !bus.gpu.display_active {
         // We're entering the active display area
         bus.gpu.display_active = true;
         irq::set_low(bus, irq::Interrupt::VBlank);
@@ -619,7 +767,14 @@ fn handle_eol(bus: &mut Bus) {
 
 /// Called when a frame is done rendering and should be displayed
 fn draw_frame(bus: &mut Bus) {
-    bus.gpu.rasterizer.end_of_frame();
+    bus.gpu.hide_frame = bus.gpu.last_frame_draws_skipped;
+    bus.gpu.last_frame_draws_skipped = bus.gpu.frame_draws_skipped;
+    bus.gpu.frame_draws_skipped = bus.gpu.skip_draws;
+
+    if bus.gpu.rasterizer.end_of_frame() {
+        bus.stats.dropped += 1;
+    }
+    bus.gte.pgxp_mut().end_of_frame();
     bus.gpu.frame_drawn = true;
     bus.frame_done = true;
 }
@@ -832,6 +987,28 @@ fn run_next_command(bus: &mut Bus) {
         bus.gpu.draw_time(2);
     }
 
+    let stats = &mut bus.stats;
+    match bus.gpu.command_fifo.peek() >> 24 {
+        0x20..=0x3f => {
+            stats.draw_calls += 1;
+            stats.polygons += 1;
+        }
+        0x40..=0x5f => {
+            stats.draw_calls += 1;
+            stats.lines += 1;
+        }
+        0x60..=0x7f => {
+            stats.draw_calls += 1;
+            stats.sprites += 1;
+        }
+        0x02 => {
+            stats.vram_transfers += 1;
+            stats.fills += 1;
+        }
+        0x80..=0xbf => stats.vram_transfers += 1,
+        _ => (),
+    }
+
     // Invoke the callback to actually implement the command
     (command.handler)(bus);
 }
@@ -860,251 +1037,6 @@ impl State {
     }
 }
 
-/// Wrapper around the Draw Mode register value (set by GP0[0xe1] and polygon draw commands)
-#[derive(serde::Serialize, serde::Deserialize, Copy, Clone)]
-pub struct DrawMode(u32);
-
-impl DrawMode {
-    pub(crate) fn new(mode: u32) -> DrawMode {
-        DrawMode(mode)
-    }
-
-    /// Update from a polygon draw command. When that happens it overwrites the previous value
-    /// globally (i.e. the configuration remains set for any subsequent draw commands, not just the
-    /// current polygon)
-    pub(crate) fn update_from_poly(&mut self, poly_cmd: u32) {
-        // XXX bit 11 (texture_disable) can also be set/cleared, but only if the functionality is
-        // enabled using GP1[0x09]
-        self.0 &= !0x9ff;
-        self.0 |= (poly_cmd >> 16) & 0x9ff;
-    }
-
-    pub(crate) fn texture_disable(self) -> bool {
-        self.0 & (1 << 11) != 0
-    }
-
-    /// Coordinate of the left side of the texture page in VRAM
-    pub(crate) fn texture_page_x(self) -> u16 {
-        let x = (self.0 & 0xf) as u16;
-
-        x << 6
-    }
-
-    /// Coordinate of the top side of the texture page in VRAM
-    pub(crate) fn texture_page_y(self) -> u16 {
-        let y = ((self.0 >> 4) & 1) as u16;
-
-        y << 8
-    }
-
-    pub(crate) fn texture_depth(self) -> u8 {
-        ((self.0 >> 7) & 3) as u8
-    }
-
-    pub(crate) fn pixel_to_texel_shift(self) -> u8 {
-        match self.texture_depth() {
-            0 => 2, // Paletted 4bpp
-            1 => 1, // Paletted 8bpp
-            2 => 0, // True Color 1555BGR, 16bits per pixel
-            _ => 0, // XXX double-check if 3 is also truecolor.
-        }
-    }
-
-    pub(crate) fn transparency_mode(self) -> TransparencyFunction {
-        match (self.0 >> 5) & 3 {
-            0 => TransparencyFunction::Average,
-            1 => TransparencyFunction::Add,
-            2 => TransparencyFunction::Sub,
-            3 => TransparencyFunction::QuarterAdd,
-            _ => unreachable!(),
-        }
-    }
-
-    /// If true rectangle textures should be flipped horizontally
-    pub(crate) fn flip_rect_x(self) -> bool {
-        self.0 & (1 << 12) != 0
-    }
-
-    /// If true rectangle textures should be flipped vertically
-    pub(crate) fn flip_rect_y(self) -> bool {
-        self.0 & (1 << 13) != 0
-    }
-
-    /// Return true if dithering is enabled
-    pub(crate) fn dither_enable(self) -> bool {
-        self.0 & (1 << 9) != 0
-    }
-
-    /// True if the GPU is allowed to draw to the display area
-    pub(crate) fn draw_to_display_area(self) -> bool {
-        self.0 & (1 << 10) != 0
-    }
-}
-
-/// The various transparency modes
-#[derive(PartialEq, Eq, Copy, Clone, Debug)]
-pub enum TransparencyFunction {
-    /// (Background + Foreground) / 2
-    Average,
-    /// Background + Foreground
-    Add,
-    /// Background - Foreground
-    Sub,
-    /// Background + (Foreground / 4)
-    QuarterAdd,
-}
-
-/// Wrapper around the Texture Window register value (set by GP0[0xe2])
-#[derive(serde::Serialize, serde::Deserialize, Copy, Clone)]
-pub struct TextureWindow(u32);
-
-impl TextureWindow {
-    pub fn new() -> TextureWindow {
-        TextureWindow(0)
-    }
-
-    pub(crate) fn set(&mut self, tw: u32) {
-        self.0 = tw;
-    }
-
-    /// Mask to be ANDed to U coordinates
-    pub(crate) fn u_mask(self) -> u8 {
-        let m = (self.0 & 0x1f) as u8;
-
-        // 8 pixel steps
-        !(m << 3)
-    }
-
-    /// Mask to be ANDed to V coordinates
-    pub(crate) fn v_mask(self) -> u8 {
-        let m = ((self.0 >> 5) & 0x1f) as u8;
-
-        // 8 pixel steps
-        !(m << 3)
-    }
-
-    /// Offset to be added to U coordinates after applying `u_mask`
-    pub(crate) fn u_offset(self) -> u8 {
-        let off = ((self.0 >> 10) & 0x1f) as u8;
-
-        (off << 3) & !self.u_mask()
-    }
-
-    /// Offset to be added to V coordinates after applying `v_mask`
-    pub(crate) fn v_offset(self) -> u8 {
-        let off = ((self.0 >> 15) & 0x1f) as u8;
-
-        (off << 3) & !self.v_mask()
-    }
-}
-
-/// Wrapper around the Display Mode register value (set by GP1[0x08])
-#[derive(serde::Serialize, serde::Deserialize, Copy, Clone)]
-pub struct DisplayMode(u32);
-
-impl DisplayMode {
-    pub(crate) fn new() -> DisplayMode {
-        DisplayMode(0)
-    }
-
-    pub(crate) fn set(&mut self, mode: u32) {
-        self.0 = mode
-    }
-
-    fn standard(self) -> VideoStandard {
-        if self.0 & (1 << 3) != 0 {
-            VideoStandard::Pal
-        } else {
-            VideoStandard::Ntsc
-        }
-    }
-
-    fn is_interlaced(self) -> bool {
-        self.0 & (1 << 5) != 0
-    }
-
-    /// To actually have the console output in interlaced (having two interlaced fields in VRAM and
-    /// the console displays one after the other) it's not enough to set the `is_interlaced` bit,
-    /// you also need to set bit 2 in Display Mode to actually tell the console to use two fields
-    /// in VRAM. Without it the console sends the same data for the top and bottom fields, which is
-    /// fairly useless.
-    pub(crate) fn is_true_interlaced(self) -> bool {
-        let two_fields = self.0 & (1 << 2) != 0;
-
-        self.is_interlaced() && two_fields
-    }
-
-    /// Retrieve the approximate horizontal resolution of the active video. This is an
-    /// approximation because it will also depend on the timing configuration of the output (column
-    /// start/column end etc...).
-    pub(crate) fn xres(self) -> u16 {
-        if (self.0 & (1 << 6)) != 0 {
-            368
-        } else {
-            match self.0 & 3 {
-                0 => 256,
-                1 => 320,
-                2 => 512,
-                3 => 640,
-                _ => unreachable!(),
-            }
-        }
-    }
-
-    /// True if we output 24 bits per pixel
-    pub(crate) fn output_24bpp(self) -> bool {
-        self.0 & (1 << 4) != 0
-    }
-}
-
-/// Wrapper around the Mask Setting register value (set by GP0[0xe6])
-#[derive(serde::Serialize, serde::Deserialize)]
-pub struct MaskSettings {
-    /// Raw register value
-    raw: u32,
-    /// Pixel value that should be ORed on write
-    or_mask: Pixel,
-}
-
-impl MaskSettings {
-    pub(crate) fn new() -> MaskSettings {
-        MaskSettings {
-            raw: 0,
-            or_mask: Pixel::from_mbgr1555(0),
-        }
-    }
-
-    pub(crate) fn set(&mut self, v: u32) {
-        self.raw = v & 3;
-
-        let p = if self.draw_with_mask_bit() { 0x8000 } else { 0 };
-
-        self.or_mask = Pixel::from_mbgr1555(p);
-    }
-
-    fn draw_with_mask_bit(&self) -> bool {
-        self.raw & 1 != 0
-    }
-
-    pub(crate) fn check_mask_bit(&self) -> bool {
-        self.raw & (1 << 1) != 0
-    }
-
-    pub(crate) fn can_draw_to(&self, p: Pixel) -> bool {
-        if self.check_mask_bit() {
-            !p.mask()
-        } else {
-            true
-        }
-    }
-
-    pub(crate) fn mask(&self, mut p: Pixel) -> Pixel {
-        p.0 |= self.or_mask.0;
-
-        p
-    }
-}
-
 /// Requested DMA direction.
 #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
 enum DmaDirection {
@@ -1134,14 +1066,6 @@ const PSX_COMMAND_FIFO_DEPTH: usize = 0x10;
 /// two for the FIFO code to work correctly.
 pub const COMMAND_FIFO_DEPTH: usize = 0x20;
 
-/// The are a few hardware differences between PAL and NTSC consoles, in particular the pixelclock
-/// runs slightly slower on PAL consoles.
-#[derive(serde::Serialize, serde::Deserialize, Clone, Copy)]
-pub enum VideoStandard {
-    Ntsc,
-    Pal,
-}
-
 /// Total number of lines in the VRAM
 const VRAM_HEIGHT: u16 = 512;
 
@@ -1165,3 +1089,21 @@ const GPU_CYCLES_PER_CPU_CYCLES_PAL: u64 =
 const GPU_FREQ_NTSC_HZ: f64 = 53_693_181.818;
 /// GPU frequency for PAL consoles (Europe)
 const GPU_FREQ_PAL_HZ: f64 = 53_203_425.;
+
+#[test]
+fn forced_ntsc_timing() {
+    let mut gpu = Gpu::new(VideoStandard::Pal);
+    // PAL 256 line display
+    gpu.display_mode.set(1 << 3);
+    gpu.display_line_start = 0x23;
+    gpu.display_line_end = 0x123;
+    gpu.refresh_lines_per_field();
+    assert_eq!(gpu.lines_per_field, 314);
+    assert_eq!(gpu.display_lines(), (0x23, 0x123));
+
+    gpu.set_force_ntsc_timing(true);
+    assert_eq!(gpu.lines_per_field, 263);
+    let (start, end) = gpu.display_lines();
+    assert_eq!(end - start, 0x100);
+    assert!(end < gpu.lines_per_field);
+}
@@ -1,2 +0,0 @@
-pub mod handle;
-pub mod draw;
\ No newline at end of file
@@ -8,7 +8,7 @@ mod util;
 
 use std::cmp::min;
 use crate::ps1::bitwise::Bitwise;
-use crate::ps1::psx::addressable::{AccessWidth, Addressable};
+use mips_r3000::addressable::{AccessWidth, Addressable};
 use crate::ps1::psx::bus::Bus;
 use crate::ps1::psx::mdec::command::Command;
 use crate::ps1::psx::mdec::fifo::Fifo;
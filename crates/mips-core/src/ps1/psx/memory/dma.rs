@@ -10,7 +10,7 @@ use crate::ps1::psx::graphics::gpu;
 use crate::ps1::psx::processor::{irq, ClockCycle};
 use crate::ps1::psx::processor::irq::IrqState;
 use crate::ps1::psx::{cd, mdec, sync};
-use crate::ps1::psx::addressable::Addressable;
+use mips_r3000::addressable::Addressable;
 use crate::ps1::psx::sound::spu;
 
 const DMASYNC: sync::SyncToken = sync::SyncToken::Dma;
@@ -284,10 +284,12 @@ fn run_channel(bus: &mut Bus, port: Port, cycles: ClockCycle) {
                     }
                 }
                 SyncMode::LinkedList => {
-                    let overflow = cur_addr & 0x80_0000 != 0;
-
-                    if overflow {
-                        unimplemented!();
+                    if is_end_of_list(cur_addr) {
+                        // An empty ordering table: the list starts with the end marker. The DMA
+                        // stops without reading anything, like in mednafen.
+                        let irq = bus.dma.end_of_dma(port);
+                        irq::set_level(bus, irq::Interrupt::Dma, irq.is_active());
+                        break;
                     }
 
                     let header: u32 = bus.xmem.ram_load(cur_addr & 0x1f_fffc);
@@ -328,6 +330,9 @@ fn run_channel(bus: &mut Bus, port: Port, cycles: ClockCycle) {
                 let (v, offset, read_delay) = port_load(bus, port);
                 bus.xmem
                     .ram_store((cur_addr.wrapping_add(offset)) & 0x1f_fffc, v);
+                if let Some(watch) = &mut bus.exec_watch {
+                    watch.record_write(cur_addr.wrapping_add(offset) & 0x1f_fffc, None);
+                }
                 read_delay
             };
 
@@ -342,6 +347,7 @@ fn run_channel(bus: &mut Bus, port: Port, cycles: ClockCycle) {
 
             bus.dma[port].clock_counter -= 1;
             bus.dma[port].remaining_words -= 1;
+            bus.stats.dma_bytes += 4;
         }
 
         if control.is_chopped() {
@@ -369,8 +375,10 @@ fn run_channel(bus: &mut Bus, port: Port, cycles: ClockCycle) {
                     channel.block_count == 0
                 }
                 SyncMode::LinkedList => {
-                    // Check for end-of-list marker
-                    bus.dma[port].base == 0xff_ffff
+                    // Check for end-of-list marker. The OTC channel terminates the ordering table
+                    // with 0xff_ffff but the DMA only looks at bit 23, so any pointer with that bit
+                    // set ends the list.
+                    is_end_of_list(bus.dma[port].base)
                 }
             };
 
@@ -386,6 +394,11 @@ fn run_channel(bus: &mut Bus, port: Port, cycles: ClockCycle) {
     }
 }
 
+/// Returns true if the linked list pointer `next` marks the end of the list
+fn is_end_of_list(next: u32) -> bool {
+    next & 0x80_0000 != 0
+}
+
 /// Check if the device targeted by `port` can either be read from of written to
 fn can_run(bus: &mut Bus, port: Port, write: bool) -> bool {
     if write {
@@ -701,3 +714,16 @@ impl IrqConfig {
 /// How often should we update the DMA state. The smaller this value the more accurate we'll be,
 /// but very small values will just ruin performance
 const DMA_REFRESH_PERIOD: ClockCycle = 128;
+
+#[test]
+fn end_of_list_marker() {
+    // The terminator written by the OTC channel
+    assert!(is_end_of_list(0xff_ffff));
+    // Only bit 23 matters
+    assert!(is_end_of_list(0x80_0000));
+    assert!(is_end_of_list(0x80_1234));
+
+    assert!(!is_end_of_list(0x00_0000));
+    assert!(!is_end_of_list(0x1f_fffc));
+    assert!(!is_end_of_list(0x7f_fffc));
+}
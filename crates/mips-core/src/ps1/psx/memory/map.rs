@@ -19,6 +19,7 @@ const REGION_MASK: [u32; 8] = [
 ];
 
 /// Mask a CPU address to remove the region bits.
+#[inline]
 pub fn mask_region(addr: u32) -> u32 {
     // Index address space in 512MB chunks
     let index = (addr >> 29) as usize;
@@ -26,10 +27,12 @@ pub fn mask_region(addr: u32) -> u32 {
     addr & REGION_MASK[index]
 }
 
-pub struct Range(pub u32, u32);
+#[derive(Clone, Copy)]
+pub struct Range(pub u32, pub u32);
 
 impl Range {
     /// Return `Some(offset)` if addr is contained in `self`
+    #[inline]
     pub fn contains(self, addr: u32) -> Option<u32> {
         let Range(start, length) = self;
 
@@ -87,4 +90,193 @@ pub const SPU: Range = Range(0x1f80_1c00, 640);
 pub const EXPANSION_2: Range = Range(0x1f80_2000, 66);
 
 /// Cache control register. Full address since it's in KSEG2
-pub const CACHE_CONTROL: Range = Range(0xfffe_0130, 4);
\ No newline at end of file
+pub const CACHE_CONTROL: Range = Range(0xfffe_0130, 4);
+
+/// Regions of the memory map, looked up with `region` instead of going through every `Range`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Region {
+    Ram,
+    Bios,
+    ScratchPad,
+    Expansion1,
+    MemControl,
+    PadMemCard,
+    RamSize,
+    IrqControl,
+    Dma,
+    Timers,
+    CdRom,
+    Gpu,
+    MDec,
+    Spu,
+    Expansion2,
+    CacheControl,
+    Unmapped,
+    /// Only used in `PAGES`: the page is split further in `HARDWARE_PAGE`
+    Hardware,
+}
+
+impl Region {
+    /// Address of the first byte of the region, `region(addr)` accesses are at `addr - base`
+    pub fn base(self) -> u32 {
+        let range = match self {
+            Region::Ram => RAM,
+            Region::Bios => BIOS,
+            Region::ScratchPad => SCRATCH_PAD,
+            Region::Expansion1 => EXPANSION_1,
+            Region::MemControl => MEM_CONTROL,
+            Region::PadMemCard => PAD_MEMCARD,
+            Region::RamSize => RAM_SIZE,
+            Region::IrqControl => IRQ_CONTROL,
+            Region::Dma => DMA,
+            Region::Timers => TIMERS,
+            Region::CdRom => CDROM,
+            Region::Gpu => GPU,
+            Region::MDec => MDEC,
+            Region::Spu => SPU,
+            Region::Expansion2 => EXPANSION_2,
+            Region::CacheControl => CACHE_CONTROL,
+            Region::Unmapped | Region::Hardware => return 0,
+        };
+
+        range.0
+    }
+}
+
+/// log2 of the size of the pages in `PAGES`
+const PAGE_SHIFT: u32 = 16;
+
+/// Pages covering the 512MB of physical address space
+static PAGES: [Region; 1 << (29 - PAGE_SHIFT)] = {
+    let mut pages = [Region::Unmapped; 1 << (29 - PAGE_SHIFT)];
+
+    let regions = [
+        (RAM, Region::Ram),
+        (EXPANSION_1, Region::Expansion1),
+        (BIOS, Region::Bios),
+        (Range(HARDWARE_PAGE_BASE, 1 << PAGE_SHIFT), Region::Hardware),
+    ];
+
+    let mut r = 0;
+    while r < regions.len() {
+        let (Range(start, len), region) = regions[r];
+
+        let mut page = start >> PAGE_SHIFT;
+        while page < (start + len) >> PAGE_SHIFT {
+            pages[page as usize] = region;
+            page += 1;
+        }
+
+        r += 1;
+    }
+
+    pages
+};
+
+/// The page containing the scratch pad and the hardware registers
+const HARDWARE_PAGE_BASE: u32 = 0x1f80_0000;
+
+/// Bytes of the hardware page mapped in `HARDWARE_PAGE`, everything above is unmapped
+const HARDWARE_PAGE_LEN: u32 = 0x4000;
+
+/// Regions for every 32bit word of the first `HARDWARE_PAGE_LEN` bytes of the hardware page
+static HARDWARE_PAGE: [Region; (HARDWARE_PAGE_LEN >> 2) as usize] = {
+    let mut words = [Region::Unmapped; (HARDWARE_PAGE_LEN >> 2) as usize];
+
+    let regions = [
+        (SCRATCH_PAD, Region::ScratchPad),
+        (MEM_CONTROL, Region::MemControl),
+        (PAD_MEMCARD, Region::PadMemCard),
+        (RAM_SIZE, Region::RamSize),
+        (IRQ_CONTROL, Region::IrqControl),
+        (DMA, Region::Dma),
+        (TIMERS, Region::Timers),
+        (CDROM, Region::CdRom),
+        (GPU, Region::Gpu),
+        (MDEC, Region::MDec),
+        (SPU, Region::Spu),
+        (EXPANSION_2, Region::Expansion2),
+    ];
+
+    let mut r = 0;
+    while r < regions.len() {
+        let (Range(start, len), region) = regions[r];
+
+        let mut word = (start - HARDWARE_PAGE_BASE) >> 2;
+        while word < (start - HARDWARE_PAGE_BASE + len).div_ceil(4) {
+            words[word as usize] = region;
+            word += 1;
+        }
+
+        r += 1;
+    }
+
+    words
+};
+
+/// Find the region containing `abs_addr`, an address returned by `mask_region`
+#[inline]
+pub fn region(abs_addr: u32) -> Region {
+    // Most accesses target the RAM, a single comparison is cheaper than the table lookup
+    if abs_addr < RAM.1 {
+        return Region::Ram;
+    }
+
+    if abs_addr >= 0x2000_0000 {
+        // KSEG2
+        return if CACHE_CONTROL.contains(abs_addr).is_some() {
+            Region::CacheControl
+        } else {
+            Region::Unmapped
+        };
+    }
+
+    match PAGES[(abs_addr >> PAGE_SHIFT) as usize] {
+        Region::Hardware => {
+            let offset = abs_addr - HARDWARE_PAGE_BASE;
+
+            if offset < HARDWARE_PAGE_LEN {
+                HARDWARE_PAGE[(offset >> 2) as usize]
+            } else {
+                Region::Unmapped
+            }
+        }
+        region => region,
+    }
+}
+
+#[test]
+fn region_lookup() {
+    let ranges = [
+        (RAM, Region::Ram),
+        (EXPANSION_1, Region::Expansion1),
+        (BIOS, Region::Bios),
+        (SCRATCH_PAD, Region::ScratchPad),
+        (MEM_CONTROL, Region::MemControl),
+        (PAD_MEMCARD, Region::PadMemCard),
+        (RAM_SIZE, Region::RamSize),
+        (IRQ_CONTROL, Region::IrqControl),
+        (DMA, Region::Dma),
+        (TIMERS, Region::Timers),
+        (CDROM, Region::CdRom),
+        (GPU, Region::Gpu),
+        (MDEC, Region::MDec),
+        (SPU, Region::Spu),
+        (EXPANSION_2, Region::Expansion2),
+        (CACHE_CONTROL, Region::CacheControl),
+    ];
+
+    for (Range(start, len), expected) in ranges {
+        assert_eq!(region(start), expected);
+        assert_eq!(region(start + len - 1), expected);
+        assert_eq!(expected.base(), start);
+    }
+
+    assert_eq!(region(mask_region(0xbfc0_0180)), Region::Bios);
+    assert_eq!(region(mask_region(0x807f_fffc)), Region::Ram);
+    assert_eq!(region(0x0080_0000), Region::Unmapped);
+    assert_eq!(region(0x1f80_0400), Region::Unmapped);
+    assert_eq!(region(0x1f80_1804), Region::Unmapped);
+    assert_eq!(region(0x1f80_4000), Region::Unmapped);
+    assert_eq!(region(0xfffe_0000), Region::Unmapped);
+}
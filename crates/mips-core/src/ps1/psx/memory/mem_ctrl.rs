@@ -1,6 +1,6 @@
 use std::ops::{Index, IndexMut};
 use bitfield::bitfield;
-use crate::ps1::psx::addressable::AccessWidth;
+use mips_r3000::addressable::AccessWidth;
 use crate::ps1::psx::processor::ClockCycle;
 
 pub struct MemoryControl {
@@ -1,4 +1,4 @@
-use crate::ps1::psx::addressable::Addressable;
+use mips_r3000::addressable::Addressable;
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(crate) struct ScratchPad {
@@ -13,6 +13,7 @@ impl ScratchPad {
     }
 
     /// Fetch the little endian value at `offset`
+    #[inline]
     pub fn load<T: Addressable>(&self, offset: u32) -> T {
         // The two MSBs are ignored, the 2MB RAM is mirrored four times over the first 8MB of
         // address space
@@ -30,6 +31,7 @@ impl ScratchPad {
     }
 
     /// Store the 32bit little endian word `val` into `offset`
+    #[inline]
     pub fn store<T: Addressable>(&mut self, offset: u32, val: T) {
         // The two MSBs are ignored, the 2MB RAM is mirrored four times over the first 8MB of
         // address space
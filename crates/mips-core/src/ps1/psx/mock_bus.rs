@@ -0,0 +1,97 @@
+//! Stand-in for the `Bus` in the unit tests of the peripherals: it holds a single device, the clock
+//! and the interrupt lines, so a test doesn't need a whole console with its BIOS and firmware. Each
+//! peripheral implements its bus trait for `MockBus<Device>` next to its tests.
+
+use crate::ps1::psx::processor::ClockCycle;
+use crate::ps1::psx::processor::irq::{Interrupt, IrqLines};
+use crate::ps1::psx::sync::{Scheduler, SyncToken};
+use crate::stats::FrameStats;
+
+pub struct MockBus<D> {
+    pub device: D,
+    pub cycles: ClockCycle,
+    /// Date of the last `resync` of each token
+    last_sync: [ClockCycle; SyncToken::NumTokens as usize],
+    /// Date of the next event asked by each token
+    pub next_event: [ClockCycle; SyncToken::NumTokens as usize],
+    /// Level of the interrupt lines, one bit per `Interrupt`
+    irq_levels: u16,
+    /// Interrupt lines that had a rising edge since the last `take_irq`
+    irq_edges: u16,
+    pub stats: FrameStats,
+}
+
+impl<D> MockBus<D> {
+    pub fn new(device: D) -> MockBus<D> {
+        MockBus {
+            device,
+            cycles: 0,
+            last_sync: [0; SyncToken::NumTokens as usize],
+            next_event: [0; SyncToken::NumTokens as usize],
+            irq_levels: 0,
+            irq_edges: 0,
+            stats: FrameStats::default(),
+        }
+    }
+
+    pub fn tick(&mut self, cycles: ClockCycle) {
+        self.cycles += cycles;
+    }
+
+    /// Current level of the `which` line
+    pub fn irq_level(&self, which: Interrupt) -> bool {
+        self.irq_levels & (1 << which as usize) != 0
+    }
+
+    /// True if `which` went high since the last call, like the interrupt controller would have
+    /// latched it
+    pub fn take_irq(&mut self, which: Interrupt) -> bool {
+        let m = 1 << which as usize;
+        let edge = self.irq_edges & m != 0;
+
+        self.irq_edges &= !m;
+
+        edge
+    }
+}
+
+impl<D> IrqLines for MockBus<D> {
+    fn set_irq_level(&mut self, which: Interrupt, high: bool) {
+        let m = 1 << which as usize;
+
+        if high {
+            if self.irq_levels & m == 0 {
+                self.irq_edges |= m;
+            }
+            self.irq_levels |= m;
+        } else {
+            self.irq_levels &= !m;
+        }
+    }
+}
+
+impl<D> Scheduler for MockBus<D> {
+    fn now(&self) -> ClockCycle {
+        self.cycles
+    }
+
+    fn resync(&mut self, who: SyncToken) -> ClockCycle {
+        let elapsed = self.cycles - self.last_sync[who as usize];
+
+        if elapsed <= 0 {
+            return 0;
+        }
+
+        self.last_sync[who as usize] = self.cycles;
+
+        elapsed
+    }
+
+    fn rewind(&mut self, who: SyncToken, cycles: ClockCycle) {
+        self.last_sync[who as usize] -= cycles;
+    }
+
+    fn next_event(&mut self, who: SyncToken, delay: ClockCycle) {
+        self.next_event[who as usize] = self.cycles + delay;
+    }
+}
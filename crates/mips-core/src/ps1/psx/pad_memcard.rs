@@ -1,13 +1,21 @@
 pub mod gamepad;
+pub mod lightgun;
 pub mod memory_card;
-
-use log::warn;
-use crate::input::{Button, ButtonState};
-use crate::ps1::psx::addressable::{AccessWidth, Addressable};
+pub mod mouse;
+
+use std::collections::VecDeque;
+use log::{info, warn};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::input::{Button, ButtonState, ControllerType, MouseInput};
+use crate::ps1::psx::pad_memcard::gamepad::{DigitalPad, DualShock, NeGcon};
+use crate::ps1::psx::pad_memcard::lightgun::{BeamPosition, GunCon, Justifier, LightgunState};
+use crate::ps1::psx::pad_memcard::memory_card::MemoryCard;
+use crate::ps1::psx::pad_memcard::mouse::Mouse;
+use mips_r3000::addressable::{AccessWidth, Addressable};
 use crate::ps1::psx::bus::Bus;
-use crate::ps1::psx::processor::{irq, ClockCycle};
-use crate::ps1::psx::processor::irq::Interrupt;
-use crate::ps1::psx::sync;
+use crate::ps1::psx::processor::ClockCycle;
+use crate::ps1::psx::processor::irq::{Interrupt, IrqLines};
+use crate::ps1::psx::sync::{self, Scheduler};
 
 const PADSYNC: sync::SyncToken = sync::SyncToken::PadMemCard;
 
@@ -86,11 +94,96 @@ impl Peripheral {
     pub fn disconnect_device(&mut self) -> Box<dyn DeviceInterface> {
         self.connect_device(Box::new(DisconnectedDevice))
     }
+
+    /// Restore the state of `saved`, loaded from a savestate, into the device currently connected.
+    /// The device itself stays: the savestate may have been made with another type of controller
+    /// or another memory card, and the frontend decides what's plugged in. If the device can't
+    /// take the saved state the transaction in progress (if any) is aborted.
+    pub fn restore(&mut self, saved: &Peripheral) {
+        let state = saved.device.save_state();
+
+        if self.device.load_state(&state) {
+            self.seq = saved.seq;
+            self.active = saved.active;
+        } else {
+            if !self.device.matches_state(&state) {
+                info!(
+                    "Savestate made with {}, keeping {}",
+                    saved.device.description(),
+                    self.device.description()
+                );
+            }
+
+            self.seq = 0;
+            self.active = false;
+        }
+    }
 }
 
+#[derive(Serialize, Deserialize)]
 struct SerializedPeripheral {
     seq: u8,
     active: bool,
+    device: DeviceState,
+}
+
+impl Serialize for Peripheral {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = SerializedPeripheral {
+            seq: self.seq,
+            active: self.active,
+            device: self.device.save_state(),
+        };
+
+        s.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Peripheral {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = SerializedPeripheral::deserialize(deserializer)?;
+
+        Ok(Peripheral {
+            device: s.device.into_device(),
+            seq: s.seq,
+            active: s.active,
+        })
+    }
+}
+
+/// Full state of a device, as stored in savestates
+#[derive(Serialize, Deserialize)]
+pub enum DeviceState {
+    Disconnected,
+    DigitalPad(DigitalPad),
+    DualShock(DualShock),
+    NeGcon(NeGcon),
+    MemoryCard(Box<MemoryCard>),
+    Mouse(Mouse),
+    GunCon(GunCon),
+    Justifier(Justifier),
+}
+
+impl DeviceState {
+    /// Rebuild the device from its state
+    fn into_device(self) -> Box<dyn DeviceInterface> {
+        match self {
+            DeviceState::Disconnected => Box::new(DisconnectedDevice),
+            DeviceState::DigitalPad(pad) => Box::new(pad),
+            DeviceState::DualShock(pad) => Box::new(pad),
+            DeviceState::NeGcon(pad) => Box::new(pad),
+            DeviceState::MemoryCard(card) => card,
+            DeviceState::Mouse(mouse) => Box::new(mouse),
+            DeviceState::GunCon(gun) => Box::new(gun),
+            DeviceState::Justifier(gun) => Box::new(gun),
+        }
+    }
 }
 
 /// Trait used to abstract away the various device types.
@@ -98,10 +191,17 @@ struct SerializedPeripheral {
 /// This can be used to implement both controllers and memory cards. Obviously the methods that are
 /// irrelevant for the concrete device should be left unimplemented (no sense getting the
 /// `write_counter` of a DualShock or setting the `axis_state` of a MemoryCard.
-pub trait DeviceInterface {
+///
+/// Devices must be `Send`, savestates are deserialized on a thread of their own.
+pub trait DeviceInterface: Send {
     /// Human-readable description of the device
     fn description(&self) -> String;
 
+    /// Type of controller, `Disconnected` for devices that aren't controllers
+    fn controller_type(&self) -> ControllerType {
+        ControllerType::Disconnected
+    }
+
     /// Called every time the device is selected (i.e. the "/select" signal goes low)
     fn select(&mut self) {}
 
@@ -119,6 +219,17 @@ pub trait DeviceInterface {
     /// Set the state of the axis. Each pair is `(x, y)`.
     fn set_axis_state(&mut self, _left: (i16, i16), _right: (i16, i16)) {}
 
+    /// Set the buttons and add to the motion of a mouse
+    fn set_mouse_state(&mut self, _input: MouseInput) {}
+
+    /// Set the buttons and the aim of a lightgun
+    fn set_lightgun_state(&mut self, _state: LightgunState) {}
+
+    /// Beam position at which the device pulls the lightpen interrupt, if it does
+    fn lightpen_irq(&self) -> Option<BeamPosition> {
+        None
+    }
+
     /// Get rumble state. The first u8 is the big motor in the left handle, the 2nd is the small
     /// motor in the right handle.
     fn get_rumble(&self) -> (u8, u8) {
@@ -143,6 +254,19 @@ pub trait DeviceInterface {
 
     /// Called once per frame
     fn new_frame(&mut self) {}
+
+    /// Full state of the device, to be stored in a savestate
+    fn save_state(&self) -> DeviceState;
+
+    /// Restore the state saved by `save_state`. Returns false if the state was saved by another
+    /// type of device (or can't be applied for some other reason), in which case the device is
+    /// left in a state the game can recover from.
+    fn load_state(&mut self, state: &DeviceState) -> bool;
+
+    /// True if `state` was saved by the same type of device
+    fn matches_state(&self, state: &DeviceState) -> bool {
+        std::mem::discriminant(&self.save_state()) == std::mem::discriminant(state)
+    }
 }
 
 /// Dummy profile emulating an empty pad or memory card slot
@@ -157,6 +281,14 @@ impl DeviceInterface for DisconnectedDevice {
         // The bus is open, no response
         (0xff, DsrState::Idle)
     }
+
+    fn save_state(&self) -> DeviceState {
+        DeviceState::Disconnected
+    }
+
+    fn load_state(&mut self, state: &DeviceState) -> bool {
+        matches!(state, DeviceState::Disconnected)
+    }
 }
 
 pub fn disconnected_gamepad() -> Peripheral {
@@ -167,6 +299,7 @@ pub fn disconnected_memory_card() -> Peripheral {
     Peripheral::new(Box::new(DisconnectedDevice))
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct PadMemCard {
     /// Serial clock divider. The LSB is read/write but is not used, This way the hardware divide
     /// the CPU clock by half of `baud_div` and can invert the serial clock polarity twice every
@@ -216,6 +349,17 @@ pub struct PadMemCard {
     memcard2_dsr: DsrState,
     /// Bus state machine
     transfer_state: TransferState,
+    /// Button changes waiting for the time they happened at, sorted by date
+    #[serde(skip)]
+    scheduled_inputs: VecDeque<ScheduledInput>,
+}
+
+/// A button change that reaches the gamepad the next time the game talks to the pads after `at`
+struct ScheduledInput {
+    at: ClockCycle,
+    port: usize,
+    state: ButtonState,
+    button: Button,
 }
 
 impl PadMemCard {
@@ -242,9 +386,15 @@ impl PadMemCard {
             memcard2: disconnected_memory_card(),
             memcard2_dsr: DsrState::Idle,
             transfer_state: TransferState::Idle,
+            scheduled_inputs: VecDeque::new(),
         }
     }
 
+    /// Return a reference to the gamepad peripherals being used.
+    pub fn gamepads(&self) -> [&Peripheral; 2] {
+        [&self.pad1, &self.pad2]
+    }
+
     /// Return a mutable reference to the gamepad peripherals being used.
     pub fn gamepads_mut(&mut self) -> [&mut Peripheral; 2] {
         [&mut self.pad1, &mut self.pad2]
@@ -260,6 +410,48 @@ impl PadMemCard {
         [&mut self.memcard1, &mut self.memcard2]
     }
 
+    /// Beam position at which a lightgun connected to one of the pads wants the lightpen interrupt
+    pub fn lightpen_irq(&self) -> Option<BeamPosition> {
+        self.gamepads().into_iter().find_map(|pad| pad.device().lightpen_irq())
+    }
+
+    /// Press or release `button` on the gamepad in `port` once the bus reaches cycle `at`, instead
+    /// of right away. The change is latched by the first access to the controller registers from
+    /// then on, i.e. when the game polls the pads.
+    pub fn schedule_input(&mut self, at: ClockCycle, port: usize, state: ButtonState, button: Button) {
+        // `latch_inputs` indexes the pads with it
+        if port >= 2 {
+            return;
+        }
+
+        let pos = self.scheduled_inputs.partition_point(|input| input.at <= at);
+
+        self.scheduled_inputs.insert(pos, ScheduledInput { at, port, state, button });
+    }
+
+    /// Apply the button changes scheduled up to cycle `now`
+    pub fn latch_inputs(&mut self, now: ClockCycle) {
+        while let Some(input) = self.scheduled_inputs.front() && input.at <= now {
+            let input = self.scheduled_inputs.pop_front().unwrap();
+
+            self.gamepads_mut()[input.port].device_mut().set_button_state(input.button, input.state);
+        }
+    }
+
+    /// Load the state of `saved`, deserialized from a savestate, keeping the devices currently
+    /// connected (see `Peripheral::restore`)
+    pub fn load_state(&mut self, mut saved: PadMemCard) {
+        let current = [&mut self.pad1, &mut self.pad2, &mut self.memcard1, &mut self.memcard2];
+        let restored = [&mut saved.pad1, &mut saved.pad2, &mut saved.memcard1, &mut saved.memcard2];
+
+        for (current, restored) in current.into_iter().zip(restored) {
+            current.restore(restored);
+            std::mem::swap(current, restored);
+        }
+
+        *self = saved;
+    }
+
     fn maybe_exchange_byte(&mut self) {
         let to_send = match self.tx_pending {
             Some(b) => b,
@@ -505,46 +697,58 @@ impl PadMemCard {
     }
 }
 
-fn run_controller(bus: &mut Bus) {
-    let elapsed = sync::resync(bus, PADSYNC);
+/// What the pad and memory card controller needs from the bus. Implemented by the `Bus`, and by the
+/// mock bus of the unit tests.
+pub trait PadMemCardBus: IrqLines + Scheduler {
+    fn pad_memcard(&mut self) -> &mut PadMemCard;
+}
 
-    run_transfer(bus, elapsed);
+impl PadMemCardBus for Bus {
+    fn pad_memcard(&mut self) -> &mut PadMemCard {
+        &mut self.pad_memcard
+    }
+}
+
+fn run_controller<B: PadMemCardBus>(bus: &mut B) {
+    let elapsed = bus.resync(PADSYNC);
+
+    run_transfer(bus.pad_memcard(), elapsed);
     run_dsr(bus, elapsed);
 }
 
 /// Update transfer state machine
-fn run_transfer(bus: &mut Bus, mut cycles: ClockCycle) {
+fn run_transfer(pad_memcard: &mut PadMemCard, mut cycles: ClockCycle) {
     while cycles > 0 {
-        let elapsed = match bus.pad_memcard.transfer_state {
+        let elapsed = match pad_memcard.transfer_state {
             TransferState::Idle => cycles,
             TransferState::TxStart(delay, to_rx, rx_byte) => {
                 if cycles < delay {
-                    bus.pad_memcard.transfer_state =
+                    pad_memcard.transfer_state =
                         TransferState::TxStart(delay - cycles, to_rx, rx_byte);
 
                     cycles
                 } else {
-                    bus.pad_memcard.transfer_state = TransferState::RxAvailable(to_rx, rx_byte);
+                    pad_memcard.transfer_state = TransferState::RxAvailable(to_rx, rx_byte);
 
                     delay
                 }
             }
             TransferState::RxAvailable(delay, rx_byte) => {
                 if cycles < delay {
-                    bus.pad_memcard.transfer_state =
+                    pad_memcard.transfer_state =
                         TransferState::RxAvailable(delay - cycles, rx_byte);
 
                     cycles
                 } else {
-                    if bus.pad_memcard.rx_not_empty {
+                    if pad_memcard.rx_not_empty {
                         // XXX should push in the non-emulated RX FIFO instead of overwriting
                         // `psx.pad_memcard.response`
                         unimplemented!("Gamepad RX while FIFO isn't empty");
                     }
 
-                    bus.pad_memcard.response = rx_byte;
-                    bus.pad_memcard.rx_not_empty = true;
-                    bus.pad_memcard.transfer_state = TransferState::Idle;
+                    pad_memcard.response = rx_byte;
+                    pad_memcard.rx_not_empty = true;
+                    pad_memcard.transfer_state = TransferState::Idle;
 
                     delay
                 }
@@ -553,61 +757,71 @@ fn run_transfer(bus: &mut Bus, mut cycles: ClockCycle) {
 
         // Need to call this here if we have a buffered transfer. That normally shouldn't happen
         // since the game should wait for the DSR pulse first
-        bus.pad_memcard.maybe_exchange_byte();
+        pad_memcard.maybe_exchange_byte();
 
         cycles -= elapsed;
     }
 }
 
 /// Update the device's DSR state
-fn run_dsr(bus: &mut Bus, cycles: ClockCycle) {
-    bus.pad_memcard.pad1_dsr.run(cycles);
-    bus.pad_memcard.pad2_dsr.run(cycles);
-    bus.pad_memcard.memcard1_dsr.run(cycles);
-    bus.pad_memcard.memcard2_dsr.run(cycles);
+fn run_dsr<B: PadMemCardBus>(bus: &mut B, cycles: ClockCycle) {
+    let pad_memcard = bus.pad_memcard();
+
+    pad_memcard.pad1_dsr.run(cycles);
+    pad_memcard.pad2_dsr.run(cycles);
+    pad_memcard.memcard1_dsr.run(cycles);
+    pad_memcard.memcard2_dsr.run(cycles);
 
     // See if a new DSR pulse occurred to trigger the IRQ
-    bus.pad_memcard.refresh_irq_level();
-    irq::set_level(bus, Interrupt::PadMemCard, bus.pad_memcard.interrupt);
+    pad_memcard.refresh_irq_level();
+
+    let interrupt = pad_memcard.interrupt;
+    bus.set_irq_level(Interrupt::PadMemCard, interrupt);
 }
 
-fn predict_next_sync(bus: &mut Bus) {
+fn predict_next_sync<B: PadMemCardBus>(bus: &mut B) {
+    let pad_memcard = bus.pad_memcard();
     let mut next_event = 1_000_000;
 
-    if bus.pad_memcard.dsr_it {
-        if let Some(e) = bus.pad_memcard.pad1_dsr.to_dsr() {
+    if pad_memcard.dsr_it {
+        if let Some(e) = pad_memcard.pad1_dsr.to_dsr() {
             if e < next_event {
                 next_event = e;
             }
         }
-        if let Some(e) = bus.pad_memcard.pad2_dsr.to_dsr() {
+        if let Some(e) = pad_memcard.pad2_dsr.to_dsr() {
             if e < next_event {
                 next_event = e;
             }
         }
-        if let Some(e) = bus.pad_memcard.memcard1_dsr.to_dsr() {
+        if let Some(e) = pad_memcard.memcard1_dsr.to_dsr() {
             if e < next_event {
                 next_event = e;
             }
         }
-        if let Some(e) = bus.pad_memcard.memcard2_dsr.to_dsr() {
+        if let Some(e) = pad_memcard.memcard2_dsr.to_dsr() {
             if e < next_event {
                 next_event = e;
             }
         }
     }
 
-    sync::next_event(bus, PADSYNC, next_event);
+    bus.next_event(PADSYNC, next_event);
 }
 
-pub fn run(bus: &mut Bus) {
+pub fn run<B: PadMemCardBus>(bus: &mut B) {
     run_controller(bus);
     predict_next_sync(bus);
 }
 
-pub fn store<T: Addressable>(bus: &mut Bus, off: u32, val: T) {
+pub fn store<B: PadMemCardBus, T: Addressable>(bus: &mut B, off: u32, val: T) {
     run_controller(bus);
 
+    let now = bus.now();
+    let pad_memcard = bus.pad_memcard();
+
+    pad_memcard.latch_inputs(now);
+
     let v = val.as_u16();
 
     match off {
@@ -616,45 +830,50 @@ pub fn store<T: Addressable>(bus: &mut Bus, off: u32, val: T) {
                 unimplemented!("Gamepad TX access ({:?})", T::width());
             }
 
-            if bus.pad_memcard.tx_pending.is_some() {
+            if pad_memcard.tx_pending.is_some() {
                 warn!("Dropping pad/memcard byte before send");
             }
 
-            bus.pad_memcard.tx_pending = Some(v as u8);
+            pad_memcard.tx_pending = Some(v as u8);
         }
-        8 => bus.pad_memcard.set_mode(val.as_u8()),
+        8 => pad_memcard.set_mode(val.as_u8()),
         10 => {
             if T::width() == AccessWidth::Byte {
                 // Byte access behaves like a halfword
                 unimplemented!("Unhandled byte gamepad control access");
             }
-            bus.pad_memcard.set_control(v);
-            irq::set_level(bus, Interrupt::PadMemCard, bus.pad_memcard.interrupt);
+            pad_memcard.set_control(v);
         }
-        14 => bus.pad_memcard.baud_div = v,
+        14 => pad_memcard.baud_div = v,
         _ => warn!("Write to gamepad register {} {:04x}", off, v),
     }
 
-    bus.pad_memcard.maybe_exchange_byte();
+    pad_memcard.maybe_exchange_byte();
+
+    // Writing the control register can acknowledge the interrupt
+    let interrupt = pad_memcard.interrupt;
+    bus.set_irq_level(Interrupt::PadMemCard, interrupt);
 
     predict_next_sync(bus);
 }
 
-pub fn load<T: Addressable>(bus: &mut Bus, off: u32) -> T {
+pub fn load<B: PadMemCardBus, T: Addressable>(bus: &mut B, off: u32) -> T {
     run_controller(bus);
 
+    let pad_memcard = bus.pad_memcard();
+
     let v = match off {
         0 => {
             if T::width() != AccessWidth::Byte {
                 unimplemented!("Unhandled gamepad RX access ({:?})", T::width());
             }
 
-            u32::from(bus.pad_memcard.get_response())
+            u32::from(pad_memcard.get_response())
         }
-        4 => bus.pad_memcard.stat(),
-        8 => u32::from(bus.pad_memcard.mode),
-        10 => u32::from(bus.pad_memcard.control()),
-        14 => u32::from(bus.pad_memcard.baud_div),
+        4 => pad_memcard.stat(),
+        8 => u32::from(pad_memcard.mode),
+        10 => u32::from(pad_memcard.control()),
+        14 => u32::from(pad_memcard.baud_div),
         _ => {
             warn!("pad_memcard read {:?} 0x{:x}", T::width(), off);
             0
@@ -770,3 +989,104 @@ impl DsrState {
         }
     }
 }
+
+#[test]
+fn peripheral_savestate() {
+    /// Second reply byte of a "read input" command: the controller ID
+    fn pad_id(pad: &mut Peripheral) -> u8 {
+        pad.select();
+        pad.exchange_byte(0x01);
+        pad.exchange_byte(0x42).0
+    }
+
+    let mut pad = Peripheral::new(Box::new(DualShock::new()));
+    pad.device_mut().set_button_state(Button::Analog, ButtonState::Pressed);
+    assert_eq!(pad_id(&mut pad), 0x73);
+
+    let saved: Peripheral = serde_json::from_str(&serde_json::to_string(&pad).unwrap()).unwrap();
+
+    // The analog mode set before the save is restored on a fresh DualShock
+    let mut current = Peripheral::new(Box::new(DualShock::new()));
+    assert_eq!(pad_id(&mut current), 0x41);
+    current.restore(&saved);
+    assert_eq!(pad_id(&mut current), 0x73);
+
+    // A digital pad stays a digital pad
+    let mut current = Peripheral::new(Box::new(DigitalPad::new()));
+    current.restore(&saved);
+    assert_eq!(pad_id(&mut current), 0x41);
+}
+
+#[test]
+fn scheduled_inputs() {
+    /// Button bits of a "read input" command, active low
+    fn buttons(pad: &mut Peripheral) -> u16 {
+        pad.select();
+        pad.exchange_byte(0x01);
+        pad.exchange_byte(0x42);
+        pad.exchange_byte(0x00);
+        let lo = pad.exchange_byte(0x00).0;
+        let hi = pad.exchange_byte(0x00).0;
+
+        u16::from_le_bytes([lo, hi])
+    }
+    let cross = 1 << Button::Cross as u16;
+
+    let mut pad_memcard = PadMemCard::new();
+    pad_memcard.pad1.connect_device(Box::new(DigitalPad::new()));
+
+    pad_memcard.schedule_input(200, 0, ButtonState::Released, Button::Cross);
+    pad_memcard.schedule_input(100, 0, ButtonState::Pressed, Button::Cross);
+
+    pad_memcard.latch_inputs(99);
+    assert_ne!(buttons(&mut pad_memcard.pad1) & cross, 0);
+    pad_memcard.latch_inputs(150);
+    assert_eq!(buttons(&mut pad_memcard.pad1) & cross, 0);
+    pad_memcard.latch_inputs(ClockCycle::MAX);
+    assert_ne!(buttons(&mut pad_memcard.pad1) & cross, 0);
+}
+
+#[cfg(test)]
+impl PadMemCardBus for crate::ps1::psx::mock_bus::MockBus<PadMemCard> {
+    fn pad_memcard(&mut self) -> &mut PadMemCard {
+        &mut self.device
+    }
+}
+
+#[test]
+fn controller_transfer() {
+    use crate::ps1::psx::mock_bus::MockBus;
+
+    /// Send `cmd` to the selected pad and wait for its DSR pulse, returns the reply
+    fn exchange(bus: &mut MockBus<PadMemCard>, cmd: u8) -> u8 {
+        store(bus, 0, cmd);
+
+        let dsr = bus.next_event[PADSYNC as usize];
+        bus.tick(dsr - bus.cycles);
+        run(bus);
+
+        assert!(bus.take_irq(Interrupt::PadMemCard));
+        assert_ne!(load::<_, u32>(bus, 4) & (1 << 7), 0);
+
+        let reply = load::<_, u8>(bus, 0);
+
+        // Wait for the end of the pulse and acknowledge the interrupt
+        bus.tick(100);
+        store(bus, 10, 0x1013u16);
+        assert!(!bus.irq_level(Interrupt::PadMemCard));
+
+        reply
+    }
+
+    let mut bus = MockBus::new(PadMemCard::new());
+    bus.device.pad1.connect_device(Box::new(DigitalPad::new()));
+
+    store(&mut bus, 14, 0x88u16);
+    // TX enable, select port 1, DSR interrupt
+    store(&mut bus, 10, 0x1003u16);
+
+    assert_eq!(exchange(&mut bus, 0x01), 0xff);
+    // Digital pad ID
+    assert_eq!(exchange(&mut bus, 0x42), 0x41);
+    assert_eq!(exchange(&mut bus, 0x00), 0x5a);
+}
@@ -1,11 +1,12 @@
 use log::{error, info, warn};
 use num_derive::FromPrimitive;
-use crate::input::{Button, ButtonState};
-use crate::ps1::psx::pad_memcard::{DeviceInterface, DsrState};
+use crate::input::{Button, ButtonState, ControllerType};
+use crate::ps1::psx::pad_memcard::{DeviceInterface, DeviceState, DsrState};
 
 /// SCPH-1080: Digital gamepad.
 ///
 /// Full state is only two bytes since we only need one bit per button.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct DigitalPad(u16);
 
 impl DigitalPad {
@@ -19,6 +20,10 @@ impl DeviceInterface for DigitalPad {
         "PlayStation Digital Controller (SCPH-1080)".to_string()
     }
 
+    fn controller_type(&self) -> ControllerType {
+        ControllerType::DigitalPad
+    }
+
     fn handle_command(&mut self, seq: u8, cmd: u8) -> (u8, DsrState) {
         let (resp, send_dsr) = match seq {
             // First byte should be 0x01 if the command targets the controller
@@ -72,9 +77,19 @@ impl DeviceInterface for DigitalPad {
         // Digital pads don't support L3/R3, so those bits are always set to 1
         self.0 |= 0x6;
     }
+
+    fn save_state(&self) -> DeviceState {
+        DeviceState::DigitalPad(self.clone())
+    }
+
+    fn load_state(&mut self, state: &DeviceState) -> bool {
+        // The only state is the buttons, which are driven by the frontend
+        matches!(state, DeviceState::DigitalPad(_))
+    }
 }
 
 /// SCPH-1200: DualShock controller
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct DualShock {
     /// State of the digital buttons
     buttons: u16,
@@ -137,6 +152,13 @@ impl DualShock {
         }
     }
 
+    /// Turn the analog mode on or off as if ANALOG had been pressed, before the game locks it
+    pub fn set_analog_mode(&mut self, analog: bool) {
+        if !self.analog_mode_locked {
+            self.analog_mode = analog;
+        }
+    }
+
     /// Should be called exactly once per frame
     fn run_frame(&mut self) {
         if let Some(ref mut f) = self.watchdog {
@@ -437,6 +459,10 @@ impl DeviceInterface for DualShock {
         "PlayStation DualShock Analog Controller (SCPH-1200)".to_string()
     }
 
+    fn controller_type(&self) -> ControllerType {
+        ControllerType::DualShock
+    }
+
     fn select(&mut self) {
         // Watchdog is reset every time the select signal goes down, even if the controller is not
         // the target. I assume that the logic is that the controller should return to the default
@@ -630,9 +656,33 @@ impl DeviceInterface for DualShock {
     fn get_rumble(&self) -> (u8, u8) {
         self.rumble
     }
+
+    fn save_state(&self) -> DeviceState {
+        DeviceState::DualShock(self.clone())
+    }
+
+    fn load_state(&mut self, state: &DeviceState) -> bool {
+        let DeviceState::DualShock(saved) = state else {
+            return false;
+        };
+
+        // Restore what the game configured but keep the buttons, sticks and calibration of the
+        // controller currently connected, otherwise inputs held while loading would get stuck.
+        self.analog_mode = saved.analog_mode;
+        self.analog_mode_locked = saved.analog_mode_locked;
+        self.dualshock_mode = saved.dualshock_mode;
+        self.access_type = saved.access_type;
+        self.watchdog = saved.watchdog;
+        self.rumble = saved.rumble;
+        self.rumble_config = saved.rumble_config;
+        self.rumble_pos = saved.rumble_pos;
+        self.command_internal = saved.command_internal;
+
+        true
+    }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq)]
 enum DsAccessType {
     ReadInput,
     /// Change mode while we're in normal mode
@@ -656,3 +706,135 @@ enum DsAccessType {
     /// Rumble configuration command (doesn't actually start the rumble, just enables it)
     DsRumbleConfig,
 }
+
+/// Namco neGcon (NPC-101): the two halves of the pad twist around the middle, and the I, II and L
+/// buttons are analog.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct NeGcon {
+    /// Start, the D-pad, R, A and B, active low like the digital pad
+    buttons: u16,
+    /// 0x00 twisted all the way to the left, 0x80 centered, 0xff all the way to the right
+    twist: u8,
+    /// Analog I, II and L buttons, 0x00 released to 0xff fully pressed
+    i: u8,
+    ii: u8,
+    l: u8,
+}
+
+impl NeGcon {
+    pub fn new() -> NeGcon {
+        NeGcon {
+            buttons: 0xffff,
+            twist: 0x80,
+            i: 0,
+            ii: 0,
+            l: 0,
+        }
+    }
+}
+
+impl DeviceInterface for NeGcon {
+    fn description(&self) -> String {
+        "Namco neGcon (NPC-101)".to_string()
+    }
+
+    fn controller_type(&self) -> ControllerType {
+        ControllerType::NeGcon
+    }
+
+    fn handle_command(&mut self, seq: u8, cmd: u8) -> (u8, DsrState) {
+        let (resp, send_dsr) = match seq {
+            // First byte should be 0x01 if the command targets the controller
+            0 => (0xff, cmd == 0x01),
+            // Only supports command 0x42: read buttons and axes.
+            //
+            // Response 0x23: we're a neGcon
+            1 => (0x23, cmd == 0x42),
+            // Response 0x5a: 2nd controller ID byte
+            2 => (0x5a, true),
+            // First button state byte: D-pad and start
+            3 => (self.buttons as u8, true),
+            // 2nd button state byte: R, B and A
+            4 => ((self.buttons >> 8) as u8, true),
+            5 => (self.twist, true),
+            6 => (self.i, true),
+            7 => (self.ii, true),
+            // We don't assert DSR for the last byte.
+            8 => (self.l, false),
+            _ => unreachable!(),
+        };
+
+        let dsr_state = if send_dsr {
+            // Same timings as the digital pad
+            DsrState::Pending(360, 90)
+        } else {
+            DsrState::Idle
+        };
+
+        (resp, dsr_state)
+    }
+
+    fn set_button_state(&mut self, button: Button, state: ButtonState) {
+        if button == Button::Analog {
+            // No analog button on the neGcon
+            return;
+        }
+
+        // The analog buttons are pressed all the way by their digital counterparts
+        let analog = match button {
+            Button::Cross => Some(&mut self.i),
+            Button::Square => Some(&mut self.ii),
+            Button::L1 => Some(&mut self.l),
+            _ => None,
+        };
+
+        if let Some(analog) = analog {
+            *analog = if state.is_pressed() { 0xff } else { 0x00 };
+            return;
+        }
+
+        // Start, the D-pad, R1 (R), Triangle (B) and Circle (A) are at the same place as on the
+        // digital pad, the other bits are always set
+        const NEGCON_BUTTONS: u16 = 0x38f8;
+
+        let mask = 1 << (button as usize);
+        if mask & NEGCON_BUTTONS == 0 {
+            return;
+        }
+
+        self.buttons = match state {
+            ButtonState::Pressed => self.buttons & !mask,
+            ButtonState::Released => self.buttons | mask,
+        };
+    }
+
+    fn set_axis_state(&mut self, left: (i16, i16), _right: (i16, i16)) {
+        // The twist follows the left stick
+        self.twist = ((i32::from(left.0) >> 8) + 0x80) as u8;
+    }
+
+    fn save_state(&self) -> DeviceState {
+        DeviceState::NeGcon(self.clone())
+    }
+
+    fn load_state(&mut self, state: &DeviceState) -> bool {
+        // The buttons and the twist are driven by the frontend
+        matches!(state, DeviceState::NeGcon(_))
+    }
+}
+
+#[test]
+fn negcon_twist() {
+    let mut negcon = NeGcon::new();
+    negcon.set_axis_state((-0x8000, 0), (0, 0));
+    negcon.set_button_state(Button::Cross, ButtonState::Pressed);
+    negcon.set_button_state(Button::Circle, ButtonState::Pressed);
+    // No such button on the neGcon
+    negcon.set_button_state(Button::Select, ButtonState::Pressed);
+
+    let read: Vec<u8> = [0x01, 0x42, 0, 0, 0, 0, 0, 0, 0].iter().enumerate()
+        .map(|(seq, &cmd)| negcon.handle_command(seq as u8, cmd).0)
+        .collect();
+
+    assert_eq!(read, [0xff, 0x23, 0x5a, 0xff, 0xdf, 0x00, 0xff, 0x00, 0x00]);
+}
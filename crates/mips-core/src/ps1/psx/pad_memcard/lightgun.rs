@@ -0,0 +1,249 @@
+//! Lightguns: they see the electron beam of the TV go past the spot they point at, the console
+//! works out the position from the video timings.
+
+use crate::input::ControllerType;
+use crate::ps1::psx::pad_memcard::{DeviceInterface, DeviceState, DsrState};
+
+/// Position of the electron beam
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BeamPosition {
+    /// Line number, counted from the vertical sync like `Gpu::cur_line`
+    pub line: u16,
+    /// GPU clock cycles since the start of the line
+    pub column: u16,
+}
+
+/// What the frontend tells a lightgun, see `LightgunInput`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct LightgunState {
+    /// Where the beam is when it passes under the gun, `None` if it points away from the screen
+    pub aim: Option<BeamPosition>,
+    pub trigger: bool,
+    pub a: bool,
+    pub b: bool,
+}
+
+impl LightgunState {
+    /// Button word (active low) with the trigger, A and B on bits `bits`
+    fn buttons(&self, bits: [u16; 3]) -> u16 {
+        let mut b = 0xffff;
+
+        for (pressed, bit) in [self.trigger, self.a, self.b].into_iter().zip(bits) {
+            if pressed {
+                b &= !(1 << bit);
+            }
+        }
+
+        b
+    }
+}
+
+/// GPU clock (NTSC) over the GunCon's 8MHz counter clock. The PAL clock is less than 1% slower,
+/// the games calibrate the gun anyway.
+const GUNCON_X_DIVIDER: u32 = 53_693_182;
+const GUNCON_X_CLOCK: u32 = 8_000_000;
+
+/// Namco GunCon (NPC-103): counts where the beam is when it sees it and reports the counters over
+/// the pad protocol
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+pub struct GunCon {
+    state: LightgunState,
+}
+
+impl GunCon {
+    pub fn new() -> GunCon {
+        GunCon::default()
+    }
+
+    /// X (8MHz cycles since the start of the line) and Y (line) counters. Off-screen the gun
+    /// returns X=1, Y=10.
+    fn position(&self) -> (u16, u16) {
+        match self.state.aim {
+            Some(aim) => {
+                let x = u32::from(aim.column) * GUNCON_X_CLOCK / GUNCON_X_DIVIDER;
+
+                (x as u16, aim.line)
+            }
+            None => (0x0001, 0x000a),
+        }
+    }
+}
+
+impl DeviceInterface for GunCon {
+    fn description(&self) -> String {
+        "Namco GunCon (NPC-103)".to_string()
+    }
+
+    fn controller_type(&self) -> ControllerType {
+        ControllerType::GunCon
+    }
+
+    fn handle_command(&mut self, seq: u8, cmd: u8) -> (u8, DsrState) {
+        // Trigger on Circle, A on Start and B on Cross
+        let buttons = self.state.buttons([13, 3, 14]);
+        let (x, y) = self.position();
+
+        let (resp, send_dsr) = match seq {
+            // First byte should be 0x01 if the command targets the controller
+            0 => (0xff, cmd == 0x01),
+            // Only supports command 0x42: read buttons and position.
+            //
+            // Response 0x63: we're a GunCon
+            1 => (0x63, cmd == 0x42),
+            // Response 0x5a: 2nd controller ID byte
+            2 => (0x5a, true),
+            3 => (buttons as u8, true),
+            4 => ((buttons >> 8) as u8, true),
+            5 => (x as u8, true),
+            6 => ((x >> 8) as u8, true),
+            7 => (y as u8, true),
+            // We don't assert DSR for the last byte.
+            8 => ((y >> 8) as u8, false),
+            _ => unreachable!(),
+        };
+
+        let dsr_state = if send_dsr {
+            // Same timings as the digital pad
+            DsrState::Pending(360, 90)
+        } else {
+            DsrState::Idle
+        };
+
+        (resp, dsr_state)
+    }
+
+    fn set_lightgun_state(&mut self, state: LightgunState) {
+        self.state = state;
+    }
+
+    fn save_state(&self) -> DeviceState {
+        DeviceState::GunCon(self.clone())
+    }
+
+    fn load_state(&mut self, state: &DeviceState) -> bool {
+        // The aim and the buttons are driven by the frontend
+        matches!(state, DeviceState::GunCon(_))
+    }
+}
+
+/// Konami Justifier: only the buttons go over the pad protocol. When it sees the beam it pulls the
+/// lightpen interrupt (IRQ10) and the game reads the position from the timers.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+pub struct Justifier {
+    state: LightgunState,
+    /// The game enables the interrupt with bit 4 of the third byte of the read command
+    irq_enabled: bool,
+}
+
+impl Justifier {
+    pub fn new() -> Justifier {
+        Justifier::default()
+    }
+}
+
+impl DeviceInterface for Justifier {
+    fn description(&self) -> String {
+        "Konami Justifier".to_string()
+    }
+
+    fn controller_type(&self) -> ControllerType {
+        ControllerType::Justifier
+    }
+
+    fn handle_command(&mut self, seq: u8, cmd: u8) -> (u8, DsrState) {
+        // Trigger on Circle, A (auxiliary) on Cross and B on Start
+        let buttons = self.state.buttons([13, 14, 3]);
+
+        let (resp, send_dsr) = match seq {
+            // First byte should be 0x01 if the command targets the controller
+            0 => (0xff, cmd == 0x01),
+            // Only supports command 0x42: read buttons.
+            //
+            // Response 0x31: we're a Justifier
+            1 => (0x31, cmd == 0x42),
+            // Response 0x5a: 2nd controller ID byte
+            2 => {
+                self.irq_enabled = cmd & 0x10 != 0;
+                (0x5a, true)
+            }
+            3 => (buttons as u8, true),
+            // We don't assert DSR for the last byte.
+            4 => ((buttons >> 8) as u8, false),
+            _ => unreachable!(),
+        };
+
+        let dsr_state = if send_dsr {
+            // Same timings as the digital pad
+            DsrState::Pending(360, 90)
+        } else {
+            DsrState::Idle
+        };
+
+        (resp, dsr_state)
+    }
+
+    fn set_lightgun_state(&mut self, state: LightgunState) {
+        self.state = state;
+    }
+
+    fn lightpen_irq(&self) -> Option<BeamPosition> {
+        self.state.aim.filter(|_| self.irq_enabled)
+    }
+
+    fn save_state(&self) -> DeviceState {
+        DeviceState::Justifier(self.clone())
+    }
+
+    fn load_state(&mut self, state: &DeviceState) -> bool {
+        let DeviceState::Justifier(saved) = state else {
+            return false;
+        };
+
+        // The game won't enable the interrupt again if it's already on
+        self.irq_enabled = saved.irq_enabled;
+
+        true
+    }
+}
+
+#[cfg(test)]
+fn read(device: &mut dyn DeviceInterface, tap: u8, len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|seq| {
+            let cmd = match seq {
+                0 => 0x01,
+                1 => 0x42,
+                2 => tap,
+                _ => 0,
+            };
+
+            device.handle_command(seq as u8, cmd).0
+        })
+        .collect()
+}
+
+#[test]
+fn guncon_position() {
+    let mut gun = GunCon::new();
+    let aim = BeamPosition { line: 0x80, column: 0x700 };
+    gun.set_lightgun_state(LightgunState { aim: Some(aim), trigger: true, ..Default::default() });
+
+    // 0x700 GPU cycles are 267 8MHz cycles
+    assert_eq!(read(&mut gun, 0, 9), [0xff, 0x63, 0x5a, 0xff, 0xdf, 0x0b, 0x01, 0x80, 0x00]);
+
+    gun.set_lightgun_state(LightgunState::default());
+    assert_eq!(read(&mut gun, 0, 9), [0xff, 0x63, 0x5a, 0xff, 0xff, 0x01, 0x00, 0x0a, 0x00]);
+}
+
+#[test]
+fn justifier_irq() {
+    let mut gun = Justifier::new();
+    let aim = BeamPosition { line: 0x80, column: 0x700 };
+    gun.set_lightgun_state(LightgunState { aim: Some(aim), b: true, ..Default::default() });
+
+    assert_eq!(read(&mut gun, 0, 5), [0xff, 0x31, 0x5a, 0xf7, 0xff]);
+    assert_eq!(gun.lightpen_irq(), None);
+
+    read(&mut gun, 0x10, 5);
+    assert_eq!(gun.lightpen_irq(), Some(aim));
+}
@@ -1,8 +1,10 @@
-use crate::ps1::psx::pad_memcard::{DeviceInterface, DsrState};
+use log::warn;
+use crate::ps1::psx::pad_memcard::{DeviceInterface, DeviceState, DsrState};
 use crate::ps1::psx::processor::ClockCycle;
 use crate::ps1::util::ds::box_slice::BoxSlice;
 
 /// The standard SCPH-1020 memory card
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct MemoryCard {
     /// The non-volatile Flash memory itself
     memory: BoxSlice<u8, FLASH_SIZE>,
@@ -20,6 +22,7 @@ pub struct MemoryCard {
     last_command: u8,
     /// Buffer holding the memory card write data while it's being received, plus the checksum
     /// byte.
+    #[serde(with = "serde_big_array::BigArray")]
     write_buffer: [u8; 129],
     /// Most games (and the BIOS) don't seem to notice if we swap memory cards without going
     /// through a disconnected state, so we use this counter to disable the memory card for a few
@@ -391,9 +394,36 @@ impl DeviceInterface for MemoryCard {
             self.disabled_frames -= 1;
         }
     }
+
+    fn save_state(&self) -> DeviceState {
+        DeviceState::MemoryCard(Box::new(self.clone()))
+    }
+
+    fn load_state(&mut self, state: &DeviceState) -> bool {
+        let DeviceState::MemoryCard(saved) = state else {
+            return false;
+        };
+
+        if saved.memory != self.memory {
+            // The card has been written since the savestate was made (or it's another card
+            // altogether). We don't want to roll back the user's saves, so we keep the current
+            // contents and simulate a reconnection instead so that the game reloads them.
+            warn!("Memory card contents differ from the savestate, reconnecting it");
+            self.connected();
+            return false;
+        }
+
+        let write_counter = self.write_counter;
+        *self = (**saved).clone();
+        // The counter is compared against the frontend's, it must not go back
+        self.write_counter = write_counter;
+
+        true
+    }
 }
 
 /// The various types of accesses to a Memory Card
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy)]
 enum AccessType {
     /// Read a sector
     Read = b'R' as isize,
@@ -0,0 +1,117 @@
+use crate::input::{ControllerType, MouseInput};
+use crate::ps1::psx::pad_memcard::{DeviceInterface, DeviceState, DsrState};
+
+/// SCPH-1030: PlayStation Mouse. Reports two buttons and the motion since the last time it was
+/// read.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+pub struct Mouse {
+    left: bool,
+    right: bool,
+    /// Motion not reported yet. Only -128 to 127 counts can be reported per read, the rest is
+    /// kept for the next one.
+    pending: (i32, i32),
+    /// Motion reported by the current read
+    report: (i8, i8),
+}
+
+impl Mouse {
+    pub fn new() -> Mouse {
+        Mouse::default()
+    }
+
+    /// Second button byte: bits 2 and 3 are the right and left buttons (active low), bits 0 and 1
+    /// are always 0
+    fn buttons(&self) -> u8 {
+        let mut b = 0xfc;
+
+        if self.right {
+            b &= !(1 << 2);
+        }
+        if self.left {
+            b &= !(1 << 3);
+        }
+
+        b
+    }
+}
+
+impl DeviceInterface for Mouse {
+    fn description(&self) -> String {
+        "PlayStation Mouse (SCPH-1030)".to_string()
+    }
+
+    fn controller_type(&self) -> ControllerType {
+        ControllerType::Mouse
+    }
+
+    fn handle_command(&mut self, seq: u8, cmd: u8) -> (u8, DsrState) {
+        let (resp, send_dsr) = match seq {
+            // First byte should be 0x01 if the command targets the controller
+            0 => (0xff, cmd == 0x01),
+            // Only supports command 0x42: read buttons and motion.
+            //
+            // Response 0x12: we're a mouse
+            1 => {
+                let report = |pending: &mut i32| {
+                    let r = (*pending).clamp(i8::MIN.into(), i8::MAX.into());
+                    *pending -= r;
+                    r as i8
+                };
+                if cmd == 0x42 {
+                    self.report = (report(&mut self.pending.0), report(&mut self.pending.1));
+                }
+                (0x12, cmd == 0x42)
+            }
+            // Response 0x5a: 2nd controller ID byte
+            2 => (0x5a, true),
+            // First button byte, unused
+            3 => (0xff, true),
+            4 => (self.buttons(), true),
+            5 => (self.report.0 as u8, true),
+            // We don't assert DSR for the last byte.
+            6 => (self.report.1 as u8, false),
+            _ => unreachable!(),
+        };
+
+        let dsr_state = if send_dsr {
+            // Same timings as the digital pad
+            DsrState::Pending(360, 90)
+        } else {
+            DsrState::Idle
+        };
+
+        (resp, dsr_state)
+    }
+
+    fn set_mouse_state(&mut self, input: MouseInput) {
+        self.left = input.left;
+        self.right = input.right;
+        self.pending.0 = self.pending.0.saturating_add(input.dx);
+        self.pending.1 = self.pending.1.saturating_add(input.dy);
+    }
+
+    fn save_state(&self) -> DeviceState {
+        DeviceState::Mouse(self.clone())
+    }
+
+    fn load_state(&mut self, state: &DeviceState) -> bool {
+        // The buttons and the motion are driven by the frontend
+        matches!(state, DeviceState::Mouse(_))
+    }
+}
+
+#[test]
+fn mouse_motion() {
+    let mut mouse = Mouse::new();
+    mouse.set_mouse_state(MouseInput { dx: 200, dy: -3, left: true, right: false });
+
+    let read = |mouse: &mut Mouse| -> Vec<u8> {
+        [0x01, 0x42, 0, 0, 0, 0, 0].iter().enumerate()
+            .map(|(seq, &cmd)| mouse.handle_command(seq as u8, cmd).0)
+            .collect()
+    };
+
+    assert_eq!(read(&mut mouse), [0xff, 0x12, 0x5a, 0xff, 0xf4, 127, (-3i8) as u8]);
+    // The rest of the motion comes with the next read
+    assert_eq!(read(&mut mouse), [0xff, 0x12, 0x5a, 0xff, 0xf4, 73, 0]);
+}
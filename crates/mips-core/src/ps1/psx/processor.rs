@@ -1,13 +1,6 @@
 pub mod cpu;
-mod cache;
-mod instruction;
-mod opcodes;
-pub mod cop0;
+pub mod dynarec;
 pub mod irq;
 pub mod gte;
 
-#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
-pub struct RegisterIndex(pub u8);
-
-pub type ClockCycle = i32;
-
+pub use mips_r3000::{cop0, ClockCycle, RegisterIndex};
@@ -0,0 +1,758 @@
+//! Dynamic recompiler: translates the code the CPU runs to x86-64 machine code a block at a time,
+//! and keeps the translations until the RAM they were made from is written to.
+//!
+//! Each block is translated twice. Before running it, we check once that the instruction cache
+//! holds the whole block as it was translated (`mips_r3000::cpu::icache_holds`): every fetch would
+//! be a hit, which costs nothing and returns the very instructions translated. The fast version
+//! then only does natively what the interpreter does between two instructions: leave for the
+//! pending events and interrupts, move the PC on and tick the clock (or catch up with a load).
+//! Otherwise, the first time a block runs or from uncached memory, the other version has each
+//! instruction go through the fetch of the interpreter (`mips_r3000::cpu::begin_instruction`) so
+//! that the cache misses and their timings are the same, and checks the word actually fetched
+//! against the one that was translated: a stale translation runs the instruction fetched with the
+//! interpreter and leaves the block. The simple ALU instructions are translated to native
+//! instructions on the registers in `Cpu`, the others call their interpreter handler directly.
+//!
+//! Blocks end after a branch and its delay slot, and before the instructions left to the
+//! interpreter: coprocessors 0, 1 and 3 (and the GTE for the games that need it, see
+//! `Dynarec::set_translate_gte`), system calls, breaks and illegal opcodes. Blocks are
+//! never entered in a delay slot or with an interrupt pending, the interpreter runs these
+//! instructions.
+
+mod emitter;
+mod exec_memory;
+
+use std::any::Any;
+use std::cell::Cell;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use fnv::FnvHashMap;
+use log::warn;
+use crate::block_cache::CompiledBlock;
+use crate::ps1::psx::bus::Bus;
+use crate::ps1::psx::memory::map;
+use mips_r3000::cpu as r3000;
+use mips_r3000::instruction::Instruction;
+use mips_r3000::opcodes;
+use crate::ps1::psx::sync;
+use crate::ps1::psx::xmem::{XMemory, CODE_PAGE_COUNT, CODE_PAGE_SHIFT};
+use emitter::{Alu, Cond, Emitter, Fixup, Reg, Shift, ARGS, RAX, RBX, RCX, SHADOW_SPACE};
+use exec_memory::ExecMemory;
+
+/// Memory for the translated code. Everything is dropped when it's full.
+const CODE_SIZE: usize = 32 << 20;
+
+/// Padding between the two versions of a block, it traps if it's ever run
+const INT3: u8 = 0xcc;
+
+/// Longest block, in instructions, not counting the delay slot of a final branch
+const MAX_BLOCK_LEN: usize = 64;
+
+/// Returned by the helpers called from the translated code: keep going or leave the block
+const CONTINUE: u32 = 0;
+const EXIT: u32 = 1;
+
+/// Offsets in `Bus` of the state the translated code accesses directly
+const REGS: usize = mem::offset_of!(Bus, cpu) + r3000::REGS_OFFSET;
+const FREE_CYCLES: usize = mem::offset_of!(Bus, cpu) + r3000::FREE_CYCLES_OFFSET;
+const PC: usize = mem::offset_of!(Bus, cpu) + r3000::PC_OFFSET;
+const CURRENT_PC: usize = mem::offset_of!(Bus, cpu) + r3000::CURRENT_PC_OFFSET;
+const NEXT_PC: usize = mem::offset_of!(Bus, cpu) + r3000::NEXT_PC_OFFSET;
+const FREE_CYCLES_REG: usize = mem::offset_of!(Bus, cpu) + r3000::FREE_CYCLES_REG_OFFSET;
+const BRANCH: usize = mem::offset_of!(Bus, cpu) + r3000::BRANCH_OFFSET;
+const DELAY_SLOT: usize = mem::offset_of!(Bus, cpu) + r3000::DELAY_SLOT_OFFSET;
+const OPCODE_TABLE_OFFSET: usize = mem::offset_of!(Bus, cpu) + r3000::OPCODE_TABLE_OFFSET_OFFSET;
+const CYCLES: usize = mem::offset_of!(Bus, cycles);
+const FIRST_EVENT: usize = mem::offset_of!(Bus, sync) + sync::FIRST_EVENT_OFFSET;
+
+type Block = unsafe extern "C" fn(*mut Bus);
+
+thread_local! {
+    /// Panic caught in a helper, to be resumed once out of the translated code: unwinding can't go
+    /// through it
+    static PANIC: Cell<Option<Box<dyn Any + Send>>> = const { Cell::new(None) };
+}
+
+/// Translated block
+struct Entry {
+    /// Code fetching each instruction through the interpreter
+    block: Block,
+    /// Code for when the instruction cache holds the whole block
+    cached: Block,
+    /// Offset of the host code of both in the code memory, and its size
+    offset: usize,
+    size: usize,
+    /// Instructions translated
+    words: Box<[u32]>,
+    /// Times the block was entered
+    executions: u64,
+}
+
+impl Entry {
+    /// Count a run of the block starting at `pc`, which the caller is about to make, and pick the
+    /// code to run
+    fn enter(&mut self, bus: &Bus, pc: u32) -> Block {
+        self.executions += 1;
+
+        // The fast version ticks the clock directly
+        if bus.cpu_clock_is_native() && r3000::icache_holds(bus, pc, &self.words) {
+            self.cached
+        } else {
+            self.block
+        }
+    }
+}
+
+pub struct Dynarec {
+    memory: ExecMemory,
+    /// Blocks by address of their first instruction. `None` where the interpreter has to run the
+    /// first instruction.
+    blocks: FnvHashMap<u32, Option<Entry>>,
+    /// Addresses of the blocks translated from each RAM code page
+    page_blocks: Vec<Vec<u32>>,
+    /// Blocks dropped because their code was written to, by address
+    invalidations: FnvHashMap<u32, u32>,
+    /// False to leave the GTE instructions and loads and stores to the interpreter
+    translate_gte: bool,
+}
+
+impl Dynarec {
+    /// `None` if the host can't run the translated code
+    pub fn new() -> Option<Dynarec> {
+        let Some(memory) = ExecMemory::new(CODE_SIZE) else {
+            warn!("The dynarec isn't available on this host, using the interpreter");
+            return None;
+        };
+
+        Some(Dynarec {
+            memory,
+            blocks: FnvHashMap::default(),
+            page_blocks: vec![Vec::new(); CODE_PAGE_COUNT],
+            invalidations: FnvHashMap::default(),
+            translate_gte: true,
+        })
+    }
+
+    /// Translate the GTE instructions with the rest, or end the blocks before them. The blocks
+    /// translated the other way are dropped.
+    pub fn set_translate_gte(&mut self, xmem: &mut XMemory, translate: bool) {
+        if translate != self.translate_gte {
+            self.translate_gte = translate;
+            self.clear(xmem);
+        }
+    }
+
+    /// Drop all the blocks
+    pub fn clear(&mut self, xmem: &mut XMemory) {
+        self.blocks.clear();
+        self.page_blocks.iter_mut().for_each(Vec::clear);
+        self.memory.clear();
+        xmem.clear_code_pages();
+    }
+
+    /// Drop the blocks translated from a code page that was written to
+    fn invalidate_page(&mut self, page: u32) {
+        for pc in self.page_blocks[page as usize].drain(..) {
+            if let Some(Some(_)) = self.blocks.remove(&pc) {
+                *self.invalidations.entry(pc).or_default() += 1;
+            }
+        }
+    }
+
+    /// The blocks currently translated, for the block cache inspector
+    pub fn compiled_blocks(&self) -> Vec<CompiledBlock> {
+        self.blocks
+            .iter()
+            .filter_map(|(&pc, entry)| {
+                let entry = entry.as_ref()?;
+
+                Some(CompiledBlock {
+                    start: pc,
+                    end: pc.wrapping_add(entry.words.len() as u32 * 4),
+                    host_size: entry.size,
+                    executions: entry.executions,
+                    invalidations: self.invalidations.get(&pc).copied().unwrap_or(0),
+                })
+            })
+            .collect()
+    }
+
+    /// Host code of the block starting at `pc`
+    pub fn block_code(&self, pc: u32) -> Option<Vec<u8>> {
+        let entry = self.blocks.get(&pc)?.as_ref()?;
+
+        Some(self.memory.code(entry.offset, entry.size).to_vec())
+    }
+
+    /// Block to run next, `None` if the interpreter has to run the next instruction
+    fn block(&mut self, bus: &mut Bus) -> Option<Block> {
+        let cpu = &bus.cpu;
+
+        if cpu.branch || cpu.opcode_table_offset != 0 || cpu.next_pc != cpu.pc.wrapping_add(4) {
+            return None;
+        }
+
+        let pc = cpu.pc;
+        if let Some(entry) = self.blocks.get_mut(&pc) {
+            return entry.as_mut().map(|entry| entry.enter(bus, pc));
+        }
+
+        let mut entry = self.translate(bus, pc);
+        let block = entry.as_mut().map(|entry| entry.enter(bus, pc));
+        self.blocks.insert(pc, entry);
+        block
+    }
+
+    fn translate(&mut self, bus: &mut Bus, pc: u32) -> Option<Entry> {
+        let ram_offset = map::RAM.contains(map::mask_region(pc));
+
+        // Only the RAM and the BIOS contain code. The interpreter raises the exceptions.
+        if pc % 4 != 0 || (ram_offset.is_none() && map::BIOS.contains(map::mask_region(pc)).is_none()) {
+            return None;
+        }
+
+        let instructions = block_instructions(&bus.xmem, pc, self.translate_gte);
+
+        let block = if instructions.is_empty() {
+            None
+        } else {
+            let mut code = generate(pc, &instructions, false);
+            // Keep the entry point aligned like the one of the block
+            code.resize(code.len().next_multiple_of(16), INT3);
+            let cached = code.len();
+            code.extend(generate(pc, &instructions, true));
+
+            let offset = match self.memory.push(&code) {
+                Some(offset) => offset,
+                None => {
+                    self.clear(&mut bus.xmem);
+                    self.memory.push(&code).expect("Block larger than the code memory")
+                }
+            };
+
+            // SAFETY: `generate` made functions with that signature
+            let function = |offset| unsafe {
+                mem::transmute::<*const u8, Block>(self.memory.entry(offset))
+            };
+
+            Some(Entry {
+                block: function(offset),
+                cached: function(offset + cached),
+                offset,
+                size: code.len(),
+                words: instructions.iter().map(|i| i.0).collect(),
+                executions: 0,
+            })
+        };
+
+        // Also tracked without a block, so that we look again if the code changes
+        if let Some(offset) = ram_offset {
+            let last = offset + (instructions.len().max(1) as u32 - 1) * 4;
+
+            for page in (offset >> CODE_PAGE_SHIFT)..=(last >> CODE_PAGE_SHIFT) {
+                let page = page as usize % CODE_PAGE_COUNT;
+
+                bus.xmem.mark_code((page as u32) << CODE_PAGE_SHIFT);
+                if !self.page_blocks[page].contains(&pc) {
+                    self.page_blocks[page].push(pc);
+                }
+            }
+        }
+
+        block
+    }
+}
+
+/// Run blocks (or the interpreter where there's none) until an event is pending
+pub fn run_until_event(bus: &mut Bus) {
+    let Some(mut dynarec) = bus.dynarec.take() else {
+        return;
+    };
+
+    while !sync::is_event_pending(bus) {
+        if bus.xmem.has_invalidated_code() {
+            for page in bus.xmem.drain_invalidated_code() {
+                dynarec.invalidate_page(page);
+            }
+        }
+
+        match dynarec.block(bus) {
+            Some(block) => {
+                // SAFETY: the block was generated for this bus layout and its code is kept until
+                // the next `translate`
+                unsafe { block(bus) };
+
+                if let Some(payload) = PANIC.take() {
+                    bus.dynarec = Some(dynarec);
+                    panic::resume_unwind(payload);
+                }
+            }
+            None => r3000::run_next_instruction(bus),
+        }
+    }
+
+    bus.dynarec = Some(dynarec);
+}
+
+/// Instructions of the block starting at `pc`, empty if the first one is left to the interpreter.
+/// The GTE ones are only translated with `gte`.
+fn block_instructions(xmem: &XMemory, pc: u32, gte: bool) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut addr = pc;
+
+    while instructions.len() < MAX_BLOCK_LEN {
+        let instruction = xmem.load_instruction(addr);
+        if !is_translatable(instruction, gte) {
+            break;
+        }
+        instructions.push(instruction);
+
+        if is_branch(instruction) {
+            // A branch in the delay slot is left to the interpreter
+            let delay_slot = xmem.load_instruction(addr.wrapping_add(4));
+            if is_translatable(delay_slot, gte) && !is_branch(delay_slot) {
+                instructions.push(delay_slot);
+            }
+            break;
+        }
+
+        addr = addr.wrapping_add(4);
+    }
+
+    instructions
+}
+
+fn is_translatable(instruction: Instruction, gte: bool) -> bool {
+    match instruction.opcode() {
+        0x12 | 0x32 | 0x3a if !gte => false,
+        0x00 => matches!(
+            instruction.function(),
+            0x00 | 0x02..=0x04 | 0x06..=0x09 | 0x10..=0x13 | 0x18..=0x1b | 0x20..=0x27 | 0x2a | 0x2b
+        ),
+        // Branches, ALU with immediates and GTE
+        0x01..=0x0f | 0x12 => true,
+        // Loads and stores, including to the GTE
+        0x20..=0x26 | 0x28..=0x2b | 0x2e | 0x32 | 0x3a => true,
+        _ => false,
+    }
+}
+
+fn is_store(instruction: Instruction) -> bool {
+    matches!(instruction.opcode(), 0x28..=0x2b | 0x2e | 0x3a)
+}
+
+fn is_branch(instruction: Instruction) -> bool {
+    match instruction.opcode() {
+        0x00 => matches!(instruction.function(), 0x08 | 0x09),
+        0x01..=0x07 => true,
+        _ => false,
+    }
+}
+
+/// Operations of the instructions translated to native code
+#[derive(Clone, Copy)]
+enum Op {
+    Alu(Alu),
+    Nor,
+    Slt,
+    Sltu,
+}
+
+/// Instructions translated to native code: they only read and write the general purpose registers
+#[derive(Clone, Copy)]
+enum Native {
+    /// `d = s op t`
+    Register(Op),
+    /// `d = t shifted by the immediate`
+    ShiftImmediate(Shift),
+    /// `d = t shifted by s`
+    ShiftRegister(Shift),
+    /// `t = s op immediate`
+    Immediate(Op, u32),
+    /// `t = immediate << 16`
+    Lui,
+}
+
+fn native(instruction: Instruction) -> Option<Native> {
+    let native = match instruction.opcode() {
+        0x00 => match instruction.function() {
+            0x00 => Native::ShiftImmediate(Shift::Shl),
+            0x02 => Native::ShiftImmediate(Shift::Shr),
+            0x03 => Native::ShiftImmediate(Shift::Sar),
+            0x04 => Native::ShiftRegister(Shift::Shl),
+            0x06 => Native::ShiftRegister(Shift::Shr),
+            0x07 => Native::ShiftRegister(Shift::Sar),
+            0x21 => Native::Register(Op::Alu(Alu::Add)),
+            0x23 => Native::Register(Op::Alu(Alu::Sub)),
+            0x24 => Native::Register(Op::Alu(Alu::And)),
+            0x25 => Native::Register(Op::Alu(Alu::Or)),
+            0x26 => Native::Register(Op::Alu(Alu::Xor)),
+            0x27 => Native::Register(Op::Nor),
+            0x2a => Native::Register(Op::Slt),
+            0x2b => Native::Register(Op::Sltu),
+            _ => return None,
+        },
+        0x09 => Native::Immediate(Op::Alu(Alu::Add), instruction.imm_se()),
+        0x0a => Native::Immediate(Op::Slt, instruction.imm_se()),
+        0x0b => Native::Immediate(Op::Sltu, instruction.imm_se()),
+        0x0c => Native::Immediate(Op::Alu(Alu::And), instruction.imm()),
+        0x0d => Native::Immediate(Op::Alu(Alu::Or), instruction.imm()),
+        0x0e => Native::Immediate(Op::Alu(Alu::Xor), instruction.imm()),
+        0x0f => Native::Lui,
+        _ => return None,
+    };
+
+    Some(native)
+}
+
+/// Generate the code of the block made of `instructions`, starting at `pc`. With `cached` the
+/// instructions aren't fetched, the instruction cache has to hold all of them.
+fn generate(pc: u32, instructions: &[Instruction], cached: bool) -> Vec<u8> {
+    let mut e = Emitter::new();
+    let mut exits = Vec::new();
+
+    e.push(RBX);
+    e.sub_rsp(SHADOW_SPACE);
+    e.mov64(RBX, ARGS[0]);
+
+    // The native code doesn't handle the load delay slot. There's no pending load after an
+    // instruction that's translated to native code, so the next one can be too.
+    let mut no_pending_load = false;
+
+    for (n, &instruction) in instructions.iter().enumerate() {
+        let current_pc = pc.wrapping_add(4 * n as u32);
+        let next_pc = current_pc.wrapping_add(4);
+
+        if cached {
+            let delay_slot = n > 0 && is_branch(instructions[n - 1]);
+            emit_enter_instruction(&mut e, &mut exits, n, current_pc, delay_slot);
+        } else {
+            call(&mut e, enter_instruction as HelperFn as usize, &[instruction.0]);
+            e.test(RAX);
+            exits.push(e.jump_if(Cond::NotEqual));
+        }
+
+        let native_op = native(instruction);
+
+        match native_op {
+            Some(native) if no_pending_load => emit_native(&mut e, instruction, native),
+            _ => {
+                let index = opcodes::base_handler_index(instruction) as u32;
+                let handler = if cached && is_store(instruction) { run_store } else { run_handler };
+
+                call(&mut e, handler as HandlerFn as usize, &[instruction.0, index]);
+                e.test(RAX);
+                exits.push(e.jump_if(Cond::NotEqual));
+
+                // Leave if it branched, or raised an exception
+                e.cmp_mem_imm(PC, next_pc);
+                exits.push(e.jump_if(Cond::NotEqual));
+            }
+        }
+
+        no_pending_load = native_op.is_some();
+    }
+
+    for exit in exits {
+        e.bind(exit);
+    }
+    e.add_rsp(SHADOW_SPACE);
+    e.pop(RBX);
+    e.ret();
+
+    e.into_code()
+}
+
+/// What `mips_r3000::cpu::begin_instruction` does when the instruction is in the cache, plus the
+/// checks of `run_until_event`, for instruction `n` of a block, at `pc`. It's a delay slot if it
+/// follows the final branch.
+fn emit_enter_instruction(
+    e: &mut Emitter,
+    exits: &mut Vec<Fixup>,
+    n: usize,
+    pc: u32,
+    delay_slot: bool,
+) {
+    // Leave for the pending events and interrupts, these were checked before entering the block
+    if n > 0 {
+        e.load(RAX, CYCLES);
+        e.alu_mem(Alu::Cmp, RAX, FIRST_EVENT);
+        exits.push(e.jump_if(Cond::GE));
+
+        e.cmp_u8_imm(OPCODE_TABLE_OFFSET, 0);
+        exits.push(e.jump_if(Cond::NotEqual));
+    }
+
+    e.store_imm(CURRENT_PC, pc);
+    if delay_slot {
+        // The PC moves on to `next_pc`, the target if the branch was taken
+        e.load(RAX, NEXT_PC);
+        e.store(PC, RAX);
+        e.alu_imm(Alu::Add, RAX, 4);
+        e.store(NEXT_PC, RAX);
+        e.load_u8(RAX, BRANCH);
+        e.store_u8(DELAY_SLOT, RAX);
+        e.store_u8_imm(BRANCH, 0);
+    } else {
+        // Nothing branched since the block was entered, which isn't done in a delay slot
+        e.store_imm(PC, pc.wrapping_add(4));
+        e.store_imm(NEXT_PC, pc.wrapping_add(8));
+        if n == 0 {
+            e.store_u8_imm(DELAY_SLOT, 0);
+        }
+    }
+
+    // `instruction_tick`: catch up with a load, or move the time forward
+    e.load_u8(RAX, FREE_CYCLES_REG);
+    e.alu_imm(Alu::And, RAX, 0x1f);
+    e.cmp_u8_indexed_imm(RAX, FREE_CYCLES, 0);
+    let tick = e.jump_if(Cond::Equal);
+    e.dec_u8_indexed(RAX, FREE_CYCLES);
+    let done = e.jump();
+    e.bind(tick);
+    e.add_mem_imm(CYCLES, 1);
+    e.bind(done);
+}
+
+/// Call `function` with the bus and `args`
+fn call(e: &mut Emitter, function: usize, args: &[u32]) {
+    e.mov64(ARGS[0], RBX);
+    for (&reg, &arg) in ARGS[1..].iter().zip(args) {
+        e.mov_imm(reg, arg);
+    }
+    e.mov_imm64(RAX, function as u64);
+    e.call(RAX);
+}
+
+fn emit_native(e: &mut Emitter, instruction: Instruction, native: Native) {
+    let (s, t, d) = (instruction.s().0, instruction.t().0, instruction.d().0);
+
+    let (target, deps) = match native {
+        Native::Register(_) | Native::ShiftRegister(_) => (d, [d, s, t]),
+        Native::ShiftImmediate(_) => (d, [d, t, 0]),
+        Native::Immediate(..) => (t, [t, s, 0]),
+        Native::Lui => (t, [t, 0, 0]),
+    };
+
+    // `reg_dep`: wait for the loads to the registers used
+    for r in deps {
+        if r != 0 {
+            e.store_u8_imm(FREE_CYCLES + r as usize, 0);
+        }
+    }
+
+    // R0 can't be written to
+    if target == 0 {
+        return;
+    }
+
+    match native {
+        Native::Register(op) => {
+            load_reg(e, RAX, s);
+            load_reg(e, RCX, t);
+            emit_op(e, op);
+        }
+        Native::ShiftImmediate(shift) => {
+            load_reg(e, RAX, t);
+            e.shift_imm(shift, RAX, instruction.shift() as u8);
+        }
+        Native::ShiftRegister(shift) => {
+            // The shift amount is truncated to 5 bits on both sides
+            load_reg(e, RAX, t);
+            load_reg(e, RCX, s);
+            e.shift_cl(shift, RAX);
+        }
+        Native::Immediate(op, imm) => {
+            load_reg(e, RAX, s);
+            e.mov_imm(RCX, imm);
+            emit_op(e, op);
+        }
+        Native::Lui => e.mov_imm(RAX, instruction.imm() << 16),
+    }
+
+    e.store(REGS + target as usize * 4, RAX);
+}
+
+/// `rax = rax op rcx`
+fn emit_op(e: &mut Emitter, op: Op) {
+    match op {
+        Op::Alu(alu) => e.alu(alu, RAX, RCX),
+        Op::Nor => {
+            e.alu(Alu::Or, RAX, RCX);
+            e.not(RAX);
+        }
+        Op::Slt => {
+            e.alu(Alu::Cmp, RAX, RCX);
+            e.set_if(Cond::L, RAX);
+        }
+        Op::Sltu => {
+            e.alu(Alu::Cmp, RAX, RCX);
+            e.set_if(Cond::B, RAX);
+        }
+    }
+}
+
+fn load_reg(e: &mut Emitter, dst: Reg, r: u8) {
+    if r == 0 {
+        e.mov_imm(dst, 0);
+    } else {
+        e.load(dst, REGS + r as usize * 4);
+    }
+}
+
+type HelperFn = extern "C" fn(*mut Bus, u32) -> u32;
+type HandlerFn = extern "C" fn(*mut Bus, u32, u32) -> u32;
+
+/// Called by the translated code before each instruction, with the instruction that was
+/// translated. Does what the interpreter does between two instructions, up to the dispatch.
+extern "C" fn enter_instruction(bus: *mut Bus, word: u32) -> u32 {
+    // SAFETY: the translated code passes the bus it was given
+    let bus = unsafe { &mut *bus };
+
+    catch_panic(|| {
+        if sync::is_event_pending(bus) || bus.cpu.opcode_table_offset != 0 {
+            return EXIT;
+        }
+
+        match r3000::begin_instruction(bus) {
+            Some(instruction) if instruction.0 == word => CONTINUE,
+            Some(instruction) => {
+                // Not what was translated: the RAM was written to since, or the instruction cache
+                // holds older code
+                opcodes::run_instruction(bus, instruction);
+                EXIT
+            }
+            None => EXIT,
+        }
+    })
+}
+
+/// Called by the translated code to run an instruction with its interpreter handler
+extern "C" fn run_handler(bus: *mut Bus, word: u32, index: u32) -> u32 {
+    // SAFETY: the translated code passes the bus it was given
+    let bus = unsafe { &mut *bus };
+
+    catch_panic(|| {
+        opcodes::handler::<Bus>(index as usize)(bus, Instruction(word));
+        CONTINUE
+    })
+}
+
+/// `run_handler` for the stores of the blocks run from the instruction cache. Leaves the block if
+/// the store disabled the cache: the instructions left would have to be fetched from the memory.
+extern "C" fn run_store(bus: *mut Bus, word: u32, index: u32) -> u32 {
+    let status = run_handler(bus, word, index);
+
+    // SAFETY: the translated code passes the bus it was given
+    let bus = unsafe { &*bus };
+
+    if bus.icache_enabled() { status } else { EXIT }
+}
+
+/// Run `f`, keeping its panic for `run_until_event` to resume
+fn catch_panic(f: impl FnOnce() -> u32) -> u32 {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(status) => status,
+        Err(payload) => {
+            PANIC.set(Some(payload));
+            EXIT
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_bus(base: u32) -> Box<Bus> {
+    use crate::ps1::psx::assembler::{Assembler, syntax::*};
+    use crate::ps1::psx::bios::bios::Bios;
+    use crate::ps1::psx::cd::CdInterface;
+
+    let program = [
+        Li(T0, 0x1234_5678),
+        Li(T1, 0x9abc_def0),
+        Li(S5, 0x8010_0000),
+        La(S4, Label::Global("patched")),
+        Local("loop"),
+        Addu(T3, T0, T1),
+        Xor(T4, T3, T0),
+        Sll(T5, T4, 3),
+        Srav(T6, T5, T0),
+        Nor(T7, T6, T3),
+        Slt(S0, T7, T0),
+        Sltu(S1, T0, T7),
+        Slti(S2, T3, -5),
+        Sltiu(S6, T3, -5),
+        Andi(T1, T4, 0xffff),
+        Ori(T1, T1, 0x55),
+        Lui(S7, 0x1234),
+        // Load delay: the ADDU sees the value from before the load
+        Sw(T3, S5, 0),
+        Lw(T7, S5, 0),
+        Addu(T8, T7, T7),
+        Mult(T0, T1),
+        Mflo(T2),
+        Divu(T2, T1),
+        Mfhi(T9),
+        Jal(Label::Global("patched")),
+        Nop,
+        // Self-modifying code: switch between ADDIU +1 and +2
+        Lw(T6, S4, 0),
+        Nop,
+        Xori(T6, T6, 3),
+        Sw(T6, S4, 0),
+        Addiu(T0, T0, 1),
+        B(Label::Local("loop", 'b')),
+        Nop,
+        Global("patched"),
+        Addiu(S3, S3, 1),
+        Jr(RA),
+        Nop,
+    ];
+
+    let cd = CdInterface::new_without_firmware();
+    let mut bus = Box::new(Bus::with_cd(Bios::new_dummy(), cd));
+
+    let mut asm = Assembler::from_base(base);
+    asm.assemble(&program).unwrap();
+    let (code, _) = asm.machine_code();
+    bus.xmem.ram_store_block(base, &code, code.len());
+
+    // Enable the instruction cache like the BIOS does
+    bus.store(0xfffe_0130, 0x0001_e988u32);
+
+    bus.cpu.pc = base;
+    bus.cpu.next_pc = base + 4;
+
+    bus
+}
+
+#[test]
+fn same_as_interpreter() {
+    use crate::ps1::savestate::serialize_raw;
+
+    if !crate::ps1::settings::cpu::CpuBackend::Dynarec.is_available() {
+        return;
+    }
+
+    // Cached, where the stores to the code aren't seen until the cacheline is reloaded, and
+    // uncached
+    for base in [0x8001_0000, 0xa001_0000] {
+        let mut interpreter = test_bus(base);
+        let mut dynarec = test_bus(base);
+        dynarec.set_dynarec(true);
+
+        for frame in 0..3 {
+            interpreter.update();
+            dynarec.update();
+
+            assert_eq!(dynarec.cycles, interpreter.cycles, "frame {} at 0x{:08x}", frame, base);
+            assert_eq!(dynarec.cpu.pc, interpreter.cpu.pc, "frame {} at 0x{:08x}", frame, base);
+            assert!(serialize_raw(&dynarec).unwrap() == serialize_raw(&interpreter).unwrap(),
+                    "frame {} at 0x{:08x}", frame, base);
+        }
+
+        let blocks = dynarec.dynarec.as_ref().unwrap().compiled_blocks();
+        assert!(blocks.iter().any(|b| b.executions > 0));
+        // The patched instruction shares its page with the loop
+        assert!(blocks.iter().any(|b| b.invalidations > 0));
+    }
+}
@@ -0,0 +1,380 @@
+//! Minimal x86-64 assembler for the dynarec: only the instructions it generates, on 32bit
+//! registers and on memory relative to RBX, which holds the address of the `Bus` in the generated
+//! code.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Reg(u8);
+
+pub const RAX: Reg = Reg(0);
+pub const RCX: Reg = Reg(1);
+pub const RDX: Reg = Reg(2);
+pub const RBX: Reg = Reg(3);
+pub const RSP: Reg = Reg(4);
+pub const RSI: Reg = Reg(6);
+pub const RDI: Reg = Reg(7);
+pub const R8: Reg = Reg(8);
+
+/// Registers of the first three integer arguments in the calling convention of the host
+pub const ARGS: [Reg; 3] = if cfg!(windows) { [RCX, RDX, R8] } else { [RDI, RSI, RDX] };
+
+/// Stack space the caller has to reserve for the callee (Windows "shadow space"), kept on all
+/// hosts. It also keeps the stack aligned on 16 bytes once RBX is pushed.
+pub const SHADOW_SPACE: u8 = 32;
+
+/// Arithmetic and logic operations, the value is the opcode extension of the immediate forms
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Alu {
+    Add = 0,
+    Or = 1,
+    And = 4,
+    Sub = 5,
+    Xor = 6,
+    Cmp = 7,
+}
+
+/// Shifts, the value is the opcode extension
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Shift {
+    Shl = 4,
+    Shr = 5,
+    Sar = 7,
+}
+
+/// Condition codes of Jcc and SETcc
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cond {
+    /// Below (unsigned)
+    B = 0x2,
+    Equal = 0x4,
+    NotEqual = 0x5,
+    /// Less (signed)
+    L = 0xc,
+    /// Greater or equal (signed)
+    GE = 0xd,
+}
+
+/// Jump whose target isn't known yet, see `Emitter::bind`
+#[must_use]
+pub struct Fixup(usize);
+
+pub struct Emitter {
+    code: Vec<u8>,
+}
+
+impl Emitter {
+    pub fn new() -> Emitter {
+        Emitter { code: Vec::new() }
+    }
+
+    pub fn into_code(self) -> Vec<u8> {
+        self.code
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// REX prefix, only emitted if needed. `reg` goes in the ModR/M reg field, `rm` in the r/m
+    /// field (or in the opcode).
+    fn rex(&mut self, wide: bool, reg: Reg, rm: Reg) {
+        let rex = 0x40 | ((wide as u8) << 3) | ((reg.0 >> 3) << 2) | (rm.0 >> 3);
+
+        if rex != 0x40 {
+            self.emit(&[rex]);
+        }
+    }
+
+    /// ModR/M for register to register operations
+    fn modrm_reg(&mut self, reg: u8, rm: Reg) {
+        self.emit(&[0xc0 | ((reg & 7) << 3) | (rm.0 & 7)]);
+    }
+
+    /// ModR/M and displacement for `[rbx + offset]`
+    fn modrm_bus(&mut self, reg: u8, offset: usize) {
+        let disp = i32::try_from(offset).expect("Bus offset out of range");
+
+        self.emit(&[0x80 | ((reg & 7) << 3) | RBX.0]);
+        self.emit(&disp.to_le_bytes());
+    }
+
+    /// ModR/M, SIB and displacement for `[rbx + index + offset]`
+    fn modrm_bus_indexed(&mut self, reg: u8, index: Reg, offset: usize) {
+        // No REX.X, and RSP can't be an index
+        assert!(index.0 < 8 && index != RSP, "Can't index with {:?}", index);
+        let disp = i32::try_from(offset).expect("Bus offset out of range");
+
+        self.emit(&[0x84 | ((reg & 7) << 3), (index.0 << 3) | RBX.0]);
+        self.emit(&disp.to_le_bytes());
+    }
+
+    pub fn push(&mut self, r: Reg) {
+        self.rex(false, Reg(0), r);
+        self.emit(&[0x50 + (r.0 & 7)]);
+    }
+
+    pub fn pop(&mut self, r: Reg) {
+        self.rex(false, Reg(0), r);
+        self.emit(&[0x58 + (r.0 & 7)]);
+    }
+
+    pub fn ret(&mut self) {
+        self.emit(&[0xc3]);
+    }
+
+    /// Reserve `bytes` on the stack
+    pub fn sub_rsp(&mut self, bytes: u8) {
+        self.emit(&[0x48, 0x83, 0xec, bytes]);
+    }
+
+    /// Release `bytes` from the stack
+    pub fn add_rsp(&mut self, bytes: u8) {
+        self.emit(&[0x48, 0x83, 0xc4, bytes]);
+    }
+
+    /// `mov dst, src` on 64bit registers
+    pub fn mov64(&mut self, dst: Reg, src: Reg) {
+        self.rex(true, src, dst);
+        self.emit(&[0x89]);
+        self.modrm_reg(src.0, dst);
+    }
+
+    /// `mov dst, imm`, zero extended to 64bit
+    pub fn mov_imm(&mut self, dst: Reg, imm: u32) {
+        self.rex(false, Reg(0), dst);
+        self.emit(&[0xb8 + (dst.0 & 7)]);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// `mov dst, imm` on a 64bit register
+    pub fn mov_imm64(&mut self, dst: Reg, imm: u64) {
+        self.rex(true, Reg(0), dst);
+        self.emit(&[0xb8 + (dst.0 & 7)]);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// `call target`, with the address of the target in a register
+    pub fn call(&mut self, target: Reg) {
+        self.rex(false, Reg(0), target);
+        self.emit(&[0xff]);
+        self.modrm_reg(2, target);
+    }
+
+    /// `test r, r`
+    pub fn test(&mut self, r: Reg) {
+        self.rex(false, r, r);
+        self.emit(&[0x85]);
+        self.modrm_reg(r.0, r);
+    }
+
+    /// Conditional jump, to be bound to its target with `bind`
+    pub fn jump_if(&mut self, cond: Cond) -> Fixup {
+        self.emit(&[0x0f, 0x80 | cond as u8]);
+        self.emit(&[0; 4]);
+
+        Fixup(self.code.len())
+    }
+
+    /// Unconditional jump, to be bound to its target with `bind`
+    pub fn jump(&mut self) -> Fixup {
+        self.emit(&[0xe9]);
+        self.emit(&[0; 4]);
+
+        Fixup(self.code.len())
+    }
+
+    /// Make `fixup` jump to the current position
+    pub fn bind(&mut self, fixup: Fixup) {
+        let Fixup(end) = fixup;
+        let rel = i32::try_from(self.code.len() - end).expect("Jump out of range");
+
+        self.code[end - 4..end].copy_from_slice(&rel.to_le_bytes());
+    }
+
+    /// `mov dst, dword [rbx + offset]`
+    pub fn load(&mut self, dst: Reg, offset: usize) {
+        self.rex(false, dst, RBX);
+        self.emit(&[0x8b]);
+        self.modrm_bus(dst.0, offset);
+    }
+
+    /// `mov dword [rbx + offset], src`
+    pub fn store(&mut self, offset: usize, src: Reg) {
+        self.rex(false, src, RBX);
+        self.emit(&[0x89]);
+        self.modrm_bus(src.0, offset);
+    }
+
+    /// `movzx dst, byte [rbx + offset]`
+    pub fn load_u8(&mut self, dst: Reg, offset: usize) {
+        self.rex(false, dst, RBX);
+        self.emit(&[0x0f, 0xb6]);
+        self.modrm_bus(dst.0, offset);
+    }
+
+    /// `mov byte [rbx + offset], src8`. Only the registers with a legacy 8bit form (AL, CL, DL)
+    /// can be used.
+    pub fn store_u8(&mut self, offset: usize, src: Reg) {
+        assert!(src.0 < 3, "No legacy 8bit form for {:?}", src);
+
+        self.emit(&[0x88]);
+        self.modrm_bus(src.0, offset);
+    }
+
+    /// `mov dword [rbx + offset], imm`
+    pub fn store_imm(&mut self, offset: usize, imm: u32) {
+        self.emit(&[0xc7]);
+        self.modrm_bus(0, offset);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// `add dword [rbx + offset], imm`
+    pub fn add_mem_imm(&mut self, offset: usize, imm: u32) {
+        self.emit(&[0x81]);
+        self.modrm_bus(Alu::Add as u8, offset);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// `cmp byte [rbx + offset], imm`
+    pub fn cmp_u8_imm(&mut self, offset: usize, imm: u8) {
+        self.emit(&[0x80]);
+        self.modrm_bus(Alu::Cmp as u8, offset);
+        self.emit(&[imm]);
+    }
+
+    /// `cmp byte [rbx + index + offset], imm`
+    pub fn cmp_u8_indexed_imm(&mut self, index: Reg, offset: usize, imm: u8) {
+        self.emit(&[0x80]);
+        self.modrm_bus_indexed(Alu::Cmp as u8, index, offset);
+        self.emit(&[imm]);
+    }
+
+    /// `dec byte [rbx + index + offset]`
+    pub fn dec_u8_indexed(&mut self, index: Reg, offset: usize) {
+        self.emit(&[0xfe]);
+        self.modrm_bus_indexed(1, index, offset);
+    }
+
+    /// `mov byte [rbx + offset], imm`
+    pub fn store_u8_imm(&mut self, offset: usize, imm: u8) {
+        self.emit(&[0xc6]);
+        self.modrm_bus(0, offset);
+        self.emit(&[imm]);
+    }
+
+    /// `cmp dword [rbx + offset], imm`
+    pub fn cmp_mem_imm(&mut self, offset: usize, imm: u32) {
+        self.emit(&[0x81]);
+        self.modrm_bus(Alu::Cmp as u8, offset);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// `op dst, src`
+    pub fn alu(&mut self, op: Alu, dst: Reg, src: Reg) {
+        self.rex(false, dst, src);
+        self.emit(&[((op as u8) << 3) | 3]);
+        self.modrm_reg(dst.0, src);
+    }
+
+    /// `op dst, dword [rbx + offset]`
+    pub fn alu_mem(&mut self, op: Alu, dst: Reg, offset: usize) {
+        self.rex(false, dst, RBX);
+        self.emit(&[((op as u8) << 3) | 3]);
+        self.modrm_bus(dst.0, offset);
+    }
+
+    /// `op r, imm`
+    pub fn alu_imm(&mut self, op: Alu, r: Reg, imm: u32) {
+        self.rex(false, Reg(0), r);
+        self.emit(&[0x81]);
+        self.modrm_reg(op as u8, r);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// `op r, imm`
+    pub fn shift_imm(&mut self, op: Shift, r: Reg, imm: u8) {
+        self.rex(false, Reg(0), r);
+        self.emit(&[0xc1]);
+        self.modrm_reg(op as u8, r);
+        self.emit(&[imm]);
+    }
+
+    /// `op r, cl`
+    pub fn shift_cl(&mut self, op: Shift, r: Reg) {
+        self.rex(false, Reg(0), r);
+        self.emit(&[0xd3]);
+        self.modrm_reg(op as u8, r);
+    }
+
+    /// `not r`
+    pub fn not(&mut self, r: Reg) {
+        self.rex(false, Reg(0), r);
+        self.emit(&[0xf7]);
+        self.modrm_reg(2, r);
+    }
+
+    /// `setcc r8` followed by `movzx r, r8`: `r` is 1 if `cond` holds, 0 otherwise. Only the
+    /// registers with a legacy 8bit form (AL, CL, DL) can be used.
+    pub fn set_if(&mut self, cond: Cond, r: Reg) {
+        assert!(r.0 < 3, "No legacy 8bit form for {:?}", r);
+
+        self.emit(&[0x0f, 0x90 | cond as u8]);
+        self.modrm_reg(0, r);
+        self.emit(&[0x0f, 0xb6]);
+        self.modrm_reg(r.0, r);
+    }
+}
+
+#[test]
+fn encodings() {
+    let encode = |f: &dyn Fn(&mut Emitter)| {
+        let mut e = Emitter::new();
+        f(&mut e);
+        e.into_code()
+    };
+
+    assert_eq!(encode(&|e| e.push(RBX)), [0x53]);
+    assert_eq!(encode(&|e| e.pop(RBX)), [0x5b]);
+    assert_eq!(encode(&|e| e.mov64(RBX, RDI)), [0x48, 0x89, 0xfb]);
+    assert_eq!(encode(&|e| e.mov64(RCX, RBX)), [0x48, 0x89, 0xd9]);
+    assert_eq!(encode(&|e| e.mov_imm(RSI, 0x1234_5678)), [0xbe, 0x78, 0x56, 0x34, 0x12]);
+    assert_eq!(encode(&|e| e.mov_imm(R8, 1)), [0x41, 0xb8, 1, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.mov_imm64(RAX, 0x1122_3344_5566_7788)),
+               [0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]);
+    assert_eq!(encode(&|e| e.call(RAX)), [0xff, 0xd0]);
+    assert_eq!(encode(&|e| e.test(RAX)), [0x85, 0xc0]);
+    assert_eq!(encode(&|e| e.load(RAX, 0x10)), [0x8b, 0x83, 0x10, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.store(0x10, RCX)), [0x89, 0x8b, 0x10, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.store_u8_imm(0x20, 0)), [0xc6, 0x83, 0x20, 0, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.load_u8(RAX, 0x10)), [0x0f, 0xb6, 0x83, 0x10, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.store_u8(0x10, RAX)), [0x88, 0x83, 0x10, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.store_imm(0x10, 5)), [0xc7, 0x83, 0x10, 0, 0, 0, 5, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.add_mem_imm(0x10, 1)), [0x81, 0x83, 0x10, 0, 0, 0, 1, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.cmp_u8_imm(0x10, 0)), [0x80, 0xbb, 0x10, 0, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.cmp_u8_indexed_imm(RAX, 0x10, 0)), [0x80, 0xbc, 0x03, 0x10, 0, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.dec_u8_indexed(RAX, 0x10)), [0xfe, 0x8c, 0x03, 0x10, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.alu_mem(Alu::Cmp, RAX, 0x10)), [0x3b, 0x83, 0x10, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.alu_imm(Alu::And, RAX, 0x1f)), [0x81, 0xe0, 0x1f, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.cmp_mem_imm(4, 8)), [0x81, 0xbb, 4, 0, 0, 0, 8, 0, 0, 0]);
+    assert_eq!(encode(&|e| e.alu(Alu::Add, RAX, RCX)), [0x03, 0xc1]);
+    assert_eq!(encode(&|e| e.alu(Alu::Sub, RAX, RCX)), [0x2b, 0xc1]);
+    assert_eq!(encode(&|e| e.alu(Alu::Cmp, RAX, RCX)), [0x3b, 0xc1]);
+    assert_eq!(encode(&|e| e.shift_imm(Shift::Sar, RAX, 3)), [0xc1, 0xf8, 3]);
+    assert_eq!(encode(&|e| e.shift_cl(Shift::Shl, RAX)), [0xd3, 0xe0]);
+    assert_eq!(encode(&|e| e.not(RAX)), [0xf7, 0xd0]);
+    assert_eq!(encode(&|e| e.set_if(Cond::L, RAX)), [0x0f, 0x9c, 0xc0, 0x0f, 0xb6, 0xc0]);
+
+    // Forward jump over a `ret`
+    let code = encode(&|e| {
+        let fixup = e.jump_if(Cond::NotEqual);
+        e.ret();
+        e.bind(fixup);
+    });
+    assert_eq!(code, [0x0f, 0x85, 1, 0, 0, 0, 0xc3]);
+
+    let code = encode(&|e| {
+        let fixup = e.jump();
+        e.ret();
+        e.bind(fixup);
+    });
+    assert_eq!(code, [0xe9, 1, 0, 0, 0, 0xc3]);
+}
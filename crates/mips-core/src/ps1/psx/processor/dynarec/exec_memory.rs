@@ -0,0 +1,207 @@
+//! Executable memory for the code generated by the dynarec. A single region is mapped up front and
+//! filled from the start; it's only ever emptied all at once. Its pages are never writable and
+//! executable at the same time: they're made writable while code is copied to them and executable
+//! again right after, nothing runs in between.
+
+use std::ptr;
+use std::slice;
+
+/// Granularity of the protection changes, the page size of x86-64 hosts
+const PAGE_SIZE: usize = 4096;
+
+pub struct ExecMemory {
+    base: *mut u8,
+    size: usize,
+    /// Bytes in use from `base`
+    used: usize,
+}
+
+// The region is only reachable through the `ExecMemory` that owns it
+unsafe impl Send for ExecMemory {}
+
+impl ExecMemory {
+    /// Map `size` bytes, `None` if the host doesn't let us or isn't supported
+    pub fn new(size: usize) -> Option<ExecMemory> {
+        let base = sys::map(size);
+
+        if base.is_null() {
+            return None;
+        }
+
+        Some(ExecMemory { base, size, used: 0 })
+    }
+
+    /// Copy `code` after the code already there, `None` if there's not enough room left. Returns
+    /// the offset of the copy.
+    pub fn push(&mut self, code: &[u8]) -> Option<usize> {
+        // Keep the entry points aligned for the instruction fetcher of the host
+        let start = self.used.next_multiple_of(16);
+
+        if start + code.len() > self.size {
+            return None;
+        }
+
+        // The pages the code goes to, some of them may hold code already
+        let first = start / PAGE_SIZE * PAGE_SIZE;
+        let len = (start + code.len()).next_multiple_of(PAGE_SIZE).min(self.size) - first;
+
+        // SAFETY: the destination is within the mapping and not in use, and the pages around it
+        // aren't running while they're not executable
+        unsafe {
+            let pages = self.base.add(first);
+            assert!(sys::protect(pages, len, false), "Can't make the code memory writable");
+
+            let dst = self.base.add(start);
+            ptr::copy_nonoverlapping(code.as_ptr(), dst, code.len());
+            self.used = start + code.len();
+
+            assert!(sys::protect(pages, len, true), "Can't make the code memory executable");
+        }
+
+        Some(start)
+    }
+
+    /// Address of the code pushed at `offset`
+    pub fn entry(&self, offset: usize) -> *const u8 {
+        assert!(offset < self.used);
+
+        // SAFETY: within the mapping
+        unsafe { self.base.add(offset) }
+    }
+
+    /// The `len` bytes of code pushed at `offset`
+    pub fn code(&self, offset: usize, len: usize) -> &[u8] {
+        assert!(offset + len <= self.used);
+
+        // SAFETY: within the part of the mapping in use, which is only written by `push`
+        unsafe { slice::from_raw_parts(self.base.add(offset), len) }
+    }
+
+    /// Drop all the code. Nothing may run it anymore.
+    pub fn clear(&mut self) {
+        self.used = 0;
+    }
+}
+
+impl Drop for ExecMemory {
+    fn drop(&mut self) {
+        sys::unmap(self.base, self.size);
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", unix))]
+mod sys {
+    use std::ffi::{c_int, c_void};
+    use std::ptr;
+
+    const PROT_READ: c_int = 1;
+    const PROT_WRITE: c_int = 2;
+    const PROT_EXEC: c_int = 4;
+    const MAP_PRIVATE: c_int = 0x02;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const MAP_ANONYMOUS: c_int = 0x20;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const MAP_ANONYMOUS: c_int = 0x1000;
+
+    unsafe extern "C" {
+        fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+        fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+    }
+
+    pub fn map(size: usize) -> *mut u8 {
+        // SAFETY: anonymous mapping, nothing else is touched
+        let base = unsafe {
+            let prot = PROT_READ | PROT_WRITE;
+            mmap(ptr::null_mut(), size, prot, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0)
+        };
+
+        // MAP_FAILED
+        if base as usize == usize::MAX {
+            return ptr::null_mut();
+        }
+
+        base.cast()
+    }
+
+    /// Make the `len` bytes at `pages` executable, or writable. Returns false if it failed.
+    ///
+    /// # Safety
+    ///
+    /// `pages` must be page aligned and within a mapping returned by `map`
+    pub unsafe fn protect(pages: *mut u8, len: usize, executable: bool) -> bool {
+        let prot = if executable { PROT_READ | PROT_EXEC } else { PROT_READ | PROT_WRITE };
+
+        // SAFETY: the caller passes pages of our mapping
+        unsafe { mprotect(pages.cast(), len, prot) == 0 }
+    }
+
+    pub fn unmap(base: *mut u8, size: usize) {
+        // SAFETY: `base` was returned by `map` for `size` bytes
+        unsafe {
+            munmap(base.cast(), size);
+        }
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", windows))]
+mod sys {
+    use std::ffi::c_void;
+
+    const MEM_COMMIT: u32 = 0x1000;
+    const MEM_RESERVE: u32 = 0x2000;
+    const MEM_RELEASE: u32 = 0x8000;
+    const PAGE_READWRITE: u32 = 0x04;
+    const PAGE_EXECUTE_READ: u32 = 0x20;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn VirtualAlloc(addr: *mut c_void, size: usize, allocation_type: u32, protect: u32) -> *mut c_void;
+        fn VirtualFree(addr: *mut c_void, size: usize, free_type: u32) -> i32;
+        fn VirtualProtect(addr: *mut c_void, size: usize, protect: u32, old_protect: *mut u32) -> i32;
+    }
+
+    pub fn map(size: usize) -> *mut u8 {
+        // SAFETY: fresh allocation, nothing else is touched
+        unsafe {
+            VirtualAlloc(std::ptr::null_mut(), size, MEM_COMMIT | MEM_RESERVE, PAGE_READWRITE).cast()
+        }
+    }
+
+    /// Make the `len` bytes at `pages` executable, or writable. Returns false if it failed.
+    ///
+    /// # Safety
+    ///
+    /// `pages` must be page aligned and within an allocation returned by `map`
+    pub unsafe fn protect(pages: *mut u8, len: usize, executable: bool) -> bool {
+        let protect = if executable { PAGE_EXECUTE_READ } else { PAGE_READWRITE };
+        let mut old = 0;
+
+        // SAFETY: the caller passes pages of our allocation
+        unsafe { VirtualProtect(pages.cast(), len, protect, &mut old) != 0 }
+    }
+
+    pub fn unmap(base: *mut u8, _size: usize) {
+        // SAFETY: `base` was returned by `map`
+        unsafe {
+            VirtualFree(base.cast(), 0, MEM_RELEASE);
+        }
+    }
+}
+
+/// Nothing the dynarec could run on
+#[cfg(not(all(target_arch = "x86_64", any(unix, windows))))]
+mod sys {
+    pub fn map(_size: usize) -> *mut u8 {
+        std::ptr::null_mut()
+    }
+
+    /// # Safety
+    ///
+    /// Never called, there's no mapping
+    pub unsafe fn protect(_pages: *mut u8, _len: usize, _executable: bool) -> bool {
+        false
+    }
+
+    pub fn unmap(_base: *mut u8, _size: usize) {}
+}
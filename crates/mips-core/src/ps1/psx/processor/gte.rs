@@ -1,7 +1,9 @@
 use log::warn;
 use crate::ps1::psx::processor::ClockCycle;
+use pgxp::Pgxp;
 
 mod divider;
+pub mod pgxp;
 #[cfg(test)]
 mod tests;
 
@@ -59,6 +61,14 @@ pub struct Gte {
     /// 3D-intensive games
     #[serde(default)]
     overclock: bool,
+    /// Precise positions of the projected vertices. Only depends on the settings, it's not part
+    /// of the state.
+    #[serde(skip)]
+    pgxp: Pgxp,
+    /// Widescreen hack: the X coordinates are projected 3/4 as wide, so that the picture stretched
+    /// to 16:9 shows the geometry in proportion and more of it on the sides. Settings only too.
+    #[serde(skip)]
+    widescreen: bool,
 }
 
 impl Gte {
@@ -89,6 +99,8 @@ impl Gte {
             lzcr: 32,
             reg_23: 0,
             overclock: false,
+            pgxp: Pgxp::default(),
+            widescreen: false,
         }
     }
 
@@ -96,6 +108,22 @@ impl Gte {
         self.overclock = overclock;
     }
 
+    pub fn pgxp(&self) -> &Pgxp {
+        &self.pgxp
+    }
+
+    pub fn pgxp_mut(&mut self) -> &mut Pgxp {
+        &mut self.pgxp
+    }
+
+    pub fn is_widescreen(&self) -> bool {
+        self.widescreen
+    }
+
+    pub fn set_widescreen(&mut self, widescreen: bool) {
+        self.widescreen = widescreen;
+    }
+
     /// Execute GTE command and returns the number of CPU cycles to completion
     pub fn command(&mut self, command: u32) -> ClockCycle {
         let opcode = command & 0x3f;
@@ -1178,6 +1206,8 @@ impl Gte {
     fn do_rtp(&mut self, config: CommandConfig, vector_index: usize) -> u32 {
         // The computed Z coordinate with unconditional 12bit shift applied
         let mut z_shifted: i32 = 0;
+        // Camera coordinates before they're shifted and saturated, for PGXP
+        let mut camera = [0i64; 3];
 
         // Step 1: we compute "tr + vector * rm" and store the 32 bit result in MAC 0, 1 and 2.
         let rm = Matrix::Rotation.index();
@@ -1200,6 +1230,8 @@ impl Gte {
                 res = self.i64_to_i44(c as u8, res + rot as i64);
             }
 
+            camera[r] = res;
+
             // Store the result in the accumulator
             self.mac[r + 1] = (res >> config.shift) as i32;
 
@@ -1286,7 +1318,11 @@ impl Gte {
 
         // Work in 64bits to detect overflows
         let factor = projection_factor as i64;
-        let x = self.ir[1] as i64;
+        let x = if self.widescreen {
+            self.ir[1] as i64 * 3 / 4
+        } else {
+            self.ir[1] as i64
+        };
         let y = self.ir[2] as i64;
         let ofx = self.ofx as i64;
         let ofy = self.ofy as i64;
@@ -1311,6 +1347,18 @@ impl Gte {
         self.xy_fifo[1] = self.xy_fifo[2];
         self.xy_fifo[2] = self.xy_fifo[3];
 
+        // Same projection without the rounding of IR and of the division
+        if self.pgxp.is_enabled() && z_saturated > self.h / 2 && camera[2] > 0 {
+            let (sx, sy) = self.xy_fifo[3];
+            let word = u32::from(sx as u16) | u32::from(sy as u16) << 16;
+            let factor = f64::from(self.h) / camera[2] as f64;
+            let aspect = if self.widescreen { 0.75 } else { 1. };
+            let x = camera[0] as f64 * aspect * factor + f64::from(self.ofx) / 65536.;
+            let y = camera[1] as f64 * factor + f64::from(self.ofy) / 65536.;
+
+            self.pgxp.record(word, x, y, z_saturated);
+        }
+
         // return projection factor
         projection_factor
     }
@@ -0,0 +1,94 @@
+//! Vertex precision tracking (PGXP): the GTE keeps the screen positions it projects with their
+//! fractional part and depth, and the GPU looks the vertices of the polygons it draws up among
+//! them to draw them at those positions. Without it the vertices snap to whole pixels, which makes
+//! the polygons jitter as the camera moves, and the textures are interpolated without perspective.
+//!
+//! The vertices are tracked by value rather than by address: whatever path the SXY words take
+//! between the GTE and the GPU (stored with SWC2, moved with MFC2, copied around in RAM), a word
+//! the GPU receives unchanged is the one the GTE made. When two vertices land on the same pixel
+//! the last one projected wins.
+
+use std::collections::HashMap;
+use std::mem;
+
+pub use mips_psx_gpu::pgxp::PreciseVertex;
+
+/// A projection further than that from its word in pixels didn't make the word (it was saturated
+/// or the GTE's approximations are off), it's not tracked
+const MAX_OFFSET: f64 = 2.0;
+
+#[derive(Default, Debug)]
+pub struct Pgxp {
+    enabled: bool,
+    /// Vertices projected this frame, by SXY word
+    current: HashMap<u32, PreciseVertex>,
+    /// Vertices projected during the previous frame, for the games drawing them a frame late
+    previous: HashMap<u32, PreciseVertex>,
+}
+
+impl Pgxp {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.current.clear();
+            self.previous.clear();
+        }
+    }
+
+    /// Remember that the GTE pushed `word` for a vertex projected at (`x`, `y`), in pixels with the
+    /// screen offset applied, at depth `z`
+    pub fn record(&mut self, word: u32, x: f64, y: f64, z: u16) {
+        let x_offset = x - f64::from(word as i16);
+        let y_offset = y - f64::from((word >> 16) as i16);
+
+        // Also false for NaNs
+        if !(x_offset.abs() < MAX_OFFSET && y_offset.abs() < MAX_OFFSET) {
+            return;
+        }
+
+        let vertex = PreciseVertex {
+            x_offset: (x_offset * 256.).round() as i16,
+            y_offset: (y_offset * 256.).round() as i16,
+            z,
+        };
+
+        self.current.insert(word, vertex);
+    }
+
+    /// Vertex that was projected to `word` lately, if any
+    pub fn lookup(&self, word: u32) -> Option<PreciseVertex> {
+        self.current.get(&word).or_else(|| self.previous.get(&word)).copied()
+    }
+
+    /// Forget the vertices of the frame before the one that just ended
+    pub fn end_of_frame(&mut self) {
+        mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
+    }
+}
+
+#[test]
+fn track_vertices() {
+    let mut pgxp = Pgxp::default();
+    pgxp.set_enabled(true);
+
+    // (-3.5, 10.25) was pushed as (-4, 10)
+    let word = 0x000a_fffc;
+    pgxp.record(word, -3.5, 10.25, 1000);
+    assert_eq!(pgxp.lookup(word), Some(PreciseVertex { x_offset: 128, y_offset: 64, z: 1000 }));
+
+    // Saturated coordinates aren't tracked
+    pgxp.record(0x0000_03ff, 1500.0, 0.0, 1000);
+    assert_eq!(pgxp.lookup(0x0000_03ff), None);
+
+    // Vertices last two frames
+    pgxp.end_of_frame();
+    assert!(pgxp.lookup(word).is_some());
+    pgxp.end_of_frame();
+    assert_eq!(pgxp.lookup(word), None);
+}
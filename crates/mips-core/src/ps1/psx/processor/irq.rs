@@ -22,6 +22,8 @@ pub enum Interrupt {
     PadMemCard = 7,
     /// SPU interrupt
     Spu = 9,
+    /// Lightpen interrupt, pulled by the Justifier lightgun through the controller port
+    Lightpen = 10,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Copy)]
@@ -44,6 +46,18 @@ impl InterruptState {
     }
 }
 
+/// The interrupt lines as the peripherals drive them. Implemented by the `Bus`, and by the mock bus
+/// the peripherals are unit tested with.
+pub trait IrqLines {
+    fn set_irq_level(&mut self, which: Interrupt, high: bool);
+}
+
+impl IrqLines for Bus {
+    fn set_irq_level(&mut self, which: Interrupt, high: bool) {
+        set_level(self, which, high)
+    }
+}
+
 pub fn status(bus: &Bus) -> u16 {
     bus.irq.status
 }
@@ -93,7 +107,7 @@ pub fn trigger(bus: &mut Bus, which: Interrupt) {
 
 pub fn set_mask(bus: &mut Bus, mask: u16) {
     // Temporary hack: trigger an error if a non-implemented interrupt is requested
-    let supported: [Interrupt; 8] = [
+    let supported: [Interrupt; 9] = [
         Interrupt::VBlank,
         Interrupt::CdRom,
         Interrupt::Dma,
@@ -102,6 +116,7 @@ pub fn set_mask(bus: &mut Bus, mask: u16) {
         Interrupt::Timer2,
         Interrupt::PadMemCard,
         Interrupt::Spu,
+        Interrupt::Lightpen,
     ];
 
     let rem = supported
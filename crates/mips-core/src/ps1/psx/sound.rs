@@ -1,4 +1,6 @@
+#[cfg(feature = "std")]
 use std::sync::mpsc;
+#[cfg(feature = "std")]
 use std::thread;
 
 pub mod spu;
@@ -6,6 +8,7 @@ mod reverb_resampler;
 mod fir;
 mod fifo;
 
+#[cfg(feature = "std")]
 pub struct Handle {
     handle: Option<thread::JoinHandle<()>>,
     audio_sender: mpsc::Sender<Vec<i16>>,
@@ -14,6 +14,27 @@ pub fn filter(phase: u8, samples: [i16; 4]) -> i32 {
     r >> 15
 }
 
+/// Catmull-Rom spline through the given samples, between `samples[1]` (phase 0) and `samples[2]`,
+/// saturated since it overshoots around the steep edges
+pub fn cubic(phase: u8, samples: [i16; 4]) -> i32 {
+    let [s0, s1, s2, s3] = samples.map(i32::from);
+    let t = i32::from(phase);
+
+    // Coefficients of the polynomial, doubled
+    let a = -s0 + 3 * s1 - 3 * s2 + s3;
+    let b = 2 * s0 - 5 * s1 + 4 * s2 - s3;
+    let c = s2 - s0;
+
+    let r = s1 + (((((a * t) >> 8) + b) * t >> 8) + c) * t / 512;
+
+    r.clamp(i32::from(i16::MIN), i32::from(i16::MAX))
+}
+
+/// Sample the given phase is closest to, between `samples[1]` and `samples[2]`
+pub fn nearest(phase: u8, samples: [i16; 4]) -> i32 {
+    i32::from(samples[1 + usize::from(phase >> 7)])
+}
+
 /// SPU FIR filter: 4 taps, 256 phases
 ///
 /// FIR coefficients taken from Mednafen. No$ seems to have a very similar table although I haven't
@@ -277,3 +298,19 @@ static FIR_COEFFS: [[i16; 4]; 256] = [
     [-1, 0x1347, 0x59b2, 0x1288],
     [-1, 0x1307, 0x59b3, 0x12c7],
 ];
+
+#[test]
+fn interpolate() {
+    let samples = [0, 1000, 2000, 3000];
+
+    // A line stays a line
+    assert_eq!(cubic(0, samples), 1000);
+    assert_eq!(cubic(128, samples), 1500);
+    assert_eq!(nearest(100, samples), 1000);
+    assert_eq!(nearest(200, samples), 2000);
+    // Within the rounding of the coefficients
+    assert!((filter(128, samples) - 1500).abs() < 10);
+
+    // Saturated overshoot
+    assert_eq!(cubic(128, [-32768, 32767, 32767, -32768]), 32767);
+}
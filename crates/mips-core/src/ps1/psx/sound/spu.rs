@@ -1,21 +1,28 @@
 //! Sound Processing Unit
 
+use std::mem;
 use std::ops::{Index, IndexMut};
 use log::warn;
-use crate::ps1::psx::addressable::{AccessWidth, Addressable};
+use crate::audio::{self, AudioChunk, AudioMix, AudioSource};
+use mips_r3000::addressable::{AccessWidth, Addressable};
 use crate::ps1::psx::bus::Bus;
-use crate::ps1::psx::processor::{cpu, irq, ClockCycle};
+use crate::ps1::psx::processor::{cpu, ClockCycle};
+use crate::ps1::psx::processor::irq::{Interrupt, IrqLines};
 use crate::ps1::psx::sound::fifo::DecoderFifo;
 use crate::ps1::psx::sound::fir;
 use crate::ps1::psx::sound::reverb_resampler::ReverbResampler;
 use crate::ps1::psx::{cd, sync};
+use crate::ps1::psx::sync::Scheduler;
+use crate::ps1::settings::audio::Interpolation;
 use crate::ps1::util::ds::box_slice::BoxSlice;
+use crate::stats::FrameStats;
 
 const SPUSYNC: sync::SyncToken = sync::SyncToken::Spu;
 
 /// Offset into the SPU internal ram
 type RamIndex = u32;
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Spu {
     /// RAM index, used for read/writes using CPU or DMA.
     ram_index: RamIndex,
@@ -48,20 +55,20 @@ pub struct Spu {
     voice_looped: u32,
     /// Most of the SPU's register behave like a R/W RAM, so to simplify the emulation we just
     /// store most registers in a big buffer
+    #[serde(with = "serde_big_array::BigArray")]
     regs: [u16; 320],
     /// SPU internal RAM, 16bit wide
     ram: BoxSlice<u16, SPU_RAM_SIZE>,
     /// Output audio buffer. Sent to the frontend after each frame, so should be large enough to
     /// store one frame worth of audio samples. Assuming a 50Hz refresh rate @ 44.1kHz that should
     /// be about ~1800 samples per frame at most.
-    audio_buffer: [i16; 2048],
-    /// Write pointer into the audio_buffer
-    audio_buffer_index: u32,
+    #[serde(skip)]
+    audio_buffer: Vec<i16>,
+    /// Position of the first stereo pair of `audio_buffer` in the output stream
+    audio_timestamp: u64,
     /// Mix volume for the samples coming from the CD, left
-    
     cd_volume_left: i16,
     /// Mix volume for the samples coming from the CD, right
-    
     cd_volume_right: i16,
     /// First of the two LFSR counters
     noise_counter1: u16,
@@ -89,6 +96,12 @@ pub struct Spu {
     reverb_upsampler_right: ReverbResampler,
     /// Used to override the emulation and force reverb off
     reverb_enable_override: bool,
+    /// How the voices are resampled, a setting of the frontend's rather than part of the state
+    #[serde(skip)]
+    interpolation: Interpolation,
+    /// Frontend volume of the sources, applied to the output only
+    #[serde(skip)]
+    mix: AudioMix,
 }
 
 impl Spu {
@@ -134,8 +147,8 @@ impl Spu {
             voice_looped: 0,
             regs: [0; 320],
             ram: BoxSlice::from_vec(vec![0; SPU_RAM_SIZE]),
-            audio_buffer: [0; 2048],
-            audio_buffer_index: 0,
+            audio_buffer: Vec::with_capacity(AUDIO_BUFFER_LEN),
+            audio_timestamp: 0,
             cd_volume_left: 0,
             cd_volume_right: 0,
             noise_counter1: 0,
@@ -151,6 +164,8 @@ impl Spu {
             reverb_upsampler_left: ReverbResampler::new(),
             reverb_upsampler_right: ReverbResampler::new(),
             reverb_enable_override: true,
+            interpolation: Interpolation::default(),
+            mix: AudioMix::default(),
         }
     }
 
@@ -158,6 +173,22 @@ impl Spu {
         self.reverb_enable_override = en
     }
 
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    pub fn mix(&self) -> AudioMix {
+        self.mix
+    }
+
+    pub fn set_mix(&mut self, mix: AudioMix) {
+        self.mix = mix;
+    }
+
     /// Returns the value of the control register
     fn control(&self) -> u16 {
         self.regs[regmap::CONTROL]
@@ -266,6 +297,42 @@ impl Spu {
             }
         }
     }
+
+    /// Set `irq` if it's enabled in the control register and `index` is equal to the `irq_addr`.
+    /// Returns true if the interrupt triggered, the caller is responsible for signaling it.
+    fn check_for_irq(&mut self, index: RamIndex) -> bool {
+        let triggered = self.irq_enabled() && index == self.irq_addr;
+
+        self.irq |= triggered;
+
+        triggered
+    }
+
+    fn ram_write(&mut self, index: RamIndex, val: u16) {
+        self.check_for_irq(index);
+        self.ram_write_no_irq(index, val);
+    }
+
+    fn ram_read(&mut self, index: RamIndex) -> u16 {
+        self.check_for_irq(index);
+        self.ram_read_no_irq(index)
+    }
+
+    fn ram_write_no_irq(&mut self, index: RamIndex, val: u16) {
+        let index = index as usize;
+
+        debug_assert!(index < self.ram.len());
+
+        self.ram[index] = val;
+    }
+
+    fn ram_read_no_irq(&self, index: RamIndex) -> u16 {
+        let index = index as usize;
+
+        debug_assert!(index < self.ram.len());
+
+        self.ram[index]
+    }
 }
 
 impl Index<u8> for Spu {
@@ -282,84 +349,174 @@ impl IndexMut<u8> for Spu {
     }
 }
 
+/// What the SPU needs from the bus. Implemented by the `Bus`, and by the mock bus of the unit
+/// tests.
+pub trait SpuBus: IrqLines + Scheduler {
+    fn spu(&mut self) -> &mut Spu;
+
+    /// Bring the CD controller up to date, it queues its audio output for us
+    fn run_cd(&mut self);
+
+    /// Next sample of the audio queued by the CD controller
+    fn cd_audio_sample(&mut self) -> [i16; 2];
+
+    /// True if the CD audio is XA-ADPCM instead of CD-DA (it goes through another volume of the
+    /// frontend's mixer)
+    fn cd_streaming_xa(&self) -> bool;
+
+    fn stats(&mut self) -> &mut FrameStats;
+}
+
+impl SpuBus for Bus {
+    fn spu(&mut self) -> &mut Spu {
+        &mut self.spu
+    }
+
+    fn run_cd(&mut self) {
+        cd::run(self)
+    }
+
+    fn cd_audio_sample(&mut self) -> [i16; 2] {
+        self.cd.next_audio_sample()
+    }
+
+    fn cd_streaming_xa(&self) -> bool {
+        self.cd.cdc.is_streaming_xa()
+    }
+
+    fn stats(&mut self) -> &mut FrameStats {
+        &mut self.stats
+    }
+}
+
 /// Run the SPU until it's caught up with the CPU
-pub fn run(bus: &mut Bus) {
-    let mut elapsed = sync::resync(bus, SPUSYNC);
+pub fn run<B: SpuBus>(bus: &mut B) {
+    // The CD audio we're about to mix must have been generated
+    bus.run_cd();
+
+    let mut elapsed = bus.resync(SPUSYNC);
 
     while elapsed >= SPU_FREQ_DIVIDER {
         elapsed -= SPU_FREQ_DIVIDER;
-        run_cycle(bus);
+        run_cycle(bus, true);
     }
 
     // If we have some leftover cycles we can just return them to the synchronization module, we'll
     // get them back on the next call to resync
-    sync::rewind(bus, SPUSYNC, elapsed);
+    bus.rewind(SPUSYNC, elapsed);
 
-    // For now force a sync at the next cycle
-    sync::next_event(bus, SPUSYNC, SPU_FREQ_DIVIDER - elapsed);
+    // Everything the CPU can observe syncs us before the access, so the only reason to come back
+    // early is the SPU interrupt, which must be raised on the exact sample that triggers it. The CD
+    // controller has its own events for its interrupt.
+    let block = if bus.spu().irq_enabled() {
+        1
+    } else {
+        SPU_BLOCK_SAMPLES
+    };
+
+    bus.next_event(SPUSYNC, block * SPU_FREQ_DIVIDER - elapsed);
 }
 
-/// Get the contents of the sample buffer
-pub fn get_samples(bus: &mut Bus) -> &[i16] {
-    let end = bus.spu.audio_buffer_index as usize;
+/// Generate `cycles` CPU cycles worth of samples while the rest of the console is frozen (game
+/// paused in a menu of the frontend): the voices and the reverb keep going so that the sound
+/// doesn't stop dead, but the CD controller doesn't move, a streaming game would find itself
+/// behind the drive otherwise. An SPU interrupt stays pending until the CPU runs again.
+///
+/// The synchronization timestamps aren't touched, the console doesn't see the time pass.
+pub fn run_frozen<B: SpuBus>(bus: &mut B, cycles: ClockCycle) {
+    // Flush what the CPU already caught up with first
+    run(bus);
 
-    &bus.spu.audio_buffer[..end]
+    for _ in 0..cycles / SPU_FREQ_DIVIDER {
+        run_cycle(bus, false);
+    }
 }
 
-/// Clear the sample buffer
-pub fn clear_samples(bus: &mut Bus) {
-    bus.spu.audio_buffer_index = 0;
+/// Take the contents of the sample buffer, leaving it empty
+pub fn take_samples<B: SpuBus>(bus: &mut B) -> AudioChunk {
+    // Samples are generated in blocks, get the ones the CPU has already caught up with
+    run(bus);
+
+    let spu = bus.spu();
+
+    let samples = mem::replace(&mut spu.audio_buffer, Vec::with_capacity(AUDIO_BUFFER_LEN));
+    let chunk = AudioChunk {
+        timestamp: spu.audio_timestamp,
+        samples,
+    };
+
+    spu.audio_timestamp += chunk.frames() as u64;
+
+    chunk
 }
 
 /// Put the provided stereo pair in the output buffer and flush it if necessary
-fn output_samples(bus: &mut Bus, left: i16, right: i16) {
-    let idx = bus.spu.audio_buffer_index as usize;
+fn output_samples<B: SpuBus>(bus: &mut B, left: i16, right: i16) {
+    let spu = bus.spu();
 
     // If this overflows the frontend isn't reading the samples fast enough
-    if bus.spu.audio_buffer.len() > idx + 1 {
-        bus.spu.audio_buffer[idx] = left;
-        bus.spu.audio_buffer[idx + 1] = right;
-        bus.spu.audio_buffer_index += 2;
-    } else {
+    if spu.audio_buffer.len() + 2 > AUDIO_BUFFER_LEN {
         warn!("Frontend isn't reading our audio samples fast enough");
         // Flush the entire buffer to give us some leeway, better to have one big glitch than many
-        // small ones
-        bus.spu.audio_buffer_index = 0;
+        // small ones. The timestamp of the next chunk tells the frontend about the gap.
+        spu.audio_timestamp += (spu.audio_buffer.len() / 2) as u64;
+        spu.audio_buffer.clear();
     }
+
+    spu.audio_buffer.push(left);
+    spu.audio_buffer.push(right);
+
+    bus.stats().audio_samples += 1;
 }
 
-/// Emulate one cycle of the SPU
-fn run_cycle(bus: &mut Bus) {
-    bus.spu.update_status();
+/// Emulate one cycle of the SPU. The audio of the CD controller is mixed unless `cd_running` is
+/// false, the CD audio is silent then.
+fn run_cycle<B: SpuBus>(bus: &mut B, cd_running: bool) {
+    let spu = bus.spu();
 
-    // Sum of the left and right voice volume levels
-    let mut left_mix = 0;
-    let mut right_mix = 0;
-    let mut sweep_factor = 0;
+    spu.update_status();
 
-    // Sum of the voices used for reverb
-    let mut left_reverb = 0;
-    let mut right_reverb = 0;
+    let irq = spu.irq;
+
+    let mut samples = [0i32; 24];
+    let mut left = [0i32; 24];
+    let mut right = [0i32; 24];
+    // All ones for the voices that feed the reverb, zero otherwise
+    let mut reverb_mask = [0i32; 24];
+    let mut sweep_factor = 0;
 
     for voice in 0..24 {
-        let (left, right) = run_voice_cycle(bus, voice, &mut sweep_factor);
+        let v = voice as usize;
 
-        left_mix += left;
-        right_mix += right;
+        // The stereo levels are sampled before `run_voice_cycle` runs the volume sweep
+        left[v] = i32::from(spu[voice].volume_left.level());
+        right[v] = i32::from(spu[voice].volume_right.level());
+        reverb_mask[v] = -(spu.is_voice_reverberated(voice) as i32);
 
-        if bus.spu.is_voice_reverberated(voice) {
-            left_reverb += left;
-            right_reverb += right;
-        }
+        samples[v] = run_voice_cycle(spu, voice, &mut sweep_factor);
     }
 
-    bus.spu.run_noise_cycle();
+    // The mixing is kept in plain loops over the arrays so that it gets vectorized
+    for v in 0..24 {
+        left[v] = (samples[v] * left[v]) >> 15;
+        right[v] = (samples[v] * right[v]) >> 15;
+    }
+
+    // Sum of the left and right voice volume levels
+    let mut left_mix: i32 = left.iter().sum();
+    let mut right_mix: i32 = right.iter().sum();
+
+    // Sum of the voices used for reverb
+    let mut left_reverb: i32 = left.iter().zip(&reverb_mask).map(|(&s, &m)| s & m).sum();
+    let mut right_reverb: i32 = right.iter().zip(&reverb_mask).map(|(&s, &m)| s & m).sum();
+
+    spu.run_noise_cycle();
 
     // Voice start/stop should've been processed by `run_voice_cycle`
-    bus.spu.voice_start = 0;
-    bus.spu.voice_stop = 0;
+    spu.voice_start = 0;
+    spu.voice_stop = 0;
 
-    if bus.spu.muted() {
+    if spu.muted() {
         // Mute bit doesn't actually mute CD audio, just the SPU voices.
         left_mix = 0;
         right_mix = 0;
@@ -369,20 +526,37 @@ fn run_cycle(bus: &mut Bus) {
         right_reverb = 0;
     }
     
-    let [cd_left, cd_right] = cd::run_audio_cycle(bus);
+    let [cd_left, cd_right] = if cd_running {
+        bus.cd_audio_sample()
+    } else {
+        [0, 0]
+    };
+    let cd_source = if bus.cd_streaming_xa() {
+        AudioSource::Xa
+    } else {
+        AudioSource::CdDa
+    };
+
+    let spu = bus.spu();
 
     // Write CD audio (pre-volume) to the RAM
-    ram_write(bus, bus.spu.capture_index, cd_left as u16);
-    ram_write(bus, bus.spu.capture_index | 0x200, cd_right as u16);
+    spu.ram_write(spu.capture_index, cd_left as u16);
+    spu.ram_write(spu.capture_index | 0x200, cd_right as u16);
+
+    // The frontend's volumes apply to the output, the reverb is fed as if they were all 1
+    let spu_gain = spu.mix.gain(AudioSource::Spu);
+    left_mix = apply_gain(left_mix, spu_gain);
+    right_mix = apply_gain(right_mix, spu_gain);
 
-    if bus.spu.cd_audio_enabled() {
-        let cd_left = (i32::from(cd_left) * i32::from(bus.spu.cd_volume_left)) >> 15;
-        let cd_right = (i32::from(cd_right) * i32::from(bus.spu.cd_volume_right)) >> 15;
+    if spu.cd_audio_enabled() {
+        let cd_left = (i32::from(cd_left) * i32::from(spu.cd_volume_left)) >> 15;
+        let cd_right = (i32::from(cd_right) * i32::from(spu.cd_volume_right)) >> 15;
 
-        left_mix += cd_left;
-        right_mix += cd_right;
+        let cd_gain = spu.mix.gain(cd_source);
+        left_mix += apply_gain(cd_left, cd_gain);
+        right_mix += apply_gain(cd_right, cd_gain);
 
-        if bus.spu.cd_audio_reverb() {
+        if spu.cd_audio_reverb() {
             left_reverb += cd_left;
             right_reverb += cd_right;
         }
@@ -392,66 +566,72 @@ fn run_cycle(bus: &mut Bus) {
     {
         let reverb_samples = (saturate_to_i16(left_reverb), saturate_to_i16(right_reverb));
 
-        let (reverb_left, reverb_right) = run_reverb_cycle(bus, reverb_samples);
+        let (reverb_left, reverb_right) = run_reverb_cycle(spu, reverb_samples);
 
         let reverb_left =
-            (i32::from(reverb_left) * i32::from(bus.spu.reverb_out_volume_left)) >> 15;
+            (i32::from(reverb_left) * i32::from(spu.reverb_out_volume_left)) >> 15;
         let reverb_right =
-            (i32::from(reverb_right) * i32::from(bus.spu.reverb_out_volume_right)) >> 15;
+            (i32::from(reverb_right) * i32::from(spu.reverb_out_volume_right)) >> 15;
 
-        left_mix += reverb_left;
-        right_mix += reverb_right;
+        left_mix += apply_gain(reverb_left, spu_gain);
+        right_mix += apply_gain(reverb_right, spu_gain);
     }
 
     left_mix = saturate_to_i16(left_mix) as i32;
     right_mix = saturate_to_i16(right_mix) as i32;
 
-    left_mix = bus.spu.main_volume_left.apply_level(left_mix);
-    right_mix = bus.spu.main_volume_right.apply_level(right_mix);
+    left_mix = spu.main_volume_left.apply_level(left_mix);
+    right_mix = spu.main_volume_right.apply_level(right_mix);
 
-    bus.spu.main_volume_left.run_sweep_cycle();
-    bus.spu.main_volume_right.run_sweep_cycle();
+    spu.main_volume_left.run_sweep_cycle();
+    spu.main_volume_right.run_sweep_cycle();
 
-    bus.spu.capture_index += 1;
-    bus.spu.capture_index &= 0x1ff;
+    spu.capture_index += 1;
+    spu.capture_index &= 0x1ff;
+
+    // The voices, the capture buffers and the reverb only flag the interrupt in the SPU, it's
+    // signaled once for the whole cycle
+    if spu.irq && !irq {
+        bus.set_irq_level(Interrupt::Spu, true);
+    }
 
     output_samples(bus, saturate_to_i16(left_mix), saturate_to_i16(right_mix));
 }
 
-fn reverb_sample_index(bus: &mut Bus, addr: u16, neg_offset: u32) -> RamIndex {
-    let idx = bus.spu.reverb_index + to_ram_index(addr) - neg_offset;
+fn reverb_sample_index(spu: &Spu, addr: u16, neg_offset: u32) -> RamIndex {
+    let idx = spu.reverb_index + to_ram_index(addr) - neg_offset;
 
     if idx <= 0x3_ffff {
         idx
     } else {
         // Overflow, wrap around to the start of the reverb working area
-        bus.spu.reverb_start.wrapping_add(idx) & 0x3_ffff
+        spu.reverb_start.wrapping_add(idx) & 0x3_ffff
     }
 }
 
-fn store_reverb_sample(bus: &mut Bus, addr: u16, v: i16) {
-    let idx = reverb_sample_index(bus, addr, 0);
+fn store_reverb_sample(spu: &mut Spu, addr: u16, v: i16) {
+    let idx = reverb_sample_index(spu, addr, 0);
 
-    ram_write(bus, idx, v as u16)
+    spu.ram_write(idx, v as u16)
 }
 
-fn load_reverb_sample(bus: &mut Bus, addr: u16) -> i16 {
-    let idx = reverb_sample_index(bus, addr, 0);
+fn load_reverb_sample(spu: &mut Spu, addr: u16) -> i16 {
+    let idx = reverb_sample_index(spu, addr, 0);
 
-    ram_read(bus, idx) as i16
+    spu.ram_read(idx) as i16
 }
 
-fn load_reverb_sample_before(bus: &mut Bus, addr: u16) -> i16 {
-    let idx = reverb_sample_index(bus, addr, 1);
+fn load_reverb_sample_before(spu: &mut Spu, addr: u16) -> i16 {
+    let idx = reverb_sample_index(spu, addr, 1);
 
-    ram_read(bus, idx) as i16
+    spu.ram_read(idx) as i16
 }
 
 /// Advance the reverb state machine. Should be called at 44.1kHz with the new reverb samples.
-fn run_reverb_cycle(bus: &mut Bus, (left_in, right_in): (i16, i16)) -> (i16, i16) {
+fn run_reverb_cycle(spu: &mut Spu, (left_in, right_in): (i16, i16)) -> (i16, i16) {
     // Reverb downsamples from 44.1Khz to 22.05kHz using a simple FIR filter
-    bus.spu.reverb_downsampler_left.push_sample(left_in);
-    bus.spu.reverb_downsampler_right.push_sample(right_in);
+    spu.reverb_downsampler_left.push_sample(left_in);
+    spu.reverb_downsampler_right.push_sample(right_in);
 
     fn iir_mul(a: i16, b: i16) -> i32 {
         (if a > i16::MIN {
@@ -463,32 +643,32 @@ fn run_reverb_cycle(bus: &mut Bus, (left_in, right_in): (i16, i16)) -> (i16, i16
         }) >> 14
     }
 
-    if bus.spu.reverb_enabled() && bus.spu.reverb_enable_override {
-        if bus.spu.reverb_run_right {
+    if spu.reverb_enabled() && spu.reverb_enable_override {
+        if spu.reverb_run_right {
             // IIR processing
-            let sample = i32::from(bus.spu.reverb_downsampler_right.resample());
+            let sample = i32::from(spu.reverb_downsampler_right.resample());
 
             let in_mix =
-                (sample * i32::from(bus.spu.regs[regmap::REVERB_INPUT_VOLUME_RIGHT] as i16)) >> 15;
+                (sample * i32::from(spu.regs[regmap::REVERB_INPUT_VOLUME_RIGHT] as i16)) >> 15;
 
-            let reflect_vol = i32::from(bus.spu.regs[regmap::REVERB_REFLECT_VOLUME2] as i16);
+            let reflect_vol = i32::from(spu.regs[regmap::REVERB_REFLECT_VOLUME2] as i16);
 
             let same_side_sample = i32::from(load_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_REFLECT_SAME_RIGHT2],
+                spu,
+                spu.regs[regmap::REVERB_REFLECT_SAME_RIGHT2],
             ));
             let same_side_mix = (same_side_sample * reflect_vol) >> 15;
 
             let diff_side_sample = i32::from(load_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_REFLECT_DIFF_RIGHT2],
+                spu,
+                spu.regs[regmap::REVERB_REFLECT_DIFF_RIGHT2],
             ));
             let diff_side_mix = (diff_side_sample * reflect_vol) >> 15;
 
             let input_same = saturate_to_i16(same_side_mix + in_mix);
             let input_diff = saturate_to_i16(diff_side_mix + in_mix);
 
-            let reflect_iir_vol = bus.spu.regs[regmap::REVERB_REFLECT_VOLUME1] as i16;
+            let reflect_iir_vol = spu.regs[regmap::REVERB_REFLECT_VOLUME1] as i16;
             let input_same_alpha = (i32::from(input_same) * i32::from(reflect_iir_vol)) >> 14;
             let input_diff_alpha = (i32::from(input_diff) * i32::from(reflect_iir_vol)) >> 14;
 
@@ -497,8 +677,8 @@ fn run_reverb_cycle(bus: &mut Bus, (left_in, right_in): (i16, i16)) -> (i16, i16
                     + iir_mul(
                     reflect_iir_vol,
                     load_reverb_sample_before(
-                        bus,
-                        bus.spu.regs[regmap::REVERB_REFLECT_SAME_RIGHT1],
+                        spu,
+                        spu.regs[regmap::REVERB_REFLECT_SAME_RIGHT1],
                     ),
                 ))
                     >> 1,
@@ -508,61 +688,61 @@ fn run_reverb_cycle(bus: &mut Bus, (left_in, right_in): (i16, i16)) -> (i16, i16
                     + iir_mul(
                     reflect_iir_vol,
                     load_reverb_sample_before(
-                        bus,
-                        bus.spu.regs[regmap::REVERB_REFLECT_DIFF_RIGHT1],
+                        spu,
+                        spu.regs[regmap::REVERB_REFLECT_DIFF_RIGHT1],
                     ),
                 ))
                     >> 1,
             );
 
             store_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_REFLECT_SAME_RIGHT1],
+                spu,
+                spu.regs[regmap::REVERB_REFLECT_SAME_RIGHT1],
                 iir_same,
             );
             store_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_REFLECT_DIFF_RIGHT1],
+                spu,
+                spu.regs[regmap::REVERB_REFLECT_DIFF_RIGHT1],
                 iir_diff,
             );
 
             let early_echo = saturate_to_i16(
                 (((i32::from(load_reverb_sample(
-                    bus,
-                    bus.spu.regs[regmap::REVERB_COMB_RIGHT1],
-                )) * i32::from(bus.spu.regs[regmap::REVERB_COMB_VOLUME1] as i16))
+                    spu,
+                    spu.regs[regmap::REVERB_COMB_RIGHT1],
+                )) * i32::from(spu.regs[regmap::REVERB_COMB_VOLUME1] as i16))
                     >> 14)
                     + ((i32::from(load_reverb_sample(
-                    bus,
-                    bus.spu.regs[regmap::REVERB_COMB_RIGHT2],
-                )) * i32::from(bus.spu.regs[regmap::REVERB_COMB_VOLUME2] as i16))
+                    spu,
+                    spu.regs[regmap::REVERB_COMB_RIGHT2],
+                )) * i32::from(spu.regs[regmap::REVERB_COMB_VOLUME2] as i16))
                     >> 14)
                     + ((i32::from(load_reverb_sample(
-                    bus,
-                    bus.spu.regs[regmap::REVERB_COMB_RIGHT3],
-                )) * i32::from(bus.spu.regs[regmap::REVERB_COMB_VOLUME3] as i16))
+                    spu,
+                    spu.regs[regmap::REVERB_COMB_RIGHT3],
+                )) * i32::from(spu.regs[regmap::REVERB_COMB_VOLUME3] as i16))
                     >> 14)
                     + ((i32::from(load_reverb_sample(
-                    bus,
-                    bus.spu.regs[regmap::REVERB_COMB_RIGHT4],
-                )) * i32::from(bus.spu.regs[regmap::REVERB_COMB_VOLUME4] as i16))
+                    spu,
+                    spu.regs[regmap::REVERB_COMB_RIGHT4],
+                )) * i32::from(spu.regs[regmap::REVERB_COMB_VOLUME4] as i16))
                     >> 14))
                     >> 1,
             );
 
             let apf_in1 = i32::from(load_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_APF_RIGHT1]
-                    .wrapping_add(bus.spu.regs[regmap::REVERB_APF_OFFSET1]),
+                spu,
+                spu.regs[regmap::REVERB_APF_RIGHT1]
+                    .wrapping_add(spu.regs[regmap::REVERB_APF_OFFSET1]),
             ));
             let apf_in2 = i32::from(load_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_APF_RIGHT2]
-                    .wrapping_add(bus.spu.regs[regmap::REVERB_APF_OFFSET2]),
+                spu,
+                spu.regs[regmap::REVERB_APF_RIGHT2]
+                    .wrapping_add(spu.regs[regmap::REVERB_APF_OFFSET2]),
             ));
 
-            let apf_vol1 = i32::from(bus.spu.regs[regmap::REVERB_APF_VOLUME1] as i16);
-            let apf_vol2 = i32::from(bus.spu.regs[regmap::REVERB_APF_VOLUME2] as i16);
+            let apf_vol1 = i32::from(spu.regs[regmap::REVERB_APF_VOLUME1] as i16);
+            let apf_vol2 = i32::from(spu.regs[regmap::REVERB_APF_VOLUME2] as i16);
 
             let out_1 = saturate_to_i16(i32::from(early_echo) - ((apf_in1 * apf_vol1) >> 15));
             let out_2 = saturate_to_i16(
@@ -571,38 +751,38 @@ fn run_reverb_cycle(bus: &mut Bus, (left_in, right_in): (i16, i16)) -> (i16, i16
                     - ((apf_in2 * apf_vol2) >> 15),
             );
 
-            store_reverb_sample(bus, bus.spu.regs[regmap::REVERB_APF_RIGHT1], out_1);
-            store_reverb_sample(bus, bus.spu.regs[regmap::REVERB_APF_RIGHT2], out_2);
+            store_reverb_sample(spu, spu.regs[regmap::REVERB_APF_RIGHT1], out_1);
+            store_reverb_sample(spu, spu.regs[regmap::REVERB_APF_RIGHT2], out_2);
 
-            bus.spu.reverb_upsampler_left.push_sample(0);
-            bus.spu
+            spu.reverb_upsampler_left.push_sample(0);
+            spu
                 .reverb_upsampler_right
                 .push_sample(saturate_to_i16((i32::from(out_1) + i32::from(out_2)) >> 1));
         } else {
             // IIR processing
-            let sample = i32::from(bus.spu.reverb_downsampler_left.resample());
+            let sample = i32::from(spu.reverb_downsampler_left.resample());
 
             let in_mix =
-                (sample * i32::from(bus.spu.regs[regmap::REVERB_INPUT_VOLUME_LEFT] as i16)) >> 15;
+                (sample * i32::from(spu.regs[regmap::REVERB_INPUT_VOLUME_LEFT] as i16)) >> 15;
 
-            let reflect_vol = i32::from(bus.spu.regs[regmap::REVERB_REFLECT_VOLUME2] as i16);
+            let reflect_vol = i32::from(spu.regs[regmap::REVERB_REFLECT_VOLUME2] as i16);
 
             let same_side_sample = i32::from(load_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_REFLECT_SAME_LEFT2],
+                spu,
+                spu.regs[regmap::REVERB_REFLECT_SAME_LEFT2],
             ));
             let same_side_mix = (same_side_sample * reflect_vol) >> 15;
 
             let diff_side_sample = i32::from(load_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_REFLECT_DIFF_LEFT2],
+                spu,
+                spu.regs[regmap::REVERB_REFLECT_DIFF_LEFT2],
             ));
             let diff_side_mix = (diff_side_sample * reflect_vol) >> 15;
 
             let input_same = saturate_to_i16(same_side_mix + in_mix);
             let input_diff = saturate_to_i16(diff_side_mix + in_mix);
 
-            let reflect_iir_vol = bus.spu.regs[regmap::REVERB_REFLECT_VOLUME1] as i16;
+            let reflect_iir_vol = spu.regs[regmap::REVERB_REFLECT_VOLUME1] as i16;
             let input_same_alpha = (i32::from(input_same) * i32::from(reflect_iir_vol)) >> 14;
             let input_diff_alpha = (i32::from(input_diff) * i32::from(reflect_iir_vol)) >> 14;
 
@@ -611,8 +791,8 @@ fn run_reverb_cycle(bus: &mut Bus, (left_in, right_in): (i16, i16)) -> (i16, i16
                     + iir_mul(
                     reflect_iir_vol,
                     load_reverb_sample_before(
-                        bus,
-                        bus.spu.regs[regmap::REVERB_REFLECT_SAME_LEFT1],
+                        spu,
+                        spu.regs[regmap::REVERB_REFLECT_SAME_LEFT1],
                     ),
                 ))
                     >> 1,
@@ -622,62 +802,62 @@ fn run_reverb_cycle(bus: &mut Bus, (left_in, right_in): (i16, i16)) -> (i16, i16
                     + iir_mul(
                     reflect_iir_vol,
                     load_reverb_sample_before(
-                        bus,
-                        bus.spu.regs[regmap::REVERB_REFLECT_DIFF_LEFT1],
+                        spu,
+                        spu.regs[regmap::REVERB_REFLECT_DIFF_LEFT1],
                     ),
                 ))
                     >> 1,
             );
 
             store_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_REFLECT_SAME_LEFT1],
+                spu,
+                spu.regs[regmap::REVERB_REFLECT_SAME_LEFT1],
                 iir_same,
             );
             store_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_REFLECT_DIFF_LEFT1],
+                spu,
+                spu.regs[regmap::REVERB_REFLECT_DIFF_LEFT1],
                 iir_diff,
             );
 
             let early_echo = saturate_to_i16(
                 (((i32::from(load_reverb_sample(
-                    bus,
-                    bus.spu.regs[regmap::REVERB_COMB_LEFT1],
-                )) * i32::from(bus.spu.regs[regmap::REVERB_COMB_VOLUME1] as i16))
+                    spu,
+                    spu.regs[regmap::REVERB_COMB_LEFT1],
+                )) * i32::from(spu.regs[regmap::REVERB_COMB_VOLUME1] as i16))
                     >> 14)
                     + ((i32::from(load_reverb_sample(
-                    bus,
-                    bus.spu.regs[regmap::REVERB_COMB_LEFT2],
-                )) * i32::from(bus.spu.regs[regmap::REVERB_COMB_VOLUME2] as i16))
+                    spu,
+                    spu.regs[regmap::REVERB_COMB_LEFT2],
+                )) * i32::from(spu.regs[regmap::REVERB_COMB_VOLUME2] as i16))
                     >> 14)
                     + ((i32::from(load_reverb_sample(
-                    bus,
-                    bus.spu.regs[regmap::REVERB_COMB_LEFT3],
-                )) * i32::from(bus.spu.regs[regmap::REVERB_COMB_VOLUME3] as i16))
+                    spu,
+                    spu.regs[regmap::REVERB_COMB_LEFT3],
+                )) * i32::from(spu.regs[regmap::REVERB_COMB_VOLUME3] as i16))
                     >> 14)
                     + ((i32::from(load_reverb_sample(
-                    bus,
-                    bus.spu.regs[regmap::REVERB_COMB_LEFT4],
-                )) * i32::from(bus.spu.regs[regmap::REVERB_COMB_VOLUME4] as i16))
+                    spu,
+                    spu.regs[regmap::REVERB_COMB_LEFT4],
+                )) * i32::from(spu.regs[regmap::REVERB_COMB_VOLUME4] as i16))
                     >> 14))
                     >> 1,
             );
 
             // All-pass filter
             let apf_in1 = i32::from(load_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_APF_LEFT1]
-                    .wrapping_add(bus.spu.regs[regmap::REVERB_APF_OFFSET1]),
+                spu,
+                spu.regs[regmap::REVERB_APF_LEFT1]
+                    .wrapping_add(spu.regs[regmap::REVERB_APF_OFFSET1]),
             ));
             let apf_in2 = i32::from(load_reverb_sample(
-                bus,
-                bus.spu.regs[regmap::REVERB_APF_LEFT2]
-                    .wrapping_add(bus.spu.regs[regmap::REVERB_APF_OFFSET2]),
+                spu,
+                spu.regs[regmap::REVERB_APF_LEFT2]
+                    .wrapping_add(spu.regs[regmap::REVERB_APF_OFFSET2]),
             ));
 
-            let apf_vol1 = i32::from(bus.spu.regs[regmap::REVERB_APF_VOLUME1] as i16);
-            let apf_vol2 = i32::from(bus.spu.regs[regmap::REVERB_APF_VOLUME2] as i16);
+            let apf_vol1 = i32::from(spu.regs[regmap::REVERB_APF_VOLUME1] as i16);
+            let apf_vol2 = i32::from(spu.regs[regmap::REVERB_APF_VOLUME2] as i16);
 
             let out_1 = saturate_to_i16(i32::from(early_echo) - ((apf_in1 * apf_vol1) >> 15));
             let out_2 = saturate_to_i16(
@@ -686,65 +866,70 @@ fn run_reverb_cycle(bus: &mut Bus, (left_in, right_in): (i16, i16)) -> (i16, i16
                     - ((apf_in2 * apf_vol2) >> 15),
             );
 
-            store_reverb_sample(bus, bus.spu.regs[regmap::REVERB_APF_LEFT1], out_1);
-            store_reverb_sample(bus, bus.spu.regs[regmap::REVERB_APF_LEFT2], out_2);
+            store_reverb_sample(spu, spu.regs[regmap::REVERB_APF_LEFT1], out_1);
+            store_reverb_sample(spu, spu.regs[regmap::REVERB_APF_LEFT2], out_2);
 
-            bus.spu
+            spu
                 .reverb_upsampler_left
                 .push_sample(saturate_to_i16((i32::from(out_1) + i32::from(out_2)) >> 1));
-            bus.spu.reverb_upsampler_right.push_sample(0);
+            spu.reverb_upsampler_right.push_sample(0);
         }
     }
 
-    if bus.spu.reverb_run_right {
-        bus.spu.reverb_index = bus.spu.reverb_index.wrapping_add(1);
-        if bus.spu.reverb_index > 0x3_ffff {
-            bus.spu.reverb_index = bus.spu.reverb_start;
+    if spu.reverb_run_right {
+        spu.reverb_index = spu.reverb_index.wrapping_add(1);
+        if spu.reverb_index > 0x3_ffff {
+            spu.reverb_index = spu.reverb_start;
         }
     }
-    bus.spu.reverb_run_right = !bus.spu.reverb_run_right;
+    spu.reverb_run_right = !spu.reverb_run_right;
 
-    let reverb_left = bus.spu.reverb_upsampler_left.resample();
-    let reverb_right = bus.spu.reverb_upsampler_right.resample();
+    let reverb_left = spu.reverb_upsampler_left.resample();
+    let reverb_right = spu.reverb_upsampler_right.resample();
 
     (reverb_left, reverb_right)
 }
 
-/// Run `voice` for one cycle and return a pair of stereo samples
-fn run_voice_cycle(bus: &mut Bus, voice: u8, sweep_factor: &mut i32) -> (i32, i32) {
+/// Run `voice` for one cycle and return its output sample, before the stereo volume is applied.
+/// Only touches the SPU so that the compiler can keep it in registers for the 24 voices, if the
+/// interrupt triggers `spu.irq` is set and it's up to the caller to signal it.
+fn run_voice_cycle(spu: &mut Spu, voice: u8, sweep_factor: &mut i32) -> i32 {
     // There's no "enable" flag for the voices, they're effectively always running. Unused voices
     // are just muted. Beyond that the ADPCM decoder is always running, even when the voice is in
     // "noise" mode and the output isn't used. This is important when the SPU interrupt is enabled.
-    run_voice_decoder(bus, voice);
+    run_voice_decoder(spu, voice);
 
-    let raw_sample = if bus.spu.is_noise(voice) {
-        (bus.spu.noise_lfsr as i16) as i32
+    let sample = if spu[voice].level() == 0 {
+        // Silent voice (most of them, most of the time), no need to interpolate
+        0
     } else {
-        bus.spu[voice].next_raw_sample()
-    };
+        let raw_sample = if spu.is_noise(voice) {
+            (spu.noise_lfsr as i16) as i32
+        } else {
+            spu[voice].next_raw_sample(spu.interpolation)
+        };
 
-    let sample = bus.spu[voice].apply_enveloppe(raw_sample);
+        spu[voice].apply_enveloppe(raw_sample)
+    };
 
     // Voices 1 and 3 write their samples back into SPU RAM (what No$ refers to as "capture")
     if voice == 1 {
-        ram_write(bus, 0x400 | bus.spu.capture_index, sample as u16);
+        spu.ram_write(0x400 | spu.capture_index, sample as u16);
     } else if voice == 3 {
-        ram_write(bus, 0x600 | bus.spu.capture_index, sample as u16);
+        spu.ram_write(0x600 | spu.capture_index, sample as u16);
     }
 
-    let (left, right) = bus.spu[voice].apply_stereo(sample);
-
-    bus.spu[voice].run_sweep_cycle();
+    spu[voice].run_sweep_cycle();
 
-    if bus.spu[voice].start_delay > 0 {
+    if spu[voice].start_delay > 0 {
         // We're still in the start delay, we don't run the envelope or frequency sweep yet
-        bus.spu[voice].start_delay -= 1;
+        spu[voice].start_delay -= 1;
     } else {
-        bus.spu[voice].run_envelope_cycle();
+        spu[voice].run_envelope_cycle();
 
-        let mut step = u32::from(bus.spu[voice].step_length);
+        let mut step = u32::from(spu[voice].step_length);
 
-        if bus.spu.is_frequency_modulated(voice) {
+        if spu.is_frequency_modulated(voice) {
             // Voice 0 cannot be frequency modulated
             debug_assert!(voice != 0);
 
@@ -758,32 +943,32 @@ fn run_voice_cycle(bus: &mut Bus, voice: u8, sweep_factor: &mut i32) -> (i32, i3
 
         let step = if step > 0x3fff { 0x3fff } else { step as u16 };
 
-        bus.spu[voice].consume_samples(step);
+        spu[voice].consume_samples(step);
     }
 
-    if bus.spu.is_voice_stopped(voice) {
-        bus.spu[voice].release();
+    if spu.is_voice_stopped(voice) {
+        spu[voice].release();
     }
 
-    if bus.spu.is_voice_started(voice) {
-        bus.spu[voice].restart();
-        bus.spu.voice_looped &= !(1 << voice);
+    if spu.is_voice_started(voice) {
+        spu[voice].restart();
+        spu.voice_looped &= !(1 << voice);
     }
 
-    if !bus.spu.enabled() {
+    if !spu.enabled() {
         // XXX Mednafen doesn't reset the ADSR divider in this situation
-        bus.spu[voice].release();
-        bus.spu[voice].mute();
+        spu[voice].release();
+        spu[voice].mute();
     }
 
     // Save sweep factor for the next voice
     *sweep_factor = sample;
 
-    (left, right)
+    sample
 }
 
 /// Run the ADPCM decoder for one cycle
-fn run_voice_decoder(bus: &mut Bus, voice: u8) {
+fn run_voice_decoder(spu: &mut Spu, voice: u8) {
     // XXX This value of 11 is taken from Mednafen. Technically we only consume 4 samples (at most)
     // per cycle so >= 4 would do the trick but apparently the original hardware decodes ahead.
     // This is important if the IRQ is enabled since it means that it would trigger a bit earlier
@@ -792,57 +977,59 @@ fn run_voice_decoder(bus: &mut Bus, voice: u8) {
     // This is still not entirely cycle accurate, so it could be further improved with more
     // testing. Mednafen's codebase has a few comments giving hints on what could be done. More
     // testing required.
-    if bus.spu[voice].decoder_fifo.len() >= 11 {
+    if spu[voice].decoder_fifo.len() >= 11 {
         // We have enough data in the decoder FIFO, no need to decode more
-        if bus.spu.irq_enabled() {
+        if spu.irq_enabled() {
             // Test prev address
-            let prev = bus.spu[voice].cur_index.wrapping_sub(1) & 0x3_ffff;
-            check_for_irq(bus, prev);
+            let prev = spu[voice].cur_index.wrapping_sub(1) & 0x3_ffff;
+            spu.check_for_irq(prev);
             // This is taken from mednafen, not shure why it's necessary
-            check_for_irq(bus, prev & 0x3_fff8);
+            spu.check_for_irq(prev & 0x3_fff8);
         }
     } else {
         // True if we're starting a new ADPCM block
-        let new_block = bus.spu[voice].cur_index % 8 == 0;
+        let new_block = spu[voice].cur_index.is_multiple_of(8);
 
         if new_block {
             // Check if looping has been requested in the previous block
-            if bus.spu[voice].maybe_loop() {
-                bus.spu.voice_looped |= 1 << voice;
+            if spu[voice].maybe_loop() {
+                spu.voice_looped |= 1 << voice;
 
                 // Mednafen doesn't apply the "release and mute" block flag if we're in noise
                 // mode. No$ doesn't seem to mention this corner case, but I suppose that it makes
                 // sense to ignore decoder envelope changes if we don't use the data.
-                if !bus.spu.is_noise(voice) {
-                    bus.spu[voice].maybe_release();
+                if !spu.is_noise(voice) {
+                    spu[voice].maybe_release();
                 }
             }
         }
 
-        if bus.spu.irq_enabled() {
+        if spu.irq_enabled() {
             // Test current address
-            check_for_irq(bus, bus.spu[voice].cur_index);
+            spu.check_for_irq(spu[voice].cur_index);
             // This is taken from mednafen, not sure why it's necessary
-            check_for_irq(bus, bus.spu[voice].cur_index & 0x3_fff8);
+            spu.check_for_irq(spu[voice].cur_index & 0x3_fff8);
         }
 
         if new_block {
             // We're starting a new block
-            let header = ram_read_no_irq(bus, bus.spu[voice].cur_index);
+            let header = spu.ram_read_no_irq(spu[voice].cur_index);
 
-            bus.spu[voice].set_block_header(header);
-            bus.spu[voice].next_index();
+            spu[voice].set_block_header(header);
+            spu[voice].next_index();
         }
 
         // Decode 4 samples
-        let encoded = ram_read_no_irq(bus, bus.spu[voice].cur_index);
-        bus.spu[voice].next_index();
-        bus.spu[voice].decode(encoded);
+        let encoded = spu.ram_read_no_irq(spu[voice].cur_index);
+        spu[voice].next_index();
+        spu[voice].decode(encoded);
     }
 }
 
 /// Handle DMA writes
-pub fn dma_store(bus: &mut Bus, v: u32) {
+pub fn dma_store<B: SpuBus>(bus: &mut B, v: u32) {
+    run(bus);
+
     let w1 = v as u16;
     let w2 = (v >> 16) as u16;
 
@@ -852,18 +1039,23 @@ pub fn dma_store(bus: &mut Bus, v: u32) {
 }
 
 /// Handle DMA reads
-pub fn dma_load(bus: &mut Bus) -> u32 {
-    let w1 = ram_read(bus, bus.spu.ram_index) as u32;
-    bus.spu.ram_index = (bus.spu.ram_index + 1) & 0x3_ffff;
-    let w2 = ram_read(bus, bus.spu.ram_index) as u32;
-    bus.spu.ram_index = (bus.spu.ram_index + 1) & 0x3_ffff;
+pub fn dma_load<B: SpuBus>(bus: &mut B) -> u32 {
+    run(bus);
 
-    check_for_irq(bus, bus.spu.ram_index);
+    let index = bus.spu().ram_index;
+
+    let w1 = ram_read(bus, index) as u32;
+    let index = (index + 1) & 0x3_ffff;
+    let w2 = ram_read(bus, index) as u32;
+    let index = (index + 1) & 0x3_ffff;
+
+    bus.spu().ram_index = index;
+    check_for_irq(bus, index);
 
     w1 | (w2 << 16)
 }
 
-pub fn store<T: Addressable>(bus: &mut Bus, off: u32, val: T) {
+pub fn store<B: SpuBus, T: Addressable>(bus: &mut B, off: u32, val: T) {
     match T::width() {
         AccessWidth::Word => {
             // Word writes behave like two u16
@@ -895,16 +1087,21 @@ pub fn store<T: Addressable>(bus: &mut Bus, off: u32, val: T) {
     }
 }
 
-fn store16(bus: &mut Bus, off: u32, val: u16) {
+fn store16<B: SpuBus>(bus: &mut B, off: u32, val: u16) {
+    // Samples are generated in blocks so we have to catch up before changing anything
+    run(bus);
+
     let val = val.as_u16();
 
     let index = (off >> 1) as usize;
 
-    bus.spu.regs[index] = val;
+    let spu = bus.spu();
+
+    spu.regs[index] = val;
 
     if index < 0xc0 {
         // Voice configuration
-        let voice = &mut bus.spu.voices[index >> 3];
+        let voice = &mut spu.voices[index >> 3];
 
         match index & 7 {
             regmap::voice::VOLUME_LEFT => voice.volume_left.set_config(val),
@@ -922,47 +1119,56 @@ fn store16(bus: &mut Bus, off: u32, val: u16) {
         }
     } else if index < 0x100 {
         match index {
-            regmap::MAIN_VOLUME_LEFT => bus.spu.main_volume_left.set_config(val),
-            regmap::MAIN_VOLUME_RIGHT => bus.spu.main_volume_right.set_config(val),
-            regmap::REVERB_VOLUME_LEFT => bus.spu.reverb_out_volume_left = val as i16,
-            regmap::REVERB_VOLUME_RIGHT => bus.spu.reverb_out_volume_right = val as i16,
-            regmap::VOICE_ON_LO => to_lo(&mut bus.spu.voice_start, val),
-            regmap::VOICE_ON_HI => to_hi(&mut bus.spu.voice_start, val),
-            regmap::VOICE_OFF_LO => to_lo(&mut bus.spu.voice_stop, val),
-            regmap::VOICE_OFF_HI => to_hi(&mut bus.spu.voice_stop, val),
+            regmap::MAIN_VOLUME_LEFT => spu.main_volume_left.set_config(val),
+            regmap::MAIN_VOLUME_RIGHT => spu.main_volume_right.set_config(val),
+            regmap::REVERB_VOLUME_LEFT => spu.reverb_out_volume_left = val as i16,
+            regmap::REVERB_VOLUME_RIGHT => spu.reverb_out_volume_right = val as i16,
+            regmap::VOICE_ON_LO => to_lo(&mut spu.voice_start, val),
+            regmap::VOICE_ON_HI => to_hi(&mut spu.voice_start, val),
+            regmap::VOICE_OFF_LO => to_lo(&mut spu.voice_stop, val),
+            regmap::VOICE_OFF_HI => to_hi(&mut spu.voice_stop, val),
             regmap::VOICE_FM_MOD_EN_LO => {
                 // Voice 0 cannot be frequency modulated
-                to_lo(&mut bus.spu.voice_frequency_modulated, val & !1);
+                to_lo(&mut spu.voice_frequency_modulated, val & !1);
             }
-            regmap::VOICE_FM_MOD_EN_HI => to_hi(&mut bus.spu.voice_frequency_modulated, val),
-            regmap::VOICE_NOISE_EN_LO => to_lo(&mut bus.spu.voice_noise, val),
-            regmap::VOICE_NOISE_EN_HI => to_hi(&mut bus.spu.voice_noise, val),
-            regmap::VOICE_REVERB_EN_LO => to_lo(&mut bus.spu.voice_reverb, val),
-            regmap::VOICE_REVERB_EN_HI => to_hi(&mut bus.spu.voice_reverb, val),
-            regmap::VOICE_STATUS_LO => to_lo(&mut bus.spu.voice_looped, val),
-            regmap::VOICE_STATUS_HI => to_hi(&mut bus.spu.voice_looped, val),
+            regmap::VOICE_FM_MOD_EN_HI => to_hi(&mut spu.voice_frequency_modulated, val),
+            regmap::VOICE_NOISE_EN_LO => to_lo(&mut spu.voice_noise, val),
+            regmap::VOICE_NOISE_EN_HI => to_hi(&mut spu.voice_noise, val),
+            regmap::VOICE_REVERB_EN_LO => to_lo(&mut spu.voice_reverb, val),
+            regmap::VOICE_REVERB_EN_HI => to_hi(&mut spu.voice_reverb, val),
+            regmap::VOICE_STATUS_LO => to_lo(&mut spu.voice_looped, val),
+            regmap::VOICE_STATUS_HI => to_hi(&mut spu.voice_looped, val),
             regmap::REVERB_BASE => {
                 let idx = to_ram_index(val);
-                bus.spu.reverb_start = idx;
-                bus.spu.reverb_index = idx;
+                spu.reverb_start = idx;
+                spu.reverb_index = idx;
             }
             regmap::IRQ_ADDRESS => {
-                bus.spu.irq_addr = to_ram_index(val);
-                check_for_irq(bus, bus.spu.ram_index);
+                spu.irq_addr = to_ram_index(val);
+
+                let index = spu.ram_index;
+                check_for_irq(bus, index);
             }
             regmap::TRANSFER_START_INDEX => {
-                bus.spu.ram_index = to_ram_index(val);
-                check_for_irq(bus, bus.spu.ram_index);
+                let index = to_ram_index(val);
+
+                spu.ram_index = index;
+                check_for_irq(bus, index);
             }
             regmap::TRANSFER_FIFO => transfer(bus, val),
             regmap::CONTROL => {
-                if bus.spu.irq_enabled() {
-                    check_for_irq(bus, bus.spu.ram_index);
+                if spu.irq_enabled() {
+                    let index = spu.ram_index;
+                    check_for_irq(bus, index);
                 } else {
                     // IRQ is acknowledged
-                    bus.spu.irq = false;
-                    irq::set_low(bus, irq::Interrupt::Spu);
+                    spu.irq = false;
+                    bus.set_irq_level(Interrupt::Spu, false);
                 }
+
+                // The block size depends on the IRQ being enabled, nothing gets generated here
+                // since we've just synced
+                run(bus);
             }
             regmap::TRANSFER_CONTROL => {
                 if val != 4 {
@@ -973,8 +1179,8 @@ fn store16(bus: &mut Bus, off: u32, val: u16) {
                     warn!("SPU TRANSFER_CONTROL set to 0x{:x}", val);
                 }
             }
-            regmap::CD_VOLUME_LEFT => bus.spu.cd_volume_left = val as i16,
-            regmap::CD_VOLUME_RIGHT => bus.spu.cd_volume_right = val as i16,
+            regmap::CD_VOLUME_LEFT => spu.cd_volume_left = val as i16,
+            regmap::CD_VOLUME_RIGHT => spu.cd_volume_right = val as i16,
             regmap::EXT_VOLUME_LEFT => (),
             regmap::EXT_VOLUME_RIGHT => (),
             // Reverb configuration
@@ -990,7 +1196,7 @@ fn store16(bus: &mut Bus, off: u32, val: u16) {
     } else if index < 0x130 {
         // Set voice level
         let voice_no = (index >> 1) & 0x1f;
-        let voice = &mut bus.spu.voices[voice_no];
+        let voice = &mut spu.voices[voice_no];
 
         let left = index & 1 == 0;
 
@@ -1004,7 +1210,7 @@ fn store16(bus: &mut Bus, off: u32, val: u16) {
     }
 }
 
-pub fn load<T: Addressable>(bus: &mut Bus, off: u32) -> T {
+pub fn load<B: SpuBus, T: Addressable>(bus: &mut B, off: u32) -> T {
     let v = match T::width() {
         AccessWidth::Word => {
             let hi = load16(bus, off | 2) as u32;
@@ -1026,17 +1232,19 @@ pub fn load<T: Addressable>(bus: &mut Bus, off: u32) -> T {
     T::from_u32(v)
 }
 
-fn load16(bus: &mut Bus, off: u32) -> u16 {
+fn load16<B: SpuBus>(bus: &mut B, off: u32) -> u16 {
     // This is probably very heavy handed, mednafen only syncs from the CD code and never on
     // register access
     run(bus);
 
     let index = (off >> 1) as usize;
 
-    let reg_v = bus.spu.regs[index];
+    let spu = bus.spu();
+
+    let reg_v = spu.regs[index];
 
     if index < 0xc0 {
-        let voice = &bus.spu.voices[index >> 3];
+        let voice = &spu.voices[index >> 3];
 
         match index & 7 {
             regmap::voice::CURRENT_ADSR_VOLUME => voice.level() as u16,
@@ -1045,11 +1253,11 @@ fn load16(bus: &mut Bus, off: u32) -> u16 {
         }
     } else if index < 0x100 {
         match index {
-            regmap::VOICE_STATUS_LO => bus.spu.voice_looped as u16,
-            regmap::VOICE_STATUS_HI => (bus.spu.voice_looped >> 16) as u16,
+            regmap::VOICE_STATUS_LO => spu.voice_looped as u16,
+            regmap::VOICE_STATUS_HI => (spu.voice_looped >> 16) as u16,
             regmap::TRANSFER_FIFO => unimplemented!(),
-            regmap::CURRENT_VOLUME_LEFT => bus.spu.main_volume_left.level() as u16,
-            regmap::CURRENT_VOLUME_RIGHT => bus.spu.main_volume_right.level() as u16,
+            regmap::CURRENT_VOLUME_LEFT => spu.main_volume_left.level() as u16,
+            regmap::CURRENT_VOLUME_RIGHT => spu.main_volume_right.level() as u16,
             // Nobody seems to know what this register is for, but mednafen returns 0
             regmap::UNKNOWN => 0,
             _ => reg_v,
@@ -1057,7 +1265,7 @@ fn load16(bus: &mut Bus, off: u32) -> u16 {
     } else if index < 0x130 {
         // Read voice level
         let voice_no = (index >> 1) & 0x1f;
-        let voice = &bus.spu.voices[voice_no];
+        let voice = &spu.voices[voice_no];
 
         let left = index & 1 == 0;
 
@@ -1074,47 +1282,35 @@ fn load16(bus: &mut Bus, off: u32) -> u16 {
 }
 
 /// Write the SPU ram at the `ram_index` an increment it.
-fn transfer(bus: &mut Bus, val: u16) {
-    let i = bus.spu.ram_index;
+fn transfer<B: SpuBus>(bus: &mut B, val: u16) {
+    let i = bus.spu().ram_index;
 
     ram_write(bus, i, val);
 
-    bus.spu.ram_index = (i + 1) & 0x3_ffff;
+    let i = (i + 1) & 0x3_ffff;
+    bus.spu().ram_index = i;
 
     // `ram_write` already checks for interrupt before the write but mednafen immediately rechecks
     // the incremented address after that. Sounds weird but let's go with it for now.
-    check_for_irq(bus, bus.spu.ram_index);
+    check_for_irq(bus, i);
 }
 
-fn ram_write(bus: &mut Bus, index: RamIndex, val: u16) {
+fn ram_write<B: SpuBus>(bus: &mut B, index: RamIndex, val: u16) {
     check_for_irq(bus, index);
 
-    let index = index as usize;
-
-    debug_assert!(index < bus.spu.ram.len());
-
-    bus.spu.ram[index] = val;
-}
-
-fn ram_read_no_irq(bus: &mut Bus, index: RamIndex) -> u16 {
-    let index = index as usize;
-
-    debug_assert!(index < bus.spu.ram.len());
-
-    bus.spu.ram[index]
+    bus.spu().ram_write_no_irq(index, val);
 }
 
-fn ram_read(bus: &mut Bus, index: RamIndex) -> u16 {
+fn ram_read<B: SpuBus>(bus: &mut B, index: RamIndex) -> u16 {
     check_for_irq(bus, index);
 
-    ram_read_no_irq(bus, index)
+    bus.spu().ram_read_no_irq(index)
 }
 
 /// Trigger an IRQ if it's enabled in the control register and `addr` is equal to the `irq_addr`
-fn check_for_irq(bus: &mut Bus, index: RamIndex) {
-    if bus.spu.irq_enabled() && index == bus.spu.irq_addr {
-        bus.spu.irq = true;
-        irq::set_high(bus, irq::Interrupt::Spu);
+fn check_for_irq<B: SpuBus>(bus: &mut B, index: RamIndex) {
+    if bus.spu().check_for_irq(index) {
+        bus.set_irq_level(Interrupt::Spu, true);
     }
 }
 
@@ -1274,7 +1470,7 @@ impl Voice {
 
     /// Returns the next "raw" decoded sample for this voice, meaning the post-ADPCM decode and
     /// resampling but pre-ADSR.
-    fn next_raw_sample(&self) -> i32 {
+    fn next_raw_sample(&self, interpolation: Interpolation) -> i32 {
         let phase = (self.phase >> 4) as u8;
         let samples = [
             self.decoder_fifo[0],
@@ -1283,7 +1479,11 @@ impl Voice {
             self.decoder_fifo[3],
         ];
 
-        fir::filter(phase, samples)
+        match interpolation {
+            Interpolation::Nearest => fir::nearest(phase, samples),
+            Interpolation::Gaussian => fir::filter(phase, samples),
+            Interpolation::Cubic => fir::cubic(phase, samples),
+        }
     }
 
     /// Run one cycle for the ADSR envelope function
@@ -1303,14 +1503,6 @@ impl Voice {
         (sample * level) >> 15
     }
 
-    /// Apply left and right volume levels
-    fn apply_stereo(&self, sample: i32) -> (i32, i32) {
-        (
-            self.volume_left.apply_level(sample),
-            self.volume_right.apply_level(sample),
-        )
-    }
-
     /// Reinitialize voice
     fn restart(&mut self) {
         self.adsr.attack();
@@ -1345,6 +1537,12 @@ impl Voice {
     }
 }
 
+/// Scale `v` by the 1.15 fixed point `gain`. The sum of the voices can be well beyond 16 bits
+/// before it's saturated, hence the 64bit product.
+fn apply_gain(v: i32, gain: i32) -> i32 {
+    ((i64::from(v) * i64::from(gain)) >> 15) as i32
+}
+
 /// Saturating cast from i32 to i16
 pub fn saturate_to_i16(v: i32) -> i16 {
     if v < i32::from(i16::min_value()) {
@@ -1919,12 +2117,197 @@ mod regmap {
     pub const REVERB_INPUT_VOLUME_RIGHT: usize = 0xff;
 }
 
+/// Size of `Spu::audio_buffer`, in samples
+const AUDIO_BUFFER_LEN: usize = 2048;
+
 /// SPU RAM size in multiple of 16bit words
 const SPU_RAM_SIZE: usize = 256 * 1024;
 
 /// The SPU runs at 44.1kHz, the CD audio frequency, this way no resampling is required
-const AUDIO_FREQ_HZ: ClockCycle = 44_100;
+const AUDIO_FREQ_HZ: ClockCycle = audio::AUDIO_FREQ_HZ as ClockCycle;
 
 /// The CPU frequency is an exact multiple of the audio frequency, so the divider is always an
 /// integer (0x300 normally)
-const SPU_FREQ_DIVIDER: ClockCycle = cpu::CPU_FREQ_HZ / AUDIO_FREQ_HZ;
+pub(crate) const SPU_FREQ_DIVIDER: ClockCycle = cpu::CPU_FREQ_HZ / AUDIO_FREQ_HZ;
+
+/// Number of samples generated in one go when nothing requires a tighter synchronization
+pub(crate) const SPU_BLOCK_SAMPLES: ClockCycle = 32;
+
+#[cfg(test)]
+impl SpuBus for crate::ps1::psx::mock_bus::MockBus<Spu> {
+    fn spu(&mut self) -> &mut Spu {
+        &mut self.device
+    }
+
+    fn run_cd(&mut self) {}
+
+    fn cd_audio_sample(&mut self) -> [i16; 2] {
+        [0, 0]
+    }
+
+    fn cd_streaming_xa(&self) -> bool {
+        false
+    }
+
+    fn stats(&mut self) -> &mut FrameStats {
+        &mut self.stats
+    }
+}
+
+#[test]
+fn sample_timestamps() {
+    use crate::ps1::psx::mock_bus::MockBus;
+
+    let mut bus = MockBus::new(Spu::new());
+
+    // Not a multiple of the block size, the leftover samples must be generated anyway
+    bus.tick(100 * SPU_FREQ_DIVIDER);
+    let first = take_samples(&mut bus);
+    assert_eq!(first.timestamp, 0);
+    assert_eq!(first.frames(), 100);
+    assert_eq!(bus.stats.audio_samples, 100);
+
+    bus.tick(10 * SPU_FREQ_DIVIDER);
+    let second = take_samples(&mut bus);
+    assert_eq!(second.timestamp, 100);
+    assert_eq!(second.frames(), 10);
+}
+
+#[test]
+fn frozen_samples() {
+    use crate::ps1::psx::mock_bus::MockBus;
+
+    let mut bus = MockBus::new(Spu::new());
+
+    // What the CPU ran before the pause comes first
+    bus.tick(10 * SPU_FREQ_DIVIDER);
+    run_frozen(&mut bus, 100 * SPU_FREQ_DIVIDER);
+    let paused = take_samples(&mut bus);
+    assert_eq!(paused.timestamp, 0);
+    assert_eq!(paused.frames(), 110);
+
+    // The console didn't see the pause
+    bus.tick(5 * SPU_FREQ_DIVIDER);
+    let resumed = take_samples(&mut bus);
+    assert_eq!(resumed.timestamp, 110);
+    assert_eq!(resumed.frames(), 5);
+}
+
+#[test]
+fn transfer_irq() {
+    use crate::ps1::psx::mock_bus::MockBus;
+
+    let mut bus = MockBus::new(Spu::new());
+
+    // Interrupt at the word that follows a 4 halfword manual transfer (the addresses are in
+    // multiples of 8 bytes)
+    store(&mut bus, 0x1a4, 0x201u16);
+    // SPU enabled, IRQ enabled
+    store(&mut bus, 0x1aa, 0x8040u16);
+    store(&mut bus, 0x1a6, 0x200u16);
+
+    for v in 0..3 {
+        store(&mut bus, 0x1a8, v as u16);
+    }
+    assert!(!bus.irq_level(Interrupt::Spu));
+
+    // The address is checked again after the increment
+    store(&mut bus, 0x1a8, 3u16);
+    assert!(bus.take_irq(Interrupt::Spu));
+
+    // The status register is refreshed by the next cycle
+    bus.tick(SPU_FREQ_DIVIDER);
+    assert_ne!(load::<_, u16>(&mut bus, 0x1ae) & (1 << 6), 0);
+
+    // Acknowledged by disabling it in the control register
+    store(&mut bus, 0x1aa, 0x8000u16);
+    assert!(!bus.irq_level(Interrupt::Spu));
+}
+
+#[test]
+fn block_output() {
+    use crate::ps1::psx::mock_bus::MockBus;
+
+    const SAMPLES: ClockCycle = 1000;
+
+    /// "Room" reverb preset from the No$ documentation
+    const REVERB_ROOM: [u16; 32] = [
+        0x007d, 0x005b, 0x6d80, 0x54b8, 0xbed0, 0x0000, 0x0000, 0xba80, 0x5800, 0x5300, 0x04d6,
+        0x0333, 0x03f0, 0x0227, 0x0374, 0x01ef, 0x0334, 0x01b5, 0x0000, 0x0000, 0x0000, 0x0000,
+        0x0000, 0x0000, 0x0000, 0x0000, 0x01b4, 0x0136, 0x00b8, 0x005c, 0x8000, 0x8000,
+    ];
+
+    // Eight voices playing pseudo-random ADPCM through the reverb, two of them frequency modulated
+    // and one of them playing noise
+    fn playing() -> MockBus<Spu> {
+        let mut bus = MockBus::new(Spu::new());
+
+        // Enabled, unmuted, reverb enabled
+        store(&mut bus, 0x1aa, 0xc080u16);
+
+        // 16 ADPCM blocks at 0x1000, the last one loops back to the first
+        store(&mut bus, 0x1a6, 0x200u16);
+        let mut seed = 0x1234_5678u32;
+        for block in 0..16u16 {
+            let flags = match block {
+                0 => 4,
+                15 => 3,
+                _ => 0,
+            };
+            store(&mut bus, 0x1a8, (flags << 8) | ((block % 5) << 4) | (block % 13));
+
+            for _ in 0..7 {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                store(&mut bus, 0x1a8, (seed >> 16) as u16);
+            }
+        }
+
+        for voice in 0..8u32 {
+            let reg = |r: u32| voice * 16 + r * 2;
+
+            store(&mut bus, reg(0), 0x3fffu16);
+            store(&mut bus, reg(1), 0x2000u16);
+            // Step length: off 44.1kHz so that the interpolation is used
+            store(&mut bus, reg(2), 0x0800u16 + voice as u16 * 0x123);
+            store(&mut bus, reg(3), 0x200u16);
+            // ADSR: fast attack, slow decay, sustain forever
+            store(&mut bus, reg(4), 0x000fu16);
+            store(&mut bus, reg(5), 0x1fc0u16);
+        }
+
+        // Main and reverb volumes
+        for off in (0x180..0x188).step_by(2) {
+            store(&mut bus, off, 0x3fffu16);
+        }
+        // Frequency modulation, noise, reverb
+        store(&mut bus, 0x190, 0x000au16);
+        store(&mut bus, 0x194, 0x0080u16);
+        store(&mut bus, 0x198, 0x00ffu16);
+        // Reverb work area at the end of the RAM
+        store(&mut bus, 0x1a2, 0xe000u16);
+        for (i, &v) in REVERB_ROOM.iter().enumerate() {
+            store(&mut bus, 0x1c0 + i as u32 * 2, v);
+        }
+        // Start the voices
+        store(&mut bus, 0x188, 0x00ffu16);
+
+        bus
+    }
+
+    // All the samples in one go
+    let mut bus = playing();
+    bus.tick(SAMPLES * SPU_FREQ_DIVIDER);
+    let blocks = take_samples(&mut bus).samples;
+    assert!(blocks.iter().any(|&s| s != 0));
+
+    // One sample per event, like while the interrupt is enabled: the output must be bit-identical
+    let mut bus = playing();
+    for _ in 0..SAMPLES {
+        bus.tick(SPU_FREQ_DIVIDER);
+        run(&mut bus);
+    }
+    let single = take_samples(&mut bus).samples;
+
+    assert_eq!(single.len(), blocks.len());
+    assert!(single == blocks);
+}
@@ -1,5 +1,6 @@
+use std::mem;
 use crate::ps1::psx::bus::Bus;
-use crate::ps1::psx::{mdec, pad_memcard, timers};
+use crate::ps1::psx::{cd, mdec, pad_memcard, timers};
 use crate::ps1::psx::graphics::gpu;
 use crate::ps1::psx::memory::dma;
 use crate::ps1::psx::processor::ClockCycle;
@@ -12,6 +13,7 @@ pub enum SyncToken {
     Timers,
     Spu,
     Dma,
+    CdRom,
     PadMemCard,
     MDec,
 
@@ -28,6 +30,9 @@ pub struct Synchronizer {
     first_event: ClockCycle,
 }
 
+/// Offset of `Synchronizer::first_event`, for the code generated by the dynarec
+pub(crate) const FIRST_EVENT_OFFSET: usize = mem::offset_of!(Synchronizer, first_event);
+
 impl Synchronizer {
     pub fn new() -> Synchronizer {
         Synchronizer {
@@ -47,6 +52,40 @@ impl Synchronizer {
     }
 }
 
+/// The synchronizer as the peripherals use it, to catch up with the CPU and schedule their next
+/// event. Implemented by the `Bus`, and by the mock bus the peripherals are unit tested with.
+pub trait Scheduler {
+    /// Current date, in CPU cycles
+    fn now(&self) -> ClockCycle;
+
+    /// See `resync`
+    fn resync(&mut self, who: SyncToken) -> ClockCycle;
+
+    /// See `rewind`
+    fn rewind(&mut self, who: SyncToken, cycles: ClockCycle);
+
+    /// See `next_event`
+    fn next_event(&mut self, who: SyncToken, delay: ClockCycle);
+}
+
+impl Scheduler for Bus {
+    fn now(&self) -> ClockCycle {
+        self.cycles
+    }
+
+    fn resync(&mut self, who: SyncToken) -> ClockCycle {
+        resync(self, who)
+    }
+
+    fn rewind(&mut self, who: SyncToken, cycles: ClockCycle) {
+        rewind(self, who, cycles)
+    }
+
+    fn next_event(&mut self, who: SyncToken, delay: ClockCycle) {
+        next_event(self, who, delay)
+    }
+}
+
 /// Resynchronize `who` with the CPU, returning the number of CPU cycles elapsed since the last
 /// sync date
 pub fn resync(bus: &mut Bus, who: SyncToken) -> ClockCycle {
@@ -93,6 +132,7 @@ pub fn rewind(bus: &mut Bus, who: SyncToken, rewind: ClockCycle) {
 }
 
 /// Returns true if an event is pending and should be treated
+#[inline]
 pub fn is_event_pending(bus: &Bus) -> bool {
     bus.cycles >= bus.sync.first_event
 }
@@ -120,6 +160,10 @@ pub fn handle_events(bus: &mut Bus) {
             dma::run(bus);
         }
 
+        if bus.sync.first_event >= bus.sync.next_event[SyncToken::CdRom as usize] {
+            cd::run(bus);
+        }
+
         // SPU sync must come after CDROM since we could be playing back CD audio and we don't want
         // to starve
         if bus.sync.first_event >= bus.sync.next_event[SyncToken::Spu as usize] {
@@ -8,7 +8,7 @@
 use std::cmp::min;
 use std::ops::{Index, IndexMut};
 use log::warn;
-use crate::ps1::psx::addressable::Addressable;
+use mips_r3000::addressable::Addressable;
 use crate::ps1::psx::bus::Bus;
 use crate::ps1::psx::processor::{irq, ClockCycle};
 use crate::ps1::psx::sync;
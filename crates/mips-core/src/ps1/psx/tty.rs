@@ -2,27 +2,67 @@ use log::info;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
-pub struct Tty(String);
+pub struct Tty {
+    line: String,
+    /// Completed lines kept for `take_lines`, `None` when they're only logged
+    captured: Option<Vec<String>>,
+}
 
 impl Tty {
     pub fn new() -> Tty {
-        Tty(String::new())
+        Tty {
+            line: String::new(),
+            captured: None,
+        }
     }
-    
+
     pub fn push_char(&mut self, c: char) {
         match c {
             '\n' => {
-                if !self.0.is_empty() {
-                    info!("TTY output: {}", self.0);
+                if !self.line.is_empty() {
+                    info!("TTY output: {}", self.line);
+                }
+                if let Some(captured) = &mut self.captured {
+                    captured.push(self.line.clone());
                 }
                 self.clear();
             },
             '\r' => {},
-            _ => self.0.push(c)
+            _ => self.line.push(c)
+        }
+    }
+
+    /// Keep the lines output from now on for `take_lines`. Retail BIOSes don't send the
+    /// kernel's standard output to the expansion port, so the `putchar` calls are caught too
+    /// while capturing.
+    pub fn set_capture(&mut self, capture: bool) {
+        self.captured = capture.then(Vec::new);
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    /// Lines output since the last call
+    pub fn take_lines(&mut self) -> Vec<String> {
+        self.captured.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Called before each instruction while capturing: the BIOS functions are called by jumping
+    /// to 0xa0 or 0xb0 with the function number in T1, `putchar` is A(3Ch) and B(3Dh)
+    pub fn check_bios_call(&mut self, pc: u32, t1: u32, a0: u32) {
+        let putchar = match pc & 0x1fff_ffff {
+            0xa0 => t1 == 0x3c,
+            0xb0 => t1 == 0x3d,
+            _ => false,
+        };
+
+        if putchar {
+            self.push_char(a0 as u8 as char);
         }
     }
-    
+
     fn clear(&mut self) {
-        self.0.clear();
+        self.line.clear();
     }
-}
\ No newline at end of file
+}
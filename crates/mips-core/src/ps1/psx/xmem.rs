@@ -1,12 +1,14 @@
 //! Optimized data structure holding the parts of the PSX address space that can contain executable
 //! code.
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::cheats::CheatMemory;
 use crate::error::{MipsError, MipsResult};
 use crate::ps1::hash::sha::sha256;
 use crate::ps1::Ps1Error;
-use crate::ps1::psx::addressable::{AccessWidth, Addressable};
+use mips_r3000::addressable::{AccessWidth, Addressable};
 use crate::ps1::psx::bios::bios::BIOS_SIZE;
-use crate::ps1::psx::processor::cpu;
+use mips_r3000::instruction::Instruction;
 use crate::ps1::util::ds::box_slice::BoxSlice;
 
 /// This structure manages all executable portions of memory and offers fast lookup to speed up
@@ -29,6 +31,10 @@ pub struct XMemory {
     offset_lut: [u8; PAGE_COUNT],
     /// BIOS SHA-256, used to make sure that we load the same BIOS when restoring the savestate
     bios_sha256: [u8; 32],
+    /// One bit per RAM code page, set for the pages the dynarec translated code from
+    code_pages: [u64; CODE_PAGE_COUNT / 64],
+    /// Code pages written to since the last `drain_invalidated_code`
+    invalidated_code: Vec<u32>,
 }
 
 impl XMemory {
@@ -40,6 +46,8 @@ impl XMemory {
             memory: BoxSlice::from_vec(vec![0xffff_ffff; (PAGE_SIZE_BYTES * 3) >> 2]),
             offset_lut: [MemoryPage::Bad as u8; PAGE_COUNT],
             bios_sha256: [0; 32],
+            code_pages: [0; CODE_PAGE_COUNT / 64],
+            invalidated_code: Vec::new(),
         };
 
         // Remap executable pages
@@ -82,6 +90,14 @@ impl XMemory {
         self.bios_sha256 = sha256(bios);
     }
 
+    /// Replace the word at `offset` in the BIOS. The SHA-256 is left as it is, the savestates
+    /// still load with the original dump.
+    pub fn patch_bios(&mut self, offset: u32, word: u32) {
+        let bios_base = (MemoryPage::Bios as u32) << PAGE_SHIFT;
+
+        self.store(bios_base + offset, word);
+    }
+
     /// Copy the BIOS from another XMemory instance, returning an error if the checksums differ
     pub fn copy_bios(&mut self, source: &XMemory) -> MipsResult<()> {
         let bios_base = ((MemoryPage::Bios as usize) << PAGE_SHIFT) / 4;
@@ -105,6 +121,7 @@ impl XMemory {
     }
 
     /// Fetch data from memory at `offset`
+    #[inline]
     fn load<T: Addressable>(&self, offset: u32) -> T {
         let offset = offset as usize;
 
@@ -128,6 +145,7 @@ impl XMemory {
     }
 
     /// Store data to memory at `offset`
+    #[inline]
     fn store<T: Addressable>(&mut self, offset: u32, val: T) {
         let offset = offset as usize;
 
@@ -159,6 +177,7 @@ impl XMemory {
     }
 
     /// Read from RAM at `offset`
+    #[inline]
     pub fn ram_load<T: Addressable>(&self, offset: u32) -> T {
         let ram_base = (MemoryPage::Ram as u32) << PAGE_SHIFT;
 
@@ -170,6 +189,7 @@ impl XMemory {
     }
 
     /// Write `val` to RAM at `offset`
+    #[inline]
     pub fn ram_store<T: Addressable>(&mut self, offset: u32, val: T) {
         let ram_base = (MemoryPage::Ram as u32) << PAGE_SHIFT;
 
@@ -177,15 +197,66 @@ impl XMemory {
         // address space
         let offset = offset & 0x1f_ffff;
 
+        self.invalidate_code(offset);
         self.store(ram_base + offset, val);
     }
 
+    /// Copy of the whole RAM
+    pub fn ram(&self) -> Vec<u8> {
+        let ram_base = ((MemoryPage::Ram as usize) << PAGE_SHIFT) / 4;
+
+        self.memory[ram_base..ram_base + RAM_SIZE_WORDS].iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
     pub fn ram_store_block(&mut self, offset: u32, block: &[u8], size: usize) {
         let ram_base = (MemoryPage::Ram as u32) << PAGE_SHIFT;
         let offset = offset & 0x1f_ffff;
+
+        let last = (offset + size as u32).saturating_sub(1);
+        for page in (offset >> CODE_PAGE_SHIFT)..=(last >> CODE_PAGE_SHIFT) {
+            self.invalidate_code(page << CODE_PAGE_SHIFT);
+        }
         self.store_block_u8(ram_base + offset, block, size);
     }
 
+    /// Mark the RAM code page containing `offset` as translated by the dynarec, so that writes to
+    /// it are reported by `drain_invalidated_code`
+    pub fn mark_code(&mut self, offset: u32) {
+        let page = ((offset & 0x1f_ffff) >> CODE_PAGE_SHIFT) as usize;
+
+        self.code_pages[page / 64] |= 1 << (page % 64);
+    }
+
+    /// Report a write to the RAM at `offset` if it hits a code page. The page stops being tracked
+    /// until it's marked again.
+    #[inline]
+    fn invalidate_code(&mut self, offset: u32) {
+        let page = ((offset & 0x1f_ffff) >> CODE_PAGE_SHIFT) as usize;
+        let bit = 1 << (page % 64);
+
+        if self.code_pages[page / 64] & bit != 0 {
+            self.code_pages[page / 64] &= !bit;
+            self.invalidated_code.push(page as u32);
+        }
+    }
+
+    /// True if code pages were written to since the last `drain_invalidated_code`
+    #[inline]
+    pub fn has_invalidated_code(&self) -> bool {
+        !self.invalidated_code.is_empty()
+    }
+
+    /// Code pages written to since the last call, as page numbers
+    pub fn drain_invalidated_code(&mut self) -> std::vec::Drain<'_, u32> {
+        self.invalidated_code.drain(..)
+    }
+
+    /// Stop tracking all the code pages
+    pub fn clear_code_pages(&mut self) {
+        self.code_pages = [0; CODE_PAGE_COUNT / 64];
+        self.invalidated_code.clear();
+    }
+
     fn store_block_u8(&mut self, offset: u32, block: &[u8], size: usize) {
         let idx = (offset / 4) as usize;
 
@@ -208,7 +279,8 @@ impl XMemory {
     }
 
     /// Fetch instruction at absolute address `addr`
-    pub fn load_instruction(&self, addr: u32) -> cpu::Instruction {
+    #[inline]
+    pub fn load_instruction(&self, addr: u32) -> Instruction {
         let page = addr >> PAGE_SHIFT;
 
         let mem_page = self.offset_lut[page as usize] as u32;
@@ -222,7 +294,47 @@ impl XMemory {
 
         let word = self.memory[offset as usize];
 
-        cpu::Instruction::new(word)
+        Instruction::new(word)
+    }
+}
+
+/// Savestates only keep the RAM and the hash of the BIOS: the BIOS itself is copied from the
+/// running console with `copy_bios` when one is loaded
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedXMemory {
+    ram: BoxSlice<u32, RAM_SIZE_WORDS>,
+    bios_sha256: [u8; 32],
+}
+
+impl Serialize for XMemory {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let ram_base = ((MemoryPage::Ram as usize) << PAGE_SHIFT) / 4;
+
+        let s = SerializedXMemory {
+            ram: BoxSlice::from_vec(self.memory[ram_base..ram_base + RAM_SIZE_WORDS].to_vec()),
+            bios_sha256: self.bios_sha256,
+        };
+
+        s.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for XMemory {
+    fn deserialize<D>(deserializer: D) -> Result<XMemory, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = SerializedXMemory::deserialize(deserializer)?;
+        let ram_base = ((MemoryPage::Ram as usize) << PAGE_SHIFT) / 4;
+
+        let mut xmem = XMemory::new();
+        xmem.memory[ram_base..ram_base + RAM_SIZE_WORDS].copy_from_slice(&s.ram[..]);
+        xmem.bios_sha256 = s.bios_sha256;
+
+        Ok(xmem)
     }
 }
 
@@ -234,6 +346,24 @@ enum MemoryPage {
     Bad = 2,
 }
 
+impl CheatMemory for XMemory {
+    fn read8(&self, addr: u32) -> u8 {
+        self.ram_load(addr)
+    }
+
+    fn read16(&self, addr: u32) -> u16 {
+        self.ram_load(addr)
+    }
+
+    fn write8(&mut self, addr: u32, val: u8) {
+        self.ram_store(addr, val)
+    }
+
+    fn write16(&mut self, addr: u32, val: u16) {
+        self.ram_store(addr, val)
+    }
+}
+
 /// Defines how big each cache page will be (log2 since it's a shift value).
 ///
 /// I use 2MB since it's the largest usable size: anything bigger won't let us address the RAM
@@ -254,10 +384,16 @@ const PAGE_COUNT: usize = 1 << (32 - PAGE_SHIFT);
 const REGION_OFFSETS: [u32; 3] = [0x0000_0000, 0x8000_0000, 0xa000_0000];
 
 /// System RAM: 2MB
-const RAM_SIZE: usize = 2 * 1024 * 1024;
+pub const RAM_SIZE: usize = 2 * 1024 * 1024;
 
 /// RAM size in number of 32bit words
 const RAM_SIZE_WORDS: usize = RAM_SIZE / 4;
 
+/// Granularity of the dynarec code invalidation (log2), 4KiB like the instruction cache tags
+pub const CODE_PAGE_SHIFT: u32 = 12;
+
+/// Number of code pages in the RAM
+pub const CODE_PAGE_COUNT: usize = RAM_SIZE >> CODE_PAGE_SHIFT;
+
 /// Total size of the memory buffer, in 32bit words
 const MEMORY_SIZE: usize = (PAGE_SIZE_BYTES * 3) >> 2;
@@ -0,0 +1,264 @@
+//! Savestate format: a header followed by the serialized `Bus` (flexbuffers), DEFLATE compressed.
+//! The BIOS, the CDC firmware and the disc image aren't part of it, they're taken from the console
+//! the state is loaded into. Rewind snapshots have a magic of their own and aren't compressed.
+//!
+//! The states saved with a picture of the screen have the thumbnail right after the header,
+//! uncompressed: its width and height (16 bits each) and its RGB888 pixels. The frontends show it
+//! without inflating the rest.
+
+use std::io::Read;
+#[cfg(feature = "std")]
+use std::thread;
+use serde::{Deserialize, Serialize};
+use crate::error::MipsResult;
+use crate::gfx::{CpuFrame, PixelFormat};
+use crate::ps1::Ps1Error;
+use crate::ps1::psx::bus::Bus;
+
+const MAGIC: &[u8; 8] = b"MIPSPS1\0";
+const RAW_MAGIC: &[u8; 8] = b"MIPSPS1R";
+/// Compressed state preceded by a thumbnail
+const THUMBNAIL_MAGIC: &[u8; 8] = b"MIPSPS1T";
+/// Bumped whenever a change to the emulated state makes the older savestates unusable
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = MAGIC.len() + 4;
+/// Size of the thumbnails, whatever the resolution of the game: 4:3 like the screen
+pub const THUMBNAIL_WIDTH: u32 = 160;
+pub const THUMBNAIL_HEIGHT: u32 = 120;
+/// States are saved while the game runs, speed matters more than size
+const COMPRESSION_LEVEL: u8 = 1;
+/// Way more than a state takes (about 5MiB), so that a corrupted file can't use up the memory
+const MAX_STATE_SIZE: usize = 64 * 1024 * 1024;
+/// The buffers of the CD controller are copied around on the stack while they're deserialized,
+/// which takes more than the 1MiB of the main thread on Windows (several MiB in debug builds)
+#[cfg(feature = "std")]
+const DESERIALIZE_STACK_SIZE: usize = 32 * 1024 * 1024;
+
+/// `thumbnail`, downscaled to `THUMBNAIL_WIDTH` x `THUMBNAIL_HEIGHT`, is saved along if there's one
+pub fn serialize(bus: &Bus, thumbnail: Option<&CpuFrame>) -> MipsResult<Vec<u8>> {
+    let mut fb = flexbuffers::FlexbufferSerializer::new();
+    bus.serialize(&mut fb)?;
+
+    let mut state = Vec::with_capacity(HEADER_SIZE + fb.view().len() / 4);
+    write_header(&mut state, thumbnail);
+    state.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(fb.view(), COMPRESSION_LEVEL));
+
+    Ok(state)
+}
+
+/// Uncompressed savestate, for the rewind buffer: quicker to make, and the snapshots of
+/// consecutive frames differ in few bytes
+pub fn serialize_raw(bus: &Bus) -> MipsResult<Vec<u8>> {
+    let mut fb = flexbuffers::FlexbufferSerializer::new();
+    bus.serialize(&mut fb)?;
+
+    let mut state = Vec::with_capacity(HEADER_SIZE + fb.view().len());
+    state.extend_from_slice(RAW_MAGIC);
+    state.extend_from_slice(&VERSION.to_le_bytes());
+    state.extend_from_slice(fb.view());
+
+    Ok(state)
+}
+
+/// Compressed copy of a savestate made by `serialize_raw`, the same as `serialize` would have
+/// made with `thumbnail`. Compressed states are returned as they are.
+pub fn compress(state: &[u8], thumbnail: Option<&CpuFrame>) -> MipsResult<Vec<u8>> {
+    let not_a_savestate = || crate::MipsError::from(Ps1Error::BadSavestate("not a savestate".to_string()));
+
+    if state.len() < HEADER_SIZE {
+        return Err(not_a_savestate());
+    }
+    match &state[..MAGIC.len()] {
+        magic if magic == MAGIC || magic == THUMBNAIL_MAGIC => return Ok(state.to_vec()),
+        magic if magic == RAW_MAGIC => (),
+        _ => return Err(not_a_savestate()),
+    }
+
+    let mut compressed = Vec::with_capacity(HEADER_SIZE + state.len() / 4);
+    write_header(&mut compressed, thumbnail);
+    compressed[MAGIC.len()..HEADER_SIZE].copy_from_slice(&state[MAGIC.len()..HEADER_SIZE]);
+    compressed.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(&state[HEADER_SIZE..], COMPRESSION_LEVEL));
+
+    Ok(compressed)
+}
+
+/// Errors out if `state` isn't a savestate made by this version of the emulator
+pub fn deserialize(state: &[u8]) -> MipsResult<Bus> {
+    let bad = |reason: String| Ps1Error::BadSavestate(reason);
+
+    if state.len() < HEADER_SIZE {
+        return Err(bad("not a savestate".to_string()).into());
+    }
+    let (compressed, thumbnail) = match &state[..MAGIC.len()] {
+        magic if magic == MAGIC => (true, false),
+        magic if magic == THUMBNAIL_MAGIC => (true, true),
+        magic if magic == RAW_MAGIC => (false, false),
+        _ => return Err(bad("not a savestate".to_string()).into()),
+    };
+
+    let version = u32::from_le_bytes(state[MAGIC.len()..HEADER_SIZE].try_into().unwrap());
+    if version != VERSION {
+        return Err(bad(format!("version {} is not supported (expected {})", version, VERSION)).into());
+    }
+
+    let mut body = &state[HEADER_SIZE..];
+    if thumbnail {
+        read_thumbnail(&mut body)?;
+    }
+
+    let inflated;
+    let fb = if compressed {
+        inflated = miniz_oxide::inflate::decompress_to_vec_with_limit(body, MAX_STATE_SIZE)
+            .map_err(|e| bad(format!("decompression failed: {}", e)))?;
+        inflated.as_slice()
+    } else {
+        body
+    };
+
+    let deserialize = || {
+        let reader = flexbuffers::Reader::get_root(fb).map_err(|e| e.to_string())?;
+        Bus::deserialize(reader).map_err(|e| e.to_string())
+    };
+
+    // WebAssembly has no threads, its stack size is set when linking. So do the hosts of the
+    // builds without `std`.
+    #[cfg(feature = "std")]
+    let bus = if cfg!(target_family = "wasm") {
+        deserialize()
+    } else {
+        thread::scope(|s| {
+            thread::Builder::new()
+                .name("savestate".to_string())
+                .stack_size(DESERIALIZE_STACK_SIZE)
+                .spawn_scoped(s, deserialize)
+                .unwrap()
+                .join()
+                .unwrap()
+        })
+    };
+    #[cfg(not(feature = "std"))]
+    let bus = deserialize();
+
+    bus.map_err(|e| bad(e).into())
+}
+
+/// Thumbnail of the savestate `reader` starts with, `None` if it was saved without one. Only the
+/// beginning of the state is read.
+pub fn thumbnail(reader: &mut impl Read) -> MipsResult<Option<CpuFrame>> {
+    let bad = || Ps1Error::BadSavestate("not a savestate".to_string());
+
+    let mut header = [0; HEADER_SIZE];
+    reader.read_exact(&mut header).map_err(|_| bad())?;
+    match &header[..MAGIC.len()] {
+        magic if magic == THUMBNAIL_MAGIC => Ok(Some(read_thumbnail(reader)?)),
+        magic if magic == MAGIC || magic == RAW_MAGIC => Ok(None),
+        _ => Err(bad().into()),
+    }
+}
+
+fn write_header(state: &mut Vec<u8>, thumbnail: Option<&CpuFrame>) {
+    let Some(frame) = thumbnail.filter(|frame| frame.width > 0 && frame.height > 0) else {
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&VERSION.to_le_bytes());
+        return;
+    };
+
+    state.extend_from_slice(THUMBNAIL_MAGIC);
+    state.extend_from_slice(&VERSION.to_le_bytes());
+
+    let thumbnail = frame.downscale(THUMBNAIL_WIDTH.min(frame.width), THUMBNAIL_HEIGHT.min(frame.height));
+    state.extend_from_slice(&(thumbnail.width as u16).to_le_bytes());
+    state.extend_from_slice(&(thumbnail.height as u16).to_le_bytes());
+    for pixel in thumbnail.pixels {
+        state.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+    }
+}
+
+/// Read the thumbnail section that follows the header
+fn read_thumbnail(reader: &mut impl Read) -> MipsResult<CpuFrame> {
+    let bad = |reason: &str| Ps1Error::BadSavestate(format!("broken thumbnail: {}", reason));
+
+    let mut size = [0; 4];
+    reader.read_exact(&mut size).map_err(|_| bad("truncated"))?;
+    let width = u32::from(u16::from_le_bytes([size[0], size[1]]));
+    let height = u32::from(u16::from_le_bytes([size[2], size[3]]));
+    if width > THUMBNAIL_WIDTH || height > THUMBNAIL_HEIGHT {
+        return Err(bad(&format!("{}x{} is too large", width, height)).into());
+    }
+
+    let mut rgb = vec![0; (width * height * 3) as usize];
+    reader.read_exact(&mut rgb).map_err(|_| bad("truncated"))?;
+    let pixels = rgb
+        .chunks_exact(3)
+        .map(|c| (u32::from(c[0]) << 16) | (u32::from(c[1]) << 8) | u32::from(c[2]))
+        .collect();
+
+    Ok(CpuFrame { pixels, width, height, format: PixelFormat::Rgb888, aspect: None })
+}
+
+#[test]
+fn save_and_load() {
+    use crate::cheats::CheatMemory;
+    use crate::ps1::psx::bios::bios::Bios;
+    use crate::ps1::psx::cd::CdInterface;
+
+    // Same setup as the benchmarks: a dummy BIOS and a drive controller looping on `BRA *`
+    let new_bus = || {
+        let cd = CdInterface::new_without_firmware();
+        Box::new(Bus::with_cd(Bios::new_dummy(), cd))
+    };
+
+    let mut bus = new_bus();
+    bus.xmem.ram_store_block(0x1000, &[0xde, 0xad, 0xbe, 0xef], 4);
+    bus.update();
+    let cycles = bus.cycles;
+
+    let state = serialize(&bus, None).unwrap();
+
+    let mut other = new_bus();
+    other.load_state(deserialize(&state).unwrap()).unwrap();
+    assert_eq!(other.cycles, cycles);
+    assert_eq!(other.xmem.read8(0x1003), 0xef);
+
+    let raw = serialize_raw(&bus).unwrap();
+    let mut other = new_bus();
+    other.load_state(deserialize(&raw).unwrap()).unwrap();
+    assert_eq!(other.cycles, cycles);
+
+    assert_eq!(compress(&raw, None).unwrap(), state);
+    assert_eq!(compress(&state, None).unwrap(), state);
+
+    assert!(deserialize(&state[..HEADER_SIZE]).is_err());
+    assert!(deserialize(b"not a savestate").is_err());
+}
+
+#[test]
+fn thumbnails() {
+    use crate::ps1::psx::bios::bios::Bios;
+    use crate::ps1::psx::cd::CdInterface;
+
+    let bus = Box::new(Bus::with_cd(Bios::new_dummy(), CdInterface::new_without_firmware()));
+
+    // Left half red, right half blue
+    let frame = CpuFrame {
+        pixels: (0..640 * 480).map(|i| if i % 640 < 320 { 0xff0000 } else { 0x0000ff }).collect(),
+        width: 640,
+        height: 480,
+        format: PixelFormat::Rgb555,
+        aspect: None,
+    };
+
+    let state = serialize(&bus, Some(&frame)).unwrap();
+    let picture = thumbnail(&mut state.as_slice()).unwrap().unwrap();
+    assert_eq!((picture.width, picture.height), (THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT));
+    assert_eq!(picture.pixels[0], 0xff0000);
+    assert_eq!(picture.pixels[THUMBNAIL_WIDTH as usize - 1], 0x0000ff);
+
+    // The thumbnail doesn't get in the way of the state
+    assert!(deserialize(&state).is_ok());
+    let raw = serialize_raw(&bus).unwrap();
+    assert_eq!(compress(&raw, Some(&frame)).unwrap(), state);
+
+    assert!(thumbnail(&mut serialize(&bus, None).unwrap().as_slice()).unwrap().is_none());
+    assert!(thumbnail(&mut &state[..HEADER_SIZE + 10]).is_err());
+}
@@ -1,9 +1,158 @@
-use crate::ps1::settings::graphics::GraphicsSettings;
+use crate::compat::Quirks;
+use crate::ps1::settings::audio::Interpolation;
+use crate::ps1::settings::cpu::{CpuBackend, CpuClock};
+use crate::ps1::settings::graphics::{DebugRender, GraphicsSettings, InternalResolution, RendererBackend};
+use crate::ps1::settings::memcard::MemoryCardScope;
 
+pub mod audio;
+pub mod cpu;
 pub mod graphics;
+pub mod memcard;
 mod cd;
 
 #[derive(Default)]
 pub struct Ps1Settings {
     graphics: GraphicsSettings,
-}
\ No newline at end of file
+    cpu: CpuBackend,
+    cpu_clock: CpuClock,
+    interpolation: Interpolation,
+    memory_card_scope: MemoryCardScope,
+    fast_boot: bool,
+    /// Overrides of the game in the drive at power on, see `compat`
+    quirks: Quirks,
+}
+
+impl Ps1Settings {
+    pub fn cpu_backend(&self) -> CpuBackend {
+        self.cpu
+    }
+
+    pub fn set_cpu_backend(&mut self, backend: CpuBackend) {
+        self.cpu = backend;
+    }
+
+    pub fn cpu_clock(&self) -> CpuClock {
+        self.cpu_clock
+    }
+
+    pub fn set_cpu_clock(&mut self, clock: CpuClock) {
+        self.cpu_clock = clock;
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    pub fn memory_card_scope(&self) -> MemoryCardScope {
+        self.memory_card_scope
+    }
+
+    pub fn set_memory_card_scope(&mut self, scope: MemoryCardScope) {
+        self.memory_card_scope = scope;
+    }
+
+    pub fn fast_boot(&self) -> bool {
+        self.fast_boot
+    }
+
+    pub fn set_fast_boot(&mut self, enabled: bool) {
+        self.fast_boot = enabled;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Backend the CPU actually runs with, the one picked unless the game needs the interpreter
+    pub fn effective_cpu_backend(&self) -> CpuBackend {
+        if self.quirks.interpreter { CpuBackend::Interpreter } else { self.cpu }
+    }
+
+    pub fn internal_resolution(&self) -> InternalResolution {
+        self.graphics.internal_resolution()
+    }
+
+    pub fn set_internal_resolution(&mut self, resolution: InternalResolution) {
+        self.graphics.set_internal_resolution(resolution);
+    }
+
+    pub fn renderer(&self) -> RendererBackend {
+        self.graphics.renderer()
+    }
+
+    pub fn set_renderer(&mut self, renderer: RendererBackend) {
+        self.graphics.set_renderer(renderer);
+    }
+
+    pub fn pgxp(&self) -> bool {
+        self.graphics.pgxp()
+    }
+
+    pub fn set_pgxp(&mut self, enabled: bool) {
+        self.graphics.set_pgxp(enabled);
+    }
+
+    pub fn dithering(&self) -> bool {
+        self.graphics.dithering()
+    }
+
+    pub fn set_dithering(&mut self, enabled: bool) {
+        self.graphics.set_dithering(enabled);
+    }
+
+    pub fn true_color(&self) -> bool {
+        self.graphics.true_color()
+    }
+
+    pub fn set_true_color(&mut self, enabled: bool) {
+        self.graphics.set_true_color(enabled);
+    }
+
+    pub fn widescreen_hack(&self) -> bool {
+        self.graphics.widescreen_hack()
+    }
+
+    pub fn set_widescreen_hack(&mut self, enabled: bool) {
+        self.graphics.set_widescreen_hack(enabled);
+    }
+
+    pub fn accurate_transparency(&self) -> bool {
+        self.graphics.accurate_transparency()
+    }
+
+    pub fn set_accurate_transparency(&mut self, enabled: bool) {
+        self.graphics.set_accurate_transparency(enabled);
+    }
+
+    pub fn debug_render(&self) -> DebugRender {
+        self.graphics.debug_render()
+    }
+
+    pub fn set_debug_render(&mut self, debug: DebugRender) {
+        self.graphics.set_debug_render(debug);
+    }
+
+    pub fn overdraw_heatmap(&self) -> bool {
+        self.graphics.overdraw_heatmap()
+    }
+
+    pub fn set_overdraw_heatmap(&mut self, enabled: bool) {
+        self.graphics.set_overdraw_heatmap(enabled);
+    }
+
+    pub fn parallel_draw_areas(&self) -> bool {
+        self.graphics.parallel_draw_areas()
+    }
+
+    pub fn set_parallel_draw_areas(&mut self, enabled: bool) {
+        self.graphics.set_parallel_draw_areas(enabled);
+    }
+}
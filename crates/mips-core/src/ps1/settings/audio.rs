@@ -0,0 +1,24 @@
+/// How the SPU voices resample their ADPCM samples to their pitch
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Interpolation {
+    /// The last sample passed, as is: crisp, with the aliasing of the low pitched samples
+    Nearest,
+    /// The console's 4-tap gaussian filter, slightly muffled
+    #[default]
+    Gaussian,
+    /// Cubic (Catmull-Rom) spline through the 4 samples: smoother than the hardware, a bit
+    /// brighter
+    Cubic,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 3] = [Interpolation::Nearest, Interpolation::Gaussian, Interpolation::Cubic];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Interpolation::Nearest => "Nearest",
+            Interpolation::Gaussian => "Gaussian",
+            Interpolation::Cubic => "Cubic",
+        }
+    }
+}
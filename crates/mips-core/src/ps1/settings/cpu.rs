@@ -0,0 +1,64 @@
+/// How the CPU instructions are run
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum CpuBackend {
+    /// Decode and run the instructions one at a time
+    #[default]
+    Interpreter,
+    /// Translate the code to x86-64 machine code, a block at a time. Falls back to the interpreter
+    /// on other hosts.
+    Dynarec,
+}
+
+impl CpuBackend {
+    /// True if this backend can run on the host
+    pub fn is_available(self) -> bool {
+        match self {
+            CpuBackend::Interpreter => true,
+            CpuBackend::Dynarec => cfg!(all(target_arch = "x86_64", any(unix, windows))),
+        }
+    }
+}
+
+/// Speed of the CPU, in percent of the console's clock. Overclocking gives the games that slow down
+/// more instructions per frame, underclocking slows them down. The other chips keep their speed:
+/// the video, the sound and the drive stay in time.
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(from = "u16", into = "u16")]
+pub struct CpuClock(u16);
+
+impl CpuClock {
+    pub const NATIVE: CpuClock = CpuClock(100);
+    pub const MIN_PERCENT: u16 = 50;
+    pub const MAX_PERCENT: u16 = 300;
+
+    /// `percent` of the console's clock, clamped to `MIN_PERCENT..=MAX_PERCENT`
+    pub fn from_percent(percent: u16) -> CpuClock {
+        CpuClock(percent.clamp(Self::MIN_PERCENT, Self::MAX_PERCENT))
+    }
+
+    pub fn percent(self) -> u16 {
+        self.0
+    }
+
+    pub fn is_native(self) -> bool {
+        self == Self::NATIVE
+    }
+}
+
+impl Default for CpuClock {
+    fn default() -> CpuClock {
+        CpuClock::NATIVE
+    }
+}
+
+impl From<u16> for CpuClock {
+    fn from(percent: u16) -> CpuClock {
+        CpuClock::from_percent(percent)
+    }
+}
+
+impl From<CpuClock> for u16 {
+    fn from(clock: CpuClock) -> u16 {
+        clock.percent()
+    }
+}
@@ -1,44 +1,192 @@
+pub use mips_psx_gpu::settings::{RendererBackend, VRamDisplayMode};
+
 pub struct GraphicsSettings {
-    vram_display_mode: VRamDisplayMode
+    vram_display_mode: VRamDisplayMode,
+    internal_resolution: InternalResolution,
+    renderer: RendererBackend,
+    /// Subpixel precision of the vertices projected by the GTE, see `Pgxp`
+    pgxp: bool,
+    /// Ordered dithering of the shaded and blended draws, like the console
+    dithering: bool,
+    /// Keep the 8bit color components drawn instead of truncating them to 15bit RGB555
+    true_color: bool,
+    /// Blend the semi-transparent draws with the 15bit precision of the GPU
+    accurate_transparency: bool,
+    /// GTE widescreen hack, see `Gte::widescreen`
+    widescreen_hack: bool,
+    /// Show how many times each pixel was drawn instead of the picture
+    overdraw_heatmap: bool,
+    /// Rasterize the independent draw areas on threads of their own
+    parallel_draw_areas: bool,
+    debug_render: DebugRender,
 }
 
 impl Default for GraphicsSettings {
     fn default() -> GraphicsSettings {
         GraphicsSettings {
-            vram_display_mode: Default::default()
+            vram_display_mode: Default::default(),
+            internal_resolution: Default::default(),
+            renderer: Default::default(),
+            pgxp: false,
+            dithering: true,
+            true_color: false,
+            accurate_transparency: true,
+            widescreen_hack: false,
+            overdraw_heatmap: false,
+            parallel_draw_areas: false,
+            debug_render: DebugRender::default(),
         }
     }
 }
 
+impl GraphicsSettings {
+    pub fn internal_resolution(&self) -> InternalResolution {
+        self.internal_resolution
+    }
+
+    pub fn set_internal_resolution(&mut self, resolution: InternalResolution) {
+        self.internal_resolution = resolution;
+    }
+
+    pub fn renderer(&self) -> RendererBackend {
+        self.renderer
+    }
+
+    pub fn set_renderer(&mut self, renderer: RendererBackend) {
+        self.renderer = renderer;
+    }
+
+    pub fn pgxp(&self) -> bool {
+        self.pgxp
+    }
+
+    pub fn set_pgxp(&mut self, enabled: bool) {
+        self.pgxp = enabled;
+    }
+
+    pub fn dithering(&self) -> bool {
+        self.dithering
+    }
+
+    pub fn set_dithering(&mut self, enabled: bool) {
+        self.dithering = enabled;
+    }
+
+    pub fn true_color(&self) -> bool {
+        self.true_color
+    }
+
+    pub fn set_true_color(&mut self, enabled: bool) {
+        self.true_color = enabled;
+    }
+
+    pub fn accurate_transparency(&self) -> bool {
+        self.accurate_transparency
+    }
+
+    pub fn set_accurate_transparency(&mut self, enabled: bool) {
+        self.accurate_transparency = enabled;
+    }
+
+    pub fn widescreen_hack(&self) -> bool {
+        self.widescreen_hack
+    }
+
+    pub fn set_widescreen_hack(&mut self, enabled: bool) {
+        self.widescreen_hack = enabled;
+    }
+
+    pub fn overdraw_heatmap(&self) -> bool {
+        self.overdraw_heatmap
+    }
+
+    pub fn set_overdraw_heatmap(&mut self, enabled: bool) {
+        self.overdraw_heatmap = enabled;
+    }
+
+    pub fn parallel_draw_areas(&self) -> bool {
+        self.parallel_draw_areas
+    }
+
+    pub fn set_parallel_draw_areas(&mut self, enabled: bool) {
+        self.parallel_draw_areas = enabled;
+    }
+
+    pub fn debug_render(&self) -> DebugRender {
+        self.debug_render
+    }
+
+    pub fn set_debug_render(&mut self, debug: DebugRender) {
+        self.debug_render = debug;
+    }
+}
+
+/// Render modes leaving out parts of the drawing, to find which one a glitch comes from. The
+/// games see the same VRAM contents except for what's drawn.
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct DebugRender {
+    pub wireframe: Wireframe,
+    /// Draw the textured polygons and sprites with their vertex colors, shaded but flat
+    pub no_textures: bool,
+    /// Draw the semi-transparent draws opaque
+    pub no_semi_transparency: bool,
+}
+
+/// Outlines of the polygons: green for the triangles, blue for the quads with their diagonal in
+/// cyan
 #[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
-#[repr(u8)]
-pub enum VRamDisplayMode {
+pub enum Wireframe {
     #[default]
-    Native,
-    Full16bpp,
-    Full8bpp,
-    Full4bpp,
+    Off,
+    /// Over the polygons
+    Overlay,
+    /// Instead of the polygons, the sprites, lines and fills are still drawn
+    Only,
 }
 
-impl VRamDisplayMode {
-    pub fn max_res(self) -> (u16, u16) {
+impl Wireframe {
+    pub const ALL: [Wireframe; 3] = [Wireframe::Off, Wireframe::Overlay, Wireframe::Only];
+
+    pub fn name(self) -> &'static str {
         match self {
-            // Maximum resolution supported by the PlayStation video output is 640x576. That high a
-            // vertical resolution would mean no blanking however, so it doesn't make a lot of
-            // sense.
-            VRamDisplayMode::Native => (640, 480),
-            VRamDisplayMode::Full16bpp => (1024, 512),
-            VRamDisplayMode::Full8bpp => (2048, 512),
-            VRamDisplayMode::Full4bpp => (4096, 512),
+            Wireframe::Off => "Off",
+            Wireframe::Overlay => "Overlay",
+            Wireframe::Only => "Only",
         }
     }
+}
+
+/// Resolution the rasterizer draws at, as a multiple of the native one. Only the drawing is
+/// upscaled: the transfers to and from the VRAM stay at the native resolution, so games see the
+/// same VRAM contents.
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum InternalResolution {
+    #[default]
+    Native,
+    X2,
+    X4,
+    X8,
+}
+
+impl InternalResolution {
+    pub const ALL: [InternalResolution; 4] = [
+        InternalResolution::Native,
+        InternalResolution::X2,
+        InternalResolution::X4,
+        InternalResolution::X8,
+    ];
 
-    pub fn aspect_ratio(self) -> f32 {
+    /// Log2 of the multiplier, the shift applied to the native coordinates
+    pub fn upscale_shift(self) -> u8 {
         match self {
-            VRamDisplayMode::Native => 4. / 3.,
-            VRamDisplayMode::Full16bpp => 2. / 1.,
-            VRamDisplayMode::Full8bpp => 4. / 1.,
-            VRamDisplayMode::Full4bpp => 8. / 1.,
+            InternalResolution::Native => 0,
+            InternalResolution::X2 => 1,
+            InternalResolution::X4 => 2,
+            InternalResolution::X8 => 3,
         }
     }
-}
\ No newline at end of file
+
+    pub fn multiplier(self) -> u32 {
+        1 << self.upscale_shift()
+    }
+}
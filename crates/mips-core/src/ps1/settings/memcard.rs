@@ -0,0 +1,41 @@
+/// Memory card images the console starts with, in the `memcards` directory of the system
+/// directory. They're created when the game first saves.
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum MemoryCardScope {
+    /// `card1.mcr` and `card2.mcr` for all the games, like a console
+    #[default]
+    Shared,
+    /// A card named after the serial number of the disc in slot 1 (`SCUS-94163.mcr`), so that no
+    /// game runs out of blocks. Slot 2 keeps the shared card, to copy saves between games. The
+    /// discs of a multi-disc game have serial numbers of their own: the card is the one of the
+    /// disc the console started with.
+    PerSerial,
+}
+
+impl MemoryCardScope {
+    pub const ALL: [MemoryCardScope; 2] = [MemoryCardScope::Shared, MemoryCardScope::PerSerial];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryCardScope::Shared => "Shared",
+            MemoryCardScope::PerSerial => "Per serial number",
+        }
+    }
+
+    /// Image of the card in `slot` (0 or 1) when the disc in the drive is `serial`
+    pub fn file_name(self, slot: usize, serial: Option<&str>) -> String {
+        match (self, serial) {
+            (MemoryCardScope::PerSerial, Some(serial)) if slot == 0 => format!("{}.mcr", serial),
+            _ => format!("card{}.mcr", slot + 1),
+        }
+    }
+}
+
+#[test]
+fn card_file_names() {
+    assert_eq!(MemoryCardScope::Shared.file_name(0, Some("SCUS-94163")), "card1.mcr");
+    assert_eq!(MemoryCardScope::PerSerial.file_name(0, Some("SCUS-94163")), "SCUS-94163.mcr");
+    assert_eq!(MemoryCardScope::PerSerial.file_name(1, Some("SCUS-94163")), "card2.mcr");
+    // The BIOS alone and the PS-EXEs get the shared cards
+    assert_eq!(MemoryCardScope::PerSerial.file_name(0, None), "card1.mcr");
+}
@@ -1,2 +1,3 @@
+#[cfg(feature = "std")]
 pub mod fs;
 pub mod ds;
\ No newline at end of file
@@ -1,2 +1,3 @@
 pub mod sys_dir;
-pub mod file;
\ No newline at end of file
+pub mod file;
+pub mod zip;
\ No newline at end of file
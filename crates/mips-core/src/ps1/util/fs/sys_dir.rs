@@ -2,7 +2,6 @@ use std::fs::DirEntry;
 use std::path::{Path, PathBuf};
 use crate::error::*;
 use crate::ps1::error::Ps1Error;
-use crate::ps1::psx::bios::bios::BIOS_SIZE;
 use crate::ps1::psx::cd::CDC_ROM_SIZE;
 
 pub struct SysDir {
@@ -16,19 +15,30 @@ impl SysDir {
         }
     }
     
+    /// Where the BIOS, firmware and game directory are
+    pub fn roms_dir(&self) -> PathBuf {
+        self.root_dir.join("assets").join("roms")
+    }
+
+    /// Where the memory card images are kept, see `MemoryCardScope`
+    pub fn memcards_dir(&self) -> PathBuf {
+        self.root_dir.join("memcards")
+    }
+
+    /// Where the disc images that need converting are converted to
+    pub fn disc_cache_dir(&self) -> PathBuf {
+        self.root_dir.join("cache").join("discs")
+    }
+
     pub fn search(&self, searchFor: SearchFor) -> MipsResult<PathBuf> {
         let assets_dir = self.root_dir.join("assets");
-        let roms_dir = assets_dir.join("roms");
+        let roms_dir = self.roms_dir();
         let roms_path = roms_dir.as_path();
         let target_path = match searchFor {
             SearchFor::CdcFirmware => find(roms_path,|e| {
                 let md = e.metadata().unwrap();
                 return md.is_file() && md.len() == CDC_ROM_SIZE as u64;
             }),
-            SearchFor::Bios => find(roms_path, |e| {
-                let md = e.metadata().unwrap();
-                return md.is_file() && md.len() == BIOS_SIZE as u64;
-            }),
             SearchFor::Games => find(roms_path, |e| {
                 let md = e.metadata().unwrap();
                 return md.is_dir() && e.path().file_name().unwrap() == "games";
@@ -51,7 +61,9 @@ fn find<F>(path: &Path, valid_predicate: F) -> Option<PathBuf>
 where
     F: Fn(&DirEntry) -> bool
 {
-    let dir = ::std::fs::read_dir(path).unwrap();
+    let Ok(dir) = ::std::fs::read_dir(path) else {
+        return None;
+    };
     for entry in dir {
         let entry = entry.unwrap();
         let path = entry.path();
@@ -64,7 +76,6 @@ where
 }
 
 pub enum SearchFor {
-    Bios,
     CdcFirmware,
     Games,
     Executables,
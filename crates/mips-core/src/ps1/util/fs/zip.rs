@@ -0,0 +1,183 @@
+//! Just enough of the ZIP format to read the small files people keep their dumps in: stored and
+//! deflated entries, no ZIP64, no encryption.
+
+use std::fs;
+use std::path::Path;
+use crate::error::{MipsError, MipsResult};
+use crate::ps1::error::Ps1Error;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR_SIGNATURE: u32 = 0x0605_4b50;
+/// Size of the end of central directory record without its comment
+const END_OF_CENTRAL_DIR_LEN: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+pub struct ZipEntry {
+    pub name: String,
+    pub size: u32,
+    compressed_size: u32,
+    method: u16,
+    encrypted: bool,
+    local_header: u32,
+}
+
+pub struct ZipArchive {
+    data: Vec<u8>,
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipArchive {
+    pub fn open(path: &Path) -> MipsResult<ZipArchive> {
+        let data = fs::read(path)
+            .map_err(|_| Ps1Error::FileOrDirNotFound(path.display().to_string()))?;
+
+        ZipArchive::new(data)
+    }
+
+    pub fn new(data: Vec<u8>) -> MipsResult<ZipArchive> {
+        // The record is followed by a comment of up to 64KiB
+        let end = (0..=data.len().saturating_sub(END_OF_CENTRAL_DIR_LEN))
+            .rev()
+            .take(0x1_0000 + 1)
+            .find(|&pos| read_u32(&data, pos) == Some(END_OF_CENTRAL_DIR_SIGNATURE))
+            .ok_or_else(|| bad_archive("end of central directory not found"))?;
+
+        let count = read_u16(&data, end + 10).unwrap_or(0);
+        let mut pos = read_u32(&data, end + 16).unwrap_or(0) as usize;
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let header = data.get(pos..pos + 46).ok_or_else(|| bad_archive("truncated central directory"))?;
+            if read_u32(header, 0) != Some(CENTRAL_HEADER_SIGNATURE) {
+                return Err(bad_archive("bad central directory entry"));
+            }
+
+            let field16 = |offset| read_u16(header, offset).unwrap_or(0);
+            let field32 = |offset| read_u32(header, offset).unwrap_or(0);
+
+            let name_len = field16(28) as usize;
+            let extra_len = field16(30) as usize;
+            let comment_len = field16(32) as usize;
+            let name = data.get(pos + 46..pos + 46 + name_len).ok_or_else(|| bad_archive("truncated file name"))?;
+
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                size: field32(24),
+                compressed_size: field32(20),
+                method: field16(10),
+                encrypted: field16(8) & 1 != 0,
+                local_header: field32(42),
+            });
+
+            pos += 46 + name_len + extra_len + comment_len;
+        }
+
+        Ok(ZipArchive { data, entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// Decompressed contents of `entry`
+    pub fn read(&self, entry: &ZipEntry) -> MipsResult<Vec<u8>> {
+        if entry.encrypted {
+            return Err(bad_archive(&format!("{} is encrypted", entry.name)));
+        }
+
+        let header = entry.local_header as usize;
+        if read_u32(&self.data, header) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(bad_archive(&format!("bad local header for {}", entry.name)));
+        }
+        let name_len = read_u16(&self.data, header + 26).unwrap_or(0) as usize;
+        let extra_len = read_u16(&self.data, header + 28).unwrap_or(0) as usize;
+
+        let start = header + 30 + name_len + extra_len;
+        let compressed = self.data.get(start..start + entry.compressed_size as usize)
+            .ok_or_else(|| bad_archive(&format!("{} is truncated", entry.name)))?;
+
+        let data = match entry.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATED => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, entry.size as usize)
+                    .map_err(|e| bad_archive(&format!("{}: {}", entry.name, e)))?
+            }
+            method => {
+                return Err(bad_archive(&format!("{} uses unsupported compression method {}", entry.name, method)));
+            }
+        };
+
+        if data.len() != entry.size as usize {
+            return Err(bad_archive(&format!("{} has the wrong size", entry.name)));
+        }
+
+        Ok(data)
+    }
+}
+
+fn bad_archive(reason: &str) -> MipsError {
+    MipsError::from(Ps1Error::BadArchive(reason.to_string()))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+#[test]
+fn stored_and_deflated_entries() {
+    fn entry(archive: &mut Vec<u8>, central: &mut Vec<u8>, name: &str, method: u16, data: &[u8], size: usize) {
+        let offset = archive.len() as u32;
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&method.to_le_bytes());
+        fields.extend_from_slice(&[0; 8]); // time, date, crc
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(size as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        archive.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        archive.extend_from_slice(&[20, 0, 0, 0]);
+        archive.extend_from_slice(&fields);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(data);
+
+        central.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+        central.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+        central.extend_from_slice(&fields);
+        central.extend_from_slice(&[0; 10]); // comment len, disk, attributes
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+
+    let text = b"the quick brown fox jumps over the lazy dog ".repeat(20);
+    let deflated = miniz_oxide::deflate::compress_to_vec(&text, 6);
+
+    let mut archive = Vec::new();
+    let mut central = Vec::new();
+    entry(&mut archive, &mut central, "stored.txt", METHOD_STORED, b"hello", 5);
+    entry(&mut archive, &mut central, "dir/deflated.txt", METHOD_DEFLATED, &deflated, text.len());
+
+    let central_offset = archive.len() as u32;
+    let central_len = central.len() as u32;
+    archive.extend_from_slice(&central);
+    archive.extend_from_slice(&END_OF_CENTRAL_DIR_SIGNATURE.to_le_bytes());
+    archive.extend_from_slice(&[0, 0, 0, 0, 2, 0, 2, 0]);
+    archive.extend_from_slice(&central_len.to_le_bytes());
+    archive.extend_from_slice(&central_offset.to_le_bytes());
+    archive.extend_from_slice(&[0, 0]);
+
+    let zip = ZipArchive::new(archive).unwrap();
+    let names: Vec<&str> = zip.entries().iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["stored.txt", "dir/deflated.txt"]);
+    assert_eq!(zip.read(&zip.entries()[0]).unwrap(), b"hello");
+    assert_eq!(zip.read(&zip.entries()[1]).unwrap(), text);
+
+    assert!(ZipArchive::new(b"not a zip".to_vec()).is_err());
+}
@@ -0,0 +1,197 @@
+//! RAM search: finds where a game keeps a value (health, gold, lives...) by narrowing the set of
+//! addresses down over several RAM snapshots, the way Cheat Engine does. Searches start from every
+//! address, or from the ones holding a known value, and each `narrow` keeps the addresses whose
+//! value passes a test against the value or the previous snapshot.
+//!
+//! Addresses are offsets in the RAM like the cheat codes, values are little endian and aligned to
+//! their size since the CPU can't access them otherwise.
+
+use crate::cheats::CheatCode;
+
+/// Size of the values searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSize {
+    U8,
+    U16,
+    U32,
+}
+
+impl ValueSize {
+    pub const ALL: [ValueSize; 3] = [ValueSize::U8, ValueSize::U16, ValueSize::U32];
+
+    pub fn bytes(self) -> usize {
+        match self {
+            ValueSize::U8 => 1,
+            ValueSize::U16 => 2,
+            ValueSize::U32 => 4,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueSize::U8 => "8 bits",
+            ValueSize::U16 => "16 bits",
+            ValueSize::U32 => "32 bits",
+        }
+    }
+
+    /// Value at `addr` in `ram`, 0 past the end
+    pub fn read(self, ram: &[u8], addr: u32) -> u32 {
+        let addr = addr as usize;
+        let Some(bytes) = ram.get(addr..addr + self.bytes()) else {
+            return 0;
+        };
+
+        bytes.iter().rev().fold(0, |v, &b| (v << 8) | u32::from(b))
+    }
+
+    /// Largest value that fits
+    pub fn max(self) -> u32 {
+        match self {
+            ValueSize::U8 => 0xff,
+            ValueSize::U16 => 0xffff,
+            ValueSize::U32 => 0xffff_ffff,
+        }
+    }
+}
+
+/// Test the value of an address has to pass to stay in the results. `Increased` and the other
+/// comparisons without a value compare with the previous snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Equal(u32),
+    NotEqual(u32),
+    Greater(u32),
+    Less(u32),
+    Increased,
+    Decreased,
+    Changed,
+    Unchanged,
+    IncreasedBy(u32),
+    DecreasedBy(u32),
+}
+
+impl Filter {
+    fn matches(self, value: u32, previous: u32) -> bool {
+        match self {
+            Filter::Equal(v) => value == v,
+            Filter::NotEqual(v) => value != v,
+            Filter::Greater(v) => value > v,
+            Filter::Less(v) => value < v,
+            Filter::Increased => value > previous,
+            Filter::Decreased => value < previous,
+            Filter::Changed => value != previous,
+            Filter::Unchanged => value == previous,
+            Filter::IncreasedBy(d) => value == previous.wrapping_add(d),
+            Filter::DecreasedBy(d) => value == previous.wrapping_sub(d),
+        }
+    }
+}
+
+pub struct RamSearch {
+    size: ValueSize,
+    /// RAM at the last `narrow`, for the comparisons with the previous values
+    snapshot: Vec<u8>,
+    /// Addresses still matching, sorted
+    results: Vec<u32>,
+}
+
+impl RamSearch {
+    /// Start a search over every address of `ram`, for a value that isn't known (a health bar)
+    pub fn new(ram: Vec<u8>, size: ValueSize) -> RamSearch {
+        let step = size.bytes();
+        let results = (0..ram.len().saturating_sub(step - 1)).step_by(step).map(|a| a as u32).collect();
+
+        RamSearch {
+            size,
+            snapshot: ram,
+            results,
+        }
+    }
+
+    /// Start a search over the 8bit values equal to `value`
+    pub fn search_u8(ram: Vec<u8>, value: u8) -> RamSearch {
+        RamSearch::search(ram, ValueSize::U8, value.into())
+    }
+
+    /// Start a search over the 16bit values equal to `value`
+    pub fn search_u16(ram: Vec<u8>, value: u16) -> RamSearch {
+        RamSearch::search(ram, ValueSize::U16, value.into())
+    }
+
+    /// Start a search over the 32bit values equal to `value`
+    pub fn search_u32(ram: Vec<u8>, value: u32) -> RamSearch {
+        RamSearch::search(ram, ValueSize::U32, value)
+    }
+
+    fn search(ram: Vec<u8>, size: ValueSize, value: u32) -> RamSearch {
+        let mut search = RamSearch::new(ram, size);
+
+        let snapshot = &search.snapshot;
+        search.results.retain(|&addr| size.read(snapshot, addr) == value);
+
+        search
+    }
+
+    /// Keep the addresses whose value in `ram` passes `filter`. `ram` becomes the snapshot the
+    /// next comparisons are made against.
+    pub fn narrow(&mut self, ram: Vec<u8>, filter: Filter) {
+        let size = self.size;
+        let previous = &self.snapshot;
+
+        self.results.retain(|&addr| filter.matches(size.read(&ram, addr), size.read(previous, addr)));
+        self.snapshot = ram;
+    }
+
+    pub fn size(&self) -> ValueSize {
+        self.size
+    }
+
+    /// Addresses still matching, sorted
+    pub fn results(&self) -> &[u32] {
+        &self.results
+    }
+
+    /// Value of `addr` in the last snapshot
+    pub fn previous(&self, addr: u32) -> u32 {
+        self.size.read(&self.snapshot, addr)
+    }
+
+    /// Codes constantly writing `value` at `addr`, for the cheat databases
+    pub fn cheat_codes(&self, addr: u32, value: u32) -> Vec<CheatCode> {
+        match self.size {
+            ValueSize::U8 => vec![CheatCode::Write8 { addr, val: value as u8 }],
+            ValueSize::U16 => vec![CheatCode::Write16 { addr, val: value as u16 }],
+            ValueSize::U32 => vec![
+                CheatCode::Write16 { addr, val: value as u16 },
+                CheatCode::Write16 { addr: addr + 2, val: (value >> 16) as u16 },
+            ],
+        }
+    }
+}
+
+#[test]
+fn narrow_down() {
+    let mut ram = vec![0u8; 64];
+    ram[8] = 100;
+    ram[12] = 100;
+    ram[20..24].copy_from_slice(&100u32.to_le_bytes());
+
+    let mut search = RamSearch::search_u16(ram.clone(), 100);
+    assert_eq!(search.results(), [8, 12, 20]);
+
+    // The health went down by 10 at 12 only
+    ram[8] = 100;
+    ram[12] = 90;
+    search.narrow(ram.clone(), Filter::Decreased);
+    assert_eq!(search.results(), [12]);
+    assert_eq!(search.previous(12), 90);
+
+    search.narrow(ram, Filter::Unchanged);
+    assert_eq!(search.results(), [12]);
+    assert_eq!(search.cheat_codes(12, 999), [CheatCode::Write16 { addr: 12, val: 999 }]);
+
+    // Unknown value: every aligned address
+    let search = RamSearch::new(vec![0; 64], ValueSize::U32);
+    assert_eq!(search.results().len(), 16);
+}
@@ -0,0 +1,236 @@
+//! Rewind: snapshots of the console taken every few frames while the game runs, to go back in
+//! time.
+//!
+//! Only the newest snapshot is kept whole. Each older one is stored as the XOR with the snapshot
+//! that followed it, DEFLATE compressed: two snapshots a few frames apart are mostly the same
+//! bytes, so the XOR is mostly zeroes and compresses to a few KiB. Going back one snapshot undoes
+//! the newest delta, and the oldest deltas can be dropped without touching the others.
+
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RewindSettings {
+    /// Frames between two snapshots, at least 1
+    pub interval: u32,
+    /// Memory the snapshots may take, in bytes. The oldest ones are dropped past it.
+    pub max_size: usize,
+}
+
+impl Default for RewindSettings {
+    fn default() -> RewindSettings {
+        RewindSettings {
+            interval: 4,
+            max_size: 64 << 20,
+        }
+    }
+}
+
+/// Snapshots are taken while the game runs, speed matters more than size
+const COMPRESSION_LEVEL: u8 = 1;
+
+/// Snapshot stored as the difference with the one that followed it
+struct Delta {
+    /// Frame the snapshot was taken on
+    frame: u64,
+    /// Length of the snapshot, the next one may be longer or shorter
+    len: usize,
+    /// Snapshot XOR the next one, compressed
+    data: Vec<u8>,
+}
+
+impl Delta {
+    /// Encode `state`, taken on `frame`, relative to `next`
+    fn new(frame: u64, state: &[u8], next: &[u8]) -> Delta {
+        Delta {
+            frame,
+            len: state.len(),
+            data: miniz_oxide::deflate::compress_to_vec(&xor(state, next), COMPRESSION_LEVEL),
+        }
+    }
+
+    /// Snapshot this delta was made from, given the one that followed it
+    fn apply(&self, next: &[u8]) -> Vec<u8> {
+        // The buffer made the data, it can't be corrupted
+        let diff = miniz_oxide::inflate::decompress_to_vec(&self.data)
+            .expect("Corrupted rewind snapshot");
+
+        let mut state = xor(&diff, next);
+        state.truncate(self.len);
+        state
+    }
+}
+
+/// `a` XOR `b`, the shortest one padded with zeroes
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+
+    let mut out = long.to_vec();
+    for (o, s) in out.iter_mut().zip(short) {
+        *o ^= s;
+    }
+    out
+}
+
+pub struct RewindBuffer {
+    settings: RewindSettings,
+    /// Newest snapshot and the frame it was taken on
+    newest: Option<(u64, Vec<u8>)>,
+    /// Older snapshots, oldest first
+    older: VecDeque<Delta>,
+    /// Bytes taken by `older`
+    older_size: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(settings: RewindSettings) -> RewindBuffer {
+        RewindBuffer {
+            settings: RewindSettings {
+                interval: settings.interval.max(1),
+                ..settings
+            },
+            newest: None,
+            older: VecDeque::new(),
+            older_size: 0,
+        }
+    }
+
+    pub fn settings(&self) -> RewindSettings {
+        self.settings
+    }
+
+    /// True if a snapshot is due on `frame`
+    pub fn wants_snapshot(&self, frame: u64) -> bool {
+        match &self.newest {
+            Some((newest, _)) => frame >= newest + u64::from(self.settings.interval) || frame < *newest,
+            None => true,
+        }
+    }
+
+    /// Add `state`, the snapshot of `frame`. Snapshots must be pushed in frame order, the ones
+    /// newer than `frame` are dropped.
+    pub fn push(&mut self, frame: u64, state: Vec<u8>) {
+        self.drop_newer(frame);
+
+        if let Some((prev_frame, prev)) = self.newest.take()
+            && prev_frame < frame
+        {
+            let delta = Delta::new(prev_frame, &prev, &state);
+            self.older_size += delta.data.len();
+            self.older.push_back(delta);
+        }
+        self.newest = Some((frame, state));
+
+        while self.size() > self.settings.max_size {
+            match self.older.pop_front() {
+                Some(delta) => self.older_size -= delta.data.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Newest snapshot taken on or before `frame` and the frame it was taken on, or the oldest
+    /// one if they're all newer. The snapshots newer than the one returned are dropped. `None`
+    /// if the buffer is empty.
+    pub fn rewind_to(&mut self, frame: u64) -> Option<(u64, &[u8])> {
+        self.drop_newer(frame);
+
+        self.newest.as_ref().map(|(frame, state)| (*frame, state.as_slice()))
+    }
+
+    /// Drop the snapshots taken after `frame`, keeping at least one
+    fn drop_newer(&mut self, frame: u64) {
+        loop {
+            match &self.newest {
+                Some((newest, state)) if *newest > frame => {
+                    let Some(delta) = self.older.pop_back() else {
+                        return;
+                    };
+                    self.older_size -= delta.data.len();
+                    self.newest = Some((delta.frame, delta.apply(state)));
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Number of snapshots
+    pub fn len(&self) -> usize {
+        self.older.len() + usize::from(self.newest.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Memory taken by the snapshots, in bytes
+    pub fn size(&self) -> usize {
+        self.older_size + self.newest.as_ref().map_or(0, |(_, state)| state.len())
+    }
+
+    /// Frames that can be rewound from `frame`
+    pub fn depth(&self, frame: u64) -> u64 {
+        let oldest = self.older.front().map(|d| d.frame).or(self.newest.as_ref().map(|(f, _)| *f));
+
+        oldest.map_or(0, |oldest| frame.saturating_sub(oldest))
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.older.clear();
+        self.older_size = 0;
+    }
+}
+
+#[test]
+fn rewind_snapshots() {
+    let snapshot = |frame: u64| -> Vec<u8> {
+        let mut state = vec![0x55; 4096 + frame as usize];
+        state[..8].copy_from_slice(&frame.to_le_bytes());
+        state
+    };
+
+    let mut buffer = RewindBuffer::new(RewindSettings { interval: 4, max_size: 1 << 20 });
+    for frame in (0..40).step_by(4) {
+        assert!(buffer.wants_snapshot(frame));
+        buffer.push(frame, snapshot(frame));
+        assert!(!buffer.wants_snapshot(frame + 1));
+    }
+    assert_eq!(buffer.len(), 10);
+    assert_eq!(buffer.depth(37), 37);
+
+    let (frame, state) = buffer.rewind_to(30).unwrap();
+    assert_eq!(frame, 28);
+    assert_eq!(state, snapshot(28));
+    assert_eq!(buffer.len(), 8);
+
+    // Going forward again replaces the snapshots that were rewound over
+    buffer.push(32, snapshot(32));
+    let (frame, state) = buffer.rewind_to(31).unwrap();
+    assert_eq!(frame, 28);
+    assert_eq!(state, snapshot(28));
+
+    let (frame, state) = buffer.rewind_to(0).unwrap();
+    assert_eq!(frame, 0);
+    assert_eq!(state, snapshot(0));
+    assert_eq!(buffer.len(), 1);
+}
+
+#[test]
+fn rewind_size_limit() {
+    let mut buffer = RewindBuffer::new(RewindSettings { interval: 1, max_size: 8192 });
+
+    // Incompressible snapshots, a delta takes about as much as the snapshot
+    let mut seed = 1u32;
+    for frame in 0..10 {
+        let state = (0..2048).map(|_| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 24) as u8
+        }).collect();
+        buffer.push(frame, state);
+    }
+
+    assert!(buffer.size() <= 8192);
+    let kept = buffer.len() as u64;
+    assert!(kept < 10);
+    assert_eq!(buffer.rewind_to(0).map(|(frame, _)| frame), Some(10 - kept));
+}
@@ -0,0 +1,187 @@
+//! Per-game startup scripts: RAM writes done once, as soon as the game executable is loaded. That's
+//! what no-CD fixes, uncensor patches or debug menu unlocks usually need.
+//!
+//! One command per line, addresses and values in hexadecimal. `#` and `;` start comments.
+//!
+//! ```text
+//! # Stop here if the game isn't the expected version
+//! check32 80012340 27bdffe8
+//! write8  80012345 ff
+//! write16 80012346 1234
+//! write32 80012348 00000000
+//! bytes   80100000 de ad be ef
+//! # Address, length, value
+//! fill    80100100 100 00
+//! ```
+
+use crate::cheats::CheatMemory;
+use crate::error::{MipsError, MipsResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptCommand {
+    /// Stop the script if the value at `addr` isn't `val`
+    Check { addr: u32, val: u32, width: u8 },
+    Write { addr: u32, val: u32, width: u8 },
+    Bytes { addr: u32, bytes: Vec<u8> },
+    Fill { addr: u32, len: u32, val: u8 },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StartupScript {
+    pub commands: Vec<ScriptCommand>,
+}
+
+impl StartupScript {
+    pub fn parse(text: &str) -> MipsResult<StartupScript> {
+        let mut commands = Vec::new();
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((&command, args)) = words.split_first() else {
+                continue;
+            };
+
+            let invalid = || MipsError::InvalidScript(format!("line {}: {}", n + 1, line.trim()));
+            let hex = |s: &str| u32::from_str_radix(s, 16).map_err(|_| invalid());
+            let byte = |s: &str| u8::from_str_radix(s, 16).map_err(|_| invalid());
+
+            let width = match command {
+                "check8" | "write8" => 8,
+                "check16" | "write16" => 16,
+                "check32" | "write32" => 32,
+                _ => 0,
+            };
+
+            let command = match (command, args) {
+                (c, &[addr, val]) if c.starts_with("check") && width != 0 => {
+                    ScriptCommand::Check { addr: hex(addr)?, val: hex(val)?, width }
+                }
+                (c, &[addr, val]) if c.starts_with("write") && width != 0 => {
+                    ScriptCommand::Write { addr: hex(addr)?, val: hex(val)?, width }
+                }
+                ("bytes", [addr, bytes @ ..]) if !bytes.is_empty() => ScriptCommand::Bytes {
+                    addr: hex(addr)?,
+                    bytes: bytes.iter().map(|b| byte(b)).collect::<MipsResult<_>>()?,
+                },
+                ("fill", &[addr, len, val]) => ScriptCommand::Fill {
+                    addr: hex(addr)?,
+                    len: hex(len)?,
+                    val: byte(val)?,
+                },
+                _ => return Err(invalid()),
+            };
+
+            if let ScriptCommand::Check { val, width, .. } | ScriptCommand::Write { val, width, .. } = command
+                && width < 32
+                && val >> width != 0
+            {
+                return Err(invalid());
+            }
+
+            commands.push(command);
+        }
+
+        Ok(StartupScript { commands })
+    }
+
+    /// Run the script, returns false if a check stopped it
+    pub fn run(&self, mem: &mut dyn CheatMemory) -> bool {
+        for command in &self.commands {
+            match *command {
+                ScriptCommand::Check { addr, val, width } => {
+                    if read(mem, ram_offset(addr), width) != val {
+                        return false;
+                    }
+                }
+                ScriptCommand::Write { addr, val, width } => write(mem, ram_offset(addr), val, width),
+                ScriptCommand::Bytes { addr, ref bytes } => {
+                    for (i, &b) in bytes.iter().enumerate() {
+                        mem.write8(ram_offset(addr.wrapping_add(i as u32)), b);
+                    }
+                }
+                ScriptCommand::Fill { addr, len, val } => {
+                    for i in 0..len {
+                        mem.write8(ram_offset(addr.wrapping_add(i)), val);
+                    }
+                }
+            }
+        }
+
+        true
+    }
+}
+
+/// The RAM is mirrored in all the regions
+fn ram_offset(addr: u32) -> u32 {
+    addr & 0x1f_ffff
+}
+
+fn read(mem: &dyn CheatMemory, addr: u32, width: u8) -> u32 {
+    match width {
+        8 => u32::from(mem.read8(addr)),
+        16 => u32::from(mem.read16(addr)),
+        _ => u32::from(mem.read16(addr)) | (u32::from(mem.read16(ram_offset(addr + 2))) << 16),
+    }
+}
+
+fn write(mem: &mut dyn CheatMemory, addr: u32, val: u32, width: u8) {
+    match width {
+        8 => mem.write8(addr, val as u8),
+        16 => mem.write16(addr, val as u16),
+        _ => {
+            mem.write16(addr, val as u16);
+            mem.write16(ram_offset(addr + 2), (val >> 16) as u16);
+        }
+    }
+}
+
+#[test]
+fn parse_and_run() {
+    struct Ram([u8; 32]);
+
+    impl CheatMemory for Ram {
+        fn read8(&self, addr: u32) -> u8 {
+            self.0[addr as usize]
+        }
+
+        fn read16(&self, addr: u32) -> u16 {
+            u16::from_le_bytes([self.0[addr as usize], self.0[addr as usize + 1]])
+        }
+
+        fn write8(&mut self, addr: u32, val: u8) {
+            self.0[addr as usize] = val;
+        }
+
+        fn write16(&mut self, addr: u32, val: u16) {
+            self.0[addr as usize..addr as usize + 2].copy_from_slice(&val.to_le_bytes());
+        }
+    }
+
+    let script = StartupScript::parse(
+        "# Test script\n\
+         check32 80000000 12345678\n\
+         write16 80000004 beef ; trailing comment\n\
+         write32 a0000008 cafebabe\n\
+         bytes 8000000c 01 02 03\n\
+         \n\
+         fill 00000010 4 ff\n",
+    )
+    .unwrap();
+    assert_eq!(script.commands.len(), 5);
+
+    let mut ram = Ram([0; 32]);
+    // Wrong version: nothing is written
+    assert!(!script.run(&mut ram));
+    assert!(ram.0.iter().all(|&b| b == 0));
+
+    ram.0[..4].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+    assert!(script.run(&mut ram));
+    assert_eq!(ram.read16(4), 0xbeef);
+    assert_eq!(ram.0[8..12], 0xcafe_babeu32.to_le_bytes());
+    assert_eq!(ram.0[12..16], [1, 2, 3, 0]);
+    assert_eq!(ram.0[16..21], [0xff, 0xff, 0xff, 0xff, 0]);
+
+    assert!(StartupScript::parse("write8 80000000 100").is_err());
+    assert!(StartupScript::parse("poke 80000000 1").is_err());
+}
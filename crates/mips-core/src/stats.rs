@@ -0,0 +1,30 @@
+/// What the console did during one call to `Console::update`, for performance overlays and bug
+/// reports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// CPU clock cycles emulated
+    pub cycles: u64,
+    /// Polygons, lines and rectangles sent to the GPU
+    pub draw_calls: u32,
+    /// Triangles and quads among the draw calls
+    pub polygons: u32,
+    /// Lines and polylines among the draw calls
+    pub lines: u32,
+    /// Rectangles (sprites) among the draw calls
+    pub sprites: u32,
+    /// VRAM fills, copies and uploads sent to the GPU
+    pub vram_transfers: u32,
+    /// Fills among the VRAM transfers, usually the screen being cleared
+    pub fills: u32,
+    /// Bytes moved by the DMA, all channels together
+    pub dma_bytes: u32,
+    /// Stereo pairs generated by the sound chip
+    pub audio_samples: u32,
+    /// Nothing was drawn, the frame shows the same picture as the previous one (30fps games do
+    /// this every other frame)
+    pub duplicated: bool,
+    /// Frames finished by the GPU and replaced by the next one before the frontend took them
+    pub dropped: u32,
+    /// Address of the instruction the CPU was running when the frame ended
+    pub pc: u32,
+}
@@ -0,0 +1,65 @@
+//! Where the console reads and writes the files it's given by path once it runs: the memory card
+//! images and the executable patches. With the `std` feature they're the files of the host by
+//! default. The hosts without a filesystem (console homebrew ports, handhelds, browsers) store
+//! them their own way with a `Vfs` of their own, see `ConsoleManager::set_vfs`.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Files by path, the paths are the ones the frontend gave to the console
+pub trait Vfs: Send + Sync {
+    /// Contents of the file `path`, an `io::ErrorKind::NotFound` error if there's none
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    /// Replace the contents of the file `path` with `data`, creating it if needed
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+}
+
+/// The files of the host. The directories are created as the files are written in them.
+#[cfg(feature = "std")]
+pub struct HostVfs;
+
+#[cfg(feature = "std")]
+impl Vfs for HostVfs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let metadata = std::fs::metadata(path)?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a file!"));
+        }
+
+        std::fs::read(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        std::fs::write(path, data)
+    }
+}
+
+/// No files at all: nothing is found and nothing can be written
+pub struct NoVfs;
+
+impl Vfs for NoVfs {
+    fn read(&self, _path: &Path) -> io::Result<Vec<u8>> {
+        Err(io::ErrorKind::NotFound.into())
+    }
+
+    fn write(&self, _path: &Path, _data: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "no filesystem"))
+    }
+}
+
+/// What the consoles use until they're given a `Vfs`: the host's files
+#[cfg(feature = "std")]
+pub fn host() -> Arc<dyn Vfs> {
+    Arc::new(HostVfs)
+}
+
+/// What the consoles use until they're given a `Vfs`: without `std` there are no files
+#[cfg(not(feature = "std"))]
+pub fn host() -> Arc<dyn Vfs> {
+    Arc::new(NoVfs)
+}
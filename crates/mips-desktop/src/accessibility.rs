@@ -0,0 +1,239 @@
+//! Accessibility: a high contrast theme and larger widgets for the UI, no animations, and color
+//! filters for color blind players. There's no shader chain for the game picture, the filter is a
+//! 3x3 matrix run over its pixels in linear light before they're uploaded.
+//!
+//! Screen readers see the menus through AccessKit, egui's widgets describe themselves. What's
+//! drawn by hand (icons, the notifications, the on-screen keyboard) is named or read out here.
+
+use std::sync::LazyLock;
+use egui::accesskit::{Live, Role};
+use egui::{Color32, Id, Response, Stroke, Style, Theme, Visuals, WidgetInfo, WidgetType};
+use crate::config::{AccessibilitySettings, ColorFilter};
+
+type Matrix = [[f32; 3]; 3];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0],
+];
+
+// Dichromat vision in linear RGB, Machado, Oliveira and Fernandes (2009) at full severity
+const PROTANOPIA: Matrix = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+const DEUTERANOPIA: Matrix = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+const TRITANOPIA: Matrix = [
+    [1.255528, -0.076749, -0.178779],
+    [-0.078411, 0.930809, 0.147602],
+    [0.004733, 0.691367, 0.303900],
+];
+
+/// Daltonization: the difference the missing cones can't see is added to the channels left
+const TO_GREEN_BLUE: Matrix = [
+    [0.0, 0.0, 0.0],
+    [0.7, 1.0, 0.0],
+    [0.7, 0.0, 1.0],
+];
+const TO_RED_GREEN: Matrix = [
+    [1.0, 0.0, 0.7],
+    [0.0, 1.0, 0.7],
+    [0.0, 0.0, 0.0],
+];
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    std::array::from_fn(|row| std::array::from_fn(|col| (0..3).map(|i| a[row][i] * b[i][col]).sum()))
+}
+
+/// original + shift * (original - simulated)
+fn daltonize(simulation: &Matrix, shift: &Matrix) -> Matrix {
+    let error: Matrix = std::array::from_fn(|row| std::array::from_fn(|col| IDENTITY[row][col] - simulation[row][col]));
+    let correction = mul(shift, &error);
+
+    std::array::from_fn(|row| std::array::from_fn(|col| IDENTITY[row][col] + correction[row][col]))
+}
+
+fn matrix(filter: ColorFilter) -> Option<Matrix> {
+    let matrix = match filter {
+        ColorFilter::Off => return None,
+        ColorFilter::SimulateProtanopia => PROTANOPIA,
+        ColorFilter::SimulateDeuteranopia => DEUTERANOPIA,
+        ColorFilter::SimulateTritanopia => TRITANOPIA,
+        ColorFilter::CorrectProtanopia => daltonize(&PROTANOPIA, &TO_GREEN_BLUE),
+        ColorFilter::CorrectDeuteranopia => daltonize(&DEUTERANOPIA, &TO_GREEN_BLUE),
+        ColorFilter::CorrectTritanopia => daltonize(&TRITANOPIA, &TO_RED_GREEN),
+    };
+
+    Some(matrix)
+}
+
+/// Steps of the linear light values converted back to sRGB, enough to tell the darkest sRGB
+/// values apart
+const LINEAR_STEPS: usize = 4095;
+
+struct Tables {
+    to_linear: [f32; 256],
+    to_srgb: Vec<u8>,
+}
+
+impl Tables {
+    fn encode(&self, linear: f32) -> u8 {
+        self.to_srgb[(linear.clamp(0.0, 1.0) * LINEAR_STEPS as f32).round() as usize]
+    }
+}
+
+static TABLES: LazyLock<Tables> = LazyLock::new(|| {
+    let to_linear = std::array::from_fn(|i| {
+        let c = i as f32 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    });
+    let to_srgb = (0..=LINEAR_STEPS)
+        .map(|i| {
+            let l = i as f32 / LINEAR_STEPS as f32;
+            let c = if l <= 0.0031308 { l * 12.92 } else { 1.055 * l.powf(1.0 / 2.4) - 0.055 };
+            (c * 255.0).round() as u8
+        })
+        .collect();
+
+    Tables { to_linear, to_srgb }
+});
+
+/// Run `filter` over the game picture
+pub fn filter_picture(filter: ColorFilter, pixels: &mut [Color32]) {
+    let Some(matrix) = matrix(filter) else {
+        return;
+    };
+    let tables = &*TABLES;
+
+    for pixel in pixels {
+        let [r, g, b, a] = pixel.to_array();
+        let rgb = [tables.to_linear[r as usize], tables.to_linear[g as usize], tables.to_linear[b as usize]];
+        let channel = |row: &[f32; 3]| tables.encode(row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]);
+
+        *pixel = Color32::from_rgba_premultiplied(channel(&matrix[0]), channel(&matrix[1]), channel(&matrix[2]), a);
+    }
+}
+
+/// Style of the UI in `theme`, egui's own with the settings applied
+pub fn style(theme: Theme, settings: &AccessibilitySettings) -> Style {
+    let mut style = theme.default_style();
+
+    if settings.high_contrast {
+        high_contrast(&mut style.visuals, theme);
+    }
+
+    if settings.large_targets {
+        let spacing = &mut style.spacing;
+        spacing.interact_size = egui::vec2(56.0, 28.0);
+        spacing.button_padding = egui::vec2(10.0, 6.0);
+        spacing.item_spacing = egui::vec2(10.0, 8.0);
+        spacing.icon_width = 20.0;
+        spacing.icon_width_inner = 12.0;
+        spacing.icon_spacing = 6.0;
+        spacing.slider_rail_height = 12.0;
+    }
+
+    if settings.reduced_motion {
+        style.animation_time = 0.0;
+    }
+
+    style
+}
+
+/// Plain black on white or white on black, the widget borders in the text color and the hovered
+/// ones in an accent color
+fn high_contrast(visuals: &mut Visuals, theme: Theme) {
+    let (fg, bg, accent) = match theme {
+        Theme::Dark => (Color32::WHITE, Color32::BLACK, Color32::YELLOW),
+        Theme::Light => (Color32::BLACK, Color32::WHITE, Color32::from_rgb(0, 0, 200)),
+    };
+
+    visuals.panel_fill = bg;
+    visuals.window_fill = bg;
+    visuals.extreme_bg_color = bg;
+    visuals.faint_bg_color = bg;
+    visuals.code_bg_color = bg;
+    visuals.window_stroke = Stroke::new(2.0, fg);
+    visuals.weak_text_color = Some(fg);
+    visuals.hyperlink_color = accent;
+    // Selected text and items drawn in the background color over the accent
+    visuals.selection.bg_fill = accent;
+    visuals.selection.stroke = Stroke::new(2.0, bg);
+
+    let widgets = &mut visuals.widgets;
+    for widget in [&mut widgets.noninteractive, &mut widgets.inactive, &mut widgets.open] {
+        widget.bg_fill = bg;
+        widget.weak_bg_fill = bg;
+        widget.bg_stroke = Stroke::new(1.5, fg);
+        widget.fg_stroke = Stroke::new(1.5, fg);
+    }
+    for widget in [&mut widgets.hovered, &mut widgets.active] {
+        widget.bg_fill = bg;
+        widget.weak_bg_fill = bg;
+        widget.bg_stroke = Stroke::new(2.5, accent);
+        widget.fg_stroke = Stroke::new(2.0, accent);
+    }
+}
+
+/// Use the settings for both themes, whichever the system asks for
+pub fn apply(ctx: &egui::Context, settings: &AccessibilitySettings) {
+    for theme in [Theme::Dark, Theme::Light] {
+        ctx.set_style_of(theme, style(theme, settings));
+    }
+}
+
+/// Name the widget of `response`, which only shows an icon, for the screen readers
+pub fn name(response: &Response, typ: WidgetType, name: &str) {
+    response.widget_info(|| WidgetInfo::labeled(typ, response.enabled(), name));
+}
+
+/// `name` for a toggle showing its state with an icon
+pub fn name_toggle(response: &Response, selected: bool, name: &str) {
+    response.widget_info(|| WidgetInfo::selected(WidgetType::Checkbox, response.enabled(), selected, name));
+}
+
+/// Have the screen readers read `text` out, without moving the focus. Must be called every frame
+/// even with nothing to say: a live region only speaks up when its text changes.
+pub fn live_region(ctx: &egui::Context, id: Id, text: &str) {
+    ctx.accesskit_node_builder(id, |node| {
+        node.set_role(Role::Status);
+        node.set_live(Live::Polite);
+        node.set_label(text);
+    });
+}
+
+#[test]
+fn color_filters() {
+    let filtered = |filter, color| {
+        let mut pixels = [color];
+        filter_picture(filter, &mut pixels);
+        pixels[0]
+    };
+    let distance = |a: Color32, b: Color32| {
+        a.to_array().iter().zip(b.to_array()).map(|(&x, y)| (i32::from(x) - i32::from(y)).abs()).sum::<i32>()
+    };
+    let red = Color32::from_rgb(220, 40, 40);
+    let green = Color32::from_rgb(40, 180, 40);
+
+    assert_eq!(filtered(ColorFilter::Off, red), red);
+
+    for filter in ColorFilter::ALL {
+        // Greys are seen the same by everyone
+        for grey in [0, 1, 50, 128, 254, 255] {
+            let grey = Color32::from_gray(grey);
+            assert!(distance(filtered(filter, grey), grey) <= 3, "{:?}", filter);
+        }
+    }
+
+    // Red and green look alike without either cone, the correction sets them apart again
+    let simulated = distance(filtered(ColorFilter::SimulateDeuteranopia, red), filtered(ColorFilter::SimulateDeuteranopia, green));
+    assert!(simulated < distance(red, green) / 2);
+    let seen = |color| filtered(ColorFilter::SimulateDeuteranopia, filtered(ColorFilter::CorrectDeuteranopia, color));
+    assert!(distance(seen(red), seen(green)) > simulated);
+}
@@ -1,56 +1,229 @@
+use std::any::Any;
 use std::env;
-use std::time::Instant;
+use std::{fs, io};
+use std::panic::{self, AssertUnwindSafe};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
 use egui::{ColorImage, TextureHandle, TextureOptions, Key};
 use tracing::info;
-use mips_core::ConsoleManager;
-use mips_core::input::{DeviceType, Button};
-use crate::audio::AudioManager;
-use crate::input::{InputManager, GamepadManager};
-use crate::config::{ConfigManager, button_display_name, key_display_name};
+use mips_core::{ConsoleManager, CpuBackend, CpuClock, CpuFrame, DebugRender, InternalResolution, Interpolation, MemoryCardScope, MipsError, PixelFormat, RendererBackend, Wireframe, compat};
+use mips_core::bios::{self, BiosSearch};
+use mips_core::audio::{AudioMix, AudioSource};
+use mips_core::exec_watch::ExecWatchSettings;
+use mips_core::input::{self as core_input, Button, ControllerType};
+use mips_core::script::StartupScript;
+use crate::audio::{self, AudioSink};
+use crate::cli::CliArgs;
+use crate::input::{self, InputManager, GamepadManager, Source};
+use crate::cloud::{self, SyncReport};
+use crate::error::AppResult;
+use crate::config::{
+    self, CloudProvider, ColorFilter, ConfigManager, ConflictPolicy, FrameSkip, GameSettings, LatencyCompensation,
+    CrowdMode, MemoryCardMode, MemoryWatch, MenuAudio, MouseCaptureMode, OsdCategory, OsdPosition, PortDevice,
+    ScreenshotSource, WatchSize, button_display_name, key_display_name,
+};
+use crate::netplay::{
+    Announcer, Blob, InputDelay, LanBrowser, LobbyJoin, LobbyTransport, NetplaySession, PadState,
+    SessionRole, SessionState, UdpTransport,
+};
+use crate::netplay::protocol::{BlobKind, DEFAULT_PORT, LOBBY_PORT};
+use crate::netplay::transport::Transport;
+use crate::netplay::session::MAX_INPUT_DELAY;
+use crate::osd::{Osd, OsdLine};
+use crate::accessibility;
+use crate::perf::PerfOverlay;
+use crate::watchdog::StallWatchdog;
+use crate::safe_mode::{self, RunSentinel};
+use crate::controller_test::ControllerTest;
+use crate::latency::LatencyCalibration;
+use crate::memory_cards::MemoryCardManager;
+use crate::ram_search::RamSearchWindow;
+use crate::log_view::LogView;
+use crate::block_cache::BlockCacheView;
+use crate::workspace::{Tool, Workspace};
+use crate::frame_skip::FrameSkipper;
+use crate::bug_report::{BugReport, LogBuffer};
+use crate::control::{self, Call, ControlServer, RpcError, CALL_FAILED};
+use crate::autosplit::WatchServer;
+use crate::crowd::CrowdInput;
+use crate::games_list::GamesList;
+use crate::library;
+use crate::save_states::{self, AutoStates, SaveStates};
+use crate::mouse::{self, MouseCapture};
+use crate::gamepad_ui::{GamepadUi, NavButton};
+use crate::display::{self, Display};
+use crate::soundtrack;
 use gilrs::Button as GilrsButton;
+use serde_json::{json, Value};
+
+/// Opens the netplay chat prompt
+const CHAT_KEY: Key = Key::T;
+/// Save state hotkeys, as in most emulators: save, next slot, load
+const SAVE_STATE_KEY: Key = Key::F1;
+const NEXT_SLOT_KEY: Key = Key::F2;
+const LOAD_STATE_KEY: Key = Key::F3;
+/// Undoes the last save or load of a state
+const UNDO_STATE_KEY: Key = Key::F5;
+/// Held to rewind, when enabled in the settings
+const REWIND_KEY: Key = Key::F4;
+/// Captures the mouse for the PlayStation Mouse, or releases it
+const MOUSE_CAPTURE_KEY: Key = Key::F8;
+const FULLSCREEN_KEY: Key = Key::F11;
+const SCREENSHOT_KEY: Key = Key::F12;
+
+/// Pace of the emulation
+const FRAME_TIME: f64 = 1.0 / 60.0;
+
+/// How long chat lines and warnings stay on screen
+const DESYNC_DURATION: Duration = Duration::from_secs(15);
 
 pub struct EmulatorApp {
     // Emulator core
     mips: ConsoleManager,
+    game: Option<String>,
+    /// Disc swapped in from the Change Disc menu, the game's file otherwise
+    disc: Option<String>,
+    /// Discs of the game, listed when the Change Disc menu is first opened
+    game_discs: Option<Vec<String>>,
 
     // Configuration
     config: ConfigManager,
+    /// Started with default settings and without patches, see `safe_mode`
+    safe_mode: bool,
+    /// Last title given to the window, with the one of the game
+    window_title: String,
+    sentinel: RunSentinel,
+    /// End of the log, for bug reports
+    log: LogBuffer,
+    /// The last run crashed, ask whether to restart in safe mode
+    offer_safe_mode: bool,
+    game_settings: GameSettings,
 
     // Audio
-    audio: AudioManager,
+    audio: Box<dyn AudioSink>,
 
     // Input
     input: InputManager,
     gamepad: GamepadManager,
+    mouse: MouseCapture,
+    /// Menus and text fields driven by the gamepad
+    gamepad_ui: GamepadUi,
 
     // Rendering
     game_texture: Option<TextureHandle>,
     cached_frame: Option<CachedFrame>,
+    /// Where the game picture was last drawn, for the OSD
+    game_rect: Option<egui::Rect>,
+    osd: Osd,
+    perf: PerfOverlay,
+    /// Displays found when the list was last refreshed
+    displays: Vec<Display>,
+    fullscreen_step: Option<FullscreenStep>,
+    screenshot: Option<PendingScreenshot>,
 
     // UI state
     show_settings: bool,
     show_input_config: bool,
     show_about: bool,
+    show_game_properties: bool,
+    show_netplay: bool,
+    controller_test: ControllerTest,
+    latency_calibration: LatencyCalibration,
+    memory_cards: MemoryCardManager,
+    ram_search: RamSearchWindow,
+    log_view: LogView,
+    block_cache: BlockCacheView,
+    /// Where the tools are, docked or floating
+    workspace: Workspace,
+    /// Debug menu render modes, not saved: they're for one session of hunting a glitch
+    debug_render: DebugRender,
+    games_list: GamesList,
+    save_states: SaveStates,
+    auto_states: AutoStates,
     paused: bool,
+    /// The volume is lowered because the game is frozen behind a menu, see `MenuAudio`
+    menu_volume: bool,
+    /// Audio source played alone, whatever the mixer settings. Not saved, it's for finding out
+    /// where a sound comes from.
+    audio_solo: Option<AudioSource>,
+    /// The core panicked, it can't run again until the game is restarted
+    halted: bool,
+    watchdog: StallWatchdog,
+    /// Why emulation was stopped behind the user's back, shown until dismissed
+    stall: Option<StallReport>,
+    /// The game couldn't start because there's no usable BIOS
+    bios_error: Option<BiosSearch>,
+    /// Dumps offered in the settings, scanned when the window opens
+    bios_scan: Option<BiosSearch>,
+    /// Data execution watch, for debugging. Not saved.
+    exec_watch: Option<ExecWatchSettings>,
+
+    // Netplay
+    netplay: Option<NetplayState>,
+    netplay_form: NetplayForm,
+
+    /// Result of the last cloud sync
+    cloud_status: Option<String>,
+
+    /// JSON-RPC server for external tools, when enabled
+    control: Option<ControlServer>,
+    /// Memory watches for autosplitters, when enabled
+    watch_server: Option<WatchServer>,
+    /// Frames emulated since the game started, for the autosplitters
+    game_frames: u64,
+    /// Presses from the viewers of a stream, see `crowd`
+    crowd: CrowdInput,
 
     // Input config state
     input_config_tab: InputConfigTab,
     waiting_for_key: Option<Button>,
     waiting_for_gamepad_button: Option<Button>,
+    /// Name typed for a new binding profile
+    new_input_profile: String,
 
     // Performance tracking
     last_emulator_update: Instant,
     frame_debt: f64,
+    frame_skipper: FrameSkipper,
+    /// When the inputs were last polled, the host time covered by the next frame starts there
+    last_input_poll: Instant,
     emulation_fps: f32,
     emulation_frame_count: u32,
     emulation_fps_timer: Instant,
 }
 
+/// Entering fullscreen takes a frame per step: the window has to be on the chosen display before
+/// it goes fullscreen, on the display it's on
+#[derive(Clone, Copy)]
+enum FullscreenStep {
+    /// Move the window to the display from the settings
+    Move,
+    Enter,
+}
+
+/// Screenshot taken from the picture presented, it's captured once the frame is drawn
+#[derive(Clone, Copy)]
+enum PendingScreenshot {
+    /// To take on the next frame, once the menu it was asked from is closed
+    Requested(ScreenshotSource),
+    /// Waiting for the image, cropped to the rect (in points) if set
+    Capturing(Option<egui::Rect>),
+}
+
 #[derive(Clone)]
 struct CachedFrame {
     rgba_pixels: Vec<u8>,
     width: usize,
     height: usize,
+    format: PixelFormat,
+    aspect: Option<f32>,
+}
+
+struct StallReport {
+    title: String,
+    details: String,
+    /// The core crashed, resuming is not possible
+    fatal: bool,
 }
 
 #[derive(PartialEq)]
@@ -59,61 +232,308 @@ enum InputConfigTab {
     Gamepad,
 }
 
+struct NetplayState {
+    session: NetplaySession,
+    /// Only set when hosting
+    announcer: Option<Announcer>,
+    /// Current state of the local pad
+    local_pad: PadState,
+    /// Pad states last given to the core, per port
+    applied: [PadState; 2],
+    chat_open: bool,
+    /// Keyboard is ignored until all keys are released after closing the chat, so that the Enter
+    /// that sent the line doesn't press Start
+    chat_closing: bool,
+    chat_input: String,
+    /// Number of chat lines already shown on the OSD
+    chat_seen: usize,
+    desync_reported: bool,
+}
+
+#[derive(PartialEq)]
+enum NetplayTab {
+    Browse,
+    Host,
+    Join,
+}
+
+struct NetplayForm {
+    tab: NetplayTab,
+    name: String,
+    host_port: String,
+    join_addr: String,
+    /// Optional for hosts, required to join with a code
+    lobby_server: String,
+    lobby_code: String,
+    allow_relay: bool,
+    /// Ping and input delay on the OSD during sessions
+    show_overlay: bool,
+    auto_delay: bool,
+    fixed_delay: u8,
+    browser: Option<LanBrowser>,
+    /// Lobby code being looked up, and whether to spectate the session once it's found
+    lookup: Option<(LobbyJoin, bool)>,
+    /// Last error or reason the previous session ended
+    status: Option<String>,
+}
+
+impl Default for NetplayForm {
+    fn default() -> Self {
+        let name = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "Player".to_string());
+
+        Self {
+            tab: NetplayTab::Browse,
+            name,
+            host_port: DEFAULT_PORT.to_string(),
+            join_addr: String::new(),
+            lobby_server: String::new(),
+            lobby_code: String::new(),
+            allow_relay: true,
+            show_overlay: true,
+            auto_delay: true,
+            fixed_delay: 2,
+            browser: None,
+            lookup: None,
+            status: None,
+        }
+    }
+}
+
+enum NetplayJoin {
+    Host,
+    Player(SocketAddr),
+    Spectator(SocketAddr),
+    /// Join the session behind a lobby code
+    Lobby { code: String, spectate: bool },
+}
+
 impl EmulatorApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, cli: CliArgs, sentinel: RunSentinel, log: LogBuffer) -> Self {
         info!("Initializing MIPS emulator");
 
         // Load configuration
-        let config = ConfigManager::new().expect("Failed to load configuration");
+        let config = if cli.safe_mode {
+            info!("Safe mode, using the default configuration");
+            ConfigManager::with_defaults()
+        } else {
+            ConfigManager::new().expect("Failed to load configuration")
+        };
+
+        // Fetch the saves made on other machines before the game starts
+        let mut cloud_status = None;
+        if config.settings.cloud.sync_on_launch && let Some(game) = &cli.game {
+            cloud_status = sync_status(cloud::sync_saves(&config.settings.cloud, game));
+        }
 
         // Load game
         let sys_dir = env::current_dir().unwrap();
         let mut mips = ConsoleManager::new();
-        if let Err(e) = mips.load_game(sys_dir.as_path(), Some("Silent Hill (USA).cue")) {
-            tracing::error!("Failed to load game: {}", e);
+        mips.set_bios(config.settings.system.bios.clone());
+        mips.set_fast_boot(config.settings.system.fast_boot);
+        mips.set_hle_bios(config.settings.system.hle_bios);
+        mips.set_preload_limit(config.settings.system.preload_limit());
+        mips.set_hardcore(config.settings.system.hardcore);
+        if let Err(e) = mips.set_force_ntsc_timing(config.settings.system.force_ntsc_timing) {
+            tracing::warn!("{}", e);
+        }
+        mips.set_cpu_backend(config.settings.system.cpu_backend);
+        if let Err(e) = mips.set_cpu_clock(config.settings.system.cpu_clock) {
+            tracing::warn!("{}", e);
         }
+        mips.set_soundtrack(cli.game.as_deref().filter(|_| !cli.safe_mode).and_then(soundtrack::load));
+        let bios_error = load_error(mips.load_game(sys_dir.as_path(), cli.game.as_deref()));
 
         // Setup input
         let input = InputManager::new();
         let gamepad = GamepadManager::new();
 
-        // Connect keyboard to port 0
-        mips.connect_device(0, DeviceType::Keyboard);
+        let game_settings = cli.game.as_deref()
+            .filter(|_| !cli.safe_mode)
+            .and_then(|game| config.game_settings(game))
+            .unwrap_or_else(|| auto_game_settings(&config, &mips));
+        for (port, &controller) in game_settings.controllers.iter().enumerate() {
+            mips.connect_device(port, controller);
+        }
+        insert_memory_cards(&mut mips, &config, &game_settings, cli.game.as_deref());
 
         // Setup audio
-        let mut audio = AudioManager::new().expect("Failed to initialize audio");
+        let audio = audio::open(cli.audio).expect("Failed to initialize audio");
         audio.set_volume(config.settings.audio.volume);
+        audio.set_rate_control(config.settings.audio.dynamic_rate_control);
 
-        Self {
+        let watchdog = stall_watchdog(&config);
+        let fullscreen_step = config.settings.video.fullscreen.then_some(FullscreenStep::Move);
+
+        // Don't run anything before the user had a chance to pick safe mode
+        let offer_safe_mode = sentinel.previous_run_crashed() && !cli.safe_mode;
+
+        let mut app = Self {
             mips,
+            game: cli.game,
+            disc: None,
+            game_discs: None,
             config,
+            safe_mode: cli.safe_mode,
+            window_title: window_title(cli.safe_mode).to_string(),
+            sentinel,
+            log,
+            offer_safe_mode,
+            game_settings,
             audio,
             input,
             gamepad,
+            mouse: MouseCapture::default(),
+            gamepad_ui: GamepadUi::default(),
             game_texture: None,
             cached_frame: None,
+            game_rect: None,
+            osd: Osd::default(),
+            perf: PerfOverlay::default(),
+            displays: display::list(),
+            fullscreen_step,
+            screenshot: None,
             show_settings: false,
             show_input_config: false,
             show_about: false,
-            paused: false,
+            show_game_properties: false,
+            controller_test: ControllerTest::default(),
+            latency_calibration: LatencyCalibration::default(),
+            memory_cards: MemoryCardManager::default(),
+            ram_search: RamSearchWindow::default(),
+            log_view: LogView::default(),
+            block_cache: BlockCacheView::default(),
+            workspace: Workspace::load(),
+            debug_render: DebugRender::default(),
+            games_list: GamesList::default(),
+            save_states: SaveStates::default(),
+            auto_states: AutoStates::default(),
+            show_netplay: false,
+            paused: offer_safe_mode,
+            menu_volume: false,
+            audio_solo: None,
+            halted: false,
+            watchdog,
+            stall: None,
+            bios_error,
+            bios_scan: None,
+            exec_watch: None,
+            netplay: None,
+            netplay_form: NetplayForm::default(),
+            cloud_status,
+            control: None,
+            watch_server: None,
+            game_frames: 0,
+            crowd: CrowdInput::default(),
             input_config_tab: InputConfigTab::Keyboard,
             waiting_for_key: None,
             waiting_for_gamepad_button: None,
+            new_input_profile: String::new(),
             last_emulator_update: Instant::now(),
             frame_debt: 0.0,
+            frame_skipper: FrameSkipper::default(),
+            last_input_poll: Instant::now(),
             emulation_fps: 60.0,
             emulation_frame_count: 0,
             emulation_fps_timer: Instant::now(),
+        };
+        app.apply_exe_patch();
+        app.apply_startup_script();
+        app.apply_widescreen_patch();
+        app.apply_control_server();
+        app.apply_accessibility(&cc.egui_ctx);
+        if let Some(game) = app.game.clone() {
+            app.games_list.record_played(&game);
+            app.apply_input_profile(&game);
         }
+
+        if app.safe_mode {
+            app.osd.notify_for(
+                OsdCategory::Other,
+                OsdLine::warning("Safe mode: default settings, no patches, saving replaces your settings"),
+                DESYNC_DURATION,
+            );
+        }
+
+        app
     }
 
     fn update_emulator(&mut self, ctx: &egui::Context) {
-        if self.paused {
+        // The session must keep talking to the peer even while we're not emulating
+        self.poll_lobby_lookup();
+        self.poll_netplay();
+        // Tools can resume a paused game
+        self.poll_control();
+
+        // Browsing the games list freezes the game like pausing, except in netplay where the peer
+        // can't wait
+        let in_menu = self.paused || (self.games_list.open && self.netplay.is_none());
+
+        // The cursor is given back as soon as the game stops or the window loses focus
+        if self.mouse.is_captured()
+            && (in_menu || self.halted || self.netplay.is_some() || !ctx.input(|i| i.focused))
+        {
+            self.mouse.release(ctx);
+        }
+
+        self.set_menu_volume(in_menu);
+        if self.halted || self.latency_calibration.open || in_menu {
+            self.gamepad.stop_rumble();
+        }
+        if self.halted || self.latency_calibration.open {
             return;
         }
+        if in_menu {
+            self.run_menu_audio();
+            return;
+        }
+
+        let resolution = self.internal_resolution();
+        self.mips.set_internal_resolution(resolution);
+        self.mips.set_renderer(self.renderer());
+        self.mips.set_pgxp(self.pgxp());
+        self.mips.set_dithering(self.dithering());
+        self.mips.set_true_color(self.true_color());
+        self.mips.set_accurate_transparency(self.accurate_transparency());
+        self.mips.set_overdraw_heatmap(self.config.settings.video.overdraw_heatmap);
+        self.mips.set_parallel_draw_areas(self.parallel_draw_areas());
+        // What's drawn ends up in the VRAM the games can read back, the peers would desync
+        self.mips.set_debug_render(if self.netplay.is_some() { DebugRender::default() } else { self.debug_render });
+        self.mips.set_widescreen_hack(self.widescreen_hack());
+        self.mips.set_interpolation(self.interpolation());
+        self.mips.set_audio_mix(self.audio_mix());
+
+        if self.mouse.is_captured() {
+            // The motion is in native console pixels
+            let scale = resolution.multiplier() as f32;
+            let picture = self.game_rect.zip(
+                self.cached_frame.as_ref().map(|f| egui::vec2(f.width as f32, f.height as f32) / scale),
+            );
+            let input = self.mouse.poll(ctx, picture, self.config.settings.input.mouse_sensitivity);
+            for port in self.mouse_ports() {
+                self.mips.handle_mouse(port, input);
+            }
+        }
+
+        // The cursor isn't part of the inputs the peers exchange
+        if self.netplay.is_none() && let Some(picture) = self.game_rect {
+            let input = mouse::lightgun_input(ctx, picture);
+            for port in self.lightgun_ports() {
+                self.mips.handle_lightgun(port, input);
+            }
+        }
 
-        const TARGET_FPS: f64 = 60.0;
-        const FRAME_TIME: f64 = 1.0 / TARGET_FPS;
+        // Peers can't go back in time on their own
+        let rewind = match self.netplay {
+            None => self.config.settings.system.rewind_settings(),
+            Some(_) => None,
+        };
+        self.mips.set_rewind(rewind);
+        let rewinding = rewind.is_some()
+            && !self.mips.is_hardcore()
+            && !ctx.wants_keyboard_input()
+            && ctx.input(|i| i.key_down(REWIND_KEY));
 
         let now = Instant::now();
         let delta = now.duration_since(self.last_emulator_update).as_secs_f64();
@@ -124,16 +544,47 @@ impl EmulatorApp {
 
         // Run emulator frames to pay off debt
         // Limit to max 2 frames per update to prevent audio issues
-        let frames_to_run = self.frame_debt.floor().min(2.0) as u32;
+        let mut frames_to_run = self.frame_debt.floor().min(2.0) as u32;
+
+        // Spectators joining late fast forward until they catch up with the host
+        if let Some(np) = &self.netplay
+            && np.session.role() == SessionRole::Spectator
+            && np.session.ready_frames() > 2 {
+            frames_to_run = np.session.ready_frames().min(8) as u32;
+        }
 
         for _ in 0..frames_to_run {
-            self.run_emulator_frame(ctx);
+            if rewinding {
+                self.rewind_frame();
+                self.frame_debt -= 1.0;
+                continue;
+            }
+
+            // Peers compare the pictures to detect desyncs, they must all draw the same frames.
+            // Captures would show the skipped frames as stutters.
+            let skip = self.netplay.is_none()
+                && !self.config.settings.video.capture_friendly
+                && self.frame_skipper.skip_next(self.config.settings.video.frame_skip, self.frame_debt >= 2.0);
+            self.mips.set_skip_draws(skip);
+
+            if !self.run_emulator_frame(ctx) {
+                // Waiting for the peer, don't let the debt pile up meanwhile
+                self.frame_debt = self.frame_debt.min(1.0);
+                break;
+            }
             self.frame_debt -= 1.0;
+            self.game_frames += 1;
+            self.publish_watches();
 
             // Count for FPS display
             self.emulation_frame_count += 1;
         }
 
+        if let Ok(game) = self.save_state_game() {
+            let minutes = self.config.settings.system.auto_state_minutes;
+            self.auto_states.poll(&self.mips, &game, self.game_frames, minutes);
+        }
+
         // Update FPS counter
         if self.emulation_fps_timer.elapsed() >= std::time::Duration::from_secs(1) {
             self.emulation_fps = self.emulation_frame_count as f32;
@@ -142,433 +593,3682 @@ impl EmulatorApp {
         }
     }
 
-    fn run_emulator_frame(&mut self, ctx: &egui::Context) {
+    /// Keep the sound of the frozen game going at the pace of the frames, see `MenuAudio`
+    fn run_menu_audio(&mut self) {
+        let now = Instant::now();
+        self.frame_debt += now.duration_since(self.last_emulator_update).as_secs_f64() / FRAME_TIME;
+        self.last_emulator_update = now;
+
+        let frames = self.frame_debt.floor().min(2.0);
+        // The game has nothing to catch up with when it resumes
+        self.frame_debt = (self.frame_debt - frames).min(1.0);
+
+        if self.config.settings.audio.menu_audio == MenuAudio::Stop {
+            return;
+        }
+
+        for _ in 0..frames as u32 {
+            self.mips.update_paused();
+
+            let audio = self.mips.take_audio();
+            if self.config.settings.audio.enabled {
+                self.audio.enqueue(audio);
+            }
+        }
+    }
+
+    /// Volume of the sound output, lowered while the game is frozen behind a menu
+    fn output_volume(&self) -> f32 {
+        let audio = &self.config.settings.audio;
+
+        match audio.menu_audio.volume() {
+            Some(fraction) if self.menu_volume => audio.volume * fraction,
+            _ => audio.volume,
+        }
+    }
+
+    fn set_menu_volume(&mut self, in_menu: bool) {
+        if in_menu != self.menu_volume {
+            self.menu_volume = in_menu;
+            self.audio.set_volume(self.output_volume());
+        }
+    }
+
+    /// Returns false if no frame could be emulated because netplay is waiting for the peer
+    fn run_emulator_frame(&mut self, ctx: &egui::Context) -> bool {
+        // Handle input (only if not configuring), by port
+        let ports = self.config.settings.input.port_devices.clone();
+        let mut button_queues: [core_input::ButtonQueue; 2] = Default::default();
+        let mut gamepad_inputs: [Vec<core_input::InputEvent>; 2] = Default::default();
+        let mut analog = [core_input::AnalogInput::default(); 2];
+        let chatting = match &mut self.netplay {
+            Some(np) => {
+                if np.chat_closing && ctx.input(|i| i.keys_down.is_empty()) {
+                    np.chat_closing = false;
+                }
+                np.chat_open || np.chat_closing
+            }
+            None => false,
+        };
+        if !self.show_input_config && !self.controller_test.open && !self.latency_calibration.open && !chatting {
+            let keys = self.input.poll_input(ctx, &self.config.keyboard_bindings.bindings);
+            if let Some(port) = input::route(&ports, Source::Keyboard) {
+                button_queues[port] = keys;
+            }
+            // In the background the sticks go back to the center
+            let input = &self.config.settings.input;
+            let gamepad = input.background_gamepad || ctx.input(|i| i.focused);
+            self.gamepad.poll_gamepad(
+                &ports,
+                &mut gamepad_inputs,
+                &self.config.gamepad_bindings.bindings,
+                gamepad,
+                &input.triggers,
+            );
+            if gamepad {
+                for (port, device) in ports.iter().enumerate() {
+                    analog[port] =
+                        self.gamepad.poll_sticks(device, input.left_stick_deadzone, input.right_stick_deadzone);
+                }
+            }
+        }
+        // The viewers play on the first port
+        button_queues[0].extend(self.crowd.next_frame(&self.config.settings.control.crowd));
+        let poll = Instant::now();
+        let window = self.last_input_poll..poll;
+        self.last_input_poll = poll;
+
+        match &mut self.netplay {
+            Some(np) => {
+                // Peers apply the inputs at the start of the frame to stay in sync. Each peer
+                // plays one pad, whatever port its devices are given to.
+                let mut button_queue: core_input::ButtonQueue = button_queues.concat();
+                button_queue.extend(gamepad_inputs.iter().flat_map(|inputs| core_input::untimed(inputs)));
+                np.local_pad.apply(&button_queue);
+                np.session.push_local_input(np.local_pad);
+
+                let Some(pads) = np.session.next_frame() else {
+                    return false;
+                };
+
+                for (port, pad) in pads.into_iter().enumerate() {
+                    self.mips.handle_port_inputs(port, np.applied[port].diff(pad));
+                    np.applied[port] = pad;
+                }
+            }
+            // egui doesn't timestamp key events, only the gamepad buttons can be placed within
+            // the frame
+            None if self.config.settings.input.mid_frame_input => {
+                self.mips.set_input_latency(self.config.settings.input.input_latency());
+                for (port, button_queue) in button_queues.into_iter().enumerate() {
+                    self.mips.handle_port_inputs(port, button_queue);
+                    self.mips.handle_timed_inputs(port, &gamepad_inputs[port], window.clone());
+                }
+            }
+            None => {
+                for (port, mut button_queue) in button_queues.into_iter().enumerate() {
+                    button_queue.extend(core_input::untimed(&gamepad_inputs[port]));
+                    self.mips.handle_port_inputs(port, button_queue);
+                }
+            }
+        }
+        // The peers only exchange the buttons, the sticks stay centered
+        if self.netplay.is_none() {
+            for (port, analog) in analog.into_iter().enumerate() {
+                if ports[port] != PortDevice::None {
+                    self.mips.handle_analog(port, analog);
+                }
+            }
+        }
+        self.mips.refresh_devices();
+
         // Handle audio
+        let audio = self.mips.take_audio();
         if self.config.settings.audio.enabled {
-            let audio_samples = self.mips.get_audio_samples();
-            self.audio.enqueue(audio_samples);
+            self.audio.enqueue(audio);
         }
-        self.mips.clear_audio_samples();
 
-        // Handle input (only if not configuring)
-        if !self.show_input_config {
-            let mut button_queue = self.input.poll_input(ctx, &self.config.keyboard_bindings.bindings);
-            self.gamepad.poll_gamepad(&mut button_queue, &self.config.gamepad_bindings.bindings);
-            self.mips.handle_inputs(button_queue);
-            self.mips.refresh_devices();
+        // Update emulator - ONE frame
+        let start = Instant::now();
+        let stats = match panic::catch_unwind(AssertUnwindSafe(|| self.mips.update())) {
+            Ok(stats) => stats,
+            Err(payload) => {
+                self.report_crash(payload);
+                return true;
+            }
+        };
+        self.perf.record(&stats, start.elapsed());
+        self.report_exec_watch_hits();
+
+        // The controller test plays its own rumble
+        for (port, device) in ports.iter().enumerate() {
+            let rumble = if self.controller_test.open { (0, 0) } else { self.mips.rumble(port) };
+            self.gamepad.set_rumble(port, device, rumble, self.config.settings.input.rumble_strength);
         }
 
-        // Update emulator - ONE frame
-        self.mips.update();
+        if self.watchdog.check(&stats) {
+            self.report_stall();
+        }
 
         // Cache the frame if we got a new one
         if let Some(frame) = self.mips.get_frame() {
-            // Convert XRGB (0xAARRGGBB) to RGBA bytes
-            let rgba_pixels: Vec<u8> = frame.pixels.iter()
-                .flat_map(|&pixel| {
-                    let r = ((pixel >> 16) & 0xFF) as u8;
-                    let g = ((pixel >> 8) & 0xFF) as u8;
-                    let b = (pixel & 0xFF) as u8;
-                    let a = 255u8;
-                    [r, g, b, a]
-                })
-                .collect();
+            if let Some(np) = &mut self.netplay
+                && np.session.wants_frame_hash() {
+                np.session.report_frame_hash(frame.hash());
+            }
+            self.netplay_checkpoint();
 
-            self.cached_frame = Some(CachedFrame {
-                rgba_pixels,
-                width: frame.width as usize,
-                height: frame.height as usize,
-            });
+            self.cache_frame(&frame);
         }
+
+        true
     }
 
-    fn render_menu_bar(&mut self, ctx: &egui::Context) {
-        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
-            egui::menu::bar(ui, |ui| {
-                ui.menu_button("File", |ui| {
-                    if ui.button("Open ROM...").clicked() {
-                        // TODO: File dialog
-                        ui.close_menu();
-                    }
-                    ui.separator();
-                    if ui.button("Exit").clicked() {
-                        // Save settings before exit
-                        let _ = self.config.save_settings();
-                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                    }
-                });
+    /// One step of the rewind: go back a snapshot and emulate a frame from there to have its
+    /// picture. Inputs are ignored and the sound is dropped.
+    fn rewind_frame(&mut self) {
+        // From the frame emulated after the last step, back to the snapshot before it
+        let step = u64::from(self.config.settings.system.rewind_interval.max(1)) + 1;
+        match self.mips.rewind(step) {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(e) => {
+                self.osd.notify(OsdCategory::SaveStates, OsdLine::warning(format!("Failed to rewind: {}", e)));
+                return;
+            }
+        }
 
-                ui.menu_button("Emulation", |ui| {
-                    let pause_text = if self.paused { "Resume" } else { "Pause" };
-                    if ui.button(pause_text).clicked() {
-                        self.paused = !self.paused;
-                        ui.close_menu();
-                    }
-                    if ui.button("Reset").clicked() {
-                        // TODO: Reset emulator
-                        ui.close_menu();
-                    }
-                    ui.separator();
-                    if ui.button("Save State").clicked() {
-                        // TODO: Save state
-                        ui.close_menu();
-                    }
-                    if ui.button("Load State").clicked() {
-                        // TODO: Load state
-                        ui.close_menu();
-                    }
-                });
+        self.mips.set_skip_draws(false);
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| self.mips.update())) {
+            self.report_crash(payload);
+            return;
+        }
+        self.mips.take_audio();
+        self.watchdog.reset();
 
-                ui.menu_button("Options", |ui| {
-                    if ui.button("Settings...").clicked() {
-                        self.show_settings = true;
-                        ui.close_menu();
-                    }
-                    if ui.button("Input Configuration...").clicked() {
-                        self.show_input_config = true;
-                        ui.close_menu();
-                    }
-                });
+        if let Some(frame) = self.mips.get_frame() {
+            self.cache_frame(&frame);
+        }
+    }
 
-                ui.menu_button("Help", |ui| {
-                    if ui.button("About").clicked() {
-                        self.show_about = true;
-                        ui.close_menu();
-                    }
-                });
+    fn cache_frame(&mut self, frame: &CpuFrame) {
+        // Convert XRGB (0xAARRGGBB) to RGBA bytes. egui textures are always RGBA8: the 15bit
+        // frames already have their 3 LSBs filled from the MSBs, both formats convert the same.
+        let rgba_pixels: Vec<u8> = frame.pixels.iter()
+            .flat_map(|&pixel| {
+                let r = ((pixel >> 16) & 0xFF) as u8;
+                let g = ((pixel >> 8) & 0xFF) as u8;
+                let b = (pixel & 0xFF) as u8;
+                let a = 255u8;
+                [r, g, b, a]
+            })
+            .collect();
 
-                // FPS counter and VSync toggle on the right
-                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    ui.label(format!("FPS: {:.0}", self.emulation_fps));
-                });
-            });
+        self.cached_frame = Some(CachedFrame {
+            rgba_pixels,
+            width: frame.width as usize,
+            height: frame.height as usize,
+            format: frame.format,
+            aspect: frame.aspect,
         });
     }
 
-    fn render_game(&mut self, ctx: &egui::Context) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            // Use cached frame to prevent flickering
-            if let Some(cached) = &self.cached_frame {
-                // Create ColorImage from cached RGBA data
-                let image = ColorImage::from_rgba_unmultiplied(
-                    [cached.width, cached.height],
-                    &cached.rgba_pixels,
-                );
-
-                // Update texture
-                let texture_options = if self.config.settings.video.bilinear_filter {
-                    TextureOptions::LINEAR
-                } else {
-                    TextureOptions::NEAREST
-                };
-
-                self.game_texture = Some(ctx.load_texture(
-                    "game_frame",
-                    image,
-                    texture_options,
-                ));
-
-                if let Some(texture) = &self.game_texture {
-                    // Calculate size to maintain aspect ratio
-                    let available_size = ui.available_size();
-                    let game_aspect = cached.width as f32 / cached.height as f32;
-                    let available_aspect = available_size.x / available_size.y;
+    /// Power cycle the console with the current game
+    fn restart_game(&mut self) {
+        let sys_dir = env::current_dir().unwrap();
+        self.mips.set_bios(self.config.settings.system.bios.clone());
+        self.mips.set_fast_boot(self.config.settings.system.fast_boot);
+        self.mips.set_hle_bios(self.config.settings.system.hle_bios);
+        self.mips.set_preload_limit(self.config.settings.system.preload_limit());
+        self.mips.set_hardcore(self.config.settings.system.hardcore);
+        self.apply_ntsc_timing();
+        self.apply_cpu_clock();
+        // The SPU captures the CD audio to RAM where the games may read it, the peers must play
+        // the same tracks
+        let game = self.game.as_deref().filter(|_| !self.safe_mode && self.netplay.is_none());
+        self.mips.set_soundtrack(game.and_then(soundtrack::load));
+        self.bios_error = load_error(self.mips.load_game(sys_dir.as_path(), self.game.as_deref()));
+        for (port, &controller) in self.game_settings.controllers.iter().enumerate() {
+            self.mips.connect_device(port, controller);
+        }
+        insert_memory_cards(&mut self.mips, &self.config, &self.game_settings, self.game.as_deref());
+        self.apply_exe_patch();
+        self.apply_startup_script();
+        self.apply_widescreen_patch();
+        self.mips.set_exec_watch(self.exec_watch);
+        self.disc = None;
+        self.game_discs = None;
+        self.game_frames = 0;
+        self.crowd = CrowdInput::default();
+        self.halted = false;
+        self.watchdog.reset();
+        self.stall = None;
+    }
 
-                    let display_size = if available_aspect > game_aspect {
-                        egui::vec2(available_size.y * game_aspect, available_size.y)
-                    } else {
-                        egui::vec2(available_size.x, available_size.x / game_aspect)
-                    };
+    /// Patch the game executable with the patch picked for the game, if any
+    fn apply_exe_patch(&mut self) {
+        let (Some(game), Some(patch)) = (&self.game, &self.game_settings.exe_patch) else {
+            return;
+        };
+        if self.safe_mode {
+            return;
+        }
 
-                    // Center the image
-                    ui.centered_and_justified(|ui| {
-                        ui.image(egui::load::SizedTexture::new(
-                            texture.id(),
-                            display_size,
-                        ));
-                    });
-                }
-            } else {
-                ui.centered_and_justified(|ui| {
-                    ui.heading("No game loaded");
-                    ui.label("Select File > Open ROM to load a game");
-                });
+        let path = config::game_patch_dir(game).join(patch);
+        let line = match self.mips.patch_executable(&path) {
+            Ok(()) => OsdLine::new(format!("Executable patched with {}", patch)),
+            Err(e) => {
+                tracing::error!("Failed to apply {}: {}", path.display(), e);
+                OsdLine::warning(format!("Failed to apply {}: {}", patch, e))
             }
-        });
+        };
+        self.osd.notify(OsdCategory::Patches, line);
     }
 
-    fn render_settings(&mut self, ctx: &egui::Context) {
-        if !self.show_settings {
+    /// Apply the game's patch from the widescreen database when widescreen is enabled, remove it
+    /// otherwise
+    fn apply_widescreen_patch(&mut self) {
+        if !self.config.settings.video.widescreen {
+            // Removing the cheats is allowed in hardcore mode too
+            let _ = self.mips.set_cheats(Vec::new());
             return;
         }
+        let Some(serial) = self.mips.serial_number() else {
+            return;
+        };
 
-        let mut show_settings = self.show_settings;
-        egui::Window::new("Settings")
-            .open(&mut show_settings)
-            .resizable(false)
-            .show(ctx, |ui| {
-                ui.heading("Video");
+        let cheats = self.config.widescreen_cheats(&serial);
+        if self.mips.is_hardcore() {
+            if !cheats.is_empty() {
+                self.osd.notify(
+                    OsdCategory::Patches,
+                    OsdLine::warning("Widescreen patches are disabled in hardcore mode, the picture is stretched"),
+                );
+            }
+            return;
+        }
+        if cheats.is_empty() {
+            self.osd.notify(
+                OsdCategory::Patches,
+                OsdLine::warning(format!("No widescreen patch for {}, the picture is stretched", serial)),
+            );
+        } else {
+            let names: Vec<&str> = cheats.iter().map(|c| c.name.as_str()).collect();
+            self.osd.notify(
+                OsdCategory::Patches,
+                OsdLine::new(format!("Widescreen patch applied: {}", names.join(", "))),
+            );
+        }
 
-                let mut vsync_changed = false;
-                if ui.checkbox(&mut self.config.settings.video.vsync, "VSync").changed() {
-                    vsync_changed = true;
-                }
+        if let Err(e) = self.mips.set_cheats(cheats) {
+            tracing::error!("Failed to apply the widescreen patch: {}", e);
+        }
+    }
 
-                ui.checkbox(&mut self.config.settings.video.bilinear_filter, "Bilinear Filtering");
+    /// Save a bug report bundle and tell the user where it is
+    fn create_bug_report(&mut self) {
+        let report = BugReport {
+            game: self.game.clone(),
+            serial: self.mips.serial_number(),
+            game_hash: self.mips.game_hash(),
+            diagnostics: self.mips.diagnostics(),
+            settings: self.config.redacted_settings().unwrap_or_else(|e| format!("# {}", e)),
+            game_settings: self.game.as_ref().and_then(|_| toml::to_string_pretty(&self.game_settings).ok()),
+            log: self.log.contents(),
+        };
 
-                ui.separator();
-                ui.heading("Audio");
+        let path = BugReport::default_path();
+        let line = match report.write(&path) {
+            Ok(()) => {
+                info!("Bug report saved to {}", path.display());
+                OsdLine::new(format!("Bug report saved to {}", path.display()))
+            }
+            Err(e) => {
+                tracing::error!("Failed to save the bug report to {}: {}", path.display(), e);
+                OsdLine::warning(format!("Failed to save the bug report: {}", e))
+            }
+        };
+        self.osd.notify(OsdCategory::Media, line);
+    }
 
-                ui.checkbox(&mut self.config.settings.audio.enabled, "Enable Audio");
+    /// Leave hardcore mode as soon as it's disabled in the settings, entering it takes a restart
+    fn sync_hardcore(&mut self) {
+        if self.mips.is_hardcore() && !self.config.settings.system.hardcore {
+            self.mips.set_hardcore(false);
+            self.apply_widescreen_patch();
+            self.apply_ntsc_timing();
+            self.apply_cpu_clock();
+        }
+    }
 
-                if ui.add(
-                    egui::Slider::new(&mut self.config.settings.audio.volume, 0.0..=1.0)
-                        .text("Volume")
-                ).changed() {
-                    self.audio.set_volume(self.config.settings.audio.volume);
-                }
+    fn apply_ntsc_timing(&mut self) {
+        if let Err(e) = self.mips.set_force_ntsc_timing(self.config.settings.system.force_ntsc_timing) {
+            self.osd.notify(OsdCategory::Other, OsdLine::warning(e.to_string()));
+        }
+    }
 
-                ui.separator();
-                ui.heading("System");
-                ui.checkbox(&mut self.config.settings.system.fast_boot, "Skip BIOS");
-                ui.checkbox(&mut self.config.settings.system.auto_save_state, "Auto-save state on exit");
+    fn apply_cpu_clock(&mut self) {
+        if let Err(e) = self.mips.set_cpu_clock(self.config.settings.system.cpu_clock) {
+            self.osd.notify(OsdCategory::Other, OsdLine::warning(e.to_string()));
+        }
+    }
+
+    /// Theme, sizes and animations of the UI to match the accessibility settings, and the OSD to
+    /// match its own
+    fn apply_accessibility(&mut self, ctx: &egui::Context) {
+        let settings = &self.config.settings.accessibility;
+
+        accessibility::apply(ctx, settings);
+        self.osd.reduced_motion = settings.reduced_motion;
+        self.osd.settings = self.config.settings.osd.clone();
+    }
+
+    /// Start, restart or stop the control and memory watch servers to match the settings. Not
+    /// started in safe mode.
+    fn apply_control_server(&mut self) {
+        let settings = self.config.settings.control.clone();
+
+        if !settings.enabled || self.safe_mode {
+            self.control = None;
+        } else if self.control.as_ref().is_none_or(|c| c.port() != settings.port) {
+            // Free the port before binding it again
+            self.control = None;
+            match ControlServer::start(settings.port) {
+                Ok(server) => self.control = Some(server),
+                Err(e) => self.report_server_error("Control server", settings.port, e),
+            }
+        }
+
+        if !settings.publish_watches || self.safe_mode {
+            self.watch_server = None;
+        } else if self.watch_server.as_ref().is_none_or(|w| w.port() != settings.watch_port) {
+            self.watch_server = None;
+            match WatchServer::start(settings.watch_port) {
+                Ok(server) => self.watch_server = Some(server),
+                Err(e) => self.report_server_error("Memory watches", settings.watch_port, e),
+            }
+        }
+    }
+
+    fn report_server_error(&mut self, server: &str, port: u16, e: io::Error) {
+        let line = OsdLine::warning(format!("{} unavailable on port {}: {}", server, port, e));
+        self.osd.notify(OsdCategory::Other, line);
+    }
+
+    /// Send the game's memory watches to the autosplitters
+    fn publish_watches(&self) {
+        let Some(server) = &self.watch_server else {
+            return;
+        };
+        if self.game_settings.memory_watches.is_empty() || !server.has_clients() {
+            return;
+        }
+
+        server.publish(
+            self.game_frames,
+            self.game.as_deref(),
+            &self.game_settings.memory_watches,
+            |address, len| self.mips.read_memory(address, len).ok(),
+        );
+    }
+
+    fn poll_control(&mut self) {
+        let Some(control) = &self.control else {
+            return;
+        };
+
+        for request in control.poll() {
+            let result = self.run_control_call(request.call.clone());
+            request.reply(result);
+        }
+    }
+
+    fn run_control_call(&mut self, call: Call) -> Result<Value, RpcError> {
+        let failed = |e: &dyn std::fmt::Display| RpcError::new(CALL_FAILED, e.to_string());
+
+        match call {
+            Call::Status => Ok(json!({
+                "game": self.game,
+                "serial": self.mips.serial_number(),
+                "running": self.mips.is_running() && !self.halted,
+                "paused": self.paused,
+                "hardcore": self.mips.is_hardcore(),
+                "crowd_mode": format!("{:?}", self.config.settings.control.crowd.mode),
+            })),
+            Call::Pause => {
+                self.paused = true;
+                Ok(Value::Null)
+            }
+            Call::Resume => {
+                self.paused = false;
+                Ok(Value::Null)
+            }
+            Call::LoadGame { game } => {
+                if self.netplay.is_some() {
+                    return Err(failed(&"Can't change games during a netplay session"));
+                }
+                self.switch_game(game);
+                match &self.bios_error {
+                    Some(search) => Err(failed(search)),
+                    None => Ok(Value::Null),
+                }
+            }
+            Call::SaveState { slot } => {
+                let path = self.save_state(slot).map_err(|e| failed(&e))?;
+                Ok(json!({ "path": path.display().to_string() }))
+            }
+            Call::LoadState { slot } => {
+                self.load_state(slot).map_err(|e| failed(&e))?;
+                Ok(Value::Null)
+            }
+            Call::Screenshot => {
+                let path = self.save_screenshot().map_err(|e| failed(&e))?;
+                Ok(json!({ "path": path.display().to_string() }))
+            }
+            Call::ReadMemory { address, length } => {
+                let data = self.mips.read_memory(address, length).map_err(|e| failed(&e))?;
+                Ok(json!({ "data": control::to_hex(&data) }))
+            }
+            Call::WriteMemory { address, data } => {
+                self.mips.write_memory(address, &data).map_err(|e| failed(&e))?;
+                Ok(Value::Null)
+            }
+            Call::Press { button, user } => {
+                self.crowd.submit(&self.config.settings.control.crowd, &user, button, Instant::now())
+                    .map_err(|e| failed(&e.message()))?;
+                Ok(Value::Null)
+            }
+        }
+    }
+
+    /// Power the console on with `game` (relative to the games directory) and its settings
+    fn switch_game(&mut self, game: String) {
+        info!("Switching to {}", game);
+
+        self.games_list.record_played(&game);
+
+        let stored = self.config.game_settings(&game);
+        self.game_settings = stored.clone().unwrap_or_default();
+        self.apply_input_profile(&game);
+        self.game = Some(game);
+        self.restart_game();
+
+        // Games without settings of their own get the ones the database suggests, which takes
+        // the console to know the serial number
+        if stored.is_none() {
+            self.game_settings = auto_game_settings(&self.config, &self.mips);
+            for (port, &controller) in self.game_settings.controllers.iter().enumerate() {
+                self.mips.connect_device(port, controller);
+            }
+        }
+        self.paused = false;
+    }
+
+    /// Switch to the binding profile picked for `game` or its genre in the games list, or back to
+    /// the default bindings
+    fn apply_input_profile(&mut self, game: &str) {
+        if self.safe_mode {
+            return;
+        }
+
+        let profile = self.games_list.input_profile(game);
+        if profile != self.config.input_profile {
+            self.switch_input_profile(profile.as_deref());
+        }
+    }
+
+    fn switch_input_profile(&mut self, name: Option<&str>) {
+        let line = match self.config.use_input_profile(name) {
+            Ok(()) => OsdLine::new(format!("Controls: {}", name.unwrap_or("default bindings"))),
+            Err(e) => {
+                tracing::error!("Failed to switch to the {} bindings: {}", name.unwrap_or("default"), e);
+                OsdLine::warning(format!("Failed to switch bindings: {}", e))
+            }
+        };
+        self.osd.notify(OsdCategory::Other, line);
+        self.waiting_for_key = None;
+        self.waiting_for_gamepad_button = None;
+    }
+
+    /// Title of the game running: the one of the compatibility database, or the file name
+    fn game_title(&self) -> Option<String> {
+        let game = self.game.as_ref()?;
+        let file_title = || {
+            std::path::Path::new(game).file_stem().map_or_else(|| game.clone(), |s| s.to_string_lossy().into_owned())
+        };
+
+        Some(self.mips.game_title().map_or_else(file_title, str::to_string))
+    }
+
+    fn update_title(&mut self, ctx: &egui::Context) {
+        let base = window_title(self.safe_mode);
+        let title = match self.game_title() {
+            Some(game) => format!("{} - {}", game, base),
+            None => base.to_string(),
+        };
+
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    /// Game whose state can be saved or loaded right now
+    fn save_state_game(&self) -> anyhow::Result<String> {
+        if self.netplay.is_some() {
+            anyhow::bail!("Save states are disabled during netplay sessions");
+        }
+        match &self.game {
+            Some(game) if self.mips.is_running() && !self.halted => Ok(game.clone()),
+            _ => anyhow::bail!("No game running"),
+        }
+    }
+
+    /// Save the state of the current game in `slot`, returns the file it went to
+    fn save_state(&mut self, slot: u32) -> anyhow::Result<std::path::PathBuf> {
+        let game = self.save_state_game()?;
+
+        Ok(self.save_states.save(&self.mips, &game, slot)?)
+    }
+
+    fn load_state(&mut self, slot: u32) -> anyhow::Result<()> {
+        let game = self.save_state_game()?;
+        self.save_states.load(&mut self.mips, &game, slot)?;
+        self.watchdog.reset();
+
+        Ok(())
+    }
+
+    fn load_auto_state(&mut self, index: u32) {
+        let result = self.save_state_game()
+            .and_then(|game| Ok(self.save_states.load_auto(&mut self.mips, &game, index)?));
+        let line = match result {
+            Ok(()) => {
+                self.watchdog.reset();
+                OsdLine::new("Auto-state loaded")
+            }
+            Err(e) => OsdLine::warning(format!("Failed to load the auto-state: {}", e)),
+        };
+        self.osd.notify(OsdCategory::SaveStates, line);
+    }
+
+    /// Save or load a state from the menu or a hotkey, the outcome is shown on the OSD
+    fn save_state_action(&mut self, load: bool) {
+        let slot = self.save_states.slot;
+        let line = if load {
+            match self.load_state(slot) {
+                Ok(()) => OsdLine::new(format!("State loaded from slot {}", slot)),
+                Err(e) => OsdLine::warning(format!("Failed to load the state: {}", e)),
+            }
+        } else {
+            match self.save_state(slot) {
+                Ok(_) => OsdLine::new(format!("State saved in slot {}", slot)),
+                Err(e) => OsdLine::warning(format!("Failed to save the state: {}", e)),
+            }
+        };
+        self.osd.notify(OsdCategory::SaveStates, line);
+    }
+
+    fn undo_save_state(&mut self) {
+        let result = self.save_state_game()
+            .and_then(|game| Ok(self.save_states.undo(&mut self.mips, &game)?));
+        let line = match result {
+            Ok(message) => {
+                // Undoing a load brings another state back
+                self.watchdog.reset();
+                OsdLine::new(message)
+            }
+            Err(e) => OsdLine::warning(format!("Failed to undo: {}", e)),
+        };
+        self.osd.notify(OsdCategory::SaveStates, line);
+    }
+
+    /// Ports with a PlayStation Mouse plugged in
+    fn mouse_ports(&self) -> Vec<usize> {
+        (0..2).filter(|&port| self.mips.device_type(port) == ControllerType::Mouse).collect()
+    }
+
+    /// Ports with a GunCon or a Justifier plugged in
+    fn lightgun_ports(&self) -> Vec<usize> {
+        let lightgun = |port| matches!(self.mips.device_type(port), ControllerType::GunCon | ControllerType::Justifier);
+
+        (0..2).filter(|&port| lightgun(port)).collect()
+    }
+
+    fn toggle_mouse_capture(&mut self, ctx: &egui::Context) {
+        if self.mouse.is_captured() {
+            self.mouse.release(ctx);
+            return;
+        }
+
+        let line = if self.netplay.is_some() {
+            OsdLine::warning("The mouse can't be captured during netplay sessions")
+        } else if self.mouse_ports().is_empty() {
+            OsdLine::warning("No PlayStation Mouse connected, plug one in the input configuration")
+        } else {
+            self.mouse.capture(ctx, self.config.settings.input.mouse_capture);
+            OsdLine::new("Mouse captured, F8 releases it")
+        };
+        self.osd.notify(OsdCategory::Other, line);
+    }
+
+    fn handle_hotkeys(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+
+        if ctx.input(|i| i.key_pressed(SAVE_STATE_KEY)) {
+            self.save_state_action(false);
+        }
+        if ctx.input(|i| i.key_pressed(NEXT_SLOT_KEY)) {
+            self.save_states.slot = (self.save_states.slot + 1) % save_states::MENU_SLOTS;
+            self.osd.notify(OsdCategory::SaveStates, OsdLine::new(format!("Save state slot {}", self.save_states.slot)));
+        }
+        if ctx.input(|i| i.key_pressed(LOAD_STATE_KEY)) {
+            self.save_state_action(true);
+        }
+        if ctx.input(|i| i.key_pressed(UNDO_STATE_KEY)) {
+            self.undo_save_state();
+        }
+        if ctx.input(|i| i.key_pressed(MOUSE_CAPTURE_KEY)) {
+            self.toggle_mouse_capture(ctx);
+        }
+        if ctx.input(|i| i.key_pressed(FULLSCREEN_KEY)) {
+            self.set_fullscreen(ctx, !self.is_fullscreen(ctx));
+        }
+        if ctx.input(|i| i.key_pressed(SCREENSHOT_KEY)) {
+            self.take_screenshot();
+        }
+    }
+
+    /// The window can also leave fullscreen through the system, ask it
+    fn is_fullscreen(&self, ctx: &egui::Context) -> bool {
+        ctx.input(|i| i.viewport().fullscreen).unwrap_or(self.config.settings.video.fullscreen)
+    }
+
+    fn set_fullscreen(&mut self, ctx: &egui::Context, fullscreen: bool) {
+        self.config.settings.video.fullscreen = fullscreen;
+
+        if fullscreen {
+            // A fullscreen window can't be moved to another display
+            if self.is_fullscreen(ctx) {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(false));
+            }
+            self.fullscreen_step = Some(FullscreenStep::Move);
+        } else {
+            self.fullscreen_step = None;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(false));
+        }
+    }
+
+    fn step_fullscreen(&mut self, ctx: &egui::Context) {
+        match self.fullscreen_step.take() {
+            Some(FullscreenStep::Move) => {
+                self.displays = display::list();
+
+                let Some(name) = self.config.settings.video.fullscreen_display.clone() else {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(true));
+                    return;
+                };
+                let Some(target) = display::find(&self.displays, &name) else {
+                    self.osd.notify(
+                        OsdCategory::Other,
+                        OsdLine::warning(format!("Display {} isn't connected, using this one", name)),
+                    );
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(true));
+                    return;
+                };
+
+                // egui positions the window in points of the display it's on now, which may not
+                // have the scale factor of the target
+                let pixels_per_point = ctx.pixels_per_point();
+                let size = ctx.input(|i| i.viewport().outer_rect)
+                    .map_or(egui::Vec2::ZERO, |rect| rect.size() * pixels_per_point);
+                let pos = display::centered(target, size);
+                ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(
+                    (pos.to_vec2() / pixels_per_point).to_pos2(),
+                ));
+                self.fullscreen_step = Some(FullscreenStep::Enter);
+            }
+            Some(FullscreenStep::Enter) => ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(true)),
+            None => {}
+        }
+    }
+
+    /// Refresh period of the display the window is on, 60 Hz if unknown
+    fn refresh_period(&self, ctx: &egui::Context) -> Duration {
+        let pixels_per_point = ctx.pixels_per_point();
+        let center = ctx.input(|i| i.viewport().outer_rect)
+            .map(|rect| (rect.center().to_vec2() * pixels_per_point).to_pos2());
+
+        let hz = center
+            .and_then(|center| display::at(&self.displays, center))
+            .map(|display| display.refresh_hz)
+            .filter(|&hz| hz > 0.0)
+            .unwrap_or(60.0);
+
+        Duration::from_secs_f64(1.0 / f64::from(hz))
+    }
+
+    fn render_fullscreen_settings(&mut self, ui: &mut egui::Ui) {
+        let mut fullscreen = self.is_fullscreen(ui.ctx());
+        if ui.checkbox(&mut fullscreen, "Fullscreen (F11)").changed() {
+            self.set_fullscreen(ui.ctx(), fullscreen);
+        }
+
+        let selected = match &self.config.settings.video.fullscreen_display {
+            None => "Current display".to_string(),
+            Some(name) => match display::find(&self.displays, name) {
+                Some(display) => display.label(),
+                None => format!("{} (not connected)", name),
+            },
+        };
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Fullscreen on");
+            let combo = egui::ComboBox::from_id_salt("fullscreen_display")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    let choice = &mut self.config.settings.video.fullscreen_display;
+                    changed |= ui.selectable_value(choice, None, "Current display").changed();
+                    for display in &self.displays {
+                        changed |= ui.selectable_value(choice, Some(display.name.clone()), display.label()).changed();
+                    }
+                });
+            // Displays come and go, look again each time the list is opened
+            if combo.response.clicked() {
+                self.displays = display::list();
+            }
+            combo.response.on_hover_text(
+                "Display the game goes fullscreen on, for instance the TV. The window keeps the \
+                 scale and the refresh rate of each display.",
+            );
+        });
+
+        if changed && fullscreen {
+            self.set_fullscreen(ui.ctx(), true);
+        }
+    }
+
+    /// Screenshot from the stage picked in the settings. The console output is saved right away,
+    /// the picture presented once the next frame is drawn.
+    fn eject_disc(&mut self) {
+        self.mips.eject_disc();
+        self.osd.notify(OsdCategory::Media, OsdLine::new("Disc ejected"));
+    }
+
+    /// Put `disc` (relative to the games directory) in the drive in place of the current one
+    fn swap_disc(&mut self, disc: &str) {
+        let line = match self.mips.swap_disc(disc) {
+            Ok(()) => {
+                self.disc = Some(disc.to_string());
+                OsdLine::new(format!("Inserted {}", disc))
+            }
+            Err(e) => {
+                tracing::error!("Failed to insert {}: {}", disc, e);
+                OsdLine::warning(format!("Failed to insert {}: {}", disc, e))
+            }
+        };
+        self.osd.notify(OsdCategory::Media, line);
+    }
+
+    fn take_screenshot(&mut self) {
+        match self.config.settings.video.screenshot_source {
+            ScreenshotSource::Console => {
+                let result = self.save_screenshot();
+                self.notify_screenshot(result);
+            }
+            source => self.screenshot = Some(PendingScreenshot::Requested(source)),
+        }
+    }
+
+    fn notify_screenshot(&mut self, result: anyhow::Result<std::path::PathBuf>) {
+        let line = match result {
+            Ok(path) => OsdLine::new(format!("Screenshot saved to {}", path.display())),
+            Err(e) => OsdLine::warning(format!("Failed to save the screenshot: {}", e)),
+        };
+        self.osd.notify(OsdCategory::Media, line);
+    }
+
+    /// Ask for the picture of the frame being drawn, at the end of `update`
+    fn capture_screenshot(&mut self, ctx: &egui::Context, source: ScreenshotSource) {
+        let crop = match source {
+            ScreenshotSource::Presented => match self.game_rect {
+                Some(rect) if self.cached_frame.is_some() => Some(rect),
+                _ => {
+                    self.notify_screenshot(Err(anyhow::anyhow!("No frame to save")));
+                    return;
+                }
+            },
+            _ => None,
+        };
+
+        ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::default()));
+        self.screenshot = Some(PendingScreenshot::Capturing(crop));
+    }
+
+    /// Save the screenshot asked by `capture_screenshot` once the image comes in
+    fn receive_screenshot(&mut self, ctx: &egui::Context) {
+        let Some(PendingScreenshot::Capturing(crop)) = self.screenshot else {
+            return;
+        };
+        let image = ctx.input(|i| {
+            i.events.iter().find_map(|event| match event {
+                egui::Event::Screenshot { image, .. } => Some(image.clone()),
+                _ => None,
+            })
+        });
+        let Some(image) = image else {
+            return;
+        };
+        self.screenshot = None;
+
+        let image = match crop {
+            Some(rect) => image.region(&rect, Some(ctx.pixels_per_point())),
+            None => (*image).clone(),
+        };
+        let frame = CpuFrame {
+            pixels: image.pixels.iter()
+                .map(|c| u32::from_be_bytes([0xff, c.r(), c.g(), c.b()]))
+                .collect(),
+            width: image.size[0] as u32,
+            height: image.size[1] as u32,
+            // Scaled and filtered
+            format: PixelFormat::Rgb888,
+            aspect: None,
+        };
+
+        let result = self.write_screenshot(&frame);
+        self.notify_screenshot(result);
+    }
+
+    /// Save the last frame output by the console, returns where it went
+    fn save_screenshot(&self) -> anyhow::Result<std::path::PathBuf> {
+        let Some(cached) = &self.cached_frame else {
+            anyhow::bail!("No frame to save");
+        };
+
+        let frame = CpuFrame {
+            pixels: cached.rgba_pixels
+                .chunks_exact(4)
+                .map(|p| u32::from_be_bytes([0xff, p[0], p[1], p[2]]))
+                .collect(),
+            width: cached.width as u32,
+            height: cached.height as u32,
+            format: cached.format,
+            aspect: cached.aspect,
+        };
+
+        self.write_screenshot(&frame)
+    }
+
+    fn write_screenshot(&self, frame: &CpuFrame) -> anyhow::Result<std::path::PathBuf> {
+        let path = config::screenshot_path(self.game.as_deref());
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, frame.to_png()?)?;
+        info!("Screenshot saved to {}", path.display());
+
+        Ok(path)
+    }
+
+    /// Plug `controller` in `port` right away and remember it for the current game
+    fn set_controller(&mut self, port: usize, controller: ControllerType) {
+        self.mips.connect_device(port, controller);
+
+        if self.mips.is_running() && self.mips.device_type(port) != controller {
+            self.osd.notify(
+                OsdCategory::Other,
+                OsdLine::warning(format!("{} isn't supported yet", controller.name())),
+            );
+            return;
+        }
+
+        self.game_settings.controllers[port] = controller;
+        self.save_game_settings();
+    }
+
+    /// Load the game's startup script, if it has one
+    fn apply_startup_script(&mut self) {
+        let Some(game) = &self.game else {
+            return;
+        };
+        if self.safe_mode {
+            return;
+        }
+        let path = config::game_startup_script(game);
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return;
+        };
+
+        let result = StartupScript::parse(&text).and_then(|script| self.mips.set_startup_script(script));
+        if let Err(e) = result {
+            tracing::error!("Failed to load {}: {}", path.display(), e);
+            self.osd.notify(OsdCategory::Patches, OsdLine::warning(format!("Startup script not loaded: {}", e)));
+        }
+    }
+
+    fn set_exec_watch(&mut self, settings: Option<ExecWatchSettings>) {
+        self.exec_watch = settings;
+        self.mips.set_exec_watch(settings);
+    }
+
+    /// Log the code executed from written RAM, and pause if the watch stopped the emulation
+    fn report_exec_watch_hits(&mut self) {
+        for hit in self.mips.take_exec_watch_hits() {
+            let writer = match hit.writer {
+                Some(pc) => format!("the store at {:08x}", pc),
+                None => "DMA".to_string(),
+            };
+            info!(
+                "Executing {} word(s) at {:08x} written by {} {} frame(s) ago",
+                hit.words, hit.pc, writer, hit.age
+            );
+
+            if hit.stopped {
+                self.paused = true;
+                self.osd.notify(
+                    OsdCategory::Other,
+                    OsdLine::warning(format!("Paused before {:08x}, written by {}", hit.pc, writer)),
+                );
+            }
+        }
+    }
+
+    /// The core panicked in the middle of a frame, its state can't be trusted anymore
+    fn report_crash(&mut self, payload: Box<dyn Any + Send>) {
+        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown error".to_string());
+        tracing::error!("Emulator crashed: {}", message);
+
+        self.halted = true;
+        self.stall = Some(StallReport {
+            title: "The emulator crashed".to_string(),
+            details: format!("{}\n\n{}\n{}", message, self.game_description(), self.mips.diagnostics()),
+            fatal: true,
+        });
+    }
+
+    /// The watchdog thinks the game is stuck waiting for something that will never happen
+    fn report_stall(&mut self) {
+        let (low, high) = self.watchdog.pc_range();
+        tracing::warn!("Game stuck between {:08x} and {:08x}, pausing", low, high);
+
+        self.paused = true;
+        self.stall = Some(StallReport {
+            title: "The game seems stuck".to_string(),
+            details: format!(
+                "Nothing drawn for {:.0} seconds, the CPU is looping between {:08x} and {:08x}\n\n{}\n{}",
+                self.watchdog.stalled_for().as_secs_f32(),
+                low,
+                high,
+                self.game_description(),
+                self.mips.diagnostics(),
+            ),
+            fatal: false,
+        });
+    }
+
+    /// First line of the diagnostics: what was running
+    fn game_description(&self) -> String {
+        format!(
+            "Game: {} ({})",
+            self.game.as_deref().unwrap_or("none"),
+            self.mips.serial_number().as_deref().unwrap_or("no serial"),
+        )
+    }
+
+    /// Configuration as it was when the app started, to undo changes
+    fn reload_config(&self) -> anyhow::Result<ConfigManager> {
+        if self.safe_mode {
+            Ok(ConfigManager::with_defaults())
+        } else {
+            ConfigManager::new()
+        }
+    }
+
+    fn save_game_settings(&self) {
+        if let Some(game) = &self.game
+            && let Err(e) = self.config.save_game_settings(game, &self.game_settings)
+        {
+            tracing::error!("Failed to save game settings: {}", e);
+        }
+    }
+
+    fn sync_saves(&mut self) {
+        if let Some(game) = &self.game {
+            self.cloud_status = sync_status(cloud::sync_saves(&self.config.settings.cloud, game));
+        }
+    }
+
+    fn poll_netplay(&mut self) {
+        let Some(np) = &mut self.netplay else {
+            return;
+        };
+
+        np.session.poll();
+
+        if let (Some(announcer), Some(announce)) = (&mut np.announcer, np.session.announcement()) {
+            announcer.tick(&announce);
+        }
+
+        let started = np.session.take_start();
+        let closed = match np.session.state() {
+            SessionState::Closed(reason) => Some(reason.clone()),
+            _ => None,
+        };
+
+        if let Some(start) = started {
+            // Both sides have to start from the same state
+            self.restart_game();
+            // Only the digital buttons go over the network, and both sides need the same pads
+            self.mips.connect_device(0, ControllerType::DigitalPad);
+            self.mips.connect_device(1, ControllerType::DigitalPad);
+            for (slot, card) in start.memory_cards.into_iter().enumerate() {
+                if let Err(e) = self.mips.set_memory_card_image(slot, card) {
+                    tracing::error!("Failed to load netplay memory card {}: {}", slot, e);
+                }
+            }
+            // Without the host's state the peers would emulate different games
+            if let Some(state) = start.save_state
+                && let Err(e) = self.mips.load_state(&state)
+            {
+                tracing::error!("Failed to load the netplay save state: {}", e);
+                if let Some(np) = &mut self.netplay {
+                    np.session.close_with(format!("Can't load the host's state: {}", e));
+                }
+            }
+            self.paused = false;
+
+            if let Some(np) = &mut self.netplay {
+                np.applied = [PadState::default(); 2];
+                np.desync_reported = false;
+            }
+        }
+
+        if let Some(np) = &mut self.netplay {
+            for line in &np.session.chat_log()[np.chat_seen..] {
+                self.osd.notify(OsdCategory::Chat, OsdLine::new(format!("{}: {}", line.name, line.text)));
+            }
+            np.chat_seen = np.session.chat_log().len();
+
+            if let Some(frame) = np.session.desync_frame()
+                && !np.desync_reported {
+                np.desync_reported = true;
+                self.osd.notify_for(
+                    OsdCategory::Chat,
+                    OsdLine::warning(format!("Desync detected on frame {}, restart the session", frame)),
+                    DESYNC_DURATION,
+                );
+            }
+        }
+
+        if let Some(reason) = closed {
+            self.netplay_form.status = Some(reason);
+            self.netplay = None;
+        }
+    }
+
+    /// Join the session once the lobby server gave the host's address
+    fn poll_lobby_lookup(&mut self) {
+        let Some((lookup, spectate)) = &self.netplay_form.lookup else {
+            return;
+        };
+        let Some(result) = lookup.poll() else {
+            return;
+        };
+
+        let spectate = *spectate;
+        self.netplay_form.lookup = None;
+
+        match result {
+            Ok((transport, host)) => {
+                let join = if spectate { NetplayJoin::Spectator(host) } else { NetplayJoin::Player(host) };
+                self.start_session(Box::new(transport), join);
+            }
+            Err(e) => self.netplay_form.status = Some(format!("Can't connect: {}", e)),
+        }
+    }
+
+    /// Memory cards and console state, what the peers start a session from
+    fn netplay_blobs(&self) -> Vec<Blob> {
+        let mut blobs: Vec<Blob> = (0..2)
+            .filter_map(|slot| {
+                let data = self.mips.memory_card_image(slot).ok().flatten()?;
+                Some(Blob { kind: BlobKind::MemoryCard(slot as u8), data })
+            })
+            .collect();
+        if let Ok(data) = self.mips.save_state() {
+            blobs.push(Blob { kind: BlobKind::SaveState, data });
+        }
+
+        blobs
+    }
+
+    /// Hosts keep a recent state for the spectators joining later to start from. Spectators
+    /// start with no buttons held, so the checkpoint waits for a frame where none are.
+    fn netplay_checkpoint(&mut self) {
+        let idle = PadState::default();
+        if !self
+            .netplay
+            .as_ref()
+            .is_some_and(|np| np.session.wants_checkpoint() && np.applied.iter().all(|&pad| pad == idle))
+        {
+            return;
+        }
+
+        let blobs = self.netplay_blobs();
+        if let Some(np) = &mut self.netplay {
+            np.session.set_checkpoint(blobs);
+        }
+    }
+
+    fn start_netplay(&mut self, join: NetplayJoin) {
+        let port = match join {
+            NetplayJoin::Host => match self.netplay_form.host_port.parse::<u16>() {
+                Ok(p) => p,
+                Err(_) => {
+                    self.netplay_form.status = Some("Invalid port".to_string());
+                    return;
+                }
+            },
+            _ => 0,
+        };
+
+        let lobby = match self.netplay_form.lobby_server.trim() {
+            "" => None,
+            addr => match resolve_netplay_addr(addr, LOBBY_PORT) {
+                Some(addr) => Some(addr),
+                None => {
+                    self.netplay_form.status = Some("Can't resolve lobby server".to_string());
+                    return;
+                }
+            },
+        };
+
+        let name = self.netplay_form.name.as_str();
+        let relay = self.netplay_form.allow_relay;
+
+        // Lobby codes are turned into the host's address by the lobby server
+        let transport: io::Result<Box<dyn Transport>> = match (&join, lobby) {
+            (NetplayJoin::Host, Some(lobby)) => LobbyTransport::host(port, lobby, name, relay)
+                .map(|t| Box::new(t) as Box<dyn Transport>),
+            // The server can take a while to answer, the session starts in `poll_lobby_lookup`
+            (NetplayJoin::Lobby { code, spectate }, Some(lobby)) => {
+                match LobbyJoin::start(lobby, code, relay) {
+                    Ok(lookup) => {
+                        self.netplay_form.lookup = Some((lookup, *spectate));
+                        self.netplay_form.status = Some("Looking the code up...".to_string());
+                    }
+                    Err(e) => self.netplay_form.status = Some(format!("Can't connect: {}", e)),
+                }
+                return;
+            }
+            (NetplayJoin::Lobby { .. }, None) => {
+                self.netplay_form.status = Some("Lobby codes need a lobby server".to_string());
+                return;
+            }
+            _ => UdpTransport::bind(port).map(|t| Box::new(t) as Box<dyn Transport>),
+        };
+
+        match transport {
+            Ok(transport) => self.start_session(transport, join),
+            Err(e) => self.netplay_form.status = Some(format!("Can't connect: {}", e)),
+        }
+    }
+
+    fn start_session(&mut self, transport: Box<dyn Transport>, join: NetplayJoin) {
+        let name = self.netplay_form.name.as_str();
+        let game = self.game.clone();
+
+        let (session, announcer) = match join {
+            NetplayJoin::Host => {
+                let delay = if self.netplay_form.auto_delay {
+                    InputDelay::Auto
+                } else {
+                    InputDelay::Fixed(self.netplay_form.fixed_delay)
+                };
+
+                let announcer = match Announcer::new() {
+                    Ok(a) => Some(a),
+                    Err(e) => {
+                        tracing::warn!("Session won't be visible on the LAN: {}", e);
+                        None
+                    }
+                };
+
+                // Everyone starts with the host's memory cards and console state
+                let blobs = self.netplay_blobs();
+
+                (NetplaySession::host(transport, name, game, delay, blobs), announcer)
+            }
+            NetplayJoin::Player(addr) => (NetplaySession::join(transport, addr, name, game), None),
+            NetplayJoin::Spectator(addr) => (NetplaySession::spectate(transport, addr, name, game), None),
+            NetplayJoin::Lobby { .. } => unreachable!(),
+        };
+
+        // Stopping in the middle of a frame would desync the peers
+        self.set_exec_watch(None);
+
+        self.netplay_form.status = None;
+        self.netplay = Some(NetplayState {
+            session,
+            announcer,
+            local_pad: PadState::default(),
+            applied: [PadState::default(); 2],
+            chat_open: false,
+            chat_closing: false,
+            chat_input: String::new(),
+            chat_seen: 0,
+            desync_reported: false,
+        });
+    }
+
+    fn render_menu_bar(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open ROM...").clicked() {
+                        // TODO: File dialog
+                        ui.close_menu();
+                    }
+                    if ui.button("Games...").clicked() {
+                        self.games_list.open = true;
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("Exit").clicked() {
+                        // Save settings before exit
+                        let _ = self.config.save_settings();
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+
+                ui.menu_button("Emulation", |ui| {
+                    let pause_text = if self.paused { "Resume" } else { "Pause" };
+                    if ui.add_enabled(!self.halted, egui::Button::new(pause_text)).clicked() {
+                        self.paused = !self.paused;
+                        ui.close_menu();
+                    }
+                    if ui.button("Reset").clicked() {
+                        // TODO: Reset emulator
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    let can_save = self.save_state_game().is_ok();
+                    ui.menu_button(format!("Slot {}", self.save_states.slot), |ui| {
+                        for slot in 0..save_states::MENU_SLOTS {
+                            let response = ui.selectable_value(&mut self.save_states.slot, slot, format!("Slot {}", slot));
+                            if let Some(game) = &self.game {
+                                let path = config::save_state_path(game, slot);
+                                response.clone().on_hover_ui(|ui| {
+                                    match self.save_states.thumbnails.get(ui.ctx(), &path) {
+                                        Some(texture) => {
+                                            ui.image((texture.id(), texture.size_vec2()));
+                                        }
+                                        None if path.exists() => {
+                                            ui.label("No picture");
+                                        }
+                                        None => {
+                                            ui.label("Empty");
+                                        }
+                                    }
+                                });
+                            }
+                            if response.clicked() {
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    if ui.add_enabled(can_save, egui::Button::new("Save State"))
+                        .on_hover_text(format!("{:?}", SAVE_STATE_KEY))
+                        .clicked()
+                    {
+                        self.save_state_action(false);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(can_save, egui::Button::new("Load State"))
+                        .on_hover_text(format!("{:?}", LOAD_STATE_KEY))
+                        .clicked()
+                    {
+                        self.save_state_action(true);
+                        ui.close_menu();
+                    }
+                    let auto_states = match &self.game {
+                        Some(game) if can_save => save_states::auto_states(game),
+                        _ => Vec::new(),
+                    };
+                    ui.add_enabled_ui(!auto_states.is_empty(), |ui| {
+                        ui.menu_button("Load Auto-State", |ui| {
+                            for (index, written) in auto_states {
+                                let age = written.elapsed().unwrap_or_default().as_secs() / 60;
+                                if ui.button(format!("{} min ago", age)).clicked() {
+                                    self.load_auto_state(index);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    });
+                    let undo = self.game.as_deref().and_then(|game| self.save_states.undo_label(game));
+                    if let Some(label) = undo
+                        && ui.add_enabled(can_save, egui::Button::new(label))
+                            .on_hover_text(format!("{:?}", UNDO_STATE_KEY))
+                            .clicked()
+                    {
+                        self.undo_save_state();
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.add_enabled(self.cached_frame.is_some(), egui::Button::new("Screenshot"))
+                        .on_hover_text(format!("{:?}", SCREENSHOT_KEY))
+                        .clicked()
+                    {
+                        self.take_screenshot();
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(self.game.is_some(), egui::Button::new("Game Properties..."))
+                        .clicked()
+                    {
+                        self.show_game_properties = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Memory Cards...").clicked() {
+                        self.workspace.open(Tool::MemoryCards);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(!self.mips.is_hardcore(), egui::Button::new("RAM Search..."))
+                        .on_disabled_hover_text("Disabled in hardcore mode")
+                        .clicked()
+                    {
+                        self.workspace.open(Tool::RamSearch);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(!self.safe_mode, egui::Button::new("Netplay...")).clicked() {
+                        self.show_netplay = true;
+                        ui.close_menu();
+                    }
+                });
+
+                // The peers would have to swap at the same frame
+                ui.add_enabled_ui(self.game.is_some() && self.netplay.is_none(), |ui| {
+                    ui.menu_button("Change Disc", |ui| {
+                        if ui.add_enabled(!self.mips.is_shell_open(), egui::Button::new("Eject")).clicked() {
+                            self.eject_disc();
+                            ui.close_menu();
+                        }
+                        ui.separator();
+
+                        let discs = match (&self.game_discs, &self.game) {
+                            (Some(discs), _) => discs.clone(),
+                            (None, Some(game)) => library::game_discs(game),
+                            (None, None) => Vec::new(),
+                        };
+                        let current = self.disc.as_ref().or(self.game.as_ref()).cloned();
+                        for (n, disc) in discs.iter().enumerate() {
+                            let inserted = current.as_ref() == Some(disc) && !self.mips.is_shell_open();
+                            if ui.selectable_label(inserted, format!("Disc {}", n + 1))
+                                .on_hover_text(disc)
+                                .clicked()
+                            {
+                                self.swap_disc(disc);
+                                ui.close_menu();
+                            }
+                        }
+                        self.game_discs = Some(discs);
+                    });
+                });
+
+                ui.menu_button("Options", |ui| {
+                    if ui.button("Settings...").clicked() {
+                        self.show_settings = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Input Configuration...").clicked() {
+                        self.show_input_config = true;
+                        ui.close_menu();
+                    }
+                    let profiles = self.config.input_profiles().to_vec();
+                    ui.add_enabled_ui(!profiles.is_empty(), |ui| {
+                        ui.menu_button("Controls Profile", |ui| {
+                            let current = self.config.input_profile.clone();
+                            let names = std::iter::once(None).chain(profiles.into_iter().map(Some));
+                            for name in names {
+                                let label = name.as_deref().unwrap_or("Default bindings");
+                                if ui.selectable_label(name == current, label).clicked() {
+                                    if name != current {
+                                        self.switch_input_profile(name.as_deref());
+                                    }
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    });
+                    if ui.button("Controller Test...").clicked() {
+                        self.controller_test.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Latency Calibration...").clicked() {
+                        self.latency_calibration.open = true;
+                        ui.close_menu();
+                    }
+                });
+
+                ui.menu_button("Debug", |ui| {
+                    if ui.checkbox(&mut self.config.settings.video.perf_overlay, "Performance Overlay")
+                        .on_hover_text("Per-frame emulation statistics, handy for bug reports")
+                        .changed()
+                        && let Err(e) = self.config.save_settings()
+                    {
+                        tracing::error!("Failed to save settings: {}", e);
+                    }
+                    if ui.checkbox(&mut self.config.settings.video.overdraw_heatmap, "Overdraw Heatmap")
+                        .on_hover_text(
+                            "Color each pixel by how many times it was drawn: blue once, then cyan, \
+                             green, yellow, orange, red, white from 8 times. Software renderer only",
+                        )
+                        .changed()
+                        && let Err(e) = self.config.save_settings()
+                    {
+                        tracing::error!("Failed to save settings: {}", e);
+                    }
+                    ui.add_enabled_ui(self.netplay.is_none(), |ui| {
+                        ui.menu_button("Wireframe", |ui| {
+                            for mode in Wireframe::ALL {
+                                ui.radio_value(&mut self.debug_render.wireframe, mode, mode.name());
+                            }
+                        });
+                        ui.checkbox(&mut self.debug_render.no_textures, "Disable Textures")
+                            .on_hover_text("Draw the textured polygons and sprites with their vertex colors");
+                        ui.checkbox(&mut self.debug_render.no_semi_transparency, "Disable Semi-Transparency")
+                            .on_hover_text("Draw the semi-transparent draws opaque. Software renderer only, like the textures");
+                    });
+                    ui.separator();
+
+                    ui.add_enabled_ui(self.netplay.is_none(), |ui| {
+                        let mut enabled = self.exec_watch.is_some();
+                        if ui.checkbox(&mut enabled, "Data Execution Watch")
+                            .on_hover_text("Log the code executed from RAM written since it last ran")
+                            .changed()
+                        {
+                            self.set_exec_watch(enabled.then(ExecWatchSettings::default));
+                        }
+
+                        if let Some(mut settings) = self.exec_watch {
+                            ui.checkbox(&mut settings.break_on_hit, "Pause Before Executing");
+                            ui.checkbox(&mut settings.include_dma, "Include DMA Writes");
+                            if Some(settings) != self.exec_watch {
+                                self.set_exec_watch(Some(settings));
+                            }
+                        }
+                    });
+                    ui.separator();
+
+                    if ui.button("Log...").clicked() {
+                        self.workspace.open(Tool::Log);
+                        ui.close_menu();
+                    }
+                    if ui.button("Block Cache...").clicked() {
+                        self.workspace.open(Tool::BlockCache);
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Workspace", |ui| self.workspace.menu(ui));
+                });
+
+                ui.menu_button("Help", |ui| {
+                    if ui.button("Create Bug Report").clicked() {
+                        self.create_bug_report();
+                        ui.close_menu();
+                    }
+                    if ui.button("About").clicked() {
+                        self.show_about = true;
+                        ui.close_menu();
+                    }
+                });
+
+                // FPS counter and VSync toggle on the right
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(format!("FPS: {:.0}", self.emulation_fps));
+                    if self.mouse.is_captured() {
+                        ui.colored_label(egui::Color32::from_rgb(120, 200, 255), "MOUSE CAPTURED")
+                            .on_hover_text("The mouse drives the PlayStation Mouse, F8 releases it");
+                    }
+                    if self.mips.is_hardcore() {
+                        ui.colored_label(egui::Color32::from_rgb(255, 170, 0), "HARDCORE")
+                            .on_hover_text("Cheats, patches and startup scripts are disabled");
+                    }
+                });
+            });
+        });
+    }
+
+    fn render_game(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            // Use cached frame to prevent flickering
+            if let Some(cached) = &self.cached_frame {
+                // Create ColorImage from cached RGBA data
+                let mut image = ColorImage::from_rgba_unmultiplied(
+                    [cached.width, cached.height],
+                    &cached.rgba_pixels,
+                );
+                // On the picture shown only, the screenshots of the console output don't have it
+                accessibility::filter_picture(self.config.settings.accessibility.color_filter, &mut image.pixels);
+
+                // Update texture
+                let texture_options = if self.config.settings.video.bilinear_filter {
+                    TextureOptions::LINEAR
+                } else {
+                    TextureOptions::NEAREST
+                };
+
+                // Updated in place, the renderer keeps drawing from the same texture
+                match &mut self.game_texture {
+                    Some(texture) => texture.set(image, texture_options),
+                    None => self.game_texture = Some(ctx.load_texture("game_frame", image, texture_options)),
+                }
+
+                if let Some(texture) = &self.game_texture {
+                    // Calculate size to maintain aspect ratio
+                    let available_size = ui.available_size();
+                    let game_aspect = match cached.aspect {
+                        Some(aspect) => aspect,
+                        None if self.config.settings.video.widescreen => 16.0 / 9.0,
+                        None => cached.width as f32 / cached.height as f32,
+                    };
+                    let available_aspect = available_size.x / available_size.y;
+
+                    let display_size = if available_aspect > game_aspect {
+                        egui::vec2(available_size.y * game_aspect, available_size.y)
+                    } else {
+                        egui::vec2(available_size.x, available_size.x / game_aspect)
+                    };
+
+                    // Center the image
+                    let texture_id = texture.id();
+                    let response = ui.centered_and_justified(|ui| {
+                        ui.image(egui::load::SizedTexture::new(
+                            texture_id,
+                            display_size,
+                        ))
+                    });
+                    accessibility::name(&response.inner, egui::WidgetType::Image, "Game picture");
+                    self.game_rect = Some(response.inner.rect);
+                }
+            } else {
+                ui.centered_and_justified(|ui| {
+                    ui.heading("No game loaded");
+                    ui.label("Select File > Open ROM to load a game");
+                });
+            }
+        });
+    }
+
+    fn render_osd(&mut self, ctx: &egui::Context) {
+        let Some(mut rect) = self.game_rect else {
+            return;
+        };
+
+        let mut status = Vec::new();
+
+        if self.config.settings.video.perf_overlay {
+            status.extend(self.perf.lines());
+        }
+
+        if let Some(np) = &mut self.netplay {
+            let session = &np.session;
+
+            if self.netplay_form.show_overlay && session.state() == &SessionState::Running {
+                if let Some(rtt) = session.rtt() {
+                    let ms = rtt.as_millis();
+                    let color = match ms {
+                        0..80 => egui::Color32::from_rgb(96, 255, 96),
+                        80..150 => egui::Color32::from_rgb(255, 224, 96),
+                        _ => egui::Color32::from_rgb(255, 96, 96),
+                    };
+                    let relayed = if session.is_relayed() { " (relayed)" } else { "" };
+                    status.push(OsdLine::colored(format!("Ping {} ms{}", ms, relayed), color));
+                }
+
+                if session.role() != SessionRole::Spectator {
+                    status.push(OsdLine::new(format!("Input delay {} frames", session.input_delay())));
+                    status.push(OsdLine::new(format!("Waited {} frames", session.stalled_frames())));
+                }
+
+                if let Some(frame) = session.desync_frame() {
+                    status.push(OsdLine::warning(format!("Desync on frame {}", frame)));
+                }
+            }
+
+            // Chat prompt at the bottom, notifications go above it
+            if np.chat_open {
+                let mut close = false;
+
+                egui::Area::new(egui::Id::new("netplay_chat"))
+                    .order(egui::Order::Foreground)
+                    .fixed_pos(rect.left_bottom() + egui::vec2(8.0, -32.0))
+                    .show(ctx, |ui| {
+                        let edit = ui.add(egui::TextEdit::singleline(&mut np.chat_input)
+                            .hint_text("Say something (Enter to send, Escape to cancel)")
+                            .desired_width(rect.width() * 0.6));
+                        edit.request_focus();
+
+                        if ui.input(|i| i.key_pressed(Key::Enter)) {
+                            np.session.send_chat(&np.chat_input);
+                            close = true;
+                        } else if ui.input(|i| i.key_pressed(Key::Escape)) {
+                            close = true;
+                        }
+                    });
+
+                if close {
+                    np.chat_open = false;
+                    np.chat_closing = true;
+                    np.chat_input.clear();
+                }
+                rect.max.y -= 36.0;
+            } else if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(CHAT_KEY)) {
+                // Buttons held when the prompt opens would stay pressed while typing
+                np.chat_open = true;
+                np.local_pad = PadState::default();
+            }
+        }
+
+        self.osd.show(ctx, rect, &status);
+    }
+
+    fn render_rewind_settings(&mut self, ui: &mut egui::Ui) {
+        let system = &mut self.config.settings.system;
+
+        ui.checkbox(&mut system.rewind, "Rewind (hold F4)")
+            .on_hover_text(
+                "Keep snapshots of the last moments of play and go back through them while F4 is \
+                 held. Takes some CPU time on every snapshot. Not available during netplay \
+                 sessions and in hardcore mode.",
+            );
+        ui.add_enabled_ui(system.rewind, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Snapshot every");
+                ui.add(egui::DragValue::new(&mut system.rewind_interval).range(1..=60).suffix(" frames"))
+                    .on_hover_text("Fewer frames rewind more smoothly but cost more CPU time and memory");
+                ui.label("keeping up to");
+                ui.add(egui::DragValue::new(&mut system.rewind_buffer_mib).range(16..=2048).suffix(" MiB"));
+            });
+        });
+        if self.mips.is_running() && system.rewind {
+            ui.weak(format!("{:.1} s can be rewound", self.mips.rewind_depth() as f64 / 60.0));
+        }
+    }
+
+    /// Peers compare the pictures to detect desyncs, they all draw at the native resolution
+    fn internal_resolution(&self) -> InternalResolution {
+        match self.netplay {
+            None => self.config.settings.video.internal_resolution,
+            Some(_) => InternalResolution::Native,
+        }
+    }
+
+    /// The GPU's rasterization differs from the console's, netplay peers draw in software
+    fn renderer(&self) -> RendererBackend {
+        match self.netplay {
+            None => self.config.settings.video.renderer,
+            Some(_) => RendererBackend::Software,
+        }
+    }
+
+    /// Precise vertices change the picture the netplay peers compare, they draw without
+    fn pgxp(&self) -> bool {
+        match self.netplay {
+            None => self.game_settings.pgxp.unwrap_or(self.config.settings.video.pgxp),
+            Some(_) => false,
+        }
+    }
+
+    /// Netplay peers draw with the console's colors, their pictures are compared
+    fn dithering(&self) -> bool {
+        match self.netplay {
+            None => self.config.settings.video.dithering,
+            Some(_) => true,
+        }
+    }
+
+    fn true_color(&self) -> bool {
+        match self.netplay {
+            None => self.config.settings.video.true_color,
+            Some(_) => false,
+        }
+    }
+
+    /// Netplay peers blend like the console, their pictures are compared
+    fn accurate_transparency(&self) -> bool {
+        match self.netplay {
+            None => self.config.settings.video.accurate_transparency,
+            Some(_) => true,
+        }
+    }
+
+    /// The workers don't share the texture cache, the peers could draw different pictures
+    fn parallel_draw_areas(&self) -> bool {
+        self.config.settings.video.parallel_draw_areas && self.netplay.is_none()
+    }
+
+    /// The games see different GTE results with the hack: not for netplay, where the peers must
+    /// compute the same, nor for hardcore mode
+    fn widescreen_hack(&self) -> bool {
+        self.game_settings.widescreen_hack && self.netplay.is_none() && !self.mips.is_hardcore()
+    }
+
+    /// The voices captured to SPU RAM depend on the interpolation, netplay peers all use the
+    /// console's
+    fn interpolation(&self) -> Interpolation {
+        match self.netplay {
+            None => self.game_settings.interpolation.unwrap_or(self.config.settings.audio.interpolation),
+            Some(_) => Interpolation::Gaussian,
+        }
+    }
+
+    /// The soloed source alone, or the sources that aren't muted
+    fn audio_mix(&self) -> AudioMix {
+        let audio = &self.config.settings.audio;
+        let mut mix = audio.mix;
+        for source in AudioSource::ALL {
+            let heard = match self.audio_solo {
+                Some(solo) => solo == source,
+                None => !audio.muted_sources.contains(&source),
+            };
+            if !heard {
+                mix.set_volume(source, 0.);
+            }
+        }
+
+        mix
+    }
+
+    fn render_renderer(&mut self, ui: &mut egui::Ui) {
+        if !RendererBackend::Hardware.is_available() {
+            return;
+        }
+
+        let renderer = &mut self.config.settings.video.renderer;
+        let label = |renderer| match renderer {
+            RendererBackend::Software => "Software",
+            RendererBackend::Hardware => "GPU",
+        };
+
+        egui::ComboBox::from_label("Renderer")
+            .selected_text(label(*renderer))
+            .show_ui(ui, |ui| {
+                for option in RendererBackend::ALL {
+                    ui.selectable_value(renderer, option, label(option));
+                }
+            })
+            .response
+            .on_hover_text(
+                "Software draws exactly like the console. GPU draws with the graphics card: \
+                 higher internal resolutions are cheap but the picture can differ slightly. \
+                 Software during netplay, and if no graphics card can be used.",
+            );
+    }
+
+    fn render_internal_resolution(&mut self, ui: &mut egui::Ui) {
+        let resolution = &mut self.config.settings.video.internal_resolution;
+        let label = |resolution: InternalResolution| match resolution {
+            InternalResolution::Native => "Native".to_string(),
+            r => format!("{}x", r.multiplier()),
+        };
+
+        egui::ComboBox::from_label("Internal resolution")
+            .selected_text(label(*resolution))
+            .show_ui(ui, |ui| {
+                for option in InternalResolution::ALL {
+                    ui.selectable_value(resolution, option, label(option));
+                }
+            })
+            .response
+            .on_hover_text(
+                "Draw the polygons at a multiple of the console's resolution for a sharper \
+                 picture. 8x takes a lot of CPU time. Native during netplay.",
+            );
+    }
+
+    fn render_cpu_backend(&mut self, ui: &mut egui::Ui) {
+        let backend = &mut self.config.settings.system.cpu_backend;
+        let label = |backend| match backend {
+            CpuBackend::Interpreter => "Interpreter",
+            CpuBackend::Dynarec => "Recompiler",
+        };
+
+        let changed = ui.horizontal(|ui| {
+            let mut changed = false;
+            egui::ComboBox::from_label("CPU")
+                .selected_text(label(*backend))
+                .show_ui(ui, |ui| {
+                    for option in [CpuBackend::Interpreter, CpuBackend::Dynarec] {
+                        let item = egui::Button::selectable(*backend == option, label(option));
+                        if ui.add_enabled(option.is_available(), item).clicked() {
+                            changed |= *backend != option;
+                            *backend = option;
+                        }
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "The recompiler translates the game code for the host CPU and runs it faster, \
+                     with the same results as the interpreter. Only available on x86-64.",
+                );
+            changed
+        }).inner;
+
+        if changed {
+            self.mips.set_cpu_backend(*backend);
+        }
+
+        let mut percent = self.config.settings.system.cpu_clock.percent();
+        let slider = egui::Slider::new(&mut percent, CpuClock::MIN_PERCENT..=CpuClock::MAX_PERCENT)
+            .step_by(10.0)
+            .suffix("%")
+            .text("CPU clock");
+        let clock = ui.add_enabled(!self.mips.is_hardcore(), slider)
+            .on_hover_text(
+                "Above 100% the games that slow down when there's a lot on screen get more time \
+                 per frame, below they run slower. The video and the sound keep their speed. Some \
+                 games time themselves on the CPU and run too fast. Not available in hardcore \
+                 mode.",
+            );
+        if clock.changed() {
+            self.config.settings.system.cpu_clock = CpuClock::from_percent(percent);
+            self.apply_cpu_clock();
+        }
+    }
+
+    fn render_frame_skip(&mut self, ui: &mut egui::Ui) {
+        let frame_skip = &mut self.config.settings.video.frame_skip;
+        let label = |frame_skip| match frame_skip {
+            FrameSkip::Off => "Off",
+            FrameSkip::Auto => "Auto",
+            FrameSkip::Fixed(_) => "Fixed",
+        };
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Frame skip")
+                .selected_text(label(*frame_skip))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(frame_skip, FrameSkip::Off, label(FrameSkip::Off));
+                    ui.selectable_value(frame_skip, FrameSkip::Auto, label(FrameSkip::Auto));
+                    let fixed = matches!(frame_skip, FrameSkip::Fixed(_));
+                    if ui.selectable_label(fixed, label(FrameSkip::Fixed(2))).clicked() && !fixed {
+                        *frame_skip = FrameSkip::Fixed(2);
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Skip drawing some frames to keep the game at full speed on slow machines. \
+                     Auto skips while the emulation runs late.",
+                );
+
+            if let FrameSkip::Fixed(n) = frame_skip {
+                ui.label("Draw 1 frame of");
+                ui.add(egui::DragValue::new(n).range(2..=6));
+            }
+        });
+    }
+
+    fn render_mouse_settings(&mut self, ui: &mut egui::Ui) {
+        let input = &mut self.config.settings.input;
+        let label = |mode| match mode {
+            MouseCaptureMode::Relative => "Relative",
+            MouseCaptureMode::Absolute => "Absolute",
+        };
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Mouse capture (F8)")
+                .selected_text(label(input.mouse_capture))
+                .show_ui(ui, |ui| {
+                    for mode in [MouseCaptureMode::Relative, MouseCaptureMode::Absolute] {
+                        ui.selectable_value(&mut input.mouse_capture, mode, label(mode));
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "How the PlayStation Mouse follows the mouse while it's captured. Relative \
+                     uses the motion of the mouse itself. Absolute follows the hidden cursor over \
+                     the picture, for games drawing a pointer and remote desktops. Takes effect \
+                     the next time the mouse is captured.",
+                );
+            ui.add(egui::Slider::new(&mut input.mouse_sensitivity, 0.1..=4.0).text("Sensitivity"));
+        });
+    }
+
+    fn render_latency_compensation(&mut self, ui: &mut egui::Ui) {
+        let input = &mut self.config.settings.input;
+        let (audio_ms, video_ms) = (input.audio_latency_ms, input.video_latency_ms);
+        let label = |compensation| match compensation {
+            LatencyCompensation::Off => "Off".to_string(),
+            LatencyCompensation::Audio => format!("Audio ({:.0} ms)", audio_ms),
+            LatencyCompensation::Video => format!("Video ({:.0} ms)", video_ms),
+        };
+        let labels = [LatencyCompensation::Off, LatencyCompensation::Audio, LatencyCompensation::Video]
+            .map(|compensation| (compensation, label(compensation)));
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Latency compensation")
+                .selected_text(label(input.latency_compensation))
+                .show_ui(ui, |ui| {
+                    for (compensation, text) in labels {
+                        ui.selectable_value(&mut input.latency_compensation, compensation, text);
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Latch the gamepad buttons earlier by the offset measured for the sound or \
+                     the picture, for rhythm games. Only the part of the offset within a frame \
+                     can be made up for.",
+                );
+            if ui.button("Calibrate...").clicked() {
+                self.latency_calibration.open = true;
+            }
+        });
+    }
+
+    fn render_accessibility_settings(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.config.settings.accessibility;
+
+        let mut changed = ui.checkbox(&mut settings.high_contrast, "High contrast")
+            .on_hover_text("Black and white menus and windows, with bright borders around the buttons and the fields.")
+            .changed();
+        changed |= ui.checkbox(&mut settings.large_targets, "Larger buttons")
+            .on_hover_text("Bigger buttons, checkboxes and sliders, further apart, easier to hit.")
+            .changed();
+        changed |= ui.checkbox(&mut settings.reduced_motion, "Reduce motion")
+            .on_hover_text("The notifications don't fade out and the menus open without animations.")
+            .changed();
+
+        ui.horizontal(|ui| {
+            ui.label("Color filter");
+            egui::ComboBox::from_id_salt("color_filter")
+                .selected_text(settings.color_filter.name())
+                .show_ui(ui, |ui| {
+                    for filter in ColorFilter::ALL {
+                        ui.selectable_value(&mut settings.color_filter, filter, filter.name());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Correct: shift the colors of the game so that those a color blind player \
+                     can't tell apart look different. Simulate: show the game as a color blind \
+                     player sees it, to check what they'd miss. Not applied to the screenshots of \
+                     the console output.",
+                );
+        });
+
+        if changed {
+            self.apply_accessibility(ui.ctx());
+        }
+    }
+
+    fn render_osd_settings(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.config.settings.osd;
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Notifications");
+            egui::ComboBox::from_id_salt("osd_position")
+                .selected_text(settings.position.name())
+                .show_ui(ui, |ui| {
+                    for position in OsdPosition::ALL {
+                        changed |= ui.selectable_value(&mut settings.position, position, position.name()).changed();
+                    }
+                });
+            changed |= ui.add(egui::DragValue::new(&mut settings.duration_secs).range(1..=60).suffix(" s"))
+                .on_hover_text("How long the notifications stay up")
+                .changed();
+        });
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Show");
+            for category in OsdCategory::ALL {
+                let mut shown = !settings.hidden.contains(&category);
+                if ui.checkbox(&mut shown, category.name()).changed() {
+                    settings.hidden.retain(|&hidden| hidden != category);
+                    if !shown {
+                        settings.hidden.push(category);
+                    }
+                    changed = true;
+                }
+            }
+        })
+        .response
+        .on_hover_text("The warnings are shown whatever their category");
+
+        if changed {
+            self.apply_accessibility(ui.ctx());
+        }
+    }
+
+    fn render_control_settings(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.config.settings.control;
+        let settings_enabled = settings.enabled;
+
+        let mut changed = ui.checkbox(&mut settings.enabled, "Control server")
+            .on_hover_text(
+                "JSON-RPC over TCP for stream decks, autosplitters and scripts: pause, load games, \
+                 take screenshots, read and write memory. Only programs on this computer can \
+                 connect.",
+            )
+            .changed();
+        ui.add_enabled_ui(settings.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Port");
+                changed |= ui.add(egui::DragValue::new(&mut settings.port).range(1024..=65535)).changed();
+            });
+        });
+
+        let mut watches_changed = ui.checkbox(&mut settings.publish_watches, "Publish memory watches")
+            .on_hover_text(
+                "Send the values of the game's memory watches (Game Properties) after every frame \
+                 to autosplitters connected over TCP. Read-only.",
+            )
+            .changed();
+        ui.add_enabled_ui(settings.publish_watches, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Port");
+                watches_changed |= ui.add(egui::DragValue::new(&mut settings.watch_port).range(1024..=65535)).changed();
+            });
+        });
+
+        if changed || watches_changed {
+            self.apply_control_server();
+        }
+
+        let crowd = &mut self.config.settings.control.crowd;
+        let crowd_mode = crowd.mode;
+        ui.add_enabled_ui(settings_enabled, |ui| {
+            egui::ComboBox::from_label("Crowd input")
+                .selected_text(format!("{:?}", crowd.mode))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut crowd.mode, CrowdMode::Off, "Off");
+                    ui.selectable_value(&mut crowd.mode, CrowdMode::Anarchy, "Anarchy");
+                    ui.selectable_value(&mut crowd.mode, CrowdMode::Democracy, "Democracy");
+                })
+                .response
+                .on_hover_text(
+                    "Button presses sent with the \"press\" method on behalf of stream viewers. \
+                     Anarchy plays every one of them, democracy the most voted one of each round.",
+                );
+            ui.add_enabled_ui(crowd.mode != CrowdMode::Off, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Hold");
+                    ui.add(egui::DragValue::new(&mut crowd.hold_frames).range(1..=60).suffix(" frames"));
+                    ui.label("Cooldown");
+                    ui.add(egui::DragValue::new(&mut crowd.user_cooldown_ms).range(0..=60_000).suffix(" ms"));
+                });
+                ui.add_enabled_ui(crowd.mode == CrowdMode::Democracy, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Voting round");
+                        ui.add(egui::DragValue::new(&mut crowd.vote_frames).range(10..=1800).suffix(" frames"));
+                    });
+                });
+            });
+        });
+
+        if self.config.settings.control.crowd.mode != crowd_mode {
+            // Votes don't carry over to anarchy and the reverse
+            let released = self.crowd.reset();
+            self.mips.handle_inputs(released);
+        }
+    }
+
+    fn render_cloud_settings(&mut self, ui: &mut egui::Ui) {
+        let cloud = &mut self.config.settings.cloud;
+
+        let provider_name = match cloud.provider {
+            CloudProvider::Disabled => "Disabled",
+            CloudProvider::WebDav { .. } => "WebDAV",
+            CloudProvider::Folder { .. } => "Folder",
+        };
+
+        egui::ComboBox::from_label("Provider")
+            .selected_text(provider_name)
+            .show_ui(ui, |ui| {
+                if ui.selectable_label(provider_name == "Disabled", "Disabled").clicked() {
+                    cloud.provider = CloudProvider::Disabled;
+                }
+                if ui.selectable_label(provider_name == "WebDAV", "WebDAV").clicked() && provider_name != "WebDAV" {
+                    cloud.provider = CloudProvider::WebDav {
+                        url: String::new(),
+                        username: String::new(),
+                        password: String::new(),
+                    };
+                }
+                if ui.selectable_label(provider_name == "Folder", "Folder").clicked() && provider_name != "Folder" {
+                    cloud.provider = CloudProvider::Folder { path: Default::default() };
+                }
+            });
+
+        match &mut cloud.provider {
+            CloudProvider::Disabled => return,
+            CloudProvider::WebDav { url, username, password } => {
+                egui::Grid::new("webdav_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("URL");
+                    ui.text_edit_singleline(url);
+                    ui.end_row();
+
+                    ui.label("Username");
+                    ui.text_edit_singleline(username);
+                    ui.end_row();
+
+                    ui.label("Password");
+                    ui.add(egui::TextEdit::singleline(password).password(true));
+                    ui.end_row();
+                });
+            }
+            CloudProvider::Folder { path } => {
+                let mut text = path.to_string_lossy().into_owned();
+                ui.horizontal(|ui| {
+                    ui.label("Path");
+                    if ui.text_edit_singleline(&mut text).changed() {
+                        *path = text.into();
+                    }
+                });
+            }
+        }
+
+        egui::ComboBox::from_label("On conflict")
+            .selected_text(match cloud.conflict_policy {
+                ConflictPolicy::Newest => "Keep the newest save",
+                ConflictPolicy::Local => "Keep the local save",
+                ConflictPolicy::Remote => "Keep the remote save",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut cloud.conflict_policy, ConflictPolicy::Newest, "Keep the newest save");
+                ui.selectable_value(&mut cloud.conflict_policy, ConflictPolicy::Local, "Keep the local save");
+                ui.selectable_value(&mut cloud.conflict_policy, ConflictPolicy::Remote, "Keep the remote save");
+            });
+
+        ui.checkbox(&mut cloud.sync_on_launch, "Sync when the game starts and stops");
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.game.is_some(), egui::Button::new("Sync Now")).clicked() {
+                self.sync_saves();
+            }
+            if let Some(status) = &self.cloud_status {
+                ui.label(status);
+            }
+        });
+    }
+
+    fn render_settings(&mut self, ctx: &egui::Context) {
+        if !self.show_settings {
+            return;
+        }
+
+        let mut show_settings = self.show_settings;
+        egui::Window::new("Settings")
+            .open(&mut show_settings)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.heading("Video");
+
+                let mut vsync_changed = false;
+                if ui.checkbox(&mut self.config.settings.video.vsync, "VSync").changed() {
+                    vsync_changed = true;
+                }
+
+                ui.checkbox(&mut self.config.settings.video.bilinear_filter, "Bilinear Filtering");
+
+                if ui.checkbox(&mut self.config.settings.video.widescreen, "Widescreen (16:9)")
+                    .on_hover_text(
+                        "Applies the game's patch from config/cheats/widescreen.txt. \
+                         Games without one are only stretched.",
+                    )
+                    .changed()
+                {
+                    self.apply_widescreen_patch();
+                }
+
+                self.render_renderer(ui);
+                self.render_internal_resolution(ui);
+                ui.checkbox(&mut self.config.settings.video.pgxp, "Precise vertices (PGXP)")
+                    .on_hover_text(
+                        "Draw the polygons where the 3D math put them instead of snapping them to \
+                         whole pixels, which stops them from wobbling, most visibly at higher \
+                         internal resolutions. The GPU renderer also draws the textures in \
+                         perspective. Can be overridden per game, off during netplay.",
+                    );
+                ui.checkbox(&mut self.config.settings.video.dithering, "Dithering")
+                    .on_hover_text(
+                        "The console's dithering pattern on the shaded and blended polygons.                          Turning it off gives cleaner but more banded gradients. On during netplay.",
+                    );
+                ui.checkbox(&mut self.config.settings.video.true_color, "True color (24-bit)")
+                    .on_hover_text(
+                        "Keep the full colors the polygons are drawn with instead of the console's                          15-bit ones, for smooth gradients. Best with the dithering off. Off during                          netplay.",
+                    );
+                ui.checkbox(&mut self.config.settings.video.accurate_transparency, "Accurate transparency")
+                    .on_hover_text(
+                        "Blend the semi-transparent polygons with the console's 15-bit colors. Off \
+                         they're blended with the full colors, smoother but with a different \
+                         rounding that shows in effects stacking many layers. No effect in true \
+                         color, on during netplay.",
+                    );
+                ui.checkbox(&mut self.config.settings.video.parallel_draw_areas, "Parallel draw areas")
+                    .on_hover_text(
+                        "Draw the viewports of split-screen games on several threads, faster on \
+                         slow machines. The threads don't share the texture cache, which glitches \
+                         the rare games relying on it. Software renderer only, off during netplay.",
+                    );
+                self.render_frame_skip(ui);
+                ui.checkbox(&mut self.config.settings.video.capture_friendly, "Capture friendly (OBS)")
+                    .on_hover_text(
+                        "Present every frame at a steady pace, even when nothing changes, and never \
+                         skip frames, so that OBS Game Capture hooks the window reliably and the \
+                         recording doesn't stutter. Uses more power.",
+                    );
+                self.render_fullscreen_settings(ui);
+                ui.horizontal(|ui| {
+                    ui.label("Screenshots (F12)");
+                    let source = &mut self.config.settings.video.screenshot_source;
+                    egui::ComboBox::from_id_salt("screenshot_source")
+                        .selected_text(source.name())
+                        .show_ui(ui, |ui| {
+                            for option in ScreenshotSource::ALL {
+                                ui.selectable_value(source, option, option.name());
+                            }
+                        })
+                        .response
+                        .on_hover_text(
+                            "Console output: the frame as the console made it, at its resolution. \
+                             Game picture as shown: scaled, filtered and stretched like on screen, \
+                             without the menus and the overlays. Whole window: everything on \
+                             screen.",
+                        );
+                });
+
+                ui.separator();
+                ui.heading("Audio");
+
+                ui.checkbox(&mut self.config.settings.audio.enabled, "Enable Audio");
+
+                if ui.add(
+                    egui::Slider::new(&mut self.config.settings.audio.volume, 0.0..=1.0)
+                        .text("Volume")
+                ).changed() {
+                    self.audio.set_volume(self.output_volume());
+                }
+
+                let rate_control = ui
+                    .checkbox(&mut self.config.settings.audio.dynamic_rate_control, "Dynamic rate control")
+                    .on_hover_text(
+                        "Speed the sound up or down by up to 0.5% to keep the audio buffer level \
+                         when the monitor isn't running at 59.94 Hz. Prevents crackling and drifting \
+                         latency, the change in pitch can't be heard.",
+                    );
+                if rate_control.changed() {
+                    self.audio.set_rate_control(self.config.settings.audio.dynamic_rate_control);
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Sound in menus:");
+                    let before = self.config.settings.audio.menu_audio;
+                    egui::ComboBox::from_id_salt("menu_audio")
+                        .selected_text(before.name())
+                        .show_ui(ui, |ui| {
+                            for mode in MenuAudio::ALL {
+                                ui.selectable_value(&mut self.config.settings.audio.menu_audio, mode, mode.name());
+                            }
+                        })
+                        .response
+                        .on_hover_text(
+                            "While the game is paused or the games list is open. Keeping the sound \
+                             running lets the notes fade out or loop instead of cutting them, \
+                             and resuming doesn't pop. The game itself and the disc stay frozen.",
+                        );
+                    if self.config.settings.audio.menu_audio != before {
+                        self.audio.set_volume(self.output_volume());
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Interpolation:");
+                    let interpolation = &mut self.config.settings.audio.interpolation;
+                    egui::ComboBox::from_id_salt("interpolation")
+                        .selected_text(interpolation.name())
+                        .show_ui(ui, |ui| {
+                            for option in Interpolation::ALL {
+                                ui.selectable_value(interpolation, option, option.name());
+                            }
+                        })
+                        .response
+                        .on_hover_text(
+                            "How the sound samples are resampled to the notes' pitch. Gaussian is \
+                             the console's, slightly muffled. Cubic is smoother and brighter, \
+                             nearest crisp but grainy. Gaussian during netplay.",
+                        );
+                });
+
+                ui.collapsing("Mixer", |ui| {
+                    let audio = &mut self.config.settings.audio;
+                    egui::Grid::new("mixer_grid").num_columns(4).show(ui, |ui| {
+                        for source in AudioSource::ALL {
+                            ui.label(source.name());
+
+                            let mut volume = audio.mix.volume(source);
+                            if ui.add(egui::Slider::new(&mut volume, 0.0..=1.0)).changed() {
+                                audio.mix.set_volume(source, volume);
+                            }
+
+                            let mut muted = audio.muted_sources.contains(&source);
+                            if ui.checkbox(&mut muted, "Mute").changed() {
+                                audio.muted_sources.retain(|&s| s != source);
+                                if muted {
+                                    audio.muted_sources.push(source);
+                                }
+                            }
+
+                            let solo = self.audio_solo == Some(source);
+                            if ui.selectable_label(solo, "Solo").clicked() {
+                                self.audio_solo = if solo { None } else { Some(source) };
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    ui.weak(
+                        "On top of the game's own volumes. Mute the CD audio or the XA streams to \
+                         play over another soundtrack. Solo isn't saved.",
+                    );
+                });
+
+                ui.separator();
+                ui.heading("System");
+                ui.checkbox(&mut self.config.settings.system.fast_boot, "Skip BIOS")
+                    .on_hover_text("Skip the boot logo, takes effect when the game restarts");
+                ui.checkbox(&mut self.config.settings.system.hle_bios, "Emulate the BIOS without a dump")
+                    .on_hover_text(
+                        "Boot the games with a BIOS written from scratch when there's no dump in the \
+                         system directory. There's no shell and some games may not work, a real dump \
+                         is always better. Takes effect when the game restarts.",
+                    );
+                ui.checkbox(&mut self.config.settings.system.auto_save_state, "Auto-save state on exit");
+                ui.horizontal(|ui| {
+                    ui.label("Auto-state every");
+                    let minutes = egui::DragValue::new(&mut self.config.settings.system.auto_state_minutes)
+                        .range(0..=120)
+                        .suffix(" min");
+                    ui.add(minutes).on_hover_text(
+                        "Save the state in the background every so many minutes of play, in three \
+                         files of their own apart from the slots. Emulation > Load Auto-State \
+                         brings them back after a crash. 0 disables it.",
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Memory cards:");
+                    egui::ComboBox::from_id_salt("memory_card_mode")
+                        .selected_text(self.config.settings.system.memory_card_mode.name())
+                        .show_ui(ui, |ui| {
+                            for mode in MemoryCardMode::ALL {
+                                ui.selectable_value(&mut self.config.settings.system.memory_card_mode, mode, mode.name());
+                            }
+                        })
+                        .response
+                        .on_hover_text(
+                            "Per game gives every game a card of its own in slot 1, so that it never \
+                             runs out of space; slot 2 keeps the shared card to copy saves between \
+                             games. Per disc serial names the card after the disc's serial number \
+                             instead, so the copies of a game share it but the discs of a multi-disc \
+                             game don't. Can be overridden per game, takes effect when the game restarts.",
+                        );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Pause stuck games after");
+                    let timeout = egui::DragValue::new(&mut self.config.settings.system.stall_timeout_secs)
+                        .range(0..=600)
+                        .suffix(" s");
+                    if ui.add(timeout)
+                        .on_hover_text(
+                            "Pause and show diagnostics when the game draws nothing and loops over \
+                             the same code for that long. 0 disables it.",
+                        )
+                        .changed()
+                    {
+                        self.watchdog = stall_watchdog(&self.config);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Preload discs up to");
+                    let limit = egui::DragValue::new(&mut self.config.settings.system.preload_disc_mib)
+                        .range(0..=1024)
+                        .suffix(" MiB");
+                    ui.add(limit).on_hover_text(
+                        "Read the whole disc into memory when the game starts, so that a sleeping \
+                         drive or a slow network share can't freeze the game. 0 reads it as it's \
+                         needed. Takes effect the next time the game starts.",
+                    );
+                });
+                let hardcore = ui.checkbox(&mut self.config.settings.system.hardcore, "Hardcore mode")
+                    .on_hover_text(
+                        "For RetroAchievements: cheats, widescreen patches, executable patches, \
+                         startup scripts, PAL games at 60 Hz and CPU overclocking are disabled. \
+                         Entering it takes a game restart.",
+                    );
+                if hardcore.changed() {
+                    self.sync_hardcore();
+                }
+                if self.config.settings.system.hardcore && !self.mips.is_hardcore() && self.mips.is_running() {
+                    ui.weak("Restart the game to enter hardcore mode");
+                }
+                let ntsc_timing = egui::Checkbox::new(
+                    &mut self.config.settings.system.force_ntsc_timing,
+                    "Run PAL games at 60 Hz",
+                );
+                let ntsc_timing = ui.add_enabled(!self.mips.is_hardcore(), ntsc_timing)
+                    .on_hover_text(
+                        "NTSC timings for PAL games: they run faster, as on an NTSC console. The \
+                         sound keeps its pitch but music tied to the frame rate plays faster. Not \
+                         available in hardcore mode.",
+                    );
+                if ntsc_timing.changed() {
+                    self.apply_ntsc_timing();
+                }
+                self.render_rewind_settings(ui);
+                self.render_cpu_backend(ui);
+                self.render_bios_selection(ui);
+
+                ui.separator();
+                ui.heading("Input");
+                ui.checkbox(
+                    &mut self.config.settings.input.auto_controller_type,
+                    "Pick the controller type from the compatibility database",
+                )
+                .on_hover_text(
+                    "Digital pad for games known not to support the DualShock, DualShock otherwise. \
+                     Games with a controller type set in the input configuration keep it.",
+                );
+                ui.checkbox(&mut self.config.settings.input.mid_frame_input, "Mid-frame input")
+                    .on_hover_text(
+                        "Buttons change when the game reads the pad, at the point of the frame \
+                         they were pressed, instead of all at the start of the frame. Keeps the \
+                         timing of rhythm games steadier, with half a frame more latency on \
+                         average. Gamepad buttons only, keyboard keys aren't timestamped.",
+                    );
+                ui.add_enabled_ui(self.config.settings.input.mid_frame_input, |ui| {
+                    self.render_latency_compensation(ui);
+                });
+                ui.add(egui::Slider::new(&mut self.config.settings.input.rumble_strength, 0.0..=1.0).text("Rumble strength"))
+                    .on_hover_text("Strength of the DualShock rumble on the gamepad, 0 turns it off");
+                ui.checkbox(&mut self.config.settings.input.background_gamepad, "Gamepad input in the background")
+                    .on_hover_text(
+                        "Keep playing with the gamepad while another window is focused, to run the \
+                         game on a second screen. The keyboard only plays while this window is \
+                         focused.",
+                    );
+                self.render_mouse_settings(ui);
+
+                ui.separator();
+                ui.heading("Cloud Sync");
+                self.render_cloud_settings(ui);
+
+                ui.separator();
+                ui.heading("Remote Control");
+                self.render_control_settings(ui);
+
+                ui.separator();
+                ui.heading("On-screen display");
+                self.render_osd_settings(ui);
+
+                ui.separator();
+                ui.heading("Accessibility");
+                self.render_accessibility_settings(ui);
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        if let Err(e) = self.config.save_settings() {
+                            tracing::error!("Failed to save settings: {}", e);
+                        }
+                        self.show_settings = false;
+                    }
+
+                    let widescreen = self.config.settings.video.widescreen;
+
+                    if ui.button("Reset to Defaults").clicked() {
+                        if let Err(e) = self.config.reset_to_defaults() {
+                            tracing::error!("Failed to reset settings: {}", e);
+                        }
+                        self.audio.set_volume(self.output_volume());
+                        self.audio.set_rate_control(self.config.settings.audio.dynamic_rate_control);
+                        self.watchdog = stall_watchdog(&self.config);
+                        self.mips.set_bios(self.config.settings.system.bios.clone());
+                        self.mips.set_fast_boot(self.config.settings.system.fast_boot);
+                        self.mips.set_hle_bios(self.config.settings.system.hle_bios);
+                        if self.config.settings.video.widescreen != widescreen {
+                            self.apply_widescreen_patch();
+                        }
+                        self.sync_hardcore();
+                        self.apply_ntsc_timing();
+                        self.apply_cpu_clock();
+                        self.apply_control_server();
+                        self.apply_accessibility(ui.ctx());
+                    }
+
+                    if ui.button("Cancel").clicked() {
+                        // Reload settings from disk
+                        if let Ok(new_config) = self.reload_config() {
+                            self.config = new_config;
+                            self.audio.set_volume(self.output_volume());
+                            self.audio.set_rate_control(self.config.settings.audio.dynamic_rate_control);
+                            self.watchdog = stall_watchdog(&self.config);
+                            self.mips.set_bios(self.config.settings.system.bios.clone());
+                            self.mips.set_fast_boot(self.config.settings.system.fast_boot);
+                            self.mips.set_hle_bios(self.config.settings.system.hle_bios);
+                            if self.config.settings.video.widescreen != widescreen {
+                                self.apply_widescreen_patch();
+                            }
+                            self.sync_hardcore();
+                            self.apply_ntsc_timing();
+                            self.apply_cpu_clock();
+                            self.apply_control_server();
+                            self.apply_accessibility(ui.ctx());
+                        }
+                        self.show_settings = false;
+                    }
+                });
+            });
+        self.show_settings = show_settings;
+        if !self.show_settings {
+            self.bios_scan = None;
+        }
+    }
+
+    fn render_bios_selection(&mut self, ui: &mut egui::Ui) {
+        let scan = self.bios_scan.get_or_insert_with(|| {
+            bios::scan(&env::current_dir().unwrap_or_default())
+        });
+
+        let selected = &mut self.config.settings.system.bios;
+        let previous = selected.clone();
+        let mut rescan = false;
+        let name = |path: &std::path::Path| {
+            path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+        };
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("BIOS")
+                .selected_text(selected.as_deref().map(name).unwrap_or_else(|| "Automatic".to_string()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(selected, None, "Automatic")
+                        .on_hover_text("A dump of the disc's region, or the first supported one");
+                    for candidate in &scan.candidates {
+                        let label = match &candidate.known {
+                            Some(known) => format!("{} (v{} {})", name(&candidate.path), known.version, known.region),
+                            None => format!("{} (not supported)", name(&candidate.path)),
+                        };
+                        ui.add_enabled_ui(candidate.known.is_some(), |ui| {
+                            ui.selectable_value(selected, Some(candidate.path.clone()), label)
+                                .on_disabled_hover_text(candidate.to_string());
+                        });
+                    }
+                });
+
+            rescan = ui.button("Rescan").clicked();
+        });
+        ui.weak(format!("Dumps (raw or zipped) from {}, used when the game restarts", scan.dir.display()));
+
+        if rescan {
+            self.bios_scan = None;
+        }
+
+        if self.config.settings.system.bios != previous {
+            self.mips.set_bios(self.config.settings.system.bios.clone());
+            self.mips.set_fast_boot(self.config.settings.system.fast_boot);
+            self.mips.set_hle_bios(self.config.settings.system.hle_bios);
+        }
+    }
+
+    fn render_input_config(&mut self, ctx: &egui::Context) {
+        if !self.show_input_config {
+            return;
+        }
+
+        let mut show_input_config = self.show_input_config;
+
+        egui::Window::new("Input Configuration")
+            .open(&mut show_input_config)
+            .resizable(false)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                self.render_controller_ports(ui);
+
+                ui.separator();
+
+                self.render_input_profiles(ui);
+
+                ui.separator();
+
+                // Tab selection
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.input_config_tab, InputConfigTab::Keyboard, "Keyboard");
+                    ui.selectable_value(&mut self.input_config_tab, InputConfigTab::Gamepad, "Gamepad");
+                });
+
+                ui.separator();
+
+                match self.input_config_tab {
+                    InputConfigTab::Keyboard => self.render_keyboard_config(ui, ctx),
+                    InputConfigTab::Gamepad => self.render_gamepad_config(ui, ctx),
+                }
 
                 ui.separator();
 
                 ui.horizontal(|ui| {
-                    if ui.button("Save").clicked() {
-                        if let Err(e) = self.config.save_settings() {
-                            tracing::error!("Failed to save settings: {}", e);
+                    if ui.button("Save").clicked() {
+                        if let Some(profile) = self.config.input_profile.clone() {
+                            if let Err(e) = self.config.save_input_profile(&profile) {
+                                tracing::error!("Failed to save input profile {}: {}", profile, e);
+                            }
+                        } else {
+                            if let Err(e) = self.config.save_keyboard_bindings() {
+                                tracing::error!("Failed to save keyboard bindings: {}", e);
+                            }
+                            if let Err(e) = self.config.save_gamepad_bindings() {
+                                tracing::error!("Failed to save gamepad bindings: {}", e);
+                            }
+                        }
+                        self.show_input_config = false;
+                        self.waiting_for_key = None;
+                        self.waiting_for_gamepad_button = None;
+                    }
+
+                    if ui.button("Reset to Defaults").clicked() {
+                        if let Err(e) = self.config.reset_to_defaults() {
+                            tracing::error!("Failed to reset bindings: {}", e);
+                        }
+                    }
+
+                    if ui.button("Cancel").clicked() {
+                        // Reload bindings from disk
+                        if let Some(profile) = self.config.input_profile.clone() {
+                            if let Err(e) = self.config.use_input_profile(Some(&profile)) {
+                                tracing::error!("Failed to reload input profile {}: {}", profile, e);
+                            }
+                        } else if let Ok(new_config) = self.reload_config() {
+                            self.config.keyboard_bindings = new_config.keyboard_bindings;
+                            self.config.gamepad_bindings = new_config.gamepad_bindings;
+                        }
+                        self.show_input_config = false;
+                        self.waiting_for_key = None;
+                        self.waiting_for_gamepad_button = None;
+                    }
+                });
+            });
+
+        self.show_input_config = show_input_config;
+    }
+
+    /// Profile the bindings below are saved to, and saving them under a new name
+    fn render_input_profiles(&mut self, ui: &mut egui::Ui) {
+        let current = self.config.input_profile.clone();
+        let mut selected = current.clone();
+        let mut delete = false;
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Profile")
+                .selected_text(current.as_deref().unwrap_or("Default bindings"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut selected, None, "Default bindings");
+                    for name in self.config.input_profiles() {
+                        ui.selectable_value(&mut selected, Some(name.clone()), name);
+                    }
+                })
+                .response
+                .on_hover_text("Switching drops the changes that weren't saved");
+            delete = current.is_some() && ui.button("Delete").clicked();
+        });
+
+        if delete && let Some(name) = &current {
+            if let Err(e) = self.config.delete_input_profile(name) {
+                tracing::error!("Failed to delete input profile {}: {}", name, e);
+            }
+        } else if selected != current {
+            self.switch_input_profile(selected.as_deref());
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_input_profile).hint_text("New profile").desired_width(160.0));
+            let name = self.new_input_profile.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Save as Profile"))
+                .on_hover_text("Switched to from the Options menu, or for the games and genres picked in the games list")
+                .clicked()
+            {
+                match self.config.save_input_profile(&name) {
+                    Ok(()) => self.new_input_profile.clear(),
+                    Err(e) => tracing::error!("Failed to save input profile {}: {}", name, e),
+                }
+            }
+        });
+    }
+
+    fn render_controller_ports(&mut self, ui: &mut egui::Ui) {
+        // Netplay sessions decide what's plugged in
+        let enabled = self.netplay.is_none();
+
+        for port in 0..2 {
+            let current = self.game_settings.controllers[port];
+            let mut selected = current;
+
+            ui.add_enabled_ui(enabled, |ui| {
+                egui::ComboBox::from_label(format!("Port {}", port + 1))
+                    .selected_text(current.name())
+                    .show_ui(ui, |ui| {
+                        for controller in ControllerType::ALL {
+                            ui.selectable_value(&mut selected, controller, controller.name());
+                        }
+                    });
+            });
+
+            if selected != current {
+                self.set_controller(port, selected);
+            }
+        }
+
+        if self.game.is_some() {
+            ui.weak("Remembered for this game");
+        }
+
+        ui.separator();
+        self.render_port_devices(ui);
+    }
+
+    /// Which keyboard and gamepads each port reads, for two players on the same machine
+    fn render_port_devices(&mut self, ui: &mut egui::Ui) {
+        let gamepads = self.gamepad.gamepads();
+        let mut changed = false;
+
+        for port in 0..2 {
+            let devices = &mut self.config.settings.input.port_devices;
+            let mut choices = vec![PortDevice::All, PortDevice::Keyboard];
+            choices.extend(gamepads.iter().cloned());
+            // Kept while its gamepad is unplugged
+            if !choices.contains(&devices[port]) {
+                choices.push(devices[port].clone());
+            }
+            choices.push(PortDevice::None);
+
+            egui::ComboBox::from_label(format!("Port {} reads", port + 1))
+                .selected_text(devices[port].label())
+                .show_ui(ui, |ui| {
+                    for device in choices {
+                        let label = device.label();
+                        changed |= ui.selectable_value(&mut devices[port], device, label).changed();
+                    }
+                });
+        }
+        ui.weak("A device read by a port of its own only reaches that port");
+
+        if changed && let Err(e) = self.config.save_settings() {
+            tracing::error!("Failed to save settings: {}", e);
+        }
+    }
+
+    fn render_controller_test(&mut self, ctx: &egui::Context) {
+        let changed = self.controller_test.show(
+            ctx,
+            self.gamepad.gilrs.as_mut(),
+            &self.config.gamepad_bindings,
+            &mut self.config.settings.input,
+        );
+
+        if changed && let Err(e) = self.config.save_settings() {
+            tracing::error!("Failed to save settings: {}", e);
+        }
+    }
+
+    fn render_latency_calibration(&mut self, ctx: &egui::Context) {
+        let changed = self.latency_calibration.show(
+            ctx,
+            self.gamepad.gilrs.as_mut(),
+            self.audio.as_ref(),
+            &mut self.config.settings.input,
+        );
+
+        if changed && let Err(e) = self.config.save_settings() {
+            tracing::error!("Failed to save settings: {}", e);
+        }
+    }
+
+    fn render_games_list(&mut self, ctx: &egui::Context) {
+        let launch = self.games_list.show(ctx, self.game.as_deref(), self.netplay.is_none(), self.config.input_profiles());
+
+        if let Some(game) = launch {
+            self.switch_game(game);
+        }
+    }
+
+    fn render_keyboard_config(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        if let Some(waiting_button) = self.waiting_for_key {
+            ui.label(format!("Press a key for {}...", button_display_name(&waiting_button)));
+            ui.label("(Press ESC to cancel)");
+
+            // Check for key press
+            ctx.input(|i| {
+                if i.key_pressed(Key::Escape) {
+                    self.waiting_for_key = None;
+                    return;
+                }
+
+                // Check for any key press
+                for key in [
+                    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H,
+                    Key::I, Key::J, Key::K, Key::L, Key::M, Key::N, Key::O, Key::P,
+                    Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X,
+                    Key::Y, Key::Z,
+                    Key::ArrowUp, Key::ArrowDown, Key::ArrowLeft, Key::ArrowRight,
+                    Key::Enter, Key::Space, Key::Backspace,
+                ] {
+                    if i.key_pressed(key) {
+                        // Remove old binding for this key
+                        self.config.keyboard_bindings.bindings.retain(|k, _| k != &key);
+                        // Add new binding
+                        self.config.keyboard_bindings.bindings.insert(key, waiting_button);
+                        self.waiting_for_key = None;
+                        return;
+                    }
+                }
+            });
+        } else {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("keyboard_grid")
+                    .num_columns(3)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Button");
+                        ui.label("Key");
+                        ui.label("");
+                        ui.end_row();
+
+                        // Define button order
+                        let buttons = [
+                            Button::DUp, Button::DDown, Button::DLeft, Button::DRight,
+                            Button::Cross, Button::Circle, Button::Square, Button::Triangle,
+                            Button::L1, Button::R1, Button::L2, Button::R2,
+                            Button::L3, Button::R3,
+                            Button::Start, Button::Select, Button::Analog,
+                        ];
+
+                        for button in buttons {
+                            ui.label(button_display_name(&button));
+
+                            // Find current key binding
+                            let current_key = self.config.keyboard_bindings.bindings
+                                .iter()
+                                .find(|(_, b)| **b == button)
+                                .map(|(k, _)| *k);
+
+                            let key_text = current_key
+                                .map(|k| key_display_name(&k))
+                                .unwrap_or_else(|| "Unbound".to_string());
+
+                            ui.label(key_text);
+
+                            if ui.button("Change").clicked() {
+                                self.waiting_for_key = Some(button);
+                            }
+
+                            ui.end_row();
+                        }
+                    });
+            });
+        }
+    }
+
+    fn render_gamepad_config(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        if let Some(waiting_button) = self.waiting_for_gamepad_button {
+            ui.label(format!("Press a gamepad button for {}...", button_display_name(&waiting_button)));
+            ui.label("(Press any key to cancel)");
+
+            // Check for gamepad button press
+            if let Some(gilrs) = &mut self.gamepad.gilrs {
+                while let Some(event) = gilrs.next_event() {
+                    if let gilrs::EventType::ButtonPressed(gilrs_button, _) = event.event {
+                        // Remove old binding for this button
+                        self.config.gamepad_bindings.bindings.retain(|b, _| b != &gilrs_button);
+                        // Add new binding
+                        self.config.gamepad_bindings.bindings.insert(gilrs_button, waiting_button);
+                        self.waiting_for_gamepad_button = None;
+                        return;
+                    }
+                }
+            }
+
+            // Check for cancel
+            ctx.input(|i| {
+                if !i.keys_down.is_empty() {
+                    self.waiting_for_gamepad_button = None;
+                }
+            });
+        } else {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("gamepad_grid")
+                    .num_columns(3)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("PS1 Button");
+                        ui.label("Gamepad Button");
+                        ui.label("");
+                        ui.end_row();
+
+                        let buttons = [
+                            Button::DUp, Button::DDown, Button::DLeft, Button::DRight,
+                            Button::Cross, Button::Circle, Button::Square, Button::Triangle,
+                            Button::L1, Button::R1, Button::L2, Button::R2,
+                            Button::L3, Button::R3,
+                            Button::Start, Button::Select, Button::Analog,
+                        ];
+
+                        for button in buttons {
+                            ui.label(button_display_name(&button));
+
+                            // Find current gamepad binding
+                            let current_gilrs = self.config.gamepad_bindings.bindings
+                                .iter()
+                                .find(|(_, b)| **b == button)
+                                .map(|(g, _)| *g);
+
+                            let gilrs_text = current_gilrs
+                                .map(|g| format!("{:?}", g))
+                                .unwrap_or_else(|| "Unbound".to_string());
+
+                            ui.label(gilrs_text);
+
+                            if ui.button("Change").clicked() {
+                                self.waiting_for_gamepad_button = Some(button);
+                            }
+
+                            ui.end_row();
+                        }
+                    });
+            });
+        }
+    }
+
+    fn render_netplay(&mut self, ctx: &egui::Context) {
+        if !self.show_netplay {
+            // Stop listening for announcements when nobody's looking
+            self.netplay_form.browser = None;
+            return;
+        }
+
+        let mut show_netplay = self.show_netplay;
+        let mut join = None;
+        let mut leave = false;
+
+        egui::Window::new("Netplay")
+            .open(&mut show_netplay)
+            .resizable(false)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+                if let Some(np) = &self.netplay {
+                    let session = &np.session;
+
+                    let state = match session.state() {
+                        SessionState::Connecting if session.role() == SessionRole::Host => {
+                            "Waiting for a player".to_string()
+                        }
+                        SessionState::Connecting => "Connecting".to_string(),
+                        SessionState::Syncing => match session.sync_progress() {
+                            Some(p) => format!("Downloading host state ({:.0}%)", p * 100.),
+                            None => "Sending state".to_string(),
+                        },
+                        SessionState::Running => "Running".to_string(),
+                        SessionState::Closed(reason) => reason.clone(),
+                    };
+
+                    egui::Grid::new("netplay_status_grid")
+                        .num_columns(2)
+                        .spacing([10.0, 4.0])
+                        .show(ui, |ui| {
+                            ui.label("Role");
+                            ui.label(format!("{:?}", session.role()));
+                            ui.end_row();
+
+                            ui.label("State");
+                            ui.label(state);
+                            ui.end_row();
+
+                            if let Some(addr) = session.local_addr() {
+                                ui.label("Local address");
+                                ui.label(addr.to_string());
+                                ui.end_row();
+                            }
+
+                            if let Some(code) = session.lobby_code() {
+                                ui.label("Lobby code");
+                                ui.horizontal(|ui| {
+                                    ui.monospace(code.as_str());
+                                    if ui.small_button("Copy").clicked() {
+                                        ui.ctx().copy_text(code.clone());
+                                    }
+                                });
+                                ui.end_row();
+                            }
+
+                            if session.is_relayed() {
+                                ui.label("Connection");
+                                ui.label("Relayed by the lobby server");
+                                ui.end_row();
+                            }
+
+                            ui.label("Peer");
+                            ui.label(session.peer_name().unwrap_or("-"));
+                            ui.end_row();
+
+                            ui.label("Ping");
+                            ui.label(session.rtt()
+                                .map(|rtt| format!("{} ms", rtt.as_millis()))
+                                .unwrap_or_else(|| "-".to_string()));
+                            ui.end_row();
+
+                            if session.role() != SessionRole::Spectator {
+                                ui.label("Input delay");
+                                ui.label(format!("{} frames", session.input_delay()));
+                                ui.end_row();
+
+                                ui.label("Stalled frames");
+                                ui.label(session.stalled_frames().to_string());
+                                ui.end_row();
+                            }
+
+                            ui.label("Frame");
+                            ui.label(session.frame().to_string());
+                            ui.end_row();
+
+                            let spectators: Vec<&str> = session.spectator_names().collect();
+                            if !spectators.is_empty() {
+                                ui.label("Spectators");
+                                ui.label(spectators.join(", "));
+                                ui.end_row();
+                            }
+                        });
+
+                    ui.separator();
+
+                    ui.checkbox(&mut self.netplay_form.show_overlay, "Show connection stats over the game");
+                    ui.label(format!("Press {} in game to chat.", key_display_name(&CHAT_KEY)));
+
+                    if ui.button("Leave Session").clicked() {
+                        leave = true;
+                    }
+                    return;
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut self.netplay_form.name);
+                });
+
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.netplay_form.tab, NetplayTab::Browse, "Browse");
+                    ui.selectable_value(&mut self.netplay_form.tab, NetplayTab::Host, "Host");
+                    ui.selectable_value(&mut self.netplay_form.tab, NetplayTab::Join, "Join");
+                });
+
+                ui.separator();
+
+                match self.netplay_form.tab {
+                    NetplayTab::Browse => {
+                        if self.netplay_form.browser.is_none() {
+                            match LanBrowser::new() {
+                                Ok(b) => self.netplay_form.browser = Some(b),
+                                Err(e) => {
+                                    ui.label(format!("Can't listen for LAN sessions: {}", e));
+                                }
+                            }
+                        }
+
+                        if let Some(browser) = &mut self.netplay_form.browser {
+                            browser.poll();
+
+                            if browser.sessions().is_empty() {
+                                ui.label("Looking for sessions on the local network...");
+                            }
+
+                            egui::Grid::new("netplay_browser_grid")
+                                .num_columns(5)
+                                .spacing([10.0, 4.0])
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for s in browser.sessions() {
+                                        ui.label(s.name.as_str());
+                                        ui.label(s.game.as_deref().unwrap_or("No game"));
+                                        ui.label(format!("{}/2, {} watching", s.players, s.spectators));
+
+                                        let same_game = s.game == self.game;
+                                        if ui.add_enabled(same_game && s.players < 2, egui::Button::new("Join")).clicked() {
+                                            join = Some(NetplayJoin::Player(s.addr));
+                                        }
+                                        if ui.add_enabled(same_game, egui::Button::new("Spectate")).clicked() {
+                                            join = Some(NetplayJoin::Spectator(s.addr));
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+
+                            // Keep the list fresh
+                            ctx.request_repaint_after(std::time::Duration::from_millis(500));
                         }
-                        self.show_settings = false;
                     }
+                    NetplayTab::Host => {
+                        ui.horizontal(|ui| {
+                            ui.label("Port:");
+                            ui.text_edit_singleline(&mut self.netplay_form.host_port);
+                        });
 
-                    if ui.button("Reset to Defaults").clicked() {
-                        if let Err(e) = self.config.reset_to_defaults() {
-                            tracing::error!("Failed to reset settings: {}", e);
+                        ui.horizontal(|ui| {
+                            ui.label("Lobby server:");
+                            ui.text_edit_singleline(&mut self.netplay_form.lobby_server);
+                        });
+                        ui.label("With a lobby server, players can join with a code instead of your address.");
+                        ui.checkbox(&mut self.netplay_form.allow_relay, "Relay traffic if a direct connection fails");
+
+                        ui.checkbox(&mut self.netplay_form.auto_delay, "Pick input delay from latency");
+                        ui.add_enabled(
+                            !self.netplay_form.auto_delay,
+                            egui::Slider::new(&mut self.netplay_form.fixed_delay, 0..=MAX_INPUT_DELAY)
+                                .text("Input delay (frames)"),
+                        );
+
+                        if ui.button("Host").clicked() {
+                            join = Some(NetplayJoin::Host);
                         }
-                        self.audio.set_volume(self.config.settings.audio.volume);
                     }
+                    NetplayTab::Join => {
+                        ui.horizontal(|ui| {
+                            ui.label("Address:");
+                            ui.text_edit_singleline(&mut self.netplay_form.join_addr);
+                        });
 
-                    if ui.button("Cancel").clicked() {
-                        // Reload settings from disk
-                        if let Ok(new_config) = ConfigManager::new() {
-                            self.config = new_config;
-                            self.audio.set_volume(self.config.settings.audio.volume);
+                        let has_addr = !self.netplay_form.join_addr.trim().is_empty();
+                        let mut clicked = None;
+
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(has_addr, egui::Button::new("Join")).clicked() {
+                                clicked = Some(NetplayJoin::Player as fn(SocketAddr) -> NetplayJoin);
+                            }
+                            if ui.add_enabled(has_addr, egui::Button::new("Spectate")).clicked() {
+                                clicked = Some(NetplayJoin::Spectator as fn(SocketAddr) -> NetplayJoin);
+                            }
+                        });
+
+                        // Only resolve on click, host names may need a (blocking) DNS lookup
+                        if let Some(kind) = clicked {
+                            match resolve_netplay_addr(&self.netplay_form.join_addr, DEFAULT_PORT) {
+                                Some(addr) => join = Some(kind(addr)),
+                                None => self.netplay_form.status = Some("Can't resolve address".to_string()),
+                            }
                         }
-                        self.show_settings = false;
+
+                        ui.separator();
+
+                        ui.horizontal(|ui| {
+                            ui.label("Lobby server:");
+                            ui.text_edit_singleline(&mut self.netplay_form.lobby_server);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Lobby code:");
+                            ui.text_edit_singleline(&mut self.netplay_form.lobby_code);
+                        });
+                        ui.checkbox(&mut self.netplay_form.allow_relay, "Relay traffic if a direct connection fails");
+
+                        let has_code = !self.netplay_form.lobby_code.trim().is_empty()
+                            && !self.netplay_form.lobby_server.trim().is_empty()
+                            && self.netplay_form.lookup.is_none();
+                        let code = self.netplay_form.lobby_code.trim().to_string();
+
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(has_code, egui::Button::new("Join with code")).clicked() {
+                                join = Some(NetplayJoin::Lobby { code: code.clone(), spectate: false });
+                            }
+                            if ui.add_enabled(has_code, egui::Button::new("Spectate with code")).clicked() {
+                                join = Some(NetplayJoin::Lobby { code, spectate: true });
+                            }
+                        });
                     }
-                });
+                }
+
+                if let Some(status) = &self.netplay_form.status {
+                    ui.separator();
+                    ui.label(status);
+                }
             });
-        self.show_settings = show_settings;
+
+        if leave {
+            if let Some(np) = &mut self.netplay {
+                np.session.close();
+            }
+            self.netplay = None;
+        }
+
+        if let Some(join) = join {
+            self.start_netplay(join);
+        }
+
+        self.show_netplay = show_netplay;
     }
 
-    fn render_input_config(&mut self, ctx: &egui::Context) {
-        if !self.show_input_config {
+    /// The docked tools, or the floating ones
+    fn render_tools(&mut self, ctx: &egui::Context, floating: bool) {
+        // Changing the cards or the RAM would desync the netplay peers
+        let editable = self.netplay.is_none();
+        let mut contents = |tool: Tool, ui: &mut egui::Ui| match tool {
+            Tool::MemoryCards => self.memory_cards.ui(ui, &mut self.mips, editable),
+            Tool::RamSearch => self.ram_search.ui(ui, &mut self.mips, editable),
+            Tool::Log => self.log_view.ui(ui, &self.log),
+            Tool::BlockCache => self.block_cache.ui(ui, &self.mips),
+        };
+
+        if floating {
+            self.workspace.show_windows(ctx, &mut contents);
+        } else {
+            self.workspace.show_dock(ctx, &mut contents);
+        }
+
+        if !self.workspace.is_open(Tool::MemoryCards) {
+            self.memory_cards.close();
+        }
+        if !self.workspace.is_open(Tool::RamSearch) {
+            self.ram_search.close();
+        }
+    }
+
+    fn render_game_properties(&mut self, ctx: &egui::Context) {
+        let Some(game) = self.game.clone() else {
+            return;
+        };
+        if !self.show_game_properties {
             return;
         }
 
-        let mut show_input_config = self.show_input_config;
+        let mut show = self.show_game_properties;
+        let mut restart = false;
 
-        egui::Window::new("Input Configuration")
-            .open(&mut show_input_config)
+        egui::Window::new("Game Properties")
+            .open(&mut show)
             .resizable(false)
-            .default_width(500.0)
             .show(ctx, |ui| {
-                // Tab selection
-                ui.horizontal(|ui| {
-                    ui.selectable_value(&mut self.input_config_tab, InputConfigTab::Keyboard, "Keyboard");
-                    ui.selectable_value(&mut self.input_config_tab, InputConfigTab::Gamepad, "Gamepad");
+                ui.heading(self.game_title().unwrap_or_else(|| game.clone()));
+                ui.weak(&game);
+                ui.separator();
+
+                let patches = config::game_exe_patches(&game);
+                let current = self.game_settings.exe_patch.clone();
+                let mut selected = current.clone();
+
+                // Netplay peers must run the same code
+                ui.add_enabled_ui(self.netplay.is_none() && !self.config.settings.system.hardcore, |ui| {
+                    egui::ComboBox::from_label("Executable patch")
+                        .selected_text(current.as_deref().unwrap_or("None"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut selected, None, "None");
+                            for patch in &patches {
+                                ui.selectable_value(&mut selected, Some(patch.clone()), patch);
+                            }
+                        });
                 });
 
-                ui.separator();
+                ui.weak(format!(
+                    "IPS or BPS patches from {}, applied when the game starts",
+                    config::game_patch_dir(&game).display(),
+                ));
 
-                match self.input_config_tab {
-                    InputConfigTab::Keyboard => self.render_keyboard_config(ui, ctx),
-                    InputConfigTab::Gamepad => self.render_gamepad_config(ui, ctx),
+                if selected != current {
+                    self.game_settings.exe_patch = selected;
+                    self.save_game_settings();
+                }
+
+                let soundtrack_dir = config::game_soundtrack_dir(&game);
+                let tracks = soundtrack::track_files(&soundtrack_dir);
+                if tracks.is_empty() {
+                    ui.weak(format!("No replacement soundtrack ({})", soundtrack_dir.display()));
+                } else {
+                    let numbers: Vec<String> = tracks.keys().map(|n| n.to_string()).collect();
+                    ui.label(format!("Soundtrack: tracks {} replaced", numbers.join(", ")))
+                        .on_hover_text(format!(
+                            "FLAC, Ogg, WAV or MP3 files from {}, used when the game starts",
+                            soundtrack_dir.display(),
+                        ));
+                }
+
+                let script = config::game_startup_script(&game);
+                if script.is_file() {
+                    ui.label(format!("Startup script: {}", script.display()));
+                } else {
+                    ui.weak(format!("No startup script ({})", script.display()));
+                }
+
+                ui.separator();
+                ui.label("Memory cards");
+                for slot in 0..2 {
+                    let read_only = &mut self.game_settings.read_only_memory_cards[slot];
+                    let changed = ui.checkbox(read_only, format!("Slot {} read-only", slot + 1))
+                        .on_hover_text(
+                            "The game can still save, but the saves are lost when it stops. For \
+                             speedruns and testing.",
+                        )
+                        .changed();
+                    if changed {
+                        self.mips.set_memory_card_read_only(slot, *read_only);
+                        self.save_game_settings();
+                    }
+                }
+                let label = |mode: Option<MemoryCardMode>| mode.map_or("Default", MemoryCardMode::name);
+                let before = self.game_settings.memory_card_mode;
+                egui::ComboBox::from_label("Cards")
+                    .selected_text(label(before))
+                    .show_ui(ui, |ui| {
+                        for option in [None].into_iter().chain(MemoryCardMode::ALL.map(Some)) {
+                            ui.selectable_value(&mut self.game_settings.memory_card_mode, option, label(option));
+                        }
+                    })
+                    .response
+                    .on_hover_text("Default follows Settings > System. Takes effect when the game restarts.");
+                if self.game_settings.memory_card_mode != before {
+                    self.save_game_settings();
                 }
 
                 ui.separator();
-
-                ui.horizontal(|ui| {
-                    if ui.button("Save").clicked() {
-                        if let Err(e) = self.config.save_keyboard_bindings() {
-                            tracing::error!("Failed to save keyboard bindings: {}", e);
+                let label = |pgxp: Option<bool>| match pgxp {
+                    None => "Default",
+                    Some(true) => "On",
+                    Some(false) => "Off",
+                };
+                let before = self.game_settings.pgxp;
+                egui::ComboBox::from_label("Precise vertices (PGXP)")
+                    .selected_text(label(before))
+                    .show_ui(ui, |ui| {
+                        for option in [None, Some(true), Some(false)] {
+                            ui.selectable_value(&mut self.game_settings.pgxp, option, label(option));
                         }
-                        if let Err(e) = self.config.save_gamepad_bindings() {
-                            tracing::error!("Failed to save gamepad bindings: {}", e);
+                    })
+                    .response
+                    .on_hover_text("Default follows Settings > Video");
+                if self.game_settings.pgxp != before {
+                    self.save_game_settings();
+                }
+                if ui.checkbox(&mut self.game_settings.widescreen_hack, "Widescreen hack")
+                    .on_hover_text(
+                        "Draw the 3D for a 16:9 screen instead of stretching it. The menus and the \
+                         HUD stay stretched, and the sides may stay empty in games that skip what \
+                         a 4:3 screen wouldn't show. Some games break, off during netplay and in \
+                         hardcore mode.",
+                    )
+                    .changed()
+                {
+                    self.save_game_settings();
+                }
+                let label = |interpolation: Option<Interpolation>| interpolation.map_or("Default", Interpolation::name);
+                let before = self.game_settings.interpolation;
+                egui::ComboBox::from_label("Sound interpolation")
+                    .selected_text(label(before))
+                    .show_ui(ui, |ui| {
+                        let options = std::iter::once(None).chain(Interpolation::ALL.map(Some));
+                        for option in options {
+                            ui.selectable_value(&mut self.game_settings.interpolation, option, label(option));
                         }
-                        self.show_input_config = false;
-                        self.waiting_for_key = None;
-                        self.waiting_for_gamepad_button = None;
-                    }
+                    })
+                    .response
+                    .on_hover_text("Default follows Settings > Audio");
+                if self.game_settings.interpolation != before {
+                    self.save_game_settings();
+                }
 
-                    if ui.button("Reset to Defaults").clicked() {
-                        if let Err(e) = self.config.reset_to_defaults() {
-                            tracing::error!("Failed to reset bindings: {}", e);
-                        }
-                    }
+                ui.separator();
+                ui.label("Memory watches");
+                if self.render_memory_watches(ui) {
+                    self.save_game_settings();
+                }
 
-                    if ui.button("Cancel").clicked() {
-                        // Reload bindings from disk
-                        if let Ok(new_config) = ConfigManager::new() {
-                            self.config.keyboard_bindings = new_config.keyboard_bindings;
-                            self.config.gamepad_bindings = new_config.gamepad_bindings;
-                        }
-                        self.show_input_config = false;
-                        self.waiting_for_key = None;
-                        self.waiting_for_gamepad_button = None;
-                    }
-                });
+                ui.separator();
+                if ui.add_enabled(self.netplay.is_none(), egui::Button::new("Restart game")).clicked() {
+                    restart = true;
+                }
             });
 
-        self.show_input_config = show_input_config;
+        self.show_game_properties = show;
+
+        if restart {
+            self.restart_game();
+        }
     }
 
-    fn render_keyboard_config(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
-        if let Some(waiting_button) = self.waiting_for_key {
-            ui.label(format!("Press a key for {}...", button_display_name(&waiting_button)));
-            ui.label("(Press ESC to cancel)");
+    /// Editor for the addresses published to the autosplitters, returns true if they changed
+    fn render_memory_watches(&mut self, ui: &mut egui::Ui) -> bool {
+        let watches = &mut self.game_settings.memory_watches;
+        let mut changed = false;
+        let mut removed = None;
 
-            // Check for key press
-            ctx.input(|i| {
-                if i.key_pressed(Key::Escape) {
-                    self.waiting_for_key = None;
-                    return;
-                }
+        egui::Grid::new("memory_watches").show(ui, |ui| {
+            for (i, watch) in watches.iter_mut().enumerate() {
+                changed |= ui.add(egui::TextEdit::singleline(&mut watch.name).desired_width(100.0)).changed();
 
-                // Check for any key press
-                for key in [
-                    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H,
-                    Key::I, Key::J, Key::K, Key::L, Key::M, Key::N, Key::O, Key::P,
-                    Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X,
-                    Key::Y, Key::Z,
-                    Key::ArrowUp, Key::ArrowDown, Key::ArrowLeft, Key::ArrowRight,
-                    Key::Enter, Key::Space, Key::Backspace,
-                ] {
-                    if i.key_pressed(key) {
-                        // Remove old binding for this key
-                        self.config.keyboard_bindings.bindings.retain(|k, _| k != &key);
-                        // Add new binding
-                        self.config.keyboard_bindings.bindings.insert(key, waiting_button);
-                        self.waiting_for_key = None;
-                        return;
-                    }
+                changed |= ui.add(egui::DragValue::new(&mut watch.address).hexadecimal(8, false, false)).changed();
+
+                egui::ComboBox::from_id_salt(("watch_size", i))
+                    .selected_text(format!("{:?}", watch.size))
+                    .show_ui(ui, |ui| {
+                        for size in [WatchSize::U8, WatchSize::U16, WatchSize::U32] {
+                            changed |= ui.selectable_value(&mut watch.size, size, format!("{:?}", size)).changed();
+                        }
+                    });
+
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(i);
                 }
+                ui.end_row();
+            }
+        });
+
+        if let Some(i) = removed {
+            watches.remove(i);
+            changed = true;
+        }
+        if ui.button("Add watch").clicked() {
+            watches.push(MemoryWatch {
+                name: format!("value{}", watches.len() + 1),
+                address: 0x8000_0000,
+                size: WatchSize::U32,
             });
-        } else {
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                egui::Grid::new("keyboard_grid")
-                    .num_columns(3)
-                    .spacing([10.0, 4.0])
-                    .striped(true)
-                    .show(ui, |ui| {
-                        ui.label("Button");
-                        ui.label("Key");
-                        ui.label("");
-                        ui.end_row();
+            changed = true;
+        }
 
-                        // Define button order
-                        let buttons = [
-                            Button::DUp, Button::DDown, Button::DLeft, Button::DRight,
-                            Button::Cross, Button::Circle, Button::Square, Button::Triangle,
-                            Button::L1, Button::R1, Button::L2, Button::R2,
-                            Button::Start, Button::Select,
-                        ];
+        if self.config.settings.control.publish_watches {
+            ui.weak(format!("Published on port {}", self.config.settings.control.watch_port));
+        } else {
+            ui.weak("Settings > Remote Control > Publish memory watches to send them to autosplitters");
+        }
 
-                        for button in buttons {
-                            ui.label(button_display_name(&button));
+        changed
+    }
 
-                            // Find current key binding
-                            let current_key = self.config.keyboard_bindings.bindings
-                                .iter()
-                                .find(|(_, b)| **b == button)
-                                .map(|(k, _)| *k);
+    fn render_stall(&mut self, ctx: &egui::Context) {
+        let Some(report) = &self.stall else {
+            return;
+        };
 
-                            let key_text = current_key
-                                .map(|k| key_display_name(&k))
-                                .unwrap_or_else(|| "Unbound".to_string());
+        let mut open = true;
+        let mut resume = false;
+        let mut reset = false;
+        let mut bug_report = false;
 
-                            ui.label(key_text);
+        egui::Window::new(&report.title)
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .show(ctx, |ui| {
+                if report.fatal {
+                    ui.label("Emulation stopped, the game has to be restarted.");
+                } else {
+                    ui.label(
+                        "Emulation is paused. The game is probably waiting for hardware that isn't \
+                         emulated properly, but it may just be a very long loading screen.",
+                    );
+                }
+                ui.label("Please attach the details below when reporting the problem.");
+                ui.separator();
 
-                            if ui.button("Change").clicked() {
-                                self.waiting_for_key = Some(button);
-                            }
+                egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    ui.monospace(&report.details);
+                });
 
-                            ui.end_row();
-                        }
-                    });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Copy").clicked() {
+                        ui.ctx().copy_text(report.details.clone());
+                    }
+                    if ui.button("Create Bug Report").clicked() {
+                        bug_report = true;
+                    }
+                    if !report.fatal && ui.button("Resume").clicked() {
+                        resume = true;
+                    }
+                    if ui.add_enabled(self.netplay.is_none(), egui::Button::new("Restart game")).clicked() {
+                        reset = true;
+                    }
+                });
             });
+
+        if bug_report {
+            self.create_bug_report();
+        }
+        if resume {
+            self.paused = false;
+            self.watchdog.reset();
+        }
+        if resume || !open {
+            self.stall = None;
+        }
+        if reset {
+            self.restart_game();
+            self.paused = false;
         }
     }
 
-    fn render_gamepad_config(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
-        if let Some(waiting_button) = self.waiting_for_gamepad_button {
-            ui.label(format!("Press a gamepad button for {}...", button_display_name(&waiting_button)));
-            ui.label("(Press any key to cancel)");
+    fn render_bios_error(&mut self, ctx: &egui::Context) {
+        let Some(search) = &self.bios_error else {
+            return;
+        };
 
-            // Check for gamepad button press
-            if let Some(gilrs) = &mut self.gamepad.gilrs {
-                while let Some(event) = gilrs.next_event() {
-                    if let gilrs::EventType::ButtonPressed(gilrs_button, _) = event.event {
-                        // Remove old binding for this button
-                        self.config.gamepad_bindings.bindings.retain(|b, _| b != &gilrs_button);
-                        // Add new binding
-                        self.config.gamepad_bindings.bindings.insert(gilrs_button, waiting_button);
-                        self.waiting_for_gamepad_button = None;
-                        return;
-                    }
-                }
-            }
+        let mut open = true;
+        let mut choose = false;
+        let mut retry = false;
 
-            // Check for cancel
-            ctx.input(|i| {
-                if !i.keys_down.is_empty() {
-                    self.waiting_for_gamepad_button = None;
+        egui::Window::new("No supported BIOS")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "The game can't start without a PlayStation BIOS. Put a dump (the 512 KiB \
+                     ROM of the console, as is or in a ZIP archive) in {}, or pick one in the \
+                     settings.",
+                    search.dir.display(),
+                ));
+                ui.separator();
+
+                if search.candidates.is_empty() {
+                    ui.label("No file of the right size was found there.");
+                } else {
+                    ui.label("Found, but not supported:");
+                    egui::Grid::new("bios_candidates").striped(true).show(ui, |ui| {
+                        for candidate in &search.candidates {
+                            let mut file = candidate.path.display().to_string();
+                            if let Some(entry) = &candidate.entry {
+                                file = format!("{} ({})", file, entry);
+                            }
+                            ui.label(file);
+                            match &candidate.sha256 {
+                                Ok(sha256) => ui.monospace(sha256),
+                                Err(e) => ui.colored_label(ui.visuals().error_fg_color, e),
+                            };
+                            ui.end_row();
+                        }
+                    });
+                    ui.weak("A dump that isn't listed below is either from a console we don't know or corrupted.");
                 }
-            });
-        } else {
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                egui::Grid::new("gamepad_grid")
-                    .num_columns(3)
-                    .spacing([10.0, 4.0])
-                    .striped(true)
-                    .show(ui, |ui| {
-                        ui.label("PS1 Button");
-                        ui.label("Gamepad Button");
-                        ui.label("");
-                        ui.end_row();
 
-                        let buttons = [
-                            Button::DUp, Button::DDown, Button::DLeft, Button::DRight,
-                            Button::Cross, Button::Circle, Button::Square, Button::Triangle,
-                            Button::L1, Button::R1, Button::L2, Button::R2,
-                            Button::Start, Button::Select,
-                        ];
+                egui::CollapsingHeader::new("Supported dumps").show(ui, |ui| {
+                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                        egui::Grid::new("bios_known").striped(true).show(ui, |ui| {
+                            for known in bios::known_dumps() {
+                                ui.label(format!("v{}", known.version));
+                                ui.label(known.region.name());
+                                ui.monospace(known.sha256);
+                                ui.end_row();
+                            }
+                        });
+                    });
+                });
 
-                        for button in buttons {
-                            ui.label(button_display_name(&button));
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Choose BIOS...").clicked() {
+                        choose = true;
+                    }
+                    if ui.button("Retry").clicked() {
+                        retry = true;
+                    }
+                });
+            });
 
-                            // Find current gamepad binding
-                            let current_gilrs = self.config.gamepad_bindings.bindings
-                                .iter()
-                                .find(|(_, b)| **b == button)
-                                .map(|(g, _)| *g);
+        if choose {
+            self.bios_scan = None;
+            self.show_settings = true;
+        }
+        if !open {
+            self.bios_error = None;
+        }
+        if retry {
+            self.restart_game();
+        }
+    }
 
-                            let gilrs_text = current_gilrs
-                                .map(|g| format!("{:?}", g))
-                                .unwrap_or_else(|| "Unbound".to_string());
+    fn render_safe_mode_offer(&mut self, ctx: &egui::Context) {
+        if !self.offer_safe_mode {
+            return;
+        }
 
-                            ui.label(gilrs_text);
+        let mut start_normally = false;
 
-                            if ui.button("Change").clicked() {
-                                self.waiting_for_gamepad_button = Some(button);
-                            }
+        egui::Window::new("MIPS didn't exit properly")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("The emulator crashed or was killed during the last run.");
+                ui.label(
+                    "If it keeps happening, safe mode starts it with software rendering, the \
+                     default settings, and without game patches, startup scripts, cloud sync or \
+                     netplay. Your files are only replaced if you save settings in safe mode.",
+                );
+                ui.separator();
 
-                            ui.end_row();
+                ui.horizontal(|ui| {
+                    if ui.button("Restart in Safe Mode").clicked() {
+                        match safe_mode::relaunch() {
+                            Ok(()) => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                            Err(e) => tracing::error!("Failed to restart in safe mode: {}", e),
                         }
-                    });
+                    }
+                    if ui.button("Start Normally").clicked() {
+                        start_normally = true;
+                    }
+                });
             });
+
+        if start_normally {
+            self.offer_safe_mode = false;
+            self.paused = false;
         }
     }
 
@@ -594,22 +4294,174 @@ impl EmulatorApp {
 }
 
 impl eframe::App for EmulatorApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if self.config.settings.cloud.sync_on_launch {
+            self.sync_saves();
+        }
+        self.sentinel.remove();
+    }
+
+    fn raw_input_hook(&mut self, ctx: &egui::Context, raw_input: &mut egui::RawInput) {
+        if self.gamepad_ui.active {
+            let buttons: Vec<NavButton> = self.gamepad.poll_ui().into_iter().filter_map(NavButton::from_gilrs).collect();
+            self.gamepad_ui.handle(ctx, &buttons, raw_input);
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.update_title(ctx);
+        self.handle_hotkeys(ctx);
+        self.step_fullscreen(ctx);
+        self.receive_screenshot(ctx);
+
+        // A screenshot of the game picture alone is taken on a frame drawn without the overlays
+        // and the windows
+        let screenshot = match self.screenshot {
+            Some(PendingScreenshot::Requested(source)) => Some(source),
+            _ => None,
+        };
+        let show_ui = screenshot != Some(ScreenshotSource::Presented);
+
         // Update emulator (adaptive timing)
         self.update_emulator(ctx);
 
         // Render UI
         self.render_menu_bar(ctx);
+        // Before the game, which takes the space the dock leaves
+        self.render_tools(ctx, false);
         self.render_game(ctx);
-        self.render_settings(ctx);
-        self.render_input_config(ctx);
-        self.render_about(ctx);
+        if show_ui {
+            self.render_osd(ctx);
+            self.render_settings(ctx);
+            self.render_input_config(ctx);
+            self.render_netplay(ctx);
+            self.render_controller_test(ctx);
+            self.render_latency_calibration(ctx);
+            self.render_games_list(ctx);
+            self.render_game_properties(ctx);
+            self.render_tools(ctx, true);
+            self.render_stall(ctx);
+            self.render_bios_error(ctx);
+            self.render_safe_mode_offer(ctx);
+            self.render_about(ctx);
+            self.gamepad_ui.show(ctx);
+        }
+        if let Some(source) = screenshot {
+            self.capture_screenshot(ctx, source);
+        }
+        self.workspace.persist(ctx);
+
+        // The gamepad drives the UI while the game isn't being played, unless a window is reading
+        // it
+        let in_menu = self.paused || (self.games_list.open && self.netplay.is_none());
+        self.gamepad_ui.active = (in_menu || ctx.wants_keyboard_input())
+            && !self.show_input_config
+            && !self.controller_test.open
+            && !self.latency_calibration.open;
 
-        // Request repaint based on vsync setting
-        if self.config.settings.video.vsync {
-            ctx.request_repaint_after(std::time::Duration::from_secs_f64(1.0/60.0));
+        // Request repaint based on vsync setting. Capture tools hooking the window need it to
+        // present continuously.
+        if self.config.settings.video.vsync && !self.config.settings.video.capture_friendly {
+            ctx.request_repaint_after(self.refresh_period(ctx));
         } else {
             ctx.request_repaint();
         }
     }
-}
\ No newline at end of file
+}
+/// Title of the window without a game
+pub fn window_title(safe_mode: bool) -> &'static str {
+    if safe_mode {
+        "MIPS - PlayStation Emulator (Safe Mode)"
+    } else {
+        "MIPS - PlayStation Emulator"
+    }
+}
+
+/// Settings of a game the user didn't configure
+fn auto_game_settings(config: &ConfigManager, mips: &ConsoleManager) -> GameSettings {
+    let mut settings = GameSettings::default();
+
+    if config.settings.input.auto_controller_type
+        && let Some(serial) = mips.serial_number()
+    {
+        let controller = compat::preferred_controller(&serial);
+        info!("Using a {} for {}", controller.name(), serial);
+        settings.controllers[0] = controller;
+    }
+
+    settings
+}
+
+/// Insert the memory cards of the mode the game uses, read-only where the game says so
+fn insert_memory_cards(
+    mips: &mut ConsoleManager,
+    config: &ConfigManager,
+    game_settings: &GameSettings,
+    game: Option<&str>,
+) {
+    if !mips.is_running() {
+        return;
+    }
+
+    let mode = game_settings.memory_card_mode.unwrap_or(config.settings.system.memory_card_mode);
+    mips.set_memory_card_scope(match mode {
+        MemoryCardMode::PerSerial => MemoryCardScope::PerSerial,
+        _ => MemoryCardScope::Shared,
+    });
+    for (slot, &read_only) in game_settings.read_only_memory_cards.iter().enumerate() {
+        mips.set_memory_card_read_only(slot, read_only);
+        // The console inserted the card of the serial number
+        if mode == MemoryCardMode::PerSerial && slot == 0 {
+            continue;
+        }
+
+        let path = config::memory_card_path(game, slot, mode);
+        if let Err(e) = mips.set_memory_card_file(slot, &path) {
+            tracing::warn!("{}", e);
+        }
+    }
+}
+
+fn stall_watchdog(config: &ConfigManager) -> StallWatchdog {
+    StallWatchdog::new(Duration::from_secs(config.settings.system.stall_timeout_secs.into()))
+}
+
+/// Log a failure to start the game, returns what was found if it's because of the BIOS
+fn load_error(result: Result<(), MipsError>) -> Option<BiosSearch> {
+    match result {
+        Ok(()) => None,
+        Err(MipsError::NoBios(search)) => {
+            tracing::error!("Failed to load game: no supported BIOS: {}", search);
+            Some(search)
+        }
+        Err(e) => {
+            tracing::error!("Failed to load game: {}", e);
+            None
+        }
+    }
+}
+
+/// Text shown in the settings after a cloud sync
+fn sync_status(result: Option<AppResult<SyncReport>>) -> Option<String> {
+    match result? {
+        Ok(report) => Some(format!("Synced: {}", report.summary())),
+        Err(e) => {
+            tracing::error!("Cloud sync failed: {}", e);
+            Some(e.to_string())
+        }
+    }
+}
+
+/// Parse a "host:port" (or just "host") netplay address
+fn resolve_netplay_addr(addr: &str, default_port: u16) -> Option<SocketAddr> {
+    let addr = addr.trim();
+    if addr.is_empty() {
+        return None;
+    }
+
+    if let Ok(mut addrs) = addr.to_socket_addrs() {
+        return addrs.next();
+    }
+
+    (addr, default_port).to_socket_addrs().ok()?.next()
+}
@@ -1,11 +1,41 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use mips_core::AudioChunk;
+use mips_core::audio::AUDIO_FREQ_HZ;
 use rodio::{DeviceSinkBuilder, MixerDeviceSink, Player};
 use rodio::buffer::SamplesBuffer;
 use rodio::nz;
 use tracing::info;
+use crate::cli::AudioBackend;
+
+/// Destination for the interleaved stereo samples produced by the emulator
+pub trait AudioSink {
+    fn enqueue(&self, chunk: AudioChunk);
+    fn set_volume(&self, volume: f32);
+    /// Stretch the audio slightly to keep the output buffer at the same level, see `RateControl`
+    fn set_rate_control(&self, enabled: bool);
+}
+
+/// Largest change of the playback rate made by the rate control, inaudible
+const MAX_RATE_DEVIATION: f64 = 0.005;
+/// Amount of audio the rate control keeps in the output buffer, in stereo pairs (50 ms)
+const TARGET_BUFFERED_FRAMES: usize = AUDIO_FREQ_HZ as usize / 20;
+
+/// Open the sink matching `backend`
+pub fn open(backend: AudioBackend) -> anyhow::Result<Box<dyn AudioSink>> {
+    match backend {
+        AudioBackend::Device => Ok(Box::new(AudioManager::new()?)),
+        AudioBackend::Null => {
+            info!("Audio output disabled (null sink)");
+            Ok(Box::new(NullAudio))
+        }
+    }
+}
 
 pub struct AudioManager {
     _handle: MixerDeviceSink,
     player: Player,
+    rate_control: Mutex<Option<RateControl>>,
 }
 
 impl AudioManager {
@@ -19,21 +49,155 @@ impl AudioManager {
         Ok(Self {
             _handle: handle,
             player,
+            rate_control: Mutex::new(None),
         })
     }
+}
 
-    pub fn enqueue(&self, samples: &[i16]) {
-        if samples.is_empty() {
+impl AudioSink for AudioManager {
+    fn enqueue(&self, chunk: AudioChunk) {
+        if chunk.is_empty() {
             return;
         }
-        let samples_f32: Vec<f32> = samples.iter()
-            .map(|&s| s as f32 / 32768.0)
+
+        let samples = match &mut *self.rate_control.lock().unwrap() {
+            Some(rate_control) => rate_control.process(&chunk, self.player.len()),
+            None => chunk.samples,
+        };
+
+        let samples_f32: Vec<f32> = samples.into_iter()
+            .map(|s| s as f32 / 32768.0)
             .collect();
         let buf = SamplesBuffer::new(nz!(2u16), nz!(44100u32), samples_f32);
         self.player.append(buf);
     }
 
-    pub fn set_volume(&self, volume: f32) {
+    fn set_volume(&self, volume: f32) {
         self.player.set_volume(volume.clamp(0.0, 1.0));
     }
-}
\ No newline at end of file
+
+    fn set_rate_control(&self, enabled: bool) {
+        let mut rate_control = self.rate_control.lock().unwrap();
+
+        if enabled != rate_control.is_some() {
+            *rate_control = enabled.then(RateControl::new);
+        }
+    }
+}
+
+/// Sink that drops everything, for machines without an audio device
+pub struct NullAudio;
+
+impl AudioSink for NullAudio {
+    fn enqueue(&self, _chunk: AudioChunk) {}
+
+    fn set_volume(&self, _volume: f32) {}
+
+    fn set_rate_control(&self, _enabled: bool) {}
+}
+
+/// Dynamic rate control, as in RetroArch: the console makes its samples on its own clock and the
+/// frames are paced on the host's, the two never quite agree (refresh rate other than 59.94 Hz,
+/// drifting crystals...) and the output buffer slowly fills up or runs dry. The samples are
+/// resampled by up to `MAX_RATE_DEVIATION` to keep it at `TARGET_BUFFERED_FRAMES`.
+struct RateControl {
+    /// Size in stereo pairs of the chunks appended to the player and not played yet, oldest first
+    queued: VecDeque<usize>,
+    /// Timestamp expected for the next chunk, the resampler starts over when the stream jumps
+    next_timestamp: u64,
+    /// Position of the next output pair in the input, in pairs from the first one of the chunk
+    /// (-1 is `last`)
+    position: f64,
+    /// Last pair of the previous chunk
+    last: [i16; 2],
+}
+
+impl RateControl {
+    fn new() -> Self {
+        Self {
+            queued: VecDeque::new(),
+            next_timestamp: 0,
+            position: 0.0,
+            last: [0; 2],
+        }
+    }
+
+    /// Output pairs per input pair for `buffered` pairs waiting in the output buffer: a bit more
+    /// than 1 when it's running low, a bit less when it's filling up
+    fn ratio(buffered: usize) -> f64 {
+        let fill = (buffered as f64 / (2 * TARGET_BUFFERED_FRAMES) as f64).min(1.0);
+
+        1.0 + MAX_RATE_DEVIATION * (1.0 - 2.0 * fill)
+    }
+
+    /// Resample `chunk` for a player holding `player_len` chunks
+    fn process(&mut self, chunk: &AudioChunk, player_len: usize) -> Vec<i16> {
+        // The player drops the chunks it's done with
+        while self.queued.len() > player_len {
+            self.queued.pop_front();
+        }
+        let buffered = self.queued.iter().sum();
+
+        if chunk.timestamp != self.next_timestamp {
+            self.position = 0.0;
+            self.last = [0; 2];
+        }
+        self.next_timestamp = chunk.timestamp + chunk.frames() as u64;
+
+        let samples = self.resample(&chunk.samples, Self::ratio(buffered));
+        self.queued.push_back(samples.len() / 2);
+
+        samples
+    }
+
+    /// Linear interpolation of the stereo `input`
+    fn resample(&mut self, input: &[i16], ratio: f64) -> Vec<i16> {
+        let frames = input.len() / 2;
+        let pair = |i: isize| match usize::try_from(i) {
+            Ok(i) => [input[i * 2], input[i * 2 + 1]],
+            Err(_) => self.last,
+        };
+
+        let step = 1.0 / ratio;
+        let mut output = Vec::with_capacity((frames as f64 * ratio) as usize * 2 + 4);
+
+        // The pair after the last one is in the next chunk
+        while self.position < (frames - 1) as f64 {
+            let index = self.position.floor();
+            let frac = self.position - index;
+            let (a, b) = (pair(index as isize), pair(index as isize + 1));
+
+            for channel in 0..2 {
+                let sample = f64::from(a[channel]) + (f64::from(b[channel]) - f64::from(a[channel])) * frac;
+                output.push(sample.round() as i16);
+            }
+
+            self.position += step;
+        }
+
+        self.position -= frames as f64;
+        self.last = pair(frames as isize - 1);
+
+        output
+    }
+}
+
+#[test]
+fn rate_control() {
+    assert!(RateControl::ratio(0) > 1.0);
+    assert_eq!(RateControl::ratio(TARGET_BUFFERED_FRAMES), 1.0);
+    assert!(RateControl::ratio(10 * TARGET_BUFFERED_FRAMES) < 1.0);
+
+    // A steady stream comes out at the requested rate
+    let mut rate_control = RateControl::new();
+    let chunk: Vec<i16> = (0..735 * 2).map(|i| (i / 2) as i16).collect();
+    let ratio = 1.0 + MAX_RATE_DEVIATION;
+    let output: usize = (0..100).map(|_| rate_control.resample(&chunk, ratio).len() / 2).sum();
+    let expected = 735.0 * 100.0 * ratio;
+    assert!((output as f64 - expected).abs() < 2.0, "{} pairs, expected {}", output, expected);
+
+    // Interpolated between the input pairs
+    let mut rate_control = RateControl::new();
+    let output = rate_control.resample(&[0, 0, 100, -100, 200, -200], 2.0);
+    assert_eq!(output, [0, 0, 50, -50, 100, -100, 150, -150]);
+}
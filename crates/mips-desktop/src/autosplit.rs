@@ -0,0 +1,144 @@
+//! Memory watches for speedrun autosplitters (LiveSplit...): the values at the addresses listed in
+//! the game's settings are published after every frame to the programs connected over TCP on the
+//! loopback interface, so they don't have to find the emulated RAM in the emulator's process.
+//! Nothing is read from the clients, they can't change anything.
+//!
+//! One JSON object per line and per frame, `frame` counts from the start of the game:
+//!
+//! ```text
+//! {"frame":5120,"game":"Crash Bandicoot (USA).cue","values":{"level":3,"igt":41870}}
+//! ```
+
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Map, Value};
+use tracing::{info, warn};
+use crate::config::{MemoryWatch, WatchSize};
+
+pub const DEFAULT_WATCH_PORT: u16 = 47011;
+
+/// How often the listening thread checks whether the server was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Clients that don't keep up for that long are dropped, the frames can't wait for them
+const WRITE_TIMEOUT: Duration = Duration::from_millis(10);
+
+impl WatchSize {
+    pub fn byte_len(self) -> usize {
+        match self {
+            WatchSize::U8 => 1,
+            WatchSize::U16 => 2,
+            WatchSize::U32 => 4,
+        }
+    }
+
+    /// Value of the little-endian `bytes` read for the watch
+    pub fn value(self, bytes: &[u8]) -> u32 {
+        bytes.iter().take(self.byte_len()).rev().fold(0, |value, &b| (value << 8) | u32::from(b))
+    }
+}
+
+pub struct WatchServer {
+    port: u16,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl WatchServer {
+    pub fn start(port: u16) -> io::Result<WatchServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (thread_clients, thread_stop) = (clients.clone(), stop.clone());
+        thread::Builder::new()
+            .name("memory watches".to_string())
+            .spawn(move || accept_loop(listener, thread_clients, thread_stop))?;
+
+        info!("Publishing memory watches on 127.0.0.1:{}", port);
+
+        Ok(WatchServer { port, clients, stop })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn has_clients(&self) -> bool {
+        !self.clients.lock().unwrap().is_empty()
+    }
+
+    /// Send the values of `watches` to every client. `read` gives the RAM at an address.
+    pub fn publish(
+        &self,
+        frame: u64,
+        game: Option<&str>,
+        watches: &[MemoryWatch],
+        read: impl Fn(u32, usize) -> Option<Vec<u8>>,
+    ) {
+        let values: Map<String, Value> = watches.iter()
+            .filter_map(|watch| {
+                let bytes = read(watch.address, watch.size.byte_len())?;
+                Some((watch.name.clone(), watch.size.value(&bytes).into()))
+            })
+            .collect();
+
+        let mut line = json!({ "frame": frame, "game": game, "values": values }).to_string();
+        line.push('\n');
+
+        self.clients.lock().unwrap().retain_mut(|client| match client.write_all(line.as_bytes()) {
+            Ok(()) => true,
+            Err(e) => {
+                info!("Memory watch client disconnected: {}", e);
+                false
+            }
+        });
+    }
+}
+
+impl Drop for WatchServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn accept_loop(listener: TcpListener, clients: Arc<Mutex<Vec<TcpStream>>>, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                let ready = stream.set_nonblocking(false)
+                    .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+                    .and_then(|_| stream.set_nodelay(true));
+                match ready {
+                    Ok(()) => {
+                        info!("Memory watch client connected from {}", addr);
+                        clients.lock().unwrap().push(stream);
+                    }
+                    Err(e) => warn!("Memory watch client {} rejected: {}", addr, e),
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                warn!("Memory watch server stopped: {}", e);
+                return;
+            }
+        }
+    }
+
+    // Closes the connections
+    clients.lock().unwrap().clear();
+}
+
+#[test]
+fn watch_values() {
+    assert_eq!(WatchSize::U8.value(&[0x12]), 0x12);
+    assert_eq!(WatchSize::U16.value(&[0x34, 0x12]), 0x1234);
+    assert_eq!(WatchSize::U32.value(&[0x78, 0x56, 0x34, 0x12]), 0x1234_5678);
+}
@@ -0,0 +1,146 @@
+//! Block cache tool: the blocks of game code the recompiler translated, how often they ran, how
+//! often the game overwrote them and the host code of the one picked.
+
+use std::time::{Duration, Instant};
+use mips_core::ConsoleManager;
+use mips_core::block_cache::CompiledBlock;
+
+/// How often the list is taken from the recompiler again, it copies the whole cache
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Bytes per line of the host code dump
+const DUMP_WIDTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SortBy {
+    #[default]
+    Executions,
+    Invalidations,
+    HostSize,
+    Address,
+}
+
+impl SortBy {
+    const ALL: [SortBy; 4] = [SortBy::Executions, SortBy::Invalidations, SortBy::HostSize, SortBy::Address];
+
+    fn name(self) -> &'static str {
+        match self {
+            SortBy::Executions => "Runs",
+            SortBy::Invalidations => "Invalidations",
+            SortBy::HostSize => "Host size",
+            SortBy::Address => "Address",
+        }
+    }
+
+    fn sort(self, blocks: &mut [CompiledBlock]) {
+        match self {
+            SortBy::Executions => blocks.sort_by(|a, b| b.executions.cmp(&a.executions)),
+            SortBy::Invalidations => blocks.sort_by(|a, b| b.invalidations.cmp(&a.invalidations)),
+            SortBy::HostSize => blocks.sort_by(|a, b| b.host_size.cmp(&a.host_size)),
+            SortBy::Address => blocks.sort_by_key(|b| b.start),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BlockCacheView {
+    blocks: Vec<CompiledBlock>,
+    refreshed: Option<Instant>,
+    sort: SortBy,
+    /// Start of the block picked, with its host code once fetched
+    selected: Option<(u32, Option<Vec<u8>>)>,
+}
+
+impl BlockCacheView {
+    pub fn ui(&mut self, ui: &mut egui::Ui, mips: &ConsoleManager) {
+        if self.refreshed.is_none_or(|at| at.elapsed() >= REFRESH_INTERVAL) {
+            self.refresh(mips);
+        }
+
+        if self.blocks.is_empty() {
+            ui.label("No translated blocks: the recompiler isn't running.");
+            ui.weak("Pick the Recompiler in Settings > System > CPU.");
+            return;
+        }
+
+        let host_size: usize = self.blocks.iter().map(|b| b.host_size).sum();
+        ui.horizontal(|ui| {
+            ui.label(format!("{} blocks, {} KiB of host code", self.blocks.len(), host_size.div_ceil(1024)));
+            egui::ComboBox::from_id_salt("block_cache_sort")
+                .selected_text(format!("Sort by {}", self.sort.name()))
+                .show_ui(ui, |ui| {
+                    for sort in SortBy::ALL {
+                        if ui.selectable_value(&mut self.sort, sort, sort.name()).changed() {
+                            sort.sort(&mut self.blocks);
+                        }
+                    }
+                });
+        });
+        ui.separator();
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        ui.monospace(format!("{:<8} {:<8} {:>5} {:>6} {:>12} {:>6}", "Start", "End", "Ins", "Host", "Runs", "Inval"));
+        egui::ScrollArea::vertical()
+            .id_salt("block_cache_blocks")
+            .max_height(ui.available_height() * 0.6)
+            .auto_shrink(false)
+            .show_rows(ui, row_height, self.blocks.len(), |ui, rows| {
+                for block in &self.blocks[rows] {
+                    let text = format!(
+                        "{:08x} {:08x} {:>5} {:>6} {:>12} {:>6}",
+                        block.start,
+                        block.end,
+                        block.len(),
+                        block.host_size,
+                        block.executions,
+                        block.invalidations,
+                    );
+                    let selected = self.selected.as_ref().is_some_and(|(pc, _)| *pc == block.start);
+                    if ui.selectable_label(selected, egui::RichText::new(text).monospace()).clicked() {
+                        self.selected = Some((block.start, mips.compiled_block_code(block.start)));
+                    }
+                }
+            });
+
+        let Some((pc, code)) = &self.selected else {
+            return;
+        };
+
+        ui.separator();
+        let Some(code) = code else {
+            ui.label(format!("The block at {pc:08x} was dropped since it was picked."));
+            return;
+        };
+
+        let dump = hex_dump(code);
+        ui.horizontal(|ui| {
+            ui.label(format!("Host code of the block at {pc:08x}"));
+            if ui.button("Copy").clicked() {
+                ui.ctx().copy_text(dump.clone());
+            }
+        })
+        .response
+        .on_hover_text("x86-64 code, disassemble it with objdump -D -b binary -mi386:x86-64");
+        egui::ScrollArea::vertical().id_salt("block_cache_code").auto_shrink(false).show(ui, |ui| {
+            ui.monospace(dump);
+        });
+    }
+
+    fn refresh(&mut self, mips: &ConsoleManager) {
+        self.blocks = mips.compiled_blocks();
+        self.sort.sort(&mut self.blocks);
+        self.refreshed = Some(Instant::now());
+    }
+}
+
+/// Offset and bytes of `code`, `DUMP_WIDTH` per line
+fn hex_dump(code: &[u8]) -> String {
+    code.chunks(DUMP_WIDTH)
+        .enumerate()
+        .map(|(line, bytes)| {
+            let bytes: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            format!("{:04x}  {}", line * DUMP_WIDTH, bytes.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
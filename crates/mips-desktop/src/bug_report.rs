@@ -0,0 +1,207 @@
+//! Bug report bundles: a ZIP archive holding what's needed to look into a problem (recent log,
+//! settings, versions of the emulator and the game, state of the console), so that every report
+//! comes with the same information.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Size of the log kept in memory for the reports
+const LOG_CAPACITY: usize = 512 * 1024;
+
+/// Directory the reports are written to
+const BUG_REPORTS_DIR: &str = "bug_reports";
+
+/// The end of the log, as written by the `tracing` subscriber
+#[derive(Clone)]
+pub struct LogBuffer {
+    capacity: usize,
+    data: Arc<Mutex<VecDeque<u8>>>,
+}
+
+impl LogBuffer {
+    pub fn new() -> Self {
+        Self::with_capacity(LOG_CAPACITY)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            data: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// The lines logged last, oldest first
+    pub fn contents(&self) -> String {
+        let data = self.data.lock().unwrap();
+        let mut bytes: Vec<u8> = data.iter().copied().collect();
+
+        // Drop the line cut in half when the buffer wrapped around
+        if data.len() == self.capacity {
+            let start = bytes.iter().position(|&b| b == b'\n').map_or(0, |nl| nl + 1);
+            bytes.drain(..start);
+        }
+
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut data = self.data.lock().unwrap();
+
+        let kept = &buf[buf.len().saturating_sub(self.capacity)..];
+        let overflow = (data.len() + kept.len()).saturating_sub(self.capacity);
+        data.drain(..overflow);
+        data.extend(kept);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = LogBuffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Everything that goes in a report
+pub struct BugReport {
+    /// Game file name
+    pub game: Option<String>,
+    pub serial: Option<String>,
+    /// Hex SHA-256 of the game executable
+    pub game_hash: Option<String>,
+    /// State of the console
+    pub diagnostics: String,
+    /// `settings.toml`, without passwords
+    pub settings: String,
+    /// Settings of the game, if one is running
+    pub game_settings: Option<String>,
+    pub log: String,
+}
+
+impl BugReport {
+    /// Where a report made now is saved
+    pub fn default_path() -> PathBuf {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        Path::new(BUG_REPORTS_DIR).join(format!("mips-bug-report-{}.zip", now))
+    }
+
+    fn summary(&self) -> String {
+        let unknown = || "unknown".to_string();
+
+        format!(
+            "Emulator: mips-desktop {}\n\
+             System: {} {}\n\
+             Game: {}\n\
+             Serial: {}\n\
+             Executable SHA-256: {}\n",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            self.game.clone().unwrap_or_else(|| "none".to_string()),
+            self.serial.clone().unwrap_or_else(unknown),
+            self.game_hash.clone().unwrap_or_else(unknown),
+        )
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let summary = self.summary();
+
+        let mut files = vec![
+            ("summary.txt", summary.as_bytes()),
+            ("log.txt", self.log.as_bytes()),
+            ("settings.toml", self.settings.as_bytes()),
+        ];
+        if let Some(game_settings) = &self.game_settings {
+            files.push(("game_settings.toml", game_settings.as_bytes()));
+        }
+        if !self.diagnostics.is_empty() {
+            files.push(("diagnostics.txt", self.diagnostics.as_bytes()));
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, zip(&files))
+    }
+}
+
+/// ZIP archive of `files`, stored without compression
+fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    // 1980-01-01, the earliest DOS date
+    const DOS_DATE: u16 = 0x21;
+
+    let mut archive = Vec::new();
+    let mut central = Vec::new();
+
+    for (name, data) in files {
+        let offset = archive.len() as u32;
+
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&0u16.to_le_bytes()); // flags
+        fields.extend_from_slice(&0u16.to_le_bytes()); // stored
+        fields.extend_from_slice(&0u16.to_le_bytes()); // time
+        fields.extend_from_slice(&DOS_DATE.to_le_bytes());
+        fields.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+
+        archive.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        archive.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        archive.extend_from_slice(&fields);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(data);
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        central.extend_from_slice(&fields);
+        central.extend_from_slice(&[0; 10]); // comment length, disk, attributes
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+
+    let central_offset = archive.len() as u32;
+    archive.extend_from_slice(&central);
+
+    archive.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    archive.extend_from_slice(&[0; 4]); // disk numbers
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&central_offset.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+    archive
+}
+
+#[test]
+fn log_buffer_keeps_the_last_lines() {
+    let mut log = LogBuffer::with_capacity(16);
+
+    log.write_all(b"first\n").unwrap();
+    assert_eq!(log.contents(), "first\n");
+
+    log.write_all(b"second\nthird\n").unwrap();
+    assert_eq!(log.contents(), "second\nthird\n");
+
+    log.write_all(b"a line longer than the buffer\n").unwrap();
+    assert_eq!(log.contents(), "");
+    log.write_all(b"fourth\n").unwrap();
+    assert_eq!(log.contents(), "fourth\n");
+}
@@ -0,0 +1,129 @@
+use anyhow::{bail, Result};
+use crate::netplay::protocol::LOBBY_PORT;
+use crate::safe_mode::SAFE_MODE_FLAG;
+
+/// Game loaded when nothing is given on the command line
+pub const DEFAULT_GAME: &str = "Silent Hill (USA).cue";
+
+/// Where the emulated video output ends up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoBackend {
+    /// Regular egui window
+    Window,
+    /// Frames are produced and dropped, no display server needed
+    Null,
+}
+
+/// Where the emulated audio output ends up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioBackend {
+    /// Default host audio device through rodio
+    Device,
+    /// Samples are consumed and dropped, no audio device needed
+    Null,
+}
+
+/// Command line options
+#[derive(Debug, Clone)]
+pub struct CliArgs {
+    pub video: VideoBackend,
+    pub audio: AudioBackend,
+    /// Disc image to load, relative to the games directory
+    pub game: Option<String>,
+    /// Number of frames to run before quitting (headless only, runs forever if `None`)
+    pub frames: Option<u64>,
+    /// Run as fast as possible instead of pacing frames at the console's refresh rate
+    pub unthrottled: bool,
+    /// Run a netplay lobby server on that port instead of the emulator
+    pub lobby_server: Option<u16>,
+    /// Software rendering, default settings and no patches or online services, to recover from a
+    /// configuration that crashes
+    pub safe_mode: bool,
+    /// Run a CPU or GTE test program headlessly and report its results instead of a game
+    pub conformance: Option<String>,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        Self {
+            video: VideoBackend::Window,
+            audio: AudioBackend::Device,
+            game: Some(DEFAULT_GAME.to_string()),
+            frames: None,
+            unthrottled: false,
+            lobby_server: None,
+            safe_mode: false,
+            conformance: None,
+        }
+    }
+}
+
+impl CliArgs {
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut cli = CliArgs::default();
+        let mut audio_set = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((n, v)) => (n.to_string(), Some(v.to_string())),
+                None => (arg.clone(), None),
+            };
+
+            let mut value = || -> Result<String> {
+                match inline_value.clone().or_else(|| args.next()) {
+                    Some(v) => Ok(v),
+                    None => bail!("Missing value for {}", name),
+                }
+            };
+
+            match name.as_str() {
+                "--headless" => {
+                    cli.video = VideoBackend::Null;
+                }
+                "--video" => {
+                    cli.video = match value()?.as_str() {
+                        "window" => VideoBackend::Window,
+                        "null" => VideoBackend::Null,
+                        other => bail!("Unknown video backend '{}'", other),
+                    };
+                }
+                "--audio" => {
+                    cli.audio = match value()?.as_str() {
+                        "device" => AudioBackend::Device,
+                        "null" => AudioBackend::Null,
+                        other => bail!("Unknown audio backend '{}'", other),
+                    };
+                    audio_set = true;
+                }
+                "--game" => cli.game = Some(value()?),
+                "--no-game" => cli.game = None,
+                "--frames" => cli.frames = Some(value()?.parse()?),
+                "--unthrottled" => cli.unthrottled = true,
+                "--conformance" => {
+                    cli.conformance = Some(value()?);
+                    cli.video = VideoBackend::Null;
+                }
+                "--lobby-server" => cli.lobby_server = Some(LOBBY_PORT),
+                "--lobby-port" => cli.lobby_server = Some(value()?.parse()?),
+                SAFE_MODE_FLAG => cli.safe_mode = true,
+                other => bail!("Unknown argument '{}'", other),
+            }
+        }
+
+        // Without a display server there's a good chance that there's no sound card either
+        if cli.video == VideoBackend::Null && !audio_set {
+            cli.audio = AudioBackend::Null;
+        }
+
+        Ok(cli)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.video == VideoBackend::Null
+    }
+}
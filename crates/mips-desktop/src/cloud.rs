@@ -0,0 +1,323 @@
+//! Sync of the per-game save directories (memory cards, save states) with a remote storage, so
+//! that saves follow the user across machines.
+//!
+//! On the remote, every game has a directory holding its files and a `manifest.json` listing their
+//! hashes. Locally, `.sync.json` in the save directory remembers the hashes as of the last sync:
+//! comparing the three tells which side changed a file since. When both did, the conflict policy
+//! picks the winner and the other version is kept next to the save with a `.conflict` suffix.
+//! Deletions aren't synced, a file missing on one side is always copied from the other.
+
+pub mod folder;
+pub mod webdav;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sha::sha256::Sha256;
+use sha::utils::{Digest, DigestExt};
+use tracing::{info, warn};
+use crate::config::{game_save_dir, CloudProvider, CloudSettings, ConflictPolicy};
+use crate::error::{AppError, AppResult};
+
+pub use folder::FolderProvider;
+pub use webdav::WebDavProvider;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const LOCAL_MANIFEST_FILE: &str = ".sync.json";
+const CONFLICT_SUFFIX: &str = ".conflict";
+
+/// Remote storage holding the saves. Paths are relative to the root of the storage and use `/`.
+pub trait SyncProvider {
+    /// Contents of `path`, `None` if it doesn't exist
+    fn get(&self, path: &str) -> AppResult<Option<Vec<u8>>>;
+
+    /// Create or replace `path`, along with its parent directories
+    fn put(&self, path: &str, data: &[u8]) -> AppResult<()>;
+}
+
+/// Provider configured in the settings, if any
+pub fn provider_from_settings(settings: &CloudSettings) -> Option<Box<dyn SyncProvider>> {
+    match &settings.provider {
+        CloudProvider::Disabled => None,
+        CloudProvider::WebDav { url, username, password } => {
+            Some(Box::new(WebDavProvider::new(url, username, password)))
+        }
+        CloudProvider::Folder { path } => Some(Box::new(FolderProvider::new(path))),
+    }
+}
+
+/// Sync the saves of `game` with the provider configured in the settings. `None` if cloud sync is
+/// disabled.
+pub fn sync_saves(settings: &CloudSettings, game: &str) -> Option<AppResult<SyncReport>> {
+    let provider = provider_from_settings(settings)?;
+
+    let dir = game_save_dir(game);
+    let name = dir.file_name()?.to_string_lossy().into_owned();
+
+    Some(sync_game(provider.as_ref(), &name, &dir, settings.conflict_policy))
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+struct Manifest {
+    files: BTreeMap<String, FileEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct FileEntry {
+    sha256: String,
+    /// Seconds since the epoch at which the file was last modified
+    modified: u64,
+}
+
+/// What a sync did
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub uploaded: Vec<String>,
+    pub downloaded: Vec<String>,
+    /// Files that changed on both sides
+    pub conflicts: Vec<String>,
+}
+
+impl SyncReport {
+    pub fn is_empty(&self) -> bool {
+        self.uploaded.is_empty() && self.downloaded.is_empty() && self.conflicts.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{} uploaded, {} downloaded", self.uploaded.len(), self.downloaded.len());
+
+        if !self.conflicts.is_empty() {
+            summary += &format!(", {} conflict(s) (see the .conflict files)", self.conflicts.len());
+        }
+
+        summary
+    }
+}
+
+enum Action {
+    Upload,
+    Download,
+}
+
+/// Sync the saves of `game`, stored locally in `local_dir`
+pub fn sync_game(
+    provider: &dyn SyncProvider,
+    game: &str,
+    local_dir: &Path,
+    policy: ConflictPolicy,
+) -> AppResult<SyncReport> {
+    fs::create_dir_all(local_dir)?;
+
+    let local = scan_local(local_dir)?;
+    let base: Manifest = read_json(&local_dir.join(LOCAL_MANIFEST_FILE)).unwrap_or_default();
+
+    let remote_manifest_path = format!("{}/{}", game, MANIFEST_FILE);
+    let mut remote: Manifest = match provider.get(&remote_manifest_path)? {
+        Some(data) => serde_json::from_slice(&data)
+            .map_err(|e| AppError::CloudSync(format!("Invalid remote manifest: {}", e)))?,
+        None => Manifest::default(),
+    };
+
+    let mut report = SyncReport::default();
+    let mut synced = Manifest::default();
+
+    let names: BTreeSet<String> = local.keys().chain(remote.files.keys()).cloned().collect();
+
+    for name in &names {
+        let l = local.get(name);
+        let r = remote.files.get(name).cloned();
+        let b = base.files.get(name);
+
+        let action = match (l, &r) {
+            (Some(l), Some(r)) if l.sha256 == r.sha256 => None,
+            (Some(_), None) => Some(Action::Upload),
+            (None, Some(_)) => Some(Action::Download),
+            (Some(l), Some(r)) => {
+                let local_changed = b.is_none_or(|b| b.sha256 != l.sha256);
+                let remote_changed = b.is_none_or(|b| b.sha256 != r.sha256);
+
+                if !local_changed {
+                    Some(Action::Download)
+                } else if !remote_changed {
+                    Some(Action::Upload)
+                } else {
+                    report.conflicts.push(name.clone());
+                    Some(resolve_conflict(provider, game, local_dir, name, l, r, policy)?)
+                }
+            }
+            (None, None) => unreachable!(),
+        };
+
+        let entry = match action {
+            None => l.cloned(),
+            Some(Action::Upload) => {
+                let data = fs::read(local_dir.join(name))?;
+                provider.put(&format!("{}/{}", game, name), &data)?;
+                report.uploaded.push(name.clone());
+
+                let entry = l.cloned();
+                if let Some(entry) = &entry {
+                    remote.files.insert(name.clone(), entry.clone());
+                }
+                entry
+            }
+            Some(Action::Download) => {
+                let data = download(provider, game, name)?;
+                write_atomic(&local_dir.join(name), &data)?;
+                report.downloaded.push(name.clone());
+
+                r
+            }
+        };
+
+        if let Some(entry) = entry {
+            synced.files.insert(name.clone(), entry);
+        }
+    }
+
+    if !report.uploaded.is_empty() {
+        provider.put(&remote_manifest_path, &to_json(&remote))?;
+    }
+    write_atomic(&local_dir.join(LOCAL_MANIFEST_FILE), &to_json(&synced))?;
+
+    if !report.is_empty() {
+        info!(
+            "Synced saves of {}: {} uploaded, {} downloaded, {} conflict(s)",
+            game, report.uploaded.len(), report.downloaded.len(), report.conflicts.len(),
+        );
+    }
+
+    Ok(report)
+}
+
+/// Pick a winner for a file changed on both sides, and keep the other version around
+fn resolve_conflict(
+    provider: &dyn SyncProvider,
+    game: &str,
+    local_dir: &Path,
+    name: &str,
+    local: &FileEntry,
+    remote: &FileEntry,
+    policy: ConflictPolicy,
+) -> AppResult<Action> {
+    let keep_local = match policy {
+        ConflictPolicy::Newest => local.modified >= remote.modified,
+        ConflictPolicy::Local => true,
+        ConflictPolicy::Remote => false,
+    };
+
+    let backup = local_dir.join(format!("{}{}", name, CONFLICT_SUFFIX));
+
+    if keep_local {
+        warn!("{} changed on both sides, keeping the local version", name);
+        let data = download(provider, game, name)?;
+        write_atomic(&backup, &data)?;
+        Ok(Action::Upload)
+    } else {
+        warn!("{} changed on both sides, keeping the remote version", name);
+        fs::copy(local_dir.join(name), &backup)?;
+        Ok(Action::Download)
+    }
+}
+
+fn download(provider: &dyn SyncProvider, game: &str, name: &str) -> AppResult<Vec<u8>> {
+    provider.get(&format!("{}/{}", game, name))?
+        .ok_or_else(|| AppError::CloudSync(format!("{} is listed in the manifest but missing", name)))
+}
+
+/// Hash and modification time of the files in `dir`, skipping our own bookkeeping
+fn scan_local(dir: &Path) -> AppResult<BTreeMap<String, FileEntry>> {
+    let mut files = BTreeMap::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') || name.ends_with(CONFLICT_SUFFIX) {
+            continue;
+        }
+
+        let data = fs::read(entry.path())?;
+        let modified = entry.metadata()?
+            .modified()
+            .unwrap_or(SystemTime::now())
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        files.insert(name, FileEntry { sha256: sha256_hex(&data), modified });
+    }
+
+    Ok(files)
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::default()
+        .digest(data)
+        .to_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Option<T> {
+    let data = fs::read(path).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+fn to_json<T: Serialize>(value: &T) -> Vec<u8> {
+    // Serializing our own types can't fail
+    serde_json::to_vec_pretty(value).unwrap()
+}
+
+/// Write to a temporary file first so that an interrupted sync doesn't leave a truncated save
+fn write_atomic(path: &Path, data: &[u8]) -> AppResult<()> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.tmp", name));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[test]
+fn sync_conflicts() {
+    let root = std::env::temp_dir().join(format!("mips-cloud-test-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    let provider = FolderProvider::new(&root.join("remote"));
+    let machine_a = root.join("a");
+    let machine_b = root.join("b");
+    let game = "Game (USA)";
+
+    // A uploads its card, B gets it
+    fs::create_dir_all(&machine_a).unwrap();
+    fs::write(machine_a.join("card1.mcr"), b"first").unwrap();
+    let report = sync_game(&provider, game, &machine_a, ConflictPolicy::Newest).unwrap();
+    assert_eq!(report.uploaded, vec!["card1.mcr".to_string()]);
+
+    let report = sync_game(&provider, game, &machine_b, ConflictPolicy::Newest).unwrap();
+    assert_eq!(report.downloaded, vec!["card1.mcr".to_string()]);
+    assert_eq!(fs::read(machine_b.join("card1.mcr")).unwrap(), b"first");
+
+    // Nothing changed
+    assert!(sync_game(&provider, game, &machine_b, ConflictPolicy::Newest).unwrap().is_empty());
+
+    // B saves and syncs, then A saves without syncing first
+    fs::write(machine_b.join("card1.mcr"), b"from b").unwrap();
+    sync_game(&provider, game, &machine_b, ConflictPolicy::Newest).unwrap();
+    fs::write(machine_a.join("card1.mcr"), b"from a").unwrap();
+
+    let report = sync_game(&provider, game, &machine_a, ConflictPolicy::Remote).unwrap();
+    assert_eq!(report.conflicts, vec!["card1.mcr".to_string()]);
+    assert_eq!(fs::read(machine_a.join("card1.mcr")).unwrap(), b"from b");
+    assert_eq!(fs::read(machine_a.join("card1.mcr.conflict")).unwrap(), b"from a");
+
+    let _ = fs::remove_dir_all(&root);
+}
@@ -0,0 +1,36 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::cloud::SyncProvider;
+use crate::error::AppResult;
+
+/// Saves stored in a local directory, typically synced by another tool or on a network share
+pub struct FolderProvider {
+    root: PathBuf,
+}
+
+impl FolderProvider {
+    pub fn new(root: &Path) -> FolderProvider {
+        FolderProvider { root: root.to_path_buf() }
+    }
+}
+
+impl SyncProvider for FolderProvider {
+    fn get(&self, path: &str) -> AppResult<Option<Vec<u8>>> {
+        match fs::read(self.root.join(path)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, path: &str, data: &[u8]) -> AppResult<()> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::Duration;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ureq::Agent;
+use ureq::http::Request;
+use crate::cloud::SyncProvider;
+use crate::error::{AppError, AppResult};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Larger files are refused, saves are much smaller than that
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Saves stored on a WebDAV server. Only needs GET, PUT and MKCOL.
+pub struct WebDavProvider {
+    agent: Agent,
+    /// Without the trailing slash
+    base_url: String,
+    /// Value of the Authorization header
+    auth: Option<String>,
+}
+
+impl WebDavProvider {
+    pub fn new(url: &str, username: &str, password: &str) -> WebDavProvider {
+        let agent = Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .http_status_as_error(false)
+            .allow_non_standard_methods(true)
+            .build()
+            .into();
+
+        let auth = (!username.is_empty())
+            .then(|| format!("Basic {}", BASE64.encode(format!("{}:{}", username, password))));
+
+        WebDavProvider {
+            agent,
+            base_url: url.trim_end_matches('/').to_string(),
+            auth,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        let encoded: Vec<String> = path.split('/').map(encode_segment).collect();
+
+        format!("{}/{}", self.base_url, encoded.join("/"))
+    }
+
+    fn try_put(&self, path: &str, data: &[u8]) -> AppResult<u16> {
+        let mut request = self.agent.put(self.url(path));
+        if let Some(auth) = &self.auth {
+            request = request.header("Authorization", auth);
+        }
+
+        let response = request.send(data).map_err(http_error)?;
+
+        Ok(response.status().as_u16())
+    }
+
+    /// Create the collections leading to `path`
+    fn create_parents(&self, path: &str) -> AppResult<()> {
+        let segments: Vec<&str> = path.split('/').collect();
+
+        for end in 1..segments.len() {
+            let mut request = Request::builder()
+                .method("MKCOL")
+                .uri(self.url(&segments[..end].join("/")));
+            if let Some(auth) = &self.auth {
+                request = request.header("Authorization", auth);
+            }
+
+            let request = request.body(()).map_err(|e| AppError::CloudSync(e.to_string()))?;
+            let status = self.agent.run(request).map_err(http_error)?.status().as_u16();
+
+            // 405 means that it already exists
+            if !(200..300).contains(&status) && status != 405 {
+                return Err(AppError::CloudSync(format!("Can't create {}: HTTP {}", path, status)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SyncProvider for WebDavProvider {
+    fn get(&self, path: &str) -> AppResult<Option<Vec<u8>>> {
+        let mut request = self.agent.get(self.url(path));
+        if let Some(auth) = &self.auth {
+            request = request.header("Authorization", auth);
+        }
+
+        let mut response = request.call().map_err(http_error)?;
+
+        match response.status().as_u16() {
+            200 => {
+                let data = response.body_mut()
+                    .with_config()
+                    .limit(MAX_FILE_SIZE)
+                    .read_to_vec()
+                    .map_err(http_error)?;
+                Ok(Some(data))
+            }
+            404 => Ok(None),
+            status => Err(AppError::CloudSync(format!("Can't download {}: HTTP {}", path, status))),
+        }
+    }
+
+    fn put(&self, path: &str, data: &[u8]) -> AppResult<()> {
+        let mut status = self.try_put(path, data)?;
+
+        // The parent collection doesn't exist yet
+        if status == 409 || status == 404 {
+            self.create_parents(path)?;
+            status = self.try_put(path, data)?;
+        }
+
+        if !(200..300).contains(&status) {
+            return Err(AppError::CloudSync(format!("Can't upload {}: HTTP {}", path, status)));
+        }
+
+        Ok(())
+    }
+}
+
+fn http_error(e: ureq::Error) -> AppError {
+    AppError::CloudSync(e.to_string())
+}
+
+/// Percent-encode a path segment, game names are full of spaces and parentheses
+fn encode_segment(segment: &str) -> String {
+    segment.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
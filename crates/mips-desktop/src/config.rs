@@ -1,43 +1,785 @@
+mod migration;
+
 use std::collections::HashMap;
-use std::fs;
+use std::{fs, io};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use mips_core::input::Button;
+use mips_core::{CpuBackend, CpuClock, InternalResolution, Interpolation, RendererBackend};
+use mips_core::audio::{AudioMix, AudioSource};
+use mips_core::cheats::{Cheat, CheatDatabase};
+use mips_core::input::{Button, ControllerType, DeviceType, InputConfig};
+use mips_core::rewind::RewindSettings;
+use migration::{Migration, Upgraded, BINDINGS_MIGRATIONS, SETTINGS_MIGRATIONS};
 use egui::Key;
 use gilrs::Button as GilrsButton;
 use anyhow::Result;
 use tracing::{info, warn};
+use crate::autosplit::DEFAULT_WATCH_PORT;
+use crate::control::DEFAULT_CONTROL_PORT;
 
 const CONFIG_DIR: &str = "config";
 const SETTINGS_FILE: &str = "settings.toml";
+/// Exists while the frontend is running, see `safe_mode`
+const RUN_SENTINEL_FILE: &str = "running";
 const KEYBOARD_BINDINGS_FILE: &str = "keyboard_bindings.toml";
 const GAMEPAD_BINDINGS_FILE: &str = "gamepad_bindings.toml";
+/// Input bindings of the versions before the TOML files, imported once
+const LEGACY_INPUT_PROFILE: &str = "profile.input.ini";
+/// Named binding profiles, `<name>.toml`, or `<name>.input.ini` for the ones kept from older versions
+const INPUT_PROFILES_DIR: &str = "input_profiles";
+const INPUT_PROFILE_EXTENSION: &str = ".toml";
+const LEGACY_INPUT_PROFILE_EXTENSION: &str = ".input.ini";
+/// Per-game settings, one file per game
+const GAMES_DIR: &str = "games";
+/// Cheat databases, see `mips_core::cheats` for the format
+const CHEATS_DIR: &str = "cheats";
+/// Per-game patches applied when widescreen is enabled
+const WIDESCREEN_CHEATS_FILE: &str = "widescreen.txt";
+/// Titles, favorites and disc groups of the games list
+const LIBRARY_FILE: &str = "library.toml";
+/// Hashes of the games, computed by the library scan
+const LIBRARY_CACHE_FILE: &str = "library_cache.toml";
+/// Where the tool windows are, and the layouts saved
+const WORKSPACE_FILE: &str = "workspace.toml";
+
+/// Where the core looks for the games, relative to the working directory
+const GAME_IMAGES_DIR: [&str; 3] = ["assets", "roms", "games"];
+/// Per-game memory cards and save states live in a directory named after the game in there
+const SAVES_DIR: &str = "saves";
+/// Save states are `slot<N>.state` in the game's save directory
+const SAVE_STATE_EXTENSION: &str = "state";
+/// Memory cards shared by all the games, `card<N>.mcr`
+const SHARED_MEMORY_CARDS_DIR: &str = "memcards";
+/// Screenshots, named after the game and the time they were taken
+const SCREENSHOTS_DIR: &str = "screenshots";
+/// Saves exported from the memory cards, named after their file on the card
+const EXPORTS_DIR: &str = "exports";
+/// Saves to copy to the memory cards, `.mcs` or `.psv`
+const IMPORTS_DIR: &str = "imports";
+/// Extensions of the saves that can be imported
+const SAVE_IMPORT_EXTENSIONS: [&str; 2] = ["mcs", "psv"];
+/// IPS/BPS patches for the game executables, in a directory named after the game
+const PATCHES_DIR: &str = "patches";
+/// Extensions of the executable patches
+const EXE_PATCH_EXTENSIONS: [&str; 2] = ["ips", "bps"];
+/// RAM writes done once the game is loaded, in the game's patch directory. See
+/// `mips_core::script` for the format.
+const STARTUP_SCRIPT_FILE: &str = "startup.txt";
+/// Audio files replacing the CD-DA tracks, in a directory named after the game
+const SOUNDTRACKS_DIR: &str = "soundtracks";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
     pub video: VideoSettings,
     pub audio: AudioSettings,
     pub system: SystemSettings,
+    #[serde(default)]
+    pub input: InputSettings,
+    #[serde(default)]
+    pub cloud: CloudSettings,
+    #[serde(default)]
+    pub control: ControlSettings,
+    #[serde(default)]
+    pub accessibility: AccessibilitySettings,
+    #[serde(default)]
+    pub osd: OsdSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoSettings {
     pub vsync: bool,
     pub bilinear_filter: bool,
+    /// Show the picture in 16:9 and apply the game's widescreen patch, if the database has one
+    #[serde(default)]
+    pub widescreen: bool,
+    /// Per-frame emulation statistics over the game picture
+    #[serde(default)]
+    pub perf_overlay: bool,
+    /// How many times each pixel gets drawn, in place of the picture
+    #[serde(default)]
+    pub overdraw_heatmap: bool,
+    /// Frames drawn to keep the game at full speed on slow machines
+    #[serde(default)]
+    pub frame_skip: FrameSkip,
+    /// Resolution the polygons are drawn at, the frames are that much larger
+    #[serde(default)]
+    pub internal_resolution: InternalResolution,
+    /// What draws the picture, the software rasterizer or the GPU
+    #[serde(default)]
+    pub renderer: RendererBackend,
+    /// Draw the vertices with the subpixel precision of the GTE (PGXP), unless the game says
+    /// otherwise
+    #[serde(default)]
+    pub pgxp: bool,
+    /// Ordered dithering of the shaded and blended draws, like the console
+    #[serde(default = "default_dithering")]
+    pub dithering: bool,
+    /// Keep 24bit colors instead of the console's 15bit
+    #[serde(default)]
+    pub true_color: bool,
+    /// Blend the semi-transparent draws with the console's 15bit precision
+    #[serde(default = "default_accurate_transparency")]
+    pub accurate_transparency: bool,
+    /// Rasterize the split-screen viewports on threads of their own
+    #[serde(default)]
+    pub parallel_draw_areas: bool,
+    /// Start in fullscreen, left with F11
+    #[serde(default)]
+    pub fullscreen: bool,
+    /// Name of the display to go fullscreen on, the one the window is on if not set or not
+    /// connected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fullscreen_display: Option<String>,
+    #[serde(default)]
+    pub screenshot_source: ScreenshotSource,
+    /// Present every frame at a steady pace for capture tools hooking the window (OBS Game
+    /// Capture), instead of saving work when nothing changes
+    #[serde(default)]
+    pub capture_friendly: bool,
     pub window_width: u32,
     pub window_height: u32,
 }
 
+/// Stage of the presentation that screenshots are taken from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ScreenshotSource {
+    /// Frame output by the console, at its resolution
+    #[default]
+    Console,
+    /// Game picture as shown: scaled to the window, stretched to 16:9 in widescreen and filtered,
+    /// without the menus and the overlays
+    Presented,
+    /// The whole window, with the menus and the overlays
+    Window,
+}
+
+impl ScreenshotSource {
+    pub const ALL: [ScreenshotSource; 3] = [
+        ScreenshotSource::Console,
+        ScreenshotSource::Presented,
+        ScreenshotSource::Window,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScreenshotSource::Console => "Console output",
+            ScreenshotSource::Presented => "Game picture as shown",
+            ScreenshotSource::Window => "Whole window",
+        }
+    }
+}
+
+/// Frames whose drawing is skipped, the game itself keeps running normally
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FrameSkip {
+    #[default]
+    Off,
+    /// Skip while the emulation runs late
+    Auto,
+    /// Draw one frame out of N
+    Fixed(u8),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioSettings {
     pub volume: f32,
     pub enabled: bool,
+    /// Resample slightly to keep the output buffer level, instead of crackling or drifting
+    #[serde(default = "default_rate_control")]
+    pub dynamic_rate_control: bool,
+    /// What happens to the sound while the game is paused or the games list is open
+    #[serde(default)]
+    pub menu_audio: MenuAudio,
+    /// How the SPU resamples the voices, gaussian like the console by default
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// Volume of each audio source, on top of `volume`
+    #[serde(default)]
+    pub mix: AudioMix,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub muted_sources: Vec<AudioSource>,
+}
+
+/// Sound of a game frozen behind a menu. Unless stopped, the SPU keeps running with the rest of the
+/// console frozen: the notes fade out or loop instead of being cut and resuming with a pop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MenuAudio {
+    /// The sound stops with the game
+    #[default]
+    Stop,
+    Muted,
+    /// At a fraction of the volume
+    Quiet,
+}
+
+impl MenuAudio {
+    pub const ALL: [MenuAudio; 3] = [MenuAudio::Stop, MenuAudio::Muted, MenuAudio::Quiet];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MenuAudio::Stop => "Stop",
+            MenuAudio::Muted => "Keep running, muted",
+            MenuAudio::Quiet => "Keep running, quietly",
+        }
+    }
+
+    /// Fraction of the volume the sound is played at, `None` if it isn't emulated
+    pub fn volume(self) -> Option<f32> {
+        match self {
+            MenuAudio::Stop => None,
+            MenuAudio::Muted => Some(0.0),
+            MenuAudio::Quiet => Some(0.25),
+        }
+    }
+}
+
+/// Memory cards inserted when a game starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MemoryCardMode {
+    /// The same two cards for all the games, like a console
+    #[default]
+    Shared,
+    /// A card of its own in slot 1, created when the game first saves, so that it never runs out
+    /// of blocks. Slot 2 keeps the shared card, to copy saves between games.
+    PerGame,
+    /// Like `PerGame` but the card is named after the disc's serial number, in the `memcards`
+    /// directory of the system directory: the versions of a game and its hacks share it, the
+    /// discs of a multi-disc game don't.
+    PerSerial,
+}
+
+impl MemoryCardMode {
+    pub const ALL: [MemoryCardMode; 3] = [MemoryCardMode::Shared, MemoryCardMode::PerGame, MemoryCardMode::PerSerial];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryCardMode::Shared => "Shared",
+            MemoryCardMode::PerGame => "Per game",
+            MemoryCardMode::PerSerial => "Per disc serial",
+        }
+    }
+}
+
+fn default_rate_control() -> bool {
+    true
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemSettings {
     pub fast_boot: bool,
+    /// Emulate the BIOS kernel when there's no dump in the system directory
+    #[serde(default)]
+    pub hle_bios: bool,
     pub auto_save_state: bool,
+    /// Pause and report when the game stops drawing and spins in a tight loop for that many
+    /// seconds, 0 disables
+    #[serde(default = "default_stall_timeout")]
+    pub stall_timeout_secs: u32,
+    /// BIOS dump (raw or zipped), picked automatically from the system directory if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bios: Option<PathBuf>,
+    /// Discs up to that many MiB are read into memory when the game starts, 0 reads them on demand
+    #[serde(default)]
+    pub preload_disc_mib: u32,
+    /// RetroAchievements hardcore mode: no cheats, patches or startup scripts. Entering it takes a
+    /// game restart.
+    #[serde(default)]
+    pub hardcore: bool,
+    /// Run PAL games at NTSC timings (60 Hz), faster than on a PAL console
+    #[serde(default)]
+    pub force_ntsc_timing: bool,
+    /// Keep snapshots of the last moments of play to go back to by holding the rewind hotkey
+    #[serde(default)]
+    pub rewind: bool,
+    /// Frames between two rewind snapshots, the rewind goes back that many frames at a time
+    #[serde(default = "default_rewind_interval")]
+    pub rewind_interval: u32,
+    /// Memory the rewind snapshots may take, in MiB
+    #[serde(default = "default_rewind_buffer")]
+    pub rewind_buffer_mib: u32,
+    /// Interpreter, or the dynarec where the host supports it
+    #[serde(default)]
+    pub cpu_backend: CpuBackend,
+    /// Speed of the CPU in percent of the console's, for the games that slow down
+    #[serde(default)]
+    pub cpu_clock: CpuClock,
+    /// Minutes of play between two auto-states, 0 disables them
+    #[serde(default)]
+    pub auto_state_minutes: u32,
+    /// Which memory cards the games get, unless the game says otherwise
+    #[serde(default)]
+    pub memory_card_mode: MemoryCardMode,
+}
+
+impl SystemSettings {
+    pub fn preload_limit(&self) -> u64 {
+        u64::from(self.preload_disc_mib) << 20
+    }
+
+    /// `None` if rewinding is disabled
+    pub fn rewind_settings(&self) -> Option<RewindSettings> {
+        self.rewind.then(|| RewindSettings {
+            interval: self.rewind_interval.max(1),
+            max_size: (self.rewind_buffer_mib as usize) << 20,
+        })
+    }
+}
+
+fn default_dithering() -> bool {
+    true
+}
+
+fn default_accurate_transparency() -> bool {
+    true
+}
+
+fn default_stall_timeout() -> u32 {
+    10
+}
+
+fn default_rewind_interval() -> u32 {
+    RewindSettings::default().interval
+}
+
+fn default_rewind_buffer() -> u32 {
+    (RewindSettings::default().max_size >> 20) as u32
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputSettings {
+    /// For games without a controller type of their own, plug a digital pad if the compatibility
+    /// database says the game doesn't support the DualShock, and a DualShock otherwise
+    pub auto_controller_type: bool,
+    /// Radius under which the gamepad sticks are considered centered, 0 to 1
+    pub left_stick_deadzone: f32,
+    pub right_stick_deadzone: f32,
+    /// Scale of the DualShock rumble played on the gamepad, 0 (off) to 1
+    pub rumble_strength: f32,
+    /// Latch button changes when the game polls the pad, at the time they happened within the
+    /// frame, instead of all of them at the start of the frame
+    pub mid_frame_input: bool,
+    /// Offsets measured by the latency calibration, how late the player reacts to a beep and to
+    /// a flash, in milliseconds
+    pub audio_latency_ms: f32,
+    pub video_latency_ms: f32,
+    pub latency_compensation: LatencyCompensation,
+    /// How the PlayStation Mouse is driven while the mouse is captured
+    pub mouse_capture: MouseCaptureMode,
+    /// Scale of the motion sent to the PlayStation Mouse
+    pub mouse_sensitivity: f32,
+    /// Host devices each console port reads
+    pub port_devices: [PortDevice; 2],
+    /// Keep reading the gamepads while the window isn't focused, to play on a second screen while
+    /// using another program. The keyboard is only read while the window is focused.
+    pub background_gamepad: bool,
+    /// How the gamepad's left and right analog triggers press the buttons bound to them
+    pub triggers: [TriggerSettings; 2],
+}
+
+/// How deep an analog trigger has to go to press the button bound to it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerSettings {
+    /// Depth at which the button is pressed, 0 to 1
+    pub threshold: f32,
+    /// Past the threshold, press the button in pulses that get longer as the trigger goes deeper
+    /// instead of holding it, to ease off the accelerator in the games that only have a button
+    /// for it
+    pub pulse: bool,
+}
+
+impl Default for TriggerSettings {
+    fn default() -> Self {
+        Self { threshold: 0.5, pulse: false }
+    }
+}
+
+/// Host devices a console port reads, so that two players on the same machine (two gamepads, or
+/// a gamepad and the keyboard) don't press each other's buttons. egui merges all the keyboards
+/// into one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PortDevice {
+    /// The keyboard and the gamepads no other port reads
+    All,
+    Keyboard,
+    /// One gamepad. The gamepads of the same model have the same UUID, `instance` tells them
+    /// apart in the order they were connected.
+    Gamepad {
+        uuid: String,
+        instance: usize,
+        name: String,
+    },
+    None,
+}
+
+impl PortDevice {
+    pub fn label(&self) -> String {
+        match self {
+            PortDevice::All => "All devices".to_string(),
+            PortDevice::Keyboard => "Keyboard".to_string(),
+            PortDevice::Gamepad { name, instance: 0, .. } => name.clone(),
+            PortDevice::Gamepad { name, instance, .. } => format!("{} #{}", name, instance + 1),
+            PortDevice::None => "None".to_string(),
+        }
+    }
+}
+
+impl InputSettings {
+    /// How much earlier the inputs are latched, see `ConsoleManager::set_input_latency`
+    pub fn input_latency(&self) -> Duration {
+        let ms = match self.latency_compensation {
+            LatencyCompensation::Off => 0.0,
+            LatencyCompensation::Audio => self.audio_latency_ms,
+            LatencyCompensation::Video => self.video_latency_ms,
+        };
+
+        Duration::from_secs_f32(ms.max(0.0) / 1000.0)
+    }
+}
+
+/// See `mouse::MouseCapture`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MouseCaptureMode {
+    /// Raw motion of the mouse, the cursor is locked
+    #[default]
+    Relative,
+    /// Motion of the hidden cursor over the game picture
+    Absolute,
+}
+
+/// Which of the measured offsets the inputs are compensated for. Rhythm games are played to the
+/// music (PaRappa) or to what's on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LatencyCompensation {
+    Off,
+    Audio,
+    Video,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self {
+            auto_controller_type: true,
+            left_stick_deadzone: 0.1,
+            right_stick_deadzone: 0.1,
+            rumble_strength: 1.0,
+            mid_frame_input: false,
+            audio_latency_ms: 0.0,
+            video_latency_ms: 0.0,
+            latency_compensation: LatencyCompensation::Off,
+            mouse_capture: MouseCaptureMode::Relative,
+            mouse_sensitivity: 1.0,
+            port_devices: [PortDevice::All, PortDevice::None],
+            background_gamepad: false,
+            triggers: [TriggerSettings::default(); 2],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloudSettings {
+    pub provider: CloudProvider,
+    pub conflict_policy: ConflictPolicy,
+    /// Download the saves before a game starts and upload them when it stops
+    pub sync_on_launch: bool,
+}
+
+impl Default for CloudSettings {
+    fn default() -> Self {
+        Self {
+            provider: CloudProvider::Disabled,
+            conflict_policy: ConflictPolicy::Newest,
+            sync_on_launch: true,
+        }
+    }
+}
+
+/// Interfaces for external tools, see `control` and `autosplit`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlSettings {
+    /// JSON-RPC server
+    pub enabled: bool,
+    /// TCP port on the loopback interface
+    pub port: u16,
+    /// Publish the game's memory watches for autosplitters
+    pub publish_watches: bool,
+    pub watch_port: u16,
+    /// Button presses from viewers, through the JSON-RPC server
+    pub crowd: CrowdSettings,
+}
+
+/// See `crowd`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrowdSettings {
+    pub mode: CrowdMode,
+    /// How long each press lasts
+    pub hold_frames: u8,
+    /// Commands from the same viewer closer than that are refused
+    pub user_cooldown_ms: u32,
+    /// Length of a democracy round
+    pub vote_frames: u32,
+}
+
+impl Default for CrowdSettings {
+    fn default() -> Self {
+        Self {
+            mode: CrowdMode::Off,
+            hold_frames: 6,
+            user_cooldown_ms: 1000,
+            vote_frames: 120,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrowdMode {
+    Off,
+    /// Every command is played, in order
+    Anarchy,
+    /// The most voted command of each round is played
+    Democracy,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_CONTROL_PORT,
+            publish_watches: false,
+            watch_port: DEFAULT_WATCH_PORT,
+            crowd: CrowdSettings::default(),
+        }
+    }
+}
+
+/// See `accessibility`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// Solid black or white backgrounds, bright borders on the widgets
+    pub high_contrast: bool,
+    /// Bigger buttons, checkboxes and sliders, spaced further apart
+    pub large_targets: bool,
+    /// No fading OSD notifications and no animated menus
+    pub reduced_motion: bool,
+    /// Applied to the game picture only
+    pub color_filter: ColorFilter,
+}
+
+/// Color blindness the game picture is simulated for or corrected for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorFilter {
+    #[default]
+    Off,
+    /// How the picture looks without the red cones
+    SimulateProtanopia,
+    /// Without the green cones
+    SimulateDeuteranopia,
+    /// Without the blue cones
+    SimulateTritanopia,
+    /// Colors shifted so that what the missing cones would tell apart stays distinct
+    CorrectProtanopia,
+    CorrectDeuteranopia,
+    CorrectTritanopia,
+}
+
+impl ColorFilter {
+    pub const ALL: [ColorFilter; 7] = [
+        ColorFilter::Off,
+        ColorFilter::CorrectProtanopia,
+        ColorFilter::CorrectDeuteranopia,
+        ColorFilter::CorrectTritanopia,
+        ColorFilter::SimulateProtanopia,
+        ColorFilter::SimulateDeuteranopia,
+        ColorFilter::SimulateTritanopia,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorFilter::Off => "Off",
+            ColorFilter::SimulateProtanopia => "Simulate protanopia",
+            ColorFilter::SimulateDeuteranopia => "Simulate deuteranopia",
+            ColorFilter::SimulateTritanopia => "Simulate tritanopia",
+            ColorFilter::CorrectProtanopia => "Correct protanopia (red)",
+            ColorFilter::CorrectDeuteranopia => "Correct deuteranopia (green)",
+            ColorFilter::CorrectTritanopia => "Correct tritanopia (blue)",
+        }
+    }
+}
+
+/// See `osd`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OsdSettings {
+    /// Corner of the game picture the notifications are stacked in
+    pub position: OsdPosition,
+    /// How long the notifications stay up
+    pub duration_secs: u32,
+    /// Categories of notifications not shown, the warnings are shown whatever their category
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hidden: Vec<OsdCategory>,
+}
+
+impl Default for OsdSettings {
+    fn default() -> Self {
+        Self {
+            position: OsdPosition::default(),
+            duration_secs: 8,
+            hidden: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OsdPosition {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+impl OsdPosition {
+    pub const ALL: [OsdPosition; 4] = [
+        OsdPosition::TopLeft,
+        OsdPosition::TopRight,
+        OsdPosition::BottomLeft,
+        OsdPosition::BottomRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OsdPosition::TopLeft => "Top left",
+            OsdPosition::TopRight => "Top right",
+            OsdPosition::BottomLeft => "Bottom left",
+            OsdPosition::BottomRight => "Bottom right",
+        }
+    }
+}
+
+/// What an OSD notification is about, for the players to hide what they don't care about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OsdCategory {
+    /// Saved and loaded states, slot changes, rewind
+    SaveStates,
+    /// Discs inserted and ejected, screenshots and bug reports saved
+    Media,
+    /// Patches, cheats and startup scripts applied
+    Patches,
+    /// Netplay chat messages
+    Chat,
+    Other,
+}
+
+impl OsdCategory {
+    pub const ALL: [OsdCategory; 5] = [
+        OsdCategory::SaveStates,
+        OsdCategory::Media,
+        OsdCategory::Patches,
+        OsdCategory::Chat,
+        OsdCategory::Other,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OsdCategory::SaveStates => "Save states",
+            OsdCategory::Media => "Discs and screenshots",
+            OsdCategory::Patches => "Patches and scripts",
+            OsdCategory::Chat => "Netplay chat",
+            OsdCategory::Other => "Other",
+        }
+    }
+}
+
+/// Where saves are synced to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CloudProvider {
+    Disabled,
+    /// WebDAV server (Nextcloud, ownCloud, NAS...). The password is stored in clear text.
+    WebDav {
+        url: String,
+        username: String,
+        password: String,
+    },
+    /// Directory synced by some other tool, or a network share
+    Folder {
+        path: PathBuf,
+    },
+}
+
+/// What to do when a save changed both locally and remotely since the last sync. The losing
+/// version is always kept next to the save with a `.conflict` suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// Keep the most recently modified version
+    Newest,
+    Local,
+    Remote,
+}
+
+/// Settings remembered for a single game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameSettings {
+    /// Controller plugged in each port
+    pub controllers: [ControllerType; 2],
+    /// Patch applied to the game executable, a file name in the game's patch directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe_patch: Option<String>,
+    /// Memory cards whose saves are kept in memory and never written to their file, per slot
+    #[serde(default)]
+    pub read_only_memory_cards: [bool; 2],
+    /// RAM published for autosplitters
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub memory_watches: Vec<MemoryWatch>,
+    /// Overrides the PGXP setting for the game (some draw 2D with the GTE and look off with it)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pgxp: Option<bool>,
+    /// Overrides the memory card mode for the game
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_card_mode: Option<MemoryCardMode>,
+    /// Have the GTE draw the 3D for 16:9, off by default: some games break with it
+    #[serde(default)]
+    pub widescreen_hack: bool,
+    /// Overrides the SPU interpolation for the game
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpolation: Option<Interpolation>,
+}
+
+/// Value in RAM an autosplitter follows (level, in-game timer, boss health...)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryWatch {
+    pub name: String,
+    /// Any RAM mirror, `0x800f1234` in TOML
+    pub address: u32,
+    pub size: WatchSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchSize {
+    U8,
+    U16,
+    U32,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            controllers: [ControllerType::DigitalPad, ControllerType::Disconnected],
+            exe_patch: None,
+            read_only_memory_cards: [false; 2],
+            memory_watches: Vec::new(),
+            pgxp: None,
+            memory_card_mode: None,
+            widescreen_hack: false,
+            interpolation: None,
+        }
+    }
 }
 
 impl Default for AppSettings {
@@ -46,17 +788,55 @@ impl Default for AppSettings {
             video: VideoSettings {
                 vsync: true,
                 bilinear_filter: false,
+                widescreen: false,
+                perf_overlay: false,
+                overdraw_heatmap: false,
+                frame_skip: FrameSkip::Off,
+                internal_resolution: InternalResolution::Native,
+                renderer: RendererBackend::Software,
+                pgxp: false,
+                dithering: true,
+                true_color: false,
+                accurate_transparency: true,
+                parallel_draw_areas: false,
+                fullscreen: false,
+                fullscreen_display: None,
+                screenshot_source: ScreenshotSource::Console,
+                capture_friendly: false,
                 window_width: 1280,
                 window_height: 720,
             },
             audio: AudioSettings {
                 volume: 1.0,
+                dynamic_rate_control: true,
                 enabled: true,
+                menu_audio: MenuAudio::Stop,
+                interpolation: Interpolation::Gaussian,
+                mix: AudioMix::default(),
+                muted_sources: Vec::new(),
             },
             system: SystemSettings {
                 fast_boot: false,
+                hle_bios: false,
                 auto_save_state: true,
+                stall_timeout_secs: default_stall_timeout(),
+                bios: None,
+                preload_disc_mib: 0,
+                hardcore: false,
+                force_ntsc_timing: false,
+                rewind: false,
+                rewind_interval: default_rewind_interval(),
+                rewind_buffer_mib: default_rewind_buffer(),
+                cpu_backend: CpuBackend::default(),
+                cpu_clock: CpuClock::NATIVE,
+                auto_state_minutes: 0,
+                memory_card_mode: MemoryCardMode::Shared,
             },
+            input: InputSettings::default(),
+            cloud: CloudSettings::default(),
+            control: ControlSettings::default(),
+            accessibility: AccessibilitySettings::default(),
+            osd: OsdSettings::default(),
         }
     }
 }
@@ -125,6 +905,11 @@ impl Default for GamepadBindings {
         bindings.insert(GilrsButton::Select, Button::Select);
         bindings.insert(GilrsButton::Start, Button::Start);
 
+        // Stick clicks and the DualShock's analog mode button
+        bindings.insert(GilrsButton::LeftThumb, Button::L3);
+        bindings.insert(GilrsButton::RightThumb, Button::R3);
+        bindings.insert(GilrsButton::Mode, Button::Analog);
+
         // D-Pad
         bindings.insert(GilrsButton::DPadUp, Button::DUp);
         bindings.insert(GilrsButton::DPadDown, Button::DDown);
@@ -135,11 +920,36 @@ impl Default for GamepadBindings {
     }
 }
 
+/// Named set of bindings switched to for some games ("Fighting", "Racing with triggers as
+/// pedals"), in place of the ones of `keyboard_bindings.toml` and `gamepad_bindings.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputProfile {
+    pub keyboard: KeyboardBindings,
+    pub gamepad: GamepadBindings,
+}
+
+/// Outcome of loading a configuration file
+enum Loaded<T> {
+    Current(T),
+    /// From an older version, to be saved in the current format
+    Upgraded(T),
+    Missing,
+    /// Corrupted or edited by hand, a copy was made before going back to the defaults
+    Invalid,
+    /// Left alone, the defaults are used for this run
+    Unreadable,
+}
+
 pub struct ConfigManager {
     config_dir: PathBuf,
     pub settings: AppSettings,
     pub keyboard_bindings: KeyboardBindings,
     pub gamepad_bindings: GamepadBindings,
+    /// Profile the bindings come from, `None` for the default ones
+    pub input_profile: Option<String>,
+    /// Names of the profiles in the profiles directory, sorted
+    input_profiles: Vec<String>,
 }
 
 impl ConfigManager {
@@ -152,12 +962,7 @@ impl ConfigManager {
             info!("Created config directory: {}", config_dir.display());
         }
 
-        let mut manager = Self {
-            config_dir,
-            settings: AppSettings::default(),
-            keyboard_bindings: KeyboardBindings::default(),
-            gamepad_bindings: GamepadBindings::default(),
-        };
+        let mut manager = Self::with_defaults();
 
         // Load existing configs or create defaults
         manager.load_or_create_defaults()?;
@@ -165,74 +970,145 @@ impl ConfigManager {
         Ok(manager)
     }
 
+    /// Default configuration, ignoring the files. Saving it replaces them.
+    pub fn with_defaults() -> Self {
+        Self {
+            config_dir: PathBuf::from(CONFIG_DIR),
+            settings: AppSettings::default(),
+            keyboard_bindings: KeyboardBindings::default(),
+            gamepad_bindings: GamepadBindings::default(),
+            input_profile: None,
+            input_profiles: Vec::new(),
+        }
+    }
+
     fn load_or_create_defaults(&mut self) -> Result<()> {
         // Load settings
-        let settings_path = self.config_dir.join(SETTINGS_FILE);
-        if settings_path.exists() {
-            match fs::read_to_string(&settings_path) {
-                Ok(content) => {
-                    match toml::from_str(&content) {
-                        Ok(settings) => {
-                            self.settings = settings;
-                            info!("Loaded settings from {}", settings_path.display());
-                        }
-                        Err(e) => {
-                            warn!("Failed to parse settings: {}. Using defaults.", e);
-                            self.save_settings()?;
-                        }
-                    }
-                }
-                Err(e) => warn!("Failed to read settings: {}. Using defaults.", e),
+        match self.load_file(SETTINGS_FILE, SETTINGS_MIGRATIONS) {
+            Loaded::Current(settings) => self.settings = settings,
+            Loaded::Upgraded(settings) => {
+                self.settings = settings;
+                self.save_settings()?;
             }
-        } else {
-            info!("No settings file found, creating default");
-            self.save_settings()?;
+            Loaded::Missing | Loaded::Invalid => self.save_settings()?,
+            Loaded::Unreadable => (),
         }
 
         // Load keyboard bindings
-        let kb_path = self.config_dir.join(KEYBOARD_BINDINGS_FILE);
-        if kb_path.exists() {
-            match fs::read_to_string(&kb_path) {
-                Ok(content) => {
-                    match toml::from_str(&content) {
-                        Ok(bindings) => {
-                            self.keyboard_bindings = bindings;
-                            info!("Loaded keyboard bindings from {}", kb_path.display());
-                        }
-                        Err(e) => {
-                            warn!("Failed to parse keyboard bindings: {}. Using defaults.", e);
-                            self.save_keyboard_bindings()?;
-                        }
-                    }
-                }
-                Err(e) => warn!("Failed to read keyboard bindings: {}. Using defaults.", e),
+        let keyboard = self.load_file(KEYBOARD_BINDINGS_FILE, BINDINGS_MIGRATIONS);
+        let keyboard_missing = matches!(keyboard, Loaded::Missing);
+        match keyboard {
+            Loaded::Current(bindings) => self.keyboard_bindings = bindings,
+            Loaded::Upgraded(bindings) => {
+                self.keyboard_bindings = bindings;
+                self.save_keyboard_bindings()?;
             }
-        } else {
-            info!("No keyboard bindings file found, creating default");
-            self.save_keyboard_bindings()?;
+            Loaded::Missing | Loaded::Invalid => self.save_keyboard_bindings()?,
+            Loaded::Unreadable => (),
         }
 
         // Load gamepad bindings
-        let gp_path = self.config_dir.join(GAMEPAD_BINDINGS_FILE);
-        if gp_path.exists() {
-            match fs::read_to_string(&gp_path) {
-                Ok(content) => {
-                    match toml::from_str(&content) {
-                        Ok(bindings) => {
-                            self.gamepad_bindings = bindings;
-                            info!("Loaded gamepad bindings from {}", gp_path.display());
-                        }
-                        Err(e) => {
-                            warn!("Failed to parse gamepad bindings: {}. Using defaults.", e);
-                            self.save_gamepad_bindings()?;
-                        }
-                    }
-                }
-                Err(e) => warn!("Failed to read gamepad bindings: {}. Using defaults.", e),
+        let gamepad = self.load_file(GAMEPAD_BINDINGS_FILE, BINDINGS_MIGRATIONS);
+        let gamepad_missing = matches!(gamepad, Loaded::Missing);
+        match gamepad {
+            Loaded::Current(bindings) => self.gamepad_bindings = bindings,
+            Loaded::Upgraded(bindings) => {
+                self.gamepad_bindings = bindings;
+                self.save_gamepad_bindings()?;
+            }
+            Loaded::Missing | Loaded::Invalid => self.save_gamepad_bindings()?,
+            Loaded::Unreadable => (),
+        }
+
+        if keyboard_missing || gamepad_missing {
+            self.import_input_profile(keyboard_missing, gamepad_missing)?;
+        }
+
+        self.list_input_profiles();
+
+        Ok(())
+    }
+
+    /// Load one of the configuration files, upgrading it if it comes from an older version. The
+    /// original is copied next to it before anything is changed, so that no setting is ever lost
+    /// without a trace.
+    fn load_file<T: DeserializeOwned>(&self, file: &str, migrations: &[Migration]) -> Loaded<T> {
+        let path = self.config_dir.join(file);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!("No {} found, creating default", file);
+                return Loaded::Missing;
+            }
+            Err(e) => {
+                warn!("Failed to read {}: {}. Using defaults.", path.display(), e);
+                return Loaded::Unreadable;
+            }
+        };
+
+        let current = migration::current_version(migrations);
+        match migration::parse(&content, migrations) {
+            Ok(Upgraded { data, version }) if version == current => {
+                info!("Loaded {}", path.display());
+                Loaded::Current(data)
+            }
+            Ok(Upgraded { data, version }) if version < current => {
+                let backup = backup_file(&path, &format!("v{}", version));
+                info!("Upgraded {} from version {}, the original is in {}", path.display(), version, backup);
+                Loaded::Upgraded(data)
+            }
+            Ok(Upgraded { data, version }) => {
+                let backup = backup_file(&path, &format!("v{}", version));
+                warn!(
+                    "{} is from a newer version of the emulator ({}), the settings we don't know \
+                     about are ignored. The original is in {}",
+                    path.display(), version, backup,
+                );
+                Loaded::Current(data)
+            }
+            Err(e) => {
+                let backup = backup_file(&path, "bak");
+                warn!("Failed to parse {}: {}. Using defaults, the original is in {}", path.display(), e, backup);
+                Loaded::Invalid
+            }
+        }
+    }
+
+    /// Bindings of the ini input profile used by older versions, for the bindings that were never
+    /// saved in the current format
+    fn import_input_profile(&mut self, keyboard: bool, gamepad: bool) -> Result<()> {
+        let path = self.config_dir.join(LEGACY_INPUT_PROFILE);
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let profile = match InputConfig::load(&path) {
+            Ok(profile) => profile,
+            Err(e) => {
+                warn!("Failed to import {}: {}", path.display(), e);
+                return Ok(());
+            }
+        };
+
+        let skipped = match profile.device_type() {
+            DeviceType::Keyboard if keyboard => {
+                let (bindings, skipped) = migration::import_keyboard_profile(&profile);
+                self.keyboard_bindings.bindings = bindings;
+                self.save_keyboard_bindings()?;
+                skipped
+            }
+            DeviceType::DualShock if gamepad => {
+                let (bindings, skipped) = migration::import_gamepad_profile(&profile);
+                self.gamepad_bindings.bindings = bindings;
+                self.save_gamepad_bindings()?;
+                skipped
             }
-        } else {
-            info!("No gamepad bindings file found, creating default");
-            self.save_gamepad_bindings()?;
+            _ => return Ok(()),
+        };
+
+        info!("Imported the bindings of {}", path.display());
+        if !skipped.is_empty() {
+            warn!("Unknown inputs not imported from {}: {}", path.display(), skipped.join(", "));
         }
 
         Ok(())
@@ -240,15 +1116,25 @@ impl ConfigManager {
 
     pub fn save_settings(&self) -> Result<()> {
         let settings_path = self.config_dir.join(SETTINGS_FILE);
-        let content = toml::to_string_pretty(&self.settings)?;
+        let content = migration::to_toml(&self.settings, SETTINGS_MIGRATIONS)?;
         fs::write(&settings_path, content)?;
         info!("Saved settings to {}", settings_path.display());
         Ok(())
     }
 
+    /// `settings.toml` as it would be saved, without the passwords, for bug reports
+    pub fn redacted_settings(&self) -> Result<String> {
+        let mut settings = self.settings.clone();
+        if let CloudProvider::WebDav { password, .. } = &mut settings.cloud.provider {
+            *password = "<redacted>".to_string();
+        }
+
+        migration::to_toml(&settings, SETTINGS_MIGRATIONS)
+    }
+
     pub fn save_keyboard_bindings(&self) -> Result<()> {
         let kb_path = self.config_dir.join(KEYBOARD_BINDINGS_FILE);
-        let content = toml::to_string_pretty(&self.keyboard_bindings)?;
+        let content = migration::to_toml(&self.keyboard_bindings, BINDINGS_MIGRATIONS)?;
         fs::write(&kb_path, content)?;
         info!("Saved keyboard bindings to {}", kb_path.display());
         Ok(())
@@ -256,16 +1142,200 @@ impl ConfigManager {
 
     pub fn save_gamepad_bindings(&self) -> Result<()> {
         let gp_path = self.config_dir.join(GAMEPAD_BINDINGS_FILE);
-        let content = toml::to_string_pretty(&self.gamepad_bindings)?;
+        let content = migration::to_toml(&self.gamepad_bindings, BINDINGS_MIGRATIONS)?;
         fs::write(&gp_path, content)?;
         info!("Saved gamepad bindings to {}", gp_path.display());
         Ok(())
     }
 
+    /// Names of the binding profiles, sorted
+    pub fn input_profiles(&self) -> &[String] {
+        &self.input_profiles
+    }
+
+    fn list_input_profiles(&mut self) {
+        let dir = self.config_dir.join(INPUT_PROFILES_DIR);
+        let Ok(entries) = fs::read_dir(&dir) else {
+            self.input_profiles.clear();
+            return;
+        };
+
+        let mut profiles: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file = entry.file_name().to_string_lossy().into_owned();
+                file.strip_suffix(INPUT_PROFILE_EXTENSION)
+                    .or_else(|| file.strip_suffix(LEGACY_INPUT_PROFILE_EXTENSION))
+                    .map(str::to_string)
+            })
+            .collect();
+        profiles.sort();
+        profiles.dedup();
+
+        self.input_profiles = profiles;
+    }
+
+    /// Switch to the bindings of profile `name`, or back to the default ones. Unsaved changes to
+    /// the current bindings are lost. A profile that can't be read leaves the bindings alone.
+    pub fn use_input_profile(&mut self, name: Option<&str>) -> Result<()> {
+        let profile = match name {
+            Some(name) => self.load_input_profile(name)?,
+            None => InputProfile {
+                keyboard: self.load_bindings(KEYBOARD_BINDINGS_FILE),
+                gamepad: self.load_bindings(GAMEPAD_BINDINGS_FILE),
+            },
+        };
+
+        self.keyboard_bindings = profile.keyboard;
+        self.gamepad_bindings = profile.gamepad;
+        self.input_profile = name.map(str::to_string);
+        info!("Using the {} bindings", name.unwrap_or("default"));
+
+        Ok(())
+    }
+
+    /// Default bindings file, as it was loaded at startup
+    fn load_bindings<T: DeserializeOwned + Default>(&self, file: &str) -> T {
+        match self.load_file(file, BINDINGS_MIGRATIONS) {
+            Loaded::Current(bindings) | Loaded::Upgraded(bindings) => bindings,
+            Loaded::Missing | Loaded::Invalid | Loaded::Unreadable => T::default(),
+        }
+    }
+
+    fn load_input_profile(&self, name: &str) -> Result<InputProfile> {
+        let dir = self.config_dir.join(INPUT_PROFILES_DIR);
+        let path = dir.join(format!("{}{}", name, INPUT_PROFILE_EXTENSION));
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                let profile = migration::parse(&content, BINDINGS_MIGRATIONS)
+                    .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+                Ok(profile.data)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.import_legacy_profile(&dir.join(format!("{}{}", name, LEGACY_INPUT_PROFILE_EXTENSION)))
+            }
+            Err(e) => anyhow::bail!("Failed to read {}: {}", path.display(), e),
+        }
+    }
+
+    /// Profile in the ini format of older versions, the bindings of the other device are the
+    /// default ones
+    fn import_legacy_profile(&self, path: &Path) -> Result<InputProfile> {
+        let legacy = InputConfig::load(path)?;
+        let mut profile = InputProfile::default();
+
+        let skipped = match legacy.device_type() {
+            DeviceType::Keyboard => {
+                let (bindings, skipped) = migration::import_keyboard_profile(&legacy);
+                profile.keyboard.bindings = bindings;
+                skipped
+            }
+            DeviceType::DualShock => {
+                let (bindings, skipped) = migration::import_gamepad_profile(&legacy);
+                profile.gamepad.bindings = bindings;
+                skipped
+            }
+            DeviceType::Unknown => anyhow::bail!("Unknown device type in {}", path.display()),
+        };
+        if !skipped.is_empty() {
+            warn!("Unknown inputs not imported from {}: {}", path.display(), skipped.join(", "));
+        }
+
+        Ok(profile)
+    }
+
+    /// Save the current bindings as profile `name` and switch to it
+    pub fn save_input_profile(&mut self, name: &str) -> Result<()> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            anyhow::bail!("Invalid profile name \"{}\"", name);
+        }
+
+        let dir = self.config_dir.join(INPUT_PROFILES_DIR);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}{}", name, INPUT_PROFILE_EXTENSION));
+        let profile = InputProfile {
+            keyboard: self.keyboard_bindings.clone(),
+            gamepad: self.gamepad_bindings.clone(),
+        };
+        fs::write(&path, migration::to_toml(&profile, BINDINGS_MIGRATIONS)?)?;
+        info!("Saved input profile to {}", path.display());
+
+        self.input_profile = Some(name.to_string());
+        self.list_input_profiles();
+        Ok(())
+    }
+
+    /// Delete profile `name`, going back to the default bindings if it's the current one
+    pub fn delete_input_profile(&mut self, name: &str) -> Result<()> {
+        let dir = self.config_dir.join(INPUT_PROFILES_DIR);
+        for extension in [INPUT_PROFILE_EXTENSION, LEGACY_INPUT_PROFILE_EXTENSION] {
+            let path = dir.join(format!("{}{}", name, extension));
+            match fs::remove_file(&path) {
+                Ok(()) => info!("Deleted {}", path.display()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        self.list_input_profiles();
+        if self.input_profile.as_deref() == Some(name) {
+            self.use_input_profile(None)?;
+        }
+        Ok(())
+    }
+
+    /// Settings saved for `game` (a disc image file name), if any
+    pub fn game_settings(&self, game: &str) -> Option<GameSettings> {
+        let path = self.game_settings_path(game);
+        let content = fs::read_to_string(&path).ok()?;
+
+        match toml::from_str(&content) {
+            Ok(settings) => {
+                info!("Loaded game settings from {}", path.display());
+                Some(settings)
+            }
+            Err(e) => {
+                warn!("Failed to parse {}: {}. Using defaults.", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn save_game_settings(&self, game: &str, settings: &GameSettings) -> Result<()> {
+        let path = self.game_settings_path(game);
+        fs::create_dir_all(self.config_dir.join(GAMES_DIR))?;
+        let content = toml::to_string_pretty(settings)?;
+        fs::write(&path, content)?;
+        info!("Saved game settings to {}", path.display());
+        Ok(())
+    }
+
+    fn game_settings_path(&self, game: &str) -> PathBuf {
+        self.config_dir.join(GAMES_DIR).join(format!("{}.toml", game_name(game)))
+    }
+
+    /// Widescreen patches for the game with the given serial number, empty if there's none
+    pub fn widescreen_cheats(&self, serial: &str) -> Vec<Cheat> {
+        let path = self.config_dir.join(CHEATS_DIR).join(WIDESCREEN_CHEATS_FILE);
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Vec::new();
+        };
+
+        match CheatDatabase::parse(&content) {
+            Ok(db) => db.cheats(serial).to_vec(),
+            Err(e) => {
+                warn!("Failed to parse {}: {}", path.display(), e);
+                Vec::new()
+            }
+        }
+    }
+
     pub fn reset_to_defaults(&mut self) -> Result<()> {
         self.settings = AppSettings::default();
         self.keyboard_bindings = KeyboardBindings::default();
         self.gamepad_bindings = GamepadBindings::default();
+        self.input_profile = None;
 
         self.save_settings()?;
         self.save_keyboard_bindings()?;
@@ -276,6 +1346,153 @@ impl ConfigManager {
     }
 }
 
+/// Copy `path` to `<path>.<suffix>`, returns the name of the copy for the logs
+fn backup_file(path: &Path, suffix: &str) -> String {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".");
+    backup.push(suffix);
+    let backup = PathBuf::from(backup);
+
+    if let Err(e) = fs::copy(path, &backup) {
+        warn!("Failed to copy {} to {}: {}", path.display(), backup.display(), e);
+    }
+
+    backup.display().to_string()
+}
+
+/// Sentinel file marking the frontend as running
+pub fn run_sentinel_path() -> PathBuf {
+    Path::new(CONFIG_DIR).join(RUN_SENTINEL_FILE)
+}
+
+/// Metadata of the games list
+pub fn library_path() -> PathBuf {
+    Path::new(CONFIG_DIR).join(LIBRARY_FILE)
+}
+
+/// Cache of the library scan
+pub fn library_cache_path() -> PathBuf {
+    Path::new(CONFIG_DIR).join(LIBRARY_CACHE_FILE)
+}
+
+/// Layout of the tool windows
+pub fn workspace_path() -> PathBuf {
+    Path::new(CONFIG_DIR).join(WORKSPACE_FILE)
+}
+
+/// Directory the disc images are loaded from
+pub fn games_dir() -> PathBuf {
+    GAME_IMAGES_DIR.iter().collect()
+}
+
+/// Directory holding the memory cards and save states of `game` (a disc image file name)
+pub fn game_save_dir(game: &str) -> PathBuf {
+    Path::new(SAVES_DIR).join(game_name(game))
+}
+
+/// Save state of `game` (a disc image file name) in `slot`, it may not exist
+pub fn save_state_path(game: &str, slot: u32) -> PathBuf {
+    game_save_dir(game).join(format!("slot{}.{}", slot, SAVE_STATE_EXTENSION))
+}
+
+/// Auto-state `index` of `game` (a disc image file name), apart from the slots. It may not exist.
+pub fn auto_state_path(game: &str, index: u32) -> PathBuf {
+    game_save_dir(game).join(format!("auto{}.{}", index, SAVE_STATE_EXTENSION))
+}
+
+/// Image of the memory card in `slot` (0 or 1) for `game` (a disc image file name, `None` when
+/// running the BIOS alone). It may not exist.
+pub fn memory_card_path(game: Option<&str>, slot: usize, mode: MemoryCardMode) -> PathBuf {
+    let file = format!("card{}.mcr", slot + 1);
+
+    match game {
+        Some(game) if mode == MemoryCardMode::PerGame && slot == 0 => game_save_dir(game).join(file),
+        _ => Path::new(SHARED_MEMORY_CARDS_DIR).join(file),
+    }
+}
+
+/// Directory holding the executable patches of `game` (a disc image file name)
+pub fn game_patch_dir(game: &str) -> PathBuf {
+    Path::new(PATCHES_DIR).join(game_name(game))
+}
+
+/// Startup script of `game` (a disc image file name), it may not exist
+pub fn game_startup_script(game: &str) -> PathBuf {
+    game_patch_dir(game).join(STARTUP_SCRIPT_FILE)
+}
+
+/// Directory holding the replacement soundtrack of `game` (a disc image file name)
+pub fn game_soundtrack_dir(game: &str) -> PathBuf {
+    Path::new(SOUNDTRACKS_DIR).join(game_name(game))
+}
+
+/// Where a screenshot of `game` (a disc image file name) taken now is saved
+pub fn screenshot_path(game: Option<&str>) -> PathBuf {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let name = game.map_or_else(|| "mips".to_string(), game_name);
+
+    Path::new(SCREENSHOTS_DIR).join(format!("{}-{}.png", name, now.as_millis()))
+}
+
+/// Where the memory card save named `name` is exported with `extension`. The characters that
+/// aren't allowed in file names everywhere are replaced.
+pub fn save_export_path(name: &str, extension: &str) -> PathBuf {
+    let name: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    Path::new(EXPORTS_DIR).join(format!("{}.{}", name, extension))
+}
+
+/// Saves in the imports directory, sorted
+pub fn save_imports() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(IMPORTS_DIR) else {
+        return Vec::new();
+    };
+
+    let mut saves: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| SAVE_IMPORT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .collect();
+
+    saves.sort();
+    saves
+}
+
+/// File names of the executable patches available for `game`, sorted
+pub fn game_exe_patches(game: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(game_patch_dir(game)) else {
+        return Vec::new();
+    };
+
+    let mut patches: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| EXE_PATCH_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .filter_map(|path| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .collect();
+
+    patches.sort();
+    patches
+}
+
+/// Name of `game` (a disc image file name) used for its files
+fn game_name(game: &str) -> String {
+    Path::new(game)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| game.to_string())
+}
+
 // Custom serialization for HashMap<Key, Button>
 mod keyboard_map {
     use super::*;
@@ -302,8 +1519,11 @@ mod keyboard_map {
         let mut result = HashMap::new();
 
         for (key_str, button_str) in map {
-            if let (Some(key), Some(button)) = (string_to_key(&key_str), string_to_button(&button_str)) {
-                result.insert(key, button);
+            match (string_to_key(&key_str), string_to_button(&button_str)) {
+                (Some(key), Some(button)) => {
+                    result.insert(key, button);
+                }
+                _ => warn!("Ignoring unknown keyboard binding {} = {}", key_str, button_str),
             }
         }
 
@@ -337,8 +1557,11 @@ mod gamepad_map {
         let mut result = HashMap::new();
 
         for (gilrs_str, button_str) in map {
-            if let (Some(gilrs_button), Some(button)) = (string_to_gilrs_button(&gilrs_str), string_to_button(&button_str)) {
-                result.insert(gilrs_button, button);
+            match (string_to_gilrs_button(&gilrs_str), string_to_button(&button_str)) {
+                (Some(gilrs_button), Some(button)) => {
+                    result.insert(gilrs_button, button);
+                }
+                _ => warn!("Ignoring unknown gamepad binding {} = {}", gilrs_str, button_str),
             }
         }
 
@@ -352,42 +1575,7 @@ fn key_to_string(key: &Key) -> String {
 }
 
 fn string_to_key(s: &str) -> Option<Key> {
-    match s {
-        "ArrowUp" => Some(Key::ArrowUp),
-        "ArrowDown" => Some(Key::ArrowDown),
-        "ArrowLeft" => Some(Key::ArrowLeft),
-        "ArrowRight" => Some(Key::ArrowRight),
-        "Enter" => Some(Key::Enter),
-        "Backspace" => Some(Key::Backspace),
-        "Space" => Some(Key::Space),
-        "A" => Some(Key::A),
-        "B" => Some(Key::B),
-        "C" => Some(Key::C),
-        "D" => Some(Key::D),
-        "E" => Some(Key::E),
-        "F" => Some(Key::F),
-        "G" => Some(Key::G),
-        "H" => Some(Key::H),
-        "I" => Some(Key::I),
-        "J" => Some(Key::J),
-        "K" => Some(Key::K),
-        "L" => Some(Key::L),
-        "M" => Some(Key::M),
-        "N" => Some(Key::N),
-        "O" => Some(Key::O),
-        "P" => Some(Key::P),
-        "Q" => Some(Key::Q),
-        "R" => Some(Key::R),
-        "S" => Some(Key::S),
-        "T" => Some(Key::T),
-        "U" => Some(Key::U),
-        "V" => Some(Key::V),
-        "W" => Some(Key::W),
-        "X" => Some(Key::X),
-        "Y" => Some(Key::Y),
-        "Z" => Some(Key::Z),
-        _ => None,
-    }
+    Key::ALL.iter().copied().find(|key| key_to_string(key) == s)
 }
 
 // Helper functions for GilrsButton serialization
@@ -411,6 +1599,9 @@ fn string_to_gilrs_button(s: &str) -> Option<GilrsButton> {
         "DPadDown" => Some(GilrsButton::DPadDown),
         "DPadLeft" => Some(GilrsButton::DPadLeft),
         "DPadRight" => Some(GilrsButton::DPadRight),
+        "Mode" => Some(GilrsButton::Mode),
+        "LeftThumb" => Some(GilrsButton::LeftThumb),
+        "RightThumb" => Some(GilrsButton::RightThumb),
         _ => None,
     }
 }
@@ -438,7 +1629,7 @@ fn button_to_string(button: &Button) -> String {
     }
 }
 
-fn string_to_button(s: &str) -> Option<Button> {
+pub fn string_to_button(s: &str) -> Option<Button> {
     match s {
         "Select" => Some(Button::Select),
         "L3" => Some(Button::L3),
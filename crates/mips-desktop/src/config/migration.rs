@@ -0,0 +1,169 @@
+//! Versions of the configuration files, and upgrades of the files written by older versions of the
+//! emulator.
+//!
+//! Every file starts with a `version` key. Files without one predate versioning and are version 0.
+//! When a file format changes, bump its version and append a migration bringing the previous
+//! version up to date, working on the raw TOML so that nothing depends on structs that don't exist
+//! anymore.
+
+use std::collections::HashMap;
+use anyhow::{bail, Result};
+use egui::Key;
+use gilrs::Button as GilrsButton;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use toml::{Table, Value};
+use mips_core::input::{Button, InputConfig};
+
+/// Upgrades a file from the version of its index in the migration list to the next one
+pub type Migration = fn(&mut Table);
+
+/// Migrations of `settings.toml`, its current version is the length of the list
+pub const SETTINGS_MIGRATIONS: &[Migration] = &[unversioned];
+
+/// Migrations of the keyboard and gamepad bindings files
+pub const BINDINGS_MIGRATIONS: &[Migration] = &[unversioned];
+
+/// The layout didn't change when the version was added
+fn unversioned(_: &mut Table) {}
+
+pub fn current_version(migrations: &[Migration]) -> u32 {
+    migrations.len() as u32
+}
+
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    version: u32,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+/// `data` as the current version of a file
+pub fn to_toml<T: Serialize>(data: &T, migrations: &[Migration]) -> Result<String> {
+    let versioned = Versioned {
+        version: current_version(migrations),
+        data,
+    };
+
+    Ok(toml::to_string_pretty(&versioned)?)
+}
+
+/// A file read from disk, upgraded to the current version
+pub struct Upgraded<T> {
+    pub data: T,
+    /// Version the file was written with. It may be newer than ours if the emulator was
+    /// downgraded, in which case the settings we don't know about are ignored.
+    pub version: u32,
+}
+
+pub fn parse<T: DeserializeOwned>(text: &str, migrations: &[Migration]) -> Result<Upgraded<T>> {
+    let mut table: Table = text.parse()?;
+
+    let version = match table.remove("version") {
+        None => 0,
+        Some(Value::Integer(v)) if v >= 0 => v as u32,
+        Some(v) => bail!("Invalid version {}", v),
+    };
+
+    for migrate in migrations.iter().skip(version as usize) {
+        migrate(&mut table);
+    }
+
+    Ok(Upgraded {
+        data: table.try_into()?,
+        version,
+    })
+}
+
+/// Keyboard bindings of an ini input profile, with the names of the keys that don't exist anymore
+pub fn import_keyboard_profile(profile: &InputConfig) -> (HashMap<Key, Button>, Vec<String>) {
+    import_profile(profile, |name| {
+        // SDL key names, the keypad keys are merged with the main ones
+        Key::from_name(name.strip_prefix("Keypad ").unwrap_or(name))
+    })
+}
+
+/// Gamepad bindings of an ini input profile, with the names of the buttons that don't exist
+/// anymore
+pub fn import_gamepad_profile(profile: &InputConfig) -> (HashMap<GilrsButton, Button>, Vec<String>) {
+    import_profile(profile, |name| {
+        // SDL game controller button names
+        let button = match name {
+            "a" => GilrsButton::South,
+            "b" => GilrsButton::East,
+            "x" => GilrsButton::West,
+            "y" => GilrsButton::North,
+            "back" => GilrsButton::Select,
+            "guide" => GilrsButton::Mode,
+            "start" => GilrsButton::Start,
+            "leftstick" => GilrsButton::LeftThumb,
+            "rightstick" => GilrsButton::RightThumb,
+            "leftshoulder" => GilrsButton::LeftTrigger,
+            "rightshoulder" => GilrsButton::RightTrigger,
+            "lefttrigger" => GilrsButton::LeftTrigger2,
+            "righttrigger" => GilrsButton::RightTrigger2,
+            "dpup" => GilrsButton::DPadUp,
+            "dpdown" => GilrsButton::DPadDown,
+            "dpleft" => GilrsButton::DPadLeft,
+            "dpright" => GilrsButton::DPadRight,
+            _ => return None,
+        };
+        Some(button)
+    })
+}
+
+fn import_profile<K: Eq + std::hash::Hash>(
+    profile: &InputConfig,
+    input: impl Fn(&str) -> Option<K>,
+) -> (HashMap<K, Button>, Vec<String>) {
+    let mut bindings = HashMap::new();
+    let mut skipped = Vec::new();
+
+    for (name, button) in profile.bindings() {
+        match input(&name) {
+            Some(input) => {
+                bindings.insert(input, button);
+            }
+            None => skipped.push(name),
+        }
+    }
+    skipped.sort();
+
+    (bindings, skipped)
+}
+
+#[test]
+fn unversioned_files_are_upgraded() {
+    use super::AppSettings;
+
+    let mut settings = AppSettings::default();
+    settings.audio.volume = 0.5;
+    settings.system.stall_timeout_secs = 3;
+
+    let current = to_toml(&settings, SETTINGS_MIGRATIONS).unwrap();
+    assert!(current.starts_with(&format!("version = {}\n", current_version(SETTINGS_MIGRATIONS))));
+
+    let upgraded: Upgraded<AppSettings> = parse(&current, SETTINGS_MIGRATIONS).unwrap();
+    assert_eq!(upgraded.version, current_version(SETTINGS_MIGRATIONS));
+    assert_eq!(upgraded.data.system.stall_timeout_secs, 3);
+
+    let old = toml::to_string_pretty(&settings).unwrap();
+    let upgraded: Upgraded<AppSettings> = parse(&old, SETTINGS_MIGRATIONS).unwrap();
+    assert_eq!(upgraded.version, 0);
+    assert_eq!(upgraded.data.audio.volume, 0.5);
+}
+
+#[test]
+fn ini_profiles_are_imported() {
+    let profile = InputConfig::parse(
+        "[Device]\nType=Keyboard\n\n[Bindings]\nW=4\nUp=12\nReturn=3\nKeypad Enter=0\nSquare=15\n",
+    )
+    .unwrap();
+
+    let (bindings, skipped) = import_keyboard_profile(&profile);
+    assert_eq!(bindings[&Key::W], Button::DUp);
+    assert_eq!(bindings[&Key::ArrowUp], Button::Triangle);
+    // Both Enter keys are the same for egui, either binding may win
+    assert!(matches!(bindings[&Key::Enter], Button::Start | Button::Select));
+    assert_eq!(skipped, ["Square"]);
+}
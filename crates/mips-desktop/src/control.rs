@@ -0,0 +1,323 @@
+//! Control server for external tools (stream decks, autosplitters, test scripts): JSON-RPC 2.0
+//! over TCP on the loopback interface, one request per line and one response per line.
+//!
+//! The connections are served on their own threads, the calls are handed to the app which runs
+//! them between two frames and answers through `Request::reply`.
+//!
+//! ```text
+//! {"jsonrpc": "2.0", "id": 1, "method": "read_memory", "params": {"address": "0x800f1234", "length": 4}}
+//! {"jsonrpc":"2.0","id":1,"result":{"data":"01000000"}}
+//! ```
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use mips_core::input::Button;
+use crate::config;
+
+pub const DEFAULT_CONTROL_PORT: u16 = 47010;
+
+/// How often the threads check whether the server was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Largest read or write, the size of the RAM
+pub const MAX_MEMORY_ACCESS: usize = 2 * 1024 * 1024;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The call was understood but couldn't be done
+pub const CALL_FAILED: i64 = -32000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    /// Game, pause state...
+    Status,
+    Pause,
+    Resume,
+    /// Power cycle the console with another game, relative to the games directory
+    LoadGame { game: String },
+    SaveState { slot: u32 },
+    LoadState { slot: u32 },
+    /// Save the last frame in the screenshots directory
+    Screenshot,
+    ReadMemory { address: u32, length: usize },
+    WriteMemory { address: u32, data: Vec<u8> },
+    /// Crowd input, `user` is who the rate limits apply to
+    Press { button: Button, user: String },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+/// A call waiting to be run by the app
+pub struct Request {
+    pub call: Call,
+    id: Value,
+    reply: Sender<String>,
+}
+
+impl Request {
+    pub fn reply(self, result: Result<Value, RpcError>) {
+        // The client may be gone, nothing to do about it
+        let _ = self.reply.send(response(&self.id, result));
+    }
+}
+
+pub struct ControlServer {
+    port: u16,
+    requests: Receiver<Request>,
+    stop: Arc<AtomicBool>,
+}
+
+impl ControlServer {
+    /// Listen on `port` of the loopback interface, only local programs can connect
+    pub fn start(port: u16) -> io::Result<ControlServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+
+        let (sender, requests) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_stop = stop.clone();
+        thread::Builder::new()
+            .name("control server".to_string())
+            .spawn(move || accept_loop(listener, sender, thread_stop))?;
+
+        info!("Control server listening on 127.0.0.1:{}", port);
+
+        Ok(ControlServer { port, requests, stop })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Calls received since the last call
+    pub fn poll(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn accept_loop(listener: TcpListener, requests: Sender<Request>, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                info!("Control client connected from {}", addr);
+
+                let requests = requests.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, requests, stop) {
+                        warn!("Control client {} disconnected: {}", addr, e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                warn!("Control server stopped: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+fn serve(stream: TcpStream, requests: Sender<Request>, stop: Arc<AtomicBool>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => (),
+            // Timed out, the partial line stays in `line`
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e),
+        }
+
+        if !line.trim().is_empty() {
+            let answer = match parse_request(&line) {
+                Ok((id, call)) => {
+                    let (reply, answer) = mpsc::channel();
+                    if requests.send(Request { call, id, reply }).is_err() {
+                        // The app is gone
+                        return Ok(());
+                    }
+                    match answer.recv() {
+                        Ok(answer) => answer,
+                        Err(_) => return Ok(()),
+                    }
+                }
+                Err((id, e)) => response(&id, Err(e)),
+            };
+
+            writer.write_all(answer.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+
+        line.clear();
+    }
+
+    Ok(())
+}
+
+fn response(id: &Value, result: Result<Value, RpcError>) -> String {
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message },
+        }),
+    };
+
+    response.to_string()
+}
+
+/// The call in `line` and its id, or the error to answer with
+fn parse_request(line: &str) -> Result<(Value, Call), (Value, RpcError)> {
+    let request: Value = serde_json::from_str(line)
+        .map_err(|e| (Value::Null, RpcError::new(PARSE_ERROR, e.to_string())))?;
+
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let invalid = |message: &str| (id.clone(), RpcError::new(INVALID_REQUEST, message));
+
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(invalid("Not a JSON-RPC 2.0 request"));
+    }
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return Err(invalid("Missing method"));
+    };
+    let params = request.get("params").unwrap_or(&Value::Null);
+
+    let call = parse_call(method, params).map_err(|e| (id.clone(), e))?;
+
+    Ok((id, call))
+}
+
+fn parse_call(method: &str, params: &Value) -> Result<Call, RpcError> {
+    let call = match method {
+        "status" => Call::Status,
+        "pause" => Call::Pause,
+        "resume" => Call::Resume,
+        "load_game" => Call::LoadGame { game: param_str(params, "game")?.to_string() },
+        "save_state" => Call::SaveState { slot: param_u32(params, "slot")? },
+        "load_state" => Call::LoadState { slot: param_u32(params, "slot")? },
+        "screenshot" => Call::Screenshot,
+        "read_memory" => {
+            let length = param_u32(params, "length")? as usize;
+            if length > MAX_MEMORY_ACCESS {
+                return Err(RpcError::new(INVALID_PARAMS, "length is larger than the RAM"));
+            }
+            Call::ReadMemory { address: param_u32(params, "address")?, length }
+        }
+        "write_memory" => {
+            let data = from_hex(param_str(params, "data")?)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "data must be an even number of hex digits"))?;
+            if data.len() > MAX_MEMORY_ACCESS {
+                return Err(RpcError::new(INVALID_PARAMS, "data is larger than the RAM"));
+            }
+            Call::WriteMemory { address: param_u32(params, "address")?, data }
+        }
+        "press" => {
+            let name = param_str(params, "button")?;
+            let button = config::string_to_button(name)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Unknown button '{}'", name)))?;
+            let user = params.get("user").and_then(Value::as_str).unwrap_or_default().to_string();
+            Call::Press { button, user }
+        }
+        _ => return Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+    };
+
+    Ok(call)
+}
+
+fn param_str<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    params.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing string parameter '{}'", name)))
+}
+
+/// A number, or a string in hex with a `0x` prefix (addresses are easier to read that way)
+fn param_u32(params: &Value, name: &str) -> Result<u32, RpcError> {
+    let value = match params.get(name) {
+        Some(Value::Number(n)) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Some(Value::String(s)) => s.strip_prefix("0x")
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .or_else(|| s.parse().ok()),
+        _ => None,
+    };
+
+    value.ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing or invalid integer parameter '{}'", name)))
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[test]
+fn requests() {
+    let call = |line: &str| parse_request(line).map(|(_, call)| call).map_err(|(_, e)| e.code);
+
+    assert_eq!(call(r#"{"jsonrpc": "2.0", "id": 1, "method": "pause"}"#), Ok(Call::Pause));
+    assert_eq!(
+        call(r#"{"jsonrpc": "2.0", "id": 2, "method": "read_memory", "params": {"address": "0x800f0000", "length": 16}}"#),
+        Ok(Call::ReadMemory { address: 0x800f_0000, length: 16 }),
+    );
+    assert_eq!(
+        call(r#"{"jsonrpc": "2.0", "id": 3, "method": "write_memory", "params": {"address": 1024, "data": "dEad"}}"#),
+        Ok(Call::WriteMemory { address: 1024, data: vec![0xde, 0xad] }),
+    );
+
+    assert_eq!(
+        call(r#"{"jsonrpc": "2.0", "id": 4, "method": "press", "params": {"button": "Cross", "user": "viewer"}}"#),
+        Ok(Call::Press { button: Button::Cross, user: "viewer".to_string() }),
+    );
+
+    assert_eq!(call("{not json"), Err(PARSE_ERROR));
+    assert_eq!(call(r#"{"id": 4, "method": "pause"}"#), Err(INVALID_REQUEST));
+    assert_eq!(call(r#"{"jsonrpc": "2.0", "id": 5, "method": "reboot"}"#), Err(METHOD_NOT_FOUND));
+    assert_eq!(call(r#"{"jsonrpc": "2.0", "id": 6, "method": "write_memory", "params": {"address": 0, "data": "abc"}}"#), Err(INVALID_PARAMS));
+
+    let (id, _) = parse_request(r#"{"jsonrpc": "2.0", "id": "x", "method": "status"}"#).unwrap();
+    assert_eq!(response(&id, Ok(json!(true))), r#"{"id":"x","jsonrpc":"2.0","result":true}"#);
+}
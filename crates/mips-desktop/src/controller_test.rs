@@ -0,0 +1,340 @@
+//! Controller test panel: shows the buttons and axes of the host gamepads as they're read, fires
+//! the rumble motors, calibrates the stick deadzones and sets how deep the analog triggers press
+//! their buttons, so that users can check their setup
+//! before starting a game. Reads gilrs directly, the emulator doesn't get the gamepad inputs while
+//! the panel is open.
+
+use std::time::{Duration, Instant};
+use egui::{Color32, Sense, Stroke, Vec2};
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks};
+use gilrs::{Axis, Button as GilrsButton, GamepadId, Gilrs};
+use tracing::warn;
+use crate::config::{GamepadBindings, InputSettings, button_display_name};
+use crate::input::{TRIGGERS, apply_deadzone, trigger_down};
+
+const RUMBLE_DURATION: Duration = Duration::from_millis(500);
+/// How long the sticks are sampled at rest
+const CALIBRATION_DURATION: Duration = Duration::from_secs(2);
+const MAX_DEADZONE: f32 = 0.5;
+
+const STICK_SIZE: f32 = 96.0;
+
+const BUTTONS: [GilrsButton; 17] = [
+    GilrsButton::South, GilrsButton::East, GilrsButton::West, GilrsButton::North,
+    GilrsButton::LeftTrigger, GilrsButton::RightTrigger,
+    GilrsButton::LeftTrigger2, GilrsButton::RightTrigger2,
+    GilrsButton::Select, GilrsButton::Start, GilrsButton::Mode,
+    GilrsButton::LeftThumb, GilrsButton::RightThumb,
+    GilrsButton::DPadUp, GilrsButton::DPadDown, GilrsButton::DPadLeft, GilrsButton::DPadRight,
+];
+
+#[derive(Clone, Copy)]
+enum Motor {
+    /// Big motor in the left handle
+    Strong,
+    /// Small motor in the right handle
+    Weak,
+}
+
+struct Calibration {
+    started: Instant,
+    /// Largest distance from the center seen for the left and right sticks
+    max_radius: [f32; 2],
+}
+
+pub struct ControllerTest {
+    pub open: bool,
+    /// Frames drawn, to show the trigger pulses
+    frame: u32,
+    gamepad: Option<GamepadId>,
+    /// Rumble strength, 0 to 1
+    magnitude: f32,
+    /// Effect being played, it stops when dropped
+    rumble: Option<(Effect, Instant)>,
+    calibration: Option<Calibration>,
+    status: Option<String>,
+}
+
+impl Default for ControllerTest {
+    fn default() -> Self {
+        Self {
+            open: false,
+            frame: 0,
+            gamepad: None,
+            magnitude: 1.0,
+            rumble: None,
+            calibration: None,
+            status: None,
+        }
+    }
+}
+
+impl ControllerTest {
+    /// Returns true when the settings changed and should be saved
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        gilrs: Option<&mut Gilrs>,
+        bindings: &GamepadBindings,
+        settings: &mut InputSettings,
+    ) -> bool {
+        if !self.open {
+            // Stop the motors if the panel was closed mid-test
+            self.rumble = None;
+            self.calibration = None;
+            return false;
+        }
+
+        let mut open = self.open;
+        let mut save = false;
+
+        egui::Window::new("Controller Test")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let Some(gilrs) = gilrs else {
+                    ui.label("Gamepad support isn't available");
+                    return;
+                };
+
+                // The emulator doesn't consume the events while we're open, the state is updated
+                // as they're drained
+                while gilrs.next_event().is_some() {}
+
+                if self.gamepad.is_none_or(|id| gilrs.connected_gamepad(id).is_none()) {
+                    self.gamepad = gilrs.gamepads().next().map(|(id, _)| id);
+                }
+                let Some(id) = self.gamepad else {
+                    ui.label("No gamepad connected");
+                    return;
+                };
+
+                egui::ComboBox::from_label("Gamepad")
+                    .selected_text(gilrs.gamepad(id).name().to_string())
+                    .show_ui(ui, |ui| {
+                        for (other, gamepad) in gilrs.gamepads() {
+                            ui.selectable_value(&mut self.gamepad, Some(other), gamepad.name());
+                        }
+                    });
+
+                ui.separator();
+                self.show_buttons(ui, gilrs, id, bindings);
+
+                ui.separator();
+                save |= self.show_sticks(ui, gilrs, id, settings);
+
+                ui.separator();
+                save |= self.show_triggers(ui, gilrs, id, settings);
+
+                ui.separator();
+                self.show_rumble(ui, gilrs, id);
+
+                if let Some(status) = &self.status {
+                    ui.separator();
+                    ui.label(status);
+                }
+            });
+
+        self.open = open;
+
+        if self.rumble.as_ref().is_some_and(|(_, started)| started.elapsed() >= RUMBLE_DURATION) {
+            self.rumble = None;
+        }
+
+        // Keep the live values moving
+        ctx.request_repaint_after(Duration::from_millis(16));
+
+        save
+    }
+
+    fn show_buttons(&self, ui: &mut egui::Ui, gilrs: &Gilrs, id: GamepadId, bindings: &GamepadBindings) {
+        let gamepad = gilrs.gamepad(id);
+
+        egui::Grid::new("controller_test_buttons")
+            .num_columns(4)
+            .spacing([12.0, 2.0])
+            .show(ui, |ui| {
+                for (i, button) in BUTTONS.into_iter().enumerate() {
+                    let value = gamepad.button_data(button).map_or(0.0, |d| d.value());
+                    let color = if gamepad.is_pressed(button) {
+                        Color32::LIGHT_GREEN
+                    } else {
+                        ui.visuals().weak_text_color()
+                    };
+
+                    let mapped = bindings.bindings.get(&button)
+                        .map(|b| format!(" → {}", button_display_name(b)))
+                        .unwrap_or_default();
+
+                    ui.colored_label(color, format!("{:?}{}", button, mapped));
+                    ui.add(egui::ProgressBar::new(value).desired_width(48.0));
+
+                    if i % 2 == 1 {
+                        ui.end_row();
+                    }
+                }
+            });
+    }
+
+    /// Returns true if a deadzone changed
+    fn show_sticks(&mut self, ui: &mut egui::Ui, gilrs: &Gilrs, id: GamepadId, settings: &mut InputSettings) -> bool {
+        let gamepad = gilrs.gamepad(id);
+        let sticks = [
+            (gamepad.value(Axis::LeftStickX), gamepad.value(Axis::LeftStickY)),
+            (gamepad.value(Axis::RightStickX), gamepad.value(Axis::RightStickY)),
+        ];
+
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            let deadzones = [&mut settings.left_stick_deadzone, &mut settings.right_stick_deadzone];
+
+            for ((name, stick), deadzone) in ["Left stick", "Right stick"].into_iter().zip(sticks).zip(deadzones) {
+                ui.vertical(|ui| {
+                    ui.label(name);
+                    draw_stick(ui, stick, *deadzone);
+
+                    let (x, y) = apply_deadzone(stick, *deadzone);
+                    ui.monospace(format!("{:+.2} {:+.2}", x, y));
+
+                    // Only save once the slider is released
+                    let slider = ui.add(egui::Slider::new(deadzone, 0.0..=MAX_DEADZONE).text("Deadzone"));
+                    changed |= slider.drag_stopped() || (slider.changed() && !slider.dragged());
+                });
+            }
+        });
+
+        match &mut self.calibration {
+            Some(calibration) => {
+                for (max, (x, y)) in calibration.max_radius.iter_mut().zip(sticks) {
+                    *max = max.max((x * x + y * y).sqrt());
+                }
+
+                if calibration.started.elapsed() >= CALIBRATION_DURATION {
+                    // Leave some margin over the noise we measured
+                    let [left, right] = calibration.max_radius.map(|r| (r * 1.2 + 0.02).min(MAX_DEADZONE));
+                    settings.left_stick_deadzone = left;
+                    settings.right_stick_deadzone = right;
+
+                    self.status = Some(format!("Deadzones set to {:.2} (left) and {:.2} (right)", left, right));
+                    self.calibration = None;
+                    changed = true;
+                } else {
+                    ui.label("Leave both sticks at rest...");
+                }
+            }
+            None => {
+                if ui.button("Calibrate deadzones").clicked() {
+                    self.calibration = Some(Calibration {
+                        started: Instant::now(),
+                        max_radius: [0.0; 2],
+                    });
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Returns true if a trigger setting changed
+    fn show_triggers(&mut self, ui: &mut egui::Ui, gilrs: &Gilrs, id: GamepadId, settings: &mut InputSettings) -> bool {
+        let gamepad = gilrs.gamepad(id);
+        self.frame = self.frame.wrapping_add(1);
+
+        let mut changed = false;
+
+        egui::Grid::new("controller_test_triggers")
+            .num_columns(4)
+            .spacing([12.0, 2.0])
+            .show(ui, |ui| {
+                for (trigger, trigger_settings) in TRIGGERS.into_iter().zip(&mut settings.triggers) {
+                    let depth = gamepad.button_data(trigger).map_or(0.0, |d| d.value());
+                    let color = if trigger_down(depth, trigger_settings, self.frame) {
+                        Color32::LIGHT_GREEN
+                    } else {
+                        ui.visuals().weak_text_color()
+                    };
+
+                    ui.colored_label(color, format!("{:?}", trigger));
+                    ui.add(egui::ProgressBar::new(depth).desired_width(48.0));
+
+                    // Only save once the slider is released
+                    let threshold = egui::Slider::new(&mut trigger_settings.threshold, 0.05..=0.95);
+                    let slider = ui.add(threshold.text("Threshold"));
+                    changed |= slider.drag_stopped() || (slider.changed() && !slider.dragged());
+                    changed |= ui.checkbox(&mut trigger_settings.pulse, "Pulse")
+                        .on_hover_text(
+                            "Press the button in pulses that get longer as the trigger goes \
+                             deeper, to ease off the accelerator in racing games that only have a \
+                             button for it",
+                        )
+                        .changed();
+                    ui.end_row();
+                }
+            });
+
+        changed
+    }
+
+    fn show_rumble(&mut self, ui: &mut egui::Ui, gilrs: &mut Gilrs, id: GamepadId) {
+        if !gilrs.gamepad(id).is_ff_supported() {
+            ui.label("This gamepad doesn't support rumble");
+            return;
+        }
+
+        ui.add(egui::Slider::new(&mut self.magnitude, 0.0..=1.0).text("Strength"));
+
+        ui.horizontal(|ui| {
+            for (label, motor) in [("Left motor (strong)", Motor::Strong), ("Right motor (weak)", Motor::Weak)] {
+                if ui.button(label).clicked() {
+                    // Replacing the effect stops the previous one
+                    self.rumble = match rumble(gilrs, id, motor, self.magnitude) {
+                        Ok(effect) => Some((effect, Instant::now())),
+                        Err(e) => {
+                            warn!("Failed to start rumble: {}", e);
+                            self.status = Some(format!("Rumble failed: {}", e));
+                            None
+                        }
+                    };
+                }
+            }
+        });
+    }
+}
+
+fn rumble(gilrs: &mut Gilrs, id: GamepadId, motor: Motor, strength: f32) -> Result<Effect, gilrs::ff::Error> {
+    let magnitude = (strength * u16::MAX as f32) as u16;
+    let kind = match motor {
+        Motor::Strong => BaseEffectType::Strong { magnitude },
+        Motor::Weak => BaseEffectType::Weak { magnitude },
+    };
+    let duration = Ticks::from_ms(RUMBLE_DURATION.as_millis() as u32);
+
+    let effect = EffectBuilder::new()
+        .add_effect(BaseEffect {
+            kind,
+            scheduling: Replay { play_for: duration, ..Default::default() },
+            ..Default::default()
+        })
+        .gamepads(&[id])
+        .repeat(Repeat::For(duration))
+        .finish(gilrs)?;
+
+    effect.play()?;
+
+    Ok(effect)
+}
+
+/// Stick position in a circle, with the deadzone shaded
+fn draw_stick(ui: &mut egui::Ui, (x, y): (f32, f32), deadzone: f32) {
+    let (response, painter) = ui.allocate_painter(Vec2::splat(STICK_SIZE), Sense::hover());
+    let center = response.rect.center();
+    let radius = STICK_SIZE / 2.0 - 4.0;
+
+    painter.circle_stroke(center, radius, Stroke::new(1.0, ui.visuals().weak_text_color()));
+    painter.circle_filled(center, radius * deadzone, Color32::from_gray(70));
+
+    // Up is positive for gilrs, down on screen
+    let pos = center + Vec2::new(x, -y) * radius;
+    painter.circle_filled(pos, 4.0, Color32::LIGHT_GREEN);
+}
@@ -0,0 +1,178 @@
+//! Crowd input ("Twitch plays"): button presses sent through the control server on behalf of a
+//! stream's viewers are merged with the local inputs before they reach the core. In anarchy mode
+//! every press is played in the order it came, in democracy mode the viewers vote and the most
+//! voted button is pressed at the end of each round.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use mips_core::input::{Button, ButtonQueue, ButtonState};
+use crate::config::{CrowdMode, CrowdSettings};
+
+/// Presses waiting in anarchy mode, further ones are refused until the queue drains
+const MAX_QUEUED: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejected {
+    Disabled,
+    /// The viewer sent something less than the cooldown ago
+    Cooldown,
+    QueueFull,
+}
+
+impl Rejected {
+    pub fn message(self) -> &'static str {
+        match self {
+            Rejected::Disabled => "Crowd input is disabled",
+            Rejected::Cooldown => "Too soon after the previous command",
+            Rejected::QueueFull => "Too many commands waiting",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CrowdInput {
+    queue: VecDeque<Button>,
+    /// Vote of each viewer in the current democracy round
+    votes: HashMap<String, Button>,
+    /// Order in which the buttons got their first vote of the round, to break ties
+    first_votes: Vec<Button>,
+    round_frames: u32,
+    last_command: HashMap<String, Instant>,
+    /// Button pressed and the frames it's still held for
+    held: Option<(Button, u32)>,
+    /// A frame with the button released between two presses
+    gap: bool,
+}
+
+impl CrowdInput {
+    /// Take a command from `user` into account, subject to the mode and the rate limits
+    pub fn submit(&mut self, settings: &CrowdSettings, user: &str, button: Button, now: Instant) -> Result<(), Rejected> {
+        if settings.mode == CrowdMode::Off {
+            return Err(Rejected::Disabled);
+        }
+
+        let cooldown = Duration::from_millis(u64::from(settings.user_cooldown_ms));
+        if self.last_command.get(user).is_some_and(|&last| now.duration_since(last) < cooldown) {
+            return Err(Rejected::Cooldown);
+        }
+
+        match settings.mode {
+            CrowdMode::Off => unreachable!(),
+            CrowdMode::Anarchy => {
+                if self.queue.len() >= MAX_QUEUED {
+                    return Err(Rejected::QueueFull);
+                }
+                self.queue.push_back(button);
+            }
+            CrowdMode::Democracy => {
+                if !self.first_votes.contains(&button) {
+                    self.first_votes.push(button);
+                }
+                // Viewers can change their mind, the last vote counts
+                self.votes.insert(user.to_string(), button);
+            }
+        }
+
+        self.last_command.insert(user.to_string(), now);
+        // Forget the viewers who went quiet
+        self.last_command.retain(|_, &mut last| now.duration_since(last) < cooldown);
+
+        Ok(())
+    }
+
+    /// Button changes for the next frame
+    pub fn next_frame(&mut self, settings: &CrowdSettings) -> ButtonQueue {
+        let mut changes = Vec::new();
+
+        if settings.mode == CrowdMode::Democracy {
+            self.round_frames += 1;
+            if self.round_frames >= settings.vote_frames.max(1) {
+                self.round_frames = 0;
+                if let Some(winner) = self.winner() {
+                    self.queue.push_back(winner);
+                }
+                self.votes.clear();
+                self.first_votes.clear();
+            }
+        }
+
+        if let Some((button, frames)) = &mut self.held {
+            *frames = frames.saturating_sub(1);
+            if *frames == 0 {
+                changes.push((ButtonState::Released, *button));
+                self.held = None;
+                self.gap = true;
+            }
+            return changes;
+        }
+
+        if self.gap {
+            self.gap = false;
+            return changes;
+        }
+
+        if let Some(button) = self.queue.pop_front() {
+            changes.push((ButtonState::Pressed, button));
+            self.held = Some((button, u32::from(settings.hold_frames.max(1))));
+        }
+
+        changes
+    }
+
+    /// Most voted button of the round, the first one to get a vote among equals
+    fn winner(&self) -> Option<Button> {
+        let count = |button: Button| self.votes.values().filter(|&&b| b == button).count();
+
+        self.first_votes.iter()
+            .copied()
+            .rev()
+            .max_by_key(|&button| count(button))
+    }
+
+    /// Drop everything pending, the presses in progress are released
+    pub fn reset(&mut self) -> ButtonQueue {
+        let released = self.held.take().map(|(button, _)| (ButtonState::Released, button));
+        *self = CrowdInput::default();
+
+        released.into_iter().collect()
+    }
+}
+
+#[test]
+fn anarchy_and_democracy() {
+    let now = Instant::now();
+    let later = now + Duration::from_secs(10);
+    let mut settings = CrowdSettings {
+        mode: CrowdMode::Anarchy,
+        hold_frames: 2,
+        user_cooldown_ms: 1000,
+        vote_frames: 3,
+    };
+    let mut crowd = CrowdInput::default();
+
+    assert_eq!(crowd.submit(&settings, "a", Button::Cross, now), Ok(()));
+    assert_eq!(crowd.submit(&settings, "a", Button::Circle, now), Err(Rejected::Cooldown));
+    assert_eq!(crowd.submit(&settings, "b", Button::Cross, now), Ok(()));
+
+    // Held for two frames, released for one between the presses
+    let frames: Vec<ButtonQueue> = (0..5).map(|_| crowd.next_frame(&settings)).collect();
+    assert_eq!(frames[0], [(ButtonState::Pressed, Button::Cross)]);
+    assert!(frames[1].is_empty());
+    assert_eq!(frames[2], [(ButtonState::Released, Button::Cross)]);
+    assert!(frames[3].is_empty());
+    assert_eq!(frames[4], [(ButtonState::Pressed, Button::Cross)]);
+
+    settings.mode = CrowdMode::Democracy;
+    let mut crowd = CrowdInput::default();
+    crowd.submit(&settings, "a", Button::Square, later).unwrap();
+    crowd.submit(&settings, "b", Button::DUp, later).unwrap();
+    crowd.submit(&settings, "c", Button::DUp, later).unwrap();
+
+    assert!(crowd.next_frame(&settings).is_empty());
+    assert!(crowd.next_frame(&settings).is_empty());
+    assert_eq!(crowd.next_frame(&settings), [(ButtonState::Pressed, Button::DUp)]);
+    assert_eq!(crowd.reset(), [(ButtonState::Released, Button::DUp)]);
+
+    settings.mode = CrowdMode::Off;
+    assert_eq!(crowd.submit(&settings, "d", Button::Start, later), Err(Rejected::Disabled));
+}
@@ -0,0 +1,101 @@
+//! Displays connected to the host, to put the game fullscreen on a chosen one (the TV in a
+//! multi-monitor setup) and to pace the repaints at the refresh rate of the one the window is on.
+//!
+//! Positions and sizes are in physical pixels of the desktop. Each display has its own scale
+//! factor, egui points can't be compared across displays.
+
+use egui::{Pos2, Rect};
+use tracing::warn;
+
+#[derive(Clone, Debug)]
+pub struct Display {
+    /// Name given by the system, saved in the settings to find the display again
+    pub name: String,
+    /// Area of the display on the desktop
+    pub rect: Rect,
+    /// Physical pixels per point
+    pub scale_factor: f32,
+    /// 0 if the system doesn't say
+    pub refresh_hz: f32,
+    pub primary: bool,
+}
+
+impl Display {
+    /// Shown in the settings
+    pub fn label(&self) -> String {
+        let mut label = format!(
+            "{} ({}x{}, {:.0}%",
+            self.name,
+            self.rect.width(),
+            self.rect.height(),
+            self.scale_factor * 100.0,
+        );
+        if self.refresh_hz > 0.0 {
+            label += &format!(", {:.0} Hz", self.refresh_hz);
+        }
+        label += ")";
+        if self.primary {
+            label += " - primary";
+        }
+
+        label
+    }
+}
+
+/// Displays connected now, none if they can't be listed
+pub fn list() -> Vec<Display> {
+    match display_info::DisplayInfo::all() {
+        Ok(displays) => displays
+            .into_iter()
+            .map(|d| Display {
+                rect: Rect::from_min_size(
+                    Pos2::new(d.x as f32, d.y as f32),
+                    egui::vec2(d.width as f32, d.height as f32),
+                ),
+                name: d.name,
+                scale_factor: d.scale_factor,
+                refresh_hz: d.frequency,
+                primary: d.is_primary,
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Failed to list the displays: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+pub fn find<'a>(displays: &'a [Display], name: &str) -> Option<&'a Display> {
+    displays.iter().find(|d| d.name == name)
+}
+
+/// Display showing `point`, in physical pixels of the desktop
+pub fn at(displays: &[Display], point: Pos2) -> Option<&Display> {
+    displays.iter().find(|d| d.rect.contains(point))
+}
+
+/// Where to put the top left corner of a window of `size` (physical pixels) to center it on
+/// `display`. The corner stays on the display if the window is larger.
+pub fn centered(display: &Display, size: egui::Vec2) -> Pos2 {
+    (display.rect.center() - size / 2.0).max(display.rect.min)
+}
+
+#[test]
+fn display_layout() {
+    let display = |name: &str, x: f32, width: f32| Display {
+        name: name.to_string(),
+        rect: Rect::from_min_size(Pos2::new(x, 0.0), egui::vec2(width, 1080.0)),
+        scale_factor: 1.0,
+        refresh_hz: 60.0,
+        primary: false,
+    };
+    let displays = [display("DP-1", 0.0, 1920.0), display("HDMI-1", 1920.0, 3840.0)];
+
+    assert_eq!(at(&displays, Pos2::new(2000.0, 10.0)).unwrap().name, "HDMI-1");
+    assert!(at(&displays, Pos2::new(-1.0, 10.0)).is_none());
+    assert_eq!(find(&displays, "DP-1").unwrap().rect.width(), 1920.0);
+
+    let tv = find(&displays, "HDMI-1").unwrap();
+    assert_eq!(centered(tv, egui::vec2(1280.0, 720.0)), Pos2::new(3200.0, 180.0));
+    assert_eq!(centered(tv, egui::vec2(5000.0, 720.0)), Pos2::new(1920.0, 180.0));
+}
@@ -1,3 +1,4 @@
+use std::io;
 use thiserror::Error;
 use mips_core::MipsError;
 
@@ -9,4 +10,10 @@ pub enum AppError {
     WindowBuildFailure(String),
     #[error("Mips error: {0}")]
     MipsError(#[from] MipsError),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Cloud sync failed: {0}")]
+    CloudSync(String),
+    #[error("Save state slot {0} is empty")]
+    EmptySlot(u32),
 }
\ No newline at end of file
@@ -0,0 +1,42 @@
+//! Frame skip policy: picks the frames whose drawing is skipped so that slow machines keep the
+//! game running at full speed, at the cost of a choppier picture.
+
+use crate::config::FrameSkip;
+
+/// Frames skipped in a row at most in auto mode, so that the picture keeps moving
+const MAX_AUTO_SKIP: u32 = 4;
+
+#[derive(Default)]
+pub struct FrameSkipper {
+    frame: u32,
+    skipped_in_row: u32,
+}
+
+impl FrameSkipper {
+    /// True if the drawing of the next frame should be skipped. `behind` is true when the
+    /// emulation runs late.
+    pub fn skip_next(&mut self, mode: FrameSkip, behind: bool) -> bool {
+        let skip = match mode {
+            FrameSkip::Off => false,
+            FrameSkip::Auto => behind && self.skipped_in_row < MAX_AUTO_SKIP,
+            FrameSkip::Fixed(n) => !self.frame.is_multiple_of(u32::from(n.max(1))),
+        };
+
+        self.frame = self.frame.wrapping_add(1);
+        self.skipped_in_row = if skip { self.skipped_in_row + 1 } else { 0 };
+
+        skip
+    }
+}
+
+#[test]
+fn frame_skip() {
+    let mut skipper = FrameSkipper::default();
+    let skipped: Vec<bool> = (0..6).map(|_| skipper.skip_next(FrameSkip::Fixed(3), false)).collect();
+    assert_eq!(skipped, [false, true, true, false, true, true]);
+
+    let mut skipper = FrameSkipper::default();
+    assert!(!skipper.skip_next(FrameSkip::Auto, false));
+    let skipped = (0..10).filter(|_| skipper.skip_next(FrameSkip::Auto, true)).count();
+    assert_eq!(skipped, 8);
+}
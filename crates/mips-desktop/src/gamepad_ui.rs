@@ -0,0 +1,102 @@
+//! Gamepad navigation of the menus and windows while the game isn't being played (paused, games
+//! list open, typing in a text field): the D-pad moves the focus between the widgets, Cross
+//! activates the one focused and Circle backs out of the menus. In the text fields the gamepad
+//! types on the on-screen keyboard instead.
+
+use egui::Key;
+use gilrs::Button as GilrsButton;
+use crate::virtual_keyboard::VirtualKeyboard;
+
+/// Gamepad buttons the UI uses, named after their place on the DualShock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavButton {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Cross
+    Accept,
+    /// Circle
+    Back,
+    /// Square
+    Square,
+    /// Triangle
+    Triangle,
+    Start,
+}
+
+impl NavButton {
+    pub fn from_gilrs(button: GilrsButton) -> Option<NavButton> {
+        let button = match button {
+            GilrsButton::DPadUp => NavButton::Up,
+            GilrsButton::DPadDown => NavButton::Down,
+            GilrsButton::DPadLeft => NavButton::Left,
+            GilrsButton::DPadRight => NavButton::Right,
+            GilrsButton::South => NavButton::Accept,
+            GilrsButton::East => NavButton::Back,
+            GilrsButton::West => NavButton::Square,
+            GilrsButton::North => NavButton::Triangle,
+            GilrsButton::Start => NavButton::Start,
+            _ => return None,
+        };
+
+        Some(button)
+    }
+}
+
+/// `key` pressed and released
+pub fn key_events(key: Key) -> [egui::Event; 2] {
+    let event = |pressed| egui::Event::Key {
+        key,
+        physical_key: None,
+        pressed,
+        repeat: false,
+        modifiers: egui::Modifiers::NONE,
+    };
+
+    [event(true), event(false)]
+}
+
+#[derive(Default)]
+pub struct GamepadUi {
+    /// The gamepad drives the UI instead of the game. Decided at the end of each pass, for the
+    /// events of the next one.
+    pub active: bool,
+    keyboard: VirtualKeyboard,
+}
+
+impl GamepadUi {
+    /// Turn the buttons pressed into egui events, before the pass starts
+    pub fn handle(&mut self, ctx: &egui::Context, buttons: &[NavButton], raw_input: &mut egui::RawInput) {
+        let typing = ctx.wants_keyboard_input();
+
+        for &button in buttons {
+            if typing {
+                raw_input.events.extend(self.keyboard.press(button));
+                continue;
+            }
+
+            let nothing_focused = ctx.memory(|m| m.focused().is_none());
+            let key = match button {
+                // The arrows only move the focus from a widget, start from the first one
+                NavButton::Up | NavButton::Down | NavButton::Left | NavButton::Right if nothing_focused => Key::Tab,
+                NavButton::Up => Key::ArrowUp,
+                NavButton::Down => Key::ArrowDown,
+                NavButton::Left => Key::ArrowLeft,
+                NavButton::Right => Key::ArrowRight,
+                NavButton::Accept => Key::Enter,
+                // Closes the menus and the popups
+                NavButton::Back => Key::Escape,
+                NavButton::Square | NavButton::Triangle | NavButton::Start => continue,
+            };
+            raw_input.events.extend(key_events(key));
+        }
+    }
+
+    /// The on-screen keyboard, while a text field has the focus
+    pub fn show(&self, ctx: &egui::Context) {
+        if self.active && ctx.wants_keyboard_input() {
+            self.keyboard.show(ctx);
+        }
+    }
+}
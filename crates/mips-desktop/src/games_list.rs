@@ -0,0 +1,429 @@
+//! Games list window: the library with favorites first, a disc selector for the games on several
+//! discs, collections and filters, and an editor for the titles, the hidden entries and the disc
+//! groups.
+
+use std::collections::HashMap;
+use std::time::Duration;
+use egui::RichText;
+use tracing::error;
+use crate::accessibility;
+use crate::save_states::Thumbnails;
+use crate::library::{GameMeta, Library, LibraryEntry, LibraryStore, ScanMode};
+
+const FAVORITE: &str = "★";
+const NOT_FAVORITE: &str = "☆";
+
+/// Entry being edited, the changes are kept until they're saved
+struct Edit {
+    key: String,
+    title: String,
+    sort_title: String,
+    genre: String,
+    meta: GameMeta,
+}
+
+impl Edit {
+    fn new(entry: &LibraryEntry) -> Self {
+        Self {
+            key: entry.key().to_string(),
+            title: entry.meta.title.clone().unwrap_or_default(),
+            sort_title: entry.meta.sort_title.clone().unwrap_or_default(),
+            genre: entry.meta.genre.clone().unwrap_or_default(),
+            meta: entry.meta.clone(),
+        }
+    }
+
+    fn meta(&self) -> GameMeta {
+        let text = |s: &str| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+        GameMeta {
+            title: text(&self.title),
+            sort_title: text(&self.sort_title),
+            genre: text(&self.genre),
+            ..self.meta.clone()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct GamesList {
+    pub open: bool,
+    /// Scanned the first time the window is opened
+    library: Option<Library>,
+    show_hidden: bool,
+    /// Disc picked for each multi-disc entry
+    discs: HashMap<String, usize>,
+    edit: Option<Edit>,
+    /// Name typed for a new collection
+    new_collection: String,
+    /// Pictures of where the games were left
+    thumbnails: Thumbnails,
+}
+
+impl GamesList {
+    /// Returns the file to start when a game is launched, relative to the games directory.
+    /// Games can't be launched during netplay sessions. `profiles` are the binding profiles the
+    /// games and the genres can be given.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        current: Option<&str>,
+        can_launch: bool,
+        profiles: &[String],
+    ) -> Option<String> {
+        if !self.open {
+            return None;
+        }
+
+        let library = self.library.get_or_insert_with(Library::load);
+        library.poll();
+        let mut open = self.open;
+        let mut launch = None;
+        let mut changed = false;
+
+        egui::Window::new("Games")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_hidden, "Show hidden");
+                    let scanning = library.scan_progress().is_some();
+                    if ui.add_enabled(!scanning, egui::Button::new("Rescan"))
+                        .on_hover_text("Look for new games, hash the ones that changed")
+                        .clicked()
+                    {
+                        library.rescan(ScanMode::ChangedOnly);
+                    }
+                    if ui.add_enabled(!scanning, egui::Button::new("Full Rescan"))
+                        .on_hover_text("Hash every game again")
+                        .clicked()
+                    {
+                        library.rescan(ScanMode::Full);
+                    }
+                    ui.weak(format!("{} games", library.entries.len()));
+                });
+
+                if let Some((done, total)) = library.scan_progress() {
+                    let fraction = if total == 0 { 0.0 } else { done as f32 / total as f32 };
+                    ui.add(egui::ProgressBar::new(fraction).text(format!("Scanning {}/{}", done, total)));
+                    ctx.request_repaint_after(Duration::from_millis(100));
+                }
+                changed |= Self::show_filter(ui, library, &mut self.new_collection, profiles);
+                ui.separator();
+
+                if library.entries.is_empty() && library.scan_progress().is_none() {
+                    ui.label(format!("No games in {}", crate::config::games_dir().display()));
+                    return;
+                }
+
+                let mut entries: Vec<&LibraryEntry> = library.entries.iter()
+                    .filter(|entry| (self.show_hidden || !entry.meta.hidden) && library.is_listed(entry))
+                    .collect();
+                entries.sort_by_key(|entry| !entry.meta.favorite);
+
+                let mut favorite = None;
+
+                egui::ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                    egui::Grid::new("games_list").num_columns(4).striped(true).show(ui, |ui| {
+                        for entry in entries {
+                            let key = entry.key();
+
+                            let star = if entry.meta.favorite { FAVORITE } else { NOT_FAVORITE };
+                            let response = ui.selectable_label(false, star).on_hover_text("Favorite");
+                            accessibility::name_toggle(&response, entry.meta.favorite, "Favorite");
+                            if response.clicked() {
+                                favorite = Some(entry.key().to_string());
+                            }
+
+                            let mut title = RichText::new(entry.title());
+                            if entry.discs.iter().any(|disc| Some(disc.as_str()) == current) {
+                                title = title.strong();
+                            }
+                            if entry.meta.hidden {
+                                title = title.weak();
+                            }
+                            ui.label(title).on_hover_ui(|ui| {
+                                if let Some(texture) = self.thumbnails.latest(ui.ctx(), &entry.discs) {
+                                    ui.image((texture.id(), texture.size_vec2()));
+                                }
+                                ui.label(entry.discs.join("\n"));
+                            });
+
+                            let disc = self.discs.entry(key.to_string()).or_default();
+                            *disc = (*disc).min(entry.discs.len() - 1);
+                            if entry.discs.len() > 1 {
+                                egui::ComboBox::from_id_salt(("disc", key))
+                                    .selected_text(format!("Disc {}", *disc + 1))
+                                    .show_ui(ui, |ui| {
+                                        for i in 0..entry.discs.len() {
+                                            ui.selectable_value(disc, i, format!("Disc {}", i + 1));
+                                        }
+                                    });
+                            } else {
+                                ui.label("");
+                            }
+
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(can_launch, egui::Button::new("Play")).clicked() {
+                                    launch = Some(entry.discs[*disc].clone());
+                                }
+                                if ui.button("Edit").clicked() {
+                                    self.edit = Some(Edit::new(entry));
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                if let Some(key) = favorite
+                    && let Some(entry) = library.entry(&key)
+                {
+                    let meta = GameMeta { favorite: !entry.meta.favorite, ..entry.meta.clone() };
+                    library.set_meta(&key, meta);
+                    changed = true;
+                }
+
+                changed |= Self::show_edit(ui, library, &mut self.edit, profiles);
+            });
+
+        self.open = open;
+
+        if changed && let Some(library) = &self.library
+            && let Err(e) = library.save()
+        {
+            error!("Failed to save the library: {}", e);
+        }
+
+        launch
+    }
+
+    /// Note that `game` was started, for the unplayed filter
+    pub fn record_played(&mut self, game: &str) {
+        let result = match &mut self.library {
+            Some(library) => {
+                library.record_played(game);
+                library.save()
+            }
+            // No need to scan the games for that
+            None => {
+                let mut store = LibraryStore::load();
+                store.record_played(game);
+                store.save()
+            }
+        };
+
+        if let Err(e) = result {
+            error!("Failed to save the library: {}", e);
+        }
+    }
+
+    /// Binding profile to switch to when `game` starts, if one was picked for it or its genre
+    pub fn input_profile(&self, game: &str) -> Option<String> {
+        match &self.library {
+            Some(library) => library.input_profile(game),
+            // The discs grouped by name aren't known without a scan, only their first one is
+            // found there
+            None => {
+                let store = LibraryStore::load();
+                store.games.get(game).and_then(|meta| store.input_profile(meta))
+            }
+        }
+    }
+
+    /// Returns true when the library changed
+    fn show_filter(
+        ui: &mut egui::Ui,
+        library: &mut Library,
+        new_collection: &mut String,
+        profiles: &[String],
+    ) -> bool {
+        let mut filter = library.filter().clone();
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Collection")
+                .selected_text(filter.collection.as_deref().unwrap_or("All games"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut filter.collection, None, "All games");
+                    for name in library.collections() {
+                        ui.selectable_value(&mut filter.collection, Some(name.clone()), name);
+                    }
+                });
+
+            egui::ComboBox::from_label("Region")
+                .selected_text(filter.region.as_deref().unwrap_or("Any"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut filter.region, None, "Any");
+                    for region in library.regions() {
+                        ui.selectable_value(&mut filter.region, Some(region.to_string()), region);
+                    }
+                });
+
+            let genres = library.genres();
+            if !genres.is_empty() || filter.genre.is_some() {
+                egui::ComboBox::from_label("Genre")
+                    .selected_text(filter.genre.as_deref().unwrap_or("Any"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut filter.genre, None, "Any");
+                        for genre in genres {
+                            ui.selectable_value(&mut filter.genre, Some(genre.clone()), genre);
+                        }
+                    });
+            }
+
+            ui.checkbox(&mut filter.unplayed, "Unplayed");
+        });
+
+        if let Some(genre) = &filter.genre {
+            let current = library.genre_profile(genre).cloned();
+            let mut selected = current.clone();
+            ui.horizontal(|ui| {
+                profile_combo(ui, "genre_profile", &mut selected, profiles, "Default bindings");
+                ui.label(format!("Controls of the {} games", genre));
+            });
+            if selected != current {
+                library.set_genre_profile(genre, selected);
+                changed = true;
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(new_collection).hint_text("New collection").desired_width(160.0));
+            let name = new_collection.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Add")).clicked() {
+                library.add_collection(&name);
+                filter.collection = Some(name);
+                new_collection.clear();
+                changed = true;
+            }
+
+            if let Some(name) = filter.collection.clone()
+                && ui.button(format!("Delete \"{}\"", name)).clicked()
+            {
+                library.remove_collection(&name);
+                filter.collection = None;
+                changed = true;
+            }
+        });
+
+        if filter != *library.filter() {
+            library.set_filter(filter);
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Returns true when the library changed
+    fn show_edit(ui: &mut egui::Ui, library: &mut Library, edit: &mut Option<Edit>, profiles: &[String]) -> bool {
+        let Some(current) = edit else {
+            return false;
+        };
+        let Some(entry) = library.entry(&current.key).cloned() else {
+            // Gone with a rescan or merged into another entry
+            *edit = None;
+            return false;
+        };
+
+        let mut changed = false;
+        let mut close = false;
+
+        ui.separator();
+        ui.heading(entry.title());
+
+        egui::Grid::new("game_meta").num_columns(2).show(ui, |ui| {
+            ui.label("Title");
+            ui.add(egui::TextEdit::singleline(&mut current.title).hint_text(entry.file_title()));
+            ui.end_row();
+
+            ui.label("Sort as");
+            ui.add(egui::TextEdit::singleline(&mut current.sort_title).hint_text("Same as the title"));
+            ui.end_row();
+
+            ui.label("Genre");
+            ui.text_edit_singleline(&mut current.genre);
+            ui.end_row();
+
+            ui.label("Controls");
+            profile_combo(ui, "game_profile", &mut current.meta.input_profile, profiles, "Same as the genre");
+            ui.end_row();
+        });
+        ui.checkbox(&mut current.meta.favorite, "Favorite");
+        ui.checkbox(&mut current.meta.hidden, "Hidden")
+            .on_hover_text("Only listed with Show hidden");
+
+        let collections: Vec<String> = library.collections().cloned().collect();
+        if !collections.is_empty() {
+            ui.label("Collections");
+            ui.horizontal_wrapped(|ui| {
+                for name in collections {
+                    let mut member = library.in_collection(&name, entry.key());
+                    if ui.checkbox(&mut member, &name).changed() {
+                        library.set_in_collection(&name, entry.key(), member);
+                        changed = true;
+                    }
+                }
+            });
+        }
+
+        ui.label("Discs");
+        for (i, disc) in entry.discs.iter().enumerate() {
+            let crc32: Vec<String> = library.hashes(disc)
+                .map(|hashes| hashes.crc32.iter().map(|crc| format!("{:08x}", crc)).collect())
+                .unwrap_or_default();
+            ui.weak(format!("{}. {}", i + 1, disc))
+                .on_hover_text(format!("CRC32: {}", crc32.join(" ")));
+        }
+
+        ui.horizontal(|ui| {
+            let mut merge = None;
+            egui::ComboBox::from_id_salt("merge_discs")
+                .selected_text("Add discs of...")
+                .show_ui(ui, |ui| {
+                    for other in library.entries.iter().filter(|other| other.key() != entry.key()) {
+                        if ui.selectable_label(false, other.title()).clicked() {
+                            merge = Some(other.key().to_string());
+                        }
+                    }
+                });
+            if let Some(other) = merge {
+                library.merge(entry.key(), &other);
+                changed = true;
+            }
+
+            if ui.add_enabled(entry.discs.len() > 1, egui::Button::new("Split discs")).clicked() {
+                library.split(entry.key());
+                changed = true;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                library.set_meta(&current.key, current.meta());
+                changed = true;
+                close = true;
+            }
+            if ui.button("Cancel").clicked() {
+                close = true;
+            }
+        });
+
+        if close {
+            *edit = None;
+        }
+
+        changed
+    }
+}
+
+/// Picks one of the binding profiles, `None` shown as `none`
+fn profile_combo(ui: &mut egui::Ui, id: &str, selected: &mut Option<String>, profiles: &[String], none: &str) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(selected.as_deref().unwrap_or(none))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(selected, None, none);
+            for profile in profiles {
+                ui.selectable_value(selected, Some(profile.clone()), profile);
+            }
+        });
+}
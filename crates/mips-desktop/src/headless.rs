@@ -0,0 +1,122 @@
+//! Window-less frontend used on servers and CI machines where there's no display server (and
+//! usually no audio device either).
+
+use std::env;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{bail, Result};
+use tracing::info;
+use mips_core::{conformance, ConsoleManager, CpuFrame};
+use mips_core::input::ControllerType;
+use crate::audio;
+use crate::cli::CliArgs;
+
+/// Frames a conformance test program gets when `--frames` isn't given, ten minutes of emulated
+/// time
+const CONFORMANCE_FRAMES: u64 = 60 * 60 * 10;
+
+/// Destination for the frames produced by the emulator
+pub trait VideoSink {
+    fn present(&mut self, frame: CpuFrame);
+}
+
+/// Sink that only counts the frames it receives
+#[derive(Default)]
+pub struct NullVideo {
+    frames: u64,
+}
+
+impl NullVideo {
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+}
+
+impl VideoSink for NullVideo {
+    fn present(&mut self, _frame: CpuFrame) {
+        self.frames += 1;
+    }
+}
+
+pub fn run(cli: &CliArgs) -> Result<()> {
+    let sys_dir = env::current_dir()?;
+
+    if let Some(exe) = &cli.conformance {
+        return run_conformance(&sys_dir, exe, cli.frames.unwrap_or(CONFORMANCE_FRAMES));
+    }
+
+    let mut mips = ConsoleManager::new();
+    mips.load_game(sys_dir.as_path(), cli.game.as_deref())?;
+    mips.connect_device(0, ControllerType::DigitalPad);
+
+    let audio = audio::open(cli.audio)?;
+    let mut video = NullVideo::default();
+
+    info!("Running headless ({} frames)", match cli.frames {
+        Some(n) => n.to_string(),
+        None => "unlimited".to_string(),
+    });
+
+    const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    let start = Instant::now();
+    let mut next_frame = start;
+    let mut emulated: u64 = 0;
+    let mut duplicated: u64 = 0;
+
+    while cli.frames.is_none_or(|n| emulated < n) {
+        mips.refresh_devices();
+        duplicated += mips.update().duplicated as u64;
+
+        audio.enqueue(mips.take_audio());
+
+        if let Some(frame) = mips.get_frame() {
+            video.present(frame);
+        }
+
+        emulated += 1;
+
+        if !cli.unthrottled {
+            next_frame += FRAME_TIME;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                // We're running late, don't try to catch up with a burst of frames
+                next_frame = now;
+            }
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    info!(
+        "Headless run done: {} frames emulated ({} duplicated), {} presented in {:.2}s ({:.1} fps)",
+        emulated,
+        duplicated,
+        video.frame_count(),
+        elapsed,
+        emulated as f64 / elapsed.max(f64::EPSILON)
+    );
+
+    Ok(())
+}
+
+/// Run the test program `exe` as fast as possible and print its results. Fails if any test failed
+/// or the program didn't finish, for CI scripts.
+fn run_conformance(sys_dir: &Path, exe: &str, frames: u64) -> Result<()> {
+    let mut mips = ConsoleManager::new();
+    mips.load_game(sys_dir, Some(exe))?;
+
+    info!("Running conformance tests from {}", exe);
+    let report = conformance::run(&mut mips, u32::try_from(frames).unwrap_or(u32::MAX));
+
+    // The lines themselves are in the log
+    println!("{}: {}", exe, report);
+
+    if !report.success() {
+        bail!("Conformance tests failed: {}", exe);
+    }
+
+    Ok(())
+}
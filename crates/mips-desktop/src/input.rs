@@ -1,8 +1,11 @@
 use std::collections::HashMap;
+use std::time::{Instant, SystemTime};
 use egui::Key;
-use mips_core::input::{Button, ButtonQueue, ButtonState};
-use gilrs::{Gilrs, Button as GilrsButton, EventType};
-use tracing::info;
+use mips_core::input::{AnalogInput, Button, ButtonQueue, ButtonState, InputEvent};
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat};
+use gilrs::{Axis, Gilrs, GamepadId, Button as GilrsButton, EventType};
+use tracing::{info, warn};
+use crate::config::{PortDevice, TriggerSettings};
 
 pub struct InputManager {
     // Store key states for change detection
@@ -20,9 +23,10 @@ impl InputManager {
         let mut queue = Vec::new();
 
         ctx.input(|i| {
-            // Check all bound keys
+            // Check all bound keys. The keys held when the window loses focus are released, their
+            // release goes to the other window.
             for (key, button) in bindings.iter() {
-                let is_down = i.key_down(*key);
+                let is_down = i.focused && i.key_down(*key);
                 let was_down = self.key_states.get(key).copied().unwrap_or(false);
 
                 if is_down != was_down {
@@ -42,8 +46,81 @@ impl InputManager {
     }
 }
 
+/// The analog triggers, read from their depth instead of their press and release events
+pub const TRIGGERS: [GilrsButton; 2] = [GilrsButton::LeftTrigger2, GilrsButton::RightTrigger2];
+/// Frames in a pulse of a trigger set to pulse its button
+const PULSE_FRAMES: u32 = 8;
+
+/// Whether a trigger at `depth` (0 to 1) presses its button on `frame`. Past the threshold the
+/// button is held, or pressed for a part of each pulse that grows with the depth.
+pub fn trigger_down(depth: f32, settings: &TriggerSettings, frame: u32) -> bool {
+    if depth <= 0.0 || depth < settings.threshold {
+        return false;
+    }
+    if !settings.pulse {
+        return true;
+    }
+
+    let range = (1.0 - settings.threshold).max(f32::EPSILON);
+    let past = ((depth - settings.threshold) / range).min(1.0);
+    let on = ((past * PULSE_FRAMES as f32).ceil() as u32).max(1);
+
+    frame % PULSE_FRAMES < on
+}
+
+/// Apply a radial deadzone to a stick position (both axes in [-1, 1]). What's left outside of the
+/// deadzone is rescaled so that the output still covers the full range.
+pub fn apply_deadzone((x, y): (f32, f32), deadzone: f32) -> (f32, f32) {
+    let radius = (x * x + y * y).sqrt();
+    if radius <= deadzone {
+        return (0.0, 0.0);
+    }
+
+    let scale = ((radius - deadzone) / (1.0 - deadzone)).min(1.0) / radius;
+
+    (x * scale, y * scale)
+}
+
+/// Stick position from gilrs (up is positive) to the DualShock range (down is positive)
+fn stick_to_analog((x, y): (f32, f32)) -> (i16, i16) {
+    let axis = |v: f32| (v * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+
+    (axis(x), axis(-y))
+}
+
+/// Host device an input comes from, see `PortDevice`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source<'a> {
+    Keyboard,
+    Gamepad { uuid: &'a str, instance: usize },
+}
+
+/// Port the inputs of `source` go to: the one reading that device, or else the first one reading
+/// them all
+pub fn route(ports: &[PortDevice], source: Source) -> Option<usize> {
+    let reads = |device: &PortDevice| match (device, source) {
+        (PortDevice::Keyboard, Source::Keyboard) => true,
+        (PortDevice::Gamepad { uuid, instance, .. }, Source::Gamepad { uuid: from, instance: from_instance }) => {
+            uuid == from && *instance == from_instance
+        }
+        _ => false,
+    };
+
+    ports.iter().position(reads).or_else(|| ports.iter().position(|device| *device == PortDevice::All))
+}
+
 pub struct GamepadManager {
     pub(crate) gilrs: Option<Gilrs>,
+    /// Gamepad the sticks of the ports reading all the devices are read from, and their rumble
+    /// played on: the last one used
+    active: Option<GamepadId>,
+    /// Effects playing the rumble of each port and the motor strengths they were built for. They
+    /// stop when dropped.
+    rumble: [Option<(Effect, (u8, u8))>; 2],
+    /// Whether the triggers of each gamepad press their button, see `trigger_down`
+    triggers: HashMap<(GamepadId, GilrsButton), bool>,
+    /// Polls so far, for the trigger pulses
+    frame: u32,
 }
 
 impl GamepadManager {
@@ -59,35 +136,265 @@ impl GamepadManager {
             }
         };
 
-        Self { gilrs }
+        Self { gilrs, active: None, rumble: [None, None], triggers: HashMap::new(), frame: 0 }
+    }
+
+    /// The connected gamepads, for the ports to pick from
+    pub fn gamepads(&self) -> Vec<PortDevice> {
+        let Some(gilrs) = &self.gilrs else {
+            return Vec::new();
+        };
+
+        gilrs.gamepads()
+            .map(|(id, gamepad)| {
+                let (uuid, instance) = identify(gilrs, id);
+                PortDevice::Gamepad { uuid, instance, name: gamepad.name().to_string() }
+            })
+            .collect()
     }
 
-    pub fn poll_gamepad(&mut self, button_queue: &mut ButtonQueue, bindings: &HashMap<GilrsButton, Button>) {
+    /// The gamepad events since the last poll, in `inputs` by port, see `route`. Without
+    /// `presses` only the releases are passed on, so that the buttons held when the window loses
+    /// focus don't stay held. The analog triggers press their buttons as set in `triggers`, left
+    /// then right.
+    pub fn poll_gamepad(
+        &mut self,
+        ports: &[PortDevice],
+        inputs: &mut [Vec<InputEvent>],
+        bindings: &HashMap<GilrsButton, Button>,
+        presses: bool,
+        triggers: &[TriggerSettings; 2],
+    ) {
         let Some(gilrs) = &mut self.gilrs else {
             return;
         };
 
         // Process gamepad events
         while let Some(event) = gilrs.next_event() {
-            match event.event {
-                EventType::ButtonPressed(gilrs_button, _) => {
-                    if let Some(ps_button) = bindings.get(&gilrs_button) {
-                        button_queue.push((ButtonState::Pressed, *ps_button));
-                    }
-                }
-                EventType::ButtonReleased(gilrs_button, _) => {
-                    if let Some(ps_button) = bindings.get(&gilrs_button) {
-                        button_queue.push((ButtonState::Released, *ps_button));
-                    }
+            // gilrs timestamps the events with the system clock
+            let age = SystemTime::now().duration_since(event.time).unwrap_or_default();
+            let time = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+
+            if matches!(event.event, EventType::ButtonPressed(..) | EventType::AxisChanged(..)) {
+                self.active = Some(event.id);
+            }
+
+            let (state, gilrs_button) = match event.event {
+                EventType::ButtonPressed(gilrs_button, _) | EventType::ButtonReleased(gilrs_button, _)
+                    if TRIGGERS.contains(&gilrs_button) =>
+                {
+                    continue;
                 }
+                EventType::ButtonPressed(gilrs_button, _) if presses => (ButtonState::Pressed, gilrs_button),
+                EventType::ButtonReleased(gilrs_button, _) => (ButtonState::Released, gilrs_button),
                 EventType::Connected => {
                     info!("Gamepad connected");
+                    continue;
                 }
                 EventType::Disconnected => {
                     info!("Gamepad disconnected");
+                    if self.active == Some(event.id) {
+                        self.active = None;
+                    }
+                    self.rumble = [None, None];
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let (uuid, instance) = identify(gilrs, event.id);
+            if let Some(&button) = bindings.get(&gilrs_button)
+                && let Some(port) = route(ports, Source::Gamepad { uuid: &uuid, instance })
+            {
+                inputs[port].push(InputEvent { state, button, time });
+            }
+        }
+
+        self.frame = self.frame.wrapping_add(1);
+        let time = Instant::now();
+        for (id, gamepad) in gilrs.gamepads() {
+            let (uuid, instance) = identify(gilrs, id);
+            let port = route(ports, Source::Gamepad { uuid: &uuid, instance });
+
+            for (trigger, settings) in TRIGGERS.into_iter().zip(triggers) {
+                let depth = gamepad.button_data(trigger).map_or(0.0, |data| data.value());
+                let down = presses && trigger_down(depth, settings, self.frame);
+                let was_down = self.triggers.insert((id, trigger), down).unwrap_or(false);
+
+                if down != was_down
+                    && let Some(&button) = bindings.get(&trigger)
+                    && let Some(port) = port
+                {
+                    let state = if down { ButtonState::Pressed } else { ButtonState::Released };
+                    inputs[port].push(InputEvent { state, button, time });
+                }
+            }
+        }
+    }
+
+    /// Buttons pressed since the last poll, for the UI while the game isn't being played
+    pub fn poll_ui(&mut self) -> Vec<GilrsButton> {
+        let Some(gilrs) = &mut self.gilrs else {
+            return Vec::new();
+        };
+
+        let mut pressed = Vec::new();
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    self.active = Some(event.id);
+                    pressed.push(button);
+                }
+                EventType::Disconnected => {
+                    if self.active == Some(event.id) {
+                        self.active = None;
+                    }
+                    self.rumble = [None, None];
                 }
                 _ => {}
             }
         }
+
+        pressed
+    }
+
+    /// Gamepad a port reading `device` follows the sticks of and rumbles
+    fn gamepad_of(&self, device: &PortDevice) -> Option<GamepadId> {
+        let gilrs = self.gilrs.as_ref()?;
+
+        match device {
+            PortDevice::All => self.active,
+            PortDevice::Gamepad { uuid, instance, .. } => gilrs.gamepads()
+                .map(|(id, _)| id)
+                .find(|&id| identify(gilrs, id) == (uuid.clone(), *instance)),
+            PortDevice::Keyboard | PortDevice::None => None,
+        }
+    }
+
+    /// Sticks of the gamepad of `device` with their deadzones applied, centered without one
+    pub fn poll_sticks(&self, device: &PortDevice, left_deadzone: f32, right_deadzone: f32) -> AnalogInput {
+        let Some((gilrs, id)) = self.gilrs.as_ref().zip(self.gamepad_of(device)) else {
+            return AnalogInput::default();
+        };
+        let Some(gamepad) = gilrs.connected_gamepad(id) else {
+            return AnalogInput::default();
+        };
+
+        let left = (gamepad.value(Axis::LeftStickX), gamepad.value(Axis::LeftStickY));
+        let right = (gamepad.value(Axis::RightStickX), gamepad.value(Axis::RightStickY));
+
+        AnalogInput {
+            left: stick_to_analog(apply_deadzone(left, left_deadzone)),
+            right: stick_to_analog(apply_deadzone(right, right_deadzone)),
+        }
+    }
+
+    /// Stop the rumble of all the ports
+    pub fn stop_rumble(&mut self) {
+        self.rumble = [None, None];
+    }
+
+    /// Play the rumble of `port`, `(big, small)` motor strengths, on the gamepad of `device` scaled
+    /// by `strength` (0 to 1). `(0, 0)` stops it.
+    pub fn set_rumble(&mut self, port: usize, device: &PortDevice, motors: (u8, u8), strength: f32) {
+        let motors = if strength > 0.0 { motors } else { (0, 0) };
+        if self.rumble[port].as_ref().map_or((0, 0), |(_, m)| *m) == motors {
+            return;
+        }
+
+        // Replacing the effect stops the previous one
+        self.rumble[port] = None;
+        if motors == (0, 0) {
+            return;
+        }
+
+        let id = self.gamepad_of(device);
+        let Some((gilrs, id)) = self.gilrs.as_mut().zip(id) else {
+            return;
+        };
+        if !gilrs.connected_gamepad(id).is_some_and(|g| g.is_ff_supported()) {
+            return;
+        }
+
+        let magnitude = |m: u8| (f32::from(m) / 255.0 * strength.min(1.0) * u16::MAX as f32) as u16;
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong { magnitude: magnitude(motors.0) },
+                ..Default::default()
+            })
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Weak { magnitude: magnitude(motors.1) },
+                ..Default::default()
+            })
+            .gamepads(&[id])
+            .repeat(Repeat::Infinitely)
+            .finish(gilrs)
+            .and_then(|effect| effect.play().map(|()| effect));
+
+        match effect {
+            Ok(effect) => self.rumble[port] = Some((effect, motors)),
+            Err(e) => warn!("Failed to start rumble: {}", e),
+        }
     }
-}
\ No newline at end of file
+}
+
+/// UUID of the model of gamepad `id`, and how many of that model were connected before it
+fn identify(gilrs: &Gilrs, id: GamepadId) -> (String, usize) {
+    let uuid = gilrs.gamepad(id).uuid();
+    let instance = gilrs.gamepads()
+        .filter(|(other, gamepad)| usize::from(*other) < usize::from(id) && gamepad.uuid() == uuid)
+        .count();
+
+    (uuid.iter().map(|b| format!("{:02x}", b)).collect(), instance)
+}
+
+#[test]
+fn deadzone() {
+    assert_eq!(apply_deadzone((0.05, -0.05), 0.1), (0.0, 0.0));
+
+    let (x, y) = apply_deadzone((1.0, 0.0), 0.2);
+    assert_eq!((x, y), (1.0, 0.0));
+
+    // Halfway between the deadzone and the edge
+    let (x, _) = apply_deadzone((0.6, 0.0), 0.2);
+    assert!((x - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn stick_axes() {
+    assert_eq!(stick_to_analog((0.0, 0.0)), (0, 0));
+    // Pushed up and right
+    assert_eq!(stick_to_analog((1.0, 1.0)), (i16::MAX, -i16::MAX));
+    assert_eq!(stick_to_analog((-1.0, -1.0)), (-i16::MAX, i16::MAX));
+}
+
+#[test]
+fn port_routing() {
+    let pad = |instance| PortDevice::Gamepad { uuid: "0300".to_string(), instance, name: "Pad".to_string() };
+    let ports = [PortDevice::All, pad(1)];
+
+    assert_eq!(route(&ports, Source::Keyboard), Some(0));
+    assert_eq!(route(&ports, Source::Gamepad { uuid: "0300", instance: 0 }), Some(0));
+    assert_eq!(route(&ports, Source::Gamepad { uuid: "0300", instance: 1 }), Some(1));
+
+    let ports = [pad(0), PortDevice::Keyboard];
+    assert_eq!(route(&ports, Source::Keyboard), Some(1));
+    assert_eq!(route(&ports, Source::Gamepad { uuid: "0300", instance: 1 }), None);
+}
+
+#[test]
+fn trigger_pulses() {
+    let held = TriggerSettings { threshold: 0.5, pulse: false };
+    assert!(!trigger_down(0.4, &held, 0));
+    assert!(trigger_down(0.5, &held, 0));
+
+    // Just past the threshold the button is pressed one frame of each pulse, all of them at the end
+    let pulsed = TriggerSettings { threshold: 0.5, pulse: true };
+    let frames = |depth| (0..PULSE_FRAMES).filter(|&frame| trigger_down(depth, &pulsed, frame)).count();
+    assert_eq!(frames(0.51), 1);
+    assert_eq!(frames(0.75), PULSE_FRAMES as usize / 2);
+    assert_eq!(frames(1.0), PULSE_FRAMES as usize);
+
+    let zero = TriggerSettings { threshold: 0.0, pulse: false };
+    assert!(!trigger_down(0.0, &zero, 0));
+}
@@ -0,0 +1,249 @@
+//! A/V latency calibration: beeps or flashes on a steady beat while the player taps along, and
+//! measures how late the taps land. The offsets are kept in the input settings; the core latches
+//! the gamepad buttons that much earlier when compensation is on, for rhythm games (PaRappa, Vib
+//! Ribbon...). Reads gilrs directly, the emulator is paused while the panel is open.
+
+use std::time::{Duration, Instant, SystemTime};
+use egui::{Color32, Key, Sense, Vec2};
+use gilrs::{EventType, Gilrs};
+use mips_core::AudioChunk;
+use crate::audio::AudioSink;
+use crate::config::InputSettings;
+
+const BEAT_INTERVAL: Duration = Duration::from_millis(750);
+/// Time to get ready before the first beat
+const LEAD_IN: Duration = Duration::from_secs(2);
+const BEATS: usize = 12;
+/// Beats played before the taps count, to find the rhythm
+const WARMUP_BEATS: usize = 2;
+/// Taps needed on the counted beats for a measurement
+const MIN_TAPS: usize = (BEATS - WARMUP_BEATS) / 2;
+
+const BEEP_DURATION: Duration = Duration::from_millis(40);
+const BEEP_FREQ_HZ: f32 = 1000.0;
+const FLASH_DURATION: Duration = Duration::from_millis(80);
+const SAMPLE_RATE: u32 = 44100;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Audio,
+    Video,
+}
+
+struct Run {
+    mode: Mode,
+    started: Instant,
+    /// When each beat was played or shown so far
+    beats: Vec<Instant>,
+    taps: Vec<Instant>,
+}
+
+impl Run {
+    fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            started: Instant::now(),
+            beats: Vec::with_capacity(BEATS),
+            taps: Vec::new(),
+        }
+    }
+
+    fn next_beat(&self) -> Option<Instant> {
+        (self.beats.len() < BEATS).then(|| self.started + LEAD_IN + BEAT_INTERVAL * self.beats.len() as u32)
+    }
+
+    fn finished(&self, now: Instant) -> bool {
+        self.beats.len() == BEATS && self.beats.last().is_some_and(|&last| now >= last + BEAT_INTERVAL)
+    }
+}
+
+#[derive(Default)]
+pub struct LatencyCalibration {
+    pub open: bool,
+    run: Option<Run>,
+    status: Option<String>,
+}
+
+impl LatencyCalibration {
+    /// Returns true when the settings changed and should be saved
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        gilrs: Option<&mut Gilrs>,
+        audio: &dyn AudioSink,
+        settings: &mut InputSettings,
+    ) -> bool {
+        if !self.open {
+            self.run = None;
+            return false;
+        }
+
+        let now = Instant::now();
+        let mut open = self.open;
+        let mut save = false;
+
+        if let Some(run) = &mut self.run {
+            if let Some(gilrs) = gilrs {
+                while let Some(event) = gilrs.next_event() {
+                    if let EventType::ButtonPressed(..) = event.event {
+                        // gilrs timestamps the events with the system clock
+                        let age = SystemTime::now().duration_since(event.time).unwrap_or_default();
+                        run.taps.push(now.checked_sub(age).unwrap_or(now));
+                    }
+                }
+            }
+            // egui doesn't timestamp key events, they're at most a frame late
+            if ctx.input(|i| i.key_pressed(Key::Space)) {
+                run.taps.push(now);
+            }
+
+            if run.next_beat().is_some_and(|beat| now >= beat) {
+                run.beats.push(now);
+                if run.mode == Mode::Audio {
+                    audio.enqueue(beep());
+                }
+            }
+        }
+
+        egui::Window::new("Latency Calibration")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Tap a gamepad button or Space in time with the beeps or the flashes. The \
+                     offsets measured are how late you react to the sound and the picture on \
+                     this setup.",
+                );
+                ui.separator();
+
+                ui.label(format!("Audio offset: {:.0} ms", settings.audio_latency_ms));
+                ui.label(format!("Video offset: {:.0} ms", settings.video_latency_ms));
+                ui.separator();
+
+                match &self.run {
+                    None => {
+                        ui.horizontal(|ui| {
+                            if ui.button("Measure Audio").clicked() {
+                                self.run = Some(Run::new(Mode::Audio));
+                                self.status = None;
+                            }
+                            if ui.button("Measure Video").clicked() {
+                                self.run = Some(Run::new(Mode::Video));
+                                self.status = None;
+                            }
+                        });
+                    }
+                    Some(run) => {
+                        let flash = run.mode == Mode::Video
+                            && run.beats.last().is_some_and(|&beat| now < beat + FLASH_DURATION);
+                        let (rect, _) = ui.allocate_exact_size(Vec2::new(240.0, 120.0), Sense::hover());
+                        let color = if flash { Color32::WHITE } else { ui.visuals().extreme_bg_color };
+                        ui.painter().rect_filled(rect, 4.0, color);
+
+                        match run.beats.len() {
+                            0 => ui.label("Get ready..."),
+                            n => ui.label(format!("Beat {} of {}", n, BEATS)),
+                        };
+                        if ui.button("Cancel").clicked() {
+                            self.run = None;
+                        }
+                    }
+                }
+
+                if let Some(status) = &self.status {
+                    ui.separator();
+                    ui.label(status);
+                }
+            });
+
+        if let Some(run) = self.run.take_if(|run| run.finished(now)) {
+            match measure(&run.beats, &run.taps) {
+                Some(offset) => {
+                    let target = match run.mode {
+                        Mode::Audio => &mut settings.audio_latency_ms,
+                        Mode::Video => &mut settings.video_latency_ms,
+                    };
+                    *target = offset;
+                    save = true;
+                    self.status = Some(format!("Measured {:.0} ms", offset));
+                }
+                None => {
+                    self.status = Some("Not enough taps in time with the beat, try again".to_string());
+                }
+            }
+        }
+
+        self.open = open;
+
+        // Beats are placed on the frame they're due
+        ctx.request_repaint_after(Duration::from_millis(4));
+
+        save
+    }
+}
+
+/// Short sine burst, 44.1 kHz stereo
+fn beep() -> AudioChunk {
+    let frames = (SAMPLE_RATE as f32 * BEEP_DURATION.as_secs_f32()) as usize;
+
+    let samples = (0..frames)
+        .flat_map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let sample = (t * BEEP_FREQ_HZ * std::f32::consts::TAU).sin() * 0.5 * i16::MAX as f32;
+            [sample as i16; 2]
+        })
+        .collect();
+
+    AudioChunk { timestamp: 0, samples }
+}
+
+/// Median offset of the taps from the beat they're closest to, in milliseconds (positive when the
+/// taps are late). Taps on the warm-up beats or more than half a beat away are ignored.
+fn measure(beats: &[Instant], taps: &[Instant]) -> Option<f32> {
+    let signed_ms = |from: Instant, to: Instant| {
+        if to >= from {
+            (to - from).as_secs_f32() * 1000.0
+        } else {
+            -(from - to).as_secs_f32() * 1000.0
+        }
+    };
+    let max_offset = BEAT_INTERVAL.as_secs_f32() * 1000.0 / 2.0;
+
+    let mut offsets: Vec<f32> = taps.iter()
+        .filter_map(|&tap| {
+            let (index, offset) = beats.iter()
+                .map(|&beat| signed_ms(beat, tap))
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))?;
+
+            (index >= WARMUP_BEATS && offset.abs() < max_offset).then_some(offset)
+        })
+        .collect();
+
+    if offsets.len() < MIN_TAPS {
+        return None;
+    }
+
+    offsets.sort_by(f32::total_cmp);
+    Some(offsets[offsets.len() / 2])
+}
+
+#[test]
+fn measure_offsets() {
+    let start = Instant::now();
+    let beats: Vec<Instant> = (0..BEATS as u32).map(|i| start + BEAT_INTERVAL * i).collect();
+
+    // Late by 60 ms, with a stray tap and one on a warm-up beat
+    let mut taps: Vec<Instant> = beats[WARMUP_BEATS..].iter().map(|&b| b + Duration::from_millis(60)).collect();
+    taps.push(beats[5] + Duration::from_millis(300));
+    taps.push(beats[0]);
+    let offset = measure(&beats, &taps).unwrap();
+    assert!((offset - 60.0).abs() < 1.0);
+
+    // Early taps give a negative offset
+    let taps: Vec<Instant> = beats[WARMUP_BEATS..].iter().map(|&b| b - Duration::from_millis(20)).collect();
+    let offset = measure(&beats, &taps).unwrap();
+    assert!((offset + 20.0).abs() < 1.0);
+
+    assert_eq!(measure(&beats, &taps[..2]), None);
+}
@@ -0,0 +1,525 @@
+//! Game library: the disc images and executables found in the games directory, with what the user
+//! set for them (titles, favorites, hidden entries, multi-disc groups, collections) kept in
+//! `config/library.toml` rather than guessed from the file names every time.
+//!
+//! Files are named by their path relative to the games directory, the way `load_game` takes them.
+//! An entry is one game, with one file per disc. The directory is watched while the library is
+//! loaded, it's scanned again when files are added, removed or changed.
+
+mod scan;
+mod watch;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use tracing::{info, warn};
+use crate::config;
+
+pub use scan::{GameHashes, HashCache, ScanMode};
+use scan::Scan;
+use watch::Watcher;
+
+/// Regions of the Redump and No-Intro names, in parentheses after the title: "(USA)",
+/// "(Europe, Australia)"...
+const REGIONS: [&str; 14] = [
+    "USA", "Europe", "Japan", "World", "Asia", "Australia", "Brazil", "Canada", "France",
+    "Germany", "Italy", "Korea", "Spain", "UK",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameMeta {
+    /// Shown instead of the name derived from the file
+    pub title: Option<String>,
+    /// Sorted under this instead of the title ("Legend of Dragoon, The")
+    pub sort_title: Option<String>,
+    /// Entered by hand or scraped
+    pub genre: Option<String>,
+    pub favorite: bool,
+    pub hidden: bool,
+    /// Binding profile switched to when the game starts, instead of the one of its genre
+    pub input_profile: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryStore {
+    /// By the first file of the entry
+    pub games: BTreeMap<String, GameMeta>,
+    /// Discs put together (or apart) by hand, in disc order. The files in no group are grouped
+    /// by name.
+    pub groups: Vec<Vec<String>>,
+    /// Entries picked by the user, by name
+    pub collections: BTreeMap<String, Vec<String>>,
+    /// When each file was last started, in seconds since the Unix epoch
+    pub last_played: BTreeMap<String, u64>,
+    /// Entries listed the last time
+    pub filter: LibraryFilter,
+    /// Binding profile of the games of each genre without one of their own
+    pub genre_profiles: BTreeMap<String, String>,
+}
+
+impl LibraryStore {
+    pub fn load() -> LibraryStore {
+        let path = config::library_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).unwrap_or_else(|e| {
+                warn!("Failed to parse {}: {}. Starting from scratch.", path.display(), e);
+                LibraryStore::default()
+            }),
+            Err(_) => LibraryStore::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = config::library_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, toml::to_string_pretty(self)?)?;
+        info!("Saved library to {}", path.display());
+        Ok(())
+    }
+
+    pub fn record_played(&mut self, file: &str) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.last_played.insert(file.to_string(), now.as_secs());
+    }
+
+    /// Binding profile picked for the game `meta` is of, by hand or for its genre
+    pub fn input_profile(&self, meta: &GameMeta) -> Option<String> {
+        meta.input_profile.clone()
+            .or_else(|| meta.genre.as_ref().and_then(|genre| self.genre_profiles.get(genre)).cloned())
+    }
+}
+
+/// Which entries are listed, everything by default
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryFilter {
+    pub collection: Option<String>,
+    pub region: Option<String>,
+    pub genre: Option<String>,
+    /// Only the games never started
+    pub unplayed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryEntry {
+    /// One file per disc, in order
+    pub discs: Vec<String>,
+    pub meta: GameMeta,
+}
+
+impl LibraryEntry {
+    /// Name the entry is stored under
+    pub fn key(&self) -> &str {
+        &self.discs[0]
+    }
+
+    pub fn title(&self) -> String {
+        self.meta.title.clone().unwrap_or_else(|| self.file_title())
+    }
+
+    /// Title made from the file name, without the disc number
+    pub fn file_title(&self) -> String {
+        split_disc_tag(&file_stem(self.key())).0
+    }
+
+    /// Regions in the file name of the first disc
+    pub fn regions(&self) -> Vec<&'static str> {
+        let stem = file_stem(self.key());
+        let tags = stem.split('(').skip(1).filter_map(|tag| tag.split_once(')')).map(|(tag, _)| tag);
+
+        tags.flat_map(|tag| tag.split(',').map(str::trim))
+            .filter_map(|name| REGIONS.iter().copied().find(|&region| region == name))
+            .collect()
+    }
+
+    fn sort_key(&self) -> String {
+        self.meta.sort_title.clone().unwrap_or_else(|| self.title()).to_lowercase()
+    }
+}
+
+pub struct Library {
+    store: LibraryStore,
+    files: Vec<String>,
+    hashes: HashCache,
+    /// Sorted by title
+    pub entries: Vec<LibraryEntry>,
+    scan: Option<Scan>,
+    watcher: Watcher,
+}
+
+impl Library {
+    /// Read the store and start scanning and watching the games directory
+    pub fn load() -> Library {
+        let mut library = Library {
+            store: LibraryStore::load(),
+            files: Vec::new(),
+            hashes: HashCache::load(),
+            entries: Vec::new(),
+            scan: None,
+            watcher: Watcher::start(config::games_dir()),
+        };
+        library.rescan(ScanMode::ChangedOnly);
+        library
+    }
+
+    /// Scan the games directory in the background, unless it's already being scanned. The
+    /// entries are updated by `poll` once it's done.
+    pub fn rescan(&mut self, mode: ScanMode) {
+        if self.scan.is_none() {
+            self.scan = Some(Scan::start(config::games_dir(), self.hashes.clone(), mode));
+        }
+    }
+
+    /// Take the result of the scan if it's done, returns true if the entries changed. Starts a
+    /// scan if the games directory changed.
+    pub fn poll(&mut self) -> bool {
+        // A change seen during a scan waits for it to finish, the scan may have listed the
+        // directory before it
+        if self.scan.is_none() && self.watcher.take_change() {
+            info!("Games directory changed, scanning it again");
+            self.rescan(ScanMode::ChangedOnly);
+        }
+
+        if !self.scan.as_ref().is_some_and(Scan::is_finished) {
+            return false;
+        }
+
+        let (files, hashes) = self.scan.take().unwrap().join();
+        self.files = files;
+        self.hashes = hashes;
+        if let Err(e) = self.hashes.save() {
+            warn!("Failed to save the game hashes: {}", e);
+        }
+        self.update_entries();
+
+        true
+    }
+
+    /// Games hashed and games found while scanning
+    pub fn scan_progress(&self) -> Option<(usize, usize)> {
+        self.scan.as_ref().map(Scan::progress)
+    }
+
+    pub fn hashes(&self, file: &str) -> Option<&GameHashes> {
+        self.hashes.games.get(file)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.store.save()
+    }
+
+    pub fn record_played(&mut self, file: &str) {
+        self.store.record_played(file);
+    }
+
+    pub fn entry(&self, key: &str) -> Option<&LibraryEntry> {
+        self.entries.iter().find(|entry| entry.key() == key)
+    }
+
+    /// Binding profile of the entry `file` is a disc of, see `LibraryStore::input_profile`
+    pub fn input_profile(&self, file: &str) -> Option<String> {
+        let entry = self.entries.iter().find(|entry| entry.discs.iter().any(|disc| disc == file))?;
+        self.store.input_profile(&entry.meta)
+    }
+
+    pub fn genre_profile(&self, genre: &str) -> Option<&String> {
+        self.store.genre_profiles.get(genre)
+    }
+
+    pub fn set_genre_profile(&mut self, genre: &str, profile: Option<String>) {
+        match profile {
+            Some(profile) => self.store.genre_profiles.insert(genre.to_string(), profile),
+            None => self.store.genre_profiles.remove(genre),
+        };
+    }
+
+    pub fn set_meta(&mut self, key: &str, meta: GameMeta) {
+        if meta == GameMeta::default() {
+            self.store.games.remove(key);
+        } else {
+            self.store.games.insert(key.to_string(), meta);
+        }
+        self.update_entries();
+    }
+
+    /// Add the discs of entry `other` after those of entry `key`
+    pub fn merge(&mut self, key: &str, other: &str) {
+        let (Some(entry), Some(other)) = (self.entry(key), self.entry(other)) else {
+            return;
+        };
+        let discs: Vec<String> = entry.discs.iter().chain(&other.discs).cloned().collect();
+
+        let other = other.key().to_string();
+        self.store.groups.retain(|group| !group.iter().any(|file| discs.contains(file)));
+        self.store.groups.push(discs);
+        self.store.games.remove(&other);
+        for entries in self.store.collections.values_mut() {
+            if entries.contains(&other) {
+                entries.retain(|entry| *entry != other && entry != key);
+                entries.push(key.to_string());
+            }
+        }
+        self.update_entries();
+    }
+
+    /// Make each disc of entry `key` an entry of its own
+    pub fn split(&mut self, key: &str) {
+        let Some(entry) = self.entry(key) else {
+            return;
+        };
+        let discs = entry.discs.clone();
+
+        self.store.groups.retain(|group| !group.iter().any(|file| discs.contains(file)));
+        self.store.groups.extend(discs.into_iter().map(|disc| vec![disc]));
+        self.update_entries();
+    }
+
+    pub fn collections(&self) -> impl Iterator<Item = &String> {
+        self.store.collections.keys()
+    }
+
+    pub fn add_collection(&mut self, name: &str) {
+        self.store.collections.entry(name.to_string()).or_default();
+    }
+
+    pub fn remove_collection(&mut self, name: &str) {
+        self.store.collections.remove(name);
+        if self.store.filter.collection.as_deref() == Some(name) {
+            self.store.filter.collection = None;
+        }
+    }
+
+    pub fn in_collection(&self, name: &str, key: &str) -> bool {
+        self.store.collections.get(name).is_some_and(|entries| entries.iter().any(|entry| entry == key))
+    }
+
+    pub fn set_in_collection(&mut self, name: &str, key: &str, member: bool) {
+        let entries = self.store.collections.entry(name.to_string()).or_default();
+        entries.retain(|entry| entry != key);
+        if member {
+            entries.push(key.to_string());
+        }
+    }
+
+    pub fn filter(&self) -> &LibraryFilter {
+        &self.store.filter
+    }
+
+    pub fn set_filter(&mut self, filter: LibraryFilter) {
+        self.store.filter = filter;
+    }
+
+    /// Whether `entry` passes the current filter
+    pub fn is_listed(&self, entry: &LibraryEntry) -> bool {
+        let filter = &self.store.filter;
+
+        filter.collection.as_ref().is_none_or(|name| self.in_collection(name, entry.key()))
+            && filter.region.as_ref().is_none_or(|region| entry.regions().contains(&region.as_str()))
+            && filter.genre.as_ref().is_none_or(|genre| entry.meta.genre.as_ref() == Some(genre))
+            && (!filter.unplayed || !entry.discs.iter().any(|disc| self.store.last_played.contains_key(disc)))
+    }
+
+    /// Regions of the entries, sorted
+    pub fn regions(&self) -> Vec<&'static str> {
+        let mut regions: Vec<&'static str> = self.entries.iter().flat_map(LibraryEntry::regions).collect();
+        regions.sort();
+        regions.dedup();
+        regions
+    }
+
+    /// Genres of the entries, sorted
+    pub fn genres(&self) -> Vec<String> {
+        let mut genres: Vec<String> = self.entries.iter().filter_map(|entry| entry.meta.genre.clone()).collect();
+        genres.sort();
+        genres.dedup();
+        genres
+    }
+
+    fn update_entries(&mut self) {
+        self.entries = build_entries(&self.files, &self.store);
+    }
+}
+
+/// Discs of the game `game` (a file of the games directory) is on, in order: the files of its
+/// entry, found by listing its directory only, and one per disc of the multi-disc PBPs
+/// (`game.pbp#2`). Just `game` for the single disc games.
+pub fn game_discs(game: &str) -> Vec<String> {
+    let dir = game.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+    let mut files = Vec::new();
+    scan::list_games(&config::games_dir().join(dir), &prefix, 0, &mut files);
+
+    let files = build_entries(&files, &LibraryStore::load())
+        .into_iter()
+        .find(|entry| entry.discs.iter().any(|disc| disc == game))
+        .map_or_else(|| vec![game.to_string()], |entry| entry.discs);
+
+    files.into_iter()
+        .flat_map(|file| {
+            let count = mips_core::disc_count(&config::games_dir().join(&file)).unwrap_or(1);
+            (1..=count).map(move |n| if n == 1 { file.clone() } else { format!("{}#{}", file, n) })
+        })
+        .collect()
+}
+
+/// Group `files` into entries: the groups of the store first, the rest by name without the disc
+/// number
+fn build_entries(files: &[String], store: &LibraryStore) -> Vec<LibraryEntry> {
+    let mut groups: Vec<Vec<String>> = Vec::new();
+
+    for group in &store.groups {
+        // Some discs may be on a drive that isn't there today
+        let discs: Vec<String> = group.iter().filter(|file| files.contains(file)).cloned().collect();
+        if !discs.is_empty() {
+            groups.push(discs);
+        }
+    }
+
+    let mut by_name: BTreeMap<(String, String), Vec<(u32, String)>> = BTreeMap::new();
+    for file in files {
+        if store.groups.iter().any(|group| group.contains(file)) {
+            continue;
+        }
+
+        let dir = file.rsplit_once('/').map_or("", |(dir, _)| dir).to_string();
+        let (name, disc) = split_disc_tag(&file_stem(file));
+        by_name.entry((dir, name)).or_default().push((disc.unwrap_or(0), file.clone()));
+    }
+    for mut discs in by_name.into_values() {
+        discs.sort();
+        groups.push(discs.into_iter().map(|(_, file)| file).collect());
+    }
+
+    let mut entries: Vec<LibraryEntry> = groups.into_iter()
+        .map(|discs| {
+            let meta = store.games.get(&discs[0]).cloned().unwrap_or_default();
+            LibraryEntry { discs, meta }
+        })
+        .collect();
+    entries.sort_by_cached_key(|entry| (entry.sort_key(), entry.key().to_string()));
+
+    entries
+}
+
+fn file_stem(file: &str) -> String {
+    let name = file.rsplit_once('/').map_or(file, |(_, name)| name);
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem).to_string()
+}
+
+/// Take the "(Disc N)" tag of the Redump and No-Intro names out of `stem`, returns what's left
+/// and the disc number
+fn split_disc_tag(stem: &str) -> (String, Option<u32>) {
+    let lower = stem.to_ascii_lowercase();
+    let Some(start) = lower.find("(disc ") else {
+        return (stem.to_string(), None);
+    };
+    let Some(len) = lower[start..].find(')') else {
+        return (stem.to_string(), None);
+    };
+
+    // "(Disc 2)", "(Disc 2 of 3)"
+    let number = lower[start + 6..start + len]
+        .split_whitespace()
+        .next()
+        .and_then(|n| n.parse().ok());
+    if number.is_none() {
+        return (stem.to_string(), None);
+    }
+
+    let name = format!("{} {}", stem[..start].trim_end(), stem[start + len + 1..].trim_start());
+    (name.trim().to_string(), number)
+}
+
+#[test]
+fn multi_disc_entries() {
+    assert_eq!(split_disc_tag("Final Fantasy VII (USA) (Disc 2)"), ("Final Fantasy VII (USA)".to_string(), Some(2)));
+    assert_eq!(split_disc_tag("Metal Gear Solid (Disc 1 of 2) (Europe)"), ("Metal Gear Solid (Europe)".to_string(), Some(1)));
+    assert_eq!(split_disc_tag("Discworld (Europe)"), ("Discworld (Europe)".to_string(), None));
+
+    let files: Vec<String> = [
+        "Final Fantasy VII (USA) (Disc 2).cue",
+        "Final Fantasy VII (USA) (Disc 1).cue",
+        "Crash Bandicoot (USA).cue",
+        "Riven (USA) (Disc 1).cue",
+        "Riven (USA) (Disc 2).cue",
+        "psxtest_cpu.exe",
+    ].map(String::from).to_vec();
+
+    let mut store = LibraryStore::default();
+    store.groups.push(vec!["Riven (USA) (Disc 1).cue".to_string()]);
+    store.groups.push(vec!["Riven (USA) (Disc 2).cue".to_string()]);
+    store.games.insert("psxtest_cpu.exe".to_string(), GameMeta {
+        title: Some("CPU tests".to_string()),
+        sort_title: Some("zzz".to_string()),
+        ..GameMeta::default()
+    });
+
+    let entries = build_entries(&files, &store);
+    let discs: Vec<&[String]> = entries.iter().map(|entry| entry.discs.as_slice()).collect();
+    assert_eq!(discs, [
+        &files[2..3],
+        &[files[1].clone(), files[0].clone()][..],
+        &files[3..4],
+        &files[4..5],
+        &files[5..6],
+    ]);
+    assert_eq!(entries[1].title(), "Final Fantasy VII (USA)");
+    assert_eq!(entries[4].title(), "CPU tests");
+}
+
+#[test]
+fn filters() {
+    let files: Vec<String> = ["Ape Escape (USA).cue", "Vagrant Story (Europe, Australia).cue", "demo.exe"]
+        .map(String::from)
+        .to_vec();
+    let mut store = LibraryStore::default();
+    store.record_played("demo.exe");
+    store.games.insert(files[1].clone(), GameMeta { genre: Some("RPG".to_string()), ..GameMeta::default() });
+
+    let mut library = Library {
+        entries: build_entries(&files, &store),
+        store,
+        files,
+        hashes: HashCache::default(),
+        scan: None,
+    };
+    library.set_in_collection("Favorites of the week", "Ape Escape (USA).cue", true);
+    assert_eq!(library.regions(), ["Australia", "Europe", "USA"]);
+    assert_eq!(library.genres(), ["RPG"]);
+
+    let listed = |library: &Library| -> Vec<String> {
+        library.entries.iter().filter(|entry| library.is_listed(entry)).map(LibraryEntry::title).collect()
+    };
+    assert_eq!(listed(&library).len(), 3);
+
+    library.set_filter(LibraryFilter { unplayed: true, ..LibraryFilter::default() });
+    assert_eq!(listed(&library), ["Ape Escape (USA)", "Vagrant Story (Europe, Australia)"]);
+
+    library.set_filter(LibraryFilter { region: Some("Australia".to_string()), ..LibraryFilter::default() });
+    assert_eq!(listed(&library), ["Vagrant Story (Europe, Australia)"]);
+
+    library.set_filter(LibraryFilter { collection: Some("Favorites of the week".to_string()), ..LibraryFilter::default() });
+    assert_eq!(listed(&library), ["Ape Escape (USA)"]);
+    library.remove_collection("Favorites of the week");
+    assert_eq!(library.filter(), &LibraryFilter::default());
+}
+
+#[test]
+fn input_profiles() {
+    let mut store = LibraryStore::default();
+    store.genre_profiles.insert("Racing".to_string(), "Triggers as pedals".to_string());
+
+    let racing = GameMeta { genre: Some("Racing".to_string()), ..GameMeta::default() };
+    assert_eq!(store.input_profile(&racing).as_deref(), Some("Triggers as pedals"));
+
+    let own = GameMeta { input_profile: Some("Arcade stick".to_string()), ..racing };
+    assert_eq!(store.input_profile(&own).as_deref(), Some("Arcade stick"));
+
+    assert_eq!(store.input_profile(&GameMeta::default()), None);
+}
@@ -0,0 +1,274 @@
+//! Library scan: lists the games in the games directory and computes the CRC32 of their tracks,
+//! the hashes of the Redump database, on all cores. That means reading whole discs, so the hashes
+//! are cached in `config/library_cache.toml` and only computed again for the games whose files
+//! changed size or modification time, unless a full rescan is asked for.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::UNIX_EPOCH;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use tracing::{info, warn};
+use crate::config;
+
+/// Files that can be loaded, the others are data tracks and such
+const GAME_EXTENSIONS: [&str; 8] = ["cue", "zip", "exe", "pbp", "iso", "ecm", "bin", "img"];
+
+/// Raw images that are also the tracks of cue sheets, only listed when no sheet names them
+const TRACK_EXTENSIONS: [&str; 2] = ["bin", "img"];
+
+/// How many directories deep games are looked for, for games kept in a folder of their own
+pub(super) const MAX_DEPTH: usize = 2;
+
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameHashes {
+    /// Total size of the files of the game
+    pub size: u64,
+    /// Latest modification of the files, in seconds since the Unix epoch
+    pub modified: u64,
+    /// Of each track of a cue sheet, or of the file itself
+    pub crc32: Vec<u32>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HashCache {
+    /// By file, relative to the games directory
+    pub games: BTreeMap<String, GameHashes>,
+}
+
+impl HashCache {
+    pub fn load() -> HashCache {
+        let path = config::library_cache_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).unwrap_or_else(|e| {
+                warn!("Failed to parse {}: {}. The games will be hashed again.", path.display(), e);
+                HashCache::default()
+            }),
+            Err(_) => HashCache::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = config::library_cache_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanMode {
+    /// Only hash the games that changed since they were cached
+    ChangedOnly,
+    Full,
+}
+
+#[derive(Default)]
+struct Progress {
+    done: AtomicUsize,
+    total: AtomicUsize,
+}
+
+/// Scan running in the background
+pub struct Scan {
+    progress: Arc<Progress>,
+    handle: JoinHandle<(Vec<String>, HashCache)>,
+}
+
+impl Scan {
+    pub fn start(dir: PathBuf, cache: HashCache, mode: ScanMode) -> Scan {
+        let progress = Arc::new(Progress::default());
+
+        let thread_progress = progress.clone();
+        let handle = thread::spawn(move || run(&dir, &cache, mode, &thread_progress));
+
+        Scan { progress, handle }
+    }
+
+    /// Games done and games found
+    pub fn progress(&self) -> (usize, usize) {
+        (self.progress.done.load(Ordering::Relaxed), self.progress.total.load(Ordering::Relaxed))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// The files found, sorted, and their hashes
+    pub fn join(self) -> (Vec<String>, HashCache) {
+        self.handle.join().unwrap_or_else(|_| {
+            warn!("Library scan failed");
+            (Vec::new(), HashCache::default())
+        })
+    }
+}
+
+fn run(dir: &Path, cache: &HashCache, mode: ScanMode, progress: &Progress) -> (Vec<String>, HashCache) {
+    let mut files = Vec::new();
+    list_games(dir, "", MAX_DEPTH, &mut files);
+    files.sort();
+    progress.total.store(files.len(), Ordering::Relaxed);
+
+    let next = AtomicUsize::new(0);
+    let hashes = Mutex::new(BTreeMap::new());
+    let threads = thread::available_parallelism().map_or(1, NonZero::get).clamp(1, files.len().max(1));
+
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(i) else {
+                    break;
+                };
+
+                let cached = cache.games.get(file).filter(|_| mode == ScanMode::ChangedOnly);
+                match hash_game(&dir.join(file), cached) {
+                    Ok(game) => {
+                        hashes.lock().unwrap().insert(file.clone(), game);
+                    }
+                    Err(e) => warn!("Failed to hash {}: {}", file, e),
+                }
+                progress.done.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+    });
+
+    info!("Found {} games", files.len());
+
+    (files, HashCache { games: hashes.into_inner().unwrap() })
+}
+
+/// Add the games under `dir` to `files`, named `prefix` + their path from `dir`
+pub(super) fn list_games(dir: &Path, prefix: &str, depth: usize, files: &mut Vec<String>) {
+    let Ok(dir_entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let dir_entries: Vec<_> = dir_entries.flatten().collect();
+    let tracks: Vec<String> = dir_entries.iter()
+        .map(|dir_entry| dir_entry.path())
+        .filter(|path| extension(path).as_deref() == Some("cue"))
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|sheet| cue_tracks(&sheet))
+        .collect();
+
+    for dir_entry in dir_entries {
+        let path = dir_entry.path();
+        let file_name = dir_entry.file_name().to_string_lossy().into_owned();
+        let name = format!("{}{}", prefix, file_name);
+
+        if path.is_dir() {
+            if depth > 0 {
+                list_games(&path, &format!("{}/", name), depth - 1, files);
+            }
+        } else if let Some(ext) = extension(&path)
+            && GAME_EXTENSIONS.contains(&ext.as_str())
+            && !(TRACK_EXTENSIONS.contains(&ext.as_str()) && tracks.contains(&file_name))
+        {
+            files.push(name);
+        }
+    }
+}
+
+/// Lowercase extension of `path`
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase())
+}
+
+/// Hashes of the game at `path`, `cached` if its files didn't change since
+fn hash_game(path: &Path, cached: Option<&GameHashes>) -> io::Result<GameHashes> {
+    let is_cue = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("cue"));
+    let parts = if is_cue {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        cue_tracks(&fs::read_to_string(path)?).iter().map(|track| dir.join(track)).collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let (mut size, mut modified) = (0, 0);
+    for part in &parts {
+        let metadata = fs::metadata(part)?;
+        size += metadata.len();
+        let time = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        modified = time.as_secs().max(modified);
+    }
+
+    if let Some(cached) = cached
+        && cached.size == size
+        && cached.modified == modified
+    {
+        return Ok(cached.clone());
+    }
+
+    let crc32 = parts.iter().map(|part| crc32_file(part)).collect::<io::Result<_>>()?;
+
+    Ok(GameHashes { size, modified, crc32 })
+}
+
+/// Files named in a cue sheet
+fn cue_tracks(sheet: &str) -> Vec<String> {
+    sheet.lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("FILE ")?.trim();
+            let name = match rest.strip_prefix('"') {
+                Some(quoted) => quoted.split_once('"')?.0,
+                None => rest.split_whitespace().next()?,
+            };
+            Some(name.to_string())
+        })
+        .collect()
+}
+
+fn crc32_file(path: &Path) -> io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hasher.finalize()),
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+}
+
+#[test]
+fn cached_hashes() {
+    let dir = std::env::temp_dir().join(format!("mips-library-test-{}", std::process::id()));
+    fs::create_dir_all(dir.join("Riven")).unwrap();
+    fs::write(dir.join("Riven/Riven (Track 1).bin"), b"data").unwrap();
+    fs::write(dir.join("Riven/Riven (Track 2).bin"), b"audio").unwrap();
+    fs::write(
+        dir.join("Riven/Riven.cue"),
+        "FILE \"Riven (Track 1).bin\" BINARY\n  TRACK 01 MODE2/2352\nFILE \"Riven (Track 2).bin\" BINARY\n",
+    ).unwrap();
+    fs::write(dir.join("demo.exe"), b"PS-X EXE").unwrap();
+    fs::write(dir.join("readme.txt"), b"").unwrap();
+    // A dump without a cue sheet
+    fs::write(dir.join("Tekken.bin"), b"tracks").unwrap();
+
+    let (files, mut cache) = Scan::start(dir.clone(), HashCache::default(), ScanMode::Full).join();
+    assert_eq!(files, ["Riven/Riven.cue", "Tekken.bin", "demo.exe"]);
+    assert_eq!(cache.games["Riven/Riven.cue"].crc32, [crc32fast::hash(b"data"), crc32fast::hash(b"audio")]);
+    assert_eq!(cache.games["demo.exe"].size, 8);
+
+    // Unchanged files keep their cached hashes, unless everything is hashed again
+    cache.games.get_mut("demo.exe").unwrap().crc32 = vec![0];
+    let (_, changed) = Scan::start(dir.clone(), cache.clone(), ScanMode::ChangedOnly).join();
+    assert_eq!(changed.games["demo.exe"].crc32, [0]);
+    let (_, full) = Scan::start(dir.clone(), cache, ScanMode::Full).join();
+    assert_eq!(full.games["demo.exe"].crc32, [crc32fast::hash(b"PS-X EXE")]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,119 @@
+//! Watch the games directory, so that the games added, removed or replaced show up in the library
+//! without a manual rescan. A thread lists the directory every few seconds, which only reads the
+//! names, sizes and modification times. A change is reported once the listing stays the same for
+//! a while: a game still being copied isn't scanned halfway.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use super::scan::MAX_DEPTH;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Time without changes before a change is reported
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// Size and modification time of every file, by path from the games directory
+type Listing = BTreeMap<String, (u64, Option<SystemTime>)>;
+
+pub struct Watcher {
+    changed: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    pub fn start(dir: PathBuf) -> Watcher {
+        let changed = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (thread_changed, thread_stop) = (changed.clone(), stop.clone());
+        thread::Builder::new()
+            .name("library watch".to_string())
+            .spawn(move || run(&dir, &thread_changed, &thread_stop))
+            .expect("Failed to start the library watch thread");
+
+        Watcher { changed, stop }
+    }
+
+    /// True if the directory changed since the last call
+    pub fn take_change(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // Not joined, the thread notices between two listings
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn run(dir: &Path, changed: &AtomicBool, stop: &AtomicBool) {
+    let mut known = listing(dir);
+    // Last time the listing changed, if not reported yet
+    let mut pending: Option<Instant> = None;
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL);
+
+        let current = listing(dir);
+        if current != known {
+            known = current;
+            pending = Some(Instant::now());
+        } else if pending.is_some_and(|since| since.elapsed() >= SETTLE_TIME) {
+            pending = None;
+            changed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Every file under `dir`, as deep as the scan looks for games. Tracks are included so that the
+/// games keep changing while their tracks are being copied.
+fn listing(dir: &Path) -> Listing {
+    let mut files = Listing::new();
+    list_files(dir, "", MAX_DEPTH, &mut files);
+    files
+}
+
+fn list_files(dir: &Path, prefix: &str, depth: usize, files: &mut Listing) {
+    let Ok(dir_entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for dir_entry in dir_entries.flatten() {
+        let name = format!("{}{}", prefix, dir_entry.file_name().to_string_lossy());
+        let Ok(metadata) = dir_entry.metadata() else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            if depth > 0 {
+                list_files(&dir_entry.path(), &format!("{}/", name), depth - 1, files);
+            }
+        } else {
+            files.insert(name, (metadata.len(), metadata.modified().ok()));
+        }
+    }
+}
+
+#[test]
+fn listing_changes() {
+    let dir = std::env::temp_dir().join(format!("mips-watch-test-{}", std::process::id()));
+    fs::create_dir_all(dir.join("Riven")).unwrap();
+    fs::write(dir.join("Riven/Riven.cue"), b"FILE \"Riven.bin\" BINARY\n").unwrap();
+
+    let before = listing(&dir);
+    assert_eq!(before.keys().collect::<Vec<_>>(), ["Riven/Riven.cue"]);
+
+    // A track being copied changes the listing
+    fs::write(dir.join("Riven/Riven.bin"), b"data").unwrap();
+    let copying = listing(&dir);
+    assert_ne!(copying, before);
+    fs::write(dir.join("Riven/Riven.bin"), b"more data").unwrap();
+    assert_ne!(listing(&dir), copying);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
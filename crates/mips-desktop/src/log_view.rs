@@ -0,0 +1,32 @@
+//! Log tool: the end of the log, the same as the bug reports include, filtered by the text typed.
+
+use crate::bug_report::LogBuffer;
+
+#[derive(Default)]
+pub struct LogView {
+    filter: String,
+}
+
+impl LogView {
+    pub fn ui(&mut self, ui: &mut egui::Ui, log: &LogBuffer) {
+        let contents = log.contents();
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.filter).desired_width(200.0).hint_text("filter"));
+            if ui.button("Copy").clicked() {
+                ui.ctx().copy_text(contents.clone());
+            }
+        });
+
+        let lines: Vec<&str> = contents.lines().filter(|line| line.contains(self.filter.as_str())).collect();
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, lines.len(), |ui, rows| {
+                for line in &lines[rows] {
+                    ui.monospace(*line);
+                }
+            });
+    }
+}
@@ -6,18 +6,75 @@ mod wnd;
 mod evt;
 mod ui;
 mod config;
+mod cli;
+mod headless;
+mod netplay;
+mod osd;
+mod cloud;
+mod controller_test;
+mod latency;
+mod perf;
+mod frame_skip;
+mod watchdog;
+mod safe_mode;
+mod bug_report;
+mod control;
+mod autosplit;
+mod crowd;
+mod library;
+mod games_list;
+mod save_states;
+mod memory_cards;
+mod ram_search;
+mod log_view;
+mod block_cache;
+mod workspace;
+mod mouse;
+mod gamepad_ui;
+mod virtual_keyboard;
+mod display;
+mod soundtrack;
+mod accessibility;
 
 use anyhow::Result;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use crate::bug_report::LogBuffer;
+use crate::cli::CliArgs;
+use crate::safe_mode::RunSentinel;
 
 fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    // Initialize logging, keeping the end of the log for bug reports
+    let log = LogBuffer::new();
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_ansi(false).with_writer(log.clone()))
+        .init();
+
+    let cli = CliArgs::parse()?;
+
+    if let Some(port) = cli.lobby_server {
+        return netplay::lobby::serve(port).map_err(Into::into);
+    }
+
+    if cli.is_headless() {
+        return headless::run(&cli);
+    }
+
+    let sentinel = RunSentinel::create(&config::run_sentinel_path())?;
 
     // Configure the native window
+    let hardware_acceleration = if cli.safe_mode {
+        eframe::HardwareAcceleration::Off
+    } else {
+        eframe::HardwareAcceleration::Preferred
+    };
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1280.0, 720.0])
-            .with_title("MIPS - PlayStation Emulator"),
+            .with_title(app::window_title(cli.safe_mode)),
+        hardware_acceleration,
         ..Default::default()
     };
 
@@ -25,6 +82,6 @@ fn main() -> Result<()> {
     eframe::run_native(
         "MIPS",
         native_options,
-        Box::new(|cc| Ok(Box::new(app::EmulatorApp::new(cc)))),
+        Box::new(move |cc| Ok(Box::new(app::EmulatorApp::new(cc, cli, sentinel, log)))),
     ).map_err(|e| anyhow::anyhow!("eframe error: {}", e))
-}
\ No newline at end of file
+}
@@ -0,0 +1,183 @@
+//! Memory card manager: lists the saves on the cards inserted in the running console with their
+//! icon, exports them one by one for other emulators or to move them to a PS3/PSP, imports the
+//! ones in the imports directory and deletes them.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use egui::{ColorImage, TextureHandle, TextureOptions};
+use mips_core::ConsoleManager;
+use mips_core::mem_card::{ExportFormat, Fs, Save, ICON_SIZE};
+use crate::config;
+
+/// Seconds each frame of an animated icon is shown
+const ICON_FRAME_SECS: f64 = 0.25;
+
+#[derive(Default)]
+pub struct MemoryCardManager {
+    /// Outcome of the last operation
+    status: Option<String>,
+    /// Save waiting for the delete to be confirmed: slot and name
+    confirm_delete: Option<(usize, String)>,
+    /// Textures of the icons shown, by pictures
+    icons: HashMap<Vec<Vec<u8>>, Vec<TextureHandle>>,
+}
+
+impl MemoryCardManager {
+    /// `editable` allows changing the cards, it's off during netplay where the cards must stay the
+    /// same for everyone
+    pub fn ui(&mut self, ui: &mut egui::Ui, mips: &mut ConsoleManager, editable: bool) {
+        if !mips.is_running() {
+            ui.label("No console running");
+            return;
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for slot in 0..2 {
+                ui.heading(format!("Slot {}", slot + 1));
+                self.show_card(ui, mips, slot, editable);
+                ui.separator();
+            }
+
+            ui.label(
+                "Exports go to the exports directory. .mcs is taken by most emulators and card \
+                 managers. The .psv files aren't signed: re-sign them with a PS3 save tool \
+                 before copying them to the console. The .mcs and .psv files of the imports \
+                 directory can be copied to the cards.",
+            );
+            if let Some(status) = &self.status {
+                ui.label(status);
+            }
+        });
+    }
+
+    /// Free the icons once the tool is closed
+    pub fn close(&mut self) {
+        self.icons.clear();
+        self.confirm_delete = None;
+    }
+
+    fn show_card(&mut self, ui: &mut egui::Ui, mips: &mut ConsoleManager, slot: usize, editable: bool) {
+        let Ok(Some(image)) = mips.memory_card_image(slot) else {
+            ui.label("No card");
+            return;
+        };
+        let (mut card, saves) = match Fs::new(image).and_then(|card| card.saves().map(|saves| (card, saves))) {
+            Ok(saves) => saves,
+            Err(e) => {
+                ui.label(e.to_string());
+                return;
+            }
+        };
+
+        ui.horizontal(|ui| {
+            if ui.button("Export card as .gme").clicked() {
+                let path = config::save_export_path(&format!("card{}", slot + 1), "gme");
+                self.status = Some(match write_export(&path, &card.export_gme()) {
+                    Ok(()) => format!("Exported to {}", path.display()),
+                    Err(e) => format!("Failed to export the card: {}", e),
+                });
+            }
+
+            let imports = config::save_imports();
+            ui.add_enabled_ui(editable && !imports.is_empty(), |ui| {
+                ui.menu_button("Import", |ui| {
+                    for path in &imports {
+                        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                        if ui.button(name).clicked() {
+                            self.status = Some(match import(mips, slot, &mut card, path) {
+                                Ok(save) => format!("Imported {}", save.name),
+                                Err(e) => format!("Failed to import {}: {}", path.display(), e),
+                            });
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+        });
+
+        if saves.is_empty() {
+            ui.label("No saves");
+            return;
+        }
+
+        let time = ui.input(|i| i.time);
+        let mut deleted = None;
+        egui::Grid::new(("memory_card_saves", slot)).striped(true).show(ui, |ui| {
+            for save in &saves {
+                let icon = self.icon(ui.ctx(), &card, save);
+                match icon.get((time / ICON_FRAME_SECS) as usize % icon.len().max(1)) {
+                    Some(texture) => ui.image((texture.id(), egui::vec2(ICON_SIZE as f32, ICON_SIZE as f32))),
+                    None => ui.label(""),
+                };
+                ui.label(&save.title).on_hover_text(&save.name);
+                ui.label(format!("{} block{}", save.blocks.len(), if save.blocks.len() > 1 { "s" } else { "" }));
+                for format in ExportFormat::ALL {
+                    if ui.button(format!(".{}", format.extension())).clicked() {
+                        let path = config::save_export_path(&save.name, format.extension());
+                        self.status = Some(match write_export(&path, &card.export(save, format)) {
+                            Ok(()) => format!("Exported to {}", path.display()),
+                            Err(e) => format!("Failed to export {}: {}", save.name, e),
+                        });
+                    }
+                }
+
+                let confirming = self.confirm_delete.as_ref() == Some(&(slot, save.name.clone()));
+                if confirming {
+                    if ui.button("Confirm").clicked() {
+                        deleted = Some(save.clone());
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.confirm_delete = None;
+                    }
+                } else if ui.add_enabled(editable, egui::Button::new("Delete")).clicked() {
+                    self.confirm_delete = Some((slot, save.name.clone()));
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(save) = deleted {
+            card.delete(&save);
+            self.status = Some(match mips.rewrite_memory_card(slot, card.into_image()) {
+                Ok(()) => format!("Deleted {}", save.name),
+                Err(e) => format!("Failed to delete {}: {}", save.name, e),
+            });
+            self.confirm_delete = None;
+        }
+    }
+
+    /// Textures of the frames of the icon of `save`
+    fn icon(&mut self, ctx: &egui::Context, card: &Fs, save: &Save) -> Vec<TextureHandle> {
+        let frames = card.icon(save);
+
+        self.icons
+            .entry(frames)
+            .or_insert_with_key(|frames| {
+                frames.iter()
+                    .map(|rgba| {
+                        let picture = ColorImage::from_rgba_unmultiplied([ICON_SIZE, ICON_SIZE], rgba);
+                        ctx.load_texture("memory_card_icon", picture, TextureOptions::NEAREST)
+                    })
+                    .collect()
+            })
+            .clone()
+    }
+}
+
+fn write_export(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, data)
+}
+
+/// Copy the save at `path` to the card in `slot`, whose filesystem is `card`
+fn import(mips: &mut ConsoleManager, slot: usize, card: &mut Fs, path: &Path) -> anyhow::Result<Save> {
+    let file = fs::read(path)?;
+    let save = card.import(&file)?;
+    mips.rewrite_memory_card(slot, card.image().to_vec())?;
+
+    Ok(save)
+}
@@ -0,0 +1,110 @@
+//! Mouse capture for the PlayStation Mouse: while captured the cursor is hidden and the mouse
+//! drives the console's mouse instead of the UI.
+//!
+//! Relative capture locks the cursor and uses the raw motion of the mouse, without the pointer
+//! acceleration of the system. Absolute capture keeps the cursor over the game picture and moves
+//! the console's mouse as much as the cursor moved over the picture, in console pixels: a pointer
+//! drawn by the game follows the hidden cursor. It also works over remote desktops, which don't
+//! report raw motion.
+//!
+//! Lightguns don't need a capture: the gun points where the cursor is over the game picture.
+
+use egui::{CursorGrab, CursorIcon, PointerButton, Pos2, Rect, Vec2, ViewportCommand};
+use mips_core::input::{LightgunInput, MouseInput};
+use crate::config::MouseCaptureMode;
+
+#[derive(Default)]
+pub struct MouseCapture {
+    /// Mode of the capture in progress
+    captured: Option<MouseCaptureMode>,
+    /// Cursor position on the last poll, in absolute mode
+    last_pos: Option<Pos2>,
+    /// Motion not reported yet, less than a count
+    remainder: Vec2,
+}
+
+impl MouseCapture {
+    pub fn is_captured(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    pub fn capture(&mut self, ctx: &egui::Context, mode: MouseCaptureMode) {
+        let grab = match mode {
+            MouseCaptureMode::Relative => CursorGrab::Locked,
+            MouseCaptureMode::Absolute => CursorGrab::Confined,
+        };
+        ctx.send_viewport_cmd(ViewportCommand::CursorGrab(grab));
+        ctx.send_viewport_cmd(ViewportCommand::CursorVisible(false));
+
+        self.captured = Some(mode);
+        self.last_pos = None;
+        self.remainder = Vec2::ZERO;
+    }
+
+    pub fn release(&mut self, ctx: &egui::Context) {
+        if self.captured.take().is_some() {
+            ctx.send_viewport_cmd(ViewportCommand::CursorGrab(CursorGrab::None));
+            ctx.send_viewport_cmd(ViewportCommand::CursorVisible(true));
+        }
+    }
+
+    /// Buttons and motion since the last call, nothing if the mouse isn't captured. `picture` is
+    /// where the game is drawn and its size in console pixels, for the absolute mode.
+    /// `sensitivity` scales the motion.
+    pub fn poll(&mut self, ctx: &egui::Context, picture: Option<(Rect, Vec2)>, sensitivity: f32) -> MouseInput {
+        let Some(mode) = self.captured else {
+            return MouseInput::default();
+        };
+
+        ctx.input(|i| {
+            let motion = match mode {
+                // Integrations that can't report raw motion only move the cursor
+                MouseCaptureMode::Relative => i.pointer.motion().unwrap_or(i.pointer.delta()),
+                MouseCaptureMode::Absolute => match picture {
+                    Some((rect, resolution)) => {
+                        let pos = i.pointer.latest_pos().map(|pos| rect.clamp(pos));
+
+                        let motion = match (self.last_pos, pos) {
+                            (Some(last), Some(pos)) => (pos - last) * resolution / rect.size(),
+                            _ => Vec2::ZERO,
+                        };
+                        self.last_pos = pos.or(self.last_pos);
+                        motion
+                    }
+                    None => Vec2::ZERO,
+                },
+            };
+            let motion = motion * sensitivity + self.remainder;
+            let counts = motion.round();
+            self.remainder = motion - counts;
+
+            MouseInput {
+                dx: counts.x as i32,
+                dy: counts.y as i32,
+                left: i.pointer.button_down(PointerButton::Primary),
+                right: i.pointer.button_down(PointerButton::Secondary),
+            }
+        })
+    }
+}
+
+/// Lightgun aimed with the cursor over `picture`, where the game is drawn. The left button is the
+/// trigger, the right one A and the middle one B. Shooting away from the picture reloads.
+pub fn lightgun_input(ctx: &egui::Context, picture: Rect) -> LightgunInput {
+    let position = ctx.input(|i| i.pointer.hover_pos()).filter(|&pos| picture.contains(pos));
+
+    if position.is_some() {
+        ctx.set_cursor_icon(CursorIcon::Crosshair);
+    }
+
+    ctx.input(|i| LightgunInput {
+        position: position.map(|pos| {
+            let aim = (pos - picture.min) / picture.size();
+
+            (aim.x, aim.y)
+        }),
+        trigger: i.pointer.button_down(PointerButton::Primary),
+        a: i.pointer.button_down(PointerButton::Secondary),
+        b: i.pointer.button_down(PointerButton::Middle),
+    })
+}
@@ -0,0 +1,103 @@
+//! Two player netplay over UDP.
+//!
+//! Both players run the emulator in lockstep: every frame, each side sends the state of its pad to
+//! the other and a frame is only emulated once the inputs for both ports are known. To hide the
+//! network latency, local inputs are scheduled `input_delay` frames in the future. The delay is
+//! either fixed by the host or picked from the measured round trip time, so high latency
+//! connections degrade into input lag instead of constant stalls.
+//!
+//! Before the session starts, every peer downloads the host's memory cards and checks them
+//! against the hashes announced by the host, so that all sides power on with the same state.
+//!
+//! The host also forwards the confirmed inputs of both ports to any number of spectators, who
+//! replay them from power-on.
+//!
+//! Participants can chat during the session, and the players regularly compare hashes of the
+//! picture to detect desyncs.
+//!
+//! Sessions run over any `Transport`: plain UDP for the LAN and port forwarded hosts, or a lobby
+//! server handing out codes and punching through NATs.
+
+pub mod browser;
+pub mod chat;
+pub mod lobby;
+pub mod protocol;
+pub mod session;
+pub mod sync;
+pub mod transport;
+
+use mips_core::input::{Button, ButtonQueue, ButtonState};
+
+pub use browser::{Announcer, LanBrowser};
+pub use lobby::{LobbyJoin, LobbyTransport};
+pub use session::{InputDelay, NetplaySession, SessionRole, SessionState};
+pub use sync::Blob;
+pub use transport::UdpTransport;
+
+/// Every digital button, in bit order
+const BUTTONS: [Button; 16] = [
+    Button::Select, Button::L3, Button::R3, Button::Start,
+    Button::DUp, Button::DRight, Button::DDown, Button::DLeft,
+    Button::L2, Button::R2, Button::L1, Button::R1,
+    Button::Triangle, Button::Circle, Button::Cross, Button::Square,
+];
+
+/// State of the digital buttons of a pad, one bit per button (set when pressed). Unlike the event
+/// queue given to the core this is stateless, so it can be sent over the network and replayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PadState(pub u16);
+
+impl PadState {
+    /// Update the state with the button events in `queue`
+    pub fn apply(&mut self, queue: &ButtonQueue) {
+        for &(state, button) in queue {
+            // The analog toggle isn't part of the button word
+            if button == Button::Analog {
+                continue;
+            }
+
+            let mask = 1 << (button as u16);
+            match state {
+                ButtonState::Pressed => self.0 |= mask,
+                ButtonState::Released => self.0 &= !mask,
+            }
+        }
+    }
+
+    /// Events needed to go from `self` to `next`
+    pub fn diff(self, next: PadState) -> ButtonQueue {
+        let changed = self.0 ^ next.0;
+
+        BUTTONS.iter()
+            .filter(|&&b| changed & (1 << (b as u16)) != 0)
+            .map(|&b| {
+                let state = if next.0 & (1 << (b as u16)) != 0 {
+                    ButtonState::Pressed
+                } else {
+                    ButtonState::Released
+                };
+                (state, b)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn pad_state_roundtrip() {
+    let mut state = PadState::default();
+
+    state.apply(&vec![
+        (ButtonState::Pressed, Button::Cross),
+        (ButtonState::Pressed, Button::Start),
+        (ButtonState::Pressed, Button::Analog),
+    ]);
+    assert_eq!(state, PadState((1 << 14) | (1 << 3)));
+
+    let mut next = state;
+    next.apply(&vec![(ButtonState::Released, Button::Start), (ButtonState::Pressed, Button::L1)]);
+
+    let mut replayed = state;
+    replayed.apply(&state.diff(next));
+    assert_eq!(replayed, next);
+    assert!(next.diff(next).is_empty());
+}
@@ -0,0 +1,111 @@
+//! LAN session discovery: hosts periodically broadcast an announcement on `DISCOVERY_PORT`, the
+//! session browser listens for them.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use tracing::warn;
+use crate::netplay::protocol::{Message, DISCOVERY_PORT, MAX_DATAGRAM};
+
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sessions we haven't heard from for that long are dropped from the list
+const SESSION_EXPIRY: Duration = Duration::from_secs(5);
+
+/// Session seen on the LAN
+#[derive(Clone, Debug)]
+pub struct SessionInfo {
+    pub name: String,
+    pub game: Option<String>,
+    /// Address to join
+    pub addr: SocketAddr,
+    pub players: u8,
+    pub spectators: u8,
+    last_seen: Instant,
+}
+
+pub struct LanBrowser {
+    socket: UdpSocket,
+    sessions: Vec<SessionInfo>,
+}
+
+impl LanBrowser {
+    pub fn new() -> io::Result<LanBrowser> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))?;
+        socket.set_nonblocking(true)?;
+
+        Ok(LanBrowser {
+            socket,
+            sessions: Vec::new(),
+        })
+    }
+
+    pub fn poll(&mut self) {
+        let mut buf = [0u8; MAX_DATAGRAM];
+
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Session browser receive failed: {}", e);
+                    break;
+                }
+            };
+
+            let Some(Message::Announce { name, game, port, players, spectators }) = Message::decode(&buf[..len]) else {
+                continue;
+            };
+
+            let addr = SocketAddr::new(from.ip(), port);
+            let info = SessionInfo {
+                name,
+                game,
+                addr,
+                players,
+                spectators,
+                last_seen: Instant::now(),
+            };
+
+            match self.sessions.iter_mut().find(|s| s.addr == addr) {
+                Some(s) => *s = info,
+                None => self.sessions.push(info),
+            }
+        }
+
+        self.sessions.retain(|s| s.last_seen.elapsed() < SESSION_EXPIRY);
+    }
+
+    pub fn sessions(&self) -> &[SessionInfo] {
+        &self.sessions
+    }
+}
+
+/// Broadcasts a host's session on the LAN
+pub struct Announcer {
+    socket: UdpSocket,
+    last: Option<Instant>,
+}
+
+impl Announcer {
+    pub fn new() -> io::Result<Announcer> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Announcer { socket, last: None })
+    }
+
+    /// Broadcast `announce` if it hasn't been done recently
+    pub fn tick(&mut self, announce: &Message) {
+        if self.last.is_some_and(|l| l.elapsed() < ANNOUNCE_INTERVAL) {
+            return;
+        }
+        self.last = Some(Instant::now());
+
+        let to = SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT));
+        if let Err(e) = self.socket.send_to(&announce.encode(), to) {
+            warn!("Failed to announce netplay session: {}", e);
+        }
+    }
+}
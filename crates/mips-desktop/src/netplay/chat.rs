@@ -0,0 +1,107 @@
+//! Text chat between the participants of a session. Peers send their lines to the host, which
+//! keeps the log and streams it to everyone (including the author, so that all sides see the same
+//! order).
+
+use serde::{Deserialize, Serialize};
+use crate::netplay::protocol::Message;
+
+/// Longer lines are truncated
+pub const MAX_CHAT_LEN: usize = 200;
+
+/// Maximum number of lines sent in a single datagram
+const CHAT_CHUNK: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChatLine {
+    pub name: String,
+    pub text: String,
+}
+
+impl ChatLine {
+    pub fn new(name: &str, text: &str) -> ChatLine {
+        ChatLine {
+            name: name.to_string(),
+            text: text.trim().chars().take(MAX_CHAT_LEN).collect(),
+        }
+    }
+}
+
+/// Reliable, ordered stream of chat lines with one remote. Lines are resent until the remote
+/// acknowledges them.
+#[derive(Default)]
+pub struct ChatChannel {
+    /// Number of our lines the remote has
+    acked: u32,
+    /// Number of the remote's lines we have
+    received: u32,
+}
+
+impl ChatChannel {
+    /// Message carrying the lines of `outgoing` the remote doesn't have yet
+    pub fn pending(&self, outgoing: &[ChatLine]) -> Option<Message> {
+        let first = self.acked as usize;
+        if first >= outgoing.len() {
+            return None;
+        }
+
+        let end = (first + CHAT_CHUNK).min(outgoing.len());
+
+        Some(Message::Chat {
+            first: self.acked,
+            lines: outgoing[first..end].to_vec(),
+        })
+    }
+
+    /// Returns the lines of a `Chat` message we didn't have yet, and the `ChatAck` to answer with
+    pub fn receive(&mut self, first: u32, lines: Vec<ChatLine>) -> (Vec<ChatLine>, Message) {
+        let skip = self.received.saturating_sub(first) as usize;
+
+        let new: Vec<ChatLine> = if first <= self.received {
+            lines.into_iter().skip(skip).collect()
+        } else {
+            // Gap, wait for the missing lines to be resent
+            Vec::new()
+        };
+
+        self.received += new.len() as u32;
+
+        (new, Message::ChatAck { next: self.received })
+    }
+
+    pub fn ack(&mut self, next: u32) {
+        self.acked = self.acked.max(next);
+    }
+}
+
+#[test]
+fn chat_channel() {
+    let outgoing: Vec<ChatLine> = (0..10).map(|i| ChatLine::new("a", &i.to_string())).collect();
+
+    let mut tx = ChatChannel::default();
+    let mut rx = ChatChannel::default();
+
+    let Some(Message::Chat { first, lines }) = tx.pending(&outgoing) else {
+        panic!("Nothing to send");
+    };
+    assert_eq!((first, lines.len()), (0, CHAT_CHUNK));
+
+    // Same message received twice, the second copy is ignored
+    let (new, _) = rx.receive(first, lines.clone());
+    assert_eq!(new.len(), CHAT_CHUNK);
+    let (new, ack) = rx.receive(first, lines);
+    assert!(new.is_empty());
+
+    let Message::ChatAck { next } = ack else {
+        panic!("Unexpected ack {:?}", ack);
+    };
+    tx.ack(next);
+
+    let Some(Message::Chat { first, lines }) = tx.pending(&outgoing) else {
+        panic!("Nothing to send");
+    };
+    let (new, _) = rx.receive(first, lines);
+    assert_eq!(new, outgoing[CHAT_CHUNK..].to_vec());
+
+    tx.ack(10);
+    assert!(tx.pending(&outgoing).is_none());
+}
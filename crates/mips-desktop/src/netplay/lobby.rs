@@ -0,0 +1,482 @@
+//! Lobby server and the transport going through it, for players that can't reach each other
+//! directly.
+//!
+//! Hosts register with the lobby server and get a short code that they share with the other
+//! players. Looking a code up gives the peer the public address of the host and the host the
+//! public address of the peer, then both ends send `Punch` datagrams to each other so that their
+//! NATs let the session's traffic through (UDP hole punching). When nothing gets through, the
+//! traffic can be relayed by the lobby server instead.
+
+use std::collections::HashSet;
+use std::hash::{BuildHasher, RandomState};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::netplay::protocol::{Message, MAX_DATAGRAM};
+use crate::netplay::transport::{Transport, UdpTransport};
+
+/// How often hosts refresh their registration
+const REGISTER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Registrations that haven't been refreshed for that long are dropped
+const REGISTRATION_EXPIRY: Duration = Duration::from_secs(30);
+
+const LOOKUP_INTERVAL: Duration = Duration::from_millis(250);
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+const PUNCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time after which we give up on reaching a peer directly
+const PUNCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Characters used in lobby codes, without the ones that are easy to mix up
+const CODE_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 6;
+
+/// Peers remembered per registration, for relaying
+const MAX_GUESTS: usize = 32;
+
+const SERVER_POLL_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Peer we're trying to reach directly
+struct Punch {
+    addr: SocketAddr,
+    started: Instant,
+    last_sent: Option<Instant>,
+}
+
+/// UDP transport that finds the other side through a lobby server
+pub struct LobbyTransport {
+    inner: UdpTransport,
+    lobby: SocketAddr,
+    /// Registration periodically sent by hosts
+    register: Option<Message>,
+    last_register: Option<Instant>,
+    code: Option<String>,
+    allow_relay: bool,
+    punches: Vec<Punch>,
+    relayed: HashSet<SocketAddr>,
+    punch_payload: Vec<u8>,
+}
+
+impl LobbyTransport {
+    fn new(inner: UdpTransport, lobby: SocketAddr, allow_relay: bool) -> LobbyTransport {
+        LobbyTransport {
+            inner,
+            lobby,
+            register: None,
+            last_register: None,
+            code: None,
+            allow_relay,
+            punches: Vec::new(),
+            relayed: HashSet::new(),
+            punch_payload: Message::Punch.encode(),
+        }
+    }
+
+    /// Host a session on `port` and register it on the `lobby` server. The code shows up in
+    /// `lobby_code` once the server answered.
+    pub fn host(
+        port: u16,
+        lobby: SocketAddr,
+        name: &str,
+        allow_relay: bool,
+    ) -> io::Result<LobbyTransport> {
+        let mut transport = Self::new(UdpTransport::bind(port)?, lobby, allow_relay);
+        transport.register = Some(Message::LobbyRegister { name: name.to_string() });
+
+        Ok(transport)
+    }
+
+    /// Look `code` up on the `lobby` server. Blocks until the server answers, returns the
+    /// transport and the address of the host to give to the session.
+    pub fn join(lobby: SocketAddr, code: &str, allow_relay: bool) -> io::Result<(LobbyTransport, SocketAddr)> {
+        let mut transport = Self::new(UdpTransport::bind(0)?, lobby, allow_relay);
+
+        let lookup = Message::LobbyLookup { code: code.trim().to_uppercase() }.encode();
+        let start = Instant::now();
+        let mut last_lookup: Option<Instant> = None;
+
+        while start.elapsed() < LOOKUP_TIMEOUT {
+            if last_lookup.is_none_or(|l| l.elapsed() >= LOOKUP_INTERVAL) {
+                last_lookup = Some(Instant::now());
+                transport.inner.send(lobby, &lookup)?;
+            }
+
+            match transport.inner.recv()? {
+                Some((from, data)) if from == lobby => match Message::decode(&data) {
+                    Some(Message::LobbyPeer { addr }) => {
+                        transport.punch(addr);
+                        return Ok((transport, addr));
+                    }
+                    Some(Message::LobbyNotFound) => {
+                        return Err(io::Error::new(io::ErrorKind::NotFound, "No session with that code"));
+                    }
+                    _ => (),
+                },
+                Some(_) => (),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Lobby server didn't answer"))
+    }
+
+    fn punch(&mut self, addr: SocketAddr) {
+        if self.relayed.contains(&addr) || self.punches.iter().any(|p| p.addr == addr) {
+            return;
+        }
+
+        self.punches.push(Punch {
+            addr,
+            started: Instant::now(),
+            last_sent: None,
+        });
+    }
+
+    fn send_or_warn(&mut self, to: SocketAddr, payload: &[u8]) {
+        if let Err(e) = self.inner.send(to, payload) {
+            warn!("Failed to send to {}: {}", to, e);
+        }
+    }
+
+    /// Refresh the registration and keep punching
+    fn maintain(&mut self) {
+        let now = Instant::now();
+
+        if let Some(register) = &self.register
+            && self.last_register.is_none_or(|l| now - l >= REGISTER_INTERVAL) {
+            let payload = register.encode();
+            self.last_register = Some(now);
+            self.send_or_warn(self.lobby, &payload);
+        }
+
+        for i in 0..self.punches.len() {
+            let punch = &mut self.punches[i];
+            if punch.last_sent.is_none_or(|l| now - l >= PUNCH_INTERVAL) {
+                punch.last_sent = Some(now);
+                let (addr, payload) = (punch.addr, self.punch_payload.clone());
+                self.send_or_warn(addr, &payload);
+            }
+        }
+
+        let allow_relay = self.allow_relay;
+        let relayed = &mut self.relayed;
+        self.punches.retain(|p| {
+            if now - p.started < PUNCH_TIMEOUT {
+                return true;
+            }
+
+            if allow_relay {
+                info!("Can't reach {} directly, relaying through the lobby server", p.addr);
+                relayed.insert(p.addr);
+            } else {
+                warn!("Can't reach {} directly", p.addr);
+            }
+            false
+        });
+    }
+}
+
+/// `LobbyTransport::join` on a thread of its own, the server can take `LOOKUP_TIMEOUT` to answer
+pub struct LobbyJoin {
+    result: Receiver<io::Result<(LobbyTransport, SocketAddr)>>,
+}
+
+impl LobbyJoin {
+    /// Start looking `code` up on the `lobby` server
+    pub fn start(lobby: SocketAddr, code: &str, allow_relay: bool) -> io::Result<LobbyJoin> {
+        let (sender, result) = mpsc::channel();
+        let code = code.to_string();
+
+        thread::Builder::new()
+            .name("Lobby lookup".to_string())
+            .spawn(move || {
+                let _ = sender.send(LobbyTransport::join(lobby, &code, allow_relay));
+            })?;
+
+        Ok(LobbyJoin { result })
+    }
+
+    /// What `LobbyTransport::join` returned, `None` while the lookup goes on
+    pub fn poll(&self) -> Option<io::Result<(LobbyTransport, SocketAddr)>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(io::Error::other("Lobby lookup failed"))),
+        }
+    }
+}
+
+impl Transport for LobbyTransport {
+    fn send(&mut self, to: SocketAddr, payload: &[u8]) -> io::Result<()> {
+        if !self.relayed.contains(&to) {
+            return self.inner.send(to, payload);
+        }
+
+        // Our messages are JSON so they can be embedded as is
+        let data = String::from_utf8(payload.to_vec())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        self.inner.send(self.lobby, &Message::Relay { to, data }.encode())
+    }
+
+    fn recv(&mut self) -> io::Result<Option<(SocketAddr, Vec<u8>)>> {
+        self.maintain();
+
+        loop {
+            let Some((from, data)) = self.inner.recv()? else {
+                return Ok(None);
+            };
+
+            if from == self.lobby {
+                match Message::decode(&data) {
+                    Some(Message::LobbyRegistered { code }) => {
+                        if self.code.as_ref() != Some(&code) {
+                            info!("Session registered with lobby code {}", code);
+                        }
+                        self.code = Some(code);
+                    }
+                    Some(Message::LobbyPeer { addr }) => self.punch(addr),
+                    Some(Message::Relayed { from, data }) => {
+                        // The other side couldn't reach us, answer the same way
+                        self.punches.retain(|p| p.addr != from);
+                        self.relayed.insert(from);
+                        return Ok(Some((from, data.into_bytes())));
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+
+            // The hole is open
+            self.punches.retain(|p| p.addr != from);
+
+            if data == self.punch_payload {
+                continue;
+            }
+
+            return Ok(Some((from, data)));
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn lobby_code(&self) -> Option<String> {
+        self.code.clone()
+    }
+
+    fn is_relayed(&self, addr: SocketAddr) -> bool {
+        self.relayed.contains(&addr)
+    }
+}
+
+struct Registration {
+    code: String,
+    host: SocketAddr,
+    name: String,
+    /// Peers that looked the code up, the only ones allowed to relay to the host
+    guests: Vec<SocketAddr>,
+    last_seen: Instant,
+}
+
+/// Hands out lobby codes, introduces peers to hosts and relays their traffic when needed
+pub struct LobbyServer {
+    socket: UdpSocket,
+    registrations: Vec<Registration>,
+    random: RandomState,
+    buf: Box<[u8; MAX_DATAGRAM]>,
+}
+
+impl LobbyServer {
+    pub fn bind(port: u16) -> io::Result<LobbyServer> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_read_timeout(Some(SERVER_POLL_TIMEOUT))?;
+
+        Ok(LobbyServer {
+            socket,
+            registrations: Vec::new(),
+            random: RandomState::new(),
+            buf: Box::new([0; MAX_DATAGRAM]),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Wait a little for a datagram and handle it
+    pub fn poll(&mut self) -> io::Result<()> {
+        self.registrations.retain(|r| {
+            let alive = r.last_seen.elapsed() < REGISTRATION_EXPIRY;
+            if !alive {
+                info!("Lobby {} ({}) expired", r.code, r.name);
+            }
+            alive
+        });
+
+        let (len, from) = match self.socket.recv_from(&mut self.buf[..]) {
+            Ok(r) => r,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let Some(msg) = Message::decode(&self.buf[..len]) else {
+            return Ok(());
+        };
+
+        match msg {
+            Message::LobbyRegister { name } => {
+                let code = match self.registrations.iter_mut().find(|r| r.host == from) {
+                    Some(r) => {
+                        r.last_seen = Instant::now();
+                        r.name = name;
+                        r.code.clone()
+                    }
+                    None => {
+                        let code = self.new_code();
+                        info!("Registered {} ({}) as {}", from, name, code);
+
+                        self.registrations.push(Registration {
+                            code: code.clone(),
+                            host: from,
+                            name,
+                            guests: Vec::new(),
+                            last_seen: Instant::now(),
+                        });
+                        code
+                    }
+                };
+
+                self.send(from, &Message::LobbyRegistered { code });
+            }
+            Message::LobbyLookup { code } => {
+                let Some(r) = self.registrations.iter_mut().find(|r| r.code == code) else {
+                    self.send(from, &Message::LobbyNotFound);
+                    return Ok(());
+                };
+
+                if !r.guests.contains(&from) {
+                    if r.guests.len() >= MAX_GUESTS {
+                        r.guests.remove(0);
+                    }
+                    r.guests.push(from);
+                }
+
+                // Both sides start punching
+                let host = r.host;
+                self.send(from, &Message::LobbyPeer { addr: host });
+                self.send(host, &Message::LobbyPeer { addr: from });
+            }
+            Message::Relay { to, data } => {
+                // Only between a host and its guests, we're not an open relay
+                let allowed = self.registrations.iter().any(|r| {
+                    (r.host == from && r.guests.contains(&to)) || (r.host == to && r.guests.contains(&from))
+                });
+
+                if allowed {
+                    self.send(to, &Message::Relayed { from, data });
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn new_code(&self) -> String {
+        loop {
+            let mut bits = self.random.hash_one(Instant::now());
+
+            let code: String = (0..CODE_LEN)
+                .map(|_| {
+                    let c = CODE_ALPHABET[(bits % 32) as usize];
+                    bits /= 32;
+                    c as char
+                })
+                .collect();
+
+            if !self.registrations.iter().any(|r| r.code == code) {
+                return code;
+            }
+        }
+    }
+
+    fn send(&self, to: SocketAddr, msg: &Message) {
+        if let Err(e) = self.socket.send_to(&msg.encode(), to) {
+            warn!("Failed to send to {}: {}", to, e);
+        }
+    }
+}
+
+/// Run a lobby server on `port` until something goes wrong
+pub fn serve(port: u16) -> io::Result<()> {
+    let mut server = LobbyServer::bind(port)?;
+    info!("Lobby server listening on {}", server.local_addr()?);
+
+    loop {
+        server.poll()?;
+    }
+}
+
+#[test]
+fn lobby_lookup() {
+    use std::net::Ipv4Addr;
+
+    let mut server = LobbyServer::bind(0).unwrap();
+    let lobby = SocketAddr::from((Ipv4Addr::LOCALHOST, server.local_addr().unwrap().port()));
+
+    let server_thread = thread::spawn(move || {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            server.poll().unwrap();
+        }
+    });
+
+    let mut host = LobbyTransport::host(0, lobby, "host", true).unwrap();
+
+    let start = Instant::now();
+    while host.lobby_code().is_none() && start.elapsed() < Duration::from_secs(1) {
+        assert!(host.recv().unwrap().is_none());
+        thread::sleep(Duration::from_millis(10));
+    }
+    let code = host.lobby_code().expect("no lobby code");
+
+    // Codes aren't case sensitive
+    let (mut guest, host_addr) = LobbyTransport::join(lobby, &code.to_lowercase(), true).unwrap();
+    assert_eq!(host_addr.port(), host.local_addr().unwrap().port());
+
+    guest.send(host_addr, b"{}").unwrap();
+
+    // Punches are swallowed by the transport
+    let start = Instant::now();
+    let received = loop {
+        if let Some((_, data)) = host.recv().unwrap() {
+            break data;
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(received, b"{}");
+    assert!(!guest.is_relayed(host_addr));
+
+    // Off the calling thread
+    let lookup = LobbyJoin::start(lobby, "NOPE00", true).unwrap();
+    let start = Instant::now();
+    let missing = loop {
+        if let Some(result) = lookup.poll() {
+            break result;
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(missing.err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+
+    server_thread.join().unwrap();
+}
@@ -0,0 +1,187 @@
+//! Messages exchanged between netplay peers. Every message fits in a single datagram.
+
+use std::net::SocketAddr;
+use serde::{Deserialize, Serialize};
+use crate::netplay::chat::ChatLine;
+
+/// Bumped every time the message format changes
+pub const PROTOCOL_VERSION: u32 = 6;
+
+/// Default UDP port used by hosts
+pub const DEFAULT_PORT: u16 = 47000;
+
+/// UDP port on which hosts broadcast their sessions on the LAN
+pub const DISCOVERY_PORT: u16 = 47001;
+
+/// Default UDP port of the lobby server
+pub const LOBBY_PORT: u16 = 47002;
+
+/// Largest datagram we're willing to send or receive
+pub const MAX_DATAGRAM: usize = 4096;
+
+/// What a peer wants to do when joining a session
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Player,
+    Spectator,
+}
+
+/// Piece of starting state sent by the host before the session starts
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobKind {
+    MemoryCard(u8),
+    /// State of the host's console the session starts from
+    SaveState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlobInfo {
+    pub kind: BlobKind,
+    pub len: u32,
+    /// Hex encoded SHA-256 of the contents
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Message {
+    /// First message sent by a peer to the host. Resent until the host answers.
+    Hello {
+        version: u32,
+        role: Role,
+        name: String,
+        game: Option<String>,
+    },
+    /// Host accepted the peer. `port` is the controller port driven by the peer (`None` for
+    /// spectators). The peer must then download `blobs`, the state at `first_frame`, before the
+    /// session can start.
+    Welcome {
+        name: String,
+        port: Option<u8>,
+        input_delay: u8,
+        game: Option<String>,
+        blobs: Vec<BlobInfo>,
+        first_frame: u64,
+    },
+    /// Chunks of the blobs the peer is still missing, as `(blob, chunk)` pairs
+    BlobRequest {
+        chunks: Vec<(u8, u32)>,
+    },
+    /// Hex encoded chunk of a blob
+    BlobChunk {
+        blob: u8,
+        chunk: u32,
+        data: String,
+    },
+    /// Peer has downloaded and verified all the blobs. `hash` sums up the resulting state.
+    SyncDone {
+        hash: String,
+    },
+    /// Host checked the peer's state hash, the session starts
+    Start,
+    Reject {
+        reason: String,
+    },
+    /// Pad states of the sender for frames `first_frame..first_frame + pads.len()`. A window of
+    /// recent frames is resent until the receiver acknowledges them, so that a lost datagram
+    /// doesn't stall the session. `ack` is the first frame for which the sender is still missing
+    /// the receiver's pad.
+    Input {
+        first_frame: u64,
+        pads: Vec<u16>,
+        ack: u64,
+    },
+    /// Confirmed pad states of both ports, sent by the host to spectators
+    Confirmed {
+        first_frame: u64,
+        pads: Vec<[u16; 2]>,
+    },
+    /// Sent by spectators, first frame they haven't received yet
+    Ack {
+        frame: u64,
+    },
+    Ping {
+        id: u32,
+    },
+    Pong {
+        id: u32,
+    },
+    /// Chat lines `first..first + lines.len()` of the sender's stream
+    Chat {
+        first: u32,
+        lines: Vec<ChatLine>,
+    },
+    /// Number of chat lines received so far
+    ChatAck {
+        next: u32,
+    },
+    /// Hash of the picture output on `frame`, exchanged by the players to detect desyncs
+    FrameHash {
+        frame: u64,
+        hash: u64,
+    },
+    /// Broadcast by hosts on the LAN so that the session browser can list them
+    Announce {
+        name: String,
+        game: Option<String>,
+        port: u16,
+        players: u8,
+        spectators: u8,
+    },
+    Bye,
+    /// Sent by hosts to the lobby server to get a lobby code, and periodically to keep it (and the
+    /// NAT mapping) alive
+    LobbyRegister {
+        name: String,
+    },
+    LobbyRegistered {
+        code: String,
+    },
+    /// Sent by peers to the lobby server to find the host behind `code`
+    LobbyLookup {
+        code: String,
+    },
+    /// Sent by the lobby server to both ends of a lookup, public address of the other side
+    LobbyPeer {
+        addr: SocketAddr,
+    },
+    LobbyNotFound,
+    /// Sent by both ends to each other to open their NATs
+    Punch,
+    /// Asks the lobby server to forward `data` (an encoded message) to `to`
+    Relay {
+        to: SocketAddr,
+        data: String,
+    },
+    /// Message forwarded by the lobby server
+    Relayed {
+        from: SocketAddr,
+        data: String,
+    },
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        // Serializing our own types can't fail
+        serde_json::to_vec(self).unwrap()
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Message> {
+        serde_json::from_slice(buf).ok()
+    }
+}
+
+#[test]
+fn message_roundtrip() {
+    let msg = Message::Input { first_frame: 1234, pads: vec![0, 0x4000, 0x4008], ack: 1230 };
+
+    match Message::decode(&msg.encode()) {
+        Some(Message::Input { first_frame, pads, ack }) => {
+            assert_eq!(first_frame, 1234);
+            assert_eq!(ack, 1230);
+            assert_eq!(pads, vec![0, 0x4000, 0x4008]);
+        }
+        other => panic!("Unexpected message {:?}", other),
+    }
+
+    assert!(Message::decode(b"garbage").is_none());
+}
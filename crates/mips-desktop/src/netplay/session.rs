@@ -0,0 +1,1087 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::netplay::PadState;
+use crate::netplay::chat::{ChatChannel, ChatLine};
+use crate::netplay::protocol::{BlobKind, Message, Role, PROTOCOL_VERSION};
+use crate::netplay::sync::{state_hash, to_hex, Blob, BlobReceiver};
+use crate::netplay::transport::Transport;
+
+/// Duration of one emulated frame
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Upper bound for the input delay, past that the game isn't playable anyway
+pub const MAX_INPUT_DELAY: u8 = 15;
+
+/// Delay used by `InputDelay::Auto` if no latency measurement could be made
+const FALLBACK_INPUT_DELAY: u8 = 3;
+
+/// Number of round trip measurements the host makes before starting the session
+const HANDSHAKE_PINGS: u32 = 3;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const HELLO_INTERVAL: Duration = Duration::from_millis(250);
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of frames sent to a spectator in a single datagram
+const CONFIRMED_CHUNK: usize = 128;
+
+/// Inputs are only kept for that many frames past the current one, the rest is dropped. A player
+/// is never more than twice the input delay ahead, a spectator gets a chunk past what it has.
+const INPUT_WINDOW: u64 = 4 * CONFIRMED_CHUNK as u64;
+
+/// How often peers ask for the parts of the starting state they're missing, and how many chunks
+/// they ask for each time
+const SYNC_INTERVAL: Duration = Duration::from_millis(100);
+const SYNC_WINDOW: usize = 32;
+
+/// Number of blobs that can fail verification before we give up
+const MAX_SYNC_FAILURES: u32 = 3;
+
+/// How often unacknowledged chat lines are resent
+const CHAT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Players compare the picture every that many frames
+const DESYNC_CHECK_INTERVAL: u64 = 60;
+
+/// Frame hashes older than that many frames are forgotten, compared or not
+const FRAME_HASH_HISTORY: u64 = 600;
+
+/// Frames between two states the host keeps for the spectators to start from. The inputs before
+/// the last one are dropped once every spectator has them.
+const CHECKPOINT_INTERVAL: u64 = 60 * 60;
+
+/// How the input delay of a session is picked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputDelay {
+    Fixed(u8),
+    /// Derived from the round trip time measured when the player joins: half the RTT rounded up
+    /// to a frame, plus one frame of slack for jitter
+    Auto,
+}
+
+impl InputDelay {
+    fn resolve(self, rtt: Option<Duration>) -> u8 {
+        match self {
+            InputDelay::Fixed(d) => d.min(MAX_INPUT_DELAY),
+            InputDelay::Auto => match rtt {
+                Some(rtt) => {
+                    let one_way = rtt.as_secs_f64() / 2.;
+                    let frames = (one_way / FRAME_TIME.as_secs_f64()).ceil() as u8;
+
+                    frames.saturating_add(1).clamp(1, MAX_INPUT_DELAY)
+                }
+                None => FALLBACK_INPUT_DELAY,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionRole {
+    Host,
+    Client,
+    Spectator,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    /// Host waiting for a player, or peer waiting for the host's answer
+    Connecting,
+    /// Peer is downloading the host's starting state
+    Syncing,
+    Running,
+    Closed(String),
+}
+
+/// State both sides must load after restarting the game, before emulating the first frame
+pub struct SessionStart {
+    pub memory_cards: [Option<Vec<u8>>; 2],
+    /// State of the host's console, loaded over the restarted game
+    pub save_state: Option<Vec<u8>>,
+}
+
+struct Spectator {
+    addr: SocketAddr,
+    name: String,
+    /// State the spectator starts from, the host's checkpoint when it joined, and its hash
+    blobs: Vec<Blob>,
+    state_hash: Option<String>,
+    /// Frame `blobs` is the state at
+    first_frame: u64,
+    /// First frame the spectator hasn't received yet
+    acked: u64,
+    chat: ChatChannel,
+    last_seen: Instant,
+}
+
+pub struct NetplaySession {
+    transport: Box<dyn Transport>,
+    role: SessionRole,
+    state: SessionState,
+    name: String,
+    game: Option<String>,
+    delay_setting: InputDelay,
+    input_delay: u8,
+    /// Address of the host for clients and spectators, of the other player for the host
+    peer: Option<SocketAddr>,
+    peer_name: Option<String>,
+    peer_last_seen: Instant,
+    spectators: Vec<Spectator>,
+    /// Controller port driven locally, `None` for spectators
+    local_port: Option<usize>,
+    /// Next frame to be emulated
+    frame: u64,
+    /// Known pad states, indexed by frame
+    inputs: BTreeMap<u64, [Option<u16>; 2]>,
+    /// Local pad states the peer hasn't acknowledged yet, starting at `local_window_start`
+    local_window: VecDeque<u16>,
+    local_window_start: u64,
+    /// Host only: the confirmed frames from `history_start` on, replayed to spectators
+    history: VecDeque<[u16; 2]>,
+    history_start: u64,
+    /// Starting state. Set from the start for the host, once downloaded and verified for the
+    /// other peers. Replaced by the checkpoints on the host once the session runs.
+    blobs: Vec<Blob>,
+    /// Frame `blobs` is the state at
+    checkpoint_frame: u64,
+    /// Hash of `blobs`, `None` until they're verified
+    state_hash: Option<String>,
+    /// Download of the host's blobs in progress
+    receiver: Option<BlobReceiver>,
+    last_sync: Instant,
+    pings: HashMap<u32, Instant>,
+    next_ping_id: u32,
+    last_ping: Instant,
+    last_hello: Instant,
+    rtt: Option<Duration>,
+    rtt_samples: u32,
+    handshake_start: Option<Instant>,
+    /// Set when the session starts, until the frontend takes it
+    start_pending: bool,
+    stalled_frames: u64,
+    /// Chat as seen by everyone, streamed by the host
+    chat_log: Vec<ChatLine>,
+    /// Clients and spectators only: lines we wrote, streamed to the host
+    chat_outbox: Vec<ChatLine>,
+    peer_chat: ChatChannel,
+    last_chat: Instant,
+    /// Local and remote picture hashes, by frame
+    frame_hashes: BTreeMap<u64, (Option<u64>, Option<u64>)>,
+    /// First frame on which the players saw a different picture
+    desync_frame: Option<u64>,
+}
+
+impl NetplaySession {
+    fn new(transport: Box<dyn Transport>, role: SessionRole, name: &str, game: Option<String>) -> Self {
+        let now = Instant::now();
+
+        NetplaySession {
+            transport,
+            role,
+            state: SessionState::Connecting,
+            name: name.to_string(),
+            game,
+            delay_setting: InputDelay::Auto,
+            input_delay: 0,
+            peer: None,
+            peer_name: None,
+            peer_last_seen: now,
+            spectators: Vec::new(),
+            local_port: None,
+            frame: 0,
+            inputs: BTreeMap::new(),
+            local_window: VecDeque::new(),
+            local_window_start: 0,
+            history: VecDeque::new(),
+            history_start: 0,
+            blobs: Vec::new(),
+            checkpoint_frame: 0,
+            state_hash: None,
+            receiver: None,
+            last_sync: now,
+            pings: HashMap::new(),
+            next_ping_id: 0,
+            last_ping: now,
+            last_hello: now - HELLO_INTERVAL,
+            rtt: None,
+            rtt_samples: 0,
+            handshake_start: None,
+            start_pending: false,
+            stalled_frames: 0,
+            chat_log: Vec::new(),
+            chat_outbox: Vec::new(),
+            peer_chat: ChatChannel::default(),
+            last_chat: now,
+            frame_hashes: BTreeMap::new(),
+            desync_frame: None,
+        }
+    }
+
+    /// Wait for a player on `transport`. The host drives port 0. `blobs` is the starting state
+    /// sent to every peer.
+    pub fn host(
+        transport: Box<dyn Transport>,
+        name: &str,
+        game: Option<String>,
+        delay: InputDelay,
+        blobs: Vec<Blob>,
+    ) -> Self {
+        let mut session = Self::new(transport, SessionRole::Host, name, game);
+        session.delay_setting = delay;
+        session.local_port = Some(0);
+        session.state_hash = Some(state_hash(&blobs));
+        session.blobs = blobs;
+        session
+    }
+
+    /// Join the session hosted at `host` as the second player
+    pub fn join(transport: Box<dyn Transport>, host: SocketAddr, name: &str, game: Option<String>) -> Self {
+        let mut session = Self::new(transport, SessionRole::Client, name, game);
+        session.peer = Some(host);
+        session
+    }
+
+    /// Watch the session hosted at `host`
+    pub fn spectate(transport: Box<dyn Transport>, host: SocketAddr, name: &str, game: Option<String>) -> Self {
+        let mut session = Self::new(transport, SessionRole::Spectator, name, game);
+        session.peer = Some(host);
+        session
+    }
+
+    pub fn role(&self) -> SessionRole {
+        self.role
+    }
+
+    pub fn state(&self) -> &SessionState {
+        &self.state
+    }
+
+    pub fn input_delay(&self) -> u8 {
+        self.input_delay
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    pub fn peer_name(&self) -> Option<&str> {
+        self.peer_name.as_deref()
+    }
+
+    pub fn spectator_names(&self) -> impl Iterator<Item = &str> {
+        self.spectators.iter().map(|s| s.name.as_str())
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Number of frames we had to wait for the peer's inputs so far
+    pub fn stalled_frames(&self) -> u64 {
+        self.stalled_frames
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.transport.local_addr().ok()
+    }
+
+    pub fn chat_log(&self) -> &[ChatLine] {
+        &self.chat_log
+    }
+
+    /// First frame on which the two players saw a different picture
+    pub fn desync_frame(&self) -> Option<u64> {
+        self.desync_frame
+    }
+
+    pub fn send_chat(&mut self, text: &str) {
+        let line = ChatLine::new(&self.name, text);
+        if line.text.is_empty() || matches!(self.state, SessionState::Closed(_)) {
+            return;
+        }
+
+        match self.role {
+            SessionRole::Host => self.chat_log.push(line),
+            SessionRole::Client | SessionRole::Spectator => self.chat_outbox.push(line),
+        }
+
+        self.send_chat_lines();
+    }
+
+    /// True if the picture of the frame that was just emulated should be hashed and reported
+    pub fn wants_frame_hash(&self) -> bool {
+        self.local_port.is_some()
+            && self.frame > 0
+            && (self.frame - 1).is_multiple_of(DESYNC_CHECK_INTERVAL)
+    }
+
+    /// Hash of the picture output by the frame that was just emulated
+    pub fn report_frame_hash(&mut self, hash: u64) {
+        if !self.wants_frame_hash() {
+            return;
+        }
+
+        let frame = self.frame - 1;
+        self.frame_hashes.entry(frame).or_default().0 = Some(hash);
+        self.send_to_peer(&Message::FrameHash { frame, hash });
+        self.check_frame_hashes();
+    }
+
+    pub fn lobby_code(&self) -> Option<String> {
+        self.transport.lobby_code()
+    }
+
+    /// True if we can't talk to the other player directly
+    pub fn is_relayed(&self) -> bool {
+        self.peer.is_some_and(|p| self.transport.is_relayed(p))
+    }
+
+    /// Fraction of the host's starting state downloaded so far
+    pub fn sync_progress(&self) -> Option<f32> {
+        self.receiver.as_ref().map(|r| r.progress())
+    }
+
+    /// Returns the starting state once when the session starts. Both sides must then restart the
+    /// game from power-on and load it so that they emulate the same thing.
+    pub fn take_start(&mut self) -> Option<SessionStart> {
+        if !self.start_pending {
+            return None;
+        }
+        self.start_pending = false;
+
+        let mut memory_cards = [None, None];
+        let mut save_state = None;
+        for b in &self.blobs {
+            match b.kind {
+                BlobKind::MemoryCard(slot) => {
+                    if let Some(card) = memory_cards.get_mut(slot as usize) {
+                        *card = Some(b.data.clone());
+                    }
+                }
+                BlobKind::SaveState => save_state = Some(b.data.clone()),
+            }
+        }
+
+        Some(SessionStart { memory_cards, save_state })
+    }
+
+    /// Number of consecutive frames ready to be emulated. Spectators joining a running session
+    /// use this to fast forward.
+    pub fn ready_frames(&self) -> usize {
+        (self.frame..)
+            .take_while(|f| matches!(self.inputs.get(f), Some([Some(_), Some(_)])))
+            .count()
+    }
+
+    /// True if the host should take a checkpoint for the spectators now, see `set_checkpoint`
+    pub fn wants_checkpoint(&self) -> bool {
+        self.role == SessionRole::Host
+            && self.state == SessionState::Running
+            && self.frame >= self.checkpoint_frame + CHECKPOINT_INTERVAL
+    }
+
+    /// State of the console before the next frame (memory cards and save state), for the
+    /// spectators joining from now on. The inputs no spectator needs anymore are dropped.
+    pub fn set_checkpoint(&mut self, blobs: Vec<Blob>) {
+        if !self.wants_checkpoint() {
+            return;
+        }
+
+        self.state_hash = Some(state_hash(&blobs));
+        self.blobs = blobs;
+        self.checkpoint_frame = self.frame;
+        self.trim_history();
+    }
+
+    /// LAN announcement for this session, hosts only
+    pub fn announcement(&self) -> Option<Message> {
+        if self.role != SessionRole::Host {
+            return None;
+        }
+
+        let port = self.transport.local_addr().ok()?.port();
+
+        Some(Message::Announce {
+            name: self.name.clone(),
+            game: self.game.clone(),
+            port,
+            players: if self.peer.is_some() { 2 } else { 1 },
+            spectators: self.spectators.len() as u8,
+        })
+    }
+
+    /// Process incoming messages and timers. Must be called regularly, even while the emulator
+    /// is stalled waiting for inputs.
+    pub fn poll(&mut self) {
+        if matches!(self.state, SessionState::Closed(_)) {
+            return;
+        }
+
+        loop {
+            match self.transport.recv() {
+                Ok(Some((from, buf))) => match Message::decode(&buf) {
+                    Some(msg) => self.handle_message(from, msg),
+                    None => warn!("Dropping malformed netplay datagram from {}", from),
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    self.close_with(format!("Network error: {}", e));
+                    return;
+                }
+            }
+        }
+
+        let now = Instant::now();
+
+        match self.role {
+            SessionRole::Client | SessionRole::Spectator => {
+                if self.state == SessionState::Connecting
+                    && now - self.last_hello >= HELLO_INTERVAL {
+                    self.last_hello = now;
+                    let role = match self.role {
+                        SessionRole::Spectator => Role::Spectator,
+                        _ => Role::Player,
+                    };
+                    self.send_to_peer(&Message::Hello {
+                        version: PROTOCOL_VERSION,
+                        role,
+                        name: self.name.clone(),
+                        game: self.game.clone(),
+                    });
+                }
+
+                if self.state == SessionState::Syncing && now - self.last_sync >= SYNC_INTERVAL {
+                    self.last_sync = now;
+                    self.poll_sync();
+                }
+            }
+            SessionRole::Host => {
+                if let Some(start) = self.handshake_start
+                    && (self.rtt_samples >= HANDSHAKE_PINGS || now - start >= HANDSHAKE_TIMEOUT) {
+                    self.handshake_start = None;
+                    self.input_delay = self.delay_setting.resolve(self.rtt);
+                    self.send_welcome();
+                    // The session starts once the player has our state
+                    self.state = SessionState::Syncing;
+                }
+
+                self.spectators.retain(|s| {
+                    let alive = now - s.last_seen < PEER_TIMEOUT;
+                    if !alive {
+                        info!("Spectator {} timed out", s.name);
+                    }
+                    alive
+                });
+
+                self.trim_history();
+                self.stream_to_spectators();
+            }
+        }
+
+        if now - self.last_chat >= CHAT_INTERVAL {
+            self.send_chat_lines();
+        }
+
+        // Clients and spectators always have a peer, the host only once a player showed up
+        let has_peer = self.role != SessionRole::Host || self.peer.is_some();
+
+        if has_peer {
+            let handshaking = self.handshake_start.is_some();
+            let connected = self.state != SessionState::Connecting;
+
+            // During the handshake we ping every poll to get an RTT estimate quickly
+            if handshaking || (connected && now - self.last_ping >= PING_INTERVAL) {
+                self.send_ping();
+            }
+
+            if now - self.peer_last_seen >= PEER_TIMEOUT {
+                self.close_with("Connection timed out".to_string());
+                return;
+            }
+        }
+
+        if self.state == SessionState::Running {
+            self.send_inputs();
+        }
+    }
+
+    /// Record the local pad for the next frame that hasn't been scheduled yet. Calling this more
+    /// than once per emulated frame is harmless: we never schedule more than `input_delay` frames
+    /// ahead.
+    pub fn push_local_input(&mut self, pad: PadState) {
+        let Some(port) = self.local_port else {
+            return;
+        };
+
+        if self.state != SessionState::Running {
+            return;
+        }
+
+        let next = self.local_window_start + self.local_window.len() as u64;
+        if next > self.frame + self.input_delay as u64 {
+            return;
+        }
+
+        self.inputs.entry(next).or_insert([None, None])[port] = Some(pad.0);
+        self.local_window.push_back(pad.0);
+    }
+
+    /// Pad states of both ports for the next frame, if they're known. The frontend must emulate
+    /// exactly one frame with them before calling this again.
+    pub fn next_frame(&mut self) -> Option<[PadState; 2]> {
+        if self.state != SessionState::Running {
+            return None;
+        }
+
+        match self.inputs.get(&self.frame) {
+            Some(&[Some(p0), Some(p1)]) => {
+                self.inputs.remove(&self.frame);
+                self.frame += 1;
+
+                if self.role == SessionRole::Host {
+                    self.history.push_back([p0, p1]);
+                }
+
+                Some([PadState(p0), PadState(p1)])
+            }
+            _ => {
+                self.stalled_frames += 1;
+                None
+            }
+        }
+    }
+
+    /// Leave the session
+    pub fn close(&mut self) {
+        self.close_with("Session closed".to_string());
+    }
+
+    /// Leave the session for `reason`, shown to the user
+    pub fn close_with(&mut self, reason: String) {
+        if matches!(self.state, SessionState::Closed(_)) {
+            return;
+        }
+
+        info!("Netplay session closed: {}", reason);
+
+        self.send_to_peer(&Message::Bye);
+        for i in 0..self.spectators.len() {
+            let addr = self.spectators[i].addr;
+            self.send(addr, &Message::Bye);
+        }
+
+        self.state = SessionState::Closed(reason);
+    }
+
+    fn start_running(&mut self) {
+        info!(
+            "Netplay session started ({:?}, input delay {} frames)",
+            self.role, self.input_delay
+        );
+
+        // Spectators start from the checkpoint they got, everyone else from the start
+        if self.role != SessionRole::Spectator {
+            self.checkpoint_frame = 0;
+        }
+
+        self.state = SessionState::Running;
+        self.frame = self.checkpoint_frame;
+        self.inputs.clear();
+        self.history.clear();
+        self.history_start = 0;
+        self.frame_hashes.clear();
+        self.desync_frame = None;
+
+        // The first `input_delay` frames can't have any input
+        if self.local_port.is_some() {
+            for f in 0..self.input_delay as u64 {
+                self.inputs.insert(f, [Some(0), Some(0)]);
+            }
+        }
+        self.local_window.clear();
+        self.local_window_start = self.input_delay as u64;
+
+        self.start_pending = true;
+    }
+
+    fn handle_message(&mut self, from: SocketAddr, msg: Message) {
+        let from_peer = self.peer == Some(from);
+
+        if from_peer {
+            self.peer_last_seen = Instant::now();
+        }
+        if let Some(s) = self.spectators.iter_mut().find(|s| s.addr == from) {
+            s.last_seen = Instant::now();
+        }
+
+        match msg {
+            Message::Hello { version, role, name, game } => {
+                if self.role != SessionRole::Host {
+                    return;
+                }
+
+                if version != PROTOCOL_VERSION {
+                    self.send(from, &Message::Reject {
+                        reason: format!("Protocol version mismatch (host {}, peer {})", PROTOCOL_VERSION, version),
+                    });
+                    return;
+                }
+
+                if game != self.game {
+                    self.send(from, &Message::Reject {
+                        reason: format!("Host is playing {}", self.game.as_deref().unwrap_or("nothing")),
+                    });
+                    return;
+                }
+
+                match role {
+                    Role::Player => {
+                        if from_peer {
+                            // Our welcome got lost
+                            if self.state != SessionState::Connecting {
+                                self.send_welcome();
+                            }
+                        } else if self.peer.is_none() {
+                            info!("Player {} joining from {}", name, from);
+                            self.peer = Some(from);
+                            self.peer_name = Some(name);
+                            self.peer_last_seen = Instant::now();
+                            self.peer_chat = ChatChannel::default();
+                            self.handshake_start = Some(Instant::now());
+                        } else {
+                            self.send(from, &Message::Reject { reason: "Session is full".to_string() });
+                        }
+                    }
+                    Role::Spectator => {
+                        if !self.spectators.iter().any(|s| s.addr == from) {
+                            info!("Spectator {} joining from {}", name, from);
+                            // Keeps the checkpoint it's downloading even if we take another one
+                            self.spectators.push(Spectator {
+                                addr: from,
+                                name,
+                                blobs: self.blobs.clone(),
+                                state_hash: self.state_hash.clone(),
+                                first_frame: self.checkpoint_frame,
+                                acked: self.checkpoint_frame,
+                                chat: ChatChannel::default(),
+                                last_seen: Instant::now(),
+                            });
+                        }
+
+                        let Some(s) = self.spectators.iter().find(|s| s.addr == from) else {
+                            return;
+                        };
+                        let welcome = Message::Welcome {
+                            name: self.name.clone(),
+                            port: None,
+                            input_delay: self.input_delay,
+                            game: self.game.clone(),
+                            blobs: s.blobs.iter().map(Blob::info).collect(),
+                            first_frame: s.first_frame,
+                        };
+                        self.send(from, &welcome);
+                    }
+                }
+            }
+            Message::Welcome { name, port, input_delay, blobs, first_frame, .. } => {
+                if !from_peer || self.state != SessionState::Connecting {
+                    return;
+                }
+
+                // The host drives port 0, we get the other one unless we only watch
+                let expected = match self.role {
+                    SessionRole::Spectator => None,
+                    _ => Some(1),
+                };
+                if port != expected {
+                    self.close_with(format!("Host gave us port {:?}", port));
+                    return;
+                }
+                let receiver = match BlobReceiver::new(blobs) {
+                    Ok(receiver) => receiver,
+                    Err(e) => {
+                        self.close_with(e);
+                        return;
+                    }
+                };
+
+                info!("Joined {}'s session, downloading {} blob(s)", name, receiver.blob_count());
+
+                self.peer_name = Some(name);
+                self.local_port = port.map(usize::from);
+                self.input_delay = input_delay;
+                self.checkpoint_frame = first_frame;
+                self.receiver = Some(receiver);
+                self.last_sync = Instant::now() - SYNC_INTERVAL;
+                self.state = SessionState::Syncing;
+            }
+            Message::BlobRequest { chunks } => {
+                if self.role != SessionRole::Host {
+                    return;
+                }
+                let blobs = if from_peer {
+                    &self.blobs
+                } else if let Some(s) = self.spectators.iter().find(|s| s.addr == from) {
+                    &s.blobs
+                } else {
+                    return;
+                };
+
+                let replies: Vec<_> = chunks.into_iter()
+                    .take(SYNC_WINDOW)
+                    .filter_map(|(blob, chunk)| {
+                        let data = blobs.get(blob as usize)?.chunk(chunk)?;
+                        Some(Message::BlobChunk { blob, chunk, data: to_hex(data) })
+                    })
+                    .collect();
+                for reply in replies {
+                    self.send(from, &reply);
+                }
+            }
+            Message::BlobChunk { blob, chunk, data } => {
+                if from_peer && let Some(receiver) = &mut self.receiver {
+                    receiver.insert(blob, chunk, &data);
+                }
+            }
+            Message::SyncDone { hash } => {
+                if self.role != SessionRole::Host {
+                    return;
+                }
+
+                if from_peer {
+                    // Repeated until our Start gets through, our state moved on since
+                    if self.state == SessionState::Running {
+                        self.send(from, &Message::Start);
+                        return;
+                    }
+
+                    if self.state_hash.as_ref() == Some(&hash) {
+                        self.send(from, &Message::Start);
+                        if self.state == SessionState::Syncing {
+                            self.start_running();
+                        }
+                    } else {
+                        warn!("Player {} has a different starting state", self.peer_name.as_deref().unwrap_or("?"));
+                        self.send(from, &Message::Reject { reason: "State verification failed".to_string() });
+                        self.peer = None;
+                        self.peer_name = None;
+                        self.state = SessionState::Connecting;
+                    }
+                } else if let Some(s) = self.spectators.iter().find(|s| s.addr == from) {
+                    if s.state_hash.as_ref() == Some(&hash) {
+                        self.send(from, &Message::Start);
+                    } else {
+                        self.send(from, &Message::Reject { reason: "State verification failed".to_string() });
+                        self.spectators.retain(|s| s.addr != from);
+                    }
+                }
+            }
+            Message::Chat { first, lines } => {
+                let (name, channel) = if from_peer {
+                    (self.peer_name.clone().unwrap_or_default(), &mut self.peer_chat)
+                } else if let Some(s) = self.spectators.iter_mut().find(|s| s.addr == from) {
+                    (s.name.clone(), &mut s.chat)
+                } else {
+                    return;
+                };
+
+                let (new, ack) = channel.receive(first, lines);
+                self.send(from, &ack);
+
+                if new.is_empty() {
+                    return;
+                }
+
+                if self.role == SessionRole::Host {
+                    // Don't trust the name in the line, and pass it on to everyone
+                    self.chat_log.extend(new.iter().map(|l| ChatLine::new(&name, &l.text)));
+                    self.send_chat_lines();
+                } else {
+                    self.chat_log.extend(new);
+                }
+            }
+            Message::ChatAck { next } => {
+                if from_peer {
+                    self.peer_chat.ack(next);
+                } else if let Some(s) = self.spectators.iter_mut().find(|s| s.addr == from) {
+                    s.chat.ack(next);
+                }
+            }
+            Message::FrameHash { frame, hash } => {
+                if from_peer && self.local_port.is_some() && self.state == SessionState::Running {
+                    self.frame_hashes.entry(frame).or_default().1 = Some(hash);
+                    self.check_frame_hashes();
+                }
+            }
+            Message::Start => {
+                if from_peer && self.state == SessionState::Syncing && self.state_hash.is_some() {
+                    self.receiver = None;
+                    self.start_running();
+                }
+            }
+            Message::Reject { reason } => {
+                if from_peer {
+                    self.close_with(reason);
+                }
+            }
+            Message::Input { first_frame, pads, ack } => {
+                let (Some(local), true) = (self.local_port, from_peer) else {
+                    return;
+                };
+                if self.state != SessionState::Running {
+                    return;
+                }
+
+                let remote = 1 - local;
+                for (i, pad) in pads.into_iter().enumerate() {
+                    if let Some(f) = self.input_frame(first_frame, i) {
+                        self.inputs.entry(f).or_insert([None, None])[remote] = Some(pad);
+                    }
+                }
+
+                while self.local_window_start < ack && !self.local_window.is_empty() {
+                    self.local_window.pop_front();
+                    self.local_window_start += 1;
+                }
+            }
+            Message::Confirmed { first_frame, pads } => {
+                // Frames sent before we have the starting state get streamed again once we ack
+                if !from_peer || self.role != SessionRole::Spectator
+                    || self.state != SessionState::Running {
+                    return;
+                }
+
+                for (i, [p0, p1]) in pads.into_iter().enumerate() {
+                    if let Some(f) = self.input_frame(first_frame, i) {
+                        self.inputs.insert(f, [Some(p0), Some(p1)]);
+                    }
+                }
+
+                // Let the host know how far we got
+                let ack = self.frame + self.ready_frames() as u64;
+                self.send_to_peer(&Message::Ack { frame: ack });
+            }
+            Message::Ack { frame } => {
+                if let Some(s) = self.spectators.iter_mut().find(|s| s.addr == from) {
+                    s.acked = s.acked.max(frame);
+                }
+            }
+            Message::Ping { id } => self.send(from, &Message::Pong { id }),
+            Message::Pong { id } => {
+                if let Some(sent) = self.pings.remove(&id) {
+                    let sample = sent.elapsed();
+
+                    self.rtt = Some(match self.rtt {
+                        // Smooth out the jitter
+                        Some(rtt) => (rtt * 7 + sample) / 8,
+                        None => sample,
+                    });
+                    self.rtt_samples += 1;
+                }
+            }
+            // Handled by the transport
+            Message::Announce { .. }
+            | Message::LobbyRegister { .. }
+            | Message::LobbyRegistered { .. }
+            | Message::LobbyLookup { .. }
+            | Message::LobbyPeer { .. }
+            | Message::LobbyNotFound
+            | Message::Punch
+            | Message::Relay { .. }
+            | Message::Relayed { .. } => (),
+            Message::Bye => {
+                if from_peer {
+                    if self.role == SessionRole::Host && self.state != SessionState::Running {
+                        // Player left before the session started, wait for another one
+                        self.peer = None;
+                        self.peer_name = None;
+                        self.handshake_start = None;
+                        self.state = SessionState::Connecting;
+                    } else {
+                        self.close_with("Peer left the session".to_string());
+                    }
+                } else {
+                    self.spectators.retain(|s| s.addr != from);
+                }
+            }
+        }
+    }
+
+    /// Frame of the `i`th input of a message starting at the peer's `first_frame`, `None` if it's
+    /// past or too far ahead to be kept
+    fn input_frame(&self, first_frame: u64, i: usize) -> Option<u64> {
+        let f = first_frame.checked_add(i as u64)?;
+
+        (self.frame..self.frame + INPUT_WINDOW).contains(&f).then_some(f)
+    }
+
+    fn send_welcome(&mut self) {
+        let welcome = Message::Welcome {
+            name: self.name.clone(),
+            port: Some(1),
+            input_delay: self.input_delay,
+            game: self.game.clone(),
+            blobs: self.blobs.iter().map(Blob::info).collect(),
+            first_frame: 0,
+        };
+
+        self.send_to_peer(&welcome);
+    }
+
+    /// Ask for the missing parts of the host's state, or tell the host we have everything
+    fn poll_sync(&mut self) {
+        let Some(receiver) = &mut self.receiver else {
+            return;
+        };
+
+        if !receiver.is_complete() {
+            let chunks = receiver.missing(SYNC_WINDOW);
+            self.send_to_peer(&Message::BlobRequest { chunks });
+            return;
+        }
+
+        if self.state_hash.is_none() {
+            match receiver.verify() {
+                Some(blobs) => {
+                    info!("Host state downloaded and verified");
+                    self.state_hash = Some(state_hash(&blobs));
+                    self.blobs = blobs;
+                }
+                None => {
+                    warn!("Host state failed verification, downloading it again");
+                    if receiver.failures() >= MAX_SYNC_FAILURES {
+                        self.close_with("State transfer failed verification".to_string());
+                    }
+                    return;
+                }
+            }
+        }
+
+        // Repeated until the host tells us to start
+        if let Some(hash) = self.state_hash.clone() {
+            self.send_to_peer(&Message::SyncDone { hash });
+        }
+    }
+
+    /// (Re)send the chat lines the other side doesn't have yet
+    fn send_chat_lines(&mut self) {
+        self.last_chat = Instant::now();
+
+        let outgoing = match self.role {
+            SessionRole::Host => &self.chat_log,
+            SessionRole::Client | SessionRole::Spectator => &self.chat_outbox,
+        };
+
+        let mut pending = Vec::new();
+        if let Some(peer) = self.peer
+            && let Some(msg) = self.peer_chat.pending(outgoing) {
+            pending.push((peer, msg));
+        }
+        for s in &self.spectators {
+            if let Some(msg) = s.chat.pending(outgoing) {
+                pending.push((s.addr, msg));
+            }
+        }
+
+        for (to, msg) in pending {
+            self.send(to, &msg);
+        }
+    }
+
+    fn check_frame_hashes(&mut self) {
+        for (&frame, &(local, remote)) in &self.frame_hashes {
+            if let (Some(local), Some(remote)) = (local, remote)
+                && local != remote
+                && self.desync_frame.is_none() {
+                warn!("Netplay desync detected on frame {}", frame);
+                self.desync_frame = Some(frame);
+            }
+        }
+
+        let oldest = self.frame.saturating_sub(FRAME_HASH_HISTORY);
+        self.frame_hashes.retain(|&f, &mut (l, r)| f >= oldest && (l.is_none() || r.is_none()));
+    }
+
+    fn send_ping(&mut self) {
+        let id = self.next_ping_id;
+        self.next_ping_id = self.next_ping_id.wrapping_add(1);
+
+        // Forget about pings that will never be answered
+        self.pings.retain(|_, sent| sent.elapsed() < PEER_TIMEOUT);
+        self.pings.insert(id, Instant::now());
+        self.last_ping = Instant::now();
+
+        self.send_to_peer(&Message::Ping { id });
+    }
+
+    fn send_inputs(&mut self) {
+        if self.local_port.is_none() {
+            return;
+        }
+
+        // First frame for which we're still missing the peer's input
+        let ack = self.frame + self.ready_frames() as u64;
+
+        self.send_to_peer(&Message::Input {
+            first_frame: self.local_window_start,
+            pads: self.local_window.iter().copied().collect(),
+            ack,
+        });
+    }
+
+    fn stream_to_spectators(&mut self) {
+        for i in 0..self.spectators.len() {
+            let addr = self.spectators[i].addr;
+            // Kept by `trim_history`
+            let Some(start) = self.spectators[i].acked.checked_sub(self.history_start) else {
+                continue;
+            };
+
+            let start = (start as usize).min(self.history.len());
+            let pads: Vec<_> = self.history.range(start..).take(CONFIRMED_CHUNK).copied().collect();
+            if pads.is_empty() {
+                continue;
+            }
+
+            let msg = Message::Confirmed {
+                first_frame: self.spectators[i].acked,
+                pads,
+            };
+
+            self.send(addr, &msg);
+        }
+    }
+
+    /// Drop the confirmed frames before the last checkpoint that every spectator has
+    fn trim_history(&mut self) {
+        let needed = self.spectators.iter().map(|s| s.acked).fold(self.checkpoint_frame, u64::min);
+
+        while self.history_start < needed && self.history.pop_front().is_some() {
+            self.history_start += 1;
+        }
+    }
+
+    fn send_to_peer(&mut self, msg: &Message) {
+        if let Some(peer) = self.peer {
+            self.send(peer, msg);
+        }
+    }
+
+    fn send(&mut self, to: SocketAddr, msg: &Message) {
+        if let Err(e) = self.transport.send(to, &msg.encode()) {
+            warn!("Failed to send netplay message to {}: {}", to, e);
+        }
+    }
+}
+
+#[test]
+fn auto_input_delay() {
+    assert_eq!(InputDelay::Auto.resolve(None), FALLBACK_INPUT_DELAY);
+    assert_eq!(InputDelay::Auto.resolve(Some(Duration::from_millis(10))), 2);
+    assert_eq!(InputDelay::Auto.resolve(Some(Duration::from_millis(120))), 5);
+    assert_eq!(InputDelay::Auto.resolve(Some(Duration::from_secs(5))), MAX_INPUT_DELAY);
+    assert_eq!(InputDelay::Fixed(40).resolve(None), MAX_INPUT_DELAY);
+}
@@ -0,0 +1,266 @@
+//! Transfer of the host's starting state (memory cards and save state) to the other peers before a
+//! session starts. Blobs are split in chunks that the receiver pulls until it has everything, then
+//! each blob is checked against the SHA-256 announced by the host.
+
+use mips_core::mem_card::CARD_SIZE;
+use sha::sha256::Sha256;
+use sha::utils::{Digest, DigestExt};
+use crate::netplay::protocol::{BlobInfo, BlobKind};
+
+/// Size of a blob chunk. Chunks are hex encoded so they take twice that in a datagram.
+pub const CHUNK_SIZE: usize = 1024;
+
+/// Most blobs a host may announce: two memory cards and a save state
+const MAX_BLOBS: usize = 3;
+/// Largest save state a host may announce, the compressed states are far smaller
+const MAX_SAVE_STATE_LEN: u32 = 16 * 1024 * 1024;
+
+/// Piece of state the host shares with the other peers
+#[derive(Clone)]
+pub struct Blob {
+    pub kind: BlobKind,
+    pub data: Vec<u8>,
+}
+
+impl Blob {
+    pub fn info(&self) -> BlobInfo {
+        BlobInfo {
+            kind: self.kind,
+            len: self.data.len() as u32,
+            sha256: sha256_hex(&self.data),
+        }
+    }
+
+    pub fn chunk(&self, chunk: u32) -> Option<&[u8]> {
+        let start = chunk as usize * CHUNK_SIZE;
+        if start >= self.data.len() {
+            return None;
+        }
+
+        let end = (start + CHUNK_SIZE).min(self.data.len());
+
+        Some(&self.data[start..end])
+    }
+}
+
+/// Hash summing up the whole starting state. Both sides compute it from the blobs they actually
+/// hold, the host only starts the session if they match.
+pub fn state_hash(blobs: &[Blob]) -> String {
+    let mut summary = Vec::new();
+
+    for b in blobs {
+        summary.extend_from_slice(format!("{:?}:", b.kind).as_bytes());
+        summary.extend_from_slice(sha256_hex(&b.data).as_bytes());
+    }
+
+    sha256_hex(&summary)
+}
+
+/// Reassembles the blobs announced by the host
+pub struct BlobReceiver {
+    manifest: Vec<BlobInfo>,
+    data: Vec<Vec<u8>>,
+    received: Vec<Vec<bool>>,
+    /// Number of blobs that failed verification so far
+    failures: u32,
+}
+
+impl BlobReceiver {
+    /// Receiver for the blobs of `manifest`, refused before allocating anything if it announces
+    /// more blobs or larger ones than a host has to share
+    pub fn new(manifest: Vec<BlobInfo>) -> Result<BlobReceiver, String> {
+        check_manifest(&manifest)?;
+
+        let data = manifest.iter().map(|b| vec![0; b.len as usize]).collect();
+        let received = manifest.iter().map(|b| vec![false; chunk_count(b.len)]).collect();
+
+        Ok(BlobReceiver {
+            manifest,
+            data,
+            received,
+            failures: 0,
+        })
+    }
+
+    pub fn insert(&mut self, blob: u8, chunk: u32, hex: &str) {
+        let (blob, chunk) = (blob as usize, chunk as usize);
+
+        let Some(received) = self.received.get_mut(blob) else {
+            return;
+        };
+        if chunk >= received.len() || received[chunk] {
+            return;
+        }
+
+        let Some(bytes) = from_hex(hex) else {
+            return;
+        };
+
+        let start = chunk * CHUNK_SIZE;
+        let end = (start + CHUNK_SIZE).min(self.data[blob].len());
+        if bytes.len() != end - start {
+            return;
+        }
+
+        self.data[blob][start..end].copy_from_slice(&bytes);
+        received[chunk] = true;
+    }
+
+    pub fn blob_count(&self) -> usize {
+        self.manifest.len()
+    }
+
+    /// Up to `max` (blob, chunk) pairs we're still missing
+    pub fn missing(&self, max: usize) -> Vec<(u8, u32)> {
+        self.received.iter()
+            .enumerate()
+            .flat_map(|(b, chunks)| {
+                chunks.iter()
+                    .enumerate()
+                    .filter(|(_, r)| !**r)
+                    .map(move |(c, _)| (b as u8, c as u32))
+            })
+            .take(max)
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received.iter().all(|chunks| chunks.iter().all(|&r| r))
+    }
+
+    /// Fraction of the chunks received so far
+    pub fn progress(&self) -> f32 {
+        let total: usize = self.received.iter().map(|c| c.len()).sum();
+        if total == 0 {
+            return 1.;
+        }
+
+        let done = self.received.iter().flatten().filter(|&&r| r).count();
+
+        done as f32 / total as f32
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Check the complete blobs against the manifest. Blobs that don't match are dropped so that
+    /// they get downloaded again.
+    pub fn verify(&mut self) -> Option<Vec<Blob>> {
+        let mut ok = true;
+
+        for (i, info) in self.manifest.iter().enumerate() {
+            if sha256_hex(&self.data[i]) != info.sha256 {
+                ok = false;
+                self.failures += 1;
+                self.received[i].iter_mut().for_each(|r| *r = false);
+            }
+        }
+
+        if !ok {
+            return None;
+        }
+
+        Some(self.manifest.iter()
+            .zip(self.data.iter())
+            .map(|(info, data)| Blob { kind: info.kind, data: data.clone() })
+            .collect())
+    }
+}
+
+fn check_manifest(manifest: &[BlobInfo]) -> Result<(), String> {
+    if manifest.len() > MAX_BLOBS {
+        return Err(format!("Host announced {} blobs", manifest.len()));
+    }
+
+    for (i, info) in manifest.iter().enumerate() {
+        if manifest[..i].iter().any(|other| other.kind == info.kind) {
+            return Err(format!("Host announced {:?} twice", info.kind));
+        }
+
+        let valid = match info.kind {
+            BlobKind::MemoryCard(slot) => slot < 2 && info.len as usize == CARD_SIZE,
+            BlobKind::SaveState => info.len > 0 && info.len <= MAX_SAVE_STATE_LEN,
+        };
+        if !valid {
+            return Err(format!("Host announced an invalid {:?} of {} bytes", info.kind, info.len));
+        }
+    }
+
+    Ok(())
+}
+
+fn chunk_count(len: u32) -> usize {
+    (len as usize).div_ceil(CHUNK_SIZE)
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::default().digest(data).to_bytes())
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[test]
+fn blob_transfer() {
+    let state = Blob {
+        kind: BlobKind::SaveState,
+        data: (0..(CHUNK_SIZE * 3 + 17)).map(|i| i as u8).collect(),
+    };
+
+    let mut rx = BlobReceiver::new(vec![state.info()]).unwrap();
+    assert_eq!(rx.missing(100).len(), 4);
+
+    // Receive out of order, with a duplicate
+    for c in [3, 1, 0, 1] {
+        rx.insert(0, c, &to_hex(state.chunk(c).unwrap()));
+    }
+    assert_eq!(rx.missing(100), vec![(0, 2)]);
+    assert!(!rx.is_complete());
+
+    // Corrupted chunk
+    let mut bad = state.chunk(2).unwrap().to_vec();
+    bad[0] ^= 1;
+    rx.insert(0, 2, &to_hex(&bad));
+    assert!(rx.is_complete());
+    assert!(rx.verify().is_none());
+    assert_eq!(rx.failures(), 1);
+    assert_eq!(rx.missing(100).len(), 4);
+
+    for c in 0..4 {
+        rx.insert(0, c, &to_hex(state.chunk(c).unwrap()));
+    }
+    let blobs = rx.verify().unwrap();
+    assert_eq!(blobs[0].data, state.data);
+    assert_eq!(state_hash(&blobs), state_hash(&[state]));
+}
+
+#[test]
+fn manifest_checks() {
+    let info = |kind, len| BlobInfo { kind, len, sha256: String::new() };
+
+    let cards = vec![info(BlobKind::MemoryCard(0), CARD_SIZE as u32), info(BlobKind::MemoryCard(1), CARD_SIZE as u32)];
+    assert!(BlobReceiver::new(cards.clone()).is_ok());
+
+    // A card of another size, a third slot, a huge state, a duplicate and too many blobs
+    assert!(BlobReceiver::new(vec![info(BlobKind::MemoryCard(0), u32::MAX)]).is_err());
+    assert!(BlobReceiver::new(vec![info(BlobKind::MemoryCard(2), CARD_SIZE as u32)]).is_err());
+    assert!(BlobReceiver::new(vec![info(BlobKind::SaveState, u32::MAX)]).is_err());
+    assert!(BlobReceiver::new(vec![info(BlobKind::SaveState, 1), info(BlobKind::SaveState, 1)]).is_err());
+
+    let mut many = cards;
+    many.extend([info(BlobKind::SaveState, 1), info(BlobKind::SaveState, 1)]);
+    assert!(BlobReceiver::new(many).is_err());
+}
@@ -0,0 +1,64 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use crate::netplay::protocol::MAX_DATAGRAM;
+
+/// Unreliable datagram channel used by the netplay session. The session handles loss and
+/// reordering itself, so implementations only need to move packets around.
+pub trait Transport {
+    fn send(&mut self, to: SocketAddr, payload: &[u8]) -> io::Result<()>;
+
+    /// Non-blocking receive, returns `None` when nothing is pending
+    fn recv(&mut self) -> io::Result<Option<(SocketAddr, Vec<u8>)>>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Code other peers can use to find us, for transports going through a lobby server
+    fn lobby_code(&self) -> Option<String> {
+        None
+    }
+
+    /// True if datagrams to `addr` don't go directly to it
+    fn is_relayed(&self, _addr: SocketAddr) -> bool {
+        false
+    }
+}
+
+/// Plain UDP socket
+pub struct UdpTransport {
+    socket: UdpSocket,
+    buf: Box<[u8; MAX_DATAGRAM]>,
+}
+
+impl UdpTransport {
+    /// Bind to `port` on all interfaces, 0 picks any free port
+    pub fn bind(port: u16) -> io::Result<UdpTransport> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_nonblocking(true)?;
+
+        Ok(UdpTransport {
+            socket,
+            buf: Box::new([0; MAX_DATAGRAM]),
+        })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, to: SocketAddr, payload: &[u8]) -> io::Result<()> {
+        self.socket.send_to(payload, to).map(|_| ())
+    }
+
+    fn recv(&mut self) -> io::Result<Option<(SocketAddr, Vec<u8>)>> {
+        match self.socket.recv_from(&mut self.buf[..]) {
+            Ok((len, from)) => Ok(Some((from, self.buf[..len].to_vec()))),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            // On Windows an ICMP "port unreachable" from a previous send shows up here, it's
+            // not fatal for a connectionless socket
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
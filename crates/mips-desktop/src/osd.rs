@@ -0,0 +1,155 @@
+//! On-screen display, text drawn over the game picture. Notifications fade out after a while (or
+//! just go away with the reduced motion), status lines stay up as long as the caller keeps passing
+//! them. Where the notifications go, how long they stay and which categories are shown is up to the
+//! OSD settings; the warnings are always shown.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use egui::{Color32, FontId, Galley, Id, LayerId, Order, Painter, Pos2, Rect, Vec2};
+use crate::accessibility;
+use crate::config::{OsdCategory, OsdPosition, OsdSettings};
+
+const MAX_NOTIFICATIONS: usize = 8;
+const FADE_OUT: Duration = Duration::from_millis(500);
+
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 4.0;
+const SPACING: f32 = 2.0;
+const FONT_SIZE: f32 = 14.0;
+
+pub struct OsdLine {
+    pub text: String,
+    pub color: Color32,
+    /// Something failed or needs the player's attention, shown whatever its category
+    pub warning: bool,
+}
+
+impl OsdLine {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self::colored(text, Color32::WHITE)
+    }
+
+    pub fn warning(text: impl Into<String>) -> Self {
+        Self { warning: true, ..Self::colored(text, Color32::from_rgb(255, 96, 96)) }
+    }
+
+    pub fn colored(text: impl Into<String>, color: Color32) -> Self {
+        Self { text: text.into(), color, warning: false }
+    }
+}
+
+struct Notification {
+    line: OsdLine,
+    expires: Instant,
+}
+
+#[derive(Default)]
+pub struct Osd {
+    notifications: VecDeque<Notification>,
+    /// Notifications disappear at once instead of fading out
+    pub reduced_motion: bool,
+    pub settings: OsdSettings,
+}
+
+impl Osd {
+    /// Show `line` for the duration of the settings, unless its category is hidden
+    pub fn notify(&mut self, category: OsdCategory, line: OsdLine) {
+        self.notify_for(category, line, Duration::ZERO);
+    }
+
+    /// `notify` for the lines that take longer to read: up for `duration` at least
+    pub fn notify_for(&mut self, category: OsdCategory, line: OsdLine, duration: Duration) {
+        if !line.warning && self.settings.hidden.contains(&category) {
+            return;
+        }
+        let duration = duration.max(Duration::from_secs(u64::from(self.settings.duration_secs)));
+
+        if self.notifications.len() >= MAX_NOTIFICATIONS {
+            self.notifications.pop_front();
+        }
+
+        self.notifications.push_back(Notification {
+            line,
+            expires: Instant::now() + duration,
+        });
+    }
+
+    /// Draw the notifications in the corner of `rect` the settings ask for, newest closest to it,
+    /// and the `status` lines in its top right corner, top left if the notifications are there.
+    /// The screen readers read the newest notification.
+    pub fn show(&mut self, ctx: &egui::Context, rect: Rect, status: &[OsdLine]) {
+        let now = Instant::now();
+        self.notifications.retain(|n| n.expires > now);
+
+        let newest = self.notifications.back().map_or("", |n| n.line.text.as_str());
+        accessibility::live_region(ctx, Id::new("osd_notifications"), newest);
+
+        let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("osd")));
+        let font = FontId::proportional(FONT_SIZE);
+
+        let position = self.settings.position;
+        let left = matches!(position, OsdPosition::TopLeft | OsdPosition::BottomLeft);
+        let top = matches!(position, OsdPosition::TopLeft | OsdPosition::TopRight);
+        let x = |left: bool, galley: &Galley| {
+            if left { rect.left() + MARGIN } else { rect.right() - MARGIN - galley.size().x - PADDING * 2.0 }
+        };
+
+        let status_left = position == OsdPosition::TopRight;
+        let mut y = rect.top() + MARGIN;
+        for line in status {
+            let galley = painter.layout_no_wrap(line.text.clone(), font.clone(), line.color);
+            let pos = Pos2::new(x(status_left, &galley), y);
+
+            y += galley.size().y + PADDING * 2.0 + SPACING;
+            draw_line(&painter, pos, galley, 1.0);
+        }
+
+        let mut y = if top { rect.top() + MARGIN } else { rect.bottom() - MARGIN };
+        for n in self.notifications.iter().rev() {
+            let remaining = n.expires - now;
+            let alpha = if self.reduced_motion {
+                1.0
+            } else {
+                (remaining.as_secs_f32() / FADE_OUT.as_secs_f32()).min(1.0)
+            };
+
+            let galley = painter.layout_no_wrap(n.line.text.clone(), font.clone(), n.line.color.gamma_multiply(alpha));
+            let height = galley.size().y + PADDING * 2.0;
+            if !top {
+                y -= height;
+            }
+            let pos = Pos2::new(x(left, &galley), y);
+            y = if top { y + height + SPACING } else { y - SPACING };
+
+            draw_line(&painter, pos, galley, alpha);
+        }
+
+        // Keep fading out even if nothing else is going on, without the fading only the
+        // notifications going away need a repaint
+        if let Some(first) = self.notifications.iter().map(|n| n.expires).min() {
+            let delay = if self.reduced_motion { first - now } else { Duration::from_millis(50) };
+            ctx.request_repaint_after(delay);
+        }
+    }
+}
+
+fn draw_line(painter: &Painter, pos: Pos2, galley: Arc<Galley>, alpha: f32) {
+    let background = Rect::from_min_size(pos, galley.size() + Vec2::splat(PADDING * 2.0));
+
+    painter.rect_filled(background, 3.0, Color32::from_black_alpha((160.0 * alpha) as u8));
+    painter.galley(pos + Vec2::splat(PADDING), galley, Color32::WHITE);
+}
+
+#[test]
+fn hidden_categories() {
+    let mut osd = Osd::default();
+    osd.settings.hidden = vec![OsdCategory::Chat];
+
+    osd.notify(OsdCategory::Chat, OsdLine::new("hi"));
+    osd.notify(OsdCategory::SaveStates, OsdLine::new("State saved in slot 1"));
+    osd.notify(OsdCategory::Chat, OsdLine::warning("Desync detected"));
+
+    let shown: Vec<&str> = osd.notifications.iter().map(|n| n.line.text.as_str()).collect();
+    assert_eq!(shown, ["State saved in slot 1", "Desync detected"]);
+}
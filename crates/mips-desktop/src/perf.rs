@@ -0,0 +1,154 @@
+//! Performance overlay: what the core did per frame over the last second, next to how long it
+//! took us to emulate it. Meant to be screenshotted into bug reports.
+
+use std::time::{Duration, Instant};
+use mips_core::FrameStats;
+use crate::osd::OsdLine;
+
+/// Frames taking longer than this can't keep up with 60fps
+const FRAME_BUDGET: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// How often the figures are refreshed
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Default, Clone, Copy)]
+struct Totals {
+    frames: u32,
+    cycles: u64,
+    draw_calls: u64,
+    polygons: u64,
+    lines: u64,
+    sprites: u64,
+    vram_transfers: u64,
+    fills: u64,
+    dma_bytes: u64,
+    audio_samples: u64,
+    duplicated: u32,
+    dropped: u32,
+    /// Host time spent emulating
+    busy: Duration,
+    slowest: Duration,
+}
+
+pub struct PerfOverlay {
+    window_start: Instant,
+    current: Totals,
+    /// Last complete window
+    shown: Option<Totals>,
+}
+
+impl Default for PerfOverlay {
+    fn default() -> Self {
+        Self {
+            window_start: Instant::now(),
+            current: Totals::default(),
+            shown: None,
+        }
+    }
+}
+
+impl PerfOverlay {
+    /// Account for a frame the core took `took` to emulate
+    pub fn record(&mut self, stats: &FrameStats, took: Duration) {
+        self.record_at(stats, took, Instant::now());
+    }
+
+    fn record_at(&mut self, stats: &FrameStats, took: Duration, now: Instant) {
+        let t = &mut self.current;
+
+        t.frames += 1;
+        t.cycles += stats.cycles;
+        t.draw_calls += u64::from(stats.draw_calls);
+        t.polygons += u64::from(stats.polygons);
+        t.lines += u64::from(stats.lines);
+        t.sprites += u64::from(stats.sprites);
+        t.vram_transfers += u64::from(stats.vram_transfers);
+        t.fills += u64::from(stats.fills);
+        t.dma_bytes += u64::from(stats.dma_bytes);
+        t.audio_samples += u64::from(stats.audio_samples);
+        t.duplicated += stats.duplicated as u32;
+        t.dropped += stats.dropped;
+        t.busy += took;
+        t.slowest = t.slowest.max(took);
+
+        if now.duration_since(self.window_start) >= WINDOW {
+            self.shown = Some(self.current);
+            self.current = Totals::default();
+            self.window_start = now;
+        }
+    }
+
+    /// Status lines for the OSD, empty until a full window has been recorded
+    pub fn lines(&self) -> Vec<OsdLine> {
+        let Some(t) = self.shown else {
+            return Vec::new();
+        };
+        let frames = u64::from(t.frames.max(1));
+
+        let average = t.busy / t.frames.max(1);
+        let time = format!(
+            "Frame {:.1} ms avg, {:.1} ms max",
+            average.as_secs_f64() * 1000.,
+            t.slowest.as_secs_f64() * 1000.
+        );
+        let time = if t.slowest > FRAME_BUDGET {
+            OsdLine::warning(time)
+        } else {
+            OsdLine::new(time)
+        };
+
+        vec![
+            time,
+            OsdLine::new(format!("{} frames, {} duplicated, {} dropped", t.frames, t.duplicated, t.dropped)),
+            OsdLine::new(format!("CPU {}k cycles/frame", t.cycles / frames / 1000)),
+            OsdLine::new(format!(
+                "GPU {} draws ({} polygons, {} lines, {} sprites)/frame",
+                t.draw_calls / frames,
+                t.polygons / frames,
+                t.lines / frames,
+                t.sprites / frames
+            )),
+            OsdLine::new(format!(
+                "GPU {} VRAM transfers ({} fills)/frame",
+                t.vram_transfers / frames,
+                t.fills / frames
+            )),
+            OsdLine::new(format!("DMA {} KiB/frame", t.dma_bytes / frames / 1024)),
+            OsdLine::new(format!("SPU {} samples/frame", t.audio_samples / frames)),
+        ]
+    }
+}
+
+#[test]
+fn averages_over_the_last_window() {
+    let start = Instant::now();
+    let mut perf = PerfOverlay {
+        window_start: start,
+        current: Totals::default(),
+        shown: None,
+    };
+
+    let stats = FrameStats {
+        cycles: 564_480,
+        draw_calls: 100,
+        polygons: 90,
+        sprites: 10,
+        dma_bytes: 8192,
+        audio_samples: 735,
+        ..FrameStats::default()
+    };
+    let ms = Duration::from_millis(1);
+
+    perf.record_at(&stats, ms, start);
+    assert!(perf.lines().is_empty());
+
+    let duplicated = FrameStats { duplicated: true, ..stats };
+    perf.record_at(&duplicated, 3 * ms, start + WINDOW);
+
+    let lines: Vec<String> = perf.lines().into_iter().map(|l| l.text).collect();
+    assert_eq!(lines[0], "Frame 2.0 ms avg, 3.0 ms max");
+    assert_eq!(lines[1], "2 frames, 1 duplicated, 0 dropped");
+    assert_eq!(lines[2], "CPU 564k cycles/frame");
+    assert_eq!(lines[3], "GPU 100 draws (90 polygons, 0 lines, 10 sprites)/frame");
+    assert_eq!(lines[5], "DMA 8 KiB/frame");
+}
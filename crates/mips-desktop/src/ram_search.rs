@@ -0,0 +1,262 @@
+//! RAM search window: narrows down where the game keeps a value, Cheat Engine style, and turns the
+//! addresses found into cheat codes.
+
+use mips_core::ConsoleManager;
+use mips_core::ram_search::{Filter, RamSearch, ValueSize};
+
+/// Results listed, the others are only counted
+const MAX_ROWS: usize = 200;
+
+/// The filters as picked in the window, the value is typed separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterKind {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    Increased,
+    Decreased,
+    Changed,
+    Unchanged,
+    IncreasedBy,
+    DecreasedBy,
+}
+
+impl FilterKind {
+    const ALL: [FilterKind; 10] = [
+        FilterKind::Equal,
+        FilterKind::NotEqual,
+        FilterKind::Greater,
+        FilterKind::Less,
+        FilterKind::Increased,
+        FilterKind::Decreased,
+        FilterKind::Changed,
+        FilterKind::Unchanged,
+        FilterKind::IncreasedBy,
+        FilterKind::DecreasedBy,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            FilterKind::Equal => "Equal to",
+            FilterKind::NotEqual => "Not equal to",
+            FilterKind::Greater => "Greater than",
+            FilterKind::Less => "Less than",
+            FilterKind::Increased => "Increased",
+            FilterKind::Decreased => "Decreased",
+            FilterKind::Changed => "Changed",
+            FilterKind::Unchanged => "Unchanged",
+            FilterKind::IncreasedBy => "Increased by",
+            FilterKind::DecreasedBy => "Decreased by",
+        }
+    }
+
+    /// The filter, `None` if it needs a value and `value` isn't one
+    fn filter(self, value: Option<u32>) -> Option<Filter> {
+        let filter = match self {
+            FilterKind::Increased => Filter::Increased,
+            FilterKind::Decreased => Filter::Decreased,
+            FilterKind::Changed => Filter::Changed,
+            FilterKind::Unchanged => Filter::Unchanged,
+            FilterKind::Equal => Filter::Equal(value?),
+            FilterKind::NotEqual => Filter::NotEqual(value?),
+            FilterKind::Greater => Filter::Greater(value?),
+            FilterKind::Less => Filter::Less(value?),
+            FilterKind::IncreasedBy => Filter::IncreasedBy(value?),
+            FilterKind::DecreasedBy => Filter::DecreasedBy(value?),
+        };
+
+        Some(filter)
+    }
+}
+
+pub struct RamSearchWindow {
+    size: ValueSize,
+    filter: FilterKind,
+    /// Value typed, decimal or hexadecimal with `0x`
+    value: String,
+    search: Option<RamSearch>,
+    /// Outcome of the last operation
+    status: Option<String>,
+}
+
+impl Default for RamSearchWindow {
+    fn default() -> RamSearchWindow {
+        RamSearchWindow {
+            size: ValueSize::U8,
+            filter: FilterKind::Equal,
+            value: String::new(),
+            search: None,
+            status: None,
+        }
+    }
+}
+
+impl RamSearchWindow {
+    /// `editable` allows writing the values, it's off during netplay where the peers would desync
+    pub fn ui(&mut self, ui: &mut egui::Ui, mips: &mut ConsoleManager, editable: bool) {
+        if !mips.is_running() {
+            ui.label("No console running");
+            return;
+        }
+
+        self.show_controls(ui, mips);
+        ui.separator();
+        self.show_results(ui, mips, editable);
+
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+    }
+
+    /// Drop the search once the tool is closed
+    pub fn close(&mut self) {
+        self.search = None;
+        self.status = None;
+    }
+
+    fn show_controls(&mut self, ui: &mut egui::Ui, mips: &mut ConsoleManager) {
+        let value = parse_value(&self.value).filter(|&v| v <= self.size.max());
+
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(self.search.is_none(), |ui| {
+                egui::ComboBox::from_id_salt("ram_search_size")
+                    .selected_text(self.size.name())
+                    .show_ui(ui, |ui| {
+                        for size in ValueSize::ALL {
+                            ui.selectable_value(&mut self.size, size, size.name());
+                        }
+                    });
+            });
+            egui::ComboBox::from_id_salt("ram_search_filter")
+                .selected_text(self.filter.name())
+                .show_ui(ui, |ui| {
+                    for filter in FilterKind::ALL {
+                        ui.selectable_value(&mut self.filter, filter, filter.name());
+                    }
+                });
+            ui.add(egui::TextEdit::singleline(&mut self.value).desired_width(80.0).hint_text("value"));
+        });
+
+        ui.horizontal(|ui| {
+            if self.search.is_none() {
+                let size = self.size;
+                let search = ui.add_enabled(value.is_some(), egui::Button::new("Search"))
+                    .on_hover_text("Start from the addresses holding the value");
+                if search.clicked() && let Some(value) = value {
+                    self.start(mips, |ram| match size {
+                        ValueSize::U8 => RamSearch::search_u8(ram, value as u8),
+                        ValueSize::U16 => RamSearch::search_u16(ram, value as u16),
+                        ValueSize::U32 => RamSearch::search_u32(ram, value),
+                    });
+                }
+                if ui.button("Unknown value").on_hover_text("Start from every address").clicked() {
+                    self.start(mips, |ram| RamSearch::new(ram, size));
+                }
+            } else {
+                let filter = self.filter.filter(value);
+                if ui.add_enabled(filter.is_some(), egui::Button::new("Narrow")).clicked()
+                    && let Some(filter) = filter
+                {
+                    self.narrow(mips, filter);
+                }
+                if ui.button("New search").clicked() {
+                    self.search = None;
+                    self.status = None;
+                }
+            }
+        });
+    }
+
+    fn start(&mut self, mips: &ConsoleManager, start: impl FnOnce(Vec<u8>) -> RamSearch) {
+        match mips.ram() {
+            Ok(ram) => {
+                let search = start(ram);
+                self.status = Some(format!("{} addresses", search.results().len()));
+                self.search = Some(search);
+            }
+            Err(e) => self.status = Some(e.to_string()),
+        }
+    }
+
+    fn narrow(&mut self, mips: &ConsoleManager, filter: Filter) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+
+        match mips.ram() {
+            Ok(ram) => {
+                search.narrow(ram, filter);
+                self.status = Some(format!("{} addresses left", search.results().len()));
+            }
+            Err(e) => self.status = Some(e.to_string()),
+        }
+    }
+
+    fn show_results(&mut self, ui: &mut egui::Ui, mips: &mut ConsoleManager, editable: bool) {
+        let Some(search) = &self.search else {
+            ui.label("Search for the value the game shows, then narrow down as it changes");
+            return;
+        };
+
+        let results = search.results();
+        if results.is_empty() {
+            ui.label("No address left");
+            return;
+        }
+        if results.len() > MAX_ROWS {
+            ui.label(format!("{} addresses, narrow down to list them", results.len()));
+            return;
+        }
+
+        let size = search.size();
+        let value = parse_value(&self.value).filter(|&v| v <= size.max());
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            egui::Grid::new("ram_search_results").striped(true).show(ui, |ui| {
+                ui.strong("Address");
+                ui.strong("Value");
+                ui.strong("Previous");
+                ui.end_row();
+
+                for &addr in results {
+                    // KSEG0 like the games' pointers
+                    let current = mips.read_memory(0x8000_0000 | addr, size.bytes())
+                        .map(|bytes| size.read(&bytes, 0))
+                        .unwrap_or(0);
+
+                    ui.monospace(format!("{:08x}", 0x8000_0000 | addr));
+                    ui.monospace(current.to_string());
+                    ui.monospace(search.previous(addr).to_string());
+
+                    if ui.button("Copy code").on_hover_text("Cheat code keeping the current value").clicked() {
+                        let codes: Vec<String> = search.cheat_codes(addr, current)
+                            .iter()
+                            .map(|code| code.to_string())
+                            .collect();
+                        ui.ctx().copy_text(codes.join("\n"));
+                        self.status = Some("Code copied, add it to a cheat database".to_string());
+                    }
+
+                    let write = ui.add_enabled(editable && value.is_some(), egui::Button::new("Write value"));
+                    if write.clicked() && let Some(value) = value {
+                        let bytes = value.to_le_bytes();
+                        if let Err(e) = mips.write_memory(0x8000_0000 | addr, &bytes[..size.bytes()]) {
+                            self.status = Some(format!("Failed to write: {}", e));
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+/// Decimal, or hexadecimal with `0x`
+fn parse_value(text: &str) -> Option<u32> {
+    let text = text.trim();
+
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -0,0 +1,82 @@
+//! Recovering from configurations that crash the frontend.
+//!
+//! A sentinel file exists while the window is open and is removed on a clean exit, so finding it
+//! at startup means the last run crashed (or was killed). The user is then offered to restart in
+//! safe mode: software rendering, default settings, no per-game patches and no online services.
+
+use std::{env, fs, io, process};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Command line flag starting the frontend in safe mode
+pub const SAFE_MODE_FLAG: &str = "--safe-mode";
+
+pub struct RunSentinel {
+    path: PathBuf,
+    previous_run_crashed: bool,
+}
+
+impl RunSentinel {
+    /// Mark the frontend as running
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let previous_run_crashed = path.exists();
+        if previous_run_crashed {
+            warn!("{} exists, the last run didn't exit properly", path.display());
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, process::id().to_string())?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            previous_run_crashed,
+        })
+    }
+
+    pub fn previous_run_crashed(&self) -> bool {
+        self.previous_run_crashed
+    }
+
+    /// Clean exit. Not done on drop: a panic unwinding out of the main loop is a crash too.
+    pub fn remove(&self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Start a new instance of the frontend in safe mode, with the same arguments as this one. The
+/// caller is expected to quit right after.
+pub fn relaunch() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).filter(|arg| arg != SAFE_MODE_FLAG).collect();
+
+    info!("Restarting in safe mode");
+    process::Command::new(env::current_exe()?)
+        .args(args)
+        .arg(SAFE_MODE_FLAG)
+        .spawn()?;
+
+    Ok(())
+}
+
+#[test]
+fn sentinel_detects_unclean_exits() {
+    let path = env::temp_dir().join(format!("mips-sentinel-test-{}", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let sentinel = RunSentinel::create(&path).unwrap();
+    assert!(!sentinel.previous_run_crashed());
+    sentinel.remove();
+
+    let sentinel = RunSentinel::create(&path).unwrap();
+    assert!(!sentinel.previous_run_crashed());
+    // Crash without removing it
+    drop(sentinel);
+
+    let sentinel = RunSentinel::create(&path).unwrap();
+    assert!(sentinel.previous_run_crashed());
+    sentinel.remove();
+    assert!(!path.exists());
+}
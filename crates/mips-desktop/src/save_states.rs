@@ -0,0 +1,285 @@
+//! Save state slots: one file per slot in the game's save directory, next to the memory cards.
+//! Saving over a slot keeps what it held and loading keeps the state that was running, so the last
+//! save or load can be undone.
+//!
+//! Auto-states are taken every few minutes of play in files of their own, rotating over
+//! `AUTO_SLOTS`, so that a crash doesn't cost a long session. They're compressed and written by a
+//! thread of their own, the game only waits for the uncompressed snapshot.
+//!
+//! The states start with a thumbnail of the screen, shown in the slot menu and in the games list
+//! without loading them.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::SystemTime;
+use egui::{ColorImage, TextureHandle, TextureOptions};
+use tracing::{info, warn};
+use mips_core::{ConsoleManager, CpuFrame};
+use crate::config;
+use crate::error::{AppError, AppResult};
+
+/// Slots offered in the menu, the control server accepts any number
+pub const MENU_SLOTS: u32 = 10;
+/// Auto-states kept per game, the oldest is replaced by the next one
+pub const AUTO_SLOTS: u32 = 3;
+/// Frames in a minute of play
+const FRAMES_PER_MINUTE: u64 = 60 * 60;
+
+/// What the last save or load replaced
+enum Undo {
+    /// `slot` held `previous` before it was saved over, `None` if it was empty
+    Save { game: String, slot: u32, previous: Option<Vec<u8>> },
+    /// State running before a slot was loaded
+    Load { game: String, state: Vec<u8> },
+}
+
+#[derive(Default)]
+pub struct SaveStates {
+    /// Slot used by the menu
+    pub slot: u32,
+    pub thumbnails: Thumbnails,
+    undo: Option<Undo>,
+}
+
+impl SaveStates {
+    /// Save the console state of `game` in `slot`, returns the file it went to
+    pub fn save(&mut self, mips: &ConsoleManager, game: &str, slot: u32) -> AppResult<PathBuf> {
+        let state = mips.save_state()?;
+        let path = config::save_state_path(game, slot);
+
+        let previous = match fs::read(&path) {
+            Ok(previous) => Some(previous),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, state)?;
+        info!("State saved to {}", path.display());
+
+        self.undo = Some(Undo::Save { game: game.to_string(), slot, previous });
+
+        Ok(path)
+    }
+
+    /// Load the state of `game` saved in `slot`. The console is untouched if it fails.
+    pub fn load(&mut self, mips: &mut ConsoleManager, game: &str, slot: u32) -> AppResult<()> {
+        let path = config::save_state_path(game, slot);
+        let state = match fs::read(&path) {
+            Ok(state) => state,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(AppError::EmptySlot(slot)),
+            Err(e) => return Err(e.into()),
+        };
+
+        self.load_state(mips, game, &path, &state)
+    }
+
+    /// Load the auto-state `index` of `game`, it can be undone like the loads of a slot
+    pub fn load_auto(&mut self, mips: &mut ConsoleManager, game: &str, index: u32) -> AppResult<()> {
+        let path = config::auto_state_path(game, index);
+        let state = fs::read(&path)?;
+
+        self.load_state(mips, game, &path, &state)
+    }
+
+    fn load_state(&mut self, mips: &mut ConsoleManager, game: &str, path: &Path, state: &[u8]) -> AppResult<()> {
+        let current = mips.save_state()?;
+        mips.load_state(state)?;
+        info!("State loaded from {}", path.display());
+
+        self.undo = Some(Undo::Load { game: game.to_string(), state: current });
+
+        Ok(())
+    }
+
+    /// Menu entry undoing the last save or load of `game`, if there's one
+    pub fn undo_label(&self, game: &str) -> Option<&'static str> {
+        match &self.undo {
+            Some(Undo::Save { game: saved, .. }) if saved == game => Some("Undo Save State"),
+            Some(Undo::Load { game: loaded, .. }) if loaded == game => Some("Undo Load State"),
+            _ => None,
+        }
+    }
+
+    /// Put back what the last save or load of `game` replaced, returns a description of it
+    pub fn undo(&mut self, mips: &mut ConsoleManager, game: &str) -> AppResult<String> {
+        match self.undo.take() {
+            Some(Undo::Save { game: saved, slot, previous }) if saved == game => {
+                let path = config::save_state_path(game, slot);
+                match previous {
+                    Some(previous) => fs::write(&path, previous)?,
+                    None => fs::remove_file(&path)?,
+                }
+                Ok(format!("Slot {} restored", slot))
+            }
+            Some(Undo::Load { game: loaded, state }) if loaded == game => {
+                mips.load_state(&state)?;
+                Ok("State before the load restored".to_string())
+            }
+            other => {
+                self.undo = other;
+                Ok("Nothing to undo".to_string())
+            }
+        }
+    }
+}
+
+/// State to compress, the file it goes to and the thumbnail saved along
+type Job = (PathBuf, Vec<u8>, Option<CpuFrame>);
+
+/// Periodic auto-states, see the module documentation
+#[derive(Default)]
+pub struct AutoStates {
+    /// Frame count of the game when the last auto-state was taken
+    last_frame: u64,
+    /// Started by the first auto-state
+    writer: Option<Sender<Job>>,
+}
+
+impl AutoStates {
+    /// Take an auto-state of `game` if `minutes` of play went by since the last one. `frames` is
+    /// the number of frames played since the game started, 0 minutes disables the auto-states.
+    pub fn poll(&mut self, mips: &ConsoleManager, game: &str, frames: u64, minutes: u32) {
+        // The game was restarted
+        if frames < self.last_frame {
+            self.last_frame = 0;
+        }
+        if minutes == 0 || frames - self.last_frame < u64::from(minutes) * FRAMES_PER_MINUTE {
+            return;
+        }
+        self.last_frame = frames;
+
+        let state = match mips.save_state_raw() {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Failed to take an auto-state: {}", e);
+                return;
+            }
+        };
+        let path = config::auto_state_path(game, next_auto_slot(game));
+
+        let writer = self.writer.get_or_insert_with(spawn_writer);
+        if let Err(mpsc::SendError(job)) = writer.send((path, state, mips.thumbnail())) {
+            // The thread died with a panic, start over
+            let writer = spawn_writer();
+            let _ = writer.send(job);
+            self.writer = Some(writer);
+        }
+    }
+}
+
+/// Auto-states of `game` as (index, time written), newest first
+pub fn auto_states(game: &str) -> Vec<(u32, SystemTime)> {
+    let mut states: Vec<_> = (0..AUTO_SLOTS)
+        .filter_map(|index| {
+            let modified = fs::metadata(config::auto_state_path(game, index)).ok()?.modified().ok()?;
+            Some((index, modified))
+        })
+        .collect();
+    states.sort_by(|a, b| b.1.cmp(&a.1));
+
+    states
+}
+
+/// Textures of the thumbnails of the states, read again when a state changes
+#[derive(Default)]
+pub struct Thumbnails {
+    /// By state file, with the time it was written
+    textures: HashMap<PathBuf, (SystemTime, Option<TextureHandle>)>,
+}
+
+impl Thumbnails {
+    /// Thumbnail of the state in `path`, `None` if there's no state or it was saved without one
+    pub fn get(&mut self, ctx: &egui::Context, path: &Path) -> Option<TextureHandle> {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+        if let Some((read, texture)) = self.textures.get(path)
+            && *read == modified
+        {
+            return texture.clone();
+        }
+
+        let texture = read_thumbnail(path).map(|frame| {
+            let rgb: Vec<u8> = frame.pixels.iter()
+                .flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+                .collect();
+            let picture = ColorImage::from_rgb([frame.width as usize, frame.height as usize], &rgb);
+            ctx.load_texture("state_thumbnail", picture, TextureOptions::LINEAR)
+        });
+        self.textures.insert(path.to_path_buf(), (modified, texture.clone()));
+
+        texture
+    }
+
+    /// Thumbnail of the state saved last among the slots and auto-states of `games` (the discs of
+    /// a game): where the game was left
+    pub fn latest(&mut self, ctx: &egui::Context, games: &[String]) -> Option<TextureHandle> {
+        let path = games.iter()
+            .flat_map(|game| {
+                let slots = (0..MENU_SLOTS).map(|slot| config::save_state_path(game, slot));
+                slots.chain((0..AUTO_SLOTS).map(|index| config::auto_state_path(game, index)))
+            })
+            .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .max_by_key(|(modified, _)| *modified)?
+            .1;
+
+        self.get(ctx, &path)
+    }
+}
+
+/// Only the beginning of the state is read
+fn read_thumbnail(path: &Path) -> Option<CpuFrame> {
+    let mut file = io::BufReader::new(fs::File::open(path).ok()?);
+
+    mips_core::state_thumbnail(&mut file).unwrap_or_else(|e| {
+        warn!("Failed to read the thumbnail of {}: {}", path.display(), e);
+        None
+    })
+}
+
+/// Auto-state of `game` to write next: a free one, or else the oldest
+fn next_auto_slot(game: &str) -> u32 {
+    let states = auto_states(game);
+
+    (0..AUTO_SLOTS)
+        .find(|index| states.iter().all(|(taken, _)| taken != index))
+        .unwrap_or_else(|| states.last().map_or(0, |&(index, _)| index))
+}
+
+fn spawn_writer() -> Sender<Job> {
+    let (sender, receiver) = mpsc::channel::<Job>();
+
+    thread::Builder::new()
+        .name("auto-state writer".to_string())
+        .spawn(move || {
+            for (path, state, thumbnail) in receiver {
+                match write_compressed(&path, &state, thumbnail.as_ref()) {
+                    Ok(()) => info!("Auto-state saved to {}", path.display()),
+                    Err(e) => warn!("Failed to save the auto-state {}: {}", path.display(), e),
+                }
+            }
+        })
+        .expect("Failed to start the auto-state writer");
+
+    sender
+}
+
+/// Write `state` compressed to `path`, with `thumbnail`. A crash while writing leaves the previous
+/// file alone.
+fn write_compressed(path: &Path, state: &[u8], thumbnail: Option<&CpuFrame>) -> AppResult<()> {
+    let state = mips_core::compress_state(state, thumbnail)?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("partial");
+    fs::write(&partial, state)?;
+    fs::rename(&partial, path)?;
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Replacement soundtracks: audio files in the game's soundtrack directory played instead of the
+//! CD-DA tracks of the disc. The first number in a file name is the track it replaces, so
+//! `02 - Opening.flac` and `Track 2.ogg` both replace track 2.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use mips_core::audio::{Soundtrack, TrackAudio, AUDIO_FREQ_HZ};
+use rodio::{Decoder, Source};
+use tracing::{info, warn};
+use crate::config;
+
+/// Extensions of the audio files taken as tracks
+const TRACK_EXTENSIONS: [&str; 4] = ["flac", "ogg", "wav", "mp3"];
+
+/// Tracks found for a game
+pub struct FileSoundtrack {
+    files: BTreeMap<u8, PathBuf>,
+}
+
+impl FileSoundtrack {
+    /// Soundtrack of `game` (a disc image file name), `None` if its directory has no track
+    pub fn find(game: &str) -> Option<FileSoundtrack> {
+        let files = track_files(&config::game_soundtrack_dir(game));
+        if files.is_empty() {
+            return None;
+        }
+
+        info!("Replacing {} audio track(s) of {}", files.len(), game);
+        Some(FileSoundtrack { files })
+    }
+}
+
+impl Soundtrack for FileSoundtrack {
+    fn open_track(&self, track: u8) -> Option<Box<dyn TrackAudio>> {
+        let path = self.files.get(&track)?;
+
+        match FileTrack::open(path) {
+            Ok(audio) => Some(Box::new(audio)),
+            Err(e) => {
+                warn!("Failed to open {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+/// Soundtrack of `game` for `ConsoleManager::set_soundtrack`
+pub fn load(game: &str) -> Option<Arc<dyn Soundtrack>> {
+    FileSoundtrack::find(game).map(|s| Arc::new(s) as Arc<dyn Soundtrack>)
+}
+
+/// Track files in `dir` by track number. When several files name the same track the first one in
+/// alphabetical order is kept.
+pub fn track_files(dir: &Path) -> BTreeMap<u8, PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return BTreeMap::new();
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| TRACK_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .collect();
+    paths.sort();
+
+    let mut files = BTreeMap::new();
+    for path in paths {
+        let number = path.file_stem().and_then(|s| s.to_str()).and_then(track_number);
+        if let Some(number) = number {
+            files.entry(number).or_insert(path);
+        }
+    }
+
+    files
+}
+
+/// First number in `name`, if it's a valid track number
+fn track_number(name: &str) -> Option<u8> {
+    let digits: String = name.chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+
+    digits.parse().ok().filter(|n| (1..=99).contains(n))
+}
+
+/// Audio file decoded on demand, resampled to 44.1 kHz stereo
+struct FileTrack {
+    decoder: Decoder<BufReader<File>>,
+    channels: usize,
+    /// Frames of the file per output pair
+    step: f64,
+    /// Output pair the next read is expected at, the file is seeked for any other
+    next: u64,
+    /// Position between the `previous` and `current` frames of the file, from 0 to 1
+    phase: f64,
+    previous: [f32; 2],
+    current: [f32; 2],
+}
+
+impl FileTrack {
+    fn open(path: &Path) -> anyhow::Result<FileTrack> {
+        let decoder = Decoder::try_from(File::open(path)?)?;
+        let channels = usize::from(decoder.channels().get());
+        let step = f64::from(decoder.sample_rate().get()) / f64::from(AUDIO_FREQ_HZ);
+
+        let mut track = FileTrack {
+            decoder,
+            channels,
+            step,
+            next: 0,
+            phase: 0.0,
+            previous: [0.0; 2],
+            current: [0.0; 2],
+        };
+        track.restart();
+
+        Ok(track)
+    }
+
+    /// Load the first two frames from the decoder's position
+    fn restart(&mut self) {
+        self.phase = 0.0;
+        self.previous = self.next_frame();
+        self.current = self.next_frame();
+    }
+
+    /// Next frame of the file as a stereo pair, silence past the end
+    fn next_frame(&mut self) -> [f32; 2] {
+        let mut frame = [0.0; 2];
+        for c in 0..self.channels {
+            let sample = self.decoder.next().unwrap_or(0.0);
+            if let Some(f) = frame.get_mut(c) {
+                *f = sample;
+            }
+        }
+        if self.channels == 1 {
+            frame[1] = frame[0];
+        }
+
+        frame
+    }
+
+    fn seek(&mut self, position: u64) {
+        let time = Duration::from_secs_f64(position as f64 / f64::from(AUDIO_FREQ_HZ));
+
+        if let Err(e) = self.decoder.try_seek(time) {
+            warn!("Failed to seek the soundtrack to {:?}: {}", time, e);
+        }
+        self.restart();
+    }
+}
+
+impl TrackAudio for FileTrack {
+    fn read(&mut self, position: u64, samples: &mut [i16]) {
+        if position != self.next {
+            self.seek(position);
+        }
+
+        for pair in samples.chunks_exact_mut(2) {
+            for (c, sample) in pair.iter_mut().enumerate() {
+                let v = self.previous[c] + (self.current[c] - self.previous[c]) * self.phase as f32;
+                *sample = (v * 32767.0).clamp(-32768.0, 32767.0) as i16;
+            }
+
+            self.phase += self.step;
+            while self.phase >= 1.0 {
+                self.phase -= 1.0;
+                self.previous = self.current;
+                self.current = self.next_frame();
+            }
+        }
+
+        self.next = position + (samples.len() / 2) as u64;
+    }
+}
+
+#[test]
+fn track_numbers() {
+    assert_eq!(track_number("02 - Opening"), Some(2));
+    assert_eq!(track_number("Track 12"), Some(12));
+    assert_eq!(track_number("Game (Track 3) 2024"), Some(3));
+    assert_eq!(track_number("Track 00"), None);
+    assert_eq!(track_number("Opening"), None);
+    assert_eq!(track_number("Track 300"), None);
+}
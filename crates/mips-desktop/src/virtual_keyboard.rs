@@ -0,0 +1,168 @@
+//! On-screen keyboard for the text fields (cheat codes, lobby codes, layout names...) when there's
+//! only a gamepad. The D-pad moves between the keys and the face buttons type, the text goes to
+//! the field focused as if it was typed on a keyboard. It isn't clickable: a click would take the
+//! focus away from the field, and whoever has a mouse has a keyboard too.
+
+use egui::Key;
+use crate::accessibility;
+use crate::gamepad_ui::{key_events, NavButton};
+
+/// Keys typing a character, in lowercase
+const CHAR_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl-", "zxcvbnm,._"];
+
+/// Keys of the bottom row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Shift,
+    Space,
+    Backspace,
+    Done,
+}
+
+const ACTIONS: [Action; 4] = [Action::Shift, Action::Space, Action::Backspace, Action::Done];
+
+impl Action {
+    fn label(self) -> &'static str {
+        match self {
+            Action::Shift => "Shift",
+            Action::Space => "Space",
+            Action::Backspace => "Delete",
+            Action::Done => "Done",
+        }
+    }
+}
+
+const ROWS: usize = CHAR_ROWS.len() + 1;
+
+#[derive(Default)]
+pub struct VirtualKeyboard {
+    row: usize,
+    col: usize,
+    /// Type the letters in uppercase
+    shift: bool,
+}
+
+impl VirtualKeyboard {
+    fn row_len(row: usize) -> usize {
+        match CHAR_ROWS.get(row) {
+            Some(chars) => chars.len(),
+            None => ACTIONS.len(),
+        }
+    }
+
+    fn char_at(&self, row: usize, col: usize) -> char {
+        let c = CHAR_ROWS[row].chars().nth(col).unwrap();
+
+        if self.shift { c.to_ascii_uppercase() } else { c }
+    }
+
+    /// Events typing what `button` asked for. Square types a space, Triangle toggles the shift,
+    /// Circle deletes and Start is Enter, which is also how the fields are left.
+    pub fn press(&mut self, button: NavButton) -> Vec<egui::Event> {
+        match button {
+            NavButton::Up => self.row = (self.row + ROWS - 1) % ROWS,
+            NavButton::Down => self.row = (self.row + 1) % ROWS,
+            NavButton::Left => self.col = (self.col + Self::row_len(self.row) - 1) % Self::row_len(self.row),
+            NavButton::Right => self.col = (self.col + 1) % Self::row_len(self.row),
+            NavButton::Accept => return self.activate(),
+            NavButton::Back => return key_events(Key::Backspace).to_vec(),
+            NavButton::Square => return vec![egui::Event::Text(" ".to_string())],
+            NavButton::Triangle => self.shift = !self.shift,
+            NavButton::Start => return key_events(Key::Enter).to_vec(),
+        }
+        self.col = self.col.min(Self::row_len(self.row) - 1);
+
+        Vec::new()
+    }
+
+    fn activate(&mut self) -> Vec<egui::Event> {
+        if self.row < CHAR_ROWS.len() {
+            return vec![egui::Event::Text(self.char_at(self.row, self.col).to_string())];
+        }
+
+        match ACTIONS[self.col] {
+            Action::Shift => {
+                self.shift = !self.shift;
+                Vec::new()
+            }
+            Action::Space => vec![egui::Event::Text(" ".to_string())],
+            Action::Backspace => key_events(Key::Backspace).to_vec(),
+            Action::Done => key_events(Key::Enter).to_vec(),
+        }
+    }
+
+    /// Name of the key selected, as the screen readers read it
+    fn selected_name(&self) -> String {
+        if self.row < CHAR_ROWS.len() {
+            return self.char_at(self.row, self.col).to_string();
+        }
+
+        match ACTIONS[self.col] {
+            Action::Shift if self.shift => "Shift, on".to_string(),
+            action => action.label().to_string(),
+        }
+    }
+
+    pub fn show(&self, ctx: &egui::Context) {
+        const KEY_SIZE: egui::Vec2 = egui::vec2(32.0, 32.0);
+
+        // The keys can't take the focus, the one selected is read out instead
+        accessibility::live_region(ctx, egui::Id::new("virtual_keyboard_key"), &self.selected_name());
+
+        egui::Area::new(egui::Id::new("virtual_keyboard"))
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -16.0])
+            .order(egui::Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    for row in 0..CHAR_ROWS.len() {
+                        ui.horizontal(|ui| {
+                            for col in 0..Self::row_len(row) {
+                                let selected = (row, col) == (self.row, self.col);
+                                let label = self.char_at(row, col).to_string();
+                                ui.add(egui::Button::new(label).selected(selected).min_size(KEY_SIZE));
+                            }
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        for (col, action) in ACTIONS.iter().enumerate() {
+                            let selected = (CHAR_ROWS.len(), col) == (self.row, self.col);
+                            let shifted = *action == Action::Shift && self.shift;
+                            let width = KEY_SIZE.x * 2.0 + ui.spacing().item_spacing.x;
+                            ui.add(egui::Button::new(action.label())
+                                .selected(selected || shifted)
+                                .min_size(egui::vec2(width, KEY_SIZE.y)));
+                        }
+                    });
+                    ui.weak("Cross: type   Circle: delete   Square: space   Triangle: shift   Start: done");
+                });
+            });
+    }
+}
+
+#[test]
+fn typing() {
+    let mut keyboard = VirtualKeyboard::default();
+    let text = |events: Vec<egui::Event>| match events.as_slice() {
+        [egui::Event::Text(text)] => text.clone(),
+        other => panic!("not text: {:?}", other),
+    };
+
+    assert_eq!(text(keyboard.press(NavButton::Accept)), "1");
+
+    // Wraps around the rows and the columns
+    keyboard.press(NavButton::Down);
+    keyboard.press(NavButton::Left);
+    assert_eq!(text(keyboard.press(NavButton::Accept)), "p");
+    keyboard.press(NavButton::Triangle);
+    assert_eq!(text(keyboard.press(NavButton::Accept)), "P");
+
+    // The bottom row is shorter: Done, at the end
+    keyboard.press(NavButton::Up);
+    keyboard.press(NavButton::Up);
+    assert_eq!(keyboard.selected_name(), "Done");
+    assert!(matches!(
+        keyboard.press(NavButton::Accept).as_slice(),
+        [egui::Event::Key { key: Key::Enter, pressed: true, .. }, _],
+    ));
+}
@@ -0,0 +1,96 @@
+//! Notices when the game stopped making progress, so that we can pause and tell the user what's
+//! going on instead of showing the same picture forever.
+//!
+//! A game is considered stuck when it hasn't drawn anything for a while and the CPU kept spinning
+//! in the same few instructions, which is what waiting on hardware we don't emulate (properly)
+//! looks like. Loading screens that don't animate keep the CPU busy all over the place, so they
+//! don't trigger it.
+
+use std::time::Duration;
+use mips_core::FrameStats;
+
+/// Emulated frames per second, the watchdog counts in emulated time so that pausing or running
+/// slowly doesn't matter
+const FRAME_RATE: u32 = 60;
+
+/// A polling loop fits in that many bytes of code
+const LOOP_SPAN: u32 = 0x100;
+
+pub struct StallWatchdog {
+    /// Frames without progress before raising the alarm, 0 when disabled
+    timeout_frames: u32,
+    /// Frames without progress so far
+    stalled_frames: u32,
+    /// Lowest and highest PC seen at the end of the stalled frames
+    pc_range: (u32, u32),
+}
+
+impl StallWatchdog {
+    /// Watchdog firing after `timeout` without progress, disabled if `timeout` is zero
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout_frames: (timeout.as_secs_f64() * f64::from(FRAME_RATE)) as u32,
+            stalled_frames: 0,
+            pc_range: (0, 0),
+        }
+    }
+
+    /// Start over, after a reset or once the user dealt with the stall
+    pub fn reset(&mut self) {
+        self.stalled_frames = 0;
+    }
+
+    /// How long the console has been stuck
+    pub fn stalled_for(&self) -> Duration {
+        Duration::from_secs_f64(f64::from(self.stalled_frames) / f64::from(FRAME_RATE))
+    }
+
+    /// Lowest and highest PC of the loop the CPU is stuck in
+    pub fn pc_range(&self) -> (u32, u32) {
+        self.pc_range
+    }
+
+    /// Account for an emulated frame. Returns true once, when the console has been stuck for the
+    /// whole timeout.
+    pub fn check(&mut self, stats: &FrameStats) -> bool {
+        if self.timeout_frames == 0 || !stats.duplicated {
+            self.stalled_frames = 0;
+            return false;
+        }
+
+        let (low, high) = self.pc_range;
+        let (low, high) = (low.min(stats.pc), high.max(stats.pc));
+
+        if self.stalled_frames == 0 || high - low > LOOP_SPAN {
+            // The CPU is doing something else, start over from this frame
+            self.stalled_frames = 0;
+            self.pc_range = (stats.pc, stats.pc);
+        } else {
+            self.pc_range = (low, high);
+        }
+
+        self.stalled_frames += 1;
+
+        self.stalled_frames == self.timeout_frames
+    }
+}
+
+#[test]
+fn fires_on_tight_loops_only() {
+    let mut watchdog = StallWatchdog::new(Duration::from_secs(1));
+    let stuck = |pc| FrameStats { duplicated: true, pc, ..FrameStats::default() };
+
+    // Busy all over the place, like a loading screen
+    for i in 0..120 {
+        assert!(!watchdog.check(&stuck(0x8001_0000 + i * 0x1000)));
+    }
+
+    let fired: Vec<bool> = (0..120).map(|i| watchdog.check(&stuck(0x8003_0000 + (i % 4) * 4))).collect();
+    assert_eq!(fired.iter().position(|&f| f), Some(59));
+    assert_eq!(fired.iter().filter(|&&f| f).count(), 1);
+    assert_eq!(watchdog.pc_range(), (0x8003_0000, 0x8003_000c));
+
+    // Drawing something is progress
+    watchdog.check(&FrameStats::default());
+    assert_eq!(watchdog.stalled_for(), Duration::ZERO);
+}
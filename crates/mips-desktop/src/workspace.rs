@@ -0,0 +1,355 @@
+//! Workspace: where the tool windows (memory cards, RAM search, log...) are. Each tool is closed,
+//! floating over the game, or docked as a tab of a panel next to the game picture. The layout is
+//! kept in `workspace.toml` across sessions, along with layouts saved under a name to switch
+//! between them.
+
+use std::collections::BTreeMap;
+use std::fs;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+use crate::accessibility;
+use crate::config;
+
+/// Width of the dock on the sides, height at the bottom, when it's first shown
+const DEFAULT_DOCK_SIZE: f32 = 360.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tool {
+    MemoryCards,
+    RamSearch,
+    Log,
+    BlockCache,
+}
+
+impl Tool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::MemoryCards => "Memory Cards",
+            Tool::RamSearch => "RAM Search",
+            Tool::Log => "Log",
+            Tool::BlockCache => "Block Cache",
+        }
+    }
+}
+
+/// Side of the game picture the docked tools are on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum DockSide {
+    Left,
+    #[default]
+    Right,
+    Bottom,
+}
+
+impl DockSide {
+    pub const ALL: [DockSide; 3] = [DockSide::Left, DockSide::Right, DockSide::Bottom];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DockSide::Left => "Left",
+            DockSide::Right => "Right",
+            DockSide::Bottom => "Bottom",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenTool {
+    pub tool: Tool,
+    pub docked: bool,
+    /// Top left corner of the window when floating, egui picks one if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pos: Option<[f32; 2]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub side: DockSide,
+    /// Width of the dock on the sides, height at the bottom
+    pub dock_size: f32,
+    /// Tab shown in the dock
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tab: Option<Tool>,
+    /// Open tools, the docked ones in the order of their tabs
+    pub tools: Vec<OpenTool>,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            side: DockSide::default(),
+            dock_size: DEFAULT_DOCK_SIZE,
+            tab: None,
+            tools: Vec::new(),
+        }
+    }
+}
+
+/// Contents of `workspace.toml`
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct WorkspaceFile {
+    current: Layout,
+    saved: BTreeMap<String, Layout>,
+}
+
+/// What the buttons of the tools asked for, applied once they're all drawn
+enum Action {
+    Select(Tool),
+    Dock(Tool, bool),
+    Close(Tool),
+}
+
+pub struct Workspace {
+    layout: Layout,
+    /// Layouts saved by name
+    saved: BTreeMap<String, Layout>,
+    /// Bumped when a layout is loaded. It's part of the ids of the windows and the dock, so that
+    /// egui forgets the positions and sizes it remembered and takes the ones of the layout.
+    generation: u32,
+    /// The layout changed since it was last written
+    dirty: bool,
+    /// Name typed to save the layout
+    name: String,
+}
+
+impl Workspace {
+    pub fn load() -> Workspace {
+        let path = config::workspace_path();
+        let file = match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).unwrap_or_else(|e| {
+                warn!("Failed to parse {}: {}. Starting from the default layout.", path.display(), e);
+                WorkspaceFile::default()
+            }),
+            Err(_) => WorkspaceFile::default(),
+        };
+
+        Workspace {
+            layout: file.current,
+            saved: file.saved,
+            generation: 0,
+            dirty: false,
+            name: String::new(),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = config::workspace_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = WorkspaceFile {
+            current: self.layout.clone(),
+            saved: self.saved.clone(),
+        };
+        fs::write(&path, toml::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+
+    /// Write the layout if it changed, once the mouse is released so that dragging a window or
+    /// the edge of the dock doesn't write it on every frame
+    pub fn persist(&mut self, ctx: &egui::Context) {
+        if !self.dirty || ctx.input(|i| i.pointer.any_down()) {
+            return;
+        }
+
+        self.dirty = false;
+        if let Err(e) = self.save() {
+            error!("Failed to save the workspace: {}", e);
+        }
+    }
+
+    pub fn is_open(&self, tool: Tool) -> bool {
+        self.layout.tools.iter().any(|t| t.tool == tool)
+    }
+
+    /// Open `tool` floating, or bring its tab to the front if it's docked
+    pub fn open(&mut self, tool: Tool) {
+        match self.layout.tools.iter().find(|t| t.tool == tool) {
+            Some(open) if open.docked => self.layout.tab = Some(tool),
+            Some(_) => (),
+            None => self.layout.tools.push(OpenTool { tool, docked: false, pos: None }),
+        }
+        self.dirty = true;
+    }
+
+    fn apply(&mut self, action: Action) {
+        match action {
+            Action::Select(tool) => self.layout.tab = Some(tool),
+            Action::Dock(tool, docked) => {
+                if let Some(open) = self.layout.tools.iter_mut().find(|t| t.tool == tool) {
+                    open.docked = docked;
+                }
+                if docked {
+                    self.layout.tab = Some(tool);
+                }
+            }
+            Action::Close(tool) => self.layout.tools.retain(|t| t.tool != tool),
+        }
+        self.dirty = true;
+    }
+
+    fn load_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.generation = self.generation.wrapping_add(1);
+        self.dirty = true;
+    }
+
+    /// The panel of the docked tools. Has to be shown before the game picture, which takes the
+    /// space left.
+    pub fn show_dock(&mut self, ctx: &egui::Context, contents: impl FnMut(Tool, &mut egui::Ui)) {
+        let docked: Vec<Tool> = self.layout.tools.iter().filter(|t| t.docked).map(|t| t.tool).collect();
+        let Some(&first) = docked.first() else {
+            return;
+        };
+        let tab = self.layout.tab.filter(|tab| docked.contains(tab)).unwrap_or(first);
+
+        let side = self.layout.side;
+        let id = egui::Id::new(("dock", side, self.generation));
+        let size = self.layout.dock_size;
+        let mut action = None;
+        let show = |ui: &mut egui::Ui| dock_contents(ui, &docked, tab, &mut action, contents);
+
+        let rect = match side {
+            DockSide::Left => egui::SidePanel::left(id).default_width(size).show(ctx, show).response.rect,
+            DockSide::Right => egui::SidePanel::right(id).default_width(size).show(ctx, show).response.rect,
+            DockSide::Bottom => {
+                egui::TopBottomPanel::bottom(id).resizable(true).default_height(size).show(ctx, show).response.rect
+            }
+        };
+
+        let size = match side {
+            DockSide::Bottom => rect.height(),
+            _ => rect.width(),
+        };
+        if (size - self.layout.dock_size).abs() >= 1.0 {
+            self.layout.dock_size = size;
+            self.dirty = true;
+        }
+        if let Some(action) = action {
+            self.apply(action);
+        }
+    }
+
+    /// The windows of the floating tools
+    pub fn show_windows(&mut self, ctx: &egui::Context, mut contents: impl FnMut(Tool, &mut egui::Ui)) {
+        let mut actions = Vec::new();
+
+        for open in self.layout.tools.iter_mut().filter(|t| !t.docked) {
+            let tool = open.tool;
+            let mut visible = true;
+            let mut window = egui::Window::new(tool.name())
+                .id(egui::Id::new(("tool", tool, self.generation)))
+                .open(&mut visible);
+            if let Some(pos) = open.pos {
+                window = window.default_pos(pos);
+            }
+
+            let response = window.show(ctx, |ui| {
+                if ui.small_button("Dock").on_hover_text("Show as a tab next to the game").clicked() {
+                    actions.push(Action::Dock(tool, true));
+                }
+                contents(tool, ui);
+            });
+
+            if let Some(response) = response {
+                let pos = response.response.rect.min;
+                if open.pos != Some([pos.x, pos.y]) {
+                    open.pos = Some([pos.x, pos.y]);
+                    self.dirty = true;
+                }
+            }
+            if !visible {
+                actions.push(Action::Close(tool));
+            }
+        }
+
+        for action in actions {
+            self.apply(action);
+        }
+    }
+
+    /// Dock side and saved layouts
+    pub fn menu(&mut self, ui: &mut egui::Ui) {
+        ui.label("Dock tools on the");
+        for side in DockSide::ALL {
+            if ui.radio_value(&mut self.layout.side, side, side.name()).clicked() {
+                self.dirty = true;
+            }
+        }
+        ui.separator();
+
+        let mut load = None;
+        let mut delete = None;
+        for (name, layout) in &self.saved {
+            ui.horizontal(|ui| {
+                if ui.button(name).on_hover_text("Switch to this layout").clicked() {
+                    load = Some(layout.clone());
+                }
+                let response = ui.small_button("🗑").on_hover_text("Delete");
+                accessibility::name(&response, egui::WidgetType::Button, &format!("Delete {}", name));
+                if response.clicked() {
+                    delete = Some(name.clone());
+                }
+            });
+        }
+        if let Some(layout) = load {
+            self.load_layout(layout);
+            ui.close_menu();
+        }
+        if let Some(name) = delete {
+            self.saved.remove(&name);
+            self.dirty = true;
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(120.0).hint_text("layout name"));
+            let name = self.name.trim();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Save")).clicked() {
+                self.saved.insert(name.to_string(), self.layout.clone());
+                self.name.clear();
+                self.dirty = true;
+            }
+        });
+        ui.separator();
+
+        if ui.button("Reset Layout").on_hover_text("Close all the tools").clicked() {
+            self.load_layout(Layout::default());
+            ui.close_menu();
+        }
+    }
+}
+
+/// Tabs of the docked tools, then the one selected
+fn dock_contents(
+    ui: &mut egui::Ui,
+    docked: &[Tool],
+    tab: Tool,
+    action: &mut Option<Action>,
+    mut contents: impl FnMut(Tool, &mut egui::Ui),
+) {
+    ui.horizontal(|ui| {
+        for &tool in docked {
+            if ui.selectable_label(tool == tab, tool.name()).clicked() {
+                *action = Some(Action::Select(tool));
+            }
+        }
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            let response = ui.small_button("✕").on_hover_text("Close");
+            accessibility::name(&response, egui::WidgetType::Button, &format!("Close {}", tab.name()));
+            if response.clicked() {
+                *action = Some(Action::Close(tab));
+            }
+            if ui.small_button("Float").on_hover_text("Show in a window of its own").clicked() {
+                *action = Some(Action::Dock(tab, false));
+            }
+        });
+    });
+    ui.separator();
+
+    // The tools scroll their contents themselves, the log has its own scrolling
+    contents(tab, ui);
+}
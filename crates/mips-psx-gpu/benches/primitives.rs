@@ -0,0 +1,79 @@
+//! Rasterizer speed on the scenes of `benches/scenes/`: `cargo bench -p mips-psx-gpu`
+//!
+//! Each scene is a GP0 command log, one hexadecimal word per line (`#` starts a comment), drawn in
+//! full once per iteration. The throughput is in draw commands.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mips_psx_gpu::handle;
+
+const SCENES: [(&str, &str); 4] = [
+    ("flat_triangles", include_str!("scenes/flat_triangles.txt")),
+    ("shaded_triangles", include_str!("scenes/shaded_triangles.txt")),
+    ("textured_quads", include_str!("scenes/textured_quads.txt")),
+    ("sprites", include_str!("scenes/sprites.txt")),
+];
+
+fn parse(scene: &str) -> Vec<u32> {
+    scene.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| u32::from_str_radix(line, 16).expect("bad command word"))
+        .collect()
+}
+
+/// Number of polygons, lines, rectangles and fills in `words`
+fn draws(words: &[u32]) -> u64 {
+    let mut count = 0;
+    let mut i = 0;
+
+    while i < words.len() {
+        let op = words[i] >> 24;
+        let len = match op {
+            0x02 => 3,
+            0x20 | 0x22 => 4,
+            0x2c => 9,
+            0x30 => 6,
+            0x64 => 4,
+            // CPU to VRAM transfer: header, then the pixels
+            0xa0 => {
+                let size = words[i + 2];
+                let pixels = (size & 0xffff) * (size >> 16);
+                3 + pixels.div_ceil(2) as usize
+            }
+            _ => 1,
+        };
+
+        if matches!(op, 0x02 | 0x20..=0x7f) {
+            count += 1;
+        }
+        i += len;
+    }
+
+    count
+}
+
+fn scenes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rasterizer");
+
+    for (name, scene) in SCENES {
+        let words = parse(scene);
+        let mut gpu = handle::start();
+
+        group.throughput(Throughput::Elements(draws(&words)));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for &word in &words {
+                    gpu.push_gp0(word);
+                }
+                // Waiting for the frame waits for the draws
+                gpu.end_of_frame();
+                gpu.take_frame()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, scenes);
+criterion_main!(benches);
@@ -1,3 +1,4 @@
 pub mod fixed_point;
 pub mod rasterizer;
-mod tests;
\ No newline at end of file
+#[cfg(test)]
+mod tests;
@@ -1,3 +1,4 @@
+use std::mem;
 use std::sync::mpsc;
 
 use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
@@ -5,12 +6,23 @@ use serde::ser::{Serialize, SerializeTuple, Serializer};
 use std::cmp::{max, min};
 use std::fmt;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 use log::{error, warn};
-use crate::ps1::psx::graphics::commands::{vram_access_dimensions, NoShading, NoTexture, Opaque, Position, Shaded, ShadingMode, TextureBlending, TextureMode, TextureRaw, TransparencyMode, Transparent};
-use crate::ps1::psx::graphics::gpu::{DisplayMode, DrawMode, MaskSettings, TextureWindow, TransparencyFunction};
-use crate::ps1::psx::graphics::rasterizer::draw::fixed_point::{FpCoord, FpVar};
-use crate::ps1::psx::graphics::rasterizer::handle::{Command, CommandBuffer, Frame, RasterizerOption};
-use crate::ps1::settings::graphics::VRamDisplayMode;
+use crate::primitive::{extend_to_i32, vram_access_dimensions, NoShading, NoTexture, Opaque, Position, Shaded, ShadingMode, TextureBlending, TextureMode, TextureRaw, TransparencyMode, Transparent};
+use crate::regs::{DisplayMode, DrawMode, MaskSettings, TextureWindow, TransparencyFunction};
+use crate::draw::fixed_point::{FpCoord, FpVar};
+use crate::handle::{Command, Frame, PixelFormat, RasterizerOption};
+use crate::renderer::Renderer;
+use crate::pgxp::{PreciseVertex, PreciseVertices};
+use crate::settings::VRamDisplayMode;
+use overdraw::Overdraw;
+use parallel::DrawBatch;
+
+mod overdraw;
+mod parallel;
+#[cfg(feature = "hardware-renderer")]
+pub mod hardware;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 enum State {
@@ -67,6 +79,11 @@ pub struct Rasterizer {
     display_off: bool,
     /// True to draw opaque pixel as semi-transparent
     force_transparency: bool,
+    /// If true semi-transparent pixels are blended with 15bit precision like the real hardware.
+    /// Otherwise we blend the full 8bit components, which is smoother but doesn't match the
+    /// rounding of the GPU.
+    #[serde(skip, default = "default_accurate_transparency")]
+    accurate_transparency: bool,
     /// Dithering tables, used for dithering, 8-to-5bit color component truncation and saturation.
     ///
     /// Here's the explanation layer by layer:
@@ -94,6 +111,39 @@ pub struct Rasterizer {
     draw_wireframe: bool,
     /// If false we don't draw triangles or quads
     draw_polygons: bool,
+    /// If false the textured draws are drawn with their vertex colors instead, gray for the raw
+    /// textures
+    #[serde(skip, default = "default_true")]
+    draw_textures: bool,
+    /// If false the semi-transparent draws are drawn opaque
+    #[serde(skip, default = "default_true")]
+    draw_semi_transparency: bool,
+    /// If true the draw commands only update the state, nothing is drawn to the VRAM (frame
+    /// skip). Transfers to, from and within the VRAM still happen.
+    skip_draws: bool,
+    /// If true draw commands are batched so that the ones targeting independent draw areas can be
+    /// rasterized in parallel
+    #[serde(skip)]
+    parallel_draw_areas: bool,
+    /// Threads rasterizing the independent draw areas, started with the first parallel batch
+    #[cfg(feature = "std")]
+    #[serde(skip)]
+    workers: Option<parallel::WorkerPool>,
+    /// Draw commands waiting to be rasterized
+    #[serde(skip)]
+    draw_batch: DrawBatch,
+    /// Precise positions of the vertices of the next polygon, see `Command::PreciseVertex`
+    #[serde(skip)]
+    precise_vertices: PreciseVertices,
+    /// Writes per pixel, output instead of the picture when set
+    #[serde(skip)]
+    overdraw: Option<Overdraw>,
+}
+
+impl Default for Rasterizer {
+    fn default() -> Rasterizer {
+        Rasterizer::new()
+    }
 }
 
 impl Rasterizer {
@@ -120,6 +170,7 @@ impl Rasterizer {
             display_vram_y_start: 0,
             display_off: true,
             force_transparency: false,
+            accurate_transparency: default_accurate_transparency(),
             dither_table: [[[0; 0x200]; 4]; 4],
             dither_enabled: false,
             dithering_force_disable: false,
@@ -127,6 +178,15 @@ impl Rasterizer {
             display_bottom_field: false,
             draw_wireframe: false,
             draw_polygons: true,
+            draw_textures: true,
+            draw_semi_transparency: true,
+            skip_draws: false,
+            parallel_draw_areas: false,
+            #[cfg(feature = "std")]
+            workers: None,
+            draw_batch: DrawBatch::default(),
+            precise_vertices: PreciseVertices::default(),
+            overdraw: None,
         }
     }
 
@@ -151,128 +211,181 @@ impl Rasterizer {
         }
     }
 
-    pub fn run(
-        &mut self,
-        command_channel: mpsc::Receiver<CommandBuffer>,
-        frame_channel: mpsc::Sender<Frame>,
-        serialization_channel: mpsc::Sender<Vec<u8>>,
-    ) {
+    /// Get ready for the first commands
+    pub fn prepare(&mut self) {
         self.rebuild_dither_table();
         self.new_frame();
+    }
 
-        loop {
-            let commands = command_channel.recv().unwrap();
-
-            let mut command_i = commands.iter();
-
-            while let Some(cmd) = command_i.next() {
-                match cmd {
-                    Command::Gp0(v) => {
-                        match self.state {
-                            State::WaitingForCommand => {
-                                let opcode = v >> 24;
-                                let h = &GP0_COMMANDS[opcode as usize];
-                                // The longest possible draw command is 12 word long (shaded and
-                                // textured quad)
-                                let mut params = [0; 12];
+    /// Process `commands`, returns false once `Command::Quit` is reached
+    pub fn process(
+        &mut self,
+        commands: &[Command],
+        frame_channel: &mpsc::Sender<Frame>,
+        serialization_channel: &mpsc::Sender<Vec<u8>>,
+    ) -> bool {
+        let mut command_i = commands.iter();
+
+        while let Some(cmd) = command_i.next() {
+            match cmd {
+                Command::Gp0(v) => {
+                    match self.state {
+                        State::WaitingForCommand => {
+                            let opcode = v >> 24;
+                            let h = &GP0_COMMANDS[opcode as usize];
+                            // The longest possible draw command is 12 word long (shaded and
+                            // textured quad)
+                            let mut params = [0; 12];
+
+                            params[0] = *v;
+
+                            let len = h.len as usize;
+
+                            for i in 1..len {
+                                // The main GPU code is supposed to send us complete draw
+                                // commands so it should be safe to expect the right number of
+                                // parameters here.
+                                match command_i.next() {
+                                    Some(Command::Gp0(v)) => params[i] = *v,
+                                    other => panic!("Expected GP0 command, got {:?}", other),
+                                }
+                            }
 
-                                params[0] = *v;
+                            // They only ever come right before their polygon
+                            let precise = mem::take(&mut self.precise_vertices);
 
-                                let len = h.len as usize;
+                            // The overdraw counts aren't shared with the workers
+                            let parallel = self.parallel_draw_areas && self.overdraw.is_none();
 
-                                for i in 1..len {
-                                    // The main GPU code is supposed to send us complete draw
-                                    // commands so it should be safe to expect the right number of
-                                    // parameters here.
-                                    match command_i.next() {
-                                        Some(Command::Gp0(v)) => params[i] = *v,
-                                        other => panic!("Expected GP0 command, got {:?}", other),
-                                    }
-                                }
+                            if parallel && !self.skip_draws && parallel::can_batch(opcode) {
+                                self.batch_draw(&params[..len], precise);
+                            } else {
+                                self.flush_draws();
 
                                 if opcode != 0xc0_u32 {
+                                    self.precise_vertices = precise;
                                     (h.handler)(self, &params[..len]);
+                                    self.precise_vertices = PreciseVertices::default();
                                 } else {
                                     // VRAM load
-                                    cmd_vram_load(self, &params[..len], &frame_channel);
+                                    cmd_vram_load(self, &params[..len], frame_channel);
                                 }
                             }
-                            State::VRamStore(ref mut store) => {
-                                let p0 = Pixel::from_mbgr1555(*v as u16);
-                                let p1 = Pixel::from_mbgr1555((*v >> 16) as u16);
-
-                                for &p in [p0, p1].iter() {
-                                    let (x, y) = store.target_vram_offset();
-
-                                    let target = self.vram.native_pixel(x, y);
-                                    if self.mask_settings.can_draw_to(target) {
-                                        self.vram.set_native_pixel(
-                                            x,
-                                            y,
-                                            self.mask_settings.mask(p),
-                                        );
-                                    }
-
-                                    if store.next().is_none() {
-                                        // End of store
-                                        self.state = State::WaitingForCommand;
-                                        break;
-                                    }
+                        }
+                        State::VRamStore(ref mut store) => {
+                            let p0 = Pixel::from_mbgr1555(*v as u16);
+                            let p1 = Pixel::from_mbgr1555((*v >> 16) as u16);
+
+                            for &p in [p0, p1].iter() {
+                                let (x, y) = store.target_vram_offset();
+
+                                let target = self.vram.native_pixel(x, y);
+                                if self.mask_settings.can_draw_to(target) {
+                                    self.vram.set_native_pixel(
+                                        x,
+                                        y,
+                                        self.mask_settings.mask(p),
+                                    );
                                 }
-                            }
-                            State::PolyLine(opcode, _) => {
-                                if *v & 0xf000_f000 == 0x5000_5000 {
-                                    // End-of-line marker
+
+                                if store.next().is_none() {
+                                    // End of store
                                     self.state = State::WaitingForCommand;
-                                } else {
-                                    // We have a new segment. The GPU code is supposed to send us
-                                    // one full vertex at a time so we should have enough in the
-                                    // buffer to continue unconditionally
-                                    let mut params = [0; 2];
-                                    let is_shaded = (opcode & 0x10) != 0;
-                                    let len = 1 + (is_shaded as usize);
-
-                                    params[0] = *v;
-                                    if is_shaded {
-                                        params[1] = match command_i.next() {
-                                            Some(Command::Gp0(v)) => *v,
-                                            other => {
-                                                panic!("Expected GP0 command, got {:?}", other)
-                                            }
-                                        };
-                                    }
-
-                                    let h = &GP0_COMMANDS[opcode as usize];
-                                    (h.handler)(self, &params[..len]);
+                                    break;
                                 }
                             }
                         }
+                        State::PolyLine(opcode, _) => {
+                            if *v & 0xf000_f000 == 0x5000_5000 {
+                                // End-of-line marker
+                                self.state = State::WaitingForCommand;
+                            } else {
+                                // We have a new segment. The GPU code is supposed to send us
+                                // one full vertex at a time so we should have enough in the
+                                // buffer to continue unconditionally
+                                let mut params = [0; 2];
+                                let is_shaded = (opcode & 0x10) != 0;
+                                let len = 1 + (is_shaded as usize);
+
+                                params[0] = *v;
+                                if is_shaded {
+                                    params[1] = match command_i.next() {
+                                        Some(Command::Gp0(v)) => *v,
+                                        other => {
+                                            panic!("Expected GP0 command, got {:?}", other)
+                                        }
+                                    };
+                                }
+
+                                let h = &GP0_COMMANDS[opcode as usize];
+                                (h.handler)(self, &params[..len]);
+                            }
+                        }
                     }
-                    Command::Gp1(v) => self.gp1(*v),
-                    Command::Quit => return,
-                    // XXX draw one line at a time
-                    Command::EndOfLine(l) => self.finish_line(*l),
-                    Command::EndOfFrame => {
-                        let frame = self.new_frame();
-                        frame_channel.send(frame).unwrap();
+                }
+                Command::PreciseVertex(index, vertex) => {
+                    self.precise_vertices[usize::from(*index)] = Some(*vertex);
+                }
+                Command::Gp1(v) => {
+                    self.flush_draws();
+                    self.gp1(*v)
+                }
+                Command::Quit => {
+                    self.flush_draws();
+                    return false;
+                }
+                // XXX draw one line at a time
+                Command::EndOfLine(l) => {
+                    if self.draw_batch.touches(self.display_area()) {
+                        self.flush_draws();
                     }
-                    Command::FieldChanged(f) => self.display_bottom_field = *f,
-                    Command::Option(opt) => self.set_option(*opt),
-                    Command::Serialize => {
-                        use serde::Serialize;
-
-                        // If there are other pending commands they would be lost by the
-                        // serialization process
-                        assert!(command_i.next().is_none());
+                    self.finish_line(*l)
+                }
+                Command::EndOfFrame => {
+                    self.flush_draws();
+                    if let Some(overdraw) = &mut self.overdraw {
+                        overdraw.end_of_frame();
+                    }
+                    let frame = self.new_frame();
+                    frame_channel.send(frame).unwrap();
+                }
+                Command::FieldChanged(f) => {
+                    self.flush_draws();
+                    self.display_bottom_field = *f
+                }
+                Command::Option(opt) => {
+                    self.flush_draws();
+                    self.set_option(*opt)
+                }
+                Command::Serialize => {
+                    self.flush_draws();
 
-                        let mut fb = flexbuffers::FlexbufferSerializer::new();
-                        self.serialize(&mut fb).unwrap();
+                    // If there are other pending commands they would be lost by the
+                    // serialization process
+                    assert!(command_i.next().is_none());
 
-                        serialization_channel.send(fb.take_buffer()).unwrap();
-                    }
+                    serialization_channel.send(self.serialize_state()).unwrap();
                 }
             }
         }
+
+        true
+    }
+
+    /// State for the save states, loaded back by `from_serialized`
+    fn serialize_state(&mut self) -> Vec<u8> {
+        use serde::Serialize;
+
+        // The state is saved at the native resolution, like the VRAM
+        let upscale_shift = self.vram.upscale_shift;
+        self.rescale_clip(upscale_shift, 0);
+
+        let mut fb = flexbuffers::FlexbufferSerializer::new();
+        self.serialize(&mut fb).unwrap();
+
+        self.rescale_clip(0, upscale_shift);
+
+        fb.take_buffer()
     }
 
     /// Returns `false` if the GPU config forbids writing to this line because it's currently
@@ -303,6 +416,7 @@ impl Rasterizer {
         match opt {
             RasterizerOption::VRamDisplayMode(v) => self.vram_display_mode = v,
             RasterizerOption::ForceTransparency(v) => self.force_transparency = v,
+            RasterizerOption::AccurateTransparency(v) => self.accurate_transparency = v,
             RasterizerOption::Draw24Bpp(v) => {
                 if v != self.draw_24bpp {
                     self.draw_24bpp = v;
@@ -315,7 +429,12 @@ impl Rasterizer {
             }
             RasterizerOption::Wireframe(v) => self.draw_wireframe = v,
             RasterizerOption::DrawPolygons(v) => self.draw_polygons = v,
+            RasterizerOption::DrawTextures(v) => self.draw_textures = v,
+            RasterizerOption::DrawSemiTransparency(v) => self.draw_semi_transparency = v,
+            RasterizerOption::SkipDraws(v) => self.skip_draws = v,
             RasterizerOption::UpscaleShift(v) => self.set_upscale_shift(v),
+            RasterizerOption::ParallelDrawAreas(v) => self.parallel_draw_areas = v && parallel::available(),
+            RasterizerOption::Overdraw(v) => self.overdraw = v.then(Overdraw::new),
         }
     }
 
@@ -324,19 +443,10 @@ impl Rasterizer {
             return;
         }
 
-        self.clip_x_min >>= self.vram.upscale_shift;
-        self.clip_y_min >>= self.vram.upscale_shift;
-        self.clip_x_max >>= self.vram.upscale_shift;
-        self.clip_x_max >>= self.vram.upscale_shift;
+        // The batched draws target the current VRAM
+        self.flush_draws();
 
-        self.clip_x_min <<= upscale_shift;
-        self.clip_y_min <<= upscale_shift;
-        self.clip_x_max <<= upscale_shift;
-        self.clip_x_max <<= upscale_shift;
-
-        // The clip is inclusive, so we need to offset when upscaling
-        self.clip_x_max += (1 << self.vram.upscale_shift) - 1;
-        self.clip_y_max += (1 << self.vram.upscale_shift) - 1;
+        self.rescale_clip(self.vram.upscale_shift, upscale_shift);
 
         let mut vram = VRam::with_upscale_shift(upscale_shift);
 
@@ -349,6 +459,16 @@ impl Rasterizer {
         self.vram = vram;
     }
 
+    /// Convert the clipping area from the `from` upscale shift to `to`
+    fn rescale_clip(&mut self, from: u8, to: u8) {
+        self.clip_x_min = (self.clip_x_min >> from) << to;
+        self.clip_y_min = (self.clip_y_min >> from) << to;
+
+        // The clip is inclusive, so we need to offset when upscaling
+        self.clip_x_max = ((self.clip_x_max >> from) << to) + (1 << to) - 1;
+        self.clip_y_max = ((self.clip_y_max >> from) << to) + (1 << to) - 1;
+    }
+
     /// Called when we should output a line to the output buffer
     pub fn finish_line(&mut self, line: u16) {
         if self.vram_display_mode != VRamDisplayMode::Native {
@@ -391,7 +511,16 @@ impl Rasterizer {
 
         let width = min(self.cur_frame.width, xres);
 
-        if self.display_mode.output_24bpp() {
+        if let Some(overdraw) = &self.overdraw {
+            let shift = self.vram.upscale_shift;
+
+            for y in 0..(1u32 << shift) {
+                for x in 0..width {
+                    let heat = overdraw.color((x_start + x) >> shift, (vram_y as u32 + y) >> shift);
+                    self.cur_frame.set_pixel(x, frame_y + y, heat);
+                }
+            }
+        } else if self.display_mode.output_24bpp() {
             // GPU is in 24bpp mode, we need to do some bitwise magic to recreate the values
             // correctly
 
@@ -448,9 +577,18 @@ impl Rasterizer {
             // DMA direction
             0x04 => (),
             0x05 => {
+                let start = (self.display_vram_x_start, self.display_vram_y_start);
+
                 // XXX from mednafen: LSB ignored.
                 self.display_vram_x_start = (val & 0x3fe) as u16;
                 self.display_vram_y_start = ((val >> 10) & 0x1ff) as u16;
+
+                let shown = self.display_area();
+                if start != (self.display_vram_x_start, self.display_vram_y_start)
+                    && let Some(overdraw) = &mut self.overdraw
+                {
+                    overdraw.flip(shown);
+                }
             }
             0x06 => {
                 self.display_column_start = (val & 0xfff) as u16;
@@ -467,30 +605,32 @@ impl Rasterizer {
         }
     }
 
-    /// Creates a new, blank frame and returns the previous one
-    fn new_frame(&mut self) -> Frame {
-        let interlaced = self.display_mode.is_true_interlaced();
+    /// Dimensions of the frames in `VRamDisplayMode::Native`, at the native resolution
+    fn native_frame_size(&self) -> (u32, u32) {
+        // XXX For now we approximate the dimensions of the visible area of the image.
+        // For better accuracy we should be emulating the output video timings more accurately
+        // but it's probably not worth it for now.
 
-        let (width, height) = match self.vram_display_mode {
-            VRamDisplayMode::Native => {
-                // XXX For now we approximate the dimensions of the visible area of the image.
-                // For better accuracy we should be emulating the output video timings more accurately
-                // but it's probably not worth it for now.
+        let width = self.display_mode.xres();
 
-                let width = self.display_mode.xres();
+        let mut height = self.display_line_end - self.display_line_start;
+        if self.display_mode.is_true_interlaced() {
+            height *= 2;
+            // Last line of the bottom field isn't drawn
 
-                let mut height = self.display_line_end - self.display_line_start;
-                if interlaced {
-                    height *= 2;
-                    // Last line of the bottom field isn't drawn
+            height -= 1;
+        }
 
-                    height -= 1;
-                }
+        (u32::from(width), u32::from(height))
+    }
 
-                let w = u32::from(width) << self.vram.upscale_shift;
-                let h = u32::from(height) << self.vram.upscale_shift;
+    /// Creates a new, blank frame and returns the previous one
+    fn new_frame(&mut self) -> Frame {
+        let (width, height) = match self.vram_display_mode {
+            VRamDisplayMode::Native => {
+                let (w, h) = self.native_frame_size();
 
-                (w, h)
+                (w << self.vram.upscale_shift, h << self.vram.upscale_shift)
             }
             mode => {
                 let (w, h) = mode.max_res();
@@ -540,6 +680,12 @@ impl Rasterizer {
             }
         };
 
+        self.cur_frame.format = if self.draw_24bpp {
+            PixelFormat::Rgb888
+        } else {
+            PixelFormat::Rgb555
+        };
+
         if width == self.cur_frame.width && height == self.cur_frame.height {
             self.cur_frame.clone()
         } else {
@@ -699,27 +845,40 @@ impl Rasterizer {
             return;
         }
 
+        if let Some(overdraw) = &mut self.overdraw {
+            // Once per native pixel
+            let subpixel = (1 << self.vram.upscale_shift) - 1;
+            if x & subpixel == 0 && y & subpixel == 0 {
+                overdraw.count(x >> self.vram.upscale_shift, y >> self.vram.upscale_shift);
+            }
+        }
+
         // If the draw command is semi-transparent and the texture mask bit is set, this is a
         // transparent pixel. If the draw command is not textured all pixels are transparent.
         let is_transparent =
             Transparency::is_transparent() && (!Texture::is_textured() || color.mask());
 
+        // The mask bit written is the texel's bit 15 (never set for the untextured draws), the
+        // blending only applies to the color components
+        let texel_mask = Texture::is_textured() && color.mask();
+
         if is_transparent {
             let mode = self.tex_mapper.draw_mode.transparency_mode();
 
-            // XXX if we wanted to be extra-accurate we might want to truncate the color here to
-            // get accurate result in 15bpp. It's unlikely to make a significant difference
-            // however.
-            color.apply_transparency(bg_pixel, mode);
-
-            if Texture::is_textured() {
-                // XXX Not entirely sure about this.
-                color.set_mask();
+            if self.accurate_transparency && !self.draw_24bpp {
+                color.apply_transparency_15bpp(bg_pixel, mode);
+            } else {
+                color.apply_transparency(bg_pixel, mode);
             }
         } else if self.force_transparency {
             color.apply_transparency(bg_pixel, TransparencyFunction::Average);
         }
 
+        if texel_mask {
+            color.set_mask();
+        }
+
+        // Then the "set mask bit" setting of GP0(E6h) forces it
         color = self.mask_settings.mask(color);
 
         self.vram.set_pixel(x, y, color);
@@ -731,6 +890,10 @@ impl Rasterizer {
         Texture: TextureMode,
         Shading: ShadingMode,
     {
+        if self.skip_draws {
+            return;
+        }
+
         // Order the vertices by y
         vertices.sort_by(|a, b| a.position.y.cmp(&b.position.y));
 
@@ -1113,11 +1276,81 @@ impl Rasterizer {
         }
     }
 
+    /// `draw_triangle` without the textures or the semi-transparency if the debug options say so
+    fn draw_debug_triangle<Transparency, Texture, Shading>(&mut self, mut vertices: [Vertex; 3])
+    where
+        Transparency: TransparencyMode,
+        Texture: TextureMode,
+        Shading: ShadingMode,
+    {
+        let opaque = Transparency::is_transparent() && !self.draw_semi_transparency;
+
+        if Texture::is_textured() && !self.draw_textures {
+            if Texture::is_raw_texture() {
+                for v in &mut vertices {
+                    v.color = Pixel::from_rgb(0x80, 0x80, 0x80);
+                }
+            }
+
+            if opaque {
+                self.draw_triangle::<Opaque, NoTexture, Shading>(vertices);
+            } else {
+                self.draw_triangle::<Transparency, NoTexture, Shading>(vertices);
+            }
+        } else if opaque {
+            self.draw_triangle::<Opaque, Texture, Shading>(vertices);
+        } else {
+            self.draw_triangle::<Transparency, Texture, Shading>(vertices);
+        }
+    }
+
+    /// `draw_rect` without the textures or the semi-transparency if the debug options say so
+    fn draw_debug_rect<Transparency, Texture>(&mut self, mut origin: Vertex, width: i32, height: i32)
+    where
+        Transparency: TransparencyMode,
+        Texture: TextureMode,
+    {
+        let opaque = Transparency::is_transparent() && !self.draw_semi_transparency;
+
+        if Texture::is_textured() && !self.draw_textures {
+            if Texture::is_raw_texture() {
+                origin.color = Pixel::from_rgb(0x80, 0x80, 0x80);
+            }
+
+            if opaque {
+                self.draw_rect::<Opaque, NoTexture>(origin, width, height);
+            } else {
+                self.draw_rect::<Transparency, NoTexture>(origin, width, height);
+            }
+        } else if opaque {
+            self.draw_rect::<Opaque, Texture>(origin, width, height);
+        } else {
+            self.draw_rect::<Transparency, Texture>(origin, width, height);
+        }
+    }
+
+    /// `draw_line` opaque if the debug options remove the semi-transparency
+    fn draw_debug_line<Transparency, Shading>(&mut self, start: Vertex, end: Vertex)
+    where
+        Transparency: TransparencyMode,
+        Shading: ShadingMode,
+    {
+        if Transparency::is_transparent() && !self.draw_semi_transparency {
+            self.draw_line::<Opaque, Shading>(start, end);
+        } else {
+            self.draw_line::<Transparency, Shading>(start, end);
+        }
+    }
+
     fn draw_rect<Transparency, Texture>(&mut self, origin: Vertex, width: i32, height: i32)
     where
         Transparency: TransparencyMode,
         Texture: TextureMode,
     {
+        if self.skip_draws {
+            return;
+        }
+
         let mut u_start = origin.u;
         let mut v = origin.v;
 
@@ -1236,6 +1469,10 @@ impl Rasterizer {
         Transparency: TransparencyMode,
         Shading: ShadingMode,
     {
+        if self.skip_draws {
+            return;
+        }
+
         // Start at the leftmost edge.
         // XXX Apparently if both sides have the same X we start from the end? This is what
         // mednafen does.
@@ -1418,6 +1655,21 @@ impl Rasterizer {
     }
 }
 
+impl Renderer for Rasterizer {
+    fn prepare(&mut self) {
+        Rasterizer::prepare(self)
+    }
+
+    fn process(
+        &mut self,
+        commands: &[Command],
+        frame_channel: &mpsc::Sender<Frame>,
+        serialization_channel: &mpsc::Sender<Vec<u8>>,
+    ) -> bool {
+        Rasterizer::process(self, commands, frame_channel, serialization_channel)
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum RasterDir {
     /// We drawing lines from top to bottom
@@ -1647,7 +1899,7 @@ fn cross_product(a: Position, b: Position, c: Position) -> i32 {
 /// Structure keeping track of the state needed to convert the extrapolated 8bit U/V values of the
 /// rasterizer into absolute coordinates in VRAM. The mapping is non-trivial because the PSX GPU
 /// uses 256x256 texture pages, coordinate masking and CLUTs of various depths.
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 struct TextureMapper {
     /// Draw mode configuration
     draw_mode: DrawMode,
@@ -1994,6 +2246,28 @@ impl Pixel {
 
         self.0 = (o_r << 16) | (o_g << 8) | o_b;
     }
+
+    /// Same as `apply_transparency` but blends the 5bit components like the real GPU does. The
+    /// result differs from the 8bit blending because of the rounding, mostly in the Average and
+    /// QuarterAdd modes where the LSBs of the 8bit components carry over.
+    fn apply_transparency_15bpp(&mut self, bg_pixel: Pixel, mode: TransparencyFunction) {
+        let blend = |shift: u32| {
+            let f = (self.0 >> (shift + 3)) & 0x1f;
+            let b = (bg_pixel.0 >> (shift + 3)) & 0x1f;
+
+            let o = match mode {
+                TransparencyFunction::Average => (f + b) >> 1,
+                TransparencyFunction::Add => min(f + b, 0x1f),
+                TransparencyFunction::Sub => b.saturating_sub(f),
+                TransparencyFunction::QuarterAdd => min((f >> 2) + b, 0x1f),
+            };
+
+            // Back to 8 bits, the same way the dithering tables do
+            ((o << 3) | (o >> 2)) << shift
+        };
+
+        self.0 = blend(16) | blend(8) | blend(0);
+    }
 }
 
 impl fmt::Display for Pixel {
@@ -2043,6 +2317,16 @@ impl Vertex {
         self.position = Position::from_command(p);
     }
 
+    /// Move the vertex, already at the internal resolution of `upscale_shift`, to the nearest
+    /// pixel of its precise position
+    fn add_precise_offset(&mut self, precise: PreciseVertex, upscale_shift: u8) {
+        // The offsets are in 1/256 native pixels
+        let offset = |o: i16| ((i32::from(o) << upscale_shift) + 0x80) >> 8;
+
+        self.position.x += offset(precise.x_offset);
+        self.position.y += offset(precise.y_offset);
+    }
+
     fn set_texture_uv(&mut self, uv: u32) {
         self.u = uv as u8;
         self.v = (uv >> 8) as u8;
@@ -2069,13 +2353,6 @@ impl Vertex {
     }
 }
 
-/// Extend a signed value on `n` bit to an i32
-fn extend_to_i32(val: u32, n: usize) -> i32 {
-    let shift = 32 - n;
-
-    ((val << shift) as i32) >> shift
-}
-
 /// Description of the various GP0 commands
 pub struct CommandHandler {
     pub handler: fn(&mut Rasterizer, params: &[u32]),
@@ -2125,6 +2402,10 @@ where
         vertex.position.x <<= rasterizer.vram.upscale_shift;
         vertex.position.y <<= rasterizer.vram.upscale_shift;
 
+        if let Some(precise) = rasterizer.precise_vertices[v] {
+            vertex.add_precise_offset(precise, rasterizer.vram.upscale_shift);
+        }
+
         if Texture::is_textured() {
             if v == 0 {
                 clut = params[index];
@@ -2148,14 +2429,14 @@ where
             vertices[1].clone(),
             vertices[2].clone(),
         ];
-        rasterizer.draw_triangle::<Transparency, Texture, Shading>(triangle);
+        rasterizer.draw_debug_triangle::<Transparency, Texture, Shading>(triangle);
 
         let triangle = [
             vertices[1].clone(),
             vertices[2].clone(),
             vertices[3].clone(),
         ];
-        rasterizer.draw_triangle::<Transparency, Texture, Shading>(triangle);
+        rasterizer.draw_debug_triangle::<Transparency, Texture, Shading>(triangle);
     }
 
     if rasterizer.draw_wireframe {
@@ -2219,6 +2500,10 @@ where
         vertex.position.x <<= rasterizer.vram.upscale_shift;
         vertex.position.y <<= rasterizer.vram.upscale_shift;
 
+        if let Some(precise) = rasterizer.precise_vertices[v] {
+            vertex.add_precise_offset(precise, rasterizer.vram.upscale_shift);
+        }
+
         if Texture::is_textured() {
             if v == 0 {
                 clut = params[index];
@@ -2237,7 +2522,7 @@ where
     }
 
     if rasterizer.draw_polygons {
-        rasterizer.draw_triangle::<Transparency, Texture, Shading>(vertices.clone());
+        rasterizer.draw_debug_triangle::<Transparency, Texture, Shading>(vertices.clone());
     }
 
     if rasterizer.draw_wireframe {
@@ -2299,7 +2584,7 @@ fn cmd_handle_rect<Transparency, Texture>(
         }
     };
 
-    rasterizer.draw_rect::<Transparency, Texture>(origin, w, h);
+    rasterizer.draw_debug_rect::<Transparency, Texture>(origin, w, h);
 }
 
 fn cmd_handle_rect_variable<Transparency, Texture>(rasterizer: &mut Rasterizer, params: &[u32])
@@ -2381,7 +2666,7 @@ where
     end_vertex.position.x <<= rasterizer.vram.upscale_shift;
     end_vertex.position.y <<= rasterizer.vram.upscale_shift;
 
-    rasterizer.draw_line::<Transparency, Shading>(start_vertex, end_vertex.clone());
+    rasterizer.draw_debug_line::<Transparency, Shading>(start_vertex, end_vertex.clone());
 
     rasterizer.state = State::PolyLine(opcode, end_vertex);
 }
@@ -2422,7 +2707,7 @@ where
     end_vertex.position.x <<= rasterizer.vram.upscale_shift;
     end_vertex.position.y <<= rasterizer.vram.upscale_shift;
 
-    rasterizer.draw_line::<Transparency, Shading>(start_vertex, end_vertex);
+    rasterizer.draw_debug_line::<Transparency, Shading>(start_vertex, end_vertex);
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -2560,6 +2845,10 @@ fn cmd_vram_load(rasterizer: &mut Rasterizer, params: &[u32], frame_channel: &mp
 
 /// Fill a rectangle with a solid color
 fn cmd_fill_rect(rasterizer: &mut Rasterizer, params: &[u32]) {
+    if rasterizer.skip_draws {
+        return;
+    }
+
     let color = Pixel::from_command(params[0]);
     let dst = params[1];
     let dim = params[2];
@@ -3710,6 +3999,14 @@ mod serialize_dither_table {
     }
 }
 
+fn default_accurate_transparency() -> bool {
+    true
+}
+
+fn default_true() -> bool {
+    true
+}
+
 /// Texture cache line containing 4 pixels
 #[derive(serde::Serialize, serde::Deserialize, Copy, Clone)]
 struct CacheLine {
@@ -3732,20 +4029,33 @@ impl CacheLine {
     }
 }
 
+/// The pixels are atomics so that the worker threads drawing independent draw areas in parallel can
+/// share them (see `parallel.rs`). Relaxed loads and stores are plain memory accesses on the
+/// architectures we care about, and the workers never touch each other's pixels.
 pub struct VRam {
-    pub(crate) pixels: Vec<Pixel>,
+    pixels: Arc<[AtomicU32]>,
     /// Upscale shift value. 0 for native.
     upscale_shift: u8,
 }
 
 impl VRam {
     fn with_upscale_shift(upscale_shift: u8) -> VRam {
+        let npixels = (1024 << upscale_shift) * (512 << upscale_shift);
+
         VRam {
-            pixels: vec![Pixel::black(); (1024 << upscale_shift) * (512 << upscale_shift)],
+            pixels: (0..npixels).map(|_| AtomicU32::new(Pixel::black().0)).collect(),
             upscale_shift,
         }
     }
 
+    /// Returns a VRam drawing to the same pixels
+    fn share(&self) -> VRam {
+        VRam {
+            pixels: self.pixels.clone(),
+            upscale_shift: self.upscale_shift,
+        }
+    }
+
     /// Returns the pixel at x, y where x an y are in native 1x coordinates.
     fn native_pixel(&self, x: u16, y: u16) -> Pixel {
         self.pixel(
@@ -3768,11 +4078,11 @@ impl VRam {
     }
 
     /// Returns the pixel at x, y where x and y are in upscaled coordinates
-    fn pixel(&self, x: u32, y: u32) -> Pixel {
+    pub(crate) fn pixel(&self, x: u32, y: u32) -> Pixel {
         let x = x as usize;
         let y = y as usize;
 
-        self.pixels[(1024 << self.upscale_shift) * y + x]
+        Pixel(self.pixels[(1024 << self.upscale_shift) * y + x].load(Ordering::Relaxed))
     }
 
     /// Sets the pixel at x, y where x and y are in upscaled coordinates
@@ -3780,7 +4090,7 @@ impl VRam {
         let x = x as usize;
         let y = y as usize;
 
-        self.pixels[(1024 << self.upscale_shift) * y + x] = p
+        self.pixels[(1024 << self.upscale_shift) * y + x].store(p.0, Ordering::Relaxed)
     }
 }
 
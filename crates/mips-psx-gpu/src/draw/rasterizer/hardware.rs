@@ -0,0 +1,1689 @@
+//! Hardware renderer: the draw commands are turned into triangles and drawn by the host's GPU
+//! through wgpu, at the internal resolution.
+//!
+//! A `Rasterizer` runs alongside as the tracker: every command goes through it so that it keeps
+//! the GPU state (draw area, texture page, mask settings, display...) but it never draws, its VRAM
+//! only gets the VRAM stores and is filled from the GPU when the state is saved or the VRAM read
+//! back. This way save states are the ones of the software rasterizer. If the GPU fails a readback
+//! the tracker takes over and draws from then on, with the VRAM it has.
+//!
+//! The VRAM lives in an RGBA8 texture holding what the software rasterizer stores for a pixel, the
+//! mask bit in alpha. Textured draws read from a copy of it, refreshed when they texture from an
+//! area drawn since the last copy. The semi-transparency functions map to the blend states, check
+//! mask to a blend on the destination alpha.
+//!
+//! Not emulated, compared to the software rasterizer: the mask check of the semi-transparent draws
+//! and of the VRAM transfers, the lines not drawn while they're displayed interlaced, the texture
+//! cache, the wireframe, forced transparency and 24bit drawing.
+
+use std::mem;
+use std::ops::Range;
+use std::sync::{mpsc, OnceLock};
+use log::{info, warn};
+use wgpu::util::DeviceExt;
+use crate::primitive::vram_access_dimensions;
+use crate::regs::TransparencyFunction;
+use crate::handle::{Command, CommandBuffer, Frame, PixelFormat, RasterizerOption};
+use crate::renderer::Renderer;
+use crate::pgxp::PreciseVertices;
+use crate::settings::VRamDisplayMode;
+use super::parallel::{texture_sources, VRamArea};
+use super::{cmd_vram_load, extend_to_i32, Pixel, Rasterizer, State, Vertex, GP0_COMMANDS};
+
+/// Format of all the textures
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// `HwVertex::flags`, same as in vram.wgsl
+const TEXTURED: u32 = 1;
+const RAW_TEXTURE: u32 = 2;
+const DITHER: u32 = 4;
+const SET_MASK: u32 = 8;
+const ONLY_OPAQUE_TEXELS: u32 = 16;
+const ONLY_SEMI_TEXELS: u32 = 32;
+const UPLOAD: u32 = 64;
+
+/// Device and pipelines, shared by all the hardware renderers. Opening a device takes a while so
+/// it's only done once, switching renderers or loading states doesn't wait for it.
+pub struct GpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    /// Layout of `Textures::draw_bind_group`
+    draw_layout: wgpu::BindGroupLayout,
+    /// Layout of `Textures::view_bind_group`
+    view_layout: wgpu::BindGroupLayout,
+    /// Indexed by `Blend`
+    draw_pipelines: Vec<wgpu::RenderPipeline>,
+    downsample_pipeline: wgpu::RenderPipeline,
+    display_pipeline: wgpu::RenderPipeline,
+}
+
+static CONTEXT: OnceLock<Option<GpuContext>> = OnceLock::new();
+
+impl GpuContext {
+    /// The context of the first usable GPU, `None` if there's none
+    pub fn shared() -> Option<&'static GpuContext> {
+        CONTEXT.get_or_init(GpuContext::open).as_ref()
+    }
+
+    fn open() -> Option<GpuContext> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+
+        let adapter = match pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        })) {
+            Ok(a) => a,
+            Err(e) => {
+                warn!("No GPU adapter for the hardware renderer: {}", e);
+                return None;
+            }
+        };
+
+        let (device, queue) = match pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("PSX GPU"),
+            // The VRAM at the highest internal resolution doesn't fit in the default limits
+            required_limits: adapter.limits(),
+            ..Default::default()
+        })) {
+            Ok(d) => d,
+            Err(e) => {
+                warn!("Failed to open {} for the hardware renderer: {}", adapter.get_info().name, e);
+                return None;
+            }
+        };
+
+        info!("Hardware renderer on {}", adapter.get_info().name);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("hardware/vram.wgsl"));
+
+        let uniform = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let draw_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("PSX draw"),
+            entries: &[uniform, texture(1), texture(2)],
+        });
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("PSX view"),
+            entries: &[uniform, texture(1)],
+        });
+
+        let pipeline = |layout: &wgpu::BindGroupLayout,
+                        vertex: &str,
+                        fragment: &str,
+                        buffers: &[wgpu::VertexBufferLayout],
+                        blend: Option<wgpu::BlendState>| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(fragment),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some(vertex),
+                    compilation_options: Default::default(),
+                    buffers,
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(fragment),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: FORMAT,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        let vertex_layout = wgpu::VertexBufferLayout {
+            array_stride: HwVertex::SIZE as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &HwVertex::ATTRIBUTES,
+        };
+
+        let draw_pipelines = Blend::ALL
+            .iter()
+            .map(|b| pipeline(&draw_layout, "vs_draw", "fs_draw", &[vertex_layout.clone()], b.state()))
+            .collect();
+        let downsample_pipeline = pipeline(&view_layout, "vs_fullscreen", "fs_downsample", &[], None);
+        let display_pipeline = pipeline(&view_layout, "vs_fullscreen", "fs_display", &[], None);
+
+        Some(GpuContext {
+            device,
+            queue,
+            draw_layout,
+            view_layout,
+            draw_pipelines,
+            downsample_pipeline,
+            display_pipeline,
+        })
+    }
+
+    fn submit(&self, encoder: wgpu::CommandEncoder) {
+        self.queue.submit([encoder.finish()]);
+    }
+
+    fn encoder(&self) -> wgpu::CommandEncoder {
+        self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default())
+    }
+
+    /// Copy `texture` to the CPU, RGBA8 rows of `width` pixels. `None` if the GPU failed to
+    /// deliver them (device lost, driver reset...).
+    fn read_texture(&self, texture: &wgpu::Texture, width: u32, height: u32) -> Option<Vec<u8>> {
+        let row_bytes = width * 4;
+        let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("PSX readback"),
+            size: u64::from(padded_row_bytes * height),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.encoder();
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            extent(width, height),
+        );
+        self.submit(encoder);
+
+        let slice = buffer.slice(..);
+        let (mapped, map_result) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| {
+            // Only fails if we already gave up waiting
+            let _ = mapped.send(r);
+        });
+
+        if let Err(e) = self.device.poll(wgpu::PollType::wait_indefinitely()) {
+            warn!("Failed to wait for the GPU: {}", e);
+            return None;
+        }
+
+        match map_result.try_recv() {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => {
+                warn!("Failed to map the readback buffer: {}", e);
+                return None;
+            }
+            Err(_) => {
+                warn!("The GPU is done but the readback buffer isn't mapped");
+                return None;
+            }
+        }
+
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+
+        Some(pixels)
+    }
+}
+
+/// Blend state of a draw
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Blend {
+    Opaque,
+    /// Opaque, only where the mask bit isn't set
+    MaskChecked,
+    Average,
+    Add,
+    Sub,
+    QuarterAdd,
+}
+
+impl Blend {
+    const ALL: [Blend; 6] = [
+        Blend::Opaque,
+        Blend::MaskChecked,
+        Blend::Average,
+        Blend::Add,
+        Blend::Sub,
+        Blend::QuarterAdd,
+    ];
+
+    fn transparent(f: TransparencyFunction) -> Blend {
+        match f {
+            TransparencyFunction::Average => Blend::Average,
+            TransparencyFunction::Add => Blend::Add,
+            TransparencyFunction::Sub => Blend::Sub,
+            TransparencyFunction::QuarterAdd => Blend::QuarterAdd,
+        }
+    }
+
+    fn state(self) -> Option<wgpu::BlendState> {
+        use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+        let component = |src_factor, dst_factor, operation| BlendComponent {
+            src_factor,
+            dst_factor,
+            operation,
+        };
+
+        // The mask bit comes from the fragment shader, except when it's checked
+        let transparent = |color| BlendState {
+            color,
+            alpha: BlendComponent::REPLACE,
+        };
+
+        match self {
+            Blend::Opaque => None,
+            Blend::MaskChecked => {
+                let keep_masked = component(
+                    BlendFactor::OneMinusDstAlpha,
+                    BlendFactor::DstAlpha,
+                    BlendOperation::Add,
+                );
+
+                Some(BlendState {
+                    color: keep_masked,
+                    alpha: keep_masked,
+                })
+            }
+            Blend::Average => Some(transparent(component(
+                BlendFactor::Constant,
+                BlendFactor::Constant,
+                BlendOperation::Add,
+            ))),
+            Blend::Add => Some(transparent(component(
+                BlendFactor::One,
+                BlendFactor::One,
+                BlendOperation::Add,
+            ))),
+            Blend::Sub => Some(transparent(component(
+                BlendFactor::One,
+                BlendFactor::One,
+                BlendOperation::ReverseSubtract,
+            ))),
+            Blend::QuarterAdd => Some(transparent(component(
+                BlendFactor::Constant,
+                BlendFactor::One,
+                BlendOperation::Add,
+            ))),
+        }
+    }
+
+    fn constant(self) -> f64 {
+        match self {
+            Blend::QuarterAdd => 0.25,
+            _ => 0.5,
+        }
+    }
+}
+
+/// Vertex of the draw pipelines, see `DrawVertex` in vram.wgsl
+#[derive(Copy, Clone, Debug)]
+struct HwVertex {
+    /// Native VRAM coordinates
+    position: [f32; 2],
+    /// 0x00BBGGRR
+    color: u32,
+    uv: [f32; 2],
+    /// CLUT position in VRAM, Y in the high half
+    clut: u32,
+    /// Texture window masks and pixel to texel shift
+    window: u32,
+    /// Texel offsets of the texture page and window, V in the high half
+    offsets: u32,
+    flags: u32,
+    /// Depth of the vertex for the perspective correction (PGXP), 1 for all the vertices of a
+    /// triangle drawn without it
+    depth: f32,
+}
+
+impl HwVertex {
+    const SIZE: usize = 40;
+
+    const ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Uint32,
+        2 => Float32x2,
+        3 => Uint32,
+        4 => Uint32,
+        5 => Uint32,
+        6 => Uint32,
+        7 => Float32,
+    ];
+
+    fn new(x: i32, y: i32, color: Pixel, flags: u32) -> HwVertex {
+        HwVertex {
+            position: [x as f32, y as f32],
+            color: u32::from(color.red()) | u32::from(color.green()) << 8 | u32::from(color.blue()) << 16,
+            uv: [0.; 2],
+            clut: 0,
+            window: 0,
+            offsets: 0,
+            flags,
+            depth: 1.,
+        }
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        let [x, y] = self.position;
+        let [u, v] = self.uv;
+
+        for f in [x, y] {
+            out.extend_from_slice(&f.to_ne_bytes());
+        }
+        out.extend_from_slice(&self.color.to_ne_bytes());
+        for f in [u, v] {
+            out.extend_from_slice(&f.to_ne_bytes());
+        }
+        for w in [self.clut, self.window, self.offsets, self.flags] {
+            out.extend_from_slice(&w.to_ne_bytes());
+        }
+        out.extend_from_slice(&self.depth.to_ne_bytes());
+    }
+}
+
+/// Draws sharing their pipeline state
+struct Segment {
+    blend: Blend,
+    /// x, y, width, height in texture pixels
+    scissor: [u32; 4],
+    vertices: Range<u32>,
+}
+
+/// Line of a VRAM store not uploaded yet
+struct UploadRow {
+    x: u16,
+    y: u16,
+    pixels: Vec<Pixel>,
+}
+
+/// Textures at the current internal resolution, and what binds them
+struct Textures {
+    vram: wgpu::Texture,
+    vram_view: wgpu::TextureView,
+    /// What the textured draws read
+    vram_read: wgpu::Texture,
+    /// 1024x512, to upload to and read from the VRAM
+    native: wgpu::Texture,
+    native_view: wgpu::TextureView,
+    globals: wgpu::Buffer,
+    /// `vram_read` and `native`, for the draws
+    draw_bind_group: wgpu::BindGroup,
+    /// `vram`, for the downsampling and the display
+    view_bind_group: wgpu::BindGroup,
+}
+
+impl Textures {
+    fn new(context: &GpuContext, upscale_shift: u8) -> Textures {
+        let device = &context.device;
+
+        let texture = |label, width, height| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: extent(width, height),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            })
+        };
+
+        let vram = texture("PSX VRAM", 1024 << upscale_shift, 512 << upscale_shift);
+        let vram_read = texture("PSX VRAM read", 1024 << upscale_shift, 512 << upscale_shift);
+        let native = texture("PSX VRAM native", 1024, 512);
+
+        let vram_view = vram.create_view(&Default::default());
+        let vram_read_view = vram_read.create_view(&Default::default());
+        let native_view = native.create_view(&Default::default());
+
+        let globals = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("PSX globals"),
+            size: 32,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let draw_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("PSX draw"),
+            layout: &context.draw_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: globals.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&vram_read_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&native_view),
+                },
+            ],
+        });
+        let view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("PSX view"),
+            layout: &context.view_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: globals.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&vram_view),
+                },
+            ],
+        });
+
+        let textures = Textures {
+            vram,
+            vram_view,
+            vram_read,
+            native,
+            native_view,
+            globals,
+            draw_bind_group,
+            view_bind_group,
+        };
+        textures.set_globals(context, upscale_shift, DisplaySource::Native15Bpp, 0, 0);
+
+        textures
+    }
+
+    fn set_globals(
+        &self,
+        context: &GpuContext,
+        upscale_shift: u8,
+        source: DisplaySource,
+        display_x: u16,
+        display_y: u16,
+    ) {
+        let mut globals = Vec::with_capacity(16);
+        for v in [
+            u32::from(upscale_shift),
+            source as u32,
+            u32::from(display_x),
+            u32::from(display_y),
+        ] {
+            globals.extend_from_slice(&v.to_ne_bytes());
+        }
+
+        context.queue.write_buffer(&self.globals, 0, &globals);
+    }
+
+    /// Keep the 8 bits of the components drawn instead of truncating them to 5 like the console
+    fn set_true_color(&self, context: &GpuContext, true_color: bool) {
+        context.queue.write_buffer(&self.globals, 16, &u32::from(true_color).to_ne_bytes());
+    }
+}
+
+/// What `fs_display` outputs
+#[derive(Copy, Clone)]
+enum DisplaySource {
+    Native15Bpp = 0,
+    Native24Bpp = 1,
+    Full16Bpp = 2,
+    Full8Bpp = 3,
+    Full4Bpp = 4,
+}
+
+pub struct HardwareRenderer {
+    context: &'static GpuContext,
+    /// Keeps the GPU state, see the module documentation
+    state: Rasterizer,
+    /// The options that make the tracker draw, it's kept from drawing
+    skip_draws: bool,
+    draw_polygons: bool,
+    draw_wireframe: bool,
+    upscale_shift: u8,
+    textures: Textures,
+    /// Frame readback target, kept as long as the frame dimensions don't change
+    output: Option<wgpu::Texture>,
+    /// Draws not submitted yet
+    vertices: Vec<HwVertex>,
+    segments: Vec<Segment>,
+    /// Areas drawn since `vram_read` was last refreshed
+    dirty: Vec<VRamArea>,
+    /// VRAM store in progress
+    upload: Vec<UploadRow>,
+    /// Precise positions of the vertices of the next polygon, see `Command::PreciseVertex`
+    precise_vertices: PreciseVertices,
+    /// The GPU failed a readback, the tracker draws in our place. See `fall_back`.
+    lost: bool,
+}
+
+impl HardwareRenderer {
+    pub fn new(context: &'static GpuContext, mut state: Rasterizer) -> HardwareRenderer {
+        // `from_serialized` always gives a native VRAM, the internal resolution is set afterwards
+        debug_assert_eq!(state.vram.upscale_shift, 0);
+
+        let skip_draws = mem::replace(&mut state.skip_draws, true);
+        let draw_polygons = mem::replace(&mut state.draw_polygons, false);
+        let draw_wireframe = mem::replace(&mut state.draw_wireframe, false);
+        // Batching would delay the state changes
+        state.parallel_draw_areas = false;
+
+        let mut renderer = HardwareRenderer {
+            context,
+            state,
+            skip_draws,
+            draw_polygons,
+            draw_wireframe,
+            upscale_shift: 0,
+            textures: Textures::new(context, 0),
+            output: None,
+            vertices: Vec::new(),
+            segments: Vec::new(),
+            dirty: Vec::new(),
+            upload: Vec::new(),
+            precise_vertices: PreciseVertices::default(),
+            lost: false,
+        };
+
+        let mut vram = Vec::with_capacity(1024 * 512 * 4);
+        for y in 0..512 {
+            for x in 0..1024 {
+                vram.extend_from_slice(&rgba(renderer.state.vram.native_pixel(x, y)));
+            }
+        }
+        renderer.load_vram(&vram);
+        renderer.textures.set_true_color(context, renderer.state.draw_24bpp);
+
+        renderer
+    }
+
+    fn gp0(&mut self, params: &[u32], frame_channel: &mpsc::Sender<Frame>) {
+        let opcode = params[0] >> 24;
+
+        match opcode {
+            0x02 => self.fill_rect(params),
+            0x20..=0x3f => self.draw_polygon(params),
+            0x40..=0x5f => self.draw_line(params),
+            0x60..=0x7f => self.draw_rect(params),
+            0x80..=0x9f => self.copy_rect(params),
+            0xa0..=0xbf => {
+                // The tracker sets up the store, we follow its progress
+                (GP0_COMMANDS[opcode as usize].handler)(&mut self.state, params);
+            }
+            0xc0..=0xdf => {
+                self.read_back_vram();
+                cmd_vram_load(&mut self.state, params, frame_channel);
+            }
+            _ => (GP0_COMMANDS[opcode as usize].handler)(&mut self.state, params),
+        }
+    }
+
+    /// Clip area in native coordinates, None if it's empty
+    fn clip_area(&self) -> Option<VRamArea> {
+        let s = &self.state;
+
+        VRamArea::new(
+            s.clip_x_min,
+            s.clip_y_min,
+            s.clip_x_max.min(1023) - s.clip_x_min + 1,
+            s.clip_y_max.min(511) - s.clip_y_min + 1,
+        )
+    }
+
+    /// Queue the triangles in `vertices`, drawn in the clip area with the current state.
+    /// `textured_by` is the CLUT word of textured draws.
+    fn push_draw(&mut self, mut vertices: Vec<HwVertex>, semi_transparent: bool, textured_by: Option<u32>) {
+        let Some(clip) = self.clip_area() else {
+            return;
+        };
+
+        if let Some(clut) = textured_by {
+            let mode = self.state.tex_mapper.draw_mode;
+            if texture_sources(mode, clut).any(|a| self.dirty.iter().any(|&d| d.intersects(a))) {
+                self.refresh_vram_read();
+            }
+
+            let tex = &self.state.tex_mapper;
+            let clut = (clut >> 16) & 0x7fff;
+            let clut = ((clut & 0x3f) << 4) | (((clut >> 6) & 0x1ff) << 16);
+            let window = u32::from(tex.u_mask)
+                | u32::from(tex.v_mask) << 8
+                | u32::from(tex.pixel_to_texel_shift) << 16;
+            let offsets = u32::from(tex.u_offset) | u32::from(tex.v_offset) << 16;
+
+            for v in &mut vertices {
+                v.clut = clut;
+                v.window = window;
+                v.offsets = offsets;
+            }
+        }
+
+        let mask = &self.state.mask_settings;
+        if mask.draw_with_mask_bit() {
+            for v in &mut vertices {
+                v.flags |= SET_MASK;
+            }
+        }
+
+        let opaque = if mask.check_mask_bit() { Blend::MaskChecked } else { Blend::Opaque };
+        let transparent = Blend::transparent(self.state.tex_mapper.draw_mode.transparency_mode());
+
+        let scissor = self.scissor(clip);
+        match (semi_transparent, textured_by.is_some()) {
+            (false, _) => self.push(opaque, scissor, vertices),
+            (true, false) => self.push(transparent, scissor, vertices),
+            (true, true) => {
+                // Only the texels with the mask bit set are semi-transparent
+                let semi = vertices.iter().map(|&v| HwVertex {
+                    flags: v.flags | ONLY_SEMI_TEXELS,
+                    ..v
+                });
+                let semi: Vec<HwVertex> = semi.collect();
+
+                for v in &mut vertices {
+                    v.flags |= ONLY_OPAQUE_TEXELS;
+                }
+                self.push(opaque, scissor, vertices);
+                self.push(transparent, scissor, semi);
+            }
+        }
+
+        self.add_dirty(clip);
+    }
+
+    /// Scissor rect of the native `area`
+    fn scissor(&self, area: VRamArea) -> [u32; 4] {
+        let s = self.upscale_shift;
+
+        [
+            (area.x as u32) << s,
+            (area.y as u32) << s,
+            (area.width.min(1024 - area.x) as u32) << s,
+            (area.height.min(512 - area.y) as u32) << s,
+        ]
+    }
+
+    fn push(&mut self, blend: Blend, scissor: [u32; 4], vertices: Vec<HwVertex>) {
+        let start = self.vertices.len() as u32;
+        self.vertices.extend(vertices);
+        let end = self.vertices.len() as u32;
+
+        match self.segments.last_mut() {
+            Some(s) if s.blend == blend && s.scissor == scissor => s.vertices.end = end,
+            _ => self.segments.push(Segment {
+                blend,
+                scissor,
+                vertices: start..end,
+            }),
+        }
+    }
+
+    fn add_dirty(&mut self, area: VRamArea) {
+        if !self.dirty.contains(&area) {
+            self.dirty.push(area);
+        }
+    }
+
+    fn draw_polygon(&mut self, params: &[u32]) {
+        let opcode = params[0] >> 24;
+        let quad = opcode & 0x08 != 0;
+        let shaded = opcode & 0x10 != 0;
+        let textured = opcode & 0x04 != 0;
+
+        let mut vertices = [Vertex::new(0), Vertex::new(1), Vertex::new(2), Vertex::new(3)];
+        let nvertices = if quad { 4 } else { 3 };
+
+        let mut index = 0;
+        let mut color = Pixel::black();
+        let mut clut = 0;
+
+        for (v, vertex) in vertices[..nvertices].iter_mut().enumerate() {
+            if v == 0 || shaded {
+                color = Pixel::from_command(params[index]);
+                index += 1;
+            }
+            vertex.color = color;
+
+            vertex.set_position(params[index]);
+            index += 1;
+
+            // Same as `cmd_handle_poly_tri`
+            let x = vertex.position.x + self.state.draw_offset_x;
+            let y = vertex.position.y + self.state.draw_offset_y;
+            vertex.position.x = extend_to_i32(x as u32, 11);
+            vertex.position.y = extend_to_i32(y as u32, 11);
+
+            if textured {
+                if v == 0 {
+                    clut = params[index];
+                }
+                vertex.set_texture_uv(params[index]);
+                index += 1;
+            }
+        }
+
+        // The tracker picks up the texture page of the polygon
+        (GP0_COMMANDS[opcode as usize].handler)(&mut self.state, params);
+
+        if self.skip_draws || !self.draw_polygons {
+            return;
+        }
+
+        let mut flags = 0;
+        if textured {
+            flags |= TEXTURED;
+            if opcode & 0x01 != 0 {
+                flags |= RAW_TEXTURE;
+            }
+        }
+        if self.state.dither_enabled && (shaded || flags == TEXTURED) {
+            flags |= DITHER;
+        }
+
+        let triangles: &[[usize; 3]] = if quad { &[[0, 1, 2], [1, 2, 3]] } else { &[[0, 1, 2]] };
+
+        let mut hw_vertices = Vec::with_capacity(6);
+        for triangle in triangles {
+            let t = triangle.map(|i| &vertices[i]);
+
+            let (x_min, x_max) = bounds(t.iter().map(|v| v.x()));
+            let (y_min, y_max) = bounds(t.iter().map(|v| v.y()));
+            if x_max - x_min >= 1024 || y_max - y_min >= 512 {
+                // Too large, the GPU doesn't draw it
+                continue;
+            }
+
+            // The perspective correction takes the depth of the three vertices
+            let depths = triangle.map(|i| self.precise_vertices[i].map(|p| p.z).filter(|&z| z > 0));
+            let perspective = depths.iter().all(Option::is_some);
+
+            for (&i, depth) in triangle.iter().zip(depths) {
+                let v = &vertices[i];
+                let mut hw_vertex = HwVertex {
+                    uv: [f32::from(v.u), f32::from(v.v)],
+                    ..HwVertex::new(v.x(), v.y(), v.color, flags)
+                };
+
+                if let Some(precise) = self.precise_vertices[i] {
+                    hw_vertex.position[0] += f32::from(precise.x_offset) / 256.;
+                    hw_vertex.position[1] += f32::from(precise.y_offset) / 256.;
+                }
+                if let Some(z) = depth.filter(|_| perspective) {
+                    hw_vertex.depth = f32::from(z);
+                }
+
+                hw_vertices.push(hw_vertex);
+            }
+        }
+
+        if !hw_vertices.is_empty() {
+            self.push_draw(hw_vertices, opcode & 0x02 != 0, textured.then_some(clut));
+        }
+    }
+
+    fn draw_rect(&mut self, params: &[u32]) {
+        let opcode = params[0] >> 24;
+        let textured = opcode & 0x04 != 0;
+
+        if self.skip_draws {
+            return;
+        }
+
+        let color = Pixel::from_command(params[0]);
+
+        // Same as `cmd_handle_rect`
+        let mut origin = Vertex::new(0);
+        origin.set_position(params[1]);
+        let x = extend_to_i32((origin.x() + self.state.draw_offset_x) as u32, 11);
+        let y = extend_to_i32((origin.y() + self.state.draw_offset_y) as u32, 11);
+
+        let mut index = 2;
+        let mut clut = 0;
+        if textured {
+            clut = params[index];
+            origin.set_texture_uv(params[index]);
+            index += 1;
+        }
+
+        let (width, height) = match (opcode >> 3) & 3 {
+            0 => ((params[index] & 0x3ff) as i32, ((params[index] >> 16) & 0x1ff) as i32),
+            1 => (1, 1),
+            2 => (8, 8),
+            _ => (16, 16),
+        };
+
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let mut flags = 0;
+        let mut u = [0.; 2];
+        let mut v = [0.; 2];
+        if textured {
+            flags |= TEXTURED;
+            if opcode & 0x01 != 0 {
+                flags |= RAW_TEXTURE;
+            }
+
+            // The texels are sampled at the pixel centers, going backwards they have to be one
+            // texel further
+            let mode = self.state.tex_mapper.draw_mode;
+            let (u0, v0) = (f32::from(origin.u), f32::from(origin.v));
+            u = if mode.flip_rect_x() {
+                let u0 = f32::from(origin.u | 1) + 1.;
+                [u0, u0 - width as f32]
+            } else {
+                [u0, u0 + width as f32]
+            };
+            v = if mode.flip_rect_y() {
+                [v0 + 1., v0 + 1. - height as f32]
+            } else {
+                [v0, v0 + height as f32]
+            };
+        }
+
+        let corner = |cx: usize, cy: usize| HwVertex {
+            uv: [u[cx], v[cy]],
+            ..HwVertex::new(x + width * cx as i32, y + height * cy as i32, color, flags)
+        };
+
+        // Rects are never dithered
+        let vertices = vec![
+            corner(0, 0),
+            corner(1, 0),
+            corner(0, 1),
+            corner(1, 0),
+            corner(0, 1),
+            corner(1, 1),
+        ];
+
+        self.push_draw(vertices, opcode & 0x02 != 0, textured.then_some(clut));
+    }
+
+    fn draw_line(&mut self, params: &[u32]) {
+        let opcode = params[0] >> 24;
+        let shaded = opcode & 0x10 != 0;
+
+        let offset = |v: &mut Vertex, x: i32, y: i32| {
+            v.position.x += x;
+            v.position.y += y;
+        };
+        let (dx, dy) = (self.state.draw_offset_x, self.state.draw_offset_y);
+
+        let mut start = Vertex::new(0);
+        start.color = Pixel::from_command(params[0]);
+        start.set_position(params[1]);
+        offset(&mut start, dx, dy);
+
+        let mut end = Vertex::new(1);
+        end.color = if shaded { Pixel::from_command(params[2]) } else { start.color };
+        end.set_position(params[2 + shaded as usize]);
+        offset(&mut end, dx, dy);
+
+        // Sets up the polyline, if it's one
+        (GP0_COMMANDS[opcode as usize].handler)(&mut self.state, params);
+
+        self.push_line(opcode, start, end);
+    }
+
+    /// Next segment of the polyline in progress
+    fn continue_polyline(&mut self, opcode: u8, params: &[u32]) {
+        let State::PolyLine(_, ref start) = self.state.state else {
+            unreachable!();
+        };
+        let start = start.clone();
+
+        (GP0_COMMANDS[opcode as usize].handler)(&mut self.state, params);
+
+        let State::PolyLine(_, ref end) = self.state.state else {
+            unreachable!();
+        };
+        let end = end.clone();
+
+        self.push_line(u32::from(opcode), start, end);
+    }
+
+    /// Lines are drawn as quads one pixel thick
+    fn push_line(&mut self, opcode: u32, mut start: Vertex, mut end: Vertex) {
+        if self.skip_draws {
+            return;
+        }
+
+        let (dx, dy) = (end.x() - start.x(), end.y() - start.y());
+        if dx.abs() >= 1024 || dy.abs() >= 512 {
+            return;
+        }
+
+        let flags = if self.state.dither_enabled { DITHER } else { 0 };
+
+        let x_major = dx.abs() >= dy.abs();
+        if (x_major && dx < 0) || (!x_major && dy < 0) {
+            mem::swap(&mut start, &mut end);
+        }
+
+        let corner = |v: &Vertex, x: i32, y: i32| HwVertex::new(v.x() + x, v.y() + y, v.color, flags);
+
+        let quad = if x_major {
+            [corner(&start, 0, 0), corner(&end, 1, 0), corner(&start, 0, 1), corner(&end, 1, 1)]
+        } else {
+            [corner(&start, 0, 0), corner(&start, 1, 0), corner(&end, 0, 1), corner(&end, 1, 1)]
+        };
+
+        let vertices = vec![quad[0], quad[1], quad[2], quad[1], quad[2], quad[3]];
+
+        self.push_draw(vertices, opcode & 0x02 != 0, None);
+    }
+
+    /// Fill a rectangle with a solid color, ignoring the clip area and the mask settings
+    fn fill_rect(&mut self, params: &[u32]) {
+        if self.skip_draws {
+            return;
+        }
+
+        let color = Pixel::from_command(params[0]);
+        let dst = params[1];
+        let dim = params[2];
+
+        // Same as `cmd_fill_rect`
+        let Some(area) = VRamArea::new(
+            (dst & 0x3f0) as i32,
+            ((dst >> 16) & 0x3ff) as i32,
+            (((dim & 0x3ff) + 0xf) & !0xf) as i32,
+            ((dim >> 16) & 0x1ff) as i32,
+        ) else {
+            return;
+        };
+
+        for a in area.split() {
+            let corner = |cx: i32, cy: i32| HwVertex::new(a.x + a.width * cx, a.y + a.height * cy, color, 0);
+            let vertices = vec![
+                corner(0, 0),
+                corner(1, 0),
+                corner(0, 1),
+                corner(1, 0),
+                corner(0, 1),
+                corner(1, 1),
+            ];
+
+            let scissor = self.scissor(a);
+            self.push(Blend::Opaque, scissor, vertices);
+            self.add_dirty(a);
+        }
+    }
+
+    /// VRAM to VRAM copy, through `vram_read`
+    fn copy_rect(&mut self, params: &[u32]) {
+        self.flush_draws();
+
+        let src = params[1];
+        let dst = params[2];
+
+        let src_x = (src & 0x3ff) as i32;
+        let src_y = ((src >> 16) & 0x1ff) as i32;
+        let dst_x = (dst & 0x3ff) as i32;
+        let dst_y = ((dst >> 16) & 0x1ff) as i32;
+
+        let (width, height) = vram_access_dimensions(params[3], false);
+
+        // Pieces wrapping around neither in the source nor in the destination
+        let cuts = |src: i32, dst: i32, len: i32, size: i32| {
+            let mut cuts = vec![0, len, size - src, size - dst];
+            cuts.retain(|&c| (0..=len).contains(&c));
+            cuts.sort();
+            cuts.dedup();
+            cuts
+        };
+        let xs = cuts(src_x, dst_x, width, 1024);
+        let ys = cuts(src_y, dst_y, height, 512);
+
+        let mut pieces = Vec::new();
+        for y in ys.windows(2) {
+            for x in xs.windows(2) {
+                pieces.push((x[0], y[0], x[1] - x[0], y[1] - y[0]));
+            }
+        }
+
+        let s = self.upscale_shift;
+        let t = &self.textures;
+        let mut encoder = self.context.encoder();
+
+        for &(x, y, w, h) in &pieces {
+            let sx = ((src_x + x) & 0x3ff) << s;
+            let sy = ((src_y + y) & 0x1ff) << s;
+            encoder.copy_texture_to_texture(
+                at(&t.vram, sx, sy),
+                at(&t.vram_read, sx, sy),
+                extent((w << s) as u32, (h << s) as u32),
+            );
+        }
+        for &(x, y, w, h) in &pieces {
+            encoder.copy_texture_to_texture(
+                at(&t.vram_read, ((src_x + x) & 0x3ff) << s, ((src_y + y) & 0x1ff) << s),
+                at(&t.vram, ((dst_x + x) & 0x3ff) << s, ((dst_y + y) & 0x1ff) << s),
+                extent((w << s) as u32, (h << s) as u32),
+            );
+        }
+
+        self.context.submit(encoder);
+
+        for &(x, y, w, h) in &pieces {
+            if let Some(a) = VRamArea::new((dst_x + x) & 0x3ff, (dst_y + y) & 0x1ff, w, h) {
+                self.add_dirty(a);
+            }
+        }
+    }
+
+    /// Word of the VRAM store in progress
+    fn store(&mut self, word: u32) {
+        for p in [word as u16, (word >> 16) as u16] {
+            let State::VRamStore(ref mut store) = self.state.state else {
+                unreachable!();
+            };
+
+            let (x, y) = store.target_vram_offset();
+            let done = store.next().is_none();
+
+            let p = self.state.mask_settings.mask(Pixel::from_mbgr1555(p));
+
+            // Cheap enough, and the textures survive if the tracker has to take over
+            self.state.vram.set_native_pixel(x, y, p);
+
+            match self.upload.last_mut() {
+                Some(row) if row.y == y && row.x + row.pixels.len() as u16 == x => row.pixels.push(p),
+                _ => self.upload.push(UploadRow { x, y, pixels: vec![p] }),
+            }
+
+            if done {
+                self.state.state = State::WaitingForCommand;
+                self.flush_upload();
+                break;
+            }
+        }
+    }
+
+    /// Draw the pixels stored so far to the VRAM
+    fn flush_upload(&mut self) {
+        if self.upload.is_empty() {
+            return;
+        }
+
+        self.flush_draws();
+
+        let rows = mem::take(&mut self.upload);
+        for row in &rows {
+            let bytes: Vec<u8> = row.pixels.iter().flat_map(|&p| rgba(p)).collect();
+            let width = row.pixels.len() as u32;
+
+            self.context.queue.write_texture(
+                at(&self.textures.native, i32::from(row.x), i32::from(row.y)),
+                &bytes,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(width * 4),
+                    rows_per_image: None,
+                },
+                extent(width, 1),
+            );
+
+            self.push_upload_quad(i32::from(row.x), i32::from(row.y), width as i32, 1);
+        }
+
+        self.flush_draws();
+
+        // The uploads are mostly textures, they're copied for the draws right away
+        let s = self.upscale_shift;
+        let mut encoder = self.context.encoder();
+        for row in &rows {
+            let (x, y) = (i32::from(row.x) << s, i32::from(row.y) << s);
+
+            encoder.copy_texture_to_texture(
+                at(&self.textures.vram, x, y),
+                at(&self.textures.vram_read, x, y),
+                extent((row.pixels.len() as u32) << s, 1 << s),
+            );
+        }
+        self.context.submit(encoder);
+    }
+
+    /// Queue a draw of the `native` texture to the VRAM
+    fn push_upload_quad(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        let corner = |cx: i32, cy: i32| HwVertex::new(x + width * cx, y + height * cy, Pixel::black(), UPLOAD);
+        let vertices = vec![
+            corner(0, 0),
+            corner(1, 0),
+            corner(0, 1),
+            corner(1, 0),
+            corner(0, 1),
+            corner(1, 1),
+        ];
+
+        let scissor = [0, 0, 1024 << self.upscale_shift, 512 << self.upscale_shift];
+        self.push(Blend::Opaque, scissor, vertices);
+    }
+
+    /// Replace the whole VRAM with `rgba`, at the native resolution
+    fn load_vram(&mut self, rgba: &[u8]) {
+        self.flush_draws();
+
+        self.context.queue.write_texture(
+            self.textures.native.as_image_copy(),
+            rgba,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(1024 * 4),
+                rows_per_image: None,
+            },
+            extent(1024, 512),
+        );
+
+        self.push_upload_quad(0, 0, 1024, 512);
+        self.flush_draws();
+
+        let mut encoder = self.context.encoder();
+        encoder.copy_texture_to_texture(
+            self.textures.vram.as_image_copy(),
+            self.textures.vram_read.as_image_copy(),
+            self.textures.vram.size(),
+        );
+        self.context.submit(encoder);
+
+        self.dirty.clear();
+    }
+
+    /// The whole VRAM at the native resolution, as RGBA8. `None` if the GPU failed to deliver it.
+    fn read_vram(&mut self) -> Option<Vec<u8>> {
+        self.flush_draws();
+
+        let mut encoder = self.context.encoder();
+        {
+            let mut pass = begin_pass(&mut encoder, &self.textures.native_view, wgpu::LoadOp::Load);
+
+            pass.set_pipeline(&self.context.downsample_pipeline);
+            pass.set_bind_group(0, &self.textures.view_bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        self.context.submit(encoder);
+
+        self.context.read_texture(&self.textures.native, 1024, 512)
+    }
+
+    /// Copy the VRAM to the tracker's, or make the tracker take over if the GPU failed to deliver it
+    fn read_back_vram(&mut self) {
+        self.flush_upload();
+
+        let Some(vram) = self.read_vram() else {
+            self.fall_back();
+            return;
+        };
+
+        for (i, p) in vram.chunks_exact(4).enumerate() {
+            let pixel = Pixel(
+                u32::from(p[3] >= 0x80) << 24
+                    | u32::from(p[0]) << 16
+                    | u32::from(p[1]) << 8
+                    | u32::from(p[2]),
+            );
+
+            self.state.vram.set_native_pixel((i % 1024) as u16, (i / 1024) as u16, pixel);
+        }
+
+        // Whatever the tracker cached came from its stale VRAM
+        self.state.tex_mapper.cache_invalidate();
+    }
+
+    /// Copy the areas drawn since the last time to `vram_read`
+    fn refresh_vram_read(&mut self) {
+        self.flush_draws();
+
+        let s = self.upscale_shift;
+        let mut encoder = self.context.encoder();
+
+        for area in mem::take(&mut self.dirty).into_iter().flat_map(|a| a.split()) {
+            let (x, y) = (area.x << s, area.y << s);
+
+            encoder.copy_texture_to_texture(
+                at(&self.textures.vram, x, y),
+                at(&self.textures.vram_read, x, y),
+                extent((area.width << s) as u32, (area.height << s) as u32),
+            );
+        }
+
+        self.context.submit(encoder);
+    }
+
+    /// Submit the queued draws
+    fn flush_draws(&mut self) {
+        if self.segments.is_empty() {
+            return;
+        }
+
+        let mut bytes = Vec::with_capacity(self.vertices.len() * HwVertex::SIZE);
+        for v in &self.vertices {
+            v.write_to(&mut bytes);
+        }
+
+        let buffer = self.context.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("PSX vertices"),
+            contents: &bytes,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let mut encoder = self.context.encoder();
+        {
+            let mut pass = begin_pass(&mut encoder, &self.textures.vram_view, wgpu::LoadOp::Load);
+
+            pass.set_bind_group(0, &self.textures.draw_bind_group, &[]);
+            pass.set_vertex_buffer(0, buffer.slice(..));
+
+            for segment in &self.segments {
+                let [x, y, width, height] = segment.scissor;
+                let c = segment.blend.constant();
+
+                pass.set_pipeline(&self.context.draw_pipelines[segment.blend as usize]);
+                pass.set_blend_constant(wgpu::Color { r: c, g: c, b: c, a: c });
+                pass.set_scissor_rect(x, y, width, height);
+                pass.draw(segment.vertices.clone(), 0..1);
+            }
+        }
+        self.context.submit(encoder);
+
+        self.vertices.clear();
+        self.segments.clear();
+    }
+
+    fn set_upscale_shift(&mut self, upscale_shift: u8) {
+        if upscale_shift == self.upscale_shift {
+            return;
+        }
+
+        self.flush_upload();
+        let Some(vram) = self.read_vram() else {
+            self.fall_back();
+            self.state.set_option(RasterizerOption::UpscaleShift(upscale_shift));
+            return;
+        };
+
+        self.upscale_shift = upscale_shift;
+        self.textures = Textures::new(self.context, upscale_shift);
+        self.textures.set_true_color(self.context, self.state.draw_24bpp);
+        self.load_vram(&vram);
+    }
+
+    fn set_option(&mut self, opt: RasterizerOption) {
+        match opt {
+            RasterizerOption::UpscaleShift(v) => self.set_upscale_shift(v),
+            RasterizerOption::SkipDraws(v) => self.skip_draws = v,
+            RasterizerOption::DrawPolygons(v) => self.draw_polygons = v,
+            RasterizerOption::Wireframe(v) => self.draw_wireframe = v,
+            RasterizerOption::ParallelDrawAreas(_) => (),
+            // Only counted by the software rasterizer
+            RasterizerOption::Overdraw(_) => (),
+            // The shaders always texture and blend
+            RasterizerOption::DrawTextures(_) | RasterizerOption::DrawSemiTransparency(_) => (),
+            RasterizerOption::Draw24Bpp(v) => {
+                // The queued draws were made with the previous depth
+                self.flush_draws();
+                self.textures.set_true_color(self.context, v);
+                self.state.set_option(opt);
+            }
+            opt => self.state.set_option(opt),
+        }
+    }
+
+    /// The GPU can't be used anymore: the tracker draws from now on, with the draw options it was
+    /// kept from and the VRAM it has. It's missing what was drawn since the last readback, the
+    /// games that redraw every frame don't notice for long.
+    fn fall_back(&mut self) {
+        warn!("The GPU stopped answering, the software rasterizer takes over");
+
+        self.lost = true;
+
+        let s = &mut self.state;
+        s.skip_draws = self.skip_draws;
+        s.draw_polygons = self.draw_polygons;
+        s.draw_wireframe = self.draw_wireframe;
+
+        s.tex_mapper.cache_invalidate();
+    }
+
+    /// State of the software rasterizer
+    fn serialize(&mut self) -> Vec<u8> {
+        self.read_back_vram();
+
+        if self.lost {
+            return self.state.serialize_state();
+        }
+
+        let s = &mut self.state;
+        let forced = (s.skip_draws, s.draw_polygons, s.draw_wireframe);
+
+        s.skip_draws = self.skip_draws;
+        s.draw_polygons = self.draw_polygons;
+        s.draw_wireframe = self.draw_wireframe;
+
+        let state = s.serialize_state();
+
+        (s.skip_draws, s.draw_polygons, s.draw_wireframe) = forced;
+
+        state
+    }
+
+    fn render_frame(&mut self) -> Frame {
+        self.flush_upload();
+        self.flush_draws();
+
+        let s = &self.state;
+        let shift = self.upscale_shift;
+
+        let (source, width, height) = match s.vram_display_mode {
+            VRamDisplayMode::Native => {
+                let (w, h) = s.native_frame_size();
+                let source = if s.display_mode.output_24bpp() {
+                    DisplaySource::Native24Bpp
+                } else {
+                    DisplaySource::Native15Bpp
+                };
+
+                (source, w << shift, h << shift)
+            }
+            mode => {
+                let (w, h) = mode.max_res();
+                let source = match mode {
+                    VRamDisplayMode::Full16bpp => DisplaySource::Full16Bpp,
+                    VRamDisplayMode::Full8bpp => DisplaySource::Full8Bpp,
+                    _ => DisplaySource::Full4Bpp,
+                };
+
+                (source, u32::from(w), u32::from(h))
+            }
+        };
+
+        if width == 0 || height == 0 {
+            return Frame::new(width, height);
+        }
+
+        self.textures.set_globals(
+            self.context,
+            shift,
+            source,
+            s.display_vram_x_start,
+            s.display_vram_y_start,
+        );
+
+        let output = match self.output.take() {
+            Some(o) if o.width() == width && o.height() == height => o,
+            _ => self.context.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("PSX frame"),
+                size: extent(width, height),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            }),
+        };
+
+        let mut encoder = self.context.encoder();
+        {
+            let view = output.create_view(&Default::default());
+            let mut pass = begin_pass(&mut encoder, &view, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+
+            if !s.display_off {
+                pass.set_pipeline(&self.context.display_pipeline);
+                pass.set_bind_group(0, &self.textures.view_bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+        self.context.submit(encoder);
+
+        let Some(rgba) = self.context.read_texture(&output, width, height) else {
+            self.fall_back();
+            return self.state.new_frame();
+        };
+        self.output = Some(output);
+
+        Frame {
+            pixels: rgba
+                .chunks_exact(4)
+                .map(|p| u32::from(p[0]) << 16 | u32::from(p[1]) << 8 | u32::from(p[2]))
+                .collect(),
+            width,
+            height,
+            format: if s.draw_24bpp {
+                PixelFormat::Rgb888
+            } else {
+                PixelFormat::Rgb555
+            },
+        }
+    }
+}
+
+impl Renderer for HardwareRenderer {
+    fn prepare(&mut self) {
+        self.state.rebuild_dither_table();
+    }
+
+    fn process(
+        &mut self,
+        commands: &[Command],
+        frame_channel: &mpsc::Sender<Frame>,
+        serialization_channel: &mpsc::Sender<Vec<u8>>,
+    ) -> bool {
+        if self.lost {
+            return self.state.process(commands, frame_channel, serialization_channel);
+        }
+
+        let mut command_i = commands.iter();
+
+        while let Some(cmd) = command_i.next() {
+            match cmd {
+                Command::Gp0(v) => match self.state.state {
+                    State::WaitingForCommand => {
+                        let opcode = v >> 24;
+                        let len = GP0_COMMANDS[opcode as usize].len as usize;
+                        let mut params = [0; 12];
+
+                        params[0] = *v;
+
+                        // Complete commands only, see `Rasterizer::process`
+                        for param in &mut params[1..len] {
+                            match command_i.next() {
+                                Some(Command::Gp0(v)) => *param = *v,
+                                other => panic!("Expected GP0 command, got {:?}", other),
+                            }
+                        }
+
+                        self.gp0(&params[..len], frame_channel);
+                        // They only ever come right before their polygon
+                        self.precise_vertices = PreciseVertices::default();
+                    }
+                    State::VRamStore(_) => self.store(*v),
+                    State::PolyLine(opcode, _) => {
+                        if *v & 0xf000_f000 == 0x5000_5000 {
+                            // End-of-line marker
+                            self.state.state = State::WaitingForCommand;
+                        } else {
+                            let mut params = [*v, 0];
+                            let is_shaded = (opcode & 0x10) != 0;
+
+                            if is_shaded {
+                                params[1] = match command_i.next() {
+                                    Some(Command::Gp0(v)) => *v,
+                                    other => panic!("Expected GP0 command, got {:?}", other),
+                                };
+                            }
+
+                            self.continue_polyline(opcode, &params[..1 + is_shaded as usize]);
+                        }
+                    }
+                },
+                Command::PreciseVertex(index, vertex) => {
+                    self.precise_vertices[usize::from(*index)] = Some(*vertex);
+                }
+                Command::Gp1(v) => self.state.gp1(*v),
+                Command::Quit => return false,
+                // The frame is drawn all at once at the end
+                Command::EndOfLine(_) => (),
+                Command::EndOfFrame => {
+                    let frame = self.render_frame();
+                    frame_channel.send(frame).unwrap();
+                }
+                Command::FieldChanged(f) => self.state.display_bottom_field = *f,
+                Command::Option(opt) => self.set_option(*opt),
+                Command::Serialize => {
+                    assert!(command_i.next().is_none());
+
+                    serialization_channel.send(self.serialize()).unwrap();
+                }
+            }
+
+            if self.lost {
+                // The tracker takes over from the next command
+                return self.state.process(command_i.as_slice(), frame_channel, serialization_channel);
+            }
+        }
+
+        true
+    }
+}
+
+fn rgba(p: Pixel) -> [u8; 4] {
+    [p.red(), p.green(), p.blue(), if p.mask() { 0xff } else { 0 }]
+}
+
+fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+}
+
+fn at(texture: &wgpu::Texture, x: i32, y: i32) -> wgpu::TexelCopyTextureInfo<'_> {
+    wgpu::TexelCopyTextureInfo {
+        texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d {
+            x: x as u32,
+            y: y as u32,
+            z: 0,
+        },
+        aspect: wgpu::TextureAspect::All,
+    }
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            depth_slice: None,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+fn bounds(values: impl Iterator<Item = i32> + Clone) -> (i32, i32) {
+    (values.clone().min().unwrap(), values.max().unwrap())
+}
+
+#[test]
+fn matches_software_transfers() {
+    let Some(context) = GpuContext::shared() else {
+        // No GPU on this host
+        return;
+    };
+
+    let commands: Vec<u32> = vec![
+        // Fill rect, wrapping around
+        0x0200_ff00,
+        0x0010_03f0,
+        0x0040_0020,
+        // Store 2x2
+        0xa000_0000,
+        0x0020_0010,
+        0x0002_0002,
+        0x7fff_001f,
+        0x83e0_7c00,
+        // Copy it further, overlapping the fill
+        0x8000_0000,
+        0x0020_0010,
+        0x0018_0000,
+        0x0002_0002,
+    ];
+
+    let run = |renderer: &mut dyn Renderer| {
+        let (command_sender, command_receiver) = mpsc::channel();
+        let (frame_sender, _frame_receiver) = mpsc::channel();
+        let (serialization_sender, serialization_receiver) = mpsc::channel();
+
+        let mut buffer: CommandBuffer = commands.iter().map(|&c| Command::Gp0(c)).collect();
+        buffer.push(Command::Serialize);
+        command_sender.send(buffer).unwrap();
+        command_sender.send(vec![Command::Quit]).unwrap();
+
+        renderer.run(command_receiver, frame_sender, serialization_sender);
+
+        Rasterizer::from_serialized(&serialization_receiver.recv().unwrap()).unwrap()
+    };
+
+    let software = run(&mut Rasterizer::new());
+    let hardware = run(&mut HardwareRenderer::new(context, Rasterizer::new()));
+
+    for y in 0..512 {
+        for x in 0..1024 {
+            assert_eq!(
+                hardware.vram.native_pixel(x, y),
+                software.vram.native_pixel(x, y),
+                "at {}x{}",
+                x,
+                y
+            );
+        }
+    }
+}
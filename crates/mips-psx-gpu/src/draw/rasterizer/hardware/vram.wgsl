@@ -0,0 +1,243 @@
+// Shaders of the hardware renderer.
+//
+// The VRAM textures are RGBA8: the color components hold the 8bit values the software rasterizer
+// stores for a pixel (the 5bit component with its MSBs copied to the LSBs) and alpha holds the
+// mask bit. The vertex positions are in native VRAM coordinates, the textures may be upscaled.
+
+struct Globals {
+    // Log2 of the internal resolution multiplier
+    shift: u32,
+    // What `fs_display` outputs, see `DisplaySource` in hardware.rs
+    display_source: u32,
+    // Top left corner of the displayed area, in native VRAM pixels
+    display_x: u32,
+    display_y: u32,
+    // Keep the 8bit components drawn instead of truncating them to 5 bits
+    true_color: u32,
+}
+
+// Same as in hardware.rs
+const TEXTURED: u32 = 1u;
+const RAW_TEXTURE: u32 = 2u;
+const DITHER: u32 = 4u;
+const SET_MASK: u32 = 8u;
+const ONLY_OPAQUE_TEXELS: u32 = 16u;
+const ONLY_SEMI_TEXELS: u32 = 32u;
+const UPLOAD: u32 = 64u;
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+// Copy of the VRAM for the draws, the VRAM itself for the downsampling and the display
+@group(0) @binding(1) var source: texture_2d<f32>;
+// Native resolution staging texture of the uploads, only bound for the draws
+@group(0) @binding(2) var native: texture_2d<f32>;
+
+struct DrawVertex {
+    @location(0) position: vec2<f32>,
+    @location(1) color: u32,
+    @location(2) uv: vec2<f32>,
+    @location(3) clut: u32,
+    @location(4) window: u32,
+    @location(5) offsets: u32,
+    @location(6) flags: u32,
+    // Perspective correction, 1 if there's none
+    @location(7) depth: f32,
+}
+
+struct DrawVaryings {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) @interpolate(flat) clut: u32,
+    @location(3) @interpolate(flat) window: u32,
+    @location(4) @interpolate(flat) offsets: u32,
+    @location(5) @interpolate(flat) flags: u32,
+}
+
+@vertex
+fn vs_draw(v: DrawVertex) -> DrawVaryings {
+    var out: DrawVaryings;
+
+    // Scaling by the depth makes the interpolation of the varyings perspective correct, the
+    // position is the same once divided
+    let ndc = vec2<f32>(v.position.x / 512.0 - 1.0, 1.0 - v.position.y / 256.0);
+    out.position = vec4<f32>(ndc * v.depth, 0.0, v.depth);
+    out.color = vec3<f32>(
+        f32(v.color & 0xffu),
+        f32((v.color >> 8u) & 0xffu),
+        f32((v.color >> 16u) & 0xffu),
+    );
+    out.uv = v.uv;
+    out.clut = v.clut;
+    out.window = v.window;
+    out.offsets = v.offsets;
+    out.flags = v.flags;
+
+    return out;
+}
+
+// MBGR1555 word of the native pixel `p` of `source`
+fn vram_word(p: vec2<u32>) -> u32 {
+    let scaled = (p & vec2<u32>(0x3ffu, 0x1ffu)) << vec2<u32>(globals.shift);
+    let c = vec4<u32>(round(textureLoad(source, vec2<i32>(scaled), 0) * 255.0));
+
+    return (c.r >> 3u) | ((c.g >> 3u) << 5u) | ((c.b >> 3u) << 10u) | (u32(c.a >= 0x80u) << 15u);
+}
+
+// 8bit components of a MBGR1555 word
+fn word_color(word: u32) -> vec3<f32> {
+    let c5 = vec3<u32>(word & 0x1fu, (word >> 5u) & 0x1fu, (word >> 10u) & 0x1fu);
+
+    return vec3<f32>((c5 << vec3<u32>(3u)) | (c5 >> vec3<u32>(2u)));
+}
+
+// Texel at `uv`, like `TextureMapper::get_texel`
+fn texel(uv: vec2<f32>, clut: u32, window: u32, offsets: u32) -> u32 {
+    let pts = (window >> 16u) & 3u;
+    let u = u32(i32(floor(uv.x))) & 0xffu;
+    let v = u32(i32(floor(uv.y))) & 0xffu;
+
+    let fb_u = (u & (window & 0xffu)) + (offsets & 0xffffu);
+    let fb_v = (v & ((window >> 8u) & 0xffu)) + (offsets >> 16u);
+
+    let raw = vram_word(vec2<u32>(fb_u >> pts, fb_v));
+
+    if pts == 0u {
+        // Truecolor
+        return raw;
+    }
+
+    let shift = (fb_u & (pts + (pts >> 1u))) << (4u - pts);
+    let key = (raw >> shift) & ((1u << (16u >> pts)) - 1u);
+
+    return vram_word(vec2<u32>((clut & 0xffffu) + key, clut >> 16u));
+}
+
+@fragment
+fn fs_draw(in: DrawVaryings) -> @location(0) vec4<f32> {
+    // Native pixel we're drawing to
+    let pixel = vec2<u32>(in.position.xy) >> vec2<u32>(globals.shift);
+
+    if (in.flags & UPLOAD) != 0u {
+        return textureLoad(native, vec2<i32>(pixel), 0);
+    }
+
+    var color = floor(in.color);
+    var mask = 0.0;
+
+    if (in.flags & TEXTURED) != 0u {
+        let word = texel(in.uv, in.clut, in.window, in.offsets);
+
+        if word == 0u {
+            discard;
+        }
+
+        let semi_transparent = (word & 0x8000u) != 0u;
+
+        if ((in.flags & ONLY_OPAQUE_TEXELS) != 0u && semi_transparent)
+            || ((in.flags & ONLY_SEMI_TEXELS) != 0u && !semi_transparent) {
+            discard;
+        }
+
+        let t = word_color(word);
+
+        if (in.flags & RAW_TEXTURE) != 0u {
+            color = t;
+        } else {
+            // Texture blending doubles the value
+            color = floor(t * color / 128.0);
+        }
+
+        mask = f32(semi_transparent);
+    }
+
+    if (in.flags & DITHER) != 0u {
+        var offsets = array<array<f32, 4>, 4>(
+            array<f32, 4>(-4.0, 0.0, -3.0, 1.0),
+            array<f32, 4>(2.0, -2.0, 3.0, -1.0),
+            array<f32, 4>(-3.0, 1.0, -4.0, 0.0),
+            array<f32, 4>(3.0, -1.0, 2.0, -2.0),
+        );
+
+        color += offsets[pixel.x & 3u][pixel.y & 3u];
+    }
+
+    // Saturation and 8-to-5bit truncation
+    color = clamp(color, vec3<f32>(0.0), vec3<f32>(255.0));
+    if globals.true_color == 0u {
+        let c8 = vec3<u32>(color) & vec3<u32>(0xf8u);
+        color = vec3<f32>(c8 | (c8 >> vec3<u32>(5u)));
+    }
+
+    if (in.flags & SET_MASK) != 0u {
+        mask = 1.0;
+    }
+
+    return vec4<f32>(color / 255.0, mask);
+}
+
+// Single triangle covering the whole target
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    let p = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+
+    return vec4<f32>(p.x * 2.0 - 1.0, 1.0 - p.y * 2.0, 0.0, 1.0);
+}
+
+// Native resolution copy of the VRAM, for the readbacks
+@fragment
+fn fs_downsample(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = vec2<u32>(position.xy) << vec2<u32>(globals.shift);
+
+    return textureLoad(source, vec2<i32>(p), 0);
+}
+
+@fragment
+fn fs_display(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let out = vec2<u32>(position.xy);
+    let shift = vec2<u32>(globals.shift);
+
+    switch globals.display_source {
+        // 15bpp
+        case 0u: {
+            let size = vec2<u32>(1024u, 512u) << shift;
+            let p = ((vec2<u32>(globals.display_x, globals.display_y) << shift) + out) % size;
+
+            return vec4<f32>(textureLoad(source, vec2<i32>(p), 0).rgb, 1.0);
+        }
+        // 24bpp, the pixels span two VRAM words
+        case 1u: {
+            let pixel = out >> shift;
+            let byte = globals.display_x * 2u + pixel.x * 3u;
+            let y = globals.display_y + pixel.y;
+
+            let word = vram_word(vec2<u32>(byte >> 1u, y)) | (vram_word(vec2<u32>((byte >> 1u) + 1u, y)) << 16u);
+            let rgb = word >> ((byte & 1u) * 8u);
+
+            return vec4<f32>(
+                f32(rgb & 0xffu) / 255.0,
+                f32((rgb >> 8u) & 0xffu) / 255.0,
+                f32((rgb >> 16u) & 0xffu) / 255.0,
+                1.0,
+            );
+        }
+        // Full VRAM, 16bpp
+        case 2u: {
+            return vec4<f32>(textureLoad(source, vec2<i32>(out << shift), 0).rgb, 1.0);
+        }
+        // Full VRAM, 8bpp
+        case 3u: {
+            let word = vram_word(vec2<u32>(out.x >> 1u, out.y));
+            let g = f32((word >> ((out.x & 1u) * 8u)) & 0xffu) / 255.0;
+
+            return vec4<f32>(g, g, g, 1.0);
+        }
+        // Full VRAM, 4bpp
+        default: {
+            let word = vram_word(vec2<u32>(out.x >> 2u, out.y));
+            let n = (word >> ((out.x & 3u) * 4u)) & 0xfu;
+            let g = f32(n | (n << 4u)) / 255.0;
+
+            return vec4<f32>(g, g, g, 1.0);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Overdraw heatmap: how many times the draw commands wrote each pixel of the picture, shown in
+//! place of the picture to see what a scene costs the GPU.
+//!
+//! The counts are kept per native VRAM pixel. Most games draw the next picture while the previous
+//! one is displayed, so the counts of a picture can't be cleared when a frame ends: they are when
+//! the game shows another part of the VRAM (everything but the displayed area starts over), or
+//! after a few frames without such a flip for the games drawing straight to the screen. Fills are
+//! clears, they aren't counted.
+
+use super::parallel::VRamArea;
+
+/// Colors of the counts from 0, the last one for anything above
+const HEAT: [u32; 9] = [
+    0x00_00_00, 0x00_00_80, 0x00_00_ff, 0x00_ff_ff, 0x00_ff_00,
+    0xff_ff_00, 0xff_80_00, 0xff_00_00, 0xff_ff_ff,
+];
+
+/// Frames without a flip after which the game is taken for drawing straight to the screen
+const SINGLE_BUFFER_FRAMES: u32 = 4;
+
+pub(super) struct Overdraw {
+    /// Writes per native VRAM pixel, saturating
+    counts: Box<[u8]>,
+    frames_since_flip: u32,
+}
+
+impl Overdraw {
+    pub(super) fn new() -> Overdraw {
+        Overdraw {
+            counts: vec![0; 1024 * 512].into_boxed_slice(),
+            frames_since_flip: 0,
+        }
+    }
+
+    /// Count a write to the native pixel `x`, `y`
+    pub(super) fn count(&mut self, x: u32, y: u32) {
+        let c = &mut self.counts[(y as usize & 0x1ff) * 1024 + (x as usize & 0x3ff)];
+        *c = c.saturating_add(1);
+    }
+
+    /// The game displays `shown`: what it drew there is kept, the rest is the next picture
+    pub(super) fn flip(&mut self, shown: Option<VRamArea>) {
+        let mut counts = vec![0; self.counts.len()].into_boxed_slice();
+
+        for area in shown.map(VRamArea::split).unwrap_or_default() {
+            for y in area.y..area.y + area.height {
+                let start = y as usize * 1024 + area.x as usize;
+                let end = start + area.width as usize;
+                counts[start..end].copy_from_slice(&self.counts[start..end]);
+            }
+        }
+
+        self.counts = counts;
+        self.frames_since_flip = 0;
+    }
+
+    pub(super) fn end_of_frame(&mut self) {
+        self.frames_since_flip += 1;
+        if self.frames_since_flip >= SINGLE_BUFFER_FRAMES {
+            self.counts.fill(0);
+        }
+    }
+
+    /// xRGB 8888 color of the native pixel `x`, `y`
+    pub(super) fn color(&self, x: u32, y: u32) -> u32 {
+        let count = self.counts[(y as usize & 0x1ff) * 1024 + (x as usize & 0x3ff)];
+
+        HEAT[usize::from(count).min(HEAT.len() - 1)]
+    }
+}
+
+#[test]
+fn flips_keep_the_displayed_counts() {
+    let mut overdraw = Overdraw::new();
+
+    // Displaying the top buffer while drawing twice to the bottom one
+    overdraw.count(10, 10);
+    overdraw.count(10, 250);
+    overdraw.count(10, 250);
+    overdraw.flip(VRamArea::new(0, 240, 320, 240));
+    assert_eq!(overdraw.color(10, 10), HEAT[0]);
+    assert_eq!(overdraw.color(10, 250), HEAT[2]);
+
+    for _ in 1..SINGLE_BUFFER_FRAMES {
+        overdraw.end_of_frame();
+    }
+    assert_eq!(overdraw.color(10, 250), HEAT[2]);
+    overdraw.end_of_frame();
+    assert_eq!(overdraw.color(10, 250), HEAT[0]);
+
+    for _ in 0..300 {
+        overdraw.count(5, 5);
+    }
+    assert_eq!(overdraw.color(5, 5), HEAT[HEAT.len() - 1]);
+}
@@ -0,0 +1,598 @@
+//! Parallel rasterization of independent draw areas.
+//!
+//! Split-screen games draw every viewport with its own draw area (clip rectangle), the viewports
+//! don't overlap and usually don't read from each other. Instead of rasterizing the draw commands
+//! as they come we batch them in segments, a new segment starting every time the draw area
+//! changes. When the batch is flushed, consecutive segments that write to disjoint areas and don't
+//! texture from each other's areas are rasterized at the same time on worker threads.
+//!
+//! Every draw command is clipped to the draw area so a segment can't write outside of it, the only
+//! exception being fill rects which write to their own rectangle. Anything we can't reason about
+//! (VRAM transfers, polylines, GP1 commands...) flushes the batch first, so it runs in order.
+//!
+//! The texture cache isn't shared between the workers: a worker starts with the cache as it was
+//! when the batch was flushed instead of the one left by the previous segment. This only matters
+//! if a game relies on stale cache contents across draw areas, which is why it's off by default.
+//!
+//! The worker threads are started with the first parallel batch and kept waiting for the next
+//! ones, spawning them for every batch would cost more than it saves on small draw areas.
+
+use std::mem;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "std")]
+use std::sync::{mpsc, Arc, Mutex};
+#[cfg(feature = "std")]
+use std::thread;
+use crate::regs::DrawMode;
+use crate::pgxp::PreciseVertices;
+use super::{Frame, Rasterizer, State, GP0_COMMANDS};
+
+/// There are no threads to run the workers on in WebAssembly and without the `std` feature
+pub(super) fn available() -> bool {
+    cfg!(feature = "std") && !cfg!(target_family = "wasm")
+}
+
+/// Returns true if the GP0 command `opcode` can be batched
+pub(super) fn can_batch(opcode: u32) -> bool {
+    match opcode {
+        // Clear cache, fill rect
+        0x01 | 0x02 => true,
+        // Polygons
+        0x20..=0x3f => true,
+        // Single lines. Polylines need the command state machine.
+        0x40..=0x5f => opcode & 0x08 == 0,
+        // Rects
+        0x60..=0x7f => true,
+        // Draw mode, texture window, draw area, draw offset, mask settings
+        0xe1..=0xe6 => true,
+        _ => false,
+    }
+}
+
+/// Rectangle in native VRAM coordinates. It wraps around past the right and bottom edges of the
+/// VRAM, like the draw commands do.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(super) struct VRamArea {
+    pub(super) x: i32,
+    pub(super) y: i32,
+    pub(super) width: i32,
+    pub(super) height: i32,
+}
+
+impl VRamArea {
+    /// Returns None if the area is empty
+    pub(super) fn new(x: i32, y: i32, width: i32, height: i32) -> Option<VRamArea> {
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+
+        Some(VRamArea {
+            x: x & 0x3ff,
+            y: y & 0x1ff,
+            width: width.min(1024),
+            height: height.min(512),
+        })
+    }
+
+    pub(super) fn intersects(self, other: VRamArea) -> bool {
+        self.split().iter().any(|a| {
+            other.split().iter().any(|b| {
+                a.x < b.x + b.width
+                    && b.x < a.x + a.width
+                    && a.y < b.y + b.height
+                    && b.y < a.y + a.height
+            })
+        })
+    }
+
+    /// Split the area in up to 4 areas that don't wrap around
+    pub(super) fn split(self) -> Vec<VRamArea> {
+        let xs = wrap(self.x, self.width, 1024);
+        let ys = wrap(self.y, self.height, 512);
+
+        ys.iter()
+            .flat_map(|&(y, height)| {
+                xs.iter().map(move |&(x, width)| VRamArea { x, y, width, height })
+            })
+            .collect()
+    }
+}
+
+/// Split `start..start + len` at `size`
+fn wrap(start: i32, len: i32, size: i32) -> Vec<(i32, i32)> {
+    if start + len > size {
+        vec![(start, size - start), (0, start + len - size)]
+    } else {
+        vec![(start, len)]
+    }
+}
+
+fn intersects_any(a: &[VRamArea], b: &[VRamArea]) -> bool {
+    a.iter().any(|&a| b.iter().any(|&b| a.intersects(b)))
+}
+
+/// Commands sharing the same draw area
+#[derive(Default)]
+struct Segment {
+    /// Raw GP0 words, complete commands only
+    commands: Vec<u32>,
+    /// Areas written by the commands
+    targets: Vec<VRamArea>,
+    /// Areas read by textured commands (texture pages and CLUTs)
+    sources: Vec<VRamArea>,
+    /// Precise vertices of the polygons that have some, by offset of the polygon in `commands`
+    precise_vertices: Vec<(usize, PreciseVertices)>,
+}
+
+impl Segment {
+    /// Returns true if the segment can be rasterized at the same time as `others`
+    fn is_independent_of(&self, others: &[Segment]) -> bool {
+        others.iter().all(|o| {
+            !intersects_any(&self.targets, &o.targets)
+                && !intersects_any(&self.targets, &o.sources)
+                && !intersects_any(&self.sources, &o.targets)
+        })
+    }
+
+    fn add_target(&mut self, area: VRamArea) {
+        if !self.targets.contains(&area) {
+            self.targets.push(area);
+        }
+    }
+
+    fn add_source(&mut self, area: VRamArea) {
+        if !self.sources.contains(&area) {
+            self.sources.push(area);
+        }
+    }
+}
+
+/// Draw commands waiting to be rasterized. We keep our own copy of the draw area and draw mode
+/// since the rasterizer state only catches up when the batch is flushed.
+pub(super) struct DrawBatch {
+    segments: Vec<Segment>,
+    /// Draw area as of the last batched command, in native coordinates, bounds inclusive
+    clip_x_min: i32,
+    clip_y_min: i32,
+    clip_x_max: i32,
+    clip_y_max: i32,
+    /// Draw mode as of the last batched command
+    draw_mode: DrawMode,
+}
+
+impl Default for DrawBatch {
+    fn default() -> DrawBatch {
+        DrawBatch {
+            segments: Vec::new(),
+            clip_x_min: 0,
+            clip_y_min: 0,
+            clip_x_max: 0,
+            clip_y_max: 0,
+            draw_mode: DrawMode::new(0),
+        }
+    }
+}
+
+impl DrawBatch {
+    /// Returns true if a batched command writes to `area`
+    pub(super) fn touches(&self, area: Option<VRamArea>) -> bool {
+        let Some(area) = area else {
+            return false;
+        };
+
+        self.segments
+            .iter()
+            .any(|s| s.targets.iter().any(|&t| t.intersects(area)))
+    }
+
+    fn clip_area(&self) -> Option<VRamArea> {
+        VRamArea::new(
+            self.clip_x_min,
+            self.clip_y_min,
+            self.clip_x_max - self.clip_x_min + 1,
+            self.clip_y_max - self.clip_y_min + 1,
+        )
+    }
+
+    fn push(&mut self, params: &[u32], precise: PreciseVertices) {
+        let opcode = params[0] >> 24;
+
+        let new_segment = match self.segments.last() {
+            None => true,
+            // Changing the draw area starts a new segment, unless the current one hasn't written
+            // anything yet
+            Some(s) => (opcode == 0xe3 || opcode == 0xe4) && !s.targets.is_empty(),
+        };
+
+        if new_segment {
+            self.segments.push(Segment::default());
+        }
+
+        match opcode {
+            0x02 => {
+                let dst = params[1];
+                let dim = params[2];
+
+                let x = (dst & 0x3f0) as i32;
+                let y = ((dst >> 16) & 0x3ff) as i32;
+                let width = (((dim & 0x3ff) + 0xf) & !0xf) as i32;
+                let height = ((dim >> 16) & 0x1ff) as i32;
+
+                if let Some(area) = VRamArea::new(x, y, width, height) {
+                    self.current().add_target(area);
+                }
+            }
+            0x20..=0x7f => {
+                // Lines are never textured
+                let is_line = (0x40..0x60).contains(&opcode);
+                let textured = opcode & 0x04 != 0 && !is_line;
+
+                if textured {
+                    if opcode < 0x40 {
+                        // Polygons set the texture page in their second UV word, rects use the
+                        // current one
+                        let shaded = opcode & 0x10 != 0;
+                        let mode = if shaded { params[5] } else { params[4] };
+                        self.draw_mode.update_from_poly(mode);
+                    }
+
+                    // The CLUT is always in the first UV word
+                    self.add_texture_sources(params[2]);
+                }
+
+                if let Some(area) = self.clip_area() {
+                    self.current().add_target(area);
+                }
+            }
+            0xe1 => self.draw_mode = DrawMode::new(params[0]),
+            0xe3 => {
+                self.clip_x_min = (params[0] & 0x3ff) as i32;
+                self.clip_y_min = ((params[0] >> 10) & 0x3ff) as i32;
+            }
+            0xe4 => {
+                self.clip_x_max = (params[0] & 0x3ff) as i32;
+                self.clip_y_max = ((params[0] >> 10) & 0x3ff) as i32;
+            }
+            _ => (),
+        }
+
+        let segment = self.current();
+        if precise.iter().any(Option::is_some) {
+            segment.precise_vertices.push((segment.commands.len(), precise));
+        }
+        segment.commands.extend_from_slice(params);
+    }
+
+    fn current(&mut self) -> &mut Segment {
+        self.segments.last_mut().unwrap()
+    }
+
+    /// Add the texture page and CLUT read by a textured command
+    fn add_texture_sources(&mut self, clut: u32) {
+        for area in texture_sources(self.draw_mode, clut) {
+            self.current().add_source(area);
+        }
+    }
+}
+
+/// Areas read by a command textured with `mode` and the CLUT word `clut`: the texture page and the
+/// CLUT
+pub(super) fn texture_sources(mode: DrawMode, clut: u32) -> impl Iterator<Item = VRamArea> {
+    let pts = mode.pixel_to_texel_shift();
+
+    // A texture page is 256x256 texels
+    let page = VRamArea::new(
+        i32::from(mode.texture_page_x()),
+        i32::from(mode.texture_page_y()),
+        256 >> pts,
+        256,
+    );
+
+    // Truecolor textures don't use the CLUT
+    let clut = if pts != 0 {
+        let clut = (clut >> 16) & 0x7fff;
+        VRamArea::new(
+            ((clut & 0x3f) << 4) as i32,
+            ((clut >> 6) & 0x1ff) as i32,
+            256 >> ((pts - 1) * 4),
+            1,
+        )
+    } else {
+        None
+    };
+
+    [page, clut].into_iter().flatten()
+}
+
+/// Iterate over the commands in `words`
+fn split_commands(mut words: &[u32]) -> impl Iterator<Item = &[u32]> {
+    std::iter::from_fn(move || {
+        let &first = words.first()?;
+        let len = GP0_COMMANDS[(first >> 24) as usize].len as usize;
+
+        let (command, rest) = words.split_at(len);
+        words = rest;
+
+        Some(command)
+    })
+}
+
+/// Threads rasterizing the segments of the parallel batches, each on a rasterizer forked for it
+#[cfg(feature = "std")]
+pub(super) struct WorkerPool {
+    /// `None` once dropped, which stops the workers
+    jobs: Option<mpsc::Sender<(Rasterizer, Segment)>>,
+    /// One message per segment drawn, false if the worker panicked
+    done: mpsc::Receiver<bool>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+#[cfg(feature = "std")]
+impl WorkerPool {
+    fn new() -> WorkerPool {
+        let (jobs, queue) = mpsc::channel::<(Rasterizer, Segment)>();
+        let (done_sender, done) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(queue));
+
+        // The rasterizer thread draws a segment itself
+        let count = thread::available_parallelism().map_or(1, |n| n.get() - 1).max(1);
+
+        let threads = (0..count)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let done = done_sender.clone();
+
+                thread::Builder::new()
+                    .name("RSX GPU worker".to_string())
+                    .spawn(move || {
+                        loop {
+                            // The queue is unlocked as soon as a job is received
+                            let job = queue.lock().unwrap().recv();
+                            let Ok((mut rasterizer, segment)) = job else {
+                                break;
+                            };
+
+                            let drawn = panic::catch_unwind(AssertUnwindSafe(|| rasterizer.execute(&segment)));
+                            let _ = done.send(drawn.is_ok());
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        WorkerPool {
+            jobs: Some(jobs),
+            done,
+            threads,
+        }
+    }
+
+    fn run(&self, job: (Rasterizer, Segment)) {
+        self.jobs.as_ref().unwrap().send(job).unwrap();
+    }
+
+    /// Wait for `count` segments to be drawn
+    fn wait(&self, count: usize) {
+        for _ in 0..count {
+            assert!(self.done.recv().unwrap(), "GPU worker panicked");
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.jobs = None;
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Rasterizer {
+    pub(super) fn batch_draw(&mut self, params: &[u32], precise: PreciseVertices) {
+        if self.draw_batch.segments.is_empty() {
+            // Start from the current rasterizer state
+            let shift = self.vram.upscale_shift;
+
+            self.draw_batch.clip_x_min = self.clip_x_min >> shift;
+            self.draw_batch.clip_y_min = self.clip_y_min >> shift;
+            self.draw_batch.clip_x_max = self.clip_x_max >> shift;
+            self.draw_batch.clip_y_max = self.clip_y_max >> shift;
+            self.draw_batch.draw_mode = self.tex_mapper.draw_mode;
+        }
+
+        self.draw_batch.push(params, precise);
+    }
+
+    /// Rasterize all the batched commands
+    pub(super) fn flush_draws(&mut self) {
+        if self.draw_batch.segments.is_empty() {
+            return;
+        }
+
+        let mut segments = mem::take(&mut self.draw_batch.segments);
+
+        while !segments.is_empty() {
+            let mut end = 1;
+
+            while end < segments.len() && segments[end].is_independent_of(&segments[..end]) {
+                end += 1;
+            }
+
+            self.draw_segments(segments.drain(..end).collect());
+        }
+    }
+
+    /// Rasterize independent `segments`, in parallel if there's more than one drawing something
+    #[cfg(feature = "std")]
+    fn draw_segments(&mut self, mut segments: Vec<Segment>) {
+        let drawing = segments.iter().filter(|s| !s.targets.is_empty()).count();
+
+        if drawing < 2 {
+            for segment in &segments {
+                self.execute(segment);
+            }
+            return;
+        }
+
+        let last = segments.pop().unwrap();
+
+        // Every worker starts from the state left by the previous segments, we get there by
+        // replaying their state changes without drawing anything. We draw the last segment
+        // ourselves, after which we're in the same state as if everything had run in order.
+        let mut jobs = Vec::new();
+        for segment in segments {
+            let worker = (!segment.targets.is_empty()).then(|| self.fork());
+            self.replay_state(&segment.commands);
+
+            if let Some(worker) = worker {
+                jobs.push((worker, segment));
+            }
+        }
+
+        let workers = self.workers.take().unwrap_or_else(WorkerPool::new);
+        let count = jobs.len();
+
+        for job in jobs {
+            workers.run(job);
+        }
+        self.execute(&last);
+        // The workers draw to our VRAM, they must be done before anything else touches it
+        workers.wait(count);
+
+        self.workers = Some(workers);
+    }
+
+    /// Without threads the segments are only ever rasterized in order
+    #[cfg(not(feature = "std"))]
+    fn draw_segments(&mut self, segments: Vec<Segment>) {
+        for segment in &segments {
+            self.execute(segment);
+        }
+    }
+
+    fn execute(&mut self, segment: &Segment) {
+        let mut precise_vertices = segment.precise_vertices.iter().peekable();
+        let mut offset = 0;
+
+        for params in split_commands(&segment.commands) {
+            let h = &GP0_COMMANDS[(params[0] >> 24) as usize];
+
+            if let Some(&&(at, precise)) = precise_vertices.peek()
+                && at == offset
+            {
+                self.precise_vertices = precise;
+                precise_vertices.next();
+            }
+
+            (h.handler)(self, params);
+
+            self.precise_vertices = PreciseVertices::default();
+            offset += params.len();
+        }
+    }
+
+    /// Apply the state changes of `commands` without drawing anything
+    #[cfg(feature = "std")]
+    fn replay_state(&mut self, commands: &[u32]) {
+        for params in split_commands(commands) {
+            let opcode = params[0] >> 24;
+            let textured = opcode & 0x04 != 0;
+
+            match opcode {
+                0x01 | 0xe1..=0xe6 => (GP0_COMMANDS[opcode as usize].handler)(self, params),
+                // Textured polygons change the draw mode
+                0x20..=0x3f if textured => {
+                    let shaded = opcode & 0x10 != 0;
+                    let mode = if shaded { params[5] } else { params[4] };
+
+                    self.tex_mapper.update_mode_from_poly(mode);
+                    self.set_clut(params[2]);
+                }
+                0x60..=0x7f if textured => self.set_clut(params[2]),
+                _ => (),
+            }
+        }
+    }
+
+    /// Create a rasterizer with the same state, drawing to the same VRAM
+    #[cfg(feature = "std")]
+    fn fork(&self) -> Rasterizer {
+        Rasterizer {
+            vram: self.vram.share(),
+            state: State::WaitingForCommand,
+            cur_frame: Frame::new(0, 0),
+            clip_x_min: self.clip_x_min,
+            clip_y_min: self.clip_y_min,
+            clip_x_max: self.clip_x_max,
+            clip_y_max: self.clip_y_max,
+            draw_offset_x: self.draw_offset_x,
+            draw_offset_y: self.draw_offset_y,
+            mask_settings: self.mask_settings.clone(),
+            tex_mapper: self.tex_mapper.clone(),
+            vram_display_mode: self.vram_display_mode,
+            display_line_start: self.display_line_start,
+            display_line_end: self.display_line_end,
+            display_column_start: self.display_column_start,
+            display_column_end: self.display_column_end,
+            display_mode: self.display_mode,
+            display_vram_x_start: self.display_vram_x_start,
+            display_vram_y_start: self.display_vram_y_start,
+            display_off: self.display_off,
+            force_transparency: self.force_transparency,
+            accurate_transparency: self.accurate_transparency,
+            dither_table: self.dither_table,
+            dither_enabled: self.dither_enabled,
+            dithering_force_disable: self.dithering_force_disable,
+            draw_24bpp: self.draw_24bpp,
+            display_bottom_field: self.display_bottom_field,
+            draw_wireframe: self.draw_wireframe,
+            draw_polygons: self.draw_polygons,
+            draw_textures: self.draw_textures,
+            draw_semi_transparency: self.draw_semi_transparency,
+            skip_draws: self.skip_draws,
+            parallel_draw_areas: false,
+            workers: None,
+            draw_batch: DrawBatch::default(),
+            precise_vertices: PreciseVertices::default(),
+            overdraw: None,
+        }
+    }
+
+    /// Area of the VRAM currently being displayed
+    pub(super) fn display_area(&self) -> Option<VRamArea> {
+        let mut width = i32::from(self.display_mode.xres());
+        if self.display_mode.output_24bpp() {
+            // 3 bytes per pixel, plus the extra pixel read at the end of the line
+            width = width * 3 / 2 + 1;
+        }
+
+        let mut height = i32::from(self.display_line_end) - i32::from(self.display_line_start);
+        if height <= 0 {
+            height = 512;
+        }
+        if self.display_mode.is_true_interlaced() {
+            height *= 2;
+        }
+
+        VRamArea::new(
+            i32::from(self.display_vram_x_start),
+            i32::from(self.display_vram_y_start),
+            width,
+            height,
+        )
+    }
+}
+
+#[test]
+fn vram_area_wrap() {
+    let a = VRamArea::new(1000, 500, 100, 100).unwrap();
+
+    assert!(a.intersects(VRamArea::new(0, 0, 10, 10).unwrap()));
+    assert!(a.intersects(VRamArea::new(1010, 10, 10, 10).unwrap()));
+    assert!(!a.intersects(VRamArea::new(100, 100, 10, 10).unwrap()));
+    assert!(VRamArea::new(0, 0, 0, 10).is_none());
+}
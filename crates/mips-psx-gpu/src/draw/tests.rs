@@ -3,9 +3,12 @@
 //! Unless otherwise noted the expected output was generated on a real PlayStation (model
 //! SCPH-7502, PAL).
 
+use std::hash::Hasher;
 use std::sync::mpsc;
-use crate::ps1::psx::graphics::rasterizer::draw::rasterizer::{Pixel, Rasterizer};
-use crate::ps1::psx::graphics::rasterizer::handle::{Command, CommandBuffer};
+use fnv::FnvHasher;
+use crate::draw::rasterizer::{Pixel, Rasterizer};
+use crate::handle::{Command, CommandBuffer, RasterizerOption};
+use crate::renderer::Renderer;
 
 fn build_rasterizer() -> (
     Rasterizer,
@@ -50,7 +53,7 @@ fn mbgr_px(mbgr: u16) -> Pixel {
 fn check_rasterizer(rasterizer: &Rasterizer, expected: &[&[Pixel]]) {
     for (y, line) in expected.iter().enumerate() {
         for (x, &color) in line.iter().enumerate() {
-            let p = rasterizer.vram.pixels[y * 1024 + x].to_mbgr1555();
+            let p = rasterizer.vram.pixel(x as u32, y as u32).to_mbgr1555();
             let color = color.to_mbgr1555();
 
             assert_eq!(
@@ -939,3 +942,460 @@ fn test_bad_draw_psx_logo() {
 
     check_rasterizer(&rasterizer, &expected);
 }
+
+/*
+ * Parallel draw areas
+ */
+
+fn draw_area(left: u32, top: u32, right: u32, bottom: u32) -> [Command; 2] {
+    [
+        Command::Gp0(0xe3000000 | left | (top << 10)),
+        Command::Gp0(0xe4000000 | right | (bottom << 10)),
+    ]
+}
+
+fn draw_split_screen(parallel: bool) -> Rasterizer {
+    let (mut rasterizer, command_channel, command_receiver) = build_rasterizer();
+    let (frame_sender, _frame_receiver) = mpsc::channel();
+    let (serialization_sender, _serialization_receiver) = mpsc::channel();
+
+    let mut commands = vec![Command::Option(RasterizerOption::ParallelDrawAreas(parallel))];
+
+    // Two independent viewports side by side
+    commands.extend(draw_area(0, 0, 15, 15));
+    commands.extend([
+        Command::Gp0(0x280000ff),
+        vertex_coord(0, 0),
+        vertex_coord(0, 16),
+        vertex_coord(16, 0),
+        vertex_coord(16, 16),
+    ]);
+    commands.extend(draw_area(16, 0, 31, 15));
+    commands.extend([
+        Command::Gp0(0x3000ff00),
+        vertex_coord(16, 0),
+        Command::Gp0(0x00ff0000),
+        vertex_coord(16, 16),
+        Command::Gp0(0x000000ff),
+        vertex_coord(32, 8),
+    ]);
+    // Third viewport texturing from the first two, it must wait for them
+    commands.extend(draw_area(32, 0, 63, 15));
+    commands.extend([
+        // 16bpp texture page at 0, 0
+        Command::Gp0(0xe1000100),
+        Command::Gp0(0x65000000),
+        vertex_coord(32, 0),
+        Command::Gp0(0x00000000),
+        Command::Gp0(0x00100020),
+        Command::Quit,
+    ]);
+
+    command_channel.send(commands).unwrap();
+
+    rasterizer.run(command_receiver, frame_sender, serialization_sender);
+
+    rasterizer
+}
+
+#[test]
+fn parallel_draw_areas() {
+    let sequential = draw_split_screen(false);
+    let parallel = draw_split_screen(true);
+
+    for y in 0..16 {
+        for x in 0..64 {
+            let p = parallel.vram.pixel(x, y);
+
+            assert_eq!(
+                sequential.vram.pixel(x, y),
+                p,
+                "VRAM {}x{}: parallel output differs",
+                x,
+                y
+            );
+
+            if x >= 32 {
+                // Copy of the first two viewports
+                assert_eq!(p, parallel.vram.pixel(x - 32, y));
+            }
+        }
+    }
+
+    assert_eq!(parallel.vram.pixel(3, 3), bgr_px(0x0000ff));
+    assert_ne!(parallel.vram.pixel(20, 8), Pixel::black());
+}
+
+/*
+ * Semi-transparency
+ */
+
+fn draw_quarter_add(accurate: bool) -> Rasterizer {
+    let (mut rasterizer, command_channel, command_receiver) = build_rasterizer();
+    let (frame_sender, _frame_receiver) = mpsc::channel();
+    let (serialization_sender, _serialization_receiver) = mpsc::channel();
+
+    let commands = vec![
+        Command::Option(RasterizerOption::AccurateTransparency(accurate)),
+        // Dark grey background
+        Command::Gp0(0x02202020),
+        Command::Gp0(0x00000000),
+        Command::Gp0(0x00010010),
+        // B + F / 4
+        Command::Gp0(0xe1000060),
+        // Semi-transparent white rect
+        Command::Gp0(0x62ffffff),
+        vertex_coord(0, 0),
+        Command::Gp0(0x00010004),
+        Command::Quit,
+    ];
+
+    command_channel.send(commands).unwrap();
+
+    rasterizer.run(command_receiver, frame_sender, serialization_sender);
+
+    rasterizer
+}
+
+#[test]
+fn transparency_quarter_add_rounding() {
+    let x = mbgr_px(0x1084);
+
+    // The GPU blends 5bit components: 4 + 31 / 4 = 11. Not from a real console, computed from
+    // the hardware formula.
+    let b = mbgr_px(0x2d6b);
+    check_rasterizer(&draw_quarter_add(true), &[&[b, b, b, b, x, x]]);
+
+    // With 8bit components the LSBs of the foreground carry over
+    let b = mbgr_px(0x318c);
+    check_rasterizer(&draw_quarter_add(false), &[&[b, b, b, b, x, x]]);
+}
+
+#[test]
+fn transparency_mask_bit() {
+    let program = [
+        // Dark grey background
+        Command::Gp0(0x02202020),
+        Command::Gp0(0x00000000),
+        Command::Gp0(0x00010010),
+        // B + F, setting the mask bit of the pixels drawn
+        Command::Gp0(0xe1000020),
+        Command::Gp0(0xe6000001),
+        // Semi-transparent dark grey rect
+        Command::Gp0(0x62202020),
+        vertex_coord(0, 0),
+        Command::Gp0(0x00010004),
+        // Leave the masked pixels alone
+        Command::Gp0(0xe6000002),
+        // Wider semi-transparent dark grey rect, only blended outside of the first one
+        Command::Gp0(0x62202020),
+        vertex_coord(0, 0),
+        Command::Gp0(0x00010008),
+    ];
+
+    let m = mbgr_px(0xa108);
+    let b = mbgr_px(0x2108);
+    let x = mbgr_px(0x1084);
+    check_rasterizer(&run_program(&program), &[&[m, m, m, m, b, b, b, b, x, x]]);
+}
+
+#[test]
+fn debug_no_semi_transparency() {
+    let program = [
+        Command::Option(RasterizerOption::DrawSemiTransparency(false)),
+        // Dark grey background
+        Command::Gp0(0x02202020),
+        Command::Gp0(0x00000000),
+        Command::Gp0(0x00010010),
+        // B + F / 4
+        Command::Gp0(0xe1000060),
+        // Semi-transparent white rect, drawn opaque
+        Command::Gp0(0x62ffffff),
+        vertex_coord(0, 0),
+        Command::Gp0(0x00010004),
+    ];
+
+    let w = bgr_px(0xffffff);
+    let x = mbgr_px(0x1084);
+    check_rasterizer(&run_program(&program), &[&[w, w, w, w, x, x]]);
+}
+
+/*
+ * Frame skip
+ */
+
+#[test]
+fn skip_draws() {
+    let (mut rasterizer, command_channel, command_receiver) = build_rasterizer();
+    let (frame_sender, _frame_receiver) = mpsc::channel();
+    let (serialization_sender, _serialization_receiver) = mpsc::channel();
+
+    let commands = vec![
+        Command::Option(RasterizerOption::SkipDraws(true)),
+        // Fill rect
+        Command::Gp0(0x02ffffff),
+        Command::Gp0(0x00000000),
+        Command::Gp0(0x00010010),
+        // Opaque rect
+        Command::Gp0(0x60ffffff),
+        vertex_coord(0, 2),
+        Command::Gp0(0x00010004),
+        // Two pixels loaded at 0, 1
+        Command::Gp0(0xa0000000),
+        vertex_coord(0, 1),
+        Command::Gp0(0x00010002),
+        Command::Gp0(0x001f7c00),
+        Command::Quit,
+    ];
+
+    command_channel.send(commands).unwrap();
+
+    rasterizer.run(command_receiver, frame_sender, serialization_sender);
+
+    let x = Pixel::black();
+    check_rasterizer(&rasterizer, &[
+        &[x, x, x, x],
+        &[mbgr_px(0x7c00), mbgr_px(0x001f), x, x],
+        &[x, x, x, x],
+    ]);
+}
+
+/*
+ * Internal resolution
+ */
+
+#[test]
+fn upscaled_clip_and_state() {
+    let (mut rasterizer, command_channel, command_receiver) = build_rasterizer();
+    let (frame_sender, _frame_receiver) = mpsc::channel();
+    let (serialization_sender, serialization_receiver) = mpsc::channel();
+
+    let white_rect = |color: u32| [
+        Command::Gp0(0x60000000 | color),
+        vertex_coord(0, 0),
+        Command::Gp0(0x00020008),
+    ];
+
+    // Clip to the 4 leftmost columns, then draw at 2x. The state is serialized last.
+    let mut commands = vec![
+        Command::Gp0(0xe4000000 | 3 | (255 << 10)),
+        Command::Option(RasterizerOption::UpscaleShift(1)),
+    ];
+    commands.extend(white_rect(0xffffff));
+    commands.push(Command::Serialize);
+    command_channel.send(commands).unwrap();
+    command_channel.send(vec![Command::Quit]).unwrap();
+
+    rasterizer.run(command_receiver, frame_sender, serialization_sender);
+
+    // Each native pixel is 2x2
+    let white = bgr_px(0xffffff);
+    assert_eq!(rasterizer.vram.pixel(7, 3), white);
+    assert_eq!(rasterizer.vram.pixel(8, 0), Pixel::black());
+    assert_eq!(rasterizer.vram.pixel(0, 4), Pixel::black());
+
+    // Saved at the native resolution, clip included
+    let state = serialization_receiver.recv().unwrap();
+    let mut restored = Rasterizer::from_serialized(&state).unwrap();
+    let (command_channel, command_receiver) = mpsc::channel();
+    let (frame_sender, _frame_receiver) = mpsc::channel();
+    let (serialization_sender, _serialization_receiver) = mpsc::channel();
+
+    let mut commands = white_rect(0x0000ff).to_vec();
+    commands.push(Command::Quit);
+    command_channel.send(commands).unwrap();
+
+    restored.run(command_receiver, frame_sender, serialization_sender);
+
+    let red = bgr_px(0x0000ff);
+    let x = Pixel::black();
+    check_rasterizer(&restored, &[
+        &[red, red, red, red, x],
+        &[red, red, red, red, x],
+        &[x, x, x, x, x],
+    ]);
+}
+
+/*
+ * Golden images
+ *
+ * Small command programs covering the main primitives, checked against the hash of the drawing
+ * area. Unlike the tests above the hashes weren't captured on a console: they're the output of
+ * this rasterizer once checked by eye, to catch regressions. When a change is expected to modify
+ * a picture the failing assertion prints the new hash.
+ */
+
+/// Size of the area hashed, from the top left corner of the VRAM
+const GOLDEN_SIZE: u16 = 128;
+
+fn run_program(program: &[Command]) -> Rasterizer {
+    let (mut rasterizer, command_channel, command_receiver) = build_rasterizer();
+    let (frame_sender, _frame_receiver) = mpsc::channel();
+    let (serialization_sender, _serialization_receiver) = mpsc::channel();
+
+    let mut commands = program.to_vec();
+    commands.push(Command::Quit);
+    command_channel.send(commands).unwrap();
+
+    rasterizer.run(command_receiver, frame_sender, serialization_sender);
+
+    rasterizer
+}
+
+/// FNV-1a hash of the drawing area
+fn vram_hash(rasterizer: &Rasterizer) -> u64 {
+    let mut hasher = FnvHasher::default();
+
+    for y in 0..GOLDEN_SIZE {
+        for x in 0..GOLDEN_SIZE {
+            hasher.write(&rasterizer.vram.pixel(u32::from(x), u32::from(y)).to_mbgr1555().to_le_bytes());
+        }
+    }
+
+    hasher.finish()
+}
+
+fn check_golden(name: &str, program: &[Command], expected: u64) {
+    let hash = vram_hash(&run_program(program));
+
+    assert_eq!(hash, expected, "{}: got 0x{:016x}", name, hash);
+}
+
+/// Load `halfwords` (an even number of them) to the VRAM at `x`, `y`
+fn vram_load(x: u16, y: u16, width: u16, halfwords: &[u16]) -> Vec<Command> {
+    let height = halfwords.len() as u16 / width;
+
+    let mut commands = vec![
+        Command::Gp0(0xa0000000),
+        vertex_coord(x as i16, y as i16),
+        vertex_coord(width as i16, height as i16),
+    ];
+    commands.extend(halfwords.chunks(2).map(|p| Command::Gp0(u32::from(p[0]) | (u32::from(p[1]) << 16))));
+
+    commands
+}
+
+#[test]
+fn golden_flat_triangle() {
+    let program = [
+        Command::Gp0(0x2000c0ff),
+        vertex_coord(8, 4),
+        vertex_coord(120, 40),
+        vertex_coord(30, 110),
+    ];
+
+    check_golden("flat triangle", &program, 0xa2d97664a094ac79);
+}
+
+#[test]
+fn golden_gouraud_quad() {
+    let program = [
+        // Dithering on
+        Command::Gp0(0xe1000200),
+        Command::Gp0(0x380000ff),
+        vertex_coord(0, 0),
+        Command::Gp0(0x0000ff00),
+        vertex_coord(127, 10),
+        Command::Gp0(0x00ff0000),
+        vertex_coord(10, 127),
+        Command::Gp0(0x00ffffff),
+        vertex_coord(100, 100),
+    ];
+
+    check_golden("gouraud quad", &program, 0xf11419a18c9039c5);
+}
+
+#[test]
+fn golden_lines() {
+    let program = [
+        // Monochrome line
+        Command::Gp0(0x4000ffff),
+        vertex_coord(2, 2),
+        vertex_coord(125, 60),
+        // Shaded line
+        Command::Gp0(0x500000ff),
+        vertex_coord(2, 120),
+        Command::Gp0(0x00ff0000),
+        vertex_coord(120, 8),
+        // Shaded poly-line
+        Command::Gp0(0x58ffffff),
+        vertex_coord(10, 70),
+        Command::Gp0(0x0000ff00),
+        vertex_coord(60, 120),
+        Command::Gp0(0x00ff00ff),
+        vertex_coord(110, 70),
+        Command::Gp0(0x55555555),
+    ];
+
+    check_golden("lines", &program, 0x601e06ea5fcba983);
+}
+
+#[test]
+fn golden_textured_sprite() {
+    // 16x16 4bpp texture at 512, 0: diagonal stripes going through the palette
+    let texture: Vec<u16> = (0..16u16)
+        .flat_map(|v| (0..4u16).map(move |u| (0..4).fold(0, |h, i| h | (((u * 4 + i + v) & 0xf) << (i * 4)))))
+        .collect();
+    // Palette at 0, 480, index 0 is transparent
+    let clut: Vec<u16> = (0..16u16).map(|i| (i << 10) | ((15 - i) << 5) | (i * 2)).collect();
+
+    let mut program = vram_load(512, 0, 4, &texture);
+    program.extend(vram_load(0, 480, 16, &clut));
+    program.extend([
+        // Grey background, to see the transparent texels
+        Command::Gp0(0x02404040),
+        Command::Gp0(0x00000000),
+        vertex_coord(GOLDEN_SIZE as i16, 64),
+        // 4bpp texture page at 512, 0
+        Command::Gp0(0xe1000008),
+        // Raw texture
+        Command::Gp0(0x65000000),
+        vertex_coord(8, 8),
+        Command::Gp0(0x78000000),
+        vertex_coord(16, 16),
+        // Modulated by half grey
+        Command::Gp0(0x64404040),
+        vertex_coord(40, 8),
+        Command::Gp0(0x78000000),
+        vertex_coord(16, 16),
+        // Textured triangle, flipped UVs
+        Command::Gp0(0x25000000),
+        vertex_coord(70, 8),
+        Command::Gp0(0x7800000f),
+        vertex_coord(120, 8),
+        Command::Gp0(0x00080000),
+        vertex_coord(70, 56),
+        Command::Gp0(0x00000f0f),
+    ]);
+
+    check_golden("textured sprite", &program, 0x6a8506831f963b51);
+}
+
+#[test]
+fn golden_semi_transparency() {
+    let mut program = vec![
+        // Half grey background
+        Command::Gp0(0x02808080),
+        Command::Gp0(0x00000000),
+        vertex_coord(GOLDEN_SIZE as i16, 32),
+    ];
+
+    // B/2 + F/2, B + F, B - F, B + F/4
+    for mode in 0..4 {
+        program.extend([
+            Command::Gp0(0xe1000000 | (mode << 5)),
+            Command::Gp0(0x6200ff40),
+            vertex_coord(mode as i16 * 32, 0),
+            vertex_coord(32, 16),
+            // Gouraud shaded semi-transparent triangle
+            Command::Gp0(0x320000ff),
+            vertex_coord(mode as i16 * 32, 16),
+            Command::Gp0(0x00ffffff),
+            vertex_coord(mode as i16 * 32 + 31, 16),
+            Command::Gp0(0x00ff0000),
+            vertex_coord(mode as i16 * 32, 31),
+        ]);
+    }
+
+    check_golden("semi-transparency", &program, 0x7a729ec95c210664);
+}
@@ -1,24 +1,55 @@
 //! Code for the rasterizer. It runs in a different threads from the rest of the emulator for
 //! performance reasons and communicates through a pair of channels (one to receive draw commands,
-//! one to send back the finished frames).
+//! one to send back the finished frames). On the targets without threads (WebAssembly) and without
+//! the `std` feature it runs on the emulation thread instead, as the commands are sent.
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
 use std::sync::mpsc;
+#[cfg(feature = "std")]
 use std::thread;
-use crate::ps1::psx::graphics::rasterizer::draw::rasterizer::Rasterizer;
-use crate::ps1::settings::graphics::VRamDisplayMode;
+use crate::draw::rasterizer::Rasterizer;
+use crate::renderer::Renderer;
+use crate::pgxp::PreciseVertex;
+use crate::settings::{RendererBackend, VRamDisplayMode};
 
 /// This is the handle used from the main thread to communicate with the rasterizer
 pub struct Handle {
     command_buffer: CommandBuffer,
     frame_pending: bool,
-    handle: Option<thread::JoinHandle<()>>,
-    command_channel: mpsc::Sender<CommandBuffer>,
+    worker: Worker,
     frame_channel: mpsc::Receiver<Frame>,
     serialization_channel: mpsc::Receiver<Vec<u8>>,
 }
 
+/// Where the renderer runs
+enum Worker {
+    /// On its own thread, until it gets `Command::Quit`
+    #[cfg(feature = "std")]
+    Thread {
+        handle: Option<thread::JoinHandle<()>>,
+        command_channel: mpsc::Sender<CommandBuffer>,
+    },
+    /// On the emulation thread, the commands are processed as soon as they're sent
+    Inline {
+        renderer: RefCell<Box<dyn Renderer>>,
+        frame_channel: mpsc::Sender<Frame>,
+        serialization_channel: mpsc::Sender<Vec<u8>>,
+    },
+}
+
 impl Handle {
+    /// Hand `commands` over to the renderer
+    fn send(&self, commands: CommandBuffer) {
+        match &self.worker {
+            #[cfg(feature = "std")]
+            Worker::Thread { command_channel, .. } => command_channel.send(commands).unwrap(),
+            Worker::Inline { renderer, frame_channel, serialization_channel } => {
+                renderer.borrow_mut().process(&commands, frame_channel, serialization_channel);
+            }
+        }
+    }
+
     pub fn push_command(&mut self, c: Command) {
         self.command_buffer.push(c);
     }
@@ -33,7 +64,7 @@ impl Handle {
 
         ::std::mem::swap(&mut commands, &mut self.command_buffer);
 
-        self.command_channel.send(commands).unwrap();
+        self.send(commands);
     }
 
     /// Notify the rasterizer that a line has been fully displayed on the TV output
@@ -55,16 +86,19 @@ impl Handle {
 
     /// Notify the rasterizer that the current frame is done drawing and should be returned through
     /// the frame channel
-    pub fn end_of_frame(&mut self) {
+    /// Returns true if the previous frame hadn't been taken and got dropped
+    pub fn end_of_frame(&mut self) -> bool {
         self.push_command(Command::EndOfFrame);
         self.flush_command_buffer();
 
         // Make sure we were not already waiting for a frame
-        self.take_frame();
+        let dropped = self.take_frame().is_some();
 
         // Instead of blocking immediately waiting for the frame let's just save the fact that we
         // asked for a frame
         self.frame_pending = true;
+
+        dropped
     }
 
     pub fn set_option(&mut self, opt: RasterizerOption) {
@@ -72,6 +106,40 @@ impl Handle {
         self.flush_command_buffer();
     }
 
+    /// Restart the rasterizer thread with `backend`, picking up where the current one is. Returns
+    /// false if the hardware renderer can't start, the software rasterizer carries on then.
+    pub fn set_renderer(&mut self, backend: RendererBackend) -> bool {
+        self.flush_command_buffer();
+
+        // Only happens if the frame wasn't taken, it's dropped
+        self.take_frame();
+
+        self.send(vec![Command::Serialize]);
+        let state = self.serialization_channel.recv().unwrap();
+        let rasterizer = Rasterizer::from_serialized(&state)
+            .expect("The rasterizer failed to load its own state");
+
+        let (renderer, started): (Box<dyn Renderer>, bool) = match backend {
+            RendererBackend::Software => (Box::new(rasterizer), true),
+            #[cfg(feature = "hardware-renderer")]
+            RendererBackend::Hardware => {
+                use crate::draw::rasterizer::hardware;
+
+                match hardware::GpuContext::shared() {
+                    Some(context) => (Box::new(hardware::HardwareRenderer::new(context, rasterizer)), true),
+                    None => (Box::new(rasterizer), false),
+                }
+            }
+            #[cfg(not(feature = "hardware-renderer"))]
+            RendererBackend::Hardware => (Box::new(rasterizer), false),
+        };
+
+        // Dropping the previous handle stops its renderer
+        *self = start_from_state(Vec::new(), renderer);
+
+        started
+    }
+
     pub fn take_frame(&mut self) -> Option<Frame> {
         if self.frame_pending {
             self.frame_pending = false;
@@ -91,6 +159,11 @@ impl Handle {
         self.push_command(Command::Gp0(gp0));
     }
 
+    /// Precise position of the vertex `index` of the next polygon
+    pub fn push_precise_vertex(&mut self, index: usize, vertex: PreciseVertex) {
+        self.push_command(Command::PreciseVertex(index as u8, vertex));
+    }
+
     pub fn push_gp1(&mut self, gp1: u32) {
         self.push_command(Command::Gp1(gp1));
     }
@@ -102,7 +175,10 @@ impl ::std::ops::Drop for Handle {
         self.push_command(Command::Quit);
         self.flush_command_buffer();
 
-        if let Some(t) = self.handle.take() {
+        #[cfg(feature = "std")]
+        if let Worker::Thread { handle, .. } = &mut self.worker
+            && let Some(t) = handle.take()
+        {
             t.join().unwrap();
         }
     }
@@ -122,7 +198,7 @@ impl Serialize for Handle {
         // Ask the rasterizer to serialize its state
         let cmd = vec![Command::Serialize];
 
-        self.command_channel.send(cmd).unwrap();
+        self.send(cmd);
 
         let command_buffer = self.command_buffer.clone();
         let rasterizer_state = self.serialization_channel.recv().unwrap();
@@ -155,38 +231,75 @@ impl<'de> Deserialize<'de> for Handle {
             }
         };
 
-        Ok(start_from_state(s.command_buffer, rasterizer))
+        Ok(start_from_state(s.command_buffer, Box::new(rasterizer)))
     }
 }
 
-pub fn start_from_state(command_buffer: CommandBuffer, mut rasterizer: Rasterizer) -> Handle {
-    let (command_sender, command_receiver) = mpsc::channel();
+pub fn start_from_state(command_buffer: CommandBuffer, renderer: Box<dyn Renderer>) -> Handle {
     let (frame_sender, frame_receiver) = mpsc::channel();
     let (serialization_sender, serialization_receiver) = mpsc::channel();
 
+    #[cfg(feature = "std")]
+    let worker = if cfg!(target_family = "wasm") {
+        inline(renderer, frame_sender, serialization_sender)
+    } else {
+        spawn(renderer, frame_sender, serialization_sender)
+    };
+    #[cfg(not(feature = "std"))]
+    let worker = inline(renderer, frame_sender, serialization_sender);
+
+    Handle {
+        command_buffer,
+        frame_pending: false,
+        worker,
+        frame_channel: frame_receiver,
+        serialization_channel: serialization_receiver,
+    }
+}
+
+/// Run `renderer` on the emulation thread, as the commands are sent
+fn inline(
+    mut renderer: Box<dyn Renderer>,
+    frame_sender: mpsc::Sender<Frame>,
+    serialization_sender: mpsc::Sender<Vec<u8>>,
+) -> Worker {
+    renderer.prepare();
+
+    Worker::Inline {
+        renderer: RefCell::new(renderer),
+        frame_channel: frame_sender,
+        serialization_channel: serialization_sender,
+    }
+}
+
+/// Run `renderer` on a thread of its own
+#[cfg(feature = "std")]
+fn spawn(
+    mut renderer: Box<dyn Renderer>,
+    frame_sender: mpsc::Sender<Frame>,
+    serialization_sender: mpsc::Sender<Vec<u8>>,
+) -> Worker {
+    let (command_sender, command_receiver) = mpsc::channel();
+
     let builder = thread::Builder::new()
         .name("RSX GPU".to_string())
         .stack_size(1024 * 1024);
 
     let handle = builder
         .spawn(move || {
-            rasterizer.run(command_receiver, frame_sender, serialization_sender);
+            renderer.run(command_receiver, frame_sender, serialization_sender);
         })
         .unwrap();
 
-    Handle {
-        command_buffer,
+    Worker::Thread {
         handle: Some(handle),
-        frame_pending: false,
         command_channel: command_sender,
-        frame_channel: frame_receiver,
-        serialization_channel: serialization_receiver,
     }
 }
 
 /// Starts a new rasterizer thread and returns a handle to it
 pub fn start() -> Handle {
-    start_from_state(Vec::new(), Rasterizer::new())
+    start_from_state(Vec::new(), Box::new(Rasterizer::new()))
 }
 
 pub type CommandBuffer = Vec<Command>;
@@ -197,6 +310,8 @@ pub enum Command {
     Gp0(u32),
     /// GP1 register command
     Gp1(u32),
+    /// Precise position (PGXP) of a vertex of the polygon command that follows, by vertex index
+    PreciseVertex(u8, PreciseVertex),
     /// Terminate rasterization
     Quit,
     /// Finalize current line
@@ -221,11 +336,34 @@ impl Command {
 pub enum RasterizerOption {
     VRamDisplayMode(VRamDisplayMode),
     ForceTransparency(bool),
+    /// Blend semi-transparent pixels with the 15bit precision of the real GPU
+    AccurateTransparency(bool),
     Draw24Bpp(bool),
     DitherForceDisable(bool),
     Wireframe(bool),
     DrawPolygons(bool),
+    /// Draw the textured draws with their vertex colors
+    DrawTextures(bool),
+    /// Draw the semi-transparent draws opaque
+    DrawSemiTransparency(bool),
+    /// Don't draw anything to the VRAM, only keep track of the GPU state and transfers
+    SkipDraws(bool),
     UpscaleShift(u8),
+    /// Rasterize the commands targeting independent draw areas (split-screen viewports) on
+    /// separate threads. Off by default: the threads don't share the texture cache.
+    ParallelDrawAreas(bool),
+    /// Output how many times each pixel was drawn instead of the picture
+    Overdraw(bool),
+}
+
+/// What the color components of the xRGB 8888 pixels of a frame hold
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum PixelFormat {
+    /// 5 significant bits per component like the real console, with the MSBs copied to the LSBs
+    #[default]
+    Rgb555,
+    /// All 8 bits are significant, the rasterizer drew in true color
+    Rgb888,
 }
 
 /// Buffer containing one rendered frame
@@ -235,6 +373,8 @@ pub struct Frame {
     pub pixels: Vec<u32>,
     pub width: u32,
     pub height: u32,
+    #[serde(default)]
+    pub format: PixelFormat,
 }
 
 impl Frame {
@@ -245,6 +385,7 @@ impl Frame {
             pixels: vec![0; npixels as usize],
             width,
             height,
+            format: PixelFormat::Rgb555,
         }
     }
 
@@ -0,0 +1,70 @@
+//! PlayStation GPU rasterizer, on its own: it draws the GP0 and GP1 command words the console's
+//! GPU receives into a 1024x512 VRAM and outputs the frames. It knows nothing of the rest of the
+//! console, which makes it usable for frame analyzers, texture dumpers or test harnesses fed with
+//! logged command streams.
+//!
+//! # Command stream
+//!
+//! The rasterizer runs on a thread of its own and is driven by `Command`s, sent in batches
+//! (`CommandBuffer`):
+//!
+//! - `Command::Gp0` and `Command::Gp1` are the words written to the GPU ports. The draw commands
+//!   must be complete: a command is never split across two buffers.
+//! - `Command::PreciseVertex` gives the subpixel position of a vertex of the polygon that follows
+//!   (see `pgxp`), the commands without it are drawn at whole pixels.
+//! - `Command::EndOfLine` and `Command::FieldChanged` follow the video output: the display area is
+//!   copied to the frame line by line, as the console scans it out.
+//! - `Command::EndOfFrame` sends the frame drawn since the previous one.
+//! - `Command::Option` changes a `RasterizerOption` (internal resolution, debug views, ...).
+//! - `Command::Serialize` sends the state of the rasterizer, to reload it with
+//!   `Rasterizer::from_serialized`.
+//! - `Command::Quit` stops the thread.
+//!
+//! The frames, and the pixels read back by the VRAM to CPU transfers (GP0 0xc0), come back in the
+//! order they were asked for as `Frame`s of xRGB 8888 pixels.
+//!
+//! `handle::start` spawns the thread and returns a `Handle` buffering the commands. In
+//! WebAssembly, which has no threads, and without the default `std` feature, the `Handle` draws
+//! them itself as they're flushed. Tools that
+//! want to run the rasterizer on their own thread can call `Renderer::run` on a `Rasterizer`
+//! with their own channels, or `Renderer::process` to feed it the commands directly.
+//!
+//! # Example
+//!
+//! ```
+//! use mips_psx_gpu::handle::{self, RasterizerOption};
+//! use mips_psx_gpu::settings::VRamDisplayMode;
+//!
+//! let mut gpu = handle::start();
+//!
+//! // Output the whole VRAM instead of the display area. The frame being drawn still has the size
+//! // of the previous mode, it's dropped.
+//! gpu.set_option(RasterizerOption::VRamDisplayMode(VRamDisplayMode::Full16bpp));
+//! gpu.end_of_frame();
+//! gpu.take_frame();
+//!
+//! // Fill a 16x16 rectangle in the top left corner with red
+//! gpu.push_gp0(0x02_0000ff);
+//! gpu.push_gp0(0x0000_0000);
+//! gpu.push_gp0(0x0010_0010);
+//! gpu.end_of_frame();
+//!
+//! let frame = gpu.take_frame().unwrap();
+//! assert_eq!((frame.width, frame.height), (1024, 512));
+//! assert_eq!(frame.pixels[0] & 0xff_ff_ff, 0xff_00_00);
+//! ```
+//!
+//! The hardware renderer, drawing with the host's GPU through wgpu, is built with the
+//! `hardware-renderer` feature.
+
+pub mod handle;
+pub mod pgxp;
+pub mod primitive;
+pub mod regs;
+pub mod renderer;
+pub mod settings;
+mod draw;
+
+pub use draw::rasterizer::{Pixel, Rasterizer};
+#[cfg(feature = "hardware-renderer")]
+pub use draw::rasterizer::hardware::{GpuContext, HardwareRenderer};
@@ -0,0 +1,17 @@
+//! Precise vertex positions (PGXP). The emulator's GTE tracks the subpixel positions of the
+//! vertices it projects and sends them along the draw commands with `Command::PreciseVertex`; the
+//! renderers draw the polygons at those positions instead of the whole pixels of the commands.
+
+/// Where a vertex is beyond the integer coordinates of its SXY word
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PreciseVertex {
+    /// Offset from the X coordinate of the word, in 1/256 pixels
+    pub x_offset: i16,
+    /// Offset from the Y coordinate of the word, in 1/256 pixels
+    pub y_offset: i16,
+    /// Depth of the vertex (SZ), for the perspective correction
+    pub z: u16,
+}
+
+/// Precise vertices of a polygon by vertex index, `None` for the ones that weren't tracked
+pub type PreciseVertices = [Option<PreciseVertex>; 4];
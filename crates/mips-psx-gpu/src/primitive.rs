@@ -0,0 +1,139 @@
+//! Properties of the draw commands shared by the GPU façade of the emulator, which times them, and
+//! the renderers, which draw them. The modes are marker types so that the draw functions are
+//! monomorphized for each combination.
+
+pub trait TransparencyMode {
+    fn is_transparent() -> bool;
+}
+
+pub struct Transparent;
+
+impl TransparencyMode for Transparent {
+    fn is_transparent() -> bool {
+        true
+    }
+}
+
+pub struct Opaque;
+
+impl TransparencyMode for Opaque {
+    fn is_transparent() -> bool {
+        false
+    }
+}
+
+pub trait TextureMode {
+    fn is_textured() -> bool;
+    fn is_raw_texture() -> bool;
+}
+
+pub struct NoTexture;
+
+impl TextureMode for NoTexture {
+    fn is_textured() -> bool {
+        false
+    }
+
+    fn is_raw_texture() -> bool {
+        false
+    }
+}
+
+pub struct TextureBlending;
+
+impl TextureMode for TextureBlending {
+    fn is_textured() -> bool {
+        true
+    }
+
+    fn is_raw_texture() -> bool {
+        false
+    }
+}
+
+pub struct TextureRaw;
+
+impl TextureMode for TextureRaw {
+    fn is_textured() -> bool {
+        true
+    }
+
+    fn is_raw_texture() -> bool {
+        true
+    }
+}
+
+pub trait ShadingMode {
+    fn is_shaded() -> bool;
+}
+
+pub struct NoShading;
+
+impl ShadingMode for NoShading {
+    fn is_shaded() -> bool {
+        false
+    }
+}
+
+pub struct Shaded;
+
+impl ShadingMode for Shaded {
+    fn is_shaded() -> bool {
+        true
+    }
+}
+
+/// A vertex's coordinates
+#[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32) -> Position {
+        Position { x, y }
+    }
+
+    pub fn from_command(c: u32) -> Position {
+        let x = c;
+        let y = c >> 16;
+
+        // XXX The coordinates each take 16bits and are signed, however mednafen extends the sign
+        // starting on bit 11, not 15.
+        let x = extend_to_i32(x, 11);
+        let y = extend_to_i32(y, 11);
+        Position::new(x, y)
+    }
+}
+
+/// Extend a signed value on `n` bit to an i32
+pub(crate) fn extend_to_i32(val: u32, n: usize) -> i32 {
+    let shift = 32 - n;
+
+    ((val << shift) as i32) >> shift
+}
+
+/// Parses the command word for a VRAM store, load or copy and returns the dimensions of the target
+/// rectangle
+pub fn vram_access_dimensions(dim: u32, is_load: bool) -> (i32, i32) {
+    // Width is in GPU pixels, i.e. 16bits per pixel
+    let mut width = dim & 0x3ff;
+    let mut height = (dim >> 16) & 0x1ff;
+
+    // XXX recheck this, a comment in mednafen says that the results for VRAM load are inconsistent
+    if width == 0 {
+        width = 1024;
+    }
+
+    // XXX not sure about this difference, taken from mednafen
+    if is_load {
+        if height > 0x200 {
+            height &= 0x1ff;
+        }
+    } else if height == 0 {
+        height = 512;
+    }
+
+    (width as i32, height as i32)
+}
@@ -0,0 +1,290 @@
+//! Wrappers around the GPU registers the draw commands depend on, shared by the GPU façade of the
+//! emulator and the rasterizer.
+
+use crate::draw::rasterizer::Pixel;
+
+/// Wrapper around the Draw Mode register value (set by GP0[0xe1] and polygon draw commands)
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone)]
+pub struct DrawMode(u32);
+
+impl DrawMode {
+    pub fn new(mode: u32) -> DrawMode {
+        DrawMode(mode)
+    }
+
+    /// Raw register value
+    pub fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// Update from a polygon draw command. When that happens it overwrites the previous value
+    /// globally (i.e. the configuration remains set for any subsequent draw commands, not just the
+    /// current polygon)
+    pub fn update_from_poly(&mut self, poly_cmd: u32) {
+        // XXX bit 11 (texture_disable) can also be set/cleared, but only if the functionality is
+        // enabled using GP1[0x09]
+        self.0 &= !0x9ff;
+        self.0 |= (poly_cmd >> 16) & 0x9ff;
+    }
+
+    pub fn texture_disable(self) -> bool {
+        self.0 & (1 << 11) != 0
+    }
+
+    /// Coordinate of the left side of the texture page in VRAM
+    pub fn texture_page_x(self) -> u16 {
+        let x = (self.0 & 0xf) as u16;
+
+        x << 6
+    }
+
+    /// Coordinate of the top side of the texture page in VRAM
+    pub fn texture_page_y(self) -> u16 {
+        let y = ((self.0 >> 4) & 1) as u16;
+
+        y << 8
+    }
+
+    pub fn texture_depth(self) -> u8 {
+        ((self.0 >> 7) & 3) as u8
+    }
+
+    pub fn pixel_to_texel_shift(self) -> u8 {
+        match self.texture_depth() {
+            0 => 2, // Paletted 4bpp
+            1 => 1, // Paletted 8bpp
+            2 => 0, // True Color 1555BGR, 16bits per pixel
+            _ => 0, // XXX double-check if 3 is also truecolor.
+        }
+    }
+
+    pub fn transparency_mode(self) -> TransparencyFunction {
+        match (self.0 >> 5) & 3 {
+            0 => TransparencyFunction::Average,
+            1 => TransparencyFunction::Add,
+            2 => TransparencyFunction::Sub,
+            3 => TransparencyFunction::QuarterAdd,
+            _ => unreachable!(),
+        }
+    }
+
+    /// If true rectangle textures should be flipped horizontally
+    pub fn flip_rect_x(self) -> bool {
+        self.0 & (1 << 12) != 0
+    }
+
+    /// If true rectangle textures should be flipped vertically
+    pub fn flip_rect_y(self) -> bool {
+        self.0 & (1 << 13) != 0
+    }
+
+    /// Return true if dithering is enabled
+    pub fn dither_enable(self) -> bool {
+        self.0 & (1 << 9) != 0
+    }
+
+    /// True if the GPU is allowed to draw to the display area
+    pub fn draw_to_display_area(self) -> bool {
+        self.0 & (1 << 10) != 0
+    }
+}
+
+/// The various transparency modes
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum TransparencyFunction {
+    /// (Background + Foreground) / 2
+    Average,
+    /// Background + Foreground
+    Add,
+    /// Background - Foreground
+    Sub,
+    /// Background + (Foreground / 4)
+    QuarterAdd,
+}
+
+/// Wrapper around the Texture Window register value (set by GP0[0xe2])
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone)]
+pub struct TextureWindow(u32);
+
+impl Default for TextureWindow {
+    fn default() -> TextureWindow {
+        TextureWindow::new()
+    }
+}
+
+impl TextureWindow {
+    pub fn new() -> TextureWindow {
+        TextureWindow(0)
+    }
+
+    /// Raw register value
+    pub fn raw(self) -> u32 {
+        self.0
+    }
+
+    pub fn set(&mut self, tw: u32) {
+        self.0 = tw;
+    }
+
+    /// Mask to be ANDed to U coordinates
+    pub fn u_mask(self) -> u8 {
+        let m = (self.0 & 0x1f) as u8;
+
+        // 8 pixel steps
+        !(m << 3)
+    }
+
+    /// Mask to be ANDed to V coordinates
+    pub fn v_mask(self) -> u8 {
+        let m = ((self.0 >> 5) & 0x1f) as u8;
+
+        // 8 pixel steps
+        !(m << 3)
+    }
+
+    /// Offset to be added to U coordinates after applying `u_mask`
+    pub fn u_offset(self) -> u8 {
+        let off = ((self.0 >> 10) & 0x1f) as u8;
+
+        (off << 3) & !self.u_mask()
+    }
+
+    /// Offset to be added to V coordinates after applying `v_mask`
+    pub fn v_offset(self) -> u8 {
+        let off = ((self.0 >> 15) & 0x1f) as u8;
+
+        (off << 3) & !self.v_mask()
+    }
+}
+
+/// Wrapper around the Display Mode register value (set by GP1[0x08])
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone)]
+pub struct DisplayMode(u32);
+
+impl Default for DisplayMode {
+    fn default() -> DisplayMode {
+        DisplayMode::new()
+    }
+}
+
+impl DisplayMode {
+    pub fn new() -> DisplayMode {
+        DisplayMode(0)
+    }
+
+    /// Raw register value
+    pub fn raw(self) -> u32 {
+        self.0
+    }
+
+    pub fn set(&mut self, mode: u32) {
+        self.0 = mode
+    }
+
+    pub fn standard(self) -> VideoStandard {
+        if self.0 & (1 << 3) != 0 {
+            VideoStandard::Pal
+        } else {
+            VideoStandard::Ntsc
+        }
+    }
+
+    pub fn is_interlaced(self) -> bool {
+        self.0 & (1 << 5) != 0
+    }
+
+    /// To actually have the console output in interlaced (having two interlaced fields in VRAM and
+    /// the console displays one after the other) it's not enough to set the `is_interlaced` bit,
+    /// you also need to set bit 2 in Display Mode to actually tell the console to use two fields
+    /// in VRAM. Without it the console sends the same data for the top and bottom fields, which is
+    /// fairly useless.
+    pub fn is_true_interlaced(self) -> bool {
+        let two_fields = self.0 & (1 << 2) != 0;
+
+        self.is_interlaced() && two_fields
+    }
+
+    /// Retrieve the approximate horizontal resolution of the active video. This is an
+    /// approximation because it will also depend on the timing configuration of the output (column
+    /// start/column end etc...).
+    pub fn xres(self) -> u16 {
+        if (self.0 & (1 << 6)) != 0 {
+            368
+        } else {
+            match self.0 & 3 {
+                0 => 256,
+                1 => 320,
+                2 => 512,
+                3 => 640,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// True if we output 24 bits per pixel
+    pub fn output_24bpp(self) -> bool {
+        self.0 & (1 << 4) != 0
+    }
+}
+
+/// Wrapper around the Mask Setting register value (set by GP0[0xe6])
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct MaskSettings {
+    /// Raw register value
+    raw: u32,
+    /// Pixel value that should be ORed on write
+    or_mask: Pixel,
+}
+
+impl Default for MaskSettings {
+    fn default() -> MaskSettings {
+        MaskSettings::new()
+    }
+}
+
+impl MaskSettings {
+    pub fn new() -> MaskSettings {
+        MaskSettings {
+            raw: 0,
+            or_mask: Pixel::from_mbgr1555(0),
+        }
+    }
+
+    pub fn set(&mut self, v: u32) {
+        self.raw = v & 3;
+
+        let p = if self.draw_with_mask_bit() { 0x8000 } else { 0 };
+
+        self.or_mask = Pixel::from_mbgr1555(p);
+    }
+
+    pub fn draw_with_mask_bit(&self) -> bool {
+        self.raw & 1 != 0
+    }
+
+    pub fn check_mask_bit(&self) -> bool {
+        self.raw & (1 << 1) != 0
+    }
+
+    pub fn can_draw_to(&self, p: Pixel) -> bool {
+        if self.check_mask_bit() {
+            !p.mask()
+        } else {
+            true
+        }
+    }
+
+    pub fn mask(&self, mut p: Pixel) -> Pixel {
+        p.0 |= self.or_mask.0;
+
+        p
+    }
+}
+
+/// The are a few hardware differences between PAL and NTSC consoles, in particular the pixelclock
+/// runs slightly slower on PAL consoles.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum VideoStandard {
+    Ntsc,
+    Pal,
+}
@@ -0,0 +1,40 @@
+//! Backends drawing the GPU commands on the rasterizer thread. The software rasterizer is always
+//! there; the hardware renderer draws with the host's GPU through wgpu, with the
+//! `hardware-renderer` feature.
+//!
+//! Whatever the backend, the state it saves is the one of the software rasterizer, save states
+//! load with either.
+
+use std::sync::mpsc;
+use crate::handle::{Command, CommandBuffer, Frame};
+
+pub trait Renderer: Send {
+    /// Get ready for the first commands
+    fn prepare(&mut self);
+
+    /// Process `commands`, returns false once `Command::Quit` is reached. The frames and the VRAM
+    /// reads go through `frame_channel`, the state asked with `Command::Serialize` through
+    /// `serialization_channel`.
+    fn process(
+        &mut self,
+        commands: &[Command],
+        frame_channel: &mpsc::Sender<Frame>,
+        serialization_channel: &mpsc::Sender<Vec<u8>>,
+    ) -> bool;
+
+    /// Process the commands until `Command::Quit`, on the rasterizer thread
+    fn run(
+        &mut self,
+        command_channel: mpsc::Receiver<CommandBuffer>,
+        frame_channel: mpsc::Sender<Frame>,
+        serialization_channel: mpsc::Sender<Vec<u8>>,
+    ) {
+        self.prepare();
+
+        while let Ok(commands) = command_channel.recv() {
+            if !self.process(&commands, &frame_channel, &serialization_channel) {
+                return;
+            }
+        }
+    }
+}
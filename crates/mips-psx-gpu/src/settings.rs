@@ -0,0 +1,58 @@
+//! Settings of the renderers, set with `Command::Option`
+
+/// What draws the GPU commands
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum RendererBackend {
+    /// The rasterizer thread, with the exact output of the console
+    #[default]
+    Software,
+    /// The host's GPU: upscaling costs next to nothing, but the rasterization rules and the
+    /// blending are the GPU's, close to the console's but not exactly the same. Falls back to the
+    /// software rasterizer if there's no usable GPU.
+    Hardware,
+}
+
+impl RendererBackend {
+    pub const ALL: [RendererBackend; 2] = [RendererBackend::Software, RendererBackend::Hardware];
+
+    /// True if this backend is built in. The hardware one still needs a GPU when it starts.
+    pub fn is_available(self) -> bool {
+        match self {
+            RendererBackend::Software => true,
+            RendererBackend::Hardware => cfg!(feature = "hardware-renderer"),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+pub enum VRamDisplayMode {
+    #[default]
+    Native,
+    Full16bpp,
+    Full8bpp,
+    Full4bpp,
+}
+
+impl VRamDisplayMode {
+    pub fn max_res(self) -> (u16, u16) {
+        match self {
+            // Maximum resolution supported by the PlayStation video output is 640x576. That high a
+            // vertical resolution would mean no blanking however, so it doesn't make a lot of
+            // sense.
+            VRamDisplayMode::Native => (640, 480),
+            VRamDisplayMode::Full16bpp => (1024, 512),
+            VRamDisplayMode::Full8bpp => (2048, 512),
+            VRamDisplayMode::Full4bpp => (4096, 512),
+        }
+    }
+
+    pub fn aspect_ratio(self) -> f32 {
+        match self {
+            VRamDisplayMode::Native => 4. / 3.,
+            VRamDisplayMode::Full16bpp => 2. / 1.,
+            VRamDisplayMode::Full8bpp => 4. / 1.,
+            VRamDisplayMode::Full4bpp => 8. / 1.,
+        }
+    }
+}
@@ -1,5 +1,3 @@
-use std::any::type_name;
-
 /// Types of access supported by the PlayStation architecture
 #[derive(PartialEq, Eq, Debug)]
 pub enum AccessWidth {
@@ -0,0 +1,67 @@
+//! Interface between the CPU and the rest of the system
+
+use crate::addressable::Addressable;
+use crate::cop0::Cop0;
+use crate::cpu::Cpu;
+use crate::instruction::Instruction;
+use crate::ClockCycle;
+
+/// Everything the interpreter needs from the system it runs in: the CPU and COP0 state, the
+/// clock, the memory and the coprocessor 2. The handlers are monomorphized for each
+/// implementation, so calling through the trait costs nothing.
+pub trait CpuBus: Sized {
+    fn cpu(&self) -> &Cpu;
+
+    fn cpu_mut(&mut self) -> &mut Cpu;
+
+    fn cop0(&self) -> &Cop0;
+
+    fn cop0_mut(&mut self) -> &mut Cop0;
+
+    /// Current date, in CPU cycles
+    fn cycles(&self) -> ClockCycle;
+
+    /// Move the clock to `cycles` without running anything, used when the CPU stalls
+    fn set_cycles(&mut self, cycles: ClockCycle);
+
+    /// Advance the clock by `cycles`
+    fn tick(&mut self, cycles: ClockCycle);
+
+    /// Data load from the bus. The CPU already took care of the load delay timings.
+    fn load<T: Addressable>(&mut self, addr: u32) -> T;
+
+    /// Data store to the bus
+    fn store<T: Addressable>(&mut self, addr: u32, val: T);
+
+    /// Loads that complete immediately, without going through the bus (the PlayStation's
+    /// scratch pad). Returns `None` if `addr` isn't one of them.
+    fn fast_load<T: Addressable>(&mut self, _addr: u32) -> Option<T> {
+        None
+    }
+
+    /// Instruction fetch from the bus, on a cache miss or from an uncached region
+    fn load_instruction(&mut self, addr: u32) -> Instruction;
+
+    /// True if the cached regions go through the instruction cache
+    fn icache_enabled(&self) -> bool;
+
+    /// True if the stores to the isolated cache invalidate whole cachelines
+    fn tag_test_mode(&self) -> bool;
+
+    /// State of the external interrupt line (CAUSE bit 10)
+    fn irq_active(&self) -> bool;
+
+    /// Coprocessor 2 command, returns how many cycles it takes to complete. Runs concurrently
+    /// with the CPU, which only stalls if it accesses the coprocessor before it's done.
+    fn cop2_command(&mut self, command: u32) -> ClockCycle;
+
+    /// Coprocessor 2 data register `reg`
+    fn cop2_data(&self, reg: u8) -> u32;
+
+    fn set_cop2_data(&mut self, reg: u8, val: u32);
+
+    /// Coprocessor 2 control register `reg`
+    fn cop2_control(&self, reg: u8) -> u32;
+
+    fn set_cop2_control(&mut self, reg: u8, val: u32);
+}
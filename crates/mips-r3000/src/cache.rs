@@ -1,4 +1,4 @@
-use super::instruction::Instruction;
+use crate::instruction::Instruction;
 
 /// Cache line for instruction cache. Instruction cache is the only
 /// cache on CPU besides data cache which does not act like a cache.
@@ -21,11 +21,13 @@ impl ICacheLine {
     }
 
     /// Return the cacheline's tag
+    #[inline]
     pub fn tag(&self) -> u32 {
         self.tag_valid & 0xffff_f000
     }
 
     /// Return the cacheline's first valid word
+    #[inline]
     pub fn valid_index(&self) -> u32 {
         // We store the valid bits in bits [4:2], this way we can just
         // mask the PC value in `set_tag_valid` without having to
@@ -35,6 +37,7 @@ impl ICacheLine {
 
     /// Set the cacheline's tag and valid bits. `pc` is the first
     /// valid PC in the cacheline.
+    #[inline]
     pub fn set_tag_valid(&mut self, pc: u32) {
         self.tag_valid =  pc & 0x7fff_f00c;
     }
@@ -48,10 +51,12 @@ impl ICacheLine {
         self.tag_valid |= 0x10;
     }
 
+    #[inline]
     pub fn instruction(&self, index: u32) -> Instruction {
         self.instructions[index as usize]
     }
 
+    #[inline]
     pub fn set_instruction(&mut self, index: u32, instruction: Instruction) {
         self.instructions[index as usize] = instruction;
     }
@@ -6,12 +6,12 @@
 //! breakpoint registers.
 //!
 //! It's also the coprocessor that's supposed to manage virtual memory but there's no such thing on
-//! the bus.
+//! the PlayStation.
 
 use log::warn;
-use crate::ps1::psx::bus::Bus;
-use super::cpu::RegisterIndex;
-use super::{cpu, irq};
+use crate::bus::CpuBus;
+use crate::cpu;
+use crate::RegisterIndex;
 
 /// Coprocessor 0: System control
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -56,8 +56,14 @@ impl Cop0 {
     }
 }
 
+impl Default for Cop0 {
+    fn default() -> Cop0 {
+        Cop0::new()
+    }
+}
+
 /// Move To Coprocessor 0
-pub fn mtc0(bus: &mut Bus, cop_r: RegisterIndex, v: u32) {
+pub fn mtc0<B: CpuBus>(bus: &mut B, cop_r: RegisterIndex, v: u32) {
     match cop_r.0 {
         // Breakpoints registers
         3 | 5 | 6 | 7 | 9 | 11 => {
@@ -66,7 +72,7 @@ pub fn mtc0(bus: &mut Bus, cop_r: RegisterIndex, v: u32) {
             }
         }
         12 => {
-            bus.cop0.sr = v;
+            bus.cop0_mut().sr = v;
             cpu::irq_changed(bus);
         }
         // Cause register
@@ -83,7 +89,7 @@ pub fn mtc0(bus: &mut Bus, cop_r: RegisterIndex, v: u32) {
 }
 
 /// Move From Coprocessor 0
-pub fn mfc0(bus: &mut Bus, cop_r: RegisterIndex) -> u32 {
+pub fn mfc0<B: CpuBus>(bus: &mut B, cop_r: RegisterIndex) -> u32 {
     match cop_r.0 {
         6 => {
             // No$ says this register "randomly" memorizes a jump target after certain exceptions
@@ -102,11 +108,11 @@ pub fn mfc0(bus: &mut Bus, cop_r: RegisterIndex) -> u32 {
             // virtual memory, however some exceptions do write to this register so maybe it's
             // worth implementing better
             warn!("Unhandled read from BAD_VADDR (cop0r8)");
-            bus.cop0.bad()
+            bus.cop0().bad()
         }
-        12 => bus.cop0.sr(),
+        12 => bus.cop0().sr(),
         13 => cause(bus),
-        14 => bus.cop0.epc,
+        14 => bus.cop0().epc,
         15 => PROCESSOR_ID,
         _ => {
             warn!("Unhandled read from COP0 register {}", cop_r.0);
@@ -117,38 +123,41 @@ pub fn mfc0(bus: &mut Bus, cop_r: RegisterIndex) -> u32 {
 
 /// Called when the CPU is about to enter an exception handler. Returns the address of the handler
 /// that should be used.
-pub fn enter_exception(bus: &mut Bus, cause: Exception) -> u32 {
+pub fn enter_exception<B: CpuBus>(bus: &mut B, cause: Exception) -> u32 {
     // Shift bits [5:0] of `SR` two places to the left. Those bits are three pairs of Interrupt
     // Enable/User Mode bits behaving like a stack 3 entries deep. Entering an exception pushes a
     // pair of zeroes by left shifting the stack which disables interrupts and puts the CPU in
     // kernel mode. The original third entry is discarded (it's up to the kernel to handle more
     // than two recursive exception levels).
-    let pc = bus.cpu.current_pc();
+    let pc = bus.cpu().current_pc();
+    let delay_slot = bus.cpu().in_delay_slot();
 
-    let mode = bus.cop0.sr & 0x3f;
+    let cop0 = bus.cop0_mut();
+
+    let mode = cop0.sr & 0x3f;
 
-    bus.cop0.sr &= !0x3f;
-    bus.cop0.sr |= (mode << 2) & 0x3f;
+    cop0.sr &= !0x3f;
+    cop0.sr |= (mode << 2) & 0x3f;
 
     // Update `CAUSE` register with the exception code (bits [6:2])
-    bus.cop0.cause &= !0x7c;
-    bus.cop0.cause |= (cause as u32) << 2;
+    cop0.cause &= !0x7c;
+    cop0.cause |= (cause as u32) << 2;
 
-    if bus.cpu.in_delay_slot() {
+    if delay_slot {
         // When an exception occurs in a delay slot `EPC` points to the branch instruction and bit
         // 31 of `CAUSE` is set.
-        bus.cop0.epc = pc.wrapping_sub(4);
-        bus.cop0.cause |= 1 << 31;
+        cop0.epc = pc.wrapping_sub(4);
+        cop0.cause |= 1 << 31;
     } else {
-        bus.cop0.epc = pc;
-        bus.cop0.cause &= !(1 << 31);
+        cop0.epc = pc;
+        cop0.cause &= !(1 << 31);
     }
 
     // Since we've just disabled the interrupts we may need to refresh the CPU state
     cpu::irq_changed(bus);
 
     // The address of the exception handler address depends on the value of the BEV bit in SR
-    if (bus.cop0.sr & (1 << 22)) != 0 {
+    if (bus.cop0().sr & (1 << 22)) != 0 {
         0xbfc0_0180
     } else {
         0x8000_0080
@@ -157,8 +166,8 @@ pub fn enter_exception(bus: &mut Bus, cause: Exception) -> u32 {
 
 /// The counterpart to "enter_exception": shift SR's mode back into place. Doesn't touch CAUSE or
 /// EPC however.
-pub fn return_from_exception(bus: &mut Bus) {
-    let cop0 = &mut bus.cop0;
+pub fn return_from_exception<B: CpuBus>(bus: &mut B) {
+    let cop0 = bus.cop0_mut();
 
     let mode = cop0.sr & 0x3f;
 
@@ -171,22 +180,22 @@ pub fn return_from_exception(bus: &mut Bus) {
     cpu::irq_changed(bus);
 }
 
-pub fn cause(bus: &Bus) -> u32 {
-    let mut c = bus.cop0.cause;
+pub fn cause<B: CpuBus>(bus: &B) -> u32 {
+    let mut c = bus.cop0().cause;
 
     // Set the IRQ bit if necessary
-    c |= (irq::active(bus) as u32) << 10;
+    c |= (bus.irq_active() as u32) << 10;
 
     c
 }
 
 /// Returns true if the CPU should be interrupted
-pub fn irq_pending(bus: &Bus) -> bool {
+pub fn irq_pending<B: CpuBus>(bus: &B) -> bool {
     // Status Register bits [8:15] line up with the same bits in cause to mask any pending
     // interrupts
-    let active_interrupts = bus.cop0.sr & cause(bus) & 0xff_ff00;
+    let active_interrupts = bus.cop0().sr & cause(bus) & 0xff_ff00;
 
-    bus.cop0.irq_enabled() && active_interrupts != 0
+    bus.cop0().irq_enabled() && active_interrupts != 0
 }
 
 /// Exception types (as stored in the `CAUSE` register)
@@ -1,44 +1,50 @@
-use std::fmt;
-use crate::ps1::psx::processor::RegisterIndex;
+use core::fmt;
+use crate::RegisterIndex;
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Copy)]
-pub struct Instruction(pub(crate) u32);
+pub struct Instruction(pub u32);
 
 impl Instruction {
+    #[inline]
     pub fn new(machine_code: u32) -> Instruction {
         Instruction(machine_code)
     }
 
     /// Return bits [31:26] of the instruction
-    pub(crate) fn opcode(self) -> usize {
+    #[inline]
+    pub fn opcode(self) -> usize {
         let Instruction(op) = self;
 
         (op >> 26) as usize
     }
 
     /// Return bits [5:0] of the instruction
-    pub(crate) fn function(self) -> usize {
+    #[inline]
+    pub fn function(self) -> usize {
         let Instruction(op) = self;
 
         (op & 0x3f) as usize
     }
 
     /// Return coprocessor opcode in bits [25:21]
-    pub(crate) fn cop_opcode(self) -> u32 {
+    #[inline]
+    pub fn cop_opcode(self) -> u32 {
         let Instruction(op) = self;
 
         (op >> 21) & 0x1f
     }
 
     /// Return immediate value in bits [16:0]
-    pub(crate) fn imm(self) -> u32 {
+    #[inline]
+    pub fn imm(self) -> u32 {
         let Instruction(op) = self;
 
         op & 0xffff
     }
 
     /// Jump target stored in bits [25:0].
-    pub(crate) fn imm_jump(self) -> u32 {
+    #[inline]
+    pub fn imm_jump(self) -> u32 {
         let Instruction(op) = self;
 
         // The two LSBs aren't stored since (due to alignment constraints) they're assumed to be 0.
@@ -47,7 +53,8 @@ impl Instruction {
 
     /// Return immediate value in bits [16:0] as a sign-extended 32bit
     /// value
-    pub(crate) fn imm_se(self) -> u32 {
+    #[inline]
+    pub fn imm_se(self) -> u32 {
         let Instruction(op) = self;
 
         let v = (op & 0xffff) as i16;
@@ -56,28 +63,32 @@ impl Instruction {
     }
 
     /// Shift Immediate values are stored in bits [10:6]
-    pub(crate) fn shift(self) -> u32 {
+    #[inline]
+    pub fn shift(self) -> u32 {
         let Instruction(op) = self;
 
         (op >> 6) & 0x1f
     }
 
     /// Return register index in bits [25:21]
-    pub(crate) fn s(self) -> RegisterIndex {
+    #[inline]
+    pub fn s(self) -> RegisterIndex {
         let Instruction(op) = self;
 
         RegisterIndex(((op >> 21) & 0x1f) as u8)
     }
 
     /// Return register index in bits [20:16]
-    pub(crate) fn t(self) -> RegisterIndex {
+    #[inline]
+    pub fn t(self) -> RegisterIndex {
         let Instruction(op) = self;
 
         RegisterIndex(((op >> 16) & 0x1f) as u8)
     }
 
     /// Return register index in bits [15:11]
-    pub(crate) fn d(self) -> RegisterIndex {
+    #[inline]
+    pub fn d(self) -> RegisterIndex {
         let Instruction(op) = self;
 
         RegisterIndex(((op >> 11) & 0x1f) as u8)
@@ -0,0 +1,32 @@
+//! MIPS R3000A interpreter: the CPU, its instruction cache and the system control coprocessor
+//! (COP0), the way the PlayStation uses them. Everything else, memory and peripherals, is behind
+//! the `CpuBus` trait, so the interpreter runs as well in the emulator as in a test harness with a
+//! flat RAM or in analysis tools.
+//!
+//! `cpu::run_next_instruction` runs one instruction with its timings: the load delay slots and
+//! stalls, the multiplication and division latencies and the instruction cache. Stopping is up to
+//! the caller, there's no notion of events here.
+//!
+//! The coprocessor 2 (the GTE on the PlayStation) is reached through the bus as well.
+//! Coprocessors 1 and 3 don't exist and raise a coprocessor error.
+//!
+//! Without the default `std` feature the crate is `no_std` and doesn't allocate.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod addressable;
+pub mod bus;
+pub mod cop0;
+pub mod cpu;
+pub mod instruction;
+pub mod opcodes;
+mod cache;
+#[cfg(test)]
+mod tests;
+
+pub use bus::CpuBus;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterIndex(pub u8);
+
+pub type ClockCycle = i32;
@@ -1,236 +1,283 @@
-use log::{info, warn};
-use crate::ps1::psx::bus::Bus;
-use crate::ps1::psx::processor::cop0::Exception;
-use crate::ps1::psx::processor::{cop0, ClockCycle, RegisterIndex};
-use crate::ps1::psx::processor::cpu::{exception, load, store};
-use crate::ps1::psx::processor::instruction::Instruction;
-
-/// Handler table for the main opcodes (instruction bits [31:26])
-#[rustfmt::skip]
-pub const OPCODE_HANDLERS: [fn(&mut Bus, Instruction); 128] = [
-    // 0x00
-    op_function, op_bxx,      op_j,        op_jal,
-    op_beq,      op_bne,      op_blez,     op_bgtz,
-    op_addi,     op_addiu,    op_slti,     op_sltiu,
-    op_andi,     op_ori,      op_xori,     op_lui,
-    // 0x10
-    op_cop0,     op_cop1,     op_cop2,     op_cop3,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    // 0x20
-    op_lb,       op_lh,       op_lwl,      op_lw,
-    op_lbu,      op_lhu,      op_lwr,      op_illegal,
-    op_sb,       op_sh,       op_swl,      op_sw,
-    op_illegal,  op_illegal,  op_swr,      op_illegal,
-    // 0x30
-    op_lwc0,     op_lwc1,     op_lwc2,     op_lwc3,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    op_swc0,     op_swc1,     op_swc2,     op_swc3,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-
-    // This second half is called when an interrupt is active and `opcode_table_offset` is set to
-    // 64. You'll notice that the interrupt code is called every time *except* for COP2 opcodes.
-    // That's because GTE operations behave weirdly when at interrupt occurs. No$ says that they
-    // get repeated, mednafen "cheats" and just postpone the interrupt if it was to occur on a GTE
-    // instruction. Here we use mednafen's approach and ignore the interrupts for GTE operations.
-
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-
-    op_irq,      op_irq,      op_cop2,     op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-    op_irq,      op_irq,      op_irq,      op_irq,
-];
+use core::marker::PhantomData;
+#[cfg(feature = "debugger")]
+use log::info;
+use log::warn;
+use crate::bus::CpuBus;
+use crate::cop0::{self, Exception};
+use crate::cpu::{exception, load, store};
+use crate::instruction::Instruction;
+use crate::{ClockCycle, RegisterIndex};
+
+pub type Handler<B> = fn(&mut B, Instruction);
+
+/// The handler tables, one set per bus type. They're constants so that each instantiation is built
+/// at compile time like a `static` would be.
+struct Handlers<B>(PhantomData<B>);
+
+impl<B: CpuBus> Handlers<B> {
+    /// Handlers indexed by `handler_index`: the main opcodes, then the function codes of opcode 0.
+    /// Each half is followed by its interrupt counterpart.
+    const ALL: [Handler<B>; 256] = {
+        let mut handlers = [op_irq::<B> as Handler<B>; 256];
+
+        let mut i = 0;
+        while i < 64 {
+            handlers[i] = Self::MAIN[i];
+            handlers[i | 64] = Self::MAIN[i | 64];
+            handlers[i | 128] = Self::FUNCTION[i];
+            i += 1;
+        }
 
-/// Handler table for the function codes (instruction bits [31:26] when opcode is 0)
-#[rustfmt::skip]
-const FUNCTION_HANDLERS: [fn(&mut Bus, Instruction); 64] = [
-    // 0x00
-    op_sll,      op_illegal,  op_srl,      op_sra,
-    op_sllv,     op_illegal,  op_srlv,     op_srav,
-    op_jr,       op_jalr,     op_illegal,  op_illegal,
-    op_syscall,  op_break,    op_illegal,  op_illegal,
-    // 0x10
-    op_mfhi,     op_mthi,     op_mflo,     op_mtlo,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    op_mult,     op_multu,    op_div,      op_divu,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    // 0x20
-    op_add,      op_addu,     op_sub,      op_subu,
-    op_and,      op_or,       op_xor,      op_nor,
-    op_illegal,  op_illegal,  op_slt,      op_sltu,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    // 0x30
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-    op_illegal,  op_illegal,  op_illegal,  op_illegal,
-];
+        handlers
+    };
+
+    /// Handler table for the main opcodes (instruction bits [31:26])
+    #[rustfmt::skip]
+    const MAIN: [Handler<B>; 128] = [
+        // 0x00
+        // Opcode 0 is dispatched directly to the function code handlers
+        op_illegal,  op_bxx,      op_j,        op_jal,
+        op_beq,      op_bne,      op_blez,     op_bgtz,
+        op_addi,     op_addiu,    op_slti,     op_sltiu,
+        op_andi,     op_ori,      op_xori,     op_lui,
+        // 0x10
+        op_cop0,     op_cop1,     op_cop2,     op_cop3,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        // 0x20
+        op_lb,       op_lh,       op_lwl,      op_lw,
+        op_lbu,      op_lhu,      op_lwr,      op_illegal,
+        op_sb,       op_sh,       op_swl,      op_sw,
+        op_illegal,  op_illegal,  op_swr,      op_illegal,
+        // 0x30
+        op_lwc0,     op_lwc1,     op_lwc2,     op_lwc3,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        op_swc0,     op_swc1,     op_swc2,     op_swc3,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+
+        // This second half is called when an interrupt is active and `opcode_table_offset` is set to
+        // 64. You'll notice that the interrupt code is called every time *except* for COP2 opcodes.
+        // That's because GTE operations behave weirdly when at interrupt occurs. No$ says that they
+        // get repeated, mednafen "cheats" and just postpone the interrupt if it was to occur on a GTE
+        // instruction. Here we use mednafen's approach and ignore the interrupts for GTE operations.
+
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+
+        op_irq,      op_irq,      op_cop2,     op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+        op_irq,      op_irq,      op_irq,      op_irq,
+    ];
+
+    /// Handler table for the function codes (instruction bits [5:0] when opcode is 0)
+    #[rustfmt::skip]
+    const FUNCTION: [Handler<B>; 64] = [
+        // 0x00
+        op_sll,      op_illegal,  op_srl,      op_sra,
+        op_sllv,     op_illegal,  op_srlv,     op_srav,
+        op_jr,       op_jalr,     op_illegal,  op_illegal,
+        op_syscall,  op_break,    op_illegal,  op_illegal,
+        // 0x10
+        op_mfhi,     op_mthi,     op_mflo,     op_mtlo,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        op_mult,     op_multu,    op_div,      op_divu,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        // 0x20
+        op_add,      op_addu,     op_sub,      op_subu,
+        op_and,      op_or,       op_xor,      op_nor,
+        op_illegal,  op_illegal,  op_slt,      op_sltu,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        // 0x30
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+        op_illegal,  op_illegal,  op_illegal,  op_illegal,
+    ];
+}
+
+/// Index of the handler of `instruction` in the table of `handler`. Dispatching the function codes
+/// from the same table saves an indirect call for most ALU instructions.
+#[inline]
+pub fn handler_index<B: CpuBus>(bus: &B, instruction: Instruction) -> usize {
+    base_handler_index(instruction) | bus.cpu().opcode_table_offset as usize
+}
+
+/// Index of the handler of `instruction` when no interrupt is pending
+#[inline]
+pub fn base_handler_index(instruction: Instruction) -> usize {
+    let opcode = instruction.opcode();
+
+    if opcode == 0 {
+        0x80 | instruction.function()
+    } else {
+        opcode
+    }
+}
+
+/// Handler at `index` (see `handler_index`)
+#[inline]
+pub fn handler<B: CpuBus>(index: usize) -> Handler<B> {
+    let handlers: &[Handler<B>; 256] = const { &Handlers::<B>::ALL };
 
-pub fn run_instruction(bus: &mut Bus, i: Instruction) {
-    let idx = i.opcode() | bus.cpu.opcode_table_offset as usize;
-    let op = OPCODE_HANDLERS[idx];
+    handlers[index]
+}
+
+pub fn run_instruction<B: CpuBus>(bus: &mut B, i: Instruction) {
+    let op = handler::<B>(handler_index(bus, i));
     op(bus, i);
 }
 
 /// Handle pipeline timings for register dependencies. Should be called for every CPU registers
 /// used as an input or output. Returns `r` to allow chaining.
-fn reg_dep(bus: &mut Bus, r: RegisterIndex) -> RegisterIndex {
+#[inline]
+fn reg_dep<B: CpuBus>(bus: &mut B, r: RegisterIndex) -> RegisterIndex {
     // R0 is always "free" to read or write, so it doesn't force a sync when used as a register. In
     // order to emulate this we can just save and restore the value of the cycle counter for R0 to
     // make this function a NOP if `r` is R0 without having to use any branching.
-    let c0 = bus.cpu.free_cycles[0];
+    let c0 = bus.cpu().free_cycles[0];
 
     // If the register was executing a load we have to wait for it to complete before we can
     // continue (this is true even if `r` is used as an output register).
-    bus.cpu.free_cycles[r.0 as usize] = 0;
+    bus.cpu_mut().free_cycles[(r.0 & 0x1f) as usize] = 0;
 
-    bus.cpu.free_cycles[0] = c0;
+    bus.cpu_mut().free_cycles[0] = c0;
 
     r
 }
 
-/// When the main opcode is 0 we need to dispatch through a secondary table based on bits [5:0] of
-/// the instruction
-fn op_function(bus: &mut Bus, instruction: Instruction) {
-    let handler = FUNCTION_HANDLERS[instruction.function()];
-
-    handler(bus, instruction);
-}
-
 /// Shift Left Logical
 ///
 /// `SLL $r0, $r0, 0` (machine code 0x0000_0000) is the idiomatic way of encoding a NOP
-fn op_sll(bus: &mut Bus, instruction: Instruction) {
+fn op_sll<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.shift();
     let t = reg_dep(bus, instruction.t());
     let d = reg_dep(bus, instruction.d());
 
-    let v = bus.cpu.reg(t) << i;
+    let v = bus.cpu().reg(t) << i;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Shift Right Logical
-fn op_srl(bus: &mut Bus, instruction: Instruction) {
+fn op_srl<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.shift();
     let t = reg_dep(bus, instruction.t());
     let d = reg_dep(bus, instruction.d());
 
-    let v = bus.cpu.reg(t) >> i;
+    let v = bus.cpu().reg(t) >> i;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Shift Right Arithmetic
-fn op_sra(bus: &mut Bus, instruction: Instruction) {
+fn op_sra<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.shift();
     let t = reg_dep(bus, instruction.t());
     let d = reg_dep(bus, instruction.d());
 
-    let v = (bus.cpu.reg(t) as i32) >> i;
+    let v = (bus.cpu().reg(t) as i32) >> i;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v as u32);
+    bus.cpu_mut().set_reg(d, v as u32);
 }
 
 /// Shift Left Logical Variable
-fn op_sllv(bus: &mut Bus, instruction: Instruction) {
+fn op_sllv<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
     // Shift amount is truncated to 5 bits
-    let v = bus.cpu.reg(t) << (bus.cpu.reg(s) & 0x1f);
+    let v = bus.cpu().reg(t) << (bus.cpu().reg(s) & 0x1f);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Shift Right Logical Variable
-fn op_srlv(bus: &mut Bus, instruction: Instruction) {
+fn op_srlv<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
     // Shift amount is truncated to 5 bits
-    let v = bus.cpu.reg(t) >> (bus.cpu.reg(s) & 0x1f);
+    let v = bus.cpu().reg(t) >> (bus.cpu().reg(s) & 0x1f);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Shift Right Arithmetic Variable
-fn op_srav(bus: &mut Bus, instruction: Instruction) {
+fn op_srav<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
     // Shift amount is truncated to 5 bits
-    let v = (bus.cpu.reg(t) as i32) >> (bus.cpu.reg(s) & 0x1f);
+    let v = (bus.cpu().reg(t) as i32) >> (bus.cpu().reg(s) & 0x1f);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v as u32);
+    bus.cpu_mut().set_reg(d, v as u32);
 }
 
 /// Jump Register
-fn op_jr(bus: &mut Bus, instruction: Instruction) {
+fn op_jr<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
 
-    bus.cpu.next_pc = bus.cpu.reg(s);
-    bus.cpu.branch = true;
+    bus.cpu_mut().next_pc = bus.cpu().reg(s);
+    bus.cpu_mut().branch = true;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 }
 
 /// Jump And Link Register
-fn op_jalr(bus: &mut Bus, instruction: Instruction) {
+fn op_jalr<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let d = reg_dep(bus, instruction.d());
 
-    let ra = bus.cpu.next_pc;
+    let ra = bus.cpu().next_pc;
 
-    bus.cpu.next_pc = bus.cpu.reg(s);
-    bus.cpu.branch = true;
+    bus.cpu_mut().next_pc = bus.cpu().reg(s);
+    bus.cpu_mut().branch = true;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     // Store return address in `d`
-    bus.cpu.set_reg(d, ra);
+    bus.cpu_mut().set_reg(d, ra);
 }
 
 /// System Call
-fn op_syscall(bus: &mut Bus, _: Instruction) {
+fn op_syscall<B: CpuBus>(bus: &mut B, _: Instruction) {
     exception(bus, Exception::SysCall);
 }
 
 /// Break
-fn op_break(bus: &mut Bus, _: Instruction) {
+fn op_break<B: CpuBus>(bus: &mut B, _: Instruction) {
     #[cfg(feature = "debugger")]
     {
-        if bus.cpu.debug_on_break {
+        if bus.cpu().debug_on_break {
             info!("BREAK instruction while debug_on_break is active");
             //debugger::trigger_break(psx);
             return;
@@ -240,8 +287,8 @@ fn op_break(bus: &mut Bus, _: Instruction) {
 }
 
 /// Block if the current DIV(U) or MULT(U) instruction has not yet finished
-fn sync_mult_div(bus: &mut Bus) {
-    let block_for = bus.cpu.mult_div_end - bus.cycles;
+fn sync_mult_div<B: CpuBus>(bus: &mut B) {
+    let block_for = bus.cpu().mult_div_end - bus.cycles();
 
     if block_for == 1 {
         // XXX timing hack from mednafen, if we only have one cycle left we ignore it. We should
@@ -250,60 +297,60 @@ fn sync_mult_div(bus: &mut Bus) {
     }
 
     if block_for > 0 {
-        bus.cycles = bus.cpu.mult_div_end;
+        bus.set_cycles(bus.cpu().mult_div_end);
 
-        let ri = bus.cpu.free_cycles_reg.0 as usize;
+        let ri = bus.cpu().free_cycles_reg.0 as usize;
 
-        if ClockCycle::from(bus.cpu.free_cycles[ri]) <= block_for {
-            bus.cpu.free_cycles[ri] = 0;
+        if ClockCycle::from(bus.cpu().free_cycles[ri]) <= block_for {
+            bus.cpu_mut().free_cycles[ri] = 0;
         } else {
-            bus.cpu.free_cycles[ri] -= block_for as u8;
+            bus.cpu_mut().free_cycles[ri] -= block_for as u8;
         }
     }
 }
 
 /// Move From HI
-fn op_mfhi(bus: &mut Bus, instruction: Instruction) {
+fn op_mfhi<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
 
-    let hi = bus.cpu.hi;
+    let hi = bus.cpu().hi;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     sync_mult_div(bus);
 
-    bus.cpu.set_reg(d, hi);
+    bus.cpu_mut().set_reg(d, hi);
 }
 
 /// Move to HI
-fn op_mthi(bus: &mut Bus, instruction: Instruction) {
+fn op_mthi<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
 
-    bus.cpu.hi = bus.cpu.reg(s);
+    bus.cpu_mut().hi = bus.cpu().reg(s);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 }
 
 /// Move From LO
-fn op_mflo(bus: &mut Bus, instruction: Instruction) {
+fn op_mflo<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
 
-    let lo = bus.cpu.lo;
+    let lo = bus.cpu().lo;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     sync_mult_div(bus);
 
-    bus.cpu.set_reg(d, lo);
+    bus.cpu_mut().set_reg(d, lo);
 }
 
 /// Move to LO
-fn op_mtlo(bus: &mut Bus, instruction: Instruction) {
+fn op_mtlo<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
 
-    bus.cpu.lo = bus.cpu.reg(s);
+    bus.cpu_mut().lo = bus.cpu().reg(s);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 }
 
 /// Multiplication timings, based on the number of leading zeroes in the first multiplicand.
@@ -316,20 +363,20 @@ const MULT_TIMINGS: [u8; 33] = [
 ];
 
 /// Multiply (signed)
-fn op_mult(bus: &mut Bus, instruction: Instruction) {
+fn op_mult<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let a = bus.cpu.reg(s) as i32;
-    let b = bus.cpu.reg(t) as i32;
+    let a = bus.cpu().reg(s) as i32;
+    let b = bus.cpu().reg(t) as i32;
 
     let res = i64::from(a) * i64::from(b);
     let res = res as u64;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.hi = (res >> 32) as u32;
-    bus.cpu.lo = res as u32;
+    bus.cpu_mut().hi = (res >> 32) as u32;
+    bus.cpu_mut().lo = res as u32;
 
     let timing_index = if a < 0 {
         (!a).leading_zeros()
@@ -339,226 +386,226 @@ fn op_mult(bus: &mut Bus, instruction: Instruction) {
 
     let penalty = ClockCycle::from(MULT_TIMINGS[timing_index as usize]);
 
-    bus.cpu.mult_div_end = bus.cycles + penalty;
+    bus.cpu_mut().mult_div_end = bus.cycles() + penalty;
 }
 
 /// Multiply Unsigned
-fn op_multu(bus: &mut Bus, instruction: Instruction) {
+fn op_multu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let a = bus.cpu.reg(s);
-    let b = bus.cpu.reg(t);
+    let a = bus.cpu().reg(s);
+    let b = bus.cpu().reg(t);
 
     let res = u64::from(a) * u64::from(b);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.hi = (res >> 32) as u32;
-    bus.cpu.lo = res as u32;
+    bus.cpu_mut().hi = (res >> 32) as u32;
+    bus.cpu_mut().lo = res as u32;
 
     let penalty = ClockCycle::from(MULT_TIMINGS[a.leading_zeros() as usize]);
 
-    bus.cpu.mult_div_end = bus.cycles + penalty;
+    bus.cpu_mut().mult_div_end = bus.cycles() + penalty;
 }
 
 /// Divide (signed)
-fn op_div(bus: &mut Bus, instruction: Instruction) {
+fn op_div<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let n = bus.cpu.reg(s) as i32;
-    let d = bus.cpu.reg(t) as i32;
+    let n = bus.cpu().reg(s) as i32;
+    let d = bus.cpu().reg(t) as i32;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     if d == 0 {
         // Division by zero, results are bogus
-        bus.cpu.hi = n as u32;
+        bus.cpu_mut().hi = n as u32;
 
         if n >= 0 {
-            bus.cpu.lo = 0xffff_ffff;
+            bus.cpu_mut().lo = 0xffff_ffff;
         } else {
-            bus.cpu.lo = 1;
+            bus.cpu_mut().lo = 1;
         }
     } else if n as u32 == 0x8000_0000 && d == -1 {
         // Result is not representable in a 32bit signed integer
-        bus.cpu.hi = 0;
-        bus.cpu.lo = 0x8000_0000;
+        bus.cpu_mut().hi = 0;
+        bus.cpu_mut().lo = 0x8000_0000;
     } else {
-        bus.cpu.hi = (n % d) as u32;
-        bus.cpu.lo = (n / d) as u32;
+        bus.cpu_mut().hi = (n % d) as u32;
+        bus.cpu_mut().lo = (n / d) as u32;
     }
 
-    bus.cpu.mult_div_end = bus.cycles + 37;
+    bus.cpu_mut().mult_div_end = bus.cycles() + 37;
 }
 
 /// Divide Unsigned
-fn op_divu(bus: &mut Bus, instruction: Instruction) {
+fn op_divu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let n = bus.cpu.reg(s);
-    let d = bus.cpu.reg(t);
+    let n = bus.cpu().reg(s);
+    let d = bus.cpu().reg(t);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     if d == 0 {
         // Division by zero, results are bogus
-        bus.cpu.hi = n;
-        bus.cpu.lo = 0xffff_ffff;
+        bus.cpu_mut().hi = n;
+        bus.cpu_mut().lo = 0xffff_ffff;
     } else {
-        bus.cpu.hi = n % d;
-        bus.cpu.lo = n / d;
+        bus.cpu_mut().hi = n % d;
+        bus.cpu_mut().lo = n / d;
     }
 
-    bus.cpu.mult_div_end = bus.cycles + 37;
+    bus.cpu_mut().mult_div_end = bus.cycles() + 37;
 }
 
 /// Add and check for signed overflow
-fn op_add(bus: &mut Bus, instruction: Instruction) {
+fn op_add<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
     let d = reg_dep(bus, instruction.d());
 
-    let s = bus.cpu.reg(s) as i32;
-    let t = bus.cpu.reg(t) as i32;
+    let s = bus.cpu().reg(s) as i32;
+    let t = bus.cpu().reg(t) as i32;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     match s.checked_add(t) {
-        Some(v) => bus.cpu.set_reg(d, v as u32),
+        Some(v) => bus.cpu_mut().set_reg(d, v as u32),
         None => exception(bus, Exception::Overflow),
     }
 }
 
 /// Add Unsigned
-fn op_addu(bus: &mut Bus, instruction: Instruction) {
+fn op_addu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
     let d = reg_dep(bus, instruction.d());
 
-    let v = bus.cpu.reg(s).wrapping_add(bus.cpu.reg(t));
+    let v = bus.cpu().reg(s).wrapping_add(bus.cpu().reg(t));
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Subtract and check for signed overflow
-fn op_sub(bus: &mut Bus, instruction: Instruction) {
+fn op_sub<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
     let d = reg_dep(bus, instruction.d());
 
-    let s = bus.cpu.reg(s) as i32;
-    let t = bus.cpu.reg(t) as i32;
+    let s = bus.cpu().reg(s) as i32;
+    let t = bus.cpu().reg(t) as i32;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     match s.checked_sub(t) {
-        Some(v) => bus.cpu.set_reg(d, v as u32),
+        Some(v) => bus.cpu_mut().set_reg(d, v as u32),
         None => exception(bus, Exception::Overflow),
     }
 }
 
 /// Subtract Unsigned
-fn op_subu(bus: &mut Bus, instruction: Instruction) {
+fn op_subu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
     let d = reg_dep(bus, instruction.d());
 
-    let v = bus.cpu.reg(s).wrapping_sub(bus.cpu.reg(t));
+    let v = bus.cpu().reg(s).wrapping_sub(bus.cpu().reg(t));
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Bitwise And
-fn op_and(bus: &mut Bus, instruction: Instruction) {
+fn op_and<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let v = bus.cpu.reg(s) & bus.cpu.reg(t);
+    let v = bus.cpu().reg(s) & bus.cpu().reg(t);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Bitwise Or
-fn op_or(bus: &mut Bus, instruction: Instruction) {
+fn op_or<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let v = bus.cpu.reg(s) | bus.cpu.reg(t);
+    let v = bus.cpu().reg(s) | bus.cpu().reg(t);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Bitwise Exclusive Or
-fn op_xor(bus: &mut Bus, instruction: Instruction) {
+fn op_xor<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let v = bus.cpu.reg(s) ^ bus.cpu.reg(t);
+    let v = bus.cpu().reg(s) ^ bus.cpu().reg(t);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Bitwise Not Or
-fn op_nor(bus: &mut Bus, instruction: Instruction) {
+fn op_nor<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let v = !(bus.cpu.reg(s) | bus.cpu.reg(t));
+    let v = !(bus.cpu().reg(s) | bus.cpu().reg(t));
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v);
+    bus.cpu_mut().set_reg(d, v);
 }
 
 /// Set on Less Than (signed)
-fn op_slt(bus: &mut Bus, instruction: Instruction) {
+fn op_slt<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let s = bus.cpu.reg(s) as i32;
-    let t = bus.cpu.reg(t) as i32;
+    let s = bus.cpu().reg(s) as i32;
+    let t = bus.cpu().reg(t) as i32;
 
     let v = s < t;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v as u32);
+    bus.cpu_mut().set_reg(d, v as u32);
 }
 
 /// Set on Less Than Unsigned
-fn op_sltu(bus: &mut Bus, instruction: Instruction) {
+fn op_sltu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let d = reg_dep(bus, instruction.d());
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let v = bus.cpu.reg(s) < bus.cpu.reg(t);
+    let v = bus.cpu().reg(s) < bus.cpu().reg(t);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(d, v as u32);
+    bus.cpu_mut().set_reg(d, v as u32);
 }
 
 /// Various branch instructions: BGEZ, BLTZ, BGEZAL, BLTZAL. Bits [20:16] are used to figure out
 /// which one to use
-fn op_bxx(bus: &mut Bus, instruction: Instruction) {
+fn op_bxx<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let s = reg_dep(bus, instruction.s());
 
@@ -570,7 +617,7 @@ fn op_bxx(bus: &mut Bus, instruction: Instruction) {
     // doesn't take place and RA is left untouched.
     let is_link = (instruction >> 17) & 0xf == 0x8;
 
-    let v = bus.cpu.reg(s) as i32;
+    let v = bus.cpu().reg(s) as i32;
 
     // Test "less than zero"
     let test = (v < 0) as u32;
@@ -580,24 +627,24 @@ fn op_bxx(bus: &mut Bus, instruction: Instruction) {
     // xor takes care of that.
     let test = test ^ is_bgez;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     // If linking is requested it occurs unconditionally, even if
     // the branch is not taken
     if is_link {
-        let ra = bus.cpu.next_pc;
+        let ra = bus.cpu().next_pc;
 
         // Store return address in R31
-        bus.cpu.set_reg(RegisterIndex(31), ra);
+        bus.cpu_mut().set_reg(RegisterIndex(31), ra);
     }
 
     if test != 0 {
-        bus.cpu.branch(i);
+        bus.cpu_mut().branch(i);
     }
 }
 
 /// Jump
-fn op_j(bus: &mut Bus, instruction: Instruction) {
+fn op_j<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let target = instruction.imm_jump();
 
     // In order to fit the immediate target in the instruction the bottom two bits are stripped
@@ -606,191 +653,191 @@ fn op_j(bus: &mut Bus, instruction: Instruction) {
     // range of this instruction is limited and it can't reach any location in memory, in
     // particular it can't be used to switch from one area to an other (like, say, from KUSEG to
     // KSEG0).
-    bus.cpu.next_pc = (bus.cpu.pc & 0xf000_0000) | target;
-    bus.cpu.branch = true;
+    bus.cpu_mut().next_pc = (bus.cpu().pc & 0xf000_0000) | target;
+    bus.cpu_mut().branch = true;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 }
 
 /// Jump And Link
-fn op_jal(bus: &mut Bus, instruction: Instruction) {
-    let ra = bus.cpu.next_pc;
+fn op_jal<B: CpuBus>(bus: &mut B, instruction: Instruction) {
+    let ra = bus.cpu().next_pc;
     let target = instruction.imm_jump();
 
     reg_dep(bus, RegisterIndex(31));
 
-    bus.cpu.next_pc = (bus.cpu.pc & 0xf000_0000) | target;
-    bus.cpu.branch = true;
+    bus.cpu_mut().next_pc = (bus.cpu().pc & 0xf000_0000) | target;
+    bus.cpu_mut().branch = true;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     // Store return address in R31
-    bus.cpu.set_reg(RegisterIndex(31), ra);
+    bus.cpu_mut().set_reg(RegisterIndex(31), ra);
 }
 
 /// Branch if Equal
-fn op_beq(bus: &mut Bus, instruction: Instruction) {
+fn op_beq<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    if bus.cpu.reg(s) == bus.cpu.reg(t) {
-        bus.cpu.branch(i);
+    if bus.cpu().reg(s) == bus.cpu().reg(t) {
+        bus.cpu_mut().branch(i);
     }
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 }
 
 /// Branch if Not Equal
-fn op_bne(bus: &mut Bus, instruction: Instruction) {
+fn op_bne<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    if bus.cpu.reg(s) != bus.cpu.reg(t) {
-        bus.cpu.branch(i);
+    if bus.cpu().reg(s) != bus.cpu().reg(t) {
+        bus.cpu_mut().branch(i);
     }
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 }
 
 /// Branch if Less than or Equal to Zero
-fn op_blez(bus: &mut Bus, instruction: Instruction) {
+fn op_blez<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let s = reg_dep(bus, instruction.s());
 
-    let v = bus.cpu.reg(s) as i32;
+    let v = bus.cpu().reg(s) as i32;
 
     if v <= 0 {
-        bus.cpu.branch(i);
+        bus.cpu_mut().branch(i);
     }
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 }
 
 /// Branch if Greater Than Zero
-fn op_bgtz(bus: &mut Bus, instruction: Instruction) {
+fn op_bgtz<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let s = reg_dep(bus, instruction.s());
 
-    let v = bus.cpu.reg(s) as i32;
+    let v = bus.cpu().reg(s) as i32;
 
     if v > 0 {
-        bus.cpu.branch(i);
+        bus.cpu_mut().branch(i);
     }
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 }
 
 /// Add Immediate and check for signed overflow
-fn op_addi(bus: &mut Bus, instruction: Instruction) {
+fn op_addi<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se() as i32;
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let s = bus.cpu.reg(s) as i32;
+    let s = bus.cpu().reg(s) as i32;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     match s.checked_add(i) {
-        Some(v) => bus.cpu.set_reg(t, v as u32),
+        Some(v) => bus.cpu_mut().set_reg(t, v as u32),
         None => exception(bus, Exception::Overflow),
     }
 }
 
 /// Add Immediate Unsigned
-fn op_addiu(bus: &mut Bus, instruction: Instruction) {
+fn op_addiu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let v = bus.cpu.reg(s).wrapping_add(i);
+    let v = bus.cpu().reg(s).wrapping_add(i);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(t, v);
+    bus.cpu_mut().set_reg(t, v);
 }
 
 /// Set if Less Than Immediate (signed)
-fn op_slti(bus: &mut Bus, instruction: Instruction) {
+fn op_slti<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se() as i32;
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let v = (bus.cpu.reg(s) as i32) < i;
+    let v = (bus.cpu().reg(s) as i32) < i;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(t, v as u32);
+    bus.cpu_mut().set_reg(t, v as u32);
 }
 
 /// Set if Less Than Immediate Unsigned
-fn op_sltiu(bus: &mut Bus, instruction: Instruction) {
+fn op_sltiu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let s = reg_dep(bus, instruction.s());
     let t = reg_dep(bus, instruction.t());
 
-    let v = bus.cpu.reg(s) < i;
+    let v = bus.cpu().reg(s) < i;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(t, v as u32);
+    bus.cpu_mut().set_reg(t, v as u32);
 }
 
 /// Bitwise And Immediate
-fn op_andi(bus: &mut Bus, instruction: Instruction) {
+fn op_andi<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let v = bus.cpu.reg(s) & i;
+    let v = bus.cpu().reg(s) & i;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(t, v);
+    bus.cpu_mut().set_reg(t, v);
 }
 
 /// Bitwise Or Immediate
-fn op_ori(bus: &mut Bus, instruction: Instruction) {
+fn op_ori<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let v = bus.cpu.reg(s) | i;
+    let v = bus.cpu().reg(s) | i;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(t, v);
+    bus.cpu_mut().set_reg(t, v);
 }
 
 /// Bitwise eXclusive Or Immediate
-fn op_xori(bus: &mut Bus, instruction: Instruction) {
+fn op_xori<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let v = bus.cpu.reg(s) ^ i;
+    let v = bus.cpu().reg(s) ^ i;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(t, v);
+    bus.cpu_mut().set_reg(t, v);
 }
 
 /// Load Upper Immediate
-fn op_lui(bus: &mut Bus, instruction: Instruction) {
+fn op_lui<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm();
     let t = reg_dep(bus, instruction.t());
 
     // Low 16bits are set to 0
     let v = i << 16;
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.cpu.set_reg(t, v);
+    bus.cpu_mut().set_reg(t, v);
 }
 
 /// Coprocessor 0 opcode
-fn op_cop0(bus: &mut Bus, instruction: Instruction) {
+fn op_cop0<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     match instruction.cop_opcode() {
         0b00000 => op_mfc0(bus, instruction),
         0b00100 => op_mtc0(bus, instruction),
@@ -800,34 +847,34 @@ fn op_cop0(bus: &mut Bus, instruction: Instruction) {
 }
 
 /// Move To Coprocessor 0
-fn op_mtc0(bus: &mut Bus, instruction: Instruction) {
+fn op_mtc0<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     // Mednafen doesn't force the register sync if a load was in progress here. It doesn't make a
     // lot of sense to me, maybe it's a mistake or maybe it compensates from something else. For
     // the time being just do whatever mednafen does.
     let cpu_r = instruction.t();
     let cop_r = instruction.d();
 
-    let v = bus.cpu.reg(cpu_r);
+    let v = bus.cpu().reg(cpu_r);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     cop0::mtc0(bus, cop_r, v);
 }
 
 /// Move From Coprocessor 0
-fn op_mfc0(bus: &mut Bus, instruction: Instruction) {
+fn op_mfc0<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let cpu_r = reg_dep(bus, instruction.t());
     let cop_r = instruction.d();
 
     let v = cop0::mfc0(bus, cop_r);
 
-    bus.cpu.delayed_load_chain(cpu_r, v, 0, false);
+    bus.cpu_mut().delayed_load_chain(cpu_r, v, 0, false);
 }
 
 /// Return From Exception. Doesn't actually jump anywhere but tells the coprocessor to return to
 /// the mode it was in when the exception occurred.
-fn op_rfe(bus: &mut Bus, instruction: Instruction) {
-    bus.cpu.delayed_load();
+fn op_rfe<B: CpuBus>(bus: &mut B, instruction: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     // There are other instructions with the same encoding but all are virtual memory related and
     // the PlayStation doesn't implement them. Still, let's make sure we're not running buggy code.
@@ -839,8 +886,8 @@ fn op_rfe(bus: &mut Bus, instruction: Instruction) {
 }
 
 /// Coprocessor 1 opcode (does not exist on the PlayStation)
-fn op_cop1(bus: &mut Bus, _: Instruction) {
-    bus.cpu.delayed_load();
+fn op_cop1<B: CpuBus>(bus: &mut B, _: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!("Encountered Cop1 instruction");
 
@@ -848,9 +895,9 @@ fn op_cop1(bus: &mut Bus, _: Instruction) {
 }
 
 /// Coprocessor 2 opcode (GTE)
-fn op_cop2(bus: &mut Bus, instruction: Instruction) {
-    if bus.cpu.gte_command_end > bus.cycles {
-        bus.cycles = bus.cpu.gte_command_end;
+fn op_cop2<B: CpuBus>(bus: &mut B, instruction: Instruction) {
+    if bus.cpu().gte_command_end > bus.cycles() {
+        bus.set_cycles(bus.cpu().gte_command_end);
     }
 
     // XXX: we should check that the GTE is enabled in cop0's status register, otherwise the cop2
@@ -862,9 +909,9 @@ fn op_cop2(bus: &mut Bus, instruction: Instruction) {
     if cop_opcode & 0x10 != 0 {
         // GTE command
 
-        bus.cpu.delayed_load();
+        bus.cpu_mut().delayed_load();
 
-        bus.cpu.gte_command_end = bus.cycles + bus.gte.command(instruction.0);
+        bus.cpu_mut().gte_command_end = bus.cycles() + bus.cop2_command(instruction.0);
     } else {
         match cop_opcode {
             0b00000 => op_mfc2(bus, instruction),
@@ -877,11 +924,11 @@ fn op_cop2(bus: &mut Bus, instruction: Instruction) {
 }
 
 /// Move From Coprocessor 2 Data register
-fn op_mfc2(bus: &mut Bus, instruction: Instruction) {
-    let block_for = bus.cpu.gte_command_end - bus.cycles;
+fn op_mfc2<B: CpuBus>(bus: &mut B, instruction: Instruction) {
+    let block_for = bus.cpu().gte_command_end - bus.cycles();
 
     let delay = if block_for > 0 {
-        bus.cycles = bus.cpu.gte_command_end;
+        bus.set_cycles(bus.cpu().gte_command_end);
         block_for as u8
     } else {
         0
@@ -890,17 +937,17 @@ fn op_mfc2(bus: &mut Bus, instruction: Instruction) {
     let cpu_r = instruction.t();
     let cop_r = instruction.d().0;
 
-    let v = bus.gte.data(cop_r);
+    let v = bus.cop2_data(cop_r);
 
-    bus.cpu.delayed_load_chain(cpu_r, v, delay, false);
+    bus.cpu_mut().delayed_load_chain(cpu_r, v, delay, false);
 }
 
 /// Move From Coprocessor 2 Control register
-fn op_cfc2(bus: &mut Bus, instruction: Instruction) {
-    let block_for = bus.cpu.gte_command_end - bus.cycles;
+fn op_cfc2<B: CpuBus>(bus: &mut B, instruction: Instruction) {
+    let block_for = bus.cpu().gte_command_end - bus.cycles();
 
     let delay = if block_for > 0 {
-        bus.cycles = bus.cpu.gte_command_end;
+        bus.set_cycles(bus.cpu().gte_command_end);
         block_for as u8
     } else {
         0
@@ -909,15 +956,15 @@ fn op_cfc2(bus: &mut Bus, instruction: Instruction) {
     let cpu_r = instruction.t();
     let cop_r = instruction.d().0;
 
-    let v = bus.gte.control(cop_r);
+    let v = bus.cop2_control(cop_r);
 
-    bus.cpu.delayed_load_chain(cpu_r, v, delay, false);
+    bus.cpu_mut().delayed_load_chain(cpu_r, v, delay, false);
 }
 
 /// Move To Coprocessor 2 Data register
-fn op_mtc2(bus: &mut Bus, instruction: Instruction) {
-    if bus.cpu.gte_command_end > bus.cycles {
-        bus.cycles = bus.cpu.gte_command_end;
+fn op_mtc2<B: CpuBus>(bus: &mut B, instruction: Instruction) {
+    if bus.cpu().gte_command_end > bus.cycles() {
+        bus.set_cycles(bus.cpu().gte_command_end);
     }
 
     // Mednafen doesn't force the register sync if a load was in progress here. It doesn't make a
@@ -926,17 +973,17 @@ fn op_mtc2(bus: &mut Bus, instruction: Instruction) {
     let cpu_r = instruction.t();
     let cop_r = instruction.d().0;
 
-    let v = bus.cpu.reg(cpu_r);
+    let v = bus.cpu().reg(cpu_r);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.gte.set_data(cop_r, v);
+    bus.set_cop2_data(cop_r, v);
 }
 
 /// Move To Coprocessor 2 Control register
-fn op_ctc2(bus: &mut Bus, instruction: Instruction) {
-    if bus.cpu.gte_command_end > bus.cycles {
-        bus.cycles = bus.cpu.gte_command_end;
+fn op_ctc2<B: CpuBus>(bus: &mut B, instruction: Instruction) {
+    if bus.cpu().gte_command_end > bus.cycles() {
+        bus.set_cycles(bus.cpu().gte_command_end);
     }
 
     // Mednafen doesn't force the register sync if a load was in progress here. It doesn't make a
@@ -945,16 +992,16 @@ fn op_ctc2(bus: &mut Bus, instruction: Instruction) {
     let cpu_r = instruction.t();
     let cop_r = instruction.d().0;
 
-    let v = bus.cpu.reg(cpu_r);
+    let v = bus.cpu().reg(cpu_r);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
-    bus.gte.set_control(cop_r, v);
+    bus.set_cop2_control(cop_r, v);
 }
 
 /// Coprocessor 3 opcode (does not exist on the PlayStation)
-fn op_cop3(bus: &mut Bus, _: Instruction) {
-    bus.cpu.delayed_load();
+fn op_cop3<B: CpuBus>(bus: &mut B, _: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!("Encountered Cop3 instruction");
 
@@ -962,12 +1009,12 @@ fn op_cop3(bus: &mut Bus, _: Instruction) {
 }
 
 /// Load Byte (signed)
-fn op_lb(bus: &mut Bus, instruction: Instruction) {
+fn op_lb<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
     let (v, duration) = load::<u8>(bus, addr, false);
 
@@ -975,47 +1022,47 @@ fn op_lb(bus: &mut Bus, instruction: Instruction) {
     let v = v as i8;
 
     // Put the load in the delay slot
-    bus.cpu.delayed_load_chain(t, v as u32, duration, true);
+    bus.cpu_mut().delayed_load_chain(t, v as u32, duration, true);
 }
 
 /// Load Halfword (signed)
-fn op_lh(bus: &mut Bus, instruction: Instruction) {
+fn op_lh<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
-    if addr % 2 == 0 {
+    if addr.is_multiple_of(2) {
         let (v, duration) = load::<u16>(bus, addr, false);
 
         // Cast as i16 to force sign extension
         let v = v as i16;
 
         // Put the load in the delay slot
-        bus.cpu.delayed_load_chain(t, v as u32, duration, true);
+        bus.cpu_mut().delayed_load_chain(t, v as u32, duration, true);
     } else {
-        bus.cpu.delayed_load();
+        bus.cpu_mut().delayed_load();
         exception(bus, Exception::LoadAddressError);
     }
 }
 
 /// Load Word Left
-fn op_lwl(bus: &mut Bus, instruction: Instruction) {
+fn op_lwl<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
-    let mut cur_v = bus.cpu.reg(t);
+    let mut cur_v = bus.cpu().reg(t);
 
     // This instruction bypasses the load delay restriction: this instruction will merge the new
     // contents with the value currently being loaded if need be.
-    if let Some((pending_reg, pending_value, _)) = bus.cpu.load {
-        if pending_reg == t {
-            cur_v = pending_value;
-        }
+    if let Some((pending_reg, pending_value, _)) = bus.cpu().load
+        && pending_reg == t
+    {
+        cur_v = pending_value;
     }
 
     // Next we load the *aligned* word containing the first addressed byte
@@ -1033,78 +1080,78 @@ fn op_lwl(bus: &mut Bus, instruction: Instruction) {
     };
 
     // Put the load in the delay slot
-    bus.cpu.delayed_load_chain(t, v, duration, true);
+    bus.cpu_mut().delayed_load_chain(t, v, duration, true);
 }
 
 /// Load Word
-fn op_lw(bus: &mut Bus, instruction: Instruction) {
+fn op_lw<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
     // Address must be 32bit aligned
-    if addr % 4 == 0 {
+    if addr.is_multiple_of(4) {
         let (v, duration) = load(bus, addr, false);
 
-        bus.cpu.delayed_load_chain(t, v, duration, true);
+        bus.cpu_mut().delayed_load_chain(t, v, duration, true);
     } else {
-        bus.cpu.delayed_load();
+        bus.cpu_mut().delayed_load();
         exception(bus, Exception::LoadAddressError);
     }
 }
 
 /// Load Byte Unsigned
-fn op_lbu(bus: &mut Bus, instruction: Instruction) {
+fn op_lbu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
     let (v, duration) = load::<u8>(bus, addr, false);
 
     // Put the load in the delay slot
-    bus.cpu.delayed_load_chain(t, u32::from(v), duration, true);
+    bus.cpu_mut().delayed_load_chain(t, u32::from(v), duration, true);
 }
 
 /// Load Halfword Unsigned
-fn op_lhu(bus: &mut Bus, instruction: Instruction) {
+fn op_lhu<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
     // Address must be 16bit aligned
-    if addr % 2 == 0 {
+    if addr.is_multiple_of(2) {
         let (v, duration) = load::<u16>(bus, addr, false);
 
         // Put the load in the delay slot
-        bus.cpu.delayed_load_chain(t, u32::from(v), duration, true);
+        bus.cpu_mut().delayed_load_chain(t, u32::from(v), duration, true);
     } else {
-        bus.cpu.delayed_load();
+        bus.cpu_mut().delayed_load();
         exception(bus, Exception::LoadAddressError);
     }
 }
 
 /// Load Word Right
-fn op_lwr(bus: &mut Bus, instruction: Instruction) {
+fn op_lwr<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
-    let mut cur_v = bus.cpu.reg(t);
+    let mut cur_v = bus.cpu().reg(t);
 
     // This instruction bypasses the load delay restriction: this instruction will merge the new
     // contents with the value currently being loaded if need be.
-    if let Some((pending_reg, pending_value, _)) = bus.cpu.load {
-        if pending_reg == t {
-            cur_v = pending_value;
-        }
+    if let Some((pending_reg, pending_value, _)) = bus.cpu().load
+        && pending_reg == t
+    {
+        cur_v = pending_value;
     }
 
     // Next we load the *aligned* word containing the first addressed byte
@@ -1122,36 +1169,36 @@ fn op_lwr(bus: &mut Bus, instruction: Instruction) {
     };
 
     // Put the load in the delay slot
-    bus.cpu.delayed_load_chain(t, v, duration, true);
+    bus.cpu_mut().delayed_load_chain(t, v, duration, true);
 }
 
 /// Store Byte
-fn op_sb(bus: &mut Bus, instruction: Instruction) {
+fn op_sb<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
-    let v = bus.cpu.reg(t);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
+    let v = bus.cpu().reg(t);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     store(bus, addr, v as u8);
 }
 
 /// Store Halfword
-fn op_sh(bus: &mut Bus, instruction: Instruction) {
+fn op_sh<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
-    let v = bus.cpu.reg(t);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
+    let v = bus.cpu().reg(t);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     // Address must be 16bit aligned
-    if addr % 2 == 0 {
+    if addr.is_multiple_of(2) {
         store(bus, addr, v as u16);
     } else {
         exception(bus, Exception::StoreAddressError);
@@ -1159,13 +1206,13 @@ fn op_sh(bus: &mut Bus, instruction: Instruction) {
 }
 
 /// Store Word Left
-fn op_swl(bus: &mut Bus, instruction: Instruction) {
+fn op_swl<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
-    let v = bus.cpu.reg(t);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
+    let v = bus.cpu().reg(t);
 
     let aligned_addr = addr & !3;
     // Load the current value for the aligned word at the target address
@@ -1179,24 +1226,24 @@ fn op_swl(bus: &mut Bus, instruction: Instruction) {
         _ => unreachable!(),
     };
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     store(bus, aligned_addr, new);
 }
 
 /// Store Word
-fn op_sw(bus: &mut Bus, instruction: Instruction) {
+fn op_sw<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
-    let v = bus.cpu.reg(t);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
+    let v = bus.cpu().reg(t);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     // Address must be 32bit aligned
-    if addr % 4 == 0 {
+    if addr.is_multiple_of(4) {
         store(bus, addr, v);
     } else {
         exception(bus, Exception::StoreAddressError);
@@ -1204,13 +1251,13 @@ fn op_sw(bus: &mut Bus, instruction: Instruction) {
 }
 
 /// Store Word Right
-fn op_swr(bus: &mut Bus, instruction: Instruction) {
+fn op_swr<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let t = reg_dep(bus, instruction.t());
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
-    let v = bus.cpu.reg(t);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
+    let v = bus.cpu().reg(t);
 
     let aligned_addr = addr & !3;
     // Load the current value for the aligned word at the target address
@@ -1224,14 +1271,14 @@ fn op_swr(bus: &mut Bus, instruction: Instruction) {
         _ => unreachable!(),
     };
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     store(bus, aligned_addr, new);
 }
 
 /// Load Word in Coprocessor 0
-fn op_lwc0(bus: &mut Bus, _: Instruction) {
-    bus.cpu.delayed_load();
+fn op_lwc0<B: CpuBus>(bus: &mut B, _: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!("Encountered LWC0 instruction");
 
@@ -1240,8 +1287,8 @@ fn op_lwc0(bus: &mut Bus, _: Instruction) {
 }
 
 /// Load Word in Coprocessor 1
-fn op_lwc1(bus: &mut Bus, _: Instruction) {
-    bus.cpu.delayed_load();
+fn op_lwc1<B: CpuBus>(bus: &mut B, _: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!("Encountered LWC1 instruction");
 
@@ -1250,29 +1297,29 @@ fn op_lwc1(bus: &mut Bus, _: Instruction) {
 }
 
 /// Load Word in Coprocessor 2
-fn op_lwc2(bus: &mut Bus, instruction: Instruction) {
+fn op_lwc2<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let cop_r = instruction.t().0;
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     // Address must be 32bit aligned
-    if addr % 4 == 0 {
+    if addr.is_multiple_of(4) {
         // XXX how should we handle duration here? No absorb?
         let (v, _duration) = load::<u32>(bus, addr, true);
 
-        bus.gte.set_data(cop_r, v);
+        bus.set_cop2_data(cop_r, v);
     } else {
         exception(bus, Exception::LoadAddressError);
     }
 }
 
 /// Load Word in Coprocessor 3
-fn op_lwc3(bus: &mut Bus, _: Instruction) {
-    bus.cpu.delayed_load();
+fn op_lwc3<B: CpuBus>(bus: &mut B, _: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!("Encountered LWC3 instruction");
 
@@ -1281,8 +1328,8 @@ fn op_lwc3(bus: &mut Bus, _: Instruction) {
 }
 
 /// Store Word in Coprocessor 0
-fn op_swc0(bus: &mut Bus, _: Instruction) {
-    bus.cpu.delayed_load();
+fn op_swc0<B: CpuBus>(bus: &mut B, _: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!("Encountered SWC0 instruction");
 
@@ -1291,8 +1338,8 @@ fn op_swc0(bus: &mut Bus, _: Instruction) {
 }
 
 /// Store Word in Coprocessor 1
-fn op_swc1(bus: &mut Bus, _: Instruction) {
-    bus.cpu.delayed_load();
+fn op_swc1<B: CpuBus>(bus: &mut B, _: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!("Encountered SWC1 instruction");
 
@@ -1301,20 +1348,20 @@ fn op_swc1(bus: &mut Bus, _: Instruction) {
 }
 
 /// Store Word in Coprocessor 2
-fn op_swc2(bus: &mut Bus, instruction: Instruction) {
+fn op_swc2<B: CpuBus>(bus: &mut B, instruction: Instruction) {
     let i = instruction.imm_se();
     let cop_r = instruction.t().0;
     let s = reg_dep(bus, instruction.s());
 
-    let addr = bus.cpu.reg(s).wrapping_add(i);
+    let addr = bus.cpu().reg(s).wrapping_add(i);
 
     // XXX read from GTE
-    let v = bus.gte.data(cop_r);
+    let v = bus.cop2_data(cop_r);
 
-    bus.cpu.delayed_load();
+    bus.cpu_mut().delayed_load();
 
     // Address must be 32bit aligned
-    if addr % 4 == 0 {
+    if addr.is_multiple_of(4) {
         store(bus, addr, v);
     } else {
         exception(bus, Exception::LoadAddressError);
@@ -1322,8 +1369,8 @@ fn op_swc2(bus: &mut Bus, instruction: Instruction) {
 }
 
 /// Store Word in Coprocessor 3
-fn op_swc3(bus: &mut Bus, _: Instruction) {
-    bus.cpu.delayed_load();
+fn op_swc3<B: CpuBus>(bus: &mut B, _: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!("Encountered SWC3 instruction");
 
@@ -1332,19 +1379,19 @@ fn op_swc3(bus: &mut Bus, _: Instruction) {
 }
 
 /// Illegal instruction
-fn op_illegal(bus: &mut Bus, instruction: Instruction) {
-    bus.cpu.delayed_load();
+fn op_illegal<B: CpuBus>(bus: &mut B, instruction: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     warn!(
         "Illegal instruction {} at PC 0x{:08x}!",
-        instruction, bus.cpu.current_pc
+        instruction, bus.cpu().current_pc
     );
 
     exception(bus, Exception::IllegalInstruction);
 }
 
-fn op_irq(bus: &mut Bus, _instruction: Instruction) {
-    bus.cpu.delayed_load();
+fn op_irq<B: CpuBus>(bus: &mut B, _instruction: Instruction) {
+    bus.cpu_mut().delayed_load();
 
     exception(bus, Exception::Interrupt);
 }
@@ -0,0 +1,223 @@
+use crate::addressable::Addressable;
+use crate::bus::CpuBus;
+use crate::cop0::{self, Cop0};
+use crate::cpu::{self, Cpu};
+use crate::instruction::Instruction;
+use crate::{ClockCycle, RegisterIndex};
+
+/// Bus with nothing but 4KiB of RAM (mirrored everywhere) and a coprocessor 2 made of plain
+/// registers
+struct TestBus {
+    cpu: Cpu,
+    cop0: Cop0,
+    cycles: ClockCycle,
+    ram: Vec<u8>,
+    cop2_data: [u32; 32],
+    cop2_control: [u32; 32],
+}
+
+impl TestBus {
+    /// Bus with `program` at 0x100, where the CPU starts
+    fn new(program: &[u32]) -> TestBus {
+        let mut bus = TestBus {
+            cpu: Cpu::new(),
+            cop0: Cop0::new(),
+            cycles: 0,
+            ram: vec![0; 0x1000],
+            cop2_data: [0; 32],
+            cop2_control: [0; 32],
+        };
+
+        for (i, &word) in program.iter().enumerate() {
+            bus.store(0x100 + i as u32 * 4, word);
+        }
+
+        bus.cpu.pc = 0x100;
+        bus.cpu.next_pc = 0x104;
+
+        bus
+    }
+
+    fn run(&mut self, instructions: usize) {
+        for _ in 0..instructions {
+            cpu::run_next_instruction(self);
+        }
+    }
+
+    fn reg(&self, r: u8) -> u32 {
+        self.cpu.reg(RegisterIndex(r))
+    }
+}
+
+impl CpuBus for TestBus {
+    fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    fn cop0(&self) -> &Cop0 {
+        &self.cop0
+    }
+
+    fn cop0_mut(&mut self) -> &mut Cop0 {
+        &mut self.cop0
+    }
+
+    fn cycles(&self) -> ClockCycle {
+        self.cycles
+    }
+
+    fn set_cycles(&mut self, cycles: ClockCycle) {
+        self.cycles = cycles;
+    }
+
+    fn tick(&mut self, cycles: ClockCycle) {
+        self.cycles += cycles;
+    }
+
+    fn load<T: Addressable>(&mut self, addr: u32) -> T {
+        let addr = (addr & 0xfff) as usize;
+
+        let mut v = 0;
+        for i in 0..T::width() as usize {
+            v |= u32::from(self.ram[addr + i]) << (i * 8);
+        }
+
+        T::from_u32(v)
+    }
+
+    fn store<T: Addressable>(&mut self, addr: u32, val: T) {
+        let addr = (addr & 0xfff) as usize;
+        let val = val.as_u32();
+
+        for i in 0..T::width() as usize {
+            self.ram[addr + i] = (val >> (i * 8)) as u8;
+        }
+    }
+
+    fn load_instruction(&mut self, addr: u32) -> Instruction {
+        Instruction::new(self.load(addr))
+    }
+
+    fn icache_enabled(&self) -> bool {
+        false
+    }
+
+    fn tag_test_mode(&self) -> bool {
+        false
+    }
+
+    fn irq_active(&self) -> bool {
+        false
+    }
+
+    fn cop2_command(&mut self, _command: u32) -> ClockCycle {
+        1
+    }
+
+    fn cop2_data(&self, reg: u8) -> u32 {
+        self.cop2_data[usize::from(reg)]
+    }
+
+    fn set_cop2_data(&mut self, reg: u8, val: u32) {
+        self.cop2_data[usize::from(reg)] = val;
+    }
+
+    fn cop2_control(&self, reg: u8) -> u32 {
+        self.cop2_control[usize::from(reg)]
+    }
+
+    fn set_cop2_control(&mut self, reg: u8, val: u32) {
+        self.cop2_control[usize::from(reg)] = val;
+    }
+}
+
+fn i_type(opcode: u32, s: u32, t: u32, imm: u16) -> u32 {
+    opcode << 26 | s << 21 | t << 16 | u32::from(imm)
+}
+
+fn r_type(s: u32, t: u32, d: u32, function: u32) -> u32 {
+    s << 21 | t << 16 | d << 11 | function
+}
+
+const T0: u32 = 8;
+const T1: u32 = 9;
+const T2: u32 = 10;
+
+#[test]
+fn load_delay_slot() {
+    let mut bus = TestBus::new(&[
+        // lw $t0, 0($zero)
+        i_type(0x23, 0, T0, 0),
+        // addu $t1, $t0, $zero
+        r_type(T0, 0, T1, 0x21),
+        // addu $t2, $t0, $zero
+        r_type(T0, 0, T2, 0x21),
+    ]);
+    bus.store(0, 0xdead_beef_u32);
+
+    bus.run(3);
+
+    // The instruction in the load delay slot still sees the old value
+    assert_eq!(bus.reg(T1 as u8), 0);
+    assert_eq!(bus.reg(T2 as u8), 0xdead_beef);
+}
+
+#[test]
+fn branch_delay_slot() {
+    let mut bus = TestBus::new(&[
+        // beq $zero, $zero, +2
+        i_type(0x04, 0, 0, 2),
+        // addiu $t0, $zero, 1
+        i_type(0x09, 0, T0, 1),
+        // addiu $t1, $zero, 1
+        i_type(0x09, 0, T1, 1),
+        // addiu $t2, $zero, 1
+        i_type(0x09, 0, T2, 1),
+    ]);
+
+    bus.run(3);
+
+    assert_eq!(bus.reg(T0 as u8), 1);
+    assert_eq!(bus.reg(T1 as u8), 0);
+    assert_eq!(bus.reg(T2 as u8), 1);
+}
+
+#[test]
+fn overflow_exception() {
+    let mut bus = TestBus::new(&[
+        // lui $t0, 0x7fff
+        i_type(0x0f, 0, T0, 0x7fff),
+        // add $t1, $t0, $t0
+        r_type(T0, T0, T1, 0x20),
+    ]);
+
+    bus.run(2);
+
+    assert_eq!(bus.reg(T1 as u8), 0);
+    assert_eq!(bus.cpu.pc, 0x8000_0080);
+    assert_eq!((cop0::cause(&bus) >> 2) & 0x1f, 0xc);
+    assert_eq!(cop0::mfc0(&mut bus, RegisterIndex(14)), 0x104);
+}
+
+#[test]
+fn cop2_transfers() {
+    let mut bus = TestBus::new(&[
+        // addiu $t0, $zero, 0x1234
+        i_type(0x09, 0, T0, 0x1234),
+        // mtc2 $t0, $5
+        0x4880_0000 | T0 << 16 | 5 << 11,
+        // mfc2 $t1, $5
+        0x4800_0000 | T1 << 16 | 5 << 11,
+        // nop (load delay slot)
+        0,
+    ]);
+
+    bus.run(4);
+
+    assert_eq!(bus.cop2_data[5], 0x1234);
+    assert_eq!(bus.reg(T1 as u8), 0x1234);
+}
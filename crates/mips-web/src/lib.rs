@@ -0,0 +1,170 @@
+//! Browser frontend: the console runs in WebAssembly, its frames are drawn into a canvas and its
+//! sound is played through WebAudio. There's no filesystem, the page hands over the BIOS and the
+//! PS-EXE as bytes and calls `Emulator::run_frame` 60 times per second, see `www/`.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{AudioContext, CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
+use mips_core::audio::AUDIO_FREQ_HZ;
+use mips_core::input::{Button, ButtonQueue, ButtonState, ControllerType};
+use mips_core::{ConsoleManager, CpuFrame};
+
+/// Audio scheduled ahead of the playback, in seconds: covers an animation frame running late
+const AUDIO_LATENCY: f64 = 0.1;
+/// Beyond that much audio ahead the chunks are dropped, the page runs the frames too fast
+const MAX_AUDIO_AHEAD: f64 = 0.5;
+
+/// Keys of the pad, by `KeyboardEvent.code`. The same as the desktop frontend's defaults.
+const KEYS: [(&str, Button); 14] = [
+    ("ArrowUp", Button::DUp),
+    ("ArrowDown", Button::DDown),
+    ("ArrowLeft", Button::DLeft),
+    ("ArrowRight", Button::DRight),
+    ("KeyZ", Button::Cross),
+    ("KeyX", Button::Circle),
+    ("KeyA", Button::Square),
+    ("KeyS", Button::Triangle),
+    ("KeyQ", Button::L1),
+    ("KeyW", Button::R1),
+    ("KeyE", Button::L2),
+    ("KeyR", Button::R2),
+    ("Enter", Button::Start),
+    ("Backspace", Button::Select),
+];
+
+#[wasm_bindgen]
+pub struct Emulator {
+    mips: ConsoleManager,
+    canvas: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+    audio: AudioContext,
+    /// When the next chunk starts playing, on the clock of `audio`
+    audio_time: f64,
+    /// Button changes since the last frame
+    inputs: ButtonQueue,
+    /// The last frame, in the canvas' RGBA
+    rgba: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Power on the console with the BIOS dump `bios`, and sideload the PS-EXE `exe` if there's
+    /// one. To be called from a user gesture (a click), the browsers don't start the audio
+    /// otherwise.
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: HtmlCanvasElement, bios: &[u8], exe: Option<Vec<u8>>) -> Result<Emulator, JsError> {
+        std::panic::set_hook(Box::new(|info| web_sys::console::error_1(&info.to_string().into())));
+
+        let context = canvas
+            .get_context("2d")
+            .ok()
+            .flatten()
+            .and_then(|context| context.dyn_into::<CanvasRenderingContext2d>().ok())
+            .ok_or_else(|| JsError::new("The canvas has no 2D context"))?;
+        let audio = AudioContext::new().map_err(|_| JsError::new("WebAudio isn't available"))?;
+
+        let mut mips = ConsoleManager::new();
+        mips.load_buffers(bios, None, exe.as_deref()).map_err(|e| JsError::new(&e.to_string()))?;
+        mips.connect_device(0, ControllerType::DigitalPad);
+
+        Ok(Emulator {
+            mips,
+            canvas,
+            context,
+            audio,
+            audio_time: 0.0,
+            inputs: ButtonQueue::new(),
+            rgba: Vec::new(),
+        })
+    }
+
+    /// Press or release the button bound to the key `code` (`KeyboardEvent.code`). Returns false
+    /// if no button is, for the page to leave the key to the browser.
+    pub fn set_key(&mut self, code: &str, pressed: bool) -> bool {
+        let Some(&(_, button)) = KEYS.iter().find(|(key, _)| *key == code) else {
+            return false;
+        };
+
+        let state = if pressed { ButtonState::Pressed } else { ButtonState::Released };
+        self.inputs.push((state, button));
+
+        true
+    }
+
+    /// Emulate a frame, then draw it and queue its audio
+    pub fn run_frame(&mut self) {
+        self.mips.handle_inputs(std::mem::take(&mut self.inputs));
+        self.mips.update();
+
+        self.queue_audio();
+        if let Some(frame) = self.mips.get_frame() {
+            self.draw(&frame);
+        }
+    }
+
+    /// Resume the audio suspended by the browser until the page was interacted with
+    pub fn resume_audio(&self) {
+        // Settles once it's running, nothing to wait for
+        let _ = self.audio.resume();
+    }
+}
+
+impl Emulator {
+    fn draw(&mut self, frame: &CpuFrame) {
+        if self.canvas.width() != frame.width || self.canvas.height() != frame.height {
+            self.canvas.set_width(frame.width);
+            self.canvas.set_height(frame.height);
+        }
+
+        self.rgba.clear();
+        self.rgba.extend(frame.pixels.iter().flat_map(|&pixel| {
+            let [_, r, g, b] = pixel.to_be_bytes();
+            [r, g, b, 0xff]
+        }));
+
+        let image = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&self.rgba), frame.width, frame.height);
+        if let Ok(image) = image {
+            let _ = self.context.put_image_data(&image, 0.0, 0.0);
+        }
+    }
+
+    /// Schedule the audio of the frame right after the previous one
+    fn queue_audio(&mut self) {
+        let chunk = self.mips.take_audio();
+        let frames = chunk.samples.len() / 2;
+        if frames == 0 {
+            return;
+        }
+
+        let now = self.audio.current_time();
+        if self.audio_time < now {
+            // Fell behind (first frame, tab in the background), start over with some margin
+            self.audio_time = now + AUDIO_LATENCY;
+        } else if self.audio_time > now + MAX_AUDIO_AHEAD {
+            return;
+        }
+
+        let Ok(buffer) = self.audio.create_buffer(2, frames as u32, AUDIO_FREQ_HZ as f32) else {
+            return;
+        };
+        for channel in 0..2 {
+            let samples: Vec<f32> = chunk.samples
+                .iter()
+                .skip(channel)
+                .step_by(2)
+                .map(|&sample| f32::from(sample) / 32768.0)
+                .collect();
+            let _ = buffer.copy_to_channel(&samples, channel as i32);
+        }
+
+        let Ok(source) = self.audio.create_buffer_source() else {
+            return;
+        };
+        source.set_buffer(Some(&buffer));
+        if source.connect_with_audio_node(&self.audio.destination()).is_ok()
+            && source.start_with_when(self.audio_time).is_ok()
+        {
+            self.audio_time += frames as f64 / f64::from(AUDIO_FREQ_HZ);
+        }
+    }
+}
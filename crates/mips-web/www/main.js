@@ -0,0 +1,66 @@
+// Loads the files picked, powers the console on and runs it at 60 frames per second whatever the
+// refresh rate of the monitor.
+import init, { Emulator } from "./pkg/mips_web.js";
+
+const FRAME_MS = 1000 / 60;
+// Frames caught up at most after a hiccup, the rest are skipped
+const MAX_FRAMES_PER_TICK = 4;
+
+const readFile = async (input) => {
+    const file = input.files[0];
+    return file ? new Uint8Array(await file.arrayBuffer()) : undefined;
+};
+
+let emulator = null;
+
+document.getElementById("start").addEventListener("click", async () => {
+    const error = document.getElementById("error");
+    error.textContent = "";
+
+    try {
+        await init();
+
+        const bios = await readFile(document.getElementById("bios"));
+        if (!bios) {
+            error.textContent = "Pick a BIOS dump first";
+            return;
+        }
+        const exe = await readFile(document.getElementById("exe"));
+
+        emulator?.free();
+        emulator = new Emulator(document.getElementById("screen"), bios, exe);
+        emulator.resume_audio();
+    } catch (e) {
+        error.textContent = e.message ?? e;
+    }
+});
+
+for (const [type, pressed] of [["keydown", true], ["keyup", false]]) {
+    document.addEventListener(type, (event) => {
+        if (emulator?.set_key(event.code, pressed)) {
+            event.preventDefault();
+        }
+    });
+}
+
+let last = performance.now();
+let debt = 0;
+
+const tick = (now) => {
+    debt = Math.min(debt + now - last, FRAME_MS * MAX_FRAMES_PER_TICK);
+    last = now;
+
+    while (debt >= FRAME_MS) {
+        try {
+            emulator?.run_frame();
+        } catch (e) {
+            // The core panicked, the message is in the console
+            document.getElementById("error").textContent = "The emulator crashed: " + (e.message ?? e);
+            emulator = null;
+        }
+        debt -= FRAME_MS;
+    }
+
+    requestAnimationFrame(tick);
+};
+requestAnimationFrame(tick);